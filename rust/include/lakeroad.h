@@ -0,0 +1,49 @@
+/* C API for lakeroad. See src/ffi.rs for details.
+ *
+ * Handles are opaque and freed with the matching _free function. Functions
+ * returning a pointer return NULL on failure, and functions returning int
+ * return -1; lakeroad_last_error() then describes the failure. Strings
+ * returned by the library are freed with lakeroad_string_free(). */
+
+#ifndef LAKEROAD_H
+#define LAKEROAD_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct LakeroadPrograms LakeroadPrograms;
+typedef struct LakeroadExploration LakeroadExploration;
+typedef struct LakeroadIsa LakeroadIsa;
+
+const char *lakeroad_last_error(void);
+void lakeroad_string_free(char *s);
+
+LakeroadPrograms *lakeroad_programs_new(void);
+int lakeroad_programs_add(LakeroadPrograms *programs, const char *name,
+                          const char *expr, double weight);
+void lakeroad_programs_free(LakeroadPrograms *programs);
+
+LakeroadExploration *lakeroad_explore(const LakeroadPrograms *programs,
+                                      size_t iter_limit, size_t node_limit);
+size_t lakeroad_exploration_num_candidates(
+    const LakeroadExploration *exploration);
+char *lakeroad_exploration_candidate(const LakeroadExploration *exploration,
+                                     size_t index);
+void lakeroad_exploration_free(LakeroadExploration *exploration);
+
+LakeroadIsa *lakeroad_synthesize(const LakeroadPrograms *programs,
+                                 size_t iter_limit, size_t node_limit,
+                                 size_t max_instructions);
+size_t lakeroad_isa_num_instructions(const LakeroadIsa *isa);
+char *lakeroad_isa_instruction(const LakeroadIsa *isa, size_t index);
+double lakeroad_isa_score(const LakeroadIsa *isa);
+void lakeroad_isa_free(LakeroadIsa *isa);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* LAKEROAD_H */
@@ -0,0 +1,602 @@
+//! The eclass analysis. Besides each eclass's type, it tracks the values a
+//! signal can take (as a range and as known bits), the variables it depends
+//! on, and the size and depth of its smallest term, which rewrites use as
+//! conditions.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use egg::{Analysis, DidMerge, EGraph, Id, Language as LanguageTrait};
+
+use crate::{
+    analysis::LanguageAnalysisData::*,
+    eval::{eval_binop, eval_unop},
+    interval::Interval,
+    known_bits::KnownBits,
+    language::{type_of, Language, Op, Type, TypeError},
+    prune::OperandPorts,
+};
+
+#[derive(Default)]
+pub struct LanguageAnalysis {
+    /// Instruction eclasses which violate the user's constraints on
+    /// candidates, as marked by [`crate::prune`]. They're never reported as
+    /// ISA instructions, and rewrites don't build larger instructions out of
+    /// them. Kept canonical by [`crate::prune::prune`], which runs between
+    /// rewrite iterations; an eclass merged into a pruned one within an
+    /// iteration is only treated as pruned from the next.
+    pub pruned: HashSet<Id>,
+    /// The register file candidates' operands must come from, which the
+    /// hole introduction rewrites check each `apply` they build against.
+    pub ports: Option<OperandPorts>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum LanguageAnalysisData {
+    /// A function which takes the arguments represented by the vector and
+    /// returns the type indicated by the second argument.
+    Function {
+        args: HashMap<String, usize>,
+        ret: usize,
+    },
+
+    /// Represents a signal with the given bitwidth. `range` bounds the
+    /// (unsigned) values the signal can take, `known` records which of its
+    /// bits are known, and `free_vars` holds the names of the `var`s reachable
+    /// from the signal. `size` and `depth` are the smallest number of signal
+    /// nodes and the smallest depth of any expression (or AST) in the eclass.
+    Signal {
+        width: usize,
+        range: Interval,
+        known: KnownBits,
+        free_vars: BTreeSet<String>,
+        size: usize,
+        depth: usize,
+    },
+    _String(String),
+    Num(i64),
+    Op(Op),
+    List(Box<[Id]>),
+    /// An instruction. The usize represents its output bitwidth.
+    Instr(usize),
+    Empty,
+    /// An ill-typed eclass. Rather than panicking on ill-typed programs, the
+    /// analysis records the error here, and it propagates to all parents.
+    Invalid(TypeError),
+}
+
+impl LanguageAnalysisData {
+    /// The type of the eclass, or the error which made it ill-typed.
+    pub fn ty(&self) -> Result<Type, TypeError> {
+        match self {
+            Function { ret, .. } => Ok(Type::Instr(*ret)),
+            Signal { width, .. } => Ok(Type::Signal(*width)),
+            _String(_) => Ok(Type::String),
+            Num(v) => Ok(Type::Num(*v)),
+            Op(op) => Ok(Type::Op(op.clone())),
+            List(ids) => Ok(Type::List(ids.len())),
+            Instr(bw) => Ok(Type::Instr(*bw)),
+            Empty => Ok(Type::CanonicalArgs),
+            Invalid(e) => Err(e.clone()),
+        }
+    }
+}
+
+impl Analysis<Language> for LanguageAnalysis {
+    type Data = LanguageAnalysisData;
+
+    fn make(egraph: &EGraph<Language, Self>, enode: &Language) -> Self::Data {
+        let ty = match type_of(enode, &mut |id| egraph[id].data.ty()) {
+            Ok(ty) => ty,
+            Err(e) => return Invalid(e),
+        };
+        match enode {
+            Language::Num(v) => Num(*v),
+            Language::String(v) => _String(v.clone()),
+            Language::Op(op) => Op(op.clone()),
+            Language::List(ids) => List(ids.clone()),
+            &Language::Concat([a_id, b_id]) => match (&egraph[a_id].data, &egraph[b_id].data) {
+                (List(a), List(b)) => List(
+                    a.iter()
+                        .chain(b.iter())
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .into_boxed_slice(),
+                ),
+                _ => unreachable!("concat children were checked to be lists"),
+            },
+            _ => match ty {
+                Type::Signal(width) => signal_data(egraph, enode, width),
+                Type::Instr(bw) => Instr(bw),
+                Type::CanonicalArgs => Empty,
+                other => unreachable!("{} cannot have type {:?}", enode, other),
+            },
+        }
+    }
+
+    /// Joins the data of two merged eclasses. [`Invalid`] is the top of the
+    /// lattice; merging eclasses of different types produces an [`Invalid`]
+    /// recording the conflict. Ranges of merged signals are intersected, as
+    /// both are sound bounds on the same values.
+    fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> egg::DidMerge {
+        if *a == b {
+            return DidMerge(false, false);
+        }
+
+        let merged = match (&*a, &b) {
+            (Invalid(_), _) => a.clone(),
+            (_, Invalid(_)) => b.clone(),
+            // Lists of equivalent ids which differ only in representation,
+            // e.g. because one was created before a union. Keep the smaller
+            // representation, so that merging is commutative.
+            (List(a_ids), List(b_ids)) if a_ids.len() == b_ids.len() => {
+                if b_ids < a_ids {
+                    b.clone()
+                } else {
+                    a.clone()
+                }
+            }
+            (
+                Signal {
+                    width: a_width,
+                    range: a_range,
+                    known: a_known,
+                    free_vars: a_vars,
+                    size: a_size,
+                    depth: a_depth,
+                },
+                Signal {
+                    width: b_width,
+                    range: b_range,
+                    known: b_known,
+                    free_vars: b_vars,
+                    size: b_size,
+                    depth: b_depth,
+                },
+            ) if a_width == b_width => Signal {
+                width: *a_width,
+                // An empty intersection means the values are unreachable;
+                // just keep one of the ranges.
+                range: a_range.intersect(b_range).unwrap_or(*a_range),
+                known: a_known.union(b_known),
+                free_vars: a_vars.union(b_vars).cloned().collect(),
+                size: (*a_size).min(*b_size),
+                depth: (*a_depth).min(*b_depth),
+            },
+            _ => Invalid(TypeError::Conflict {
+                a: a.ty().expect("invalid data handled above"),
+                b: b.ty().expect("invalid data handled above"),
+            }),
+        };
+
+        let did_merge = DidMerge(merged != *a, merged != b);
+        *a = merged;
+        did_merge
+    }
+
+    /// Constant propagation: when an `apply` is known to compute a constant
+    /// (e.g. because all of its arguments are constants), union in the
+    /// corresponding `const`, so that trivially-constant candidates collapse.
+    fn modify(egraph: &mut EGraph<Language, Self>, id: Id) {
+        let value = match &egraph[id].data {
+            Signal { width, known, .. } => known.as_constant(*width).map(|v| (v, *width)),
+            _ => None,
+        };
+        let has_apply = egraph[id]
+            .nodes
+            .iter()
+            .any(|node| matches!(node, Language::Apply(_)));
+        if let (Some((value, width)), true) = (value, has_apply) {
+            // `const` values are i64s; leave wider constants alone.
+            if value > i64::MAX as u128 && width > 64 {
+                return;
+            }
+            let value_id = egraph.add(Language::Num(value as i64));
+            let width_id = egraph.add(Language::Num(width as i64));
+            let const_id = egraph.add(Language::Const([value_id, width_id]));
+            egraph.union(id, const_id);
+        }
+    }
+}
+
+/// The data of the signal computed by `enode`, given the data of its
+/// children. Ranges and known bits are computed separately and then used to
+/// refine each other.
+fn signal_data(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    enode: &Language,
+    width: usize,
+) -> LanguageAnalysisData {
+    // A narrower child, i.e. a shift amount or a resized operand, is
+    // zero-extended, and a wider one, a resized operand, truncated.
+    let child = |id: Id| match &egraph[id].data {
+        Signal {
+            width: child_width,
+            range,
+            known,
+            ..
+        } => (
+            range.truncate(width),
+            known.zero_extend(*child_width, width).truncate(width),
+        ),
+        _ => (Interval::full(width), KnownBits::unknown()),
+    };
+    let (range, known) = match enode {
+        &Language::Const([val_id, _]) => match &egraph[val_id].data {
+            Num(v) => (
+                Interval::of_const(*v, width),
+                KnownBits::of_const(*v, width),
+            ),
+            _ => (Interval::full(width), KnownBits::unknown()),
+        },
+        &Language::UnOp([op_id, _, a_id]) | &Language::UnOpAst([op_id, _, a_id]) => {
+            match &egraph[op_id].data {
+                Op(op) => {
+                    let (a_range, a_known) = child(a_id);
+                    (
+                        Interval::unop(op, width, a_range),
+                        KnownBits::unop(op, width, a_known),
+                    )
+                }
+                _ => (Interval::full(width), KnownBits::unknown()),
+            }
+        }
+        &Language::BinOp([op_id, _, a_id, b_id]) | &Language::BinOpAst([op_id, _, a_id, b_id]) => {
+            match &egraph[op_id].data {
+                Op(op) => {
+                    let ((a_range, a_known), (b_range, b_known)) = (child(a_id), child(b_id));
+                    (
+                        Interval::binop(op, width, a_range, b_range),
+                        KnownBits::binop(op, width, a_known, b_known),
+                    )
+                }
+                _ => (Interval::full(width), KnownBits::unknown()),
+            }
+        }
+        // The range follows from the known bits, below.
+        &Language::Cat([hi_id, lo_id]) => match (&egraph[hi_id].data, &egraph[lo_id].data) {
+            (
+                Signal { known: hi, .. },
+                Signal {
+                    width: lo_width,
+                    known: lo,
+                    ..
+                },
+            ) => (Interval::full(width), hi.concat(lo, *lo_width, width)),
+            _ => (Interval::full(width), KnownBits::unknown()),
+        },
+        &Language::Apply([instr_id, args_id]) => match apply_constant(egraph, instr_id, args_id) {
+            Some(v) => (Interval::constant(v), KnownBits::constant(v, width)),
+            None => (Interval::full(width), KnownBits::unknown()),
+        },
+        _ => (Interval::full(width), KnownBits::unknown()),
+    };
+
+    let known = known.union(&KnownBits::of_range(&range, width));
+    // Known ones are a lower bound, and bits which might be one give an
+    // upper bound.
+    let range = range
+        .intersect(&Interval {
+            lo: known.ones,
+            hi: known.possible_ones(width),
+        })
+        .unwrap_or(range);
+
+    let free_vars = match enode {
+        &Language::Var([name_id, _]) => match &egraph[name_id].data {
+            _String(name) => BTreeSet::from([name.clone()]),
+            _ => BTreeSet::default(),
+        },
+        _ => enode
+            .children()
+            .iter()
+            .flat_map(|id| free_vars(egraph, *id))
+            .collect(),
+    };
+
+    // Signal children, looking through argument lists so that the size of an
+    // `apply` counts its arguments.
+    let children: Vec<(usize, usize)> = enode
+        .children()
+        .iter()
+        .flat_map(|id| match &egraph[*id].data {
+            List(ids) => ids.to_vec(),
+            _ => vec![*id],
+        })
+        .filter_map(|id| match &egraph[id].data {
+            Signal { size, depth, .. } => Some((*size, *depth)),
+            _ => None,
+        })
+        .collect();
+
+    Signal {
+        width,
+        range,
+        known,
+        free_vars,
+        size: 1 + children.iter().map(|(size, _)| size).sum::<usize>(),
+        depth: 1 + children.iter().map(|(_, depth)| *depth).max().unwrap_or(0),
+    }
+}
+
+/// The value of `(apply instr args)`, if all of the arguments are constants.
+/// Holes in the instruction's AST are filled by the arguments in order.
+fn apply_constant(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    instr_id: Id,
+    args_id: Id,
+) -> Option<u128> {
+    let args = match &egraph[args_id].data {
+        List(ids) => ids
+            .iter()
+            .map(|id| match &egraph[*id].data {
+                Signal { width, known, .. } => known.as_constant(*width),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?,
+        _ => return None,
+    };
+    egraph[instr_id].nodes.iter().find_map(|node| match node {
+        &Language::Instr([ast_id, _]) => {
+            let mut next_hole = 0;
+            let value = eval_ast(egraph, ast_id, &args, &mut next_hole, &mut HashSet::new())?;
+            if next_hole == args.len() {
+                Some(value)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    })
+}
+
+/// Evaluates some representation of the AST eclass `id`, taking hole values
+/// from `args` starting at `next_hole`.
+fn eval_ast(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    id: Id,
+    args: &[u128],
+    next_hole: &mut usize,
+    visiting: &mut HashSet<Id>,
+) -> Option<u128> {
+    let id = egraph.find(id);
+    if !visiting.insert(id) {
+        return None;
+    }
+    let num = |id: Id| match &egraph[id].data {
+        Num(v) if *v > 0 => Some(*v as usize),
+        _ => None,
+    };
+    let op = |id: Id| match &egraph[id].data {
+        Op(op) => Some(op.clone()),
+        _ => None,
+    };
+    let start = *next_hole;
+    let out = egraph[id].nodes.iter().find_map(|node| {
+        *next_hole = start;
+        match node {
+            Language::Hole(_) => {
+                let v = args.get(*next_hole).cloned();
+                *next_hole += 1;
+                v
+            }
+            &Language::UnOpAst([op_id, bw_id, a_id]) => {
+                let a = eval_ast(egraph, a_id, args, next_hole, visiting)?;
+                eval_unop(&op(op_id)?, num(bw_id)?, a)
+            }
+            &Language::BinOpAst([op_id, bw_id, a_id, b_id]) => {
+                let a = eval_ast(egraph, a_id, args, next_hole, visiting)?;
+                let b = eval_ast(egraph, b_id, args, next_hole, visiting)?;
+                eval_binop(&op(op_id)?, num(bw_id)?, a, b)
+            }
+            _ => None,
+        }
+    });
+    visiting.remove(&id);
+    out
+}
+
+/// The names of the `var`s reachable from an eclass. For lists, this is the
+/// union over the list's elements.
+pub fn free_vars(egraph: &EGraph<Language, LanguageAnalysis>, id: Id) -> BTreeSet<String> {
+    match &egraph[id].data {
+        Signal { free_vars, .. } => free_vars.clone(),
+        List(ids) => ids.iter().flat_map(|id| free_vars(egraph, *id)).collect(),
+        _ => BTreeSet::default(),
+    }
+}
+
+/// Whether the instruction eclass `id` has been pruned.
+pub fn is_pruned(egraph: &EGraph<Language, LanguageAnalysis>, id: Id) -> bool {
+    egraph.analysis.pruned.contains(&egraph.find(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::{RecExpr, Runner};
+
+    use crate::rewrites::asr_nonnegative_to_lsr;
+
+    use super::*;
+
+    #[test]
+    fn ceil_avg() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+
+        let id = egraph.add_expr(
+            &RecExpr::from_str(
+            "(binop sub 8 (binop or 8 (var x 8) (var y 8)) (binop asr 8 (binop xor 8 (var x 8) (var y 8)) (const 1 8)))",
+            )
+            .unwrap(),
+        );
+
+        match &egraph[id].data {
+            Signal { width: 8, .. } => (),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn merge_is_a_join() {
+        let mut analysis = LanguageAnalysis::default();
+        let signal = |width| Signal {
+            width,
+            range: Interval::full(width),
+            known: KnownBits::unknown(),
+            free_vars: BTreeSet::default(),
+            size: 1,
+            depth: 1,
+        };
+
+        let mut a = signal(8);
+        let did_merge = analysis.merge(&mut a, signal(8));
+        assert!(!did_merge.0 && !did_merge.1);
+
+        // Differently-represented lists keep the smaller representation,
+        // regardless of merge order.
+        let small = List(vec![Id::from(0), Id::from(1)].into_boxed_slice());
+        let big = List(vec![Id::from(2), Id::from(1)].into_boxed_slice());
+        let mut a = big.clone();
+        let did_merge = analysis.merge(&mut a, small.clone());
+        assert!(did_merge.0 && !did_merge.1);
+        assert_eq!(a, small);
+        let mut a = small.clone();
+        let did_merge = analysis.merge(&mut a, big);
+        assert!(!did_merge.0 && did_merge.1);
+        assert_eq!(a, small);
+
+        // Conflicting types become invalid.
+        let mut a = signal(8);
+        let did_merge = analysis.merge(&mut a, signal(4));
+        assert!(did_merge.0 && did_merge.1);
+        assert_eq!(
+            a,
+            Invalid(TypeError::Conflict {
+                a: Type::Signal(8),
+                b: Type::Signal(4)
+            })
+        );
+
+        // Invalid absorbs everything.
+        let mut b = signal(8);
+        let did_merge = analysis.merge(&mut b, a.clone());
+        assert!(did_merge.0 && !did_merge.1);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn merge_intersects_ranges() {
+        let mut analysis = LanguageAnalysis::default();
+        let mut a = Signal {
+            width: 8,
+            range: Interval { lo: 0, hi: 15 },
+            known: KnownBits {
+                zeros: 0xf0,
+                ones: 0,
+            },
+            free_vars: BTreeSet::from(["x".to_string()]),
+            size: 5,
+            depth: 3,
+        };
+        let did_merge = analysis.merge(
+            &mut a,
+            Signal {
+                width: 8,
+                range: Interval { lo: 4, hi: 255 },
+                known: KnownBits { zeros: 0, ones: 4 },
+                free_vars: BTreeSet::from(["y".to_string()]),
+                size: 3,
+                depth: 2,
+            },
+        );
+        assert!(did_merge.0 && did_merge.1);
+        assert_eq!(
+            a,
+            Signal {
+                width: 8,
+                range: Interval { lo: 4, hi: 15 },
+                known: KnownBits {
+                    zeros: 0xf0,
+                    ones: 4
+                },
+                free_vars: BTreeSet::from(["x".to_string(), "y".to_string()]),
+                size: 3,
+                depth: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn range_analysis() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let id = egraph.add_expr(
+            &RecExpr::from_str("(binop lsr 8 (binop and 8 (var x 8) (const 127 8)) (const 1 8))")
+                .unwrap(),
+        );
+        match &egraph[id].data {
+            Signal {
+                width: 8,
+                range,
+                known,
+                ..
+            } => {
+                assert_eq!(*range, Interval { lo: 0, hi: 63 });
+                assert_eq!(known.zeros, 0xc0);
+            }
+            _ => panic!(),
+        }
+
+        let asr = egraph.add_expr(
+            &RecExpr::from_str("(binop asr 8 (binop and 8 (var x 8) (const 127 8)) (const 1 8))")
+                .unwrap(),
+        );
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .run(&vec![asr_nonnegative_to_lsr()]);
+        assert_eq!(runner.egraph.find(asr), runner.egraph.find(id));
+
+        // A three-bit shift amount, zero-extended or not, is at most 7.
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        for expr in [
+            "(binop lsr 8 (const 255 8) (var s 3))",
+            "(binop lsr 8 (const 255 8) (cat (const 0 5) (var s 3)))",
+        ] {
+            let id = egraph.add_expr(&RecExpr::from_str(expr).unwrap());
+            match &egraph[id].data {
+                Signal { range, .. } => assert_eq!(*range, Interval { lo: 1, hi: 255 }),
+                _ => panic!(),
+            }
+        }
+    }
+
+    #[test]
+    fn constant_propagation_through_apply() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let id = egraph.add_expr(
+            &RecExpr::from_str(
+                "(apply
+                  (instr (binop-ast add 8 (hole 8) (hole 8)) (canonical-args 0 1))
+                  (list (const 200 8) (const 100 8)))",
+            )
+            .unwrap(),
+        );
+        egraph.rebuild();
+        let folded = egraph.add_expr(&RecExpr::from_str("(const 44 8)").unwrap());
+        assert_eq!(egraph.find(id), egraph.find(folded));
+
+        // Non-constant arguments are left alone.
+        let id = egraph.add_expr(
+            &RecExpr::from_str(
+                "(apply
+                  (instr (binop-ast add 8 (hole 8) (hole 8)) (canonical-args 0 1))
+                  (list (var x 8) (const 100 8)))",
+            )
+            .unwrap(),
+        );
+        egraph.rebuild();
+        assert!(egraph[id]
+            .nodes
+            .iter()
+            .all(|node| !matches!(node, Language::Const(_))));
+    }
+}
@@ -0,0 +1,220 @@
+//! Scoring implementations by the primitives they take on an architecture.
+//!
+//! [`extract_mapped`] extracts the implementation of a program whose
+//! primitives weigh the least, as in [`PrimitiveWeights::score`]. Each
+//! operator and `apply` is costed by mapping it alone, with its operands as
+//! inputs, so the cost of a tree is what mapping it takes, except that
+//! shared operands are counted once per use. `reg`s are costed the same
+//! way, as the flip-flops they take, so with
+//! [`crate::pipeline::retiming_rules`] extraction places registers where
+//! they're cheapest.
+
+use std::collections::{HashMap, HashSet};
+
+use egg::{AstSize, CostFunction, EGraph, Extractor, Id, Language as LanguageTrait, RecExpr};
+use serde::Deserialize;
+
+use super::{Architecture, Netlist, PrimitiveCount};
+use crate::{
+    analysis::{LanguageAnalysis, LanguageAnalysisData::*},
+    error::LakeroadError,
+    isa::Isa,
+    language::Language,
+};
+
+/// How much a cell of each kind costs.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrimitiveWeights {
+    pub lut: f64,
+    /// Muxes come with the LUTs they combine, so are free by default.
+    pub mux: f64,
+    pub carry: f64,
+    pub dsp: f64,
+    /// Every LUT comes with flip-flops, so registers are free by default.
+    pub register: f64,
+}
+
+impl Default for PrimitiveWeights {
+    fn default() -> Self {
+        PrimitiveWeights {
+            lut: 1.0,
+            mux: 0.0,
+            carry: 1.0,
+            dsp: 1.0,
+            register: 0.0,
+        }
+    }
+}
+
+impl PrimitiveWeights {
+    pub fn score(&self, count: &PrimitiveCount) -> f64 {
+        self.lut * count.luts as f64
+            + self.mux * count.muxes as f64
+            + self.carry * count.carries as f64
+            + self.dsp * count.dsps as f64
+            + self.register * count.registers as f64
+    }
+}
+
+/// The weighted primitives a term takes, as a tree, and then its size, to
+/// break ties. Terms which don't map onto the architecture, and with an ISA,
+/// anything but `apply`s of its instructions, cost infinitely much.
+struct PrimitiveCost<'a> {
+    egraph: &'a EGraph<Language, LanguageAnalysis>,
+    arch: &'a dyn Architecture,
+    weights: &'a PrimitiveWeights,
+    isa: Option<HashSet<Id>>,
+    /// The smallest term of each eclass, for operands which aren't signals.
+    terms: Extractor<'a, AstSize, Language, LanguageAnalysis>,
+    /// The score of each operator or `apply` mapped so far, by its term.
+    scores: HashMap<String, f64>,
+}
+
+impl PrimitiveCost<'_> {
+    /// The eclass `id` as an operand: a variable per signal, named after its
+    /// eclass, so that repeated operands are the same input.
+    fn operand(&self, id: Id) -> String {
+        let id = self.egraph.find(id);
+        match &self.egraph[id].data {
+            Signal { width, .. } => format!("(var v{} {})", id, width),
+            List(ids) => format!(
+                "(list{})",
+                ids.iter()
+                    .map(|id| format!(" {}", self.operand(*id)))
+                    .collect::<String>()
+            ),
+            _ => self.terms.find_best(id).1.to_string(),
+        }
+    }
+
+    /// The score of mapping `enode` alone.
+    fn score(&mut self, enode: &Language) -> f64 {
+        let term = format!(
+            "({}{})",
+            enode,
+            enode
+                .children()
+                .iter()
+                .map(|id| format!(" {}", self.operand(*id)))
+                .collect::<String>()
+        );
+        if let Some(score) = self.scores.get(&term) {
+            return *score;
+        }
+        let score = match Netlist::map(self.arch, &term.parse().unwrap()) {
+            Ok(netlist) => self.weights.score(&netlist.count()),
+            Err(_) => f64::INFINITY,
+        };
+        self.scores.insert(term, score);
+        score
+    }
+}
+
+impl CostFunction<Language> for PrimitiveCost<'_> {
+    type Cost = (f64, usize);
+
+    fn cost<C>(&mut self, enode: &Language, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        let score = match (enode, &self.isa) {
+            (&Language::Apply([instr_id, _]), Some(isa)) if !isa.contains(&instr_id) => {
+                f64::INFINITY
+            }
+            (Language::UnOp(_) | Language::BinOp(_), Some(_)) => f64::INFINITY,
+            (Language::Apply(_) | Language::UnOp(_) | Language::BinOp(_) | Language::Reg(_), _) => {
+                self.score(enode)
+            }
+            _ => 0.0,
+        };
+        enode.children().iter().fold((score, 1), |(a, b), id| {
+            let (c, d) = costs(*id);
+            (a + c, b.saturating_add(d))
+        })
+    }
+}
+
+/// Extracts the implementation of the program at `root` whose primitives on
+/// `arch` weigh the least, and maps it. With `isa`, only `apply`s of its
+/// instructions are used, as in [`crate::isa::program_cost`]. Fails with
+/// [`LakeroadError::Unsupported`] if no implementation maps onto `arch`.
+pub fn extract_mapped(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    root: Id,
+    arch: &dyn Architecture,
+    weights: &PrimitiveWeights,
+    isa: Option<&Isa>,
+) -> Result<(RecExpr<Language>, Netlist), LakeroadError> {
+    let extractor = Extractor::new(
+        egraph,
+        PrimitiveCost {
+            egraph,
+            arch,
+            weights,
+            isa: isa.map(|isa| isa.instructions.iter().cloned().collect()),
+            terms: Extractor::new(egraph, AstSize),
+            scores: HashMap::new(),
+        },
+    );
+    match extractor.find_best(egraph.find(root)) {
+        ((score, _), expr) if score.is_infinite() => Err(LakeroadError::Unsupported(format!(
+            "mapping {} onto {}",
+            expr,
+            arch.name()
+        ))),
+        (_, expr) => {
+            let netlist = Netlist::map(arch, &expr)?;
+            Ok((expr, netlist))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::Runner;
+
+    use super::*;
+    use crate::{
+        arch::{lattice::Ecp5, xilinx::UltraScalePlus},
+        dsp::dsp_rules,
+        rewrites::canonicalize,
+    };
+
+    #[test]
+    fn extract_fewest_primitives() {
+        let program =
+            RecExpr::from_str("(binop add 8 (binop mul 8 (var a 8) (var b 8)) (var c 8))").unwrap();
+        let mut egraph = EGraph::default();
+        let root = egraph.add_expr(&program);
+        let mut rules = dsp_rules(48);
+        rules.push(canonicalize());
+        let runner = Runner::default().with_egraph(egraph).run(&rules);
+        let weights = PrimitiveWeights::default();
+
+        // A DSP48E2 multiplies and adds at once.
+        let (_, netlist) =
+            extract_mapped(&runner.egraph, root, &UltraScalePlus, &weights, None).unwrap();
+        assert_eq!(
+            netlist.count(),
+            PrimitiveCount {
+                dsps: 1,
+                ..PrimitiveCount::default()
+            }
+        );
+
+        // A MULT18X18D only multiplies, so the addition takes a carry chain,
+        // two bits per CCU2C.
+        let (_, netlist) = extract_mapped(&runner.egraph, root, &Ecp5, &weights, None).unwrap();
+        assert_eq!(
+            netlist.count(),
+            PrimitiveCount {
+                carries: 4,
+                dsps: 1,
+                ..PrimitiveCount::default()
+            }
+        );
+    }
+}
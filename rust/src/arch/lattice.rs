@@ -0,0 +1,656 @@
+//! Lattice ECP5: `LUT4`s, combined by `PFUMX`s and `L6MUX21`s into mux
+//! trees, `CCU2C` carry cells, `MULT18X18D` multipliers, and `FD1S3AX`
+//! flip-flops.
+//!
+//! Unlike Xilinx's, these primitives have a port per bit, e.g. `A0` to
+//! `A17`. The `MULT18X18D` semantics only covers it without registers but
+//! the output register, and the multiplier is the only DSP configuration
+//! mapped, as the rest need an `ALU54B`. See the ECP5 sysDSP usage guide (TN1267) for the primitives.
+
+use super::{
+    always_registered, bits_parameter, combinational, const_bits, const_port, lut_term, mux_term,
+    port_term, port_value, signed, string_parameter, unsupported, Architecture, Bit, Cell, Netlist,
+    Parameter, Parameters, PortTerms, PortValues, Primitive, PrimitiveKind,
+};
+use crate::{bitvec::BitVec, dsp::DspConfig, error::LakeroadError, interval::mask, smt::Term};
+
+pub struct Ecp5;
+
+/// The names of the one-bit ports making up a bus, e.g. `A0` to `A17`.
+macro_rules! bus {
+    ($prefix:literal; $($bit:literal)*) => {
+        [$(concat!($prefix, $bit)),*]
+    };
+}
+
+const MULT_A: [&str; 18] = bus!("A"; 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17);
+const MULT_B: [&str; 18] = bus!("B"; 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17);
+const MULT_C: [&str; 18] = bus!("C"; 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17);
+const MULT_P: [&str; 36] = bus!("P";
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17
+    18 19 20 21 22 23 24 25 26 27 28 29 30 31 32 33 34 35);
+const MULT_CONTROL: [&str; 16] = [
+    "SIGNEDA", "SIGNEDB", "SOURCEA", "SOURCEB", "CE0", "CE1", "CE2", "CE3", "CLK0", "CLK1", "CLK2",
+    "CLK3", "RST0", "RST1", "RST2", "RST3",
+];
+
+/// The `MULT18X18D`'s registers, by the parameter giving their clock, which
+/// is `NONE` in the cells the mapping adds, but for the output register's,
+/// which is `CLK0` for `reg`s.
+const MULT_REGISTERS: [&str; 5] = [
+    "REG_INPUTA_CLK",
+    "REG_INPUTB_CLK",
+    "REG_INPUTC_CLK",
+    "REG_PIPELINE_CLK",
+    "REG_OUTPUT_CLK",
+];
+
+/// The bits on one-bit `ports`, read as a number, `ports[0]` least
+/// significant.
+fn bus_value(values: &PortValues, ports: &[&str]) -> Result<u128, LakeroadError> {
+    let mut out = 0;
+    for (i, port) in ports.iter().enumerate() {
+        out |= port_value(values, port)? << i;
+    }
+    Ok(out)
+}
+
+/// A LUT4 of `init`, as in both the `LUT4` and the `CCU2C`.
+fn lut4(init: &BitVec, a: u128, b: u128, c: u128, d: u128) -> u128 {
+    init.bit((d << 3 | c << 2 | b << 1 | a) as usize) as u128
+}
+
+fn lut4_semantics(
+    parameters: &Parameters,
+    values: &PortValues,
+) -> Result<PortValues, LakeroadError> {
+    let port = |name: &str| port_value(values, name);
+    let z = lut4(
+        bits_parameter(parameters, "INIT")?,
+        port("A")?,
+        port("B")?,
+        port("C")?,
+        port("D")?,
+    );
+    Ok([("Z".to_string(), BitVec::new(1, z))].into_iter().collect())
+}
+
+fn lut4_symbolic(parameters: &Parameters, terms: &PortTerms) -> Result<PortTerms, LakeroadError> {
+    let inputs = ["A", "B", "C", "D"]
+        .iter()
+        .map(|port| port_term(terms, port).cloned())
+        .collect::<Result<Vec<_>, _>>()?;
+    let z = lut_term(bits_parameter(parameters, "INIT")?, &inputs);
+    Ok([("Z".to_string(), z)].into_iter().collect())
+}
+
+/// Two bits of carry chain, each a LUT4 of `INITn` whose output propagates
+/// the carry, and a LUT2 of its low four bits which generates it otherwise.
+fn ccu2c(parameters: &Parameters, values: &PortValues) -> Result<PortValues, LakeroadError> {
+    let port = |name: &str| port_value(values, name);
+    let mut carry = port("CIN")?;
+    let mut out = PortValues::new();
+    for (init, inject, a, b, c, d, s) in [
+        ("INIT0", "INJECT1_0", "A0", "B0", "C0", "D0", "S0"),
+        ("INIT1", "INJECT1_1", "A1", "B1", "C1", "D1", "S1"),
+    ] {
+        let init = bits_parameter(parameters, init)?;
+        let inject = match string_parameter(parameters, inject)? {
+            "YES" => true,
+            "NO" => false,
+            other => return unsupported(format!("a CCU2C with {} = {:?}", inject, other)),
+        };
+        let (a, b) = (port(a)?, port(b)?);
+        let propagate = lut4(init, a, b, port(c)?, port(d)?);
+        let generate = if inject { 0 } else { lut4(init, a, b, 0, 0) };
+        let sum = propagate ^ if inject { 0 } else { carry };
+        out.insert(s.to_string(), BitVec::new(1, sum));
+        carry = if propagate == 1 { carry } else { generate };
+    }
+    out.insert("COUT".to_string(), BitVec::new(1, carry));
+    Ok(out)
+}
+
+fn ccu2c_symbolic(parameters: &Parameters, terms: &PortTerms) -> Result<PortTerms, LakeroadError> {
+    let port = |name: &str| port_term(terms, name).cloned();
+    let zero = Term::Const(BitVec::zero(1));
+    let mut carry = port("CIN")?;
+    let mut out = PortTerms::new();
+    for (init, inject, a, b, c, d, s) in [
+        ("INIT0", "INJECT1_0", "A0", "B0", "C0", "D0", "S0"),
+        ("INIT1", "INJECT1_1", "A1", "B1", "C1", "D1", "S1"),
+    ] {
+        let init = bits_parameter(parameters, init)?;
+        let inject = match string_parameter(parameters, inject)? {
+            "YES" => true,
+            "NO" => false,
+            other => return unsupported(format!("a CCU2C with {} = {:?}", inject, other)),
+        };
+        let (a, b) = (port(a)?, port(b)?);
+        let propagate = lut_term(init, &[a.clone(), b.clone(), port(c)?, port(d)?]);
+        let generate = if inject {
+            zero.clone()
+        } else {
+            lut_term(init, &[a, b, zero.clone(), zero.clone()])
+        };
+        let sum = if inject {
+            propagate.clone()
+        } else {
+            Term::app("bvxor", vec![propagate.clone(), carry.clone()])
+        };
+        out.insert(s.to_string(), sum);
+        carry = mux_term(&propagate, &carry, &generate);
+    }
+    out.insert("COUT".to_string(), carry);
+    Ok(out)
+}
+
+/// Whether a `MULT18X18D` registers `P`.
+fn mult18x18d_registered(parameters: &Parameters) -> bool {
+    matches!(string_parameter(parameters, "REG_OUTPUT_CLK"), Ok("CLK0"))
+}
+
+/// Fails on the `MULT18X18D` settings the semantics doesn't cover.
+fn check_mult18x18d(parameters: &Parameters) -> Result<(), LakeroadError> {
+    for register in MULT_REGISTERS {
+        let clock = string_parameter(parameters, register)?;
+        if clock != "NONE" && !(register == "REG_OUTPUT_CLK" && clock == "CLK0") {
+            return unsupported(format!("a MULT18X18D with {} = {:?}", register, clock));
+        }
+    }
+    match string_parameter(parameters, "MULT_BYPASS")? {
+        "DISABLED" => Ok(()),
+        other => unsupported(format!("a MULT18X18D with MULT_BYPASS = {:?}", other)),
+    }
+}
+
+fn mult18x18d(parameters: &Parameters, values: &PortValues) -> Result<PortValues, LakeroadError> {
+    check_mult18x18d(parameters)?;
+    let port = |name: &str| port_value(values, name);
+    if port("SOURCEA")? != 0 || port("SOURCEB")? != 0 {
+        return unsupported("a MULT18X18D reading its shift inputs".to_string());
+    }
+    if mult18x18d_registered(parameters) && port("CE0")? != 1 {
+        return unsupported("a MULT18X18D whose output register isn't enabled".to_string());
+    }
+    // Each operand is signed if its `SIGNED` port is set.
+    let operand = |ports: &[&str], signed_port: &str| -> Result<i128, LakeroadError> {
+        let value = bus_value(values, ports)?;
+        Ok(if port(signed_port)? == 1 {
+            signed(value, 18)
+        } else {
+            value as i128
+        })
+    };
+    let p =
+        operand(&MULT_A, "SIGNEDA")?.wrapping_mul(operand(&MULT_B, "SIGNEDB")?) as u128 & mask(36);
+    Ok(MULT_P
+        .iter()
+        .enumerate()
+        .map(|(i, port)| (port.to_string(), BitVec::new(1, p >> i & 1)))
+        .collect())
+}
+
+/// As [`mult18x18d`], whose `SIGNED` and `SOURCE` ports must be constants.
+fn mult18x18d_symbolic(
+    parameters: &Parameters,
+    terms: &PortTerms,
+) -> Result<PortTerms, LakeroadError> {
+    check_mult18x18d(parameters)?;
+    if const_port(terms, "SOURCEA")? != 0 || const_port(terms, "SOURCEB")? != 0 {
+        return unsupported("a MULT18X18D reading its shift inputs".to_string());
+    }
+    if mult18x18d_registered(parameters) && const_port(terms, "CE0")? != 1 {
+        return unsupported("a MULT18X18D whose output register isn't enabled".to_string());
+    }
+    let operand = |ports: &[&str], signed_port: &str| -> Result<Term, LakeroadError> {
+        let bits = ports
+            .iter()
+            .rev()
+            .map(|port| port_term(terms, port).cloned())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Term::app("concat", bits).extend(const_port(terms, signed_port)? == 1, 18))
+    };
+    let p = Term::app(
+        "bvmul",
+        vec![operand(&MULT_A, "SIGNEDA")?, operand(&MULT_B, "SIGNEDB")?],
+    );
+    Ok(MULT_P
+        .iter()
+        .enumerate()
+        .map(|(i, port)| (port.to_string(), p.extract(i, i)))
+        .collect())
+}
+
+/// A mux of `inputs`, the ports selected when `sel` is low and high, as in
+/// the `PFUMX` and `L6MUX21`.
+fn mux(values: &PortValues, sel: &str, inputs: [&str; 2]) -> Result<PortValues, LakeroadError> {
+    let input = inputs[port_value(values, sel)? as usize];
+    Ok(
+        [("Z".to_string(), BitVec::new(1, port_value(values, input)?))]
+            .into_iter()
+            .collect(),
+    )
+}
+
+fn mux_symbolic(
+    terms: &PortTerms,
+    sel: &str,
+    [lo, hi]: [&str; 2],
+) -> Result<PortTerms, LakeroadError> {
+    let z = mux_term(
+        port_term(terms, sel)?,
+        port_term(terms, hi)?,
+        port_term(terms, lo)?,
+    );
+    Ok([("Z".to_string(), z)].into_iter().collect())
+}
+
+/// `Z = C0 ? ALUT : BLUT`, combining a slice's two LUT4s.
+fn pfumx(_: &Parameters, values: &PortValues) -> Result<PortValues, LakeroadError> {
+    mux(values, "C0", ["BLUT", "ALUT"])
+}
+
+fn pfumx_symbolic(_: &Parameters, terms: &PortTerms) -> Result<PortTerms, LakeroadError> {
+    mux_symbolic(terms, "C0", ["BLUT", "ALUT"])
+}
+
+/// `Z = SD ? D1 : D0`, combining two slices' `PFUMX`s.
+fn l6mux21(_: &Parameters, values: &PortValues) -> Result<PortValues, LakeroadError> {
+    mux(values, "SD", ["D0", "D1"])
+}
+
+fn l6mux21_symbolic(_: &Parameters, terms: &PortTerms) -> Result<PortTerms, LakeroadError> {
+    mux_symbolic(terms, "SD", ["D0", "D1"])
+}
+
+/// What goes into an `FD1S3AX`, which always loads `D`.
+fn fd1s3ax(_: &Parameters, values: &PortValues) -> Result<PortValues, LakeroadError> {
+    Ok(
+        [("Q".to_string(), BitVec::new(1, port_value(values, "D")?))]
+            .into_iter()
+            .collect(),
+    )
+}
+
+fn fd1s3ax_symbolic(_: &Parameters, terms: &PortTerms) -> Result<PortTerms, LakeroadError> {
+    Ok([("Q".to_string(), port_term(terms, "D")?.clone())]
+        .into_iter()
+        .collect())
+}
+
+impl Architecture for Ecp5 {
+    fn name(&self) -> &'static str {
+        "lattice-ecp5"
+    }
+
+    fn primitives(&self) -> Vec<Primitive> {
+        let one_bit = |ports: &[&'static str]| -> Vec<(&'static str, usize)> {
+            ports.iter().map(|port| (*port, 1)).collect()
+        };
+        vec![
+            Primitive {
+                name: "LUT4",
+                kind: PrimitiveKind::Lut,
+                inputs: one_bit(&["A", "B", "C", "D"]),
+                outputs: vec![("Z", 1)],
+                semantics: lut4_semantics,
+                symbolic: lut4_symbolic,
+                registered: combinational,
+            },
+            Primitive {
+                name: "PFUMX",
+                kind: PrimitiveKind::Mux,
+                inputs: one_bit(&["ALUT", "BLUT", "C0"]),
+                outputs: vec![("Z", 1)],
+                semantics: pfumx,
+                symbolic: pfumx_symbolic,
+                registered: combinational,
+            },
+            Primitive {
+                name: "L6MUX21",
+                kind: PrimitiveKind::Mux,
+                inputs: one_bit(&["D0", "D1", "SD"]),
+                outputs: vec![("Z", 1)],
+                semantics: l6mux21,
+                symbolic: l6mux21_symbolic,
+                registered: combinational,
+            },
+            Primitive {
+                name: "CCU2C",
+                kind: PrimitiveKind::Carry,
+                inputs: one_bit(&["A0", "B0", "C0", "D0", "A1", "B1", "C1", "D1", "CIN"]),
+                outputs: one_bit(&["S0", "S1", "COUT"]),
+                semantics: ccu2c,
+                symbolic: ccu2c_symbolic,
+                registered: combinational,
+            },
+            Primitive {
+                name: "MULT18X18D",
+                kind: PrimitiveKind::Dsp,
+                inputs: one_bit(
+                    &[&MULT_A[..], &MULT_B[..], &MULT_C[..], &MULT_CONTROL[..]].concat(),
+                ),
+                outputs: one_bit(&MULT_P),
+                semantics: mult18x18d,
+                symbolic: mult18x18d_symbolic,
+                registered: mult18x18d_registered,
+            },
+            Primitive {
+                name: "FD1S3AX",
+                kind: PrimitiveKind::Register,
+                inputs: one_bit(&["CK", "D"]),
+                outputs: one_bit(&["Q"]),
+                semantics: fd1s3ax,
+                symbolic: fd1s3ax_symbolic,
+                registered: always_registered,
+            },
+        ]
+    }
+
+    fn lut_inputs(&self) -> usize {
+        4
+    }
+
+    fn multiplier_width(&self) -> usize {
+        18
+    }
+
+    /// A `PFUMX` and then an `L6MUX21`, for up to six inputs.
+    fn mux_levels(&self) -> usize {
+        2
+    }
+
+    /// A `PFUMX` of two `LUT4`s, or an `L6MUX21` of two `PFUMX`s.
+    fn mux(&self, netlist: &mut Netlist, sel: &Bit, lo: &Bit, hi: &Bit) -> Option<Bit> {
+        let (primitive, ports) = match (netlist.driver(lo)?, netlist.driver(hi)?) {
+            ("LUT4", "LUT4") => ("PFUMX", ["BLUT", "ALUT", "C0"]),
+            ("PFUMX", "PFUMX") => ("L6MUX21", ["D0", "D1", "SD"]),
+            _ => return None,
+        };
+        let cell = netlist.add(Cell {
+            primitive,
+            parameters: Parameters::new(),
+            inputs: ports
+                .into_iter()
+                .zip([lo, hi, sel])
+                .map(|(port, bit)| (port, vec![bit.clone()]))
+                .collect(),
+        });
+        Some(Bit::Output {
+            cell,
+            port: "Z",
+            bit: 0,
+        })
+    }
+
+    fn lut(
+        &self,
+        netlist: &mut Netlist,
+        inputs: &[Bit],
+        table: &[bool],
+    ) -> Result<Bit, LakeroadError> {
+        // As for the LUT6, the table is repeated for inputs past the
+        // function's, which are tied to zero.
+        let init = (0..16)
+            .filter(|n| table[n % table.len()])
+            .fold(0u128, |init, n| init | 1 << n);
+        let cell = netlist.add(Cell {
+            primitive: "LUT4",
+            parameters: [("INIT".to_string(), Parameter::Bits(BitVec::new(16, init)))]
+                .into_iter()
+                .collect(),
+            inputs: ["A", "B", "C", "D"]
+                .into_iter()
+                .zip(inputs)
+                .map(|(port, bit)| (port, vec![bit.clone()]))
+                .collect(),
+        });
+        Ok(Bit::Output {
+            cell,
+            port: "Z",
+            bit: 0,
+        })
+    }
+
+    /// A `CCU2C` per two bits, whose LUT4s compute `a ^ b` (`a ^ !b` to
+    /// subtract) from `A` and `B`, with `C` and `D` tied high, and whose
+    /// LUT2s pass `a` through to generate the carry.
+    fn adder(
+        &self,
+        netlist: &mut Netlist,
+        a: &[Bit],
+        b: &[Bit],
+        subtract: bool,
+    ) -> Result<Vec<Bit>, LakeroadError> {
+        // Rows 12 to 15 of the LUT4 are where `C` and `D` are high, and rows
+        // 0 to 3 are the LUT2's.
+        let init = if subtract { 0x900a } else { 0x600a };
+        let string = |value: &str| Parameter::String(value.to_string());
+        let parameters = [
+            ("INIT0".to_string(), Parameter::Bits(BitVec::new(16, init))),
+            ("INIT1".to_string(), Parameter::Bits(BitVec::new(16, init))),
+            ("INJECT1_0".to_string(), string("NO")),
+            ("INJECT1_1".to_string(), string("NO")),
+        ]
+        .into_iter()
+        .collect::<Parameters>();
+        let high = Bit::Const(true);
+        let mut carry = Bit::Const(subtract);
+        let mut out = vec![];
+        for (a, b) in a.chunks(2).zip(b.chunks(2)) {
+            let mut inputs = vec![("CIN", vec![carry])];
+            for (i, (a, b)) in a.iter().zip(b).enumerate() {
+                let [a_port, b_port, c_port, d_port] =
+                    [["A0", "B0", "C0", "D0"], ["A1", "B1", "C1", "D1"]][i];
+                inputs.push((a_port, vec![a.clone()]));
+                inputs.push((b_port, vec![b.clone()]));
+                inputs.push((c_port, vec![high.clone()]));
+                inputs.push((d_port, vec![high.clone()]));
+            }
+            let cell = netlist.add(Cell {
+                primitive: "CCU2C",
+                parameters: parameters.clone(),
+                inputs: inputs.into_iter().collect(),
+            });
+            for port in ["S0", "S1"].into_iter().take(a.len()) {
+                out.extend(netlist.outputs(cell, port));
+            }
+            carry = netlist.outputs(cell, "COUT")[0].clone();
+        }
+        Ok(out)
+    }
+
+    /// A `MULT18X18D`, which can only multiply, at up to 18 bits.
+    fn dsp(
+        &self,
+        netlist: &mut Netlist,
+        config: &DspConfig,
+        inputs: &[Vec<Bit>],
+    ) -> Result<Vec<Bit>, LakeroadError> {
+        let width = inputs[0].len();
+        let multiply_only = config.multiply
+            && config.pre_adder.is_none()
+            && config.alu.is_none()
+            && !config.pattern_detect;
+        if !multiply_only || width > 18 {
+            return unsupported(format!(
+                "{} at {} bits on a MULT18X18D",
+                config.name(),
+                width
+            ));
+        }
+        let string = |value: &str| Parameter::String(value.to_string());
+        let mut parameters = MULT_REGISTERS
+            .iter()
+            .map(|register| (register.to_string(), string("NONE")))
+            .collect::<Parameters>();
+        for (name, value) in [
+            ("MULT_BYPASS", "DISABLED"),
+            ("CAS_MATCH_REG", "FALSE"),
+            ("SOURCEB_MODE", "B_SHIFT"),
+            ("GSR", "ENABLED"),
+            ("RESETMODE", "SYNC"),
+        ] {
+            parameters.insert(name.to_string(), string(value));
+        }
+        // Unsigned, so the operands are zero-extended.
+        let mut ports = vec![];
+        for (bus, bits) in [(&MULT_A, &inputs[0]), (&MULT_B, &inputs[1])] {
+            for (i, port) in bus.iter().enumerate() {
+                let bit = bits.get(i).cloned().unwrap_or(Bit::Const(false));
+                ports.push((*port, vec![bit]));
+            }
+        }
+        ports.push(("SIGNEDA", const_bits(0, 1)));
+        ports.push(("SIGNEDB", const_bits(0, 1)));
+        let cell = netlist.add(Cell {
+            primitive: "MULT18X18D",
+            parameters,
+            inputs: ports.into_iter().collect(),
+        });
+        Ok(MULT_P[..width]
+            .iter()
+            .flat_map(|port| netlist.outputs(cell, *port))
+            .collect())
+    }
+
+    /// An `FD1S3AX` per bit which isn't always zero. They're cleared by the
+    /// global set/reset, so start at zero.
+    fn register(&self, netlist: &mut Netlist, d: &[Bit]) -> Result<Vec<Bit>, LakeroadError> {
+        Ok(d.iter()
+            .map(|bit| match bit {
+                Bit::Const(false) => bit.clone(),
+                _ => {
+                    let cell = netlist.add(Cell {
+                        primitive: "FD1S3AX",
+                        parameters: [("GSR".to_string(), Parameter::String("ENABLED".to_string()))]
+                            .into_iter()
+                            .collect(),
+                        inputs: [("CK", vec![Bit::Clock]), ("D", vec![bit.clone()])]
+                            .into_iter()
+                            .collect(),
+                    });
+                    netlist.outputs(cell, "Q").remove(0)
+                }
+            })
+            .collect())
+    }
+
+    /// Clocks a `MULT18X18D`'s output register.
+    fn absorb_register(&self, netlist: &mut Netlist, cell: usize) -> bool {
+        let cell = &mut netlist.cells[cell];
+        if cell.primitive != "MULT18X18D" || mult18x18d_registered(&cell.parameters) {
+            return false;
+        }
+        cell.parameters.insert(
+            "REG_OUTPUT_CLK".to_string(),
+            Parameter::String("CLK0".to_string()),
+        );
+        cell.inputs.insert("CE0", vec![Bit::Const(true)]);
+        cell.inputs.insert("CLK0", vec![Bit::Clock]);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::RecExpr;
+
+    use super::*;
+    use crate::{
+        ast::Expr,
+        equiv::{shared_vars, test_vectors},
+        eval::eval_expr,
+        language::{Language, Op},
+        lut::map_to_luts,
+    };
+
+    #[test]
+    fn map_to_ecp5() {
+        let arch = Ecp5;
+        let expr = |s: &str| RecExpr::<Language>::from_str(s).unwrap();
+        let map = |program: &RecExpr<Language>| {
+            let netlist = Netlist::map(&arch, program).unwrap();
+            let expr = Expr::try_from(program).unwrap();
+            for inputs in test_vectors(&shared_vars(&expr, &expr).unwrap(), 64, 0) {
+                assert_eq!(
+                    netlist.eval(&inputs).unwrap(),
+                    eval_expr(&expr, &inputs).unwrap(),
+                    "{:?}",
+                    inputs
+                );
+            }
+            netlist
+        };
+        let primitives = |netlist: &Netlist| {
+            netlist
+                .cells
+                .iter()
+                .map(|cell| cell.primitive)
+                .collect::<Vec<_>>()
+        };
+
+        // The same program as on UltraScale+, which takes more than one
+        // LUT4 here.
+        let program = expr(
+            "(binop xor 1 (binop and 1 (var a 1) (binop or 1 (var b 1) (var c 1))) \
+             (binop xor 1 (binop or 1 (var d 1) (var e 1)) (binop and 1 (var f 1) (var g 1))))",
+        );
+        let mapping = map_to_luts(&program, arch.lut_inputs(), 10).unwrap();
+        let netlist = map(&mapping.expr);
+        assert!(mapping.luts > 1);
+        assert_eq!(primitives(&netlist), vec!["LUT4"; mapping.luts]);
+        assert!(netlist.to_verilog("top").contains("LUT4 #(.INIT(16'h"));
+
+        // Five bits take three CCU2Cs, the last half used.
+        let netlist = map(&expr("(binop sub 5 (var x 5) (var y 5))"));
+        assert_eq!(primitives(&netlist), vec!["CCU2C"; 3]);
+        assert!(map(&expr("(binop add 5 (var x 5) (const 3 5))"))
+            .to_verilog("add")
+            .contains(".INJECT1_0(\"NO\")"));
+
+        let netlist = map(&expr("(binop mul 12 (var a 12) (var b 12))"));
+        assert_eq!(primitives(&netlist), vec!["MULT18X18D"]);
+        // Six inputs take two levels of muxes.
+        let mut netlist = Netlist::new(&arch);
+        netlist.inputs.push(("x".to_string(), 6));
+        let inputs = (0..6)
+            .map(|bit| Bit::Input {
+                name: "x".to_string(),
+                bit,
+            })
+            .collect::<Vec<_>>();
+        let parity = (0..64u32)
+            .map(|n| n.count_ones() % 2 == 1)
+            .collect::<Vec<_>>();
+        let out = netlist.lut(&arch, &inputs, &parity).unwrap();
+        netlist.output = vec![out];
+        assert_eq!(
+            primitives(&netlist),
+            vec!["LUT4", "LUT4", "PFUMX", "LUT4", "LUT4", "PFUMX", "L6MUX21"]
+        );
+        for n in 0..64u32 {
+            let inputs = [("x".to_string(), BitVec::new(6, n as u128))]
+                .into_iter()
+                .collect();
+            assert_eq!(
+                netlist.eval(&inputs).unwrap(),
+                BitVec::new(1, parity[n as usize] as u128)
+            );
+        }
+
+        let mac = DspConfig {
+            pre_adder: None,
+            multiply: true,
+            alu: Some(Op::Add),
+            pattern_detect: false,
+        };
+        assert!(matches!(
+            arch.dsp(&mut Netlist::new(&arch), &mac, &[vec![], vec![], vec![]]),
+            Err(LakeroadError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,1280 @@
+//! Architecture descriptions: an FPGA's primitives, what they compute, and
+//! how to build LUTs, adders, and DSP configurations out of them.
+//!
+//! [`Netlist::map`] maps a program, as covered by e.g.
+//! [`crate::lut::map_to_luts`], onto an [`Architecture`]'s primitives, and
+//! [`Netlist::to_verilog`] writes the result as structural Verilog, which
+//! vendor tools take as it is instead of re-mapping it. [`Netlist::eval`]
+//! evaluates the netlist with the primitives' semantics, so a mapping can
+//! be checked against the program it came from, and [`Netlist::count`]
+//! counts its cells by [`PrimitiveKind`], which [`cost`] extracts
+//! implementations by. Each primitive also has symbolic semantics, as SMT
+//! terms, so [`Netlist::verify`] proves a mapping correct for every input
+//! rather than testing it.
+//!
+//! Programs with `reg`s map onto registers: flip-flops, or the output
+//! registers of primitives which have them, e.g. a DSP's. Cells whose
+//! outputs are registered show the outputs their semantics computes a cycle
+//! later, so [`Netlist::eval_cycles`] evaluates a netlist cycle by cycle,
+//! and [`Netlist::verify`] unrolls both sides over as many cycles as their
+//! registers look back.
+//!
+//! The architectures cover the same primitives as the YAML descriptions in
+//! `architecture_descriptions/`, which the Racket backend reads, but carry
+//! their semantics in Rust, so that mappings can be checked without Racket.
+
+pub mod cost;
+pub mod lattice;
+pub mod xilinx;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+};
+
+use egg::RecExpr;
+use serde::{Deserialize, Serialize};
+
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+use crate::smt::{symbolic, symbolic_cycles, SmtSolver};
+
+use crate::{
+    ast::{Ast, Expr, Instr},
+    bitvec::BitVec,
+    dsp::DspConfig,
+    equiv::TestVector,
+    error::LakeroadError,
+    language::{Language, Op},
+    smt::{cycle_var, Term},
+    truth_table::{cofactor, mux_select, TruthTable},
+};
+
+/// The value of one of a cell's parameters (Verilog's `#(...)`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Parameter {
+    Bits(BitVec),
+    Int(i64),
+    String(String),
+}
+
+impl Parameter {
+    fn to_verilog(&self) -> String {
+        match self {
+            Parameter::Bits(value) => format!("{}'h{}", value.width(), value.to_hex()),
+            Parameter::Int(value) => value.to_string(),
+            Parameter::String(value) => format!("{:?}", value),
+        }
+    }
+}
+
+/// The parameters of a cell, by name.
+pub type Parameters = BTreeMap<String, Parameter>;
+
+/// The values on a cell's ports, by name.
+pub type PortValues = BTreeMap<String, BitVec>;
+
+/// What a primitive computes: its outputs, given its parameters and the
+/// values on all of its inputs, before any output register. Fails with
+/// [`LakeroadError::Unsupported`] for settings the model doesn't cover, e.g.
+/// pipeline registers.
+pub type Semantics = fn(&Parameters, &PortValues) -> Result<PortValues, LakeroadError>;
+
+/// The terms on a cell's ports, by name.
+pub type PortTerms = BTreeMap<String, Term>;
+
+/// [`Semantics`] as SMT terms: the terms on a primitive's outputs, given its
+/// parameters and the terms on its inputs, each as wide as its port. Control
+/// inputs the semantics decodes must be constants.
+pub type SymbolicSemantics = fn(&Parameters, &PortTerms) -> Result<PortTerms, LakeroadError>;
+
+/// What a primitive is for, which is what cost models count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrimitiveKind {
+    Lut,
+    /// A multiplexer combining LUTs into wider ones, e.g. `MUXF7`.
+    Mux,
+    /// A carry chain, or a slice of one.
+    Carry,
+    Dsp,
+    /// A flip-flop.
+    Register,
+}
+
+/// A cell type an architecture provides.
+#[derive(Debug, Clone)]
+pub struct Primitive {
+    /// The name vendor tools know it by, e.g. `LUT6`.
+    pub name: &'static str,
+    pub kind: PrimitiveKind,
+    /// The input ports and their widths. Inputs a cell doesn't connect are
+    /// tied to zero.
+    pub inputs: Vec<(&'static str, usize)>,
+    /// The output ports the semantics computes, and their widths.
+    pub outputs: Vec<(&'static str, usize)>,
+    pub semantics: Semantics,
+    /// The same semantics, which must agree with `semantics`.
+    pub symbolic: SymbolicSemantics,
+    /// Whether a cell with the given parameters registers its outputs, on
+    /// the design's clock.
+    pub registered: fn(&Parameters) -> bool,
+}
+
+impl Primitive {
+    fn input_width(&self, port: &str) -> Option<usize> {
+        self.inputs
+            .iter()
+            .find(|(name, _)| *name == port)
+            .map(|(_, width)| *width)
+    }
+
+    fn output_width(&self, port: &str) -> Option<usize> {
+        self.outputs
+            .iter()
+            .find(|(name, _)| *name == port)
+            .map(|(_, width)| *width)
+    }
+}
+
+/// A parameter a primitive's semantics needs.
+pub(crate) fn parameter<'a>(
+    parameters: &'a Parameters,
+    name: &str,
+) -> Result<&'a Parameter, LakeroadError> {
+    parameters
+        .get(name)
+        .ok_or_else(|| LakeroadError::Malformed(format!("missing parameter {}", name)))
+}
+
+/// A string parameter a primitive's semantics needs.
+pub(crate) fn string_parameter<'a>(
+    parameters: &'a Parameters,
+    name: &str,
+) -> Result<&'a str, LakeroadError> {
+    match parameter(parameters, name)? {
+        Parameter::String(value) => Ok(value),
+        other => Err(LakeroadError::Malformed(format!(
+            "parameter {} is {:?}, not a string",
+            name, other
+        ))),
+    }
+}
+
+/// A bitvector parameter a primitive's semantics needs.
+pub(crate) fn bits_parameter<'a>(
+    parameters: &'a Parameters,
+    name: &str,
+) -> Result<&'a BitVec, LakeroadError> {
+    match parameter(parameters, name)? {
+        Parameter::Bits(value) => Ok(value),
+        other => Err(LakeroadError::Malformed(format!(
+            "parameter {} is {:?}, not a bitvector",
+            name, other
+        ))),
+    }
+}
+
+/// [`Primitive::registered`] for primitives without output registers.
+pub(crate) fn combinational(_: &Parameters) -> bool {
+    false
+}
+
+/// [`Primitive::registered`] for flip-flops.
+pub(crate) fn always_registered(_: &Parameters) -> bool {
+    true
+}
+
+/// The value on an input port, which must be at most 128 bits wide.
+pub(crate) fn port_value(values: &PortValues, port: &str) -> Result<u128, LakeroadError> {
+    values
+        .get(port)
+        .and_then(|value| value.to_u128())
+        .ok_or_else(|| LakeroadError::Malformed(format!("no value on port {}", port)))
+}
+
+/// `value`, `width` bits wide, read as signed.
+pub(crate) fn signed(value: u128, width: usize) -> i128 {
+    ((value << (128 - width)) as i128) >> (128 - width)
+}
+
+/// The term on an input port.
+pub(crate) fn port_term<'a>(terms: &'a PortTerms, port: &str) -> Result<&'a Term, LakeroadError> {
+    terms
+        .get(port)
+        .ok_or_else(|| LakeroadError::Malformed(format!("no term on port {}", port)))
+}
+
+/// The value on an input port which must be a constant, e.g. a mode.
+pub(crate) fn const_port(terms: &PortTerms, port: &str) -> Result<u128, LakeroadError> {
+    match port_term(terms, port)?.as_const().map(BitVec::to_u128) {
+        Some(Some(value)) => Ok(value),
+        _ => unsupported(format!("a non-constant {}", port)),
+    }
+}
+
+/// The bit of `init` indexed by `inputs`, read as a number, `inputs[0]`
+/// least significant, as a LUT computes it.
+pub(crate) fn lut_term(init: &BitVec, inputs: &[Term]) -> Term {
+    let index = match inputs {
+        [input] => input.clone(),
+        _ => Term::app("concat", inputs.iter().rev().cloned().collect()),
+    };
+    Term::app(
+        "bvlshr",
+        vec![
+            Term::Const(init.clone()),
+            index.extend(false, init.width() - inputs.len()),
+        ],
+    )
+    .extract(0, 0)
+}
+
+/// `(lhs & sel) | (rhs & ~sel)`, of one-bit terms.
+pub(crate) fn mux_term(sel: &Term, lhs: &Term, rhs: &Term) -> Term {
+    Term::app(
+        "bvor",
+        vec![
+            Term::app("bvand", vec![sel.clone(), lhs.clone()]),
+            Term::app(
+                "bvand",
+                vec![Term::app("bvnot", vec![sel.clone()]), rhs.clone()],
+            ),
+        ],
+    )
+}
+
+pub(crate) fn unsupported<T>(what: String) -> Result<T, LakeroadError> {
+    Err(LakeroadError::Unsupported(what))
+}
+
+/// The bits of `value`, truncated to `width`, least significant first.
+pub(crate) fn const_bits(value: u128, width: usize) -> Vec<Bit> {
+    (0..width)
+        .map(|bit| Bit::Const(bit < 128 && value >> bit & 1 == 1))
+        .collect()
+}
+
+/// `bits`, zero-extended or truncated to `width`.
+pub(crate) fn padded(bits: &[Bit], width: usize) -> Vec<Bit> {
+    let mut out = bits.iter().take(width).cloned().collect::<Vec<_>>();
+    out.resize(width, Bit::Const(false));
+    out
+}
+
+/// One bit of a signal in a [`Netlist`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Bit {
+    Const(bool),
+    /// Bit `bit` of the design's input `name`.
+    Input {
+        name: String,
+        bit: usize,
+    },
+    /// Bit `bit` of output `port` of the `cell`th cell.
+    Output {
+        cell: usize,
+        port: &'static str,
+        bit: usize,
+    },
+    /// The design's clock, `clk`, which registers are clocked by. Its value
+    /// is low as far as the semantics go.
+    Clock,
+}
+
+impl Bit {
+    fn to_verilog(&self) -> String {
+        match self {
+            Bit::Const(value) => format!("1'b{}", *value as u8),
+            Bit::Clock => "clk".to_string(),
+            Bit::Input { name, bit } => format!("{}[{}]", name, bit),
+            Bit::Output { cell, port, bit } => format!("c{}_{}[{}]", cell, port, bit),
+        }
+    }
+}
+
+/// An instance of a primitive.
+#[derive(Debug, Clone)]
+pub struct Cell {
+    /// The name of its [`Primitive`].
+    pub primitive: &'static str,
+    pub parameters: Parameters,
+    /// The bits on each input port, least significant first. Ports which
+    /// aren't listed are tied to zero.
+    pub inputs: BTreeMap<&'static str, Vec<Bit>>,
+}
+
+/// Cells of an architecture's primitives, in an order where each cell only
+/// reads the design's inputs and the outputs of the cells before it.
+#[derive(Debug, Clone)]
+pub struct Netlist {
+    primitives: Vec<Primitive>,
+    /// The design's inputs and their widths.
+    pub inputs: Vec<(String, usize)>,
+    pub cells: Vec<Cell>,
+    /// The design's output, least significant bit first.
+    pub output: Vec<Bit>,
+}
+
+/// How many cells of each [`PrimitiveKind`] a netlist has.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrimitiveCount {
+    pub luts: usize,
+    pub muxes: usize,
+    pub carries: usize,
+    pub dsps: usize,
+    #[serde(default)]
+    pub registers: usize,
+}
+
+impl Display for PrimitiveCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} LUTs, {} muxes, {} carries, {} DSPs, {} registers",
+            self.luts, self.muxes, self.carries, self.dsps, self.registers
+        )
+    }
+}
+
+/// An FPGA architecture, as far as mapping programs onto it goes.
+pub trait Architecture {
+    /// The name to select it by, e.g. on the command line.
+    fn name(&self) -> &'static str;
+
+    fn primitives(&self) -> Vec<Primitive>;
+
+    /// The most inputs [`lut`](Self::lut) can take.
+    fn lut_inputs(&self) -> usize;
+
+    /// How many levels of [`mux`](Self::mux)es can combine LUTs of
+    /// [`lut_inputs`](Self::lut_inputs) inputs, so that any function of that
+    /// many more inputs takes only LUTs and dedicated muxes.
+    fn mux_levels(&self) -> usize;
+
+    /// The widest operands [`dsp`](Self::dsp) can multiply. Wider products
+    /// are split into partial products of half this width.
+    fn multiplier_width(&self) -> usize;
+
+    /// Adds cells which compute the function of `inputs` whose output on
+    /// the inputs read as a number, `inputs[0]` least significant, is
+    /// `table[n]`. `inputs` holds no constants, and the function depends on
+    /// each of them.
+    fn lut(
+        &self,
+        netlist: &mut Netlist,
+        inputs: &[Bit],
+        table: &[bool],
+    ) -> Result<Bit, LakeroadError>;
+
+    /// Adds a dedicated mux computing `hi` if `sel` is set and `lo`
+    /// otherwise, e.g. a `MUXF7` combining two LUTs, if the architecture has
+    /// one which can take `lo` and `hi` from the cells they come from.
+    fn mux(&self, netlist: &mut Netlist, sel: &Bit, lo: &Bit, hi: &Bit) -> Option<Bit>;
+
+    /// Adds cells which compute `a + b`, or `a - b` if `subtract`. `a` and
+    /// `b` have the same width, as does the result.
+    fn adder(
+        &self,
+        netlist: &mut Netlist,
+        a: &[Bit],
+        b: &[Bit],
+        subtract: bool,
+    ) -> Result<Vec<Bit>, LakeroadError>;
+
+    /// Adds cells which compute `config` of `inputs`, in the order of
+    /// [`DspConfig::inputs`], each as wide as the result.
+    fn dsp(
+        &self,
+        netlist: &mut Netlist,
+        config: &DspConfig,
+        inputs: &[Vec<Bit>],
+    ) -> Result<Vec<Bit>, LakeroadError>;
+
+    /// Adds cells which delay `d` by a cycle, and are zero in the first.
+    fn register(&self, netlist: &mut Netlist, d: &[Bit]) -> Result<Vec<Bit>, LakeroadError>;
+
+    /// Turns on the output register of the `cell`th cell, if its primitive
+    /// has one, returning whether it did.
+    fn absorb_register(&self, netlist: &mut Netlist, cell: usize) -> bool;
+}
+
+/// The architectures [`by_name`] knows.
+pub fn architectures() -> Vec<Box<dyn Architecture>> {
+    vec![Box::new(xilinx::UltraScalePlus), Box::new(lattice::Ecp5)]
+}
+
+/// The architecture named `name`, e.g. `xilinx-ultrascale-plus`.
+pub fn by_name(name: &str) -> Result<Box<dyn Architecture>, LakeroadError> {
+    architectures()
+        .into_iter()
+        .find(|arch| arch.name() == name)
+        .ok_or_else(|| {
+            LakeroadError::Config(format!(
+                "unknown architecture {:?}; expected one of {}",
+                name,
+                architectures()
+                    .iter()
+                    .map(|arch| arch.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })
+}
+
+impl Netlist {
+    /// An empty netlist of `arch`'s primitives.
+    pub fn new(arch: &dyn Architecture) -> Self {
+        Netlist {
+            primitives: arch.primitives(),
+            inputs: vec![],
+            cells: vec![],
+            output: vec![],
+        }
+    }
+
+    fn primitive(&self, name: &str) -> &Primitive {
+        self.primitives
+            .iter()
+            .find(|primitive| primitive.name == name)
+            .unwrap_or_else(|| panic!("no primitive {}", name))
+    }
+
+    /// How many cells of each kind the netlist has.
+    pub fn count(&self) -> PrimitiveCount {
+        let mut count = PrimitiveCount::default();
+        for cell in &self.cells {
+            let n = match self.primitive(cell.primitive).kind {
+                PrimitiveKind::Lut => &mut count.luts,
+                PrimitiveKind::Mux => &mut count.muxes,
+                PrimitiveKind::Carry => &mut count.carries,
+                PrimitiveKind::Dsp => &mut count.dsps,
+                PrimitiveKind::Register => &mut count.registers,
+            };
+            *n += 1;
+        }
+        count
+    }
+
+    /// Whether the `cell`th cell registers its outputs.
+    fn registered(&self, cell: usize) -> bool {
+        let cell = &self.cells[cell];
+        (self.primitive(cell.primitive).registered)(&cell.parameters)
+    }
+
+    /// The most registers on a path from an input to the output: how many
+    /// cycles back the output depends on. Zero means the netlist is
+    /// combinational.
+    pub fn reg_depth(&self) -> usize {
+        let mut depths: Vec<usize> = vec![];
+        let depth = |bits: &[Bit], depths: &[usize]| {
+            bits.iter()
+                .map(|bit| match bit {
+                    Bit::Output { cell, .. } => depths[*cell],
+                    _ => 0,
+                })
+                .max()
+                .unwrap_or(0)
+        };
+        for (i, cell) in self.cells.iter().enumerate() {
+            let inputs = cell
+                .inputs
+                .values()
+                .map(|bits| depth(bits, &depths))
+                .max()
+                .unwrap_or(0);
+            depths.push(inputs + self.registered(i) as usize);
+        }
+        depth(&self.output, &depths)
+    }
+
+    /// Adds `cell`, returning its index. Panics if the cell doesn't match
+    /// its primitive's ports, which is a bug in the architecture.
+    pub fn add(&mut self, cell: Cell) -> usize {
+        let primitive = self.primitive(cell.primitive);
+        for (port, bits) in &cell.inputs {
+            let width = primitive
+                .input_width(port)
+                .unwrap_or_else(|| panic!("{} has no input {}", primitive.name, port));
+            assert_eq!(bits.len(), width, "{}.{}", primitive.name, port);
+        }
+        self.cells.push(cell);
+        self.cells.len() - 1
+    }
+
+    /// The bits of output `port` of the `cell`th cell.
+    pub fn outputs(&self, cell: usize, port: &'static str) -> Vec<Bit> {
+        let width = self
+            .primitive(self.cells[cell].primitive)
+            .output_width(port)
+            .unwrap_or_else(|| panic!("{} has no output {}", self.cells[cell].primitive, port));
+        (0..width)
+            .map(|bit| Bit::Output { cell, port, bit })
+            .collect()
+    }
+
+    /// Adds cells computing the function of `inputs` given by `table`, as
+    /// for [`Architecture::lut`], after dropping constant inputs and inputs
+    /// the function doesn't depend on. Functions of no inputs are constants
+    /// and functions of just one input are that input, so they take no
+    /// cells. Functions of more inputs than `arch`'s LUTs take become mux
+    /// trees.
+    pub fn lut(
+        &mut self,
+        arch: &dyn Architecture,
+        inputs: &[Bit],
+        table: &[bool],
+    ) -> Result<Bit, LakeroadError> {
+        let mut inputs = inputs.to_vec();
+        let mut table = table.to_vec();
+        let mut i = 0;
+        while i < inputs.len() {
+            // The rows where input `i` is `value`.
+            let half = |value: bool| {
+                (0..table.len())
+                    .filter(|n| (n >> i & 1 == 1) == value)
+                    .map(|n| table[n])
+                    .collect::<Vec<_>>()
+            };
+            match &inputs[i] {
+                Bit::Const(value) => table = half(*value),
+                _ if half(false) == half(true) => table = half(false),
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            }
+            inputs.remove(i);
+        }
+        if inputs.is_empty() {
+            return Ok(Bit::Const(table[0]));
+        }
+        if table == [false, true] {
+            return Ok(inputs[0].clone());
+        }
+        if inputs.len() > arch.lut_inputs() {
+            return self.mux_tree(arch, &inputs, &table);
+        }
+        arch.lut(self, &inputs, &table)
+    }
+
+    /// Adds cells computing a function of more inputs than `arch`'s LUTs
+    /// take: a LUT (or tree of them) for each value of the input
+    /// [`mux_select`] picks, combined by a mux on it.
+    fn mux_tree(
+        &mut self,
+        arch: &dyn Architecture,
+        inputs: &[Bit],
+        table: &[bool],
+    ) -> Result<Bit, LakeroadError> {
+        let select = mux_select(table);
+        let mut rest = inputs.to_vec();
+        let sel = rest.remove(select);
+        let lo = self.lut(arch, &rest, &cofactor(table, select, false))?;
+        let hi = self.lut(arch, &rest, &cofactor(table, select, true))?;
+        self.mux(arch, &sel, lo, hi)
+    }
+
+    /// Adds cells computing `hi` if `sel` is set and `lo` otherwise: one of
+    /// `arch`'s dedicated muxes if it can take them, and a LUT otherwise.
+    pub fn mux(
+        &mut self,
+        arch: &dyn Architecture,
+        sel: &Bit,
+        lo: Bit,
+        hi: Bit,
+    ) -> Result<Bit, LakeroadError> {
+        if lo == hi {
+            return Ok(lo);
+        }
+        if let Some(out) = arch.mux(self, sel, &lo, &hi) {
+            return Ok(out);
+        }
+        // Indexed by `lo`'s bit, plus twice `hi`'s, plus four times `sel`'s.
+        let table = [false, true, false, true, false, false, true, true];
+        self.lut(arch, &[lo, hi, sel.clone()], &table)
+    }
+
+    /// The primitive of the cell `bit` comes from, if it comes from one.
+    fn driver(&self, bit: &Bit) -> Option<&'static str> {
+        match bit {
+            Bit::Output { cell, .. } => Some(self.cells[*cell].primitive),
+            _ => None,
+        }
+    }
+
+    /// Maps `expr` onto `arch`'s primitives. `apply`s of instrs which are
+    /// [`DspConfig`]s become DSPs, unless their arguments fit in a LUT, and
+    /// other `apply`s become a LUT per output bit, or a tree of LUTs and
+    /// muxes if their arguments are more than [`Architecture::lut_inputs`]
+    /// bits wide in total. Outside `apply`s, additions and subtractions
+    /// become adders, multiplications DSPs, and bitwise operators a LUT per
+    /// bit; anything else is unsupported. `reg`s
+    /// become the output register of the cell computing their argument if
+    /// it has one, and [`Architecture::register`]s otherwise.
+    pub fn map(arch: &dyn Architecture, expr: &RecExpr<Language>) -> Result<Self, LakeroadError> {
+        let expr = Expr::try_from(expr)?;
+        let mut netlist = Netlist::new(arch);
+        for (name, width) in expr.vars() {
+            let width = usize::try_from(width)
+                .map_err(|_| LakeroadError::Malformed(format!("bitwidth {}", width)))?;
+            netlist.inputs.push((name, width));
+        }
+        netlist.output = netlist.map_expr(arch, &expr, &mut HashMap::new())?;
+        Ok(netlist)
+    }
+
+    fn map_expr(
+        &mut self,
+        arch: &dyn Architecture,
+        expr: &Expr,
+        memo: &mut HashMap<Expr, Vec<Bit>>,
+    ) -> Result<Vec<Bit>, LakeroadError> {
+        if let Some(bits) = memo.get(expr) {
+            return Ok(bits.clone());
+        }
+        let bits = match expr {
+            Expr::Var { name, width } => (0..*width as usize)
+                .map(|bit| Bit::Input {
+                    name: name.clone(),
+                    bit,
+                })
+                .collect(),
+            Expr::Const { value, width } => {
+                let value = BitVec::from_i64(*width as usize, *value);
+                (0..value.width())
+                    .map(|bit| Bit::Const(value.bit(bit)))
+                    .collect()
+            }
+            Expr::UnOp { op, arg, .. } => {
+                let arg = self.map_expr(arch, arg, memo)?;
+                match op {
+                    Op::Not => arg
+                        .iter()
+                        .map(|bit| self.lut(arch, &[bit.clone()], &[true, false]))
+                        .collect::<Result<_, _>>()?,
+                    Op::Neg => {
+                        let zero = vec![Bit::Const(false); arg.len()];
+                        arch.adder(self, &zero, &arg, true)?
+                    }
+                    _ => return Err(unmappable(op)),
+                }
+            }
+            Expr::BinOp {
+                op,
+                width,
+                lhs,
+                rhs,
+            } => {
+                let a = self.map_expr(arch, lhs, memo)?;
+                let b = self.map_expr(arch, rhs, memo)?;
+                match op {
+                    Op::And | Op::Or | Op::Xor => {
+                        // Indexed by `a`'s bit plus twice `b`'s.
+                        let table = match op {
+                            Op::And => [false, false, false, true],
+                            Op::Or => [false, true, true, true],
+                            _ => [false, true, true, false],
+                        };
+                        (0..*width as usize)
+                            .map(|i| self.lut(arch, &[a[i].clone(), b[i].clone()], &table))
+                            .collect::<Result<_, _>>()?
+                    }
+                    Op::Add | Op::Sub => arch.adder(self, &a, &b, *op == Op::Sub)?,
+                    Op::Mul if a.len() > arch.multiplier_width() => {
+                        self.wide_multiply(arch, &a, &b)?
+                    }
+                    Op::Mul => {
+                        let config = DspConfig {
+                            pre_adder: None,
+                            multiply: true,
+                            alu: None,
+                            pattern_detect: false,
+                        };
+                        arch.dsp(self, &config, &[a, b])?
+                    }
+                    _ => return Err(unmappable(op)),
+                }
+            }
+            Expr::Apply { instr, args } => {
+                let args = hole_args(instr, args)?
+                    .into_iter()
+                    .map(|arg| self.map_expr(arch, arg, memo))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let input_bits = instr.as_expr().map_or(0, |expr| {
+                    expr.vars().iter().map(|(_, width)| *width as usize).sum()
+                });
+                match (&instr.ast, DspConfig::of_ast(&instr.ast)) {
+                    // A wire.
+                    (Ast::Hole { .. }, _) => args[0].clone(),
+                    (_, Some((config, _))) if input_bits > arch.lut_inputs() => {
+                        arch.dsp(self, &config, &args)?
+                    }
+                    _ => self.map_instr(arch, instr, &args)?,
+                }
+            }
+            Expr::Extract { hi, lo, arg } => {
+                let arg = self.map_expr(arch, arg, memo)?;
+                arg[*lo as usize..=*hi as usize].to_vec()
+            }
+            Expr::Cat { hi, lo } => {
+                let mut bits = self.map_expr(arch, lo, memo)?;
+                bits.extend(self.map_expr(arch, hi, memo)?);
+                bits
+            }
+            Expr::Reg { arg, .. } => {
+                let first = self.cells.len();
+                let d = self.map_expr(arch, arg, memo)?;
+                match self.sole_driver(&d) {
+                    Some(cell) if cell >= first && arch.absorb_register(self, cell) => {
+                        // The cell's outputs are a cycle late now, so they
+                        // no longer compute what they were mapped for.
+                        memo.retain(|_, bits| {
+                            !bits
+                                .iter()
+                                .any(|bit| matches!(bit, Bit::Output { cell: c, .. } if *c == cell))
+                        });
+                        d
+                    }
+                    _ => arch.register(self, &d)?,
+                }
+            }
+        };
+        memo.insert(expr.clone(), bits.clone());
+        Ok(bits)
+    }
+
+    /// Maps `a * b`, which is too wide for `arch`'s multipliers, to the
+    /// products of chunks of half a multiplier's width, each on a DSP,
+    /// shifted into place and summed with adders.
+    fn wide_multiply(
+        &mut self,
+        arch: &dyn Architecture,
+        a: &[Bit],
+        b: &[Bit],
+    ) -> Result<Vec<Bit>, LakeroadError> {
+        let width = a.len();
+        let chunk = arch.multiplier_width() / 2;
+        let config = DspConfig {
+            pre_adder: None,
+            multiply: true,
+            alu: None,
+            pattern_detect: false,
+        };
+        // The chunk at `lo`, zero-extended so that its product is exact.
+        let operand =
+            |bits: &[Bit], lo: usize| padded(&bits[lo..(lo + chunk).min(width)], 2 * chunk);
+        let mut sum: Option<Vec<Bit>> = None;
+        for i in (0..width).step_by(chunk) {
+            // Products which land at or above `width` are truncated away.
+            for j in (0..width - i).step_by(chunk) {
+                let product = arch.dsp(self, &config, &[operand(a, i), operand(b, j)])?;
+                let mut shifted = vec![Bit::Const(false); i + j];
+                shifted.extend(product);
+                let shifted = padded(&shifted, width);
+                sum = Some(match sum {
+                    None => shifted,
+                    Some(sum) => arch.adder(self, &sum, &shifted, false)?,
+                });
+            }
+        }
+        Ok(sum.expect("bitwidths are positive"))
+    }
+
+    /// The cell all of `bits` come from, if it's one no other cell reads,
+    /// and the rest of `bits` are zero, so registering its outputs registers
+    /// `bits`.
+    fn sole_driver(&self, bits: &[Bit]) -> Option<usize> {
+        let mut driver = None;
+        for bit in bits {
+            match bit {
+                Bit::Const(false) => (),
+                Bit::Output { cell, .. } if driver.map_or(true, |driver| driver == *cell) => {
+                    driver = Some(*cell)
+                }
+                _ => return None,
+            }
+        }
+        let driver = driver?;
+        let read = self
+            .cells
+            .iter()
+            .flat_map(|cell| cell.inputs.values().flatten())
+            .any(|bit| matches!(bit, Bit::Output { cell, .. } if *cell == driver));
+        (!read).then_some(driver)
+    }
+
+    /// Maps `instr`, with the bits of the argument of each of its holes in
+    /// `args`, to a LUT per output bit.
+    fn map_instr(
+        &mut self,
+        arch: &dyn Architecture,
+        instr: &Instr,
+        args: &[Vec<Bit>],
+    ) -> Result<Vec<Bit>, LakeroadError> {
+        let table = TruthTable::of_instr(&RecExpr::from(instr))?;
+        // The table's inputs are named after canonical arguments, e.g. `a1`.
+        let mut inputs = vec![];
+        for (name, _) in &table.inputs {
+            let hole = instr
+                .canonical_args
+                .iter()
+                .position(|arg| format!("a{}", arg) == *name)
+                .ok_or_else(|| LakeroadError::Malformed(format!("no argument {}", name)))?;
+            inputs.extend(args[hole].iter().cloned());
+        }
+        (0..table.output_width)
+            .map(|bit| self.lut(arch, &inputs, &table.column(bit)))
+            .collect()
+    }
+
+    /// The design's output on `inputs` in the first cycle, where registers
+    /// are zero, according to the semantics of its primitives.
+    pub fn eval(&self, inputs: &TestVector) -> Result<BitVec, LakeroadError> {
+        Ok(self.eval_cycles(std::slice::from_ref(inputs))?.remove(0))
+    }
+
+    /// The design's output in each cycle, given the inputs in each cycle.
+    /// Registered cells' outputs are zero in the first cycle, and after that
+    /// what their semantics computed the cycle before.
+    pub fn eval_cycles(&self, inputs: &[TestVector]) -> Result<Vec<BitVec>, LakeroadError> {
+        let from_bits = |bits: Vec<bool>| {
+            let mut words = vec![0u64; (bits.len() + 63) / 64];
+            for (i, bit) in bits.iter().enumerate() {
+                words[i / 64] |= (*bit as u64) << (i % 64);
+            }
+            BitVec::from_words(bits.len(), &words)
+        };
+        // What each cell's semantics computed in the cycle before.
+        let mut last: Vec<PortValues> = vec![];
+        let mut out = vec![];
+        for inputs in inputs {
+            let value = |bit: &Bit, outputs: &[PortValues]| match bit {
+                Bit::Const(value) => Ok(*value),
+                Bit::Clock => Ok(false),
+                Bit::Input { name, bit } => inputs
+                    .get(name)
+                    .map(|value| value.bit(*bit))
+                    .ok_or_else(|| LakeroadError::Malformed(format!("no value for {}", name))),
+                Bit::Output { cell, port, bit } => Ok(outputs[*cell][*port].bit(*bit)),
+            };
+            // What each cell's outputs are in this cycle, and what its
+            // semantics computes.
+            let mut outputs: Vec<PortValues> = vec![];
+            let mut computed: Vec<PortValues> = vec![];
+            for (i, cell) in self.cells.iter().enumerate() {
+                let primitive = self.primitive(cell.primitive);
+                let mut values = PortValues::new();
+                for (port, width) in &primitive.inputs {
+                    let value = match cell.inputs.get(port) {
+                        Some(bits) => from_bits(
+                            bits.iter()
+                                .map(|bit| value(bit, &outputs))
+                                .collect::<Result<_, _>>()?,
+                        ),
+                        None => BitVec::zero(*width),
+                    };
+                    values.insert(port.to_string(), value);
+                }
+                let values = (primitive.semantics)(&cell.parameters, &values)?;
+                outputs.push(if !self.registered(i) {
+                    values.clone()
+                } else if let Some(last) = last.get(i) {
+                    last.clone()
+                } else {
+                    primitive
+                        .outputs
+                        .iter()
+                        .map(|(port, width)| (port.to_string(), BitVec::zero(*width)))
+                        .collect()
+                });
+                computed.push(values);
+            }
+            out.push(from_bits(
+                self.output
+                    .iter()
+                    .map(|bit| value(bit, &outputs))
+                    .collect::<Result<_, _>>()?,
+            ));
+            last = computed;
+        }
+        Ok(out)
+    }
+
+    /// The netlist as terms over the design's inputs, in the first cycle: a
+    /// definition of what each cell's semantics computes on each output
+    /// port, in order, named e.g. `cell0.O`, with its width, and the
+    /// design's output in terms of them.
+    pub fn symbolic(&self) -> Result<(Vec<(String, usize, Term)>, Term), LakeroadError> {
+        let (definitions, mut outputs) = self.unroll(1, &|name, _| name.to_string())?;
+        Ok((definitions, outputs.remove(0)))
+    }
+
+    /// The netlist as terms over the design's inputs in each of the first
+    /// `cycles` cycles, named as [`crate::smt::symbolic_cycles`] names
+    /// them: definitions as for [`symbolic`](Self::symbolic), of each cycle
+    /// in turn, named e.g. `cell0.O@1`, and the design's output in each
+    /// cycle.
+    pub fn symbolic_cycles(
+        &self,
+        cycles: usize,
+    ) -> Result<(Vec<(String, usize, Term)>, Vec<Term>), LakeroadError> {
+        self.unroll(cycles, &cycle_var)
+    }
+
+    fn unroll(
+        &self,
+        cycles: usize,
+        var: &dyn Fn(&str, usize) -> String,
+    ) -> Result<(Vec<(String, usize, Term)>, Vec<Term>), LakeroadError> {
+        let cell_output = |cell: usize, port: &str| -> (String, usize) {
+            let width = self
+                .primitive(self.cells[cell].primitive)
+                .output_width(port)
+                .unwrap_or(0);
+            (format!("cell{}.{}", cell, port), width)
+        };
+        let bits = |bits: &[Bit], cycle: usize| -> Result<Term, LakeroadError> {
+            // The bits with constant values. Registers are zero in the
+            // first cycle.
+            let values = bits
+                .iter()
+                .map(|bit| match bit {
+                    Bit::Const(value) => Some(*value),
+                    Bit::Clock => Some(false),
+                    Bit::Output { cell, .. } if cycle == 0 && self.registered(*cell) => Some(false),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            if let Some(values) = values.iter().cloned().collect::<Option<Vec<_>>>() {
+                let value = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| (*value as u128) << i)
+                    .sum();
+                return Ok(Term::Const(BitVec::new(values.len(), value)));
+            }
+            let mut terms = bits
+                .iter()
+                .zip(&values)
+                .rev()
+                .map(|(bit, value)| match (bit, value) {
+                    (_, Some(value)) => Ok(Term::Const(BitVec::new(1, *value as u128))),
+                    (Bit::Input { name, bit }, _) => {
+                        let width = self
+                            .inputs
+                            .iter()
+                            .find(|(input, _)| input == name)
+                            .map(|(_, width)| *width)
+                            .ok_or_else(|| {
+                                LakeroadError::Malformed(format!("no input {}", name))
+                            })?;
+                        Ok(Term::Var {
+                            name: var(name, cycle),
+                            width,
+                        }
+                        .extract(*bit, *bit))
+                    }
+                    (Bit::Output { cell, port, bit }, _) => {
+                        let (name, width) = cell_output(*cell, port);
+                        // A registered cell's outputs are what it computed
+                        // the cycle before.
+                        let cycle = cycle - self.registered(*cell) as usize;
+                        Ok(Term::Var {
+                            name: var(&name, cycle),
+                            width,
+                        }
+                        .extract(*bit, *bit))
+                    }
+                    _ => unreachable!("constant bits have values"),
+                })
+                .collect::<Result<Vec<_>, LakeroadError>>()?;
+            Ok(if terms.len() == 1 {
+                terms.remove(0)
+            } else {
+                Term::app("concat", terms)
+            })
+        };
+        let mut definitions = vec![];
+        let mut outputs = vec![];
+        for cycle in 0..cycles {
+            for (i, cell) in self.cells.iter().enumerate() {
+                let primitive = self.primitive(cell.primitive);
+                let mut terms = PortTerms::new();
+                for (port, width) in &primitive.inputs {
+                    let term = match cell.inputs.get(port) {
+                        Some(port_bits) => bits(port_bits, cycle)?,
+                        None => Term::Const(BitVec::zero(*width)),
+                    };
+                    terms.insert(port.to_string(), term);
+                }
+                let mut computed = (primitive.symbolic)(&cell.parameters, &terms)?;
+                for (port, width) in &primitive.outputs {
+                    let term = computed.remove(*port).ok_or_else(|| {
+                        LakeroadError::Malformed(format!("{} computes no {}", primitive.name, port))
+                    })?;
+                    let (name, _) = cell_output(i, port);
+                    definitions.push((var(&name, cycle), *width, term));
+                }
+            }
+            outputs.push(bits(&self.output, cycle)?);
+        }
+        Ok((definitions, outputs))
+    }
+
+    /// Whether the netlist computes `expr` for every input, as `solver`
+    /// proves from the primitives' symbolic semantics. With registers on
+    /// either side, both are unrolled over one more cycle than the most
+    /// registers on a path through either, which covers every cycle.
+    #[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+    pub fn verify(
+        &self,
+        solver: &mut SmtSolver,
+        expr: &RecExpr<Language>,
+    ) -> Result<bool, LakeroadError> {
+        let expr = Expr::try_from(expr)?;
+        match expr.reg_depth().max(self.reg_depth()) {
+            0 => {
+                let (definitions, output) = self.symbolic()?;
+                solver.equivalent_under(&definitions, &symbolic(&expr)?, &output)
+            }
+            depth => {
+                let (definitions, outputs) = self.symbolic_cycles(depth + 1)?;
+                solver.equivalent_under(
+                    &definitions,
+                    &Term::app("concat", symbolic_cycles(&expr, depth + 1)?),
+                    &Term::app("concat", outputs),
+                )
+            }
+        }
+    }
+
+    /// Writes the netlist as a Verilog module named `module`, with an input
+    /// per input of the design, a clock, `clk`, if it has registers, and a
+    /// single output, `out`, which instantiates a primitive per cell. Cell `n` is named `cn`, and its
+    /// outputs are wires named after it and the port, e.g. `c0_O`.
+    pub fn to_verilog(&self, module: &str) -> String {
+        let concat = |bits: &[Bit]| match bits {
+            [bit] => bit.to_verilog(),
+            _ => format!(
+                "{{{}}}",
+                bits.iter()
+                    .rev()
+                    .map(|bit| bit.to_verilog())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let mut ports = self
+            .inputs
+            .iter()
+            .map(|(name, width)| format!("input [{}:0] {}", width - 1, name))
+            .collect::<Vec<_>>();
+        if (0..self.cells.len()).any(|cell| self.registered(cell)) {
+            ports.push("input clk".to_string());
+        }
+        ports.push(format!("output [{}:0] out", self.output.len().max(1) - 1));
+        let mut body = String::new();
+        for (i, cell) in self.cells.iter().enumerate() {
+            let primitive = self.primitive(cell.primitive);
+            for (port, width) in &primitive.outputs {
+                body.push_str(&format!("  wire [{}:0] c{}_{};\n", width - 1, i, port));
+            }
+        }
+        for (i, cell) in self.cells.iter().enumerate() {
+            let primitive = self.primitive(cell.primitive);
+            let parameters = cell
+                .parameters
+                .iter()
+                .map(|(name, value)| format!(".{}({})", name, value.to_verilog()))
+                .collect::<Vec<_>>();
+            let mut connections = primitive
+                .inputs
+                .iter()
+                .map(|(port, width)| {
+                    let value = match cell.inputs.get(port) {
+                        Some(bits) => concat(bits),
+                        None => format!("{}'h0", width),
+                    };
+                    format!(".{}({})", port, value)
+                })
+                .collect::<Vec<_>>();
+            connections.extend(
+                primitive
+                    .outputs
+                    .iter()
+                    .map(|(port, _)| format!(".{}(c{}_{})", port, i, port)),
+            );
+            let parameters = if parameters.is_empty() {
+                String::new()
+            } else {
+                format!(" #({})", parameters.join(", "))
+            };
+            body.push_str(&format!(
+                "  {}{} c{} ({});\n",
+                primitive.name,
+                parameters,
+                i,
+                connections.join(", ")
+            ));
+        }
+        let out = if self.output.is_empty() {
+            "1'b0".to_string()
+        } else {
+            concat(&self.output)
+        };
+        format!(
+            "module {}({});\n{}  assign out = {};\nendmodule\n",
+            module,
+            ports.join(", "),
+            body,
+            out
+        )
+    }
+}
+
+/// The argument of each of `instr`'s holes, from left to right, bound as in
+/// [`crate::eval::eval_instr`].
+fn hole_args<'a>(instr: &Instr, args: &'a [Expr]) -> Result<Vec<&'a Expr>, LakeroadError> {
+    let holes = instr.ast.num_holes();
+    instr
+        .canonical_args
+        .iter()
+        .enumerate()
+        .map(|(hole, canonical)| {
+            let index = if args.len() == holes {
+                hole
+            } else {
+                *canonical as usize
+            };
+            args.get(index)
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|args| args.len() == holes)
+        .ok_or_else(|| {
+            LakeroadError::Malformed(format!(
+                "an instr with {} holes applied to {} args",
+                holes,
+                args.len()
+            ))
+        })
+}
+
+fn unmappable(op: &Op) -> LakeroadError {
+    LakeroadError::Unsupported(format!("mapping {} outside an apply", op))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{
+        arch::{lattice::Ecp5, xilinx::UltraScalePlus},
+        equiv::{shared_vars, test_vectors},
+        lut::map_to_luts,
+    };
+
+    /// Evaluates `term`, with its variables bound in `env`.
+    fn eval(term: &Term, env: &TestVector) -> BitVec {
+        let bits = |value: &BitVec| (0..value.width()).map(|i| value.bit(i)).collect::<Vec<_>>();
+        let from_bits = |bits: &[bool]| {
+            let value = bits
+                .iter()
+                .enumerate()
+                .map(|(i, bit)| (*bit as u128) << i)
+                .sum();
+            BitVec::new(bits.len(), value)
+        };
+        match term {
+            Term::Var { name, .. } => env[name].clone(),
+            Term::Const(value) => value.clone(),
+            Term::Eq { width, lhs, rhs } => {
+                BitVec::new(*width, (eval(lhs, env) == eval(rhs, env)) as u128)
+            }
+            Term::Extract { hi, lo, arg } => from_bits(&bits(&eval(arg, env))[*lo..=*hi]),
+            Term::Extend { signed, by, arg } => {
+                let mut out = bits(&eval(arg, env));
+                let fill = *signed && out[out.len() - 1];
+                out.resize(out.len() + by, fill);
+                from_bits(&out)
+            }
+            Term::App { op, args } => {
+                let args = args.iter().map(|arg| eval(arg, env)).collect::<Vec<_>>();
+                match *op {
+                    "concat" => from_bits(&args.iter().rev().flat_map(bits).collect::<Vec<_>>()),
+                    "bvnot" => args[0].not(),
+                    "bvand" => args[0].and(&args[1]),
+                    "bvor" => args[0].or(&args[1]),
+                    "bvxor" => args[0].xor(&args[1]),
+                    "bvadd" => args[0].add(&args[1]),
+                    "bvsub" => args[0].sub(&args[1]),
+                    "bvmul" => args[0].mul(&args[1]),
+                    "bvlshr" => args[0].lsr(&args[1]),
+                    other => panic!("unexpected {}", other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn symbolic_semantics_agree() {
+        let mac = DspConfig {
+            pre_adder: Some(Op::Sub),
+            multiply: true,
+            alu: Some(Op::Add),
+            pattern_detect: false,
+        };
+        let pattern = DspConfig {
+            pre_adder: None,
+            multiply: false,
+            alu: None,
+            pattern_detect: true,
+        };
+        let apply = |config: &DspConfig, width: usize| {
+            let args = config
+                .inputs()
+                .iter()
+                .map(|input| format!(" (var {} {})", input, width))
+                .collect::<String>();
+            format!("(apply {} (list{}))", config.instr(width), args)
+        };
+        let mut programs = vec![
+            "(binop xor 1 (binop and 1 (var a 1) (binop or 1 (var b 1) (var c 1))) \
+             (binop xor 1 (binop or 1 (var d 1) (var e 1)) (binop and 1 (var f 1) (var g 1))))"
+                .to_string(),
+            "(binop sub 12 (var x 12) (var y 12))".to_string(),
+            "(binop add 5 (var x 5) (const 3 5))".to_string(),
+            "(binop mul 12 (var x 12) (var y 12))".to_string(),
+        ];
+        let common = programs.len();
+        programs.push(apply(&mac, 16));
+        programs.push(apply(&pattern, 8));
+
+        let archs: [(&dyn Architecture, usize); 2] =
+            [(&UltraScalePlus, programs.len()), (&Ecp5, common)];
+        for (arch, n) in archs {
+            for program in &programs[..n] {
+                let program = RecExpr::from_str(program).unwrap();
+                let mapped = map_to_luts(&program, arch.lut_inputs(), 10)
+                    .map_or_else(|_| program.clone(), |mapping| mapping.expr);
+                let netlist = Netlist::map(arch, &mapped).unwrap();
+                let (definitions, output) = netlist.symbolic().unwrap();
+                let expr = Expr::try_from(&program).unwrap();
+                for inputs in test_vectors(&shared_vars(&expr, &expr).unwrap(), 16, 0) {
+                    let mut env = inputs.clone();
+                    for (name, _, term) in &definitions {
+                        let value = eval(term, &env);
+                        env.insert(name.clone(), value);
+                    }
+                    assert_eq!(
+                        eval(&output, &env),
+                        netlist.eval(&inputs).unwrap(),
+                        "{} on {}: {:?}",
+                        program,
+                        arch.name(),
+                        inputs
+                    );
+                }
+            }
+        }
+    }
+}
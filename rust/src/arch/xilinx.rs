@@ -0,0 +1,968 @@
+//! Xilinx UltraScale+: `LUT6`s, combined by `MUXF7`s and `MUXF8`s into
+//! LUTs of up to eight inputs, and by `MUXF9`s into mux trees of more,
+//! `CARRY8` carry chains, `DSP48E2` slices, and `FDRE` flip-flops.
+//!
+//! The `DSP48E2` semantics covers the subset the mapping uses: no registers
+//! but the output register, no cascades, and no feedback from `P`. The rest
+//! of its settings are unsupported rather than approximated. See UG574 and
+//! UG579 for the primitives.
+
+use super::{
+    always_registered, bits_parameter, combinational, const_bits, const_port, lut_term, mux_term,
+    padded, parameter, port_term, port_value, signed, string_parameter, unsupported, Architecture,
+    Bit, Cell, Netlist, Parameter, Parameters, PortTerms, PortValues, Primitive, PrimitiveKind,
+};
+use crate::{
+    bitvec::BitVec, dsp::DspConfig, error::LakeroadError, interval::mask, language::Op, smt::Term,
+};
+
+pub struct UltraScalePlus;
+
+const LUT6_INPUTS: [&str; 6] = ["I0", "I1", "I2", "I3", "I4", "I5"];
+
+/// The `DSP48E2`'s pipeline registers, which are all off in the cells the
+/// mapping adds, but for `PREG`, the output register, which `reg`s turn on.
+const DSP48E2_REGISTERS: [&str; 14] = [
+    "ACASCREG",
+    "ADREG",
+    "ALUMODEREG",
+    "AREG",
+    "BCASCREG",
+    "BREG",
+    "CARRYINREG",
+    "CARRYINSELREG",
+    "CREG",
+    "DREG",
+    "INMODEREG",
+    "MREG",
+    "OPMODEREG",
+    "PREG",
+];
+
+/// The `DSP48E2`'s string parameters the semantics only supports one value
+/// of, and those values.
+const DSP48E2_FIXED: [(&str, &str); 5] = [
+    ("A_INPUT", "DIRECT"),
+    ("B_INPUT", "DIRECT"),
+    ("BMULTSEL", "B"),
+    ("PREADDINSEL", "A"),
+    ("USE_SIMD", "ONE48"),
+];
+
+fn outputs(values: &[(&str, BitVec)]) -> PortValues {
+    values
+        .iter()
+        .map(|(port, value)| (port.to_string(), value.clone()))
+        .collect()
+}
+
+fn output_terms(terms: Vec<(&str, Term)>) -> PortTerms {
+    terms
+        .into_iter()
+        .map(|(port, term)| (port.to_string(), term))
+        .collect()
+}
+
+fn lut6(parameters: &Parameters, values: &PortValues) -> Result<PortValues, LakeroadError> {
+    let init = bits_parameter(parameters, "INIT")?;
+    let mut index = 0;
+    for (i, port) in LUT6_INPUTS.iter().enumerate() {
+        index |= (port_value(values, port)? as usize) << i;
+    }
+    Ok(outputs(&[("O", BitVec::new(1, init.bit(index) as u128))]))
+}
+
+/// `MUXF7`, `MUXF8`, and `MUXF9`, which differ only in where they sit in a
+/// CLB.
+fn muxf(_: &Parameters, values: &PortValues) -> Result<PortValues, LakeroadError> {
+    let input = if port_value(values, "S")? == 1 {
+        "I1"
+    } else {
+        "I0"
+    };
+    Ok(outputs(&[(
+        "O",
+        BitVec::new(1, port_value(values, input)?),
+    )]))
+}
+
+fn lut6_symbolic(parameters: &Parameters, terms: &PortTerms) -> Result<PortTerms, LakeroadError> {
+    let inputs = LUT6_INPUTS
+        .iter()
+        .map(|port| port_term(terms, port).cloned())
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(output_terms(vec![(
+        "O",
+        lut_term(bits_parameter(parameters, "INIT")?, &inputs),
+    )]))
+}
+
+fn muxf_symbolic(_: &Parameters, terms: &PortTerms) -> Result<PortTerms, LakeroadError> {
+    Ok(output_terms(vec![(
+        "O",
+        mux_term(
+            port_term(terms, "S")?,
+            port_term(terms, "I1")?,
+            port_term(terms, "I0")?,
+        ),
+    )]))
+}
+
+fn carry8(parameters: &Parameters, values: &PortValues) -> Result<PortValues, LakeroadError> {
+    match string_parameter(parameters, "CARRY_TYPE")? {
+        "SINGLE_CY8" => (),
+        other => return unsupported(format!("a CARRY8 of type {}", other)),
+    }
+    let (di, s) = (port_value(values, "DI")?, port_value(values, "S")?);
+    let mut carry = port_value(values, "CI")?;
+    let (mut o, mut co) = (0, 0);
+    for i in 0..8 {
+        // Each bit propagates the carry if `S` is set, and generates `DI`
+        // otherwise.
+        let s = s >> i & 1;
+        o |= (s ^ carry) << i;
+        carry = if s == 1 { carry } else { di >> i & 1 };
+        co |= carry << i;
+    }
+    Ok(outputs(&[
+        ("CO", BitVec::new(8, co)),
+        ("O", BitVec::new(8, o)),
+    ]))
+}
+
+fn carry8_symbolic(parameters: &Parameters, terms: &PortTerms) -> Result<PortTerms, LakeroadError> {
+    match string_parameter(parameters, "CARRY_TYPE")? {
+        "SINGLE_CY8" => (),
+        other => return unsupported(format!("a CARRY8 of type {}", other)),
+    }
+    let (di, s) = (port_term(terms, "DI")?, port_term(terms, "S")?);
+    // The chain adds `a` and `b`, where `a ^ b` is `S` and, where `S` is
+    // clear, `a & b` is `DI`, nine bits wide to keep the last carry.
+    let a = Term::app("bvor", vec![s.clone(), di.clone()]).extend(false, 1);
+    let b = Term::app(
+        "bvand",
+        vec![Term::app("bvnot", vec![s.clone()]), di.clone()],
+    )
+    .extend(false, 1);
+    let sum = Term::app(
+        "bvadd",
+        vec![
+            Term::app("bvadd", vec![a.clone(), b.clone()]),
+            port_term(terms, "CI")?.extend(false, 8),
+        ],
+    );
+    // The carry into each bit, and out of the last.
+    let carries = Term::app("bvxor", vec![sum.clone(), Term::app("bvxor", vec![a, b])]);
+    Ok(output_terms(vec![
+        ("CO", carries.extract(8, 1)),
+        ("O", sum.extract(7, 0)),
+    ]))
+}
+
+/// Whether a `DSP48E2` registers `P`.
+fn dsp48e2_registered(parameters: &Parameters) -> bool {
+    matches!(parameter(parameters, "PREG"), Ok(Parameter::Int(1)))
+}
+
+/// Fails on the `DSP48E2` settings the semantics doesn't cover.
+fn check_dsp48e2(parameters: &Parameters) -> Result<(), LakeroadError> {
+    for register in DSP48E2_REGISTERS {
+        let off = *parameter(parameters, register)? == Parameter::Int(0);
+        if !off && !(register == "PREG" && dsp48e2_registered(parameters)) {
+            return unsupported(format!("a DSP48E2 with {} set", register));
+        }
+    }
+    for (name, expected) in DSP48E2_FIXED {
+        let value = string_parameter(parameters, name)?;
+        if value != expected {
+            return unsupported(format!("a DSP48E2 with {} = {:?}", name, value));
+        }
+    }
+    Ok(())
+}
+
+fn dsp48e2(parameters: &Parameters, values: &PortValues) -> Result<PortValues, LakeroadError> {
+    check_dsp48e2(parameters)?;
+    let port = |name: &str| port_value(values, name);
+    if dsp48e2_registered(parameters) && port("CEP")? != 1 {
+        return unsupported("a DSP48E2 whose output register isn't enabled".to_string());
+    }
+    let (a, b, c, d) = (port("A")?, port("B")?, port("C")?, port("D")?);
+
+    // The pre-adder computes `D ± A`, 27 bits wide.
+    let inmode = port("INMODE")?;
+    let pre_a = if inmode & 0b10 == 0 { a & mask(27) } else { 0 };
+    let pre_d = if inmode & 0b100 == 0 { 0 } else { d };
+    let ad = if inmode & 0b1000 == 0 {
+        pre_d.wrapping_add(pre_a)
+    } else {
+        pre_d.wrapping_sub(pre_a)
+    } & mask(27);
+    let multiplicand = match string_parameter(parameters, "AMULTSEL")? {
+        "A" => a & mask(27),
+        "AD" => ad,
+        other => return unsupported(format!("a DSP48E2 with AMULTSEL = {:?}", other)),
+    };
+    // A signed 27 by 18 bit multiplier.
+    let m = match string_parameter(parameters, "USE_MULT")? {
+        "MULTIPLY" => signed(multiplicand, 27).wrapping_mul(signed(b & mask(18), 18)) as u128,
+        "NONE" => 0,
+        other => return unsupported(format!("a DSP48E2 with USE_MULT = {:?}", other)),
+    } & mask(48);
+
+    let opmode = port("OPMODE")?;
+    let (x, y, z, w) = (
+        opmode & 0b11,
+        opmode >> 2 & 0b11,
+        opmode >> 4 & 0b111,
+        opmode >> 7 & 0b11,
+    );
+    // The multiplier's two partial products go to X and Y, and are only
+    // selected together; their sum, M, is counted on X.
+    let x_value = match (x, y) {
+        (0, _) => 0,
+        (1, 1) => m,
+        (3, _) => (a << 18 | b) & mask(48),
+        _ => return unsupported(format!("a DSP48E2 with OPMODE {:09b}", opmode)),
+    };
+    let y_value = match (x, y) {
+        (1, 1) | (_, 0) => 0,
+        (_, 2) => mask(48),
+        (_, 3) => c,
+        _ => return unsupported(format!("a DSP48E2 with OPMODE {:09b}", opmode)),
+    };
+    let z_value = match (z, w) {
+        (0, 0) => 0,
+        (3, 0) => c,
+        _ => return unsupported(format!("a DSP48E2 with OPMODE {:09b}", opmode)),
+    };
+    let carry = match port("CARRYINSEL")? {
+        0 => port("CARRYIN")?,
+        other => return unsupported(format!("a DSP48E2 with CARRYINSEL {:03b}", other)),
+    };
+    let sum = x_value + y_value + carry;
+    let alumode = port("ALUMODE")?;
+    let p = match (alumode, y) {
+        (0b0000, _) => z_value + sum,
+        (0b0001, _) => sum.wrapping_sub(z_value).wrapping_sub(1),
+        (0b0011, _) => z_value.wrapping_sub(sum),
+        // The logic unit, whose operation also depends on Y.
+        (0b0100, 0) => x_value ^ z_value,
+        (0b0100, 2) => !(x_value ^ z_value),
+        (0b1100, 0) => x_value & z_value,
+        (0b1100, 2) => x_value | z_value,
+        _ => return unsupported(format!("a DSP48E2 with ALUMODE {:04b}", alumode)),
+    } & mask(48);
+
+    let detect = match string_parameter(parameters, "USE_PATTERN_DETECT")? {
+        "NO_PATDET" => false,
+        "PATDET" => {
+            let pattern = match string_parameter(parameters, "SEL_PATTERN")? {
+                "C" => c,
+                "PATTERN" => bits_parameter(parameters, "PATTERN")?
+                    .to_u128()
+                    .unwrap_or(0),
+                other => return unsupported(format!("a DSP48E2 with SEL_PATTERN = {:?}", other)),
+            };
+            let ignored = match string_parameter(parameters, "SEL_MASK")? {
+                "C" => c,
+                "MASK" => bits_parameter(parameters, "MASK")?.to_u128().unwrap_or(0),
+                other => return unsupported(format!("a DSP48E2 with SEL_MASK = {:?}", other)),
+            };
+            (p ^ pattern) & !ignored & mask(48) == 0
+        }
+        other => return unsupported(format!("a DSP48E2 with USE_PATTERN_DETECT = {:?}", other)),
+    };
+    Ok(outputs(&[
+        ("P", BitVec::new(48, p)),
+        ("PATTERNDETECT", BitVec::new(1, detect as u128)),
+    ]))
+}
+
+/// As [`dsp48e2`], decoding the same modes, which must be constants.
+fn dsp48e2_symbolic(
+    parameters: &Parameters,
+    terms: &PortTerms,
+) -> Result<PortTerms, LakeroadError> {
+    check_dsp48e2(parameters)?;
+    let port = |name: &str| port_term(terms, name).cloned();
+    if dsp48e2_registered(parameters) && const_port(terms, "CEP")? != 1 {
+        return unsupported("a DSP48E2 whose output register isn't enabled".to_string());
+    }
+    let (a, b, c, d) = (port("A")?, port("B")?, port("C")?, port("D")?);
+    let zero = |width| Term::Const(BitVec::zero(width));
+    let app = Term::app;
+
+    let inmode = const_port(terms, "INMODE")?;
+    let pre_a = if inmode & 0b10 == 0 {
+        a.extract(26, 0)
+    } else {
+        zero(27)
+    };
+    let pre_d = if inmode & 0b100 == 0 { zero(27) } else { d };
+    let ad = app(
+        if inmode & 0b1000 == 0 {
+            "bvadd"
+        } else {
+            "bvsub"
+        },
+        vec![pre_d, pre_a],
+    );
+    let multiplicand = match string_parameter(parameters, "AMULTSEL")? {
+        "A" => a.extract(26, 0),
+        "AD" => ad,
+        other => return unsupported(format!("a DSP48E2 with AMULTSEL = {:?}", other)),
+    };
+    let m = match string_parameter(parameters, "USE_MULT")? {
+        "MULTIPLY" => app(
+            "bvmul",
+            vec![
+                multiplicand.extend(true, 21),
+                b.extract(17, 0).extend(true, 30),
+            ],
+        ),
+        "NONE" => zero(48),
+        other => return unsupported(format!("a DSP48E2 with USE_MULT = {:?}", other)),
+    };
+
+    let opmode = const_port(terms, "OPMODE")?;
+    let (x, y, z, w) = (
+        opmode & 0b11,
+        opmode >> 2 & 0b11,
+        opmode >> 4 & 0b111,
+        opmode >> 7 & 0b11,
+    );
+    let x_value = match (x, y) {
+        (0, _) => zero(48),
+        (1, 1) => m,
+        (3, _) => app("concat", vec![a, b]),
+        _ => return unsupported(format!("a DSP48E2 with OPMODE {:09b}", opmode)),
+    };
+    let y_value = match (x, y) {
+        (1, 1) | (_, 0) => zero(48),
+        (_, 2) => Term::Const(BitVec::from_i64(48, -1)),
+        (_, 3) => c.clone(),
+        _ => return unsupported(format!("a DSP48E2 with OPMODE {:09b}", opmode)),
+    };
+    let z_value = match (z, w) {
+        (0, 0) => zero(48),
+        (3, 0) => c.clone(),
+        _ => return unsupported(format!("a DSP48E2 with OPMODE {:09b}", opmode)),
+    };
+    let carry = match const_port(terms, "CARRYINSEL")? {
+        0 => port("CARRYIN")?.extend(false, 47),
+        other => return unsupported(format!("a DSP48E2 with CARRYINSEL {:03b}", other)),
+    };
+    let sum = app(
+        "bvadd",
+        vec![app("bvadd", vec![x_value.clone(), y_value]), carry],
+    );
+    let alumode = const_port(terms, "ALUMODE")?;
+    let p = match (alumode, y) {
+        (0b0000, _) => app("bvadd", vec![z_value, sum]),
+        (0b0001, _) => app(
+            "bvsub",
+            vec![
+                app("bvsub", vec![sum, z_value]),
+                Term::Const(BitVec::new(48, 1)),
+            ],
+        ),
+        (0b0011, _) => app("bvsub", vec![z_value, sum]),
+        (0b0100, 0) => app("bvxor", vec![x_value, z_value]),
+        (0b0100, 2) => app("bvnot", vec![app("bvxor", vec![x_value, z_value])]),
+        (0b1100, 0) => app("bvand", vec![x_value, z_value]),
+        (0b1100, 2) => app("bvor", vec![x_value, z_value]),
+        _ => return unsupported(format!("a DSP48E2 with ALUMODE {:04b}", alumode)),
+    };
+
+    let detect = match string_parameter(parameters, "USE_PATTERN_DETECT")? {
+        "NO_PATDET" => zero(1),
+        "PATDET" => {
+            let constant = |name| -> Result<Term, LakeroadError> {
+                let value = bits_parameter(parameters, name)?.to_u128().unwrap_or(0);
+                Ok(Term::Const(BitVec::new(48, value)))
+            };
+            let pattern = match string_parameter(parameters, "SEL_PATTERN")? {
+                "C" => c.clone(),
+                "PATTERN" => constant("PATTERN")?,
+                other => return unsupported(format!("a DSP48E2 with SEL_PATTERN = {:?}", other)),
+            };
+            let ignored = match string_parameter(parameters, "SEL_MASK")? {
+                "C" => c,
+                "MASK" => constant("MASK")?,
+                other => return unsupported(format!("a DSP48E2 with SEL_MASK = {:?}", other)),
+            };
+            Term::Eq {
+                width: 1,
+                lhs: Box::new(app(
+                    "bvand",
+                    vec![
+                        app("bvxor", vec![p.clone(), pattern]),
+                        app("bvnot", vec![ignored]),
+                    ],
+                )),
+                rhs: Box::new(zero(48)),
+            }
+        }
+        other => return unsupported(format!("a DSP48E2 with USE_PATTERN_DETECT = {:?}", other)),
+    };
+    Ok(output_terms(vec![("P", p), ("PATTERNDETECT", detect)]))
+}
+
+/// Fails on the `FDRE` settings the semantics doesn't cover: it must start
+/// at zero and be enabled.
+fn check_fdre(parameters: &Parameters) -> Result<(), LakeroadError> {
+    if bits_parameter(parameters, "INIT")?.to_u128() != Some(0) {
+        return unsupported("an FDRE which starts at one".to_string());
+    }
+    Ok(())
+}
+
+/// What goes into an `FDRE`, which is cleared by `R` and only loads `D`
+/// when `CE` is high.
+fn fdre(parameters: &Parameters, values: &PortValues) -> Result<PortValues, LakeroadError> {
+    check_fdre(parameters)?;
+    if port_value(values, "CE")? != 1 || port_value(values, "R")? != 0 {
+        return unsupported("an FDRE which isn't always loaded".to_string());
+    }
+    Ok(outputs(&[("Q", BitVec::new(1, port_value(values, "D")?))]))
+}
+
+fn fdre_symbolic(parameters: &Parameters, terms: &PortTerms) -> Result<PortTerms, LakeroadError> {
+    check_fdre(parameters)?;
+    if const_port(terms, "CE")? != 1 || const_port(terms, "R")? != 0 {
+        return unsupported("an FDRE which isn't always loaded".to_string());
+    }
+    Ok(output_terms(vec![("Q", port_term(terms, "D")?.clone())]))
+}
+
+impl Architecture for UltraScalePlus {
+    fn name(&self) -> &'static str {
+        "xilinx-ultrascale-plus"
+    }
+
+    fn primitives(&self) -> Vec<Primitive> {
+        let muxf = |name| Primitive {
+            name,
+            kind: PrimitiveKind::Mux,
+            inputs: vec![("I0", 1), ("I1", 1), ("S", 1)],
+            outputs: vec![("O", 1)],
+            semantics: muxf,
+            symbolic: muxf_symbolic,
+            registered: combinational,
+        };
+        vec![
+            Primitive {
+                name: "LUT6",
+                kind: PrimitiveKind::Lut,
+                inputs: LUT6_INPUTS.iter().map(|port| (*port, 1)).collect(),
+                outputs: vec![("O", 1)],
+                semantics: lut6,
+                symbolic: lut6_symbolic,
+                registered: combinational,
+            },
+            muxf("MUXF7"),
+            muxf("MUXF8"),
+            muxf("MUXF9"),
+            Primitive {
+                name: "CARRY8",
+                kind: PrimitiveKind::Carry,
+                inputs: vec![("CI", 1), ("CI_TOP", 1), ("DI", 8), ("S", 8)],
+                outputs: vec![("CO", 8), ("O", 8)],
+                semantics: carry8,
+                symbolic: carry8_symbolic,
+                registered: combinational,
+            },
+            Primitive {
+                name: "DSP48E2",
+                kind: PrimitiveKind::Dsp,
+                inputs: vec![
+                    ("A", 30),
+                    ("ACIN", 30),
+                    ("ALUMODE", 4),
+                    ("B", 18),
+                    ("BCIN", 18),
+                    ("C", 48),
+                    ("CARRYCASCIN", 1),
+                    ("CARRYIN", 1),
+                    ("CARRYINSEL", 3),
+                    ("CEA1", 1),
+                    ("CEA2", 1),
+                    ("CEAD", 1),
+                    ("CEALUMODE", 1),
+                    ("CEB1", 1),
+                    ("CEB2", 1),
+                    ("CEC", 1),
+                    ("CECARRYIN", 1),
+                    ("CECTRL", 1),
+                    ("CED", 1),
+                    ("CEINMODE", 1),
+                    ("CEM", 1),
+                    ("CEP", 1),
+                    ("CLK", 1),
+                    ("D", 27),
+                    ("INMODE", 5),
+                    ("MULTSIGNIN", 1),
+                    ("OPMODE", 9),
+                    ("PCIN", 48),
+                    ("RSTA", 1),
+                    ("RSTALLCARRYIN", 1),
+                    ("RSTALUMODE", 1),
+                    ("RSTB", 1),
+                    ("RSTC", 1),
+                    ("RSTCTRL", 1),
+                    ("RSTD", 1),
+                    ("RSTINMODE", 1),
+                    ("RSTM", 1),
+                    ("RSTP", 1),
+                ],
+                outputs: vec![("P", 48), ("PATTERNDETECT", 1)],
+                semantics: dsp48e2,
+                symbolic: dsp48e2_symbolic,
+                registered: dsp48e2_registered,
+            },
+            Primitive {
+                name: "FDRE",
+                kind: PrimitiveKind::Register,
+                inputs: vec![("C", 1), ("CE", 1), ("D", 1), ("R", 1)],
+                outputs: vec![("Q", 1)],
+                semantics: fdre,
+                symbolic: fdre_symbolic,
+                registered: always_registered,
+            },
+        ]
+    }
+
+    /// A `LUT6`, or two or four of them combined by `MUXF7`s and a `MUXF8`,
+    /// as in one slice.
+    fn lut_inputs(&self) -> usize {
+        8
+    }
+
+    fn multiplier_width(&self) -> usize {
+        18
+    }
+
+    /// A `MUXF9` combining two eight-input LUTs.
+    fn mux_levels(&self) -> usize {
+        1
+    }
+
+    fn lut(
+        &self,
+        netlist: &mut Netlist,
+        inputs: &[Bit],
+        table: &[bool],
+    ) -> Result<Bit, LakeroadError> {
+        if inputs.len() <= 6 {
+            // Inputs past the function's are tied to zero, but the table is
+            // repeated for them anyway, so they don't matter.
+            let init = (0..64)
+                .filter(|n| table[n % table.len()])
+                .fold(0u128, |init, n| init | 1 << n);
+            let cell = netlist.add(Cell {
+                primitive: "LUT6",
+                parameters: [("INIT".to_string(), Parameter::Bits(BitVec::new(64, init)))]
+                    .into_iter()
+                    .collect(),
+                inputs: LUT6_INPUTS
+                    .iter()
+                    .zip(inputs)
+                    .map(|(port, bit)| (*port, vec![bit.clone()]))
+                    .collect(),
+            });
+            return Ok(Bit::Output {
+                cell,
+                port: "O",
+                bit: 0,
+            });
+        }
+        // A LUT of the other inputs for each value of the last, and a mux.
+        let (last, rest) = inputs.split_last().expect("more than six inputs");
+        let (low, high) = table.split_at(table.len() / 2);
+        let low = self.lut(netlist, rest, low)?;
+        let high = self.lut(netlist, rest, high)?;
+        let cell = netlist.add(Cell {
+            primitive: if inputs.len() == 7 { "MUXF7" } else { "MUXF8" },
+            parameters: Parameters::new(),
+            inputs: [
+                ("I0", vec![low]),
+                ("I1", vec![high]),
+                ("S", vec![last.clone()]),
+            ]
+            .into_iter()
+            .collect(),
+        });
+        Ok(Bit::Output {
+            cell,
+            port: "O",
+            bit: 0,
+        })
+    }
+
+    /// A `MUXF7` of two `LUT6`s, a `MUXF8` of two `MUXF7`s, or a `MUXF9` of
+    /// two `MUXF8`s, as they're wired in a CLB.
+    fn mux(&self, netlist: &mut Netlist, sel: &Bit, lo: &Bit, hi: &Bit) -> Option<Bit> {
+        let primitive = match (netlist.driver(lo)?, netlist.driver(hi)?) {
+            ("LUT6", "LUT6") => "MUXF7",
+            ("MUXF7", "MUXF7") => "MUXF8",
+            ("MUXF8", "MUXF8") => "MUXF9",
+            _ => return None,
+        };
+        let cell = netlist.add(Cell {
+            primitive,
+            parameters: Parameters::new(),
+            inputs: [
+                ("I0", vec![lo.clone()]),
+                ("I1", vec![hi.clone()]),
+                ("S", vec![sel.clone()]),
+            ]
+            .into_iter()
+            .collect(),
+        });
+        Some(Bit::Output {
+            cell,
+            port: "O",
+            bit: 0,
+        })
+    }
+
+    /// A `CARRY8` per eight bits, whose `S` is `a ^ b` (`a ^ !b` to
+    /// subtract) and `DI` is `a`.
+    fn adder(
+        &self,
+        netlist: &mut Netlist,
+        a: &[Bit],
+        b: &[Bit],
+        subtract: bool,
+    ) -> Result<Vec<Bit>, LakeroadError> {
+        // Indexed by `a`'s bit plus twice `b`'s.
+        let propagate = if subtract {
+            [true, false, false, true]
+        } else {
+            [false, true, true, false]
+        };
+        let mut carry = Bit::Const(subtract);
+        let mut out = vec![];
+        for (a, b) in a.chunks(8).zip(b.chunks(8)) {
+            let s = a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| netlist.lut(self, &[a.clone(), b.clone()], &propagate))
+                .collect::<Result<Vec<_>, _>>()?;
+            let cell = netlist.add(Cell {
+                primitive: "CARRY8",
+                parameters: [(
+                    "CARRY_TYPE".to_string(),
+                    Parameter::String("SINGLE_CY8".to_string()),
+                )]
+                .into_iter()
+                .collect(),
+                inputs: [
+                    ("CI", vec![carry]),
+                    ("DI", padded(a, 8)),
+                    ("S", padded(&s, 8)),
+                ]
+                .into_iter()
+                .collect(),
+            });
+            out.extend(netlist.outputs(cell, "O").into_iter().take(a.len()));
+            carry = netlist.outputs(cell, "CO")[7].clone();
+        }
+        Ok(out)
+    }
+
+    /// A `DSP48E2`. Without the multiplier, `a` comes in on `A:B` and can be
+    /// 48 bits wide; with it, the result can only be 18 bits wide, the width
+    /// of `B`. The logic unit can't follow the multiplier, and the pattern
+    /// comes in on `C`, so it can't be used with the ALU.
+    fn dsp(
+        &self,
+        netlist: &mut Netlist,
+        config: &DspConfig,
+        inputs: &[Vec<Bit>],
+    ) -> Result<Vec<Bit>, LakeroadError> {
+        let width = inputs[0].len();
+        let logic = matches!(config.alu, Some(Op::And | Op::Or | Op::Xor));
+        let fits = if config.multiply {
+            width <= 18
+        } else {
+            width <= 48
+        };
+        if !fits || (config.multiply && logic) || (config.pattern_detect && config.alu.is_some()) {
+            return unsupported(format!("{} at {} bits on a DSP48E2", config.name(), width));
+        }
+        let input = |name: &str| {
+            let index = config
+                .inputs()
+                .iter()
+                .position(|input| *input == name)
+                .expect("the configuration uses the input");
+            inputs[index].clone()
+        };
+
+        let mut ports = vec![];
+        let (x, y) = if config.multiply {
+            // The pre-adder computes `D ± A`, so `a` goes on `D` to subtract.
+            let (a, d, inmode) = match &config.pre_adder {
+                None => (input("a"), vec![], 0b00000),
+                Some(Op::Sub) => (input("d"), input("a"), 0b01100),
+                Some(_) => (input("a"), input("d"), 0b00100),
+            };
+            ports.push(("A", padded(&a, 30)));
+            ports.push(("D", padded(&d, 27)));
+            ports.push(("INMODE", const_bits(inmode, 5)));
+            ports.push(("B", padded(&input("b"), 18)));
+            (0b01, 0b01)
+        } else {
+            let a = input("a");
+            ports.push(("B", padded(&a, 18)));
+            ports.push(("A", padded(a.get(18..).unwrap_or(&[]), 30)));
+            (
+                0b11,
+                if config.alu == Some(Op::Or) {
+                    0b10
+                } else {
+                    0b00
+                },
+            )
+        };
+        let z = if config.alu.is_some() {
+            ports.push(("C", padded(&input("c"), 48)));
+            0b011
+        } else {
+            0b000
+        };
+        let (alumode, carry) = match &config.alu {
+            None | Some(Op::Add) => (0b0000, false),
+            Some(Op::Sub) => (0b0001, true),
+            Some(Op::Xor) => (0b0100, false),
+            Some(_) => (0b1100, false),
+        };
+        ports.push(("OPMODE", const_bits(z << 4 | y << 2 | x, 9)));
+        ports.push(("ALUMODE", const_bits(alumode, 4)));
+        ports.push(("CARRYIN", vec![Bit::Const(carry)]));
+        if config.pattern_detect {
+            ports.push(("C", padded(&input("pattern"), 48)));
+        }
+
+        let string = |value: &str| Parameter::String(value.to_string());
+        let mut parameters = DSP48E2_REGISTERS
+            .iter()
+            .map(|register| (register.to_string(), Parameter::Int(0)))
+            .chain(
+                DSP48E2_FIXED
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), string(value))),
+            )
+            .collect::<Parameters>();
+        let amultsel = if config.pre_adder.is_some() {
+            "AD"
+        } else {
+            "A"
+        };
+        let use_mult = if config.multiply { "MULTIPLY" } else { "NONE" };
+        let use_pattern_detect = if config.pattern_detect {
+            "PATDET"
+        } else {
+            "NO_PATDET"
+        };
+        parameters.insert("AMULTSEL".to_string(), string(amultsel));
+        parameters.insert("USE_MULT".to_string(), string(use_mult));
+        parameters.insert("USE_PATTERN_DETECT".to_string(), string(use_pattern_detect));
+        parameters.insert("SEL_PATTERN".to_string(), string("C"));
+        parameters.insert("SEL_MASK".to_string(), string("MASK"));
+        // Compares only the low `width` bits.
+        parameters.insert(
+            "MASK".to_string(),
+            Parameter::Bits(BitVec::new(48, !mask(width) & mask(48))),
+        );
+
+        let cell = netlist.add(Cell {
+            primitive: "DSP48E2",
+            parameters,
+            inputs: ports.into_iter().collect(),
+        });
+        Ok(if config.pattern_detect {
+            padded(&netlist.outputs(cell, "PATTERNDETECT"), width)
+        } else {
+            netlist.outputs(cell, "P").into_iter().take(width).collect()
+        })
+    }
+
+    /// An `FDRE` per bit which isn't always zero.
+    fn register(&self, netlist: &mut Netlist, d: &[Bit]) -> Result<Vec<Bit>, LakeroadError> {
+        Ok(d.iter()
+            .map(|bit| match bit {
+                Bit::Const(false) => bit.clone(),
+                _ => {
+                    let cell = netlist.add(Cell {
+                        primitive: "FDRE",
+                        parameters: [("INIT".to_string(), Parameter::Bits(BitVec::zero(1)))]
+                            .into_iter()
+                            .collect(),
+                        inputs: [
+                            ("C", vec![Bit::Clock]),
+                            ("CE", vec![Bit::Const(true)]),
+                            ("D", vec![bit.clone()]),
+                        ]
+                        .into_iter()
+                        .collect(),
+                    });
+                    netlist.outputs(cell, "Q").remove(0)
+                }
+            })
+            .collect())
+    }
+
+    /// Sets a `DSP48E2`'s `PREG`.
+    fn absorb_register(&self, netlist: &mut Netlist, cell: usize) -> bool {
+        let cell = &mut netlist.cells[cell];
+        if cell.primitive != "DSP48E2" || dsp48e2_registered(&cell.parameters) {
+            return false;
+        }
+        cell.parameters
+            .insert("PREG".to_string(), Parameter::Int(1));
+        cell.inputs.insert("CEP", vec![Bit::Const(true)]);
+        cell.inputs.insert("CLK", vec![Bit::Clock]);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::RecExpr;
+
+    use super::*;
+    use crate::{
+        ast::Expr,
+        equiv::{shared_vars, test_vectors},
+        eval::{eval_cycles, eval_expr},
+        language::Language,
+        lut::map_to_luts,
+    };
+
+    /// Checks `netlist` against `expr` on a few test vectors.
+    fn check(netlist: &Netlist, expr: &RecExpr<Language>) {
+        let expr = Expr::try_from(expr).unwrap();
+        for inputs in test_vectors(&shared_vars(&expr, &expr).unwrap(), 64, 0) {
+            assert_eq!(
+                netlist.eval(&inputs).unwrap(),
+                eval_expr(&expr, &inputs).unwrap(),
+                "{:?}",
+                inputs
+            );
+        }
+    }
+
+    #[test]
+    fn map_to_ultrascale_plus() {
+        let arch = UltraScalePlus;
+        let expr = |s: &str| RecExpr::from_str(s).unwrap();
+
+        // Seven inputs take two LUT6s and a MUXF7.
+        let program = expr(
+            "(binop xor 1 (binop and 1 (var a 1) (binop or 1 (var b 1) (var c 1))) \
+             (binop xor 1 (binop or 1 (var d 1) (var e 1)) (binop and 1 (var f 1) (var g 1))))",
+        );
+        let mapping = map_to_luts(&program, 8, 10).unwrap();
+        let netlist = Netlist::map(&arch, &mapping.expr).unwrap();
+        let primitives = netlist
+            .cells
+            .iter()
+            .map(|cell| cell.primitive)
+            .collect::<Vec<_>>();
+        assert_eq!(primitives, vec!["LUT6", "LUT6", "MUXF7"]);
+        check(&netlist, &program);
+        let verilog = netlist.to_verilog("top");
+        assert!(verilog.contains("LUT6 #(.INIT(64'h"));
+        assert!(verilog.contains("MUXF7 c2 (.I0(c0_O[0]), .I1(c1_O[0]), .S("));
+
+        // Twelve bits take two CARRY8s.
+        let sub = expr("(binop sub 12 (var x 12) (var y 12))");
+        let netlist = Netlist::map(&arch, &sub).unwrap();
+        check(&netlist, &sub);
+        assert!(netlist
+            .to_verilog("sub")
+            .contains("CARRY8 #(.CARRY_TYPE(\"SINGLE_CY8\"))"));
+
+        // Configurations which fit on one DSP48E2, and one which doesn't.
+        let config = |pre_adder, multiply, alu, pattern_detect| DspConfig {
+            pre_adder,
+            multiply,
+            alu,
+            pattern_detect,
+        };
+        let apply = |config: &DspConfig, width: usize| {
+            let args = config
+                .inputs()
+                .iter()
+                .map(|input| format!(" (var {} {})", input, width))
+                .collect::<String>();
+            expr(&format!("(apply {} (list{}))", config.instr(width), args))
+        };
+        for (config, width) in [
+            (config(Some(Op::Sub), true, Some(Op::Add), false), 16),
+            (config(None, true, Some(Op::Sub), false), 12),
+            (config(None, false, Some(Op::Or), false), 40),
+            (config(None, false, None, true), 8),
+        ] {
+            let program = apply(&config, width);
+            let netlist = Netlist::map(&arch, &program).unwrap();
+            assert_eq!(netlist.cells.len(), 1, "{}", config.name());
+            check(&netlist, &program);
+        }
+        let wide = apply(&config(None, true, None, false), 32);
+        assert!(matches!(
+            Netlist::map(&arch, &wide),
+            Err(LakeroadError::Unsupported(_))
+        ));
+
+        // A 24-bit multiply takes the six partial products of 9-bit chunks
+        // which land below bit 24.
+        let mul = expr("(binop mul 24 (var x 24) (var y 24))");
+        let netlist = Netlist::map(&arch, &mul).unwrap();
+        assert_eq!(netlist.count().dsps, 6);
+        check(&netlist, &mul);
+    }
+
+    #[test]
+    fn map_registers() {
+        let arch = UltraScalePlus;
+        // A register after a multiplication is the DSP48E2's PREG, and one
+        // after LUTs takes an FDRE per bit.
+        for (program, primitives) in [
+            ("(reg 8 (binop mul 8 (var a 8) (var b 8)))", vec!["DSP48E2"]),
+            (
+                "(binop and 2 (reg 2 (binop xor 2 (var a 2) (var b 2))) (var c 2))",
+                vec!["LUT6", "LUT6", "FDRE", "FDRE", "LUT6", "LUT6"],
+            ),
+        ] {
+            let program = RecExpr::from_str(program).unwrap();
+            let netlist = Netlist::map(&arch, &program).unwrap();
+            assert_eq!(
+                netlist
+                    .cells
+                    .iter()
+                    .map(|cell| cell.primitive)
+                    .collect::<Vec<_>>(),
+                primitives
+            );
+            assert_eq!(netlist.reg_depth(), 1);
+            assert!(netlist.to_verilog("top").contains("input clk"));
+            let expr = Expr::try_from(&program).unwrap();
+            let vectors = test_vectors(&shared_vars(&expr, &expr).unwrap(), 16, 0);
+            for inputs in vectors.windows(3) {
+                assert_eq!(
+                    netlist.eval_cycles(inputs).unwrap(),
+                    eval_cycles(&expr, inputs).unwrap(),
+                    "{} on {:?}",
+                    program,
+                    inputs
+                );
+            }
+        }
+    }
+}
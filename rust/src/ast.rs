@@ -0,0 +1,752 @@
+//! A tree-shaped view of programs and extracted instructions.
+//!
+//! [`RecExpr`]s are flat and refer to children by [`Id`], which makes them
+//! awkward to pattern-match on. [`Expr`] and [`Instr`] hold their children
+//! directly, and convert to and from `RecExpr<Language>`:
+//!
+//! ```
+//! use egg::RecExpr;
+//! use lakeroad::{ast::Expr, language::{Language, Op}};
+//!
+//! let expr: RecExpr<Language> = "(binop and 8 (var x 8) (var y 8))".parse().unwrap();
+//! match Expr::try_from(&expr).unwrap() {
+//!     Expr::BinOp { op, width, .. } => assert_eq!((op, width), (Op::And, 8)),
+//!     _ => unreachable!(),
+//! }
+//! ```
+//!
+//! Only the nodes of finished programs and instructions are represented;
+//! converting a term with e.g. a `concat` or a `canonicalize` fails.
+
+use egg::{Id, RecExpr};
+
+use crate::{
+    error::LakeroadError,
+    language::{Language, Op},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Expr {
+    Var {
+        name: String,
+        width: i64,
+    },
+    Const {
+        value: i64,
+        width: i64,
+    },
+    UnOp {
+        op: Op,
+        width: i64,
+        arg: Box<Expr>,
+    },
+    BinOp {
+        op: Op,
+        width: i64,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    /// `arg` delayed by a cycle, as the language's `reg`.
+    Reg {
+        width: i64,
+        arg: Box<Expr>,
+    },
+    /// Bits `lo` to `hi` of `arg`, inclusive.
+    Extract {
+        hi: i64,
+        lo: i64,
+        arg: Box<Expr>,
+    },
+    /// `hi` above `lo`.
+    Cat {
+        hi: Box<Expr>,
+        lo: Box<Expr>,
+    },
+    Apply {
+        instr: Instr,
+        args: Vec<Expr>,
+    },
+}
+
+/// The body of an [`Instr`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Ast {
+    Hole {
+        width: i64,
+    },
+    UnOp {
+        op: Op,
+        width: i64,
+        arg: Box<Ast>,
+    },
+    BinOp {
+        op: Op,
+        width: i64,
+        lhs: Box<Ast>,
+        rhs: Box<Ast>,
+    },
+    /// `instr` applied to `args`, which fill its holes as the arguments of
+    /// an [`Expr::Apply`] do, making this AST's instruction a macro-op.
+    Apply {
+        instr: Box<Instr>,
+        args: Vec<Ast>,
+    },
+}
+
+/// An instruction: an [`Ast`] whose holes, from left to right, are filled by
+/// the arguments numbered in `canonical_args`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Instr {
+    pub ast: Ast,
+    pub canonical_args: Vec<i64>,
+}
+
+impl Ast {
+    /// The width of the AST's value.
+    pub fn width(&self) -> i64 {
+        match self {
+            Ast::Hole { width } | Ast::UnOp { width, .. } | Ast::BinOp { width, .. } => *width,
+            Ast::Apply { instr, .. } => instr.ast.width(),
+        }
+    }
+
+    pub fn num_holes(&self) -> usize {
+        match self {
+            Ast::Hole { .. } => 1,
+            Ast::UnOp { arg, .. } => arg.num_holes(),
+            Ast::BinOp { lhs, rhs, .. } => lhs.num_holes() + rhs.num_holes(),
+            Ast::Apply { args, .. } => args.iter().map(Ast::num_holes).sum(),
+        }
+    }
+
+    /// The widths of the holes, from left to right.
+    pub fn hole_widths(&self) -> Vec<i64> {
+        match self {
+            Ast::Hole { width } => vec![*width],
+            Ast::UnOp { arg, .. } => arg.hole_widths(),
+            Ast::BinOp { lhs, rhs, .. } => {
+                let mut widths = lhs.hole_widths();
+                widths.extend(rhs.hole_widths());
+                widths
+            }
+            Ast::Apply { args, .. } => args.iter().flat_map(Ast::hole_widths).collect(),
+        }
+    }
+
+    /// The expression with each hole, from left to right, replaced by what
+    /// `hole` returns given the hole's width. Returns `None` if `hole` does.
+    pub fn fill(&self, hole: &mut impl FnMut(i64) -> Option<Expr>) -> Option<Expr> {
+        Some(match self {
+            Ast::Hole { width } => hole(*width)?,
+            Ast::UnOp { op, width, arg } => Expr::UnOp {
+                op: op.clone(),
+                width: *width,
+                arg: Box::new(arg.fill(hole)?),
+            },
+            Ast::BinOp {
+                op,
+                width,
+                lhs,
+                rhs,
+            } => Expr::BinOp {
+                op: op.clone(),
+                width: *width,
+                lhs: Box::new(lhs.fill(hole)?),
+                rhs: Box::new(rhs.fill(hole)?),
+            },
+            Ast::Apply { instr, args } => Expr::Apply {
+                instr: (**instr).clone(),
+                args: args
+                    .iter()
+                    .map(|arg| arg.fill(hole))
+                    .collect::<Option<_>>()?,
+            },
+        })
+    }
+}
+
+impl Instr {
+    /// The instruction with the ASTs of the instructions it applies
+    /// substituted in, so that it's made only of holes and operators.
+    pub fn inline(&self) -> Instr {
+        Instr {
+            ast: self.ast.inline(),
+            canonical_args: self.canonical_args.clone(),
+        }
+    }
+
+    /// The expression the instruction computes, with holes filled by `var`s
+    /// named by their canonical argument, e.g. `a0`. Returns `None` if there
+    /// isn't exactly one canonical argument per hole.
+    pub fn as_expr(&self) -> Option<Expr> {
+        let mut args = self.canonical_args.iter();
+        let expr = self.ast.fill(&mut |width| {
+            Some(Expr::Var {
+                name: format!("a{}", args.next()?),
+                width,
+            })
+        })?;
+        match args.next() {
+            None => Some(expr),
+            Some(_) => None,
+        }
+    }
+
+    /// The instruction with its canonical arguments renumbered in the order
+    /// they first appear, e.g. `(canonical-args 1 0 1)` becomes
+    /// `(canonical-args 0 1 0)`, so that instructions which differ only in
+    /// how their arguments are numbered are the same.
+    pub fn alpha_renamed(&self) -> Instr {
+        let mut seen: Vec<i64> = vec![];
+        let canonical_args = self
+            .canonical_args
+            .iter()
+            .map(|arg| match seen.iter().position(|s| s == arg) {
+                Some(i) => i as i64,
+                None => {
+                    seen.push(*arg);
+                    seen.len() as i64 - 1
+                }
+            })
+            .collect();
+        Instr {
+            ast: self.ast.clone(),
+            canonical_args,
+        }
+    }
+
+    /// A name for the instruction which is the same in every run, unlike
+    /// its e-class id, and is shared by the instructions it's the same as
+    /// up to [`Instr::alpha_renamed`]: the 64-bit FNV-1a hash of the renamed
+    /// instruction's s-expression, in hex.
+    pub fn stable_id(&self) -> String {
+        let hash = RecExpr::from(&self.alpha_renamed())
+            .to_string()
+            .bytes()
+            .fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+        format!("{:016x}", hash)
+    }
+}
+
+impl Ast {
+    /// The AST with each [`Ast::Apply`] replaced by the applied
+    /// instruction's AST, its holes filled by the arguments.
+    pub fn inline(&self) -> Ast {
+        /// `ast` with its holes, from left to right, replaced by `holes`.
+        fn substitute(ast: &Ast, holes: &mut dyn Iterator<Item = Ast>) -> Ast {
+            match ast {
+                Ast::Hole { width } => holes.next().unwrap_or(Ast::Hole { width: *width }),
+                Ast::UnOp { op, width, arg } => Ast::UnOp {
+                    op: op.clone(),
+                    width: *width,
+                    arg: Box::new(substitute(arg, holes)),
+                },
+                Ast::BinOp {
+                    op,
+                    width,
+                    lhs,
+                    rhs,
+                } => Ast::BinOp {
+                    op: op.clone(),
+                    width: *width,
+                    lhs: Box::new(substitute(lhs, holes)),
+                    rhs: Box::new(substitute(rhs, holes)),
+                },
+                Ast::Apply { .. } => unreachable!("substituting into an inlined AST"),
+            }
+        }
+        match self {
+            Ast::Hole { .. } => self.clone(),
+            Ast::UnOp { op, width, arg } => Ast::UnOp {
+                op: op.clone(),
+                width: *width,
+                arg: Box::new(arg.inline()),
+            },
+            Ast::BinOp {
+                op,
+                width,
+                lhs,
+                rhs,
+            } => Ast::BinOp {
+                op: op.clone(),
+                width: *width,
+                lhs: Box::new(lhs.inline()),
+                rhs: Box::new(rhs.inline()),
+            },
+            Ast::Apply { instr, args } => {
+                let args = args.iter().map(Ast::inline).collect::<Vec<_>>();
+                // An argument per hole, or per distinct canonical argument,
+                // as in `eval_instr`.
+                let mut holes = instr.canonical_args.iter().enumerate().map(|(hole, arg)| {
+                    if args.len() == instr.canonical_args.len() {
+                        args[hole].clone()
+                    } else {
+                        args[*arg as usize].clone()
+                    }
+                });
+                substitute(&instr.inline().ast, &mut holes)
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// The expression's variables and their widths, in the order they first
+    /// appear.
+    pub fn vars(&self) -> Vec<(String, i64)> {
+        fn go(expr: &Expr, out: &mut Vec<(String, i64)>) {
+            match expr {
+                Expr::Var { name, width } => {
+                    if !out.iter().any(|(n, _)| n == name) {
+                        out.push((name.clone(), *width));
+                    }
+                }
+                Expr::Const { .. } => (),
+                Expr::UnOp { arg, .. } | Expr::Reg { arg, .. } | Expr::Extract { arg, .. } => {
+                    go(arg, out)
+                }
+                Expr::BinOp { lhs, rhs, .. } | Expr::Cat { hi: lhs, lo: rhs } => {
+                    go(lhs, out);
+                    go(rhs, out);
+                }
+                Expr::Apply { args, .. } => args.iter().for_each(|arg| go(arg, out)),
+            }
+        }
+
+        let mut out = vec![];
+        go(self, &mut out);
+        out
+    }
+
+    /// The width of the expression's value.
+    pub fn width(&self) -> i64 {
+        match self {
+            Expr::Var { width, .. }
+            | Expr::Const { width, .. }
+            | Expr::UnOp { width, .. }
+            | Expr::BinOp { width, .. }
+            | Expr::Reg { width, .. } => *width,
+            Expr::Extract { hi, lo, .. } => hi - lo + 1,
+            Expr::Cat { hi, lo } => hi.width() + lo.width(),
+            Expr::Apply { instr, .. } => instr.ast.width(),
+        }
+    }
+
+    /// The most `reg`s on a path from the root to a leaf: how many cycles
+    /// back the expression's value depends on. Zero means it's
+    /// combinational.
+    pub fn reg_depth(&self) -> usize {
+        match self {
+            Expr::Var { .. } | Expr::Const { .. } => 0,
+            Expr::UnOp { arg, .. } | Expr::Extract { arg, .. } => arg.reg_depth(),
+            Expr::BinOp { lhs, rhs, .. } | Expr::Cat { hi: lhs, lo: rhs } => {
+                lhs.reg_depth().max(rhs.reg_depth())
+            }
+            Expr::Reg { arg, .. } => 1 + arg.reg_depth(),
+            Expr::Apply { args, .. } => args.iter().map(Expr::reg_depth).max().unwrap_or(0),
+        }
+    }
+}
+
+fn add_num(out: &mut RecExpr<Language>, n: i64) -> Id {
+    out.add(Language::Num(n))
+}
+
+impl Expr {
+    /// Adds the expression to `out`, returning its root.
+    pub fn add_to(&self, out: &mut RecExpr<Language>) -> Id {
+        match self {
+            Expr::Var { name, width } => {
+                let name = out.add(Language::String(name.clone()));
+                let width = add_num(out, *width);
+                out.add(Language::Var([name, width]))
+            }
+            Expr::Const { value, width } => {
+                let value = add_num(out, *value);
+                let width = add_num(out, *width);
+                out.add(Language::Const([value, width]))
+            }
+            Expr::UnOp { op, width, arg } => {
+                let op = out.add(Language::Op(op.clone()));
+                let width = add_num(out, *width);
+                let arg = arg.add_to(out);
+                out.add(Language::UnOp([op, width, arg]))
+            }
+            Expr::BinOp {
+                op,
+                width,
+                lhs,
+                rhs,
+            } => {
+                let op = out.add(Language::Op(op.clone()));
+                let width = add_num(out, *width);
+                let lhs = lhs.add_to(out);
+                let rhs = rhs.add_to(out);
+                out.add(Language::BinOp([op, width, lhs, rhs]))
+            }
+            Expr::Reg { width, arg } => {
+                let width = add_num(out, *width);
+                let arg = arg.add_to(out);
+                out.add(Language::Reg([width, arg]))
+            }
+            Expr::Extract { hi, lo, arg } => {
+                let hi = add_num(out, *hi);
+                let lo = add_num(out, *lo);
+                let arg = arg.add_to(out);
+                out.add(Language::Extract([hi, lo, arg]))
+            }
+            Expr::Cat { hi, lo } => {
+                let hi = hi.add_to(out);
+                let lo = lo.add_to(out);
+                out.add(Language::Cat([hi, lo]))
+            }
+            Expr::Apply { instr, args } => {
+                let instr = instr.add_to(out);
+                let args = args.iter().map(|arg| arg.add_to(out)).collect();
+                let args = out.add(Language::List(args));
+                out.add(Language::Apply([instr, args]))
+            }
+        }
+    }
+}
+
+impl Ast {
+    pub fn add_to(&self, out: &mut RecExpr<Language>) -> Id {
+        match self {
+            Ast::Hole { width } => {
+                let width = add_num(out, *width);
+                out.add(Language::Hole([width]))
+            }
+            Ast::UnOp { op, width, arg } => {
+                let op = out.add(Language::Op(op.clone()));
+                let width = add_num(out, *width);
+                let arg = arg.add_to(out);
+                out.add(Language::UnOpAst([op, width, arg]))
+            }
+            Ast::BinOp {
+                op,
+                width,
+                lhs,
+                rhs,
+            } => {
+                let op = out.add(Language::Op(op.clone()));
+                let width = add_num(out, *width);
+                let lhs = lhs.add_to(out);
+                let rhs = rhs.add_to(out);
+                out.add(Language::BinOpAst([op, width, lhs, rhs]))
+            }
+            Ast::Apply { instr, args } => {
+                let instr = instr.add_to(out);
+                let args = args.iter().map(|arg| arg.add_to(out)).collect();
+                let args = out.add(Language::List(args));
+                out.add(Language::ApplyAst([instr, args]))
+            }
+        }
+    }
+}
+
+impl Instr {
+    pub fn add_to(&self, out: &mut RecExpr<Language>) -> Id {
+        let ast = self.ast.add_to(out);
+        let args = self
+            .canonical_args
+            .iter()
+            .map(|arg| add_num(out, *arg))
+            .collect();
+        let args = out.add(Language::CanonicalArgs(args));
+        out.add(Language::Instr([ast, args]))
+    }
+}
+
+macro_rules! into_rec_expr {
+    ($ty:ty, $read:ident) => {
+        impl From<&$ty> for RecExpr<Language> {
+            fn from(term: &$ty) -> Self {
+                let mut out = RecExpr::default();
+                term.add_to(&mut out);
+                out
+            }
+        }
+
+        impl From<$ty> for RecExpr<Language> {
+            fn from(term: $ty) -> Self {
+                RecExpr::from(&term)
+            }
+        }
+
+        impl TryFrom<&RecExpr<Language>> for $ty {
+            type Error = LakeroadError;
+
+            fn try_from(expr: &RecExpr<Language>) -> Result<Self, Self::Error> {
+                let root = expr
+                    .as_ref()
+                    .len()
+                    .checked_sub(1)
+                    .ok_or_else(|| LakeroadError::Malformed("empty expression".to_string()))?;
+                Reader(expr).$read(Id::from(root))
+            }
+        }
+    };
+}
+
+into_rec_expr!(Expr, expr);
+into_rec_expr!(Ast, ast);
+into_rec_expr!(Instr, instr);
+
+/// Reads the subterm at an [`Id`] as one of the tree types.
+struct Reader<'a>(&'a RecExpr<Language>);
+
+impl Reader<'_> {
+    fn node(&self, id: Id) -> &Language {
+        &self.0[id]
+    }
+
+    fn num(&self, id: Id) -> Result<i64, LakeroadError> {
+        match self.node(id) {
+            Language::Num(n) => Ok(*n),
+            other => Err(self.malformed("a number", other)),
+        }
+    }
+
+    fn op(&self, id: Id) -> Result<Op, LakeroadError> {
+        match self.node(id) {
+            Language::Op(op) => Ok(op.clone()),
+            other => Err(self.malformed("an op", other)),
+        }
+    }
+
+    fn malformed(&self, expected: &str, found: &Language) -> LakeroadError {
+        LakeroadError::Malformed(format!("expected {}, found {}", expected, found))
+    }
+
+    fn expr(&self, id: Id) -> Result<Expr, LakeroadError> {
+        Ok(match self.node(id) {
+            Language::Var([name, width]) => Expr::Var {
+                name: match self.node(*name) {
+                    Language::String(name) => name.clone(),
+                    other => return Err(self.malformed("a name", other)),
+                },
+                width: self.num(*width)?,
+            },
+            Language::Const([value, width]) => Expr::Const {
+                value: self.num(*value)?,
+                width: self.num(*width)?,
+            },
+            Language::UnOp([op, width, arg]) => Expr::UnOp {
+                op: self.op(*op)?,
+                width: self.num(*width)?,
+                arg: Box::new(self.expr(*arg)?),
+            },
+            Language::BinOp([op, width, lhs, rhs]) => Expr::BinOp {
+                op: self.op(*op)?,
+                width: self.num(*width)?,
+                lhs: Box::new(self.expr(*lhs)?),
+                rhs: Box::new(self.expr(*rhs)?),
+            },
+            Language::Reg([width, arg]) => Expr::Reg {
+                width: self.num(*width)?,
+                arg: Box::new(self.expr(*arg)?),
+            },
+            Language::Extract([hi, lo, arg]) => Expr::Extract {
+                hi: self.num(*hi)?,
+                lo: self.num(*lo)?,
+                arg: Box::new(self.expr(*arg)?),
+            },
+            Language::Cat([hi, lo]) => Expr::Cat {
+                hi: Box::new(self.expr(*hi)?),
+                lo: Box::new(self.expr(*lo)?),
+            },
+            Language::Apply([instr, args]) => Expr::Apply {
+                instr: self.instr(*instr)?,
+                args: match self.node(*args) {
+                    Language::List(args) => args
+                        .iter()
+                        .map(|arg| self.expr(*arg))
+                        .collect::<Result<_, _>>()?,
+                    other => return Err(self.malformed("a list", other)),
+                },
+            },
+            other => {
+                return Err(LakeroadError::Unsupported(format!(
+                    "{} in an expression",
+                    other
+                )))
+            }
+        })
+    }
+
+    fn ast(&self, id: Id) -> Result<Ast, LakeroadError> {
+        Ok(match self.node(id) {
+            Language::Hole([width]) => Ast::Hole {
+                width: self.num(*width)?,
+            },
+            Language::UnOpAst([op, width, arg]) => Ast::UnOp {
+                op: self.op(*op)?,
+                width: self.num(*width)?,
+                arg: Box::new(self.ast(*arg)?),
+            },
+            Language::BinOpAst([op, width, lhs, rhs]) => Ast::BinOp {
+                op: self.op(*op)?,
+                width: self.num(*width)?,
+                lhs: Box::new(self.ast(*lhs)?),
+                rhs: Box::new(self.ast(*rhs)?),
+            },
+            Language::ApplyAst([instr, args]) => Ast::Apply {
+                instr: Box::new(self.instr(*instr)?),
+                args: match self.node(*args) {
+                    Language::List(args) => args
+                        .iter()
+                        .map(|arg| self.ast(*arg))
+                        .collect::<Result<_, _>>()?,
+                    other => return Err(self.malformed("a list", other)),
+                },
+            },
+            other => return Err(LakeroadError::Unsupported(format!("{} in an AST", other))),
+        })
+    }
+
+    fn instr(&self, id: Id) -> Result<Instr, LakeroadError> {
+        match self.node(id) {
+            Language::Instr([ast, args]) => Ok(Instr {
+                ast: self.ast(*ast)?,
+                canonical_args: match self.node(*args) {
+                    Language::CanonicalArgs(args) => args
+                        .iter()
+                        .map(|arg| self.num(*arg))
+                        .collect::<Result<_, _>>()?,
+                    other => {
+                        return Err(LakeroadError::Unsupported(format!(
+                            "{} as an instr's arguments",
+                            other
+                        )))
+                    }
+                },
+            }),
+            other => Err(self.malformed("an instr", other)),
+        }
+    }
+}
+
+/// [`proptest`] strategies for generating programs.
+#[cfg(test)]
+pub(crate) mod strategies {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Well-typed expressions of `width` bits over `x0`, `x1`, and `x2`.
+    pub(crate) fn arb_expr(width: i64) -> impl Strategy<Value = Expr> {
+        let leaf = prop_oneof![
+            (0..3).prop_map(move |i| Expr::Var {
+                name: format!("x{}", i),
+                width,
+            }),
+            any::<i64>().prop_map(move |value| Expr::Const { value, width }),
+        ];
+        leaf.prop_recursive(4, 16, 2, move |inner| {
+            prop_oneof![
+                (prop::sample::select(vec![Op::Not, Op::Neg]), inner.clone()).prop_map(
+                    move |(op, arg)| Expr::UnOp {
+                        op,
+                        width,
+                        arg: Box::new(arg),
+                    }
+                ),
+                (
+                    prop::sample::select(vec![
+                        Op::And,
+                        Op::Or,
+                        Op::Xor,
+                        Op::Add,
+                        Op::Sub,
+                        Op::Mul,
+                        Op::Lsr,
+                        Op::Asr,
+                        Op::Eq,
+                    ]),
+                    inner.clone(),
+                    inner,
+                )
+                    .prop_map(move |(op, lhs, rhs)| Expr::BinOp {
+                        op,
+                        width,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    }),
+            ]
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::example_programs::all_programs;
+
+    #[test]
+    fn round_trip_examples() {
+        for (name, expr) in all_programs() {
+            let tree = Expr::try_from(&expr).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(
+                RecExpr::from(&tree).to_string(),
+                expr.to_string(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn instrs() {
+        let instr = RecExpr::<Language>::from_str(
+            "(instr (binop-ast and 8 (hole 8) (hole 8)) (canonical-args 0 0))",
+        )
+        .unwrap();
+        let tree = Instr::try_from(&instr).unwrap();
+        assert_eq!(tree.ast.num_holes(), 2);
+        assert_eq!(RecExpr::from(&tree).to_string(), instr.to_string());
+        assert_eq!(
+            RecExpr::from(tree.as_expr().unwrap()).to_string(),
+            "(binop and 8 (var a0 8) (var a0 8))"
+        );
+
+        let unfinished =
+            RecExpr::<Language>::from_str("(instr (hole 8) (canonicalize (list (var x 8))))")
+                .unwrap();
+        assert!(matches!(
+            Instr::try_from(&unfinished),
+            Err(LakeroadError::Unsupported(_))
+        ));
+        assert!(matches!(
+            Expr::try_from(&instr),
+            Err(LakeroadError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn stable_ids() {
+        let id = |instr: &str| {
+            Instr::try_from(&RecExpr::<Language>::from_str(instr).unwrap())
+                .unwrap()
+                .stable_id()
+        };
+        let sub = id("(instr (binop-ast sub 8 (hole 8) (hole 8)) (canonical-args 0 1))");
+        assert_eq!(sub.len(), 16);
+        assert_eq!(
+            sub,
+            id("(instr (binop-ast sub 8 (hole 8) (hole 8)) (canonical-args 1 0))")
+        );
+        assert_ne!(
+            sub,
+            id("(instr (binop-ast sub 8 (hole 8) (hole 8)) (canonical-args 0 0))")
+        );
+        assert_ne!(
+            sub,
+            id("(instr (binop-ast sub 16 (hole 16) (hole 16)) (canonical-args 0 1))")
+        );
+    }
+}
@@ -0,0 +1,409 @@
+//! The interface between the pipeline and whatever checks candidates, e.g.
+//! [`RacketBackend`](crate::racket::RacketBackend).
+
+use std::collections::HashMap;
+
+use egg::{AstSize, EClass, EGraph, Extractor, Id, RecExpr};
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+
+use crate::{
+    analysis::{is_pruned, LanguageAnalysis, LanguageAnalysisData, LanguageAnalysisData::Signal},
+    ast::{Expr, Instr},
+    error::LakeroadError,
+    language::{instr_as_expr, Language},
+    synthesizer::timed,
+};
+
+pub trait SynthesisBackend {
+    /// Whether `expr` can be implemented on the target hardware.
+    fn check_feasible(&self, expr: &RecExpr<Language>) -> Result<bool, LakeroadError>;
+
+    /// Tells backends apart, e.g. in checkpoints, whose verdicts are only
+    /// reused by a run with the same backend. By default, the type's name.
+    fn name(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+
+    /// Whether `a` and `b` compute the same function of their variables.
+    fn verify_equivalent(
+        &self,
+        _a: &RecExpr<Language>,
+        _b: &RecExpr<Language>,
+    ) -> Result<bool, LakeroadError> {
+        Err(LakeroadError::Unsupported(
+            "this backend can't check equivalence".to_string(),
+        ))
+    }
+
+    /// Whether `candidate` computes what `spec` does, `latency` cycles
+    /// later, in every cycle after the first `latency`, both starting with
+    /// their registers zero. By default, only programs without `reg`s and
+    /// without latency are checked, with [`verify_equivalent`].
+    ///
+    /// [`verify_equivalent`]: SynthesisBackend::verify_equivalent
+    fn verify_sequential(
+        &self,
+        spec: &RecExpr<Language>,
+        candidate: &RecExpr<Language>,
+        latency: usize,
+    ) -> Result<bool, LakeroadError> {
+        let depth = Expr::try_from(spec)?
+            .reg_depth()
+            .max(Expr::try_from(candidate)?.reg_depth());
+        if depth > 0 || latency > 0 {
+            return Err(LakeroadError::Unsupported(
+                "this backend can't check sequential equivalence".to_string(),
+            ));
+        }
+        self.verify_equivalent(spec, candidate)
+    }
+
+    /// Fills the holes of `instr` with variables of `target` so that it
+    /// computes `target`, ignoring the instr's canonical arguments. Returns
+    /// `None` if no filling works.
+    ///
+    /// By default, tries every filling with [`verify_equivalent`], so this
+    /// is only practical for instrs with a few holes.
+    ///
+    /// [`verify_equivalent`]: SynthesisBackend::verify_equivalent
+    fn synthesize_holes(
+        &self,
+        instr: &RecExpr<Language>,
+        target: &RecExpr<Language>,
+    ) -> Result<Option<RecExpr<Language>>, LakeroadError> {
+        let instr = Instr::try_from(instr)?;
+        let vars = Expr::try_from(target)?.vars();
+        let holes = instr.ast.num_holes();
+        if holes > 0 && vars.is_empty() {
+            return Ok(None);
+        }
+        // Counts through every assignment of variables to holes.
+        let mut choice = vec![0; holes];
+        loop {
+            let mut next = choice.iter();
+            let filled = instr.ast.fill(&mut |width| {
+                let (name, var_width) = &vars[*next.next()?];
+                (*var_width == width).then(|| Expr::Var {
+                    name: name.clone(),
+                    width,
+                })
+            });
+            if let Some(filled) = filled {
+                let filled = RecExpr::from(filled);
+                if self.verify_equivalent(&filled, target)? {
+                    return Ok(Some(filled));
+                }
+            }
+            match choice.iter().rposition(|c| c + 1 < vars.len()) {
+                Some(i) => {
+                    choice[i] += 1;
+                    choice[i + 1..].iter_mut().for_each(|c| *c = 0);
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Whether the pipeline should keep the candidate `instr`. By default,
+    /// whether the expression it computes (see [`instr_as_expr`]) is
+    /// feasible, with the outputs of the instructions a macro-op applies as
+    /// inputs: those were checked already, so only what's around them is.
+    fn check_candidate(&self, instr: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+        match candidate_expr(instr)? {
+            Some(expr) => self.check_feasible(&expr),
+            None => Ok(false),
+        }
+    }
+}
+
+/// What [`SynthesisBackend::check_candidate`] checks of `instr` by default:
+/// the expression it computes, around the instructions it applies. `None`
+/// if `instr` isn't a well-formed `instr`.
+pub(crate) fn candidate_expr(
+    instr: &RecExpr<Language>,
+) -> Result<Option<RecExpr<Language>>, LakeroadError> {
+    match instr_as_expr(instr) {
+        Some(expr) => Ok(Some(RecExpr::from(around_applies(
+            &Expr::try_from(&expr)?,
+            &mut 0,
+        )))),
+        None => Ok(None),
+    }
+}
+
+/// `expr`, an instruction's expression, with each `apply` replaced by a
+/// fresh `var`, named `applied` and a number counted by `next`.
+fn around_applies(expr: &Expr, next: &mut usize) -> Expr {
+    match expr {
+        Expr::Apply { .. } => {
+            *next += 1;
+            Expr::Var {
+                name: format!("applied{}", *next - 1),
+                width: expr.width(),
+            }
+        }
+        Expr::UnOp { op, width, arg } => Expr::UnOp {
+            op: op.clone(),
+            width: *width,
+            arg: Box::new(around_applies(arg, next)),
+        },
+        Expr::BinOp {
+            op,
+            width,
+            lhs,
+            rhs,
+        } => Expr::BinOp {
+            op: op.clone(),
+            width: *width,
+            lhs: Box::new(around_applies(lhs, next)),
+            rhs: Box::new(around_applies(rhs, next)),
+        },
+        _ => expr.clone(),
+    }
+}
+
+/// A backend deciding with a closure. The closure is given candidates as
+/// `instr`s, and any other term as it is.
+pub struct FnBackend<F>(pub F);
+
+impl<F: Fn(&RecExpr<Language>) -> bool> SynthesisBackend for FnBackend<F> {
+    fn check_feasible(&self, expr: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+        Ok((self.0)(expr))
+    }
+
+    fn check_candidate(&self, instr: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+        Ok((self.0)(instr))
+    }
+
+    /// Closures can't be told apart, so every `FnBackend` is the same.
+    fn name(&self) -> String {
+        "FnBackend".to_string()
+    }
+}
+
+/// What [`explore_new`] decided about an eclass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Feasible,
+    Infeasible,
+    /// The eclass is a pruned instruction, so the backend wasn't asked.
+    Pruned,
+    /// Every bit of the eclass's signal is known, so it's a constant, and
+    /// not worth asking the backend about.
+    Constant,
+}
+
+/// [`explore_new`]'s result for one eclass.
+#[derive(Debug, Clone)]
+pub struct CandidateResult {
+    pub eclass: Id,
+    /// The smallest term in the eclass.
+    pub expr: RecExpr<Language>,
+    pub verdict: Verdict,
+    /// Seconds the backend took; zero if it wasn't asked, including when
+    /// another eclass's term was the same.
+    pub solver_time: f64,
+}
+
+/// What [`explore_new`] found.
+#[derive(Debug, Clone, Default)]
+pub struct ExploreReport {
+    /// A result per eclass, by eclass.
+    pub results: Vec<CandidateResult>,
+    /// How many distinct terms the backend was asked about.
+    pub queries: usize,
+    /// The backend's seconds, summed over its queries.
+    pub solver_time: f64,
+}
+
+/// Asks `backend` whether the smallest term in every eclass is feasible.
+/// The terms are extracted up front, and each distinct term is checked
+/// once. With the `parallel` feature, the checks run on a pool of at most
+/// `workers` threads (or rayon's default number, if `workers` is zero), so
+/// at most that many backend queries, e.g. Racket processes, run at once.
+/// Backend failures are returned.
+pub fn explore_new(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    backend: &(impl SynthesisBackend + Sync),
+    workers: usize,
+) -> Result<ExploreReport, LakeroadError> {
+    let extractor = Extractor::new(egraph, AstSize);
+    let mut classes = egraph.classes().collect::<Vec<_>>();
+    classes.sort_by_key(|eclass| eclass.id);
+    let mut results = classes
+        .into_iter()
+        .map(|eclass| CandidateResult {
+            eclass: eclass.id,
+            expr: extractor.find_best(eclass.id).1,
+            // Replaced by the backend's answer below, unless skipped.
+            verdict: skipped(egraph, eclass).unwrap_or(Verdict::Infeasible),
+            solver_time: 0.0,
+        })
+        .collect::<Vec<_>>();
+
+    // The terms to ask about, each once, in eclass order.
+    let mut queries: Vec<RecExpr<Language>> = vec![];
+    let mut query_of = HashMap::new();
+    let mut asked = vec![None; results.len()];
+    for (result, asked) in results.iter().zip(asked.iter_mut()) {
+        if matches!(result.verdict, Verdict::Pruned | Verdict::Constant) {
+            continue;
+        }
+        *asked = Some(*query_of.entry(result.expr.to_string()).or_insert_with(|| {
+            queries.push(result.expr.clone());
+            queries.len() - 1
+        }));
+    }
+
+    let check = |expr: &RecExpr<Language>| -> Result<(bool, f64), LakeroadError> {
+        let (feasible, solver_time) = timed(|| backend.check_feasible(expr));
+        Ok((feasible?, solver_time))
+    };
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    let answers = rayon::ThreadPoolBuilder::new()
+        .num_threads(workers)
+        .build()
+        .map_err(|e| LakeroadError::Config(format!("worker pool: {}", e)))?
+        .install(|| {
+            queries
+                .par_iter()
+                .map(check)
+                .collect::<Result<Vec<_>, LakeroadError>>()
+        })?;
+    #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+    let answers = {
+        let _ = workers;
+        queries
+            .iter()
+            .map(check)
+            .collect::<Result<Vec<_>, LakeroadError>>()?
+    };
+
+    let mut first = vec![true; queries.len()];
+    for (result, asked) in results.iter_mut().zip(asked) {
+        if let Some(query) = asked {
+            let (feasible, solver_time) = answers[query];
+            result.verdict = if feasible {
+                Verdict::Feasible
+            } else {
+                Verdict::Infeasible
+            };
+            if std::mem::replace(&mut first[query], false) {
+                result.solver_time = solver_time;
+            }
+        }
+    }
+    Ok(ExploreReport {
+        results,
+        queries: queries.len(),
+        solver_time: answers.iter().map(|(_, seconds)| seconds).sum(),
+    })
+}
+
+/// The verdict on `eclass` if the backend needn't be asked about it.
+fn skipped(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    eclass: &EClass<Language, LanguageAnalysisData>,
+) -> Option<Verdict> {
+    if is_pruned(egraph, eclass.id) {
+        return Some(Verdict::Pruned);
+    }
+    match &eclass.data {
+        Signal { width, known, .. } if known.as_constant(*width).is_some() => {
+            Some(Verdict::Constant)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, str::FromStr};
+
+    use super::*;
+
+    /// Considers terms equivalent only if they're identical.
+    #[derive(Default)]
+    struct Mock {
+        queries: Cell<usize>,
+    }
+
+    impl SynthesisBackend for Mock {
+        fn check_feasible(&self, _: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+            Ok(true)
+        }
+
+        fn verify_equivalent(
+            &self,
+            a: &RecExpr<Language>,
+            b: &RecExpr<Language>,
+        ) -> Result<bool, LakeroadError> {
+            self.queries.set(self.queries.get() + 1);
+            Ok(a.to_string() == b.to_string())
+        }
+    }
+
+    #[test]
+    fn synthesize_holes_by_enumeration() {
+        let instr =
+            RecExpr::from_str("(instr (binop-ast and 8 (hole 8) (hole 8)) (canonical-args 0 0))")
+                .unwrap();
+        let target = RecExpr::from_str("(binop and 8 (var x 8) (var y 8))").unwrap();
+        let mock = Mock::default();
+        let filled = mock.synthesize_holes(&instr, &target).unwrap().unwrap();
+        assert_eq!(filled.to_string(), target.to_string());
+        // (x, x) is tried before (x, y).
+        assert_eq!(mock.queries.get(), 2);
+
+        let or = RecExpr::from_str("(binop or 8 (var x 8) (var y 8))").unwrap();
+        assert_eq!(mock.synthesize_holes(&instr, &or).unwrap(), None);
+
+        let narrow = RecExpr::from_str("(binop and 4 (var x 4) (var y 4))").unwrap();
+        let queries = mock.queries.get();
+        assert_eq!(mock.synthesize_holes(&instr, &narrow).unwrap(), None);
+        assert_eq!(mock.queries.get(), queries);
+
+        assert!(mock.check_candidate(&instr).unwrap());
+        assert!(matches!(
+            FnBackend(|_: &RecExpr<Language>| true).verify_equivalent(&target, &target),
+            Err(LakeroadError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn explore_new_results() {
+        let mut egraph = EGraph::<Language, LanguageAnalysis>::default();
+        let root =
+            egraph.add_expr(&RecExpr::from_str("(binop and 8 (var x 8) (const 1 8))").unwrap());
+        egraph.rebuild();
+        let backend = FnBackend(|expr: &RecExpr<Language>| expr.to_string().contains("var"));
+        let report = explore_new(&egraph, &backend, 2).unwrap();
+        let results = &report.results;
+
+        assert_eq!(results.len(), egraph.number_of_classes());
+        assert!(results.windows(2).all(|w| w[0].eclass < w[1].eclass));
+        let verdict = |expr: &str| {
+            results
+                .iter()
+                .find(|r| r.expr.to_string() == expr)
+                .unwrap()
+                .verdict
+        };
+        assert_eq!(verdict("(const 1 8)"), Verdict::Constant);
+        assert_eq!(verdict("(var x 8)"), Verdict::Feasible);
+        assert_eq!(verdict("and"), Verdict::Infeasible);
+        assert_eq!(
+            results.iter().find(|r| r.eclass == root).unwrap().verdict,
+            Verdict::Feasible
+        );
+        // Everything but the constant's eclasses was asked about once.
+        assert_eq!(
+            report.queries,
+            results
+                .iter()
+                .filter(|r| !matches!(r.verdict, Verdict::Constant | Verdict::Pruned))
+                .count()
+        );
+    }
+}
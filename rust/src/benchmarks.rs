@@ -0,0 +1,90 @@
+//! Benchmark programs bundled with the crate, for reproductions and
+//! regression comparisons.
+
+use std::str::FromStr;
+
+use egg::RecExpr;
+
+use crate::{language::Language, program_set::ProgramSet};
+
+/// Where the bithack benchmarks come from.
+pub const CHLOROPHYLL_BITHACKS: &str =
+    "https://github.com/mangpo/chlorophyll/tree/241b68f4419c356e10828bbefead4f8407461ef9/examples/bithack";
+
+/// A benchmark program and its metadata.
+#[derive(Debug, Clone)]
+pub struct Benchmark {
+    pub name: &'static str,
+    /// Where the program comes from.
+    pub provenance: &'static str,
+    /// The width of every signal in the program.
+    pub width: usize,
+    pub expr: RecExpr<Language>,
+}
+
+/// The bithack examples from the Chlorophyll paper which fit in 8 bits; see
+/// `lakeroad_programs/README.md`. Programs which rely on wider "magic
+/// numbers" (e.g. `count.cll`) are left out.
+pub fn bithack_benchmarks() -> Vec<Benchmark> {
+    macro_rules! bithack {
+        ($name:literal) => {
+            Benchmark {
+                name: $name,
+                provenance: CHLOROPHYLL_BITHACKS,
+                width: 8,
+                expr: RecExpr::from_str(include_str!(concat!(
+                    "../lakeroad_programs/bithack_",
+                    $name,
+                    ".lakeroad"
+                )))
+                .unwrap(),
+            }
+        };
+    }
+    vec![
+        bithack!("bithack1"),
+        bithack!("bithack2"),
+        bithack!("bithack3"),
+        bithack!("ceil_avg"),
+        bithack!("cycle"),
+        bithack!("exchange"),
+        bithack!("floor_avg"),
+        bithack!("roundpower"),
+    ]
+}
+
+/// The bithack benchmarks as a program set, named as in
+/// [`bithack_benchmarks`].
+pub fn bithacks() -> ProgramSet {
+    bithack_benchmarks()
+        .into_iter()
+        .map(|b| (b.name.to_string(), b.expr))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        example_programs::all_programs,
+        language::{typecheck_expr, Type},
+    };
+
+    #[test]
+    fn bithacks_match_example_programs() {
+        let examples = all_programs();
+        let benchmarks = bithack_benchmarks();
+        assert_eq!(benchmarks.len(), examples.len());
+        for benchmark in benchmarks {
+            assert_eq!(
+                benchmark.expr.to_string(),
+                examples[&format!("bithack_{}", benchmark.name)].to_string()
+            );
+            assert_eq!(
+                typecheck_expr(&benchmark.expr).unwrap(),
+                Type::Signal(benchmark.width)
+            );
+        }
+        assert_eq!(bithacks().len(), examples.len());
+    }
+}
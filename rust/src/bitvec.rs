@@ -0,0 +1,361 @@
+//! Bitvectors of any positive width, with the semantics of the language's
+//! operators: arithmetic wraps, and shifting by the width or more shifts
+//! every bit out.
+//!
+//! [`crate::eval`]'s `u128` operators are enough for the analysis, which
+//! only tracks constants precisely up to 128 bits; [`BitVec`] is for
+//! evaluating programs of any width exactly.
+
+use std::fmt::Display;
+
+use crate::language::Op;
+
+/// A concrete value of a signal: `width` bits, read as unsigned.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitVec {
+    width: usize,
+    /// The bits, 64 at a time, least significant first. Bits at and above
+    /// `width` are always zero.
+    words: Vec<u64>,
+}
+
+impl BitVec {
+    /// All zeros. Panics if `width` is zero.
+    pub fn zero(width: usize) -> Self {
+        assert!(width > 0, "bitvectors must be at least one bit wide");
+        BitVec {
+            width,
+            words: vec![0; (width + 63) / 64],
+        }
+    }
+
+    /// `value` truncated to (or zero-extended to) `width` bits.
+    pub fn new(width: usize, value: u128) -> Self {
+        Self::from_words(width, &[value as u64, (value >> 64) as u64])
+    }
+
+    /// `value` in two's complement, truncated to (or sign-extended to)
+    /// `width` bits, as `const`s are.
+    pub fn from_i64(width: usize, value: i64) -> Self {
+        let mut out = Self::zero(width);
+        let fill = if value < 0 { u64::MAX } else { 0 };
+        out.words.fill(fill);
+        out.words[0] = value as u64;
+        out.truncate()
+    }
+
+    /// The bits of `words`, least significant word first, truncated to (or
+    /// zero-extended to) `width` bits.
+    pub fn from_words(width: usize, words: &[u64]) -> Self {
+        let mut out = Self::zero(width);
+        for (out, word) in out.words.iter_mut().zip(words) {
+            *out = *word;
+        }
+        out.truncate()
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The bits, least significant word first.
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// The `i`th bit, counting from the least significant.
+    pub fn bit(&self, i: usize) -> bool {
+        i < self.width && (self.words[i / 64] >> (i % 64)) & 1 == 1
+    }
+
+    /// The value, if it fits in a `u128`.
+    pub fn to_u128(&self) -> Option<u128> {
+        if self.words.iter().skip(2).any(|w| *w != 0) {
+            return None;
+        }
+        let word = |i: usize| self.words.get(i).copied().unwrap_or(0) as u128;
+        Some(word(0) | word(1) << 64)
+    }
+
+    /// The value in hexadecimal, zero-padded to a digit per four bits and
+    /// without a prefix, e.g. `0f` for 15 at 8 bits.
+    pub fn to_hex(&self) -> String {
+        (0..(self.width + 3) / 4)
+            .rev()
+            .map(|i| {
+                let nibble = (0..4).fold(0, |n, bit| n | (self.bit(i * 4 + bit) as u32) << bit);
+                std::char::from_digit(nibble, 16).unwrap()
+            })
+            .collect()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
+
+    fn is_negative(&self) -> bool {
+        self.bit(self.width - 1)
+    }
+
+    /// Clears the bits at and above `width`.
+    fn truncate(mut self) -> Self {
+        let extra = self.words.len() * 64 - self.width;
+        if let Some(top) = self.words.last_mut() {
+            *top &= u64::MAX >> extra;
+        }
+        self
+    }
+
+    fn zip_with(&self, other: &BitVec, f: impl Fn(u64, u64) -> u64) -> BitVec {
+        assert_eq!(self.width, other.width, "operands of different widths");
+        BitVec {
+            width: self.width,
+            words: self
+                .words
+                .iter()
+                .zip(&other.words)
+                .map(|(a, b)| f(*a, *b))
+                .collect(),
+        }
+        .truncate()
+    }
+
+    pub fn not(&self) -> BitVec {
+        BitVec {
+            width: self.width,
+            words: self.words.iter().map(|w| !w).collect(),
+        }
+        .truncate()
+    }
+
+    pub fn and(&self, other: &BitVec) -> BitVec {
+        self.zip_with(other, |a, b| a & b)
+    }
+
+    pub fn or(&self, other: &BitVec) -> BitVec {
+        self.zip_with(other, |a, b| a | b)
+    }
+
+    pub fn xor(&self, other: &BitVec) -> BitVec {
+        self.zip_with(other, |a, b| a ^ b)
+    }
+
+    /// The sum, wrapping.
+    pub fn add(&self, other: &BitVec) -> BitVec {
+        assert_eq!(self.width, other.width, "operands of different widths");
+        let mut carry = false;
+        let words = self
+            .words
+            .iter()
+            .zip(&other.words)
+            .map(|(a, b)| {
+                let (sum, c0) = a.overflowing_add(*b);
+                let (sum, c1) = sum.overflowing_add(carry as u64);
+                carry = c0 || c1;
+                sum
+            })
+            .collect();
+        BitVec {
+            width: self.width,
+            words,
+        }
+        .truncate()
+    }
+
+    /// The two's complement negation, wrapping.
+    pub fn neg(&self) -> BitVec {
+        self.not().add(&BitVec::new(self.width, 1))
+    }
+
+    /// The difference, wrapping.
+    pub fn sub(&self, other: &BitVec) -> BitVec {
+        self.add(&other.neg())
+    }
+
+    /// The product, wrapping.
+    pub fn mul(&self, other: &BitVec) -> BitVec {
+        assert_eq!(self.width, other.width, "operands of different widths");
+        let mut words = vec![0u64; self.words.len()];
+        for (i, a) in self.words.iter().enumerate() {
+            let mut carry = 0u128;
+            for (j, b) in other.words.iter().enumerate().take(words.len() - i) {
+                let product = *a as u128 * *b as u128 + words[i + j] as u128 + carry;
+                words[i + j] = product as u64;
+                carry = product >> 64;
+            }
+        }
+        BitVec {
+            width: self.width,
+            words,
+        }
+        .truncate()
+    }
+
+    /// How far `self` shifts a value of `width` bits, or `None` if it shifts
+    /// every bit out.
+    fn shift_amount(&self, width: usize) -> Option<usize> {
+        self.to_u128()
+            .and_then(|n| usize::try_from(n).ok())
+            .filter(|n| *n < width)
+    }
+
+    /// Shifts right by `amount`, filling with `fill`'s bits. The amount is
+    /// read as unsigned, so it may be of any width.
+    fn shift_right(&self, amount: &BitVec, fill: bool) -> BitVec {
+        let mut out = BitVec::from_i64(self.width, if fill { -1 } else { 0 });
+        if let Some(n) = amount.shift_amount(self.width) {
+            for i in 0..self.width - n {
+                let word = &mut out.words[i / 64];
+                *word &= !(1 << (i % 64));
+                *word |= (self.bit(i + n) as u64) << (i % 64);
+            }
+        }
+        out
+    }
+
+    /// The logical right shift by `amount`.
+    pub fn lsr(&self, amount: &BitVec) -> BitVec {
+        self.shift_right(amount, false)
+    }
+
+    /// The arithmetic right shift by `amount`.
+    pub fn asr(&self, amount: &BitVec) -> BitVec {
+        self.shift_right(amount, self.is_negative())
+    }
+
+    /// Bits `lo` to `hi`, inclusive.
+    pub fn extract(&self, hi: usize, lo: usize) -> BitVec {
+        assert!(
+            lo <= hi && hi < self.width,
+            "bits {}..={} out of range",
+            lo,
+            hi
+        );
+        let mut out = BitVec::zero(hi - lo + 1);
+        for i in 0..out.width {
+            out.words[i / 64] |= (self.bit(lo + i) as u64) << (i % 64);
+        }
+        out
+    }
+
+    /// `self` above `lo`, as SMT-LIB's `concat`.
+    pub fn concat(&self, lo: &BitVec) -> BitVec {
+        let mut out = BitVec::zero(self.width + lo.width);
+        for i in 0..out.width {
+            let bit = if i < lo.width {
+                lo.bit(i)
+            } else {
+                self.bit(i - lo.width)
+            };
+            out.words[i / 64] |= (bit as u64) << (i % 64);
+        }
+        out
+    }
+
+    /// One if the operands are equal, and zero otherwise, at their width.
+    pub fn equals(&self, other: &BitVec) -> BitVec {
+        assert_eq!(self.width, other.width, "operands of different widths");
+        BitVec::new(self.width, (self == other) as u128)
+    }
+
+    /// The value of `(unop op width self)`, or `None` if `op` isn't unary.
+    pub fn unop(&self, op: &Op) -> Option<BitVec> {
+        match op {
+            Op::Not => Some(self.not()),
+            Op::Neg => Some(self.neg()),
+            _ => None,
+        }
+    }
+
+    /// The value of `(binop op width self other)`, or `None` if `op` isn't
+    /// binary.
+    pub fn binop(&self, op: &Op, other: &BitVec) -> Option<BitVec> {
+        match op {
+            Op::And => Some(self.and(other)),
+            Op::Or => Some(self.or(other)),
+            Op::Xor => Some(self.xor(other)),
+            Op::Add => Some(self.add(other)),
+            Op::Sub => Some(self.sub(other)),
+            Op::Mul => Some(self.mul(other)),
+            Op::Lsr => Some(self.lsr(other)),
+            Op::Asr => Some(self.asr(other)),
+            Op::Eq => Some(self.equals(other)),
+            Op::Not | Op::Neg => None,
+        }
+    }
+}
+
+impl Display for BitVec {
+    /// Writes the value as a Rosette-style literal, e.g. `(bv #x0f 8)`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(bv #x{} {})", self.to_hex(), self.width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{eval_binop, eval_unop};
+
+    #[test]
+    fn wide_ops() {
+        let max = BitVec::from_i64(130, -1);
+        assert!(max.bit(129));
+        assert_eq!(max.to_u128(), None);
+        assert!(max.add(&BitVec::new(130, 1)).is_zero());
+        assert_eq!(BitVec::new(130, 0).sub(&BitVec::new(130, 1)), max);
+        assert_eq!(
+            BitVec::new(130, 1 << 127).add(&BitVec::new(130, 1 << 127)),
+            BitVec::from_words(130, &[0, 0, 1])
+        );
+        let top = BitVec::from_words(130, &[0, 0, 2]);
+        assert_eq!(top.lsr(&BitVec::new(130, 129)).to_u128(), Some(1),);
+        assert_eq!(top.asr(&BitVec::new(130, 129)), max);
+        assert!(top.lsr(&BitVec::new(130, 130)).is_zero());
+        assert_eq!(top.asr(&BitVec::from_i64(130, -1)), max);
+        // (2^130 - 1)^2 = 2^260 - 2^131 + 1, which is 1 modulo 2^130.
+        assert_eq!(max.mul(&max), BitVec::new(130, 1));
+        assert_eq!(max.to_string(), format!("(bv #x3{} 130)", "f".repeat(32)));
+    }
+
+    /// Agrees with the `u128` operators wherever they're exact.
+    #[test]
+    fn matches_u128_ops() {
+        let values = [0, 1, 2, 7, 0x55, 0x80, 0xfe, 0xff];
+        for a in values {
+            let x = BitVec::new(8, a);
+            for op in [Op::Not, Op::Neg] {
+                assert_eq!(x.unop(&op).unwrap().to_u128(), eval_unop(&op, 8, a));
+            }
+            for b in values {
+                let y = BitVec::new(8, b);
+                for op in [
+                    Op::And,
+                    Op::Or,
+                    Op::Xor,
+                    Op::Add,
+                    Op::Sub,
+                    Op::Mul,
+                    Op::Lsr,
+                    Op::Asr,
+                    Op::Eq,
+                ] {
+                    assert_eq!(
+                        x.binop(&op, &y).unwrap().to_u128(),
+                        eval_binop(&op, 8, a, b),
+                        "{} {} {}",
+                        op,
+                        a,
+                        b
+                    );
+                }
+                // A narrower amount is zero-extended.
+                let narrow = BitVec::new(4, b);
+                assert_eq!(
+                    x.asr(&narrow).to_u128(),
+                    eval_binop(&Op::Asr, 8, a, b & 0xf)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Building programs in Rust without formatting s-expressions.
+//!
+//! ```
+//! use lakeroad::builder::ExprBuilder;
+//!
+//! let b = ExprBuilder::new();
+//! let x = b.var("x", 8);
+//! let expr = b.build(b.sub(x, b.and(x, b.var("y", 8)))).unwrap();
+//! assert_eq!(
+//!     expr.to_string(),
+//!     "(binop sub 8 (var x 8) (binop and 8 (var x 8) (var y 8)))"
+//! );
+//! ```
+//!
+//! Operators take the width of their first argument; mismatched widths are
+//! reported by [`ExprBuilder::build`], which type-checks the result.
+
+use std::cell::RefCell;
+
+use egg::{Id, Language as LanguageTrait, RecExpr};
+
+use crate::language::{typecheck_expr, ExprTypeError, Language, Op};
+
+/// An expression added to an [`ExprBuilder`]. It is only meaningful to the
+/// builder which returned it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Term {
+    id: Id,
+    bitwidth: i64,
+}
+
+impl Term {
+    pub fn bitwidth(&self) -> i64 {
+        self.bitwidth
+    }
+}
+
+/// Accumulates the nodes of an expression. Methods take `&self`, so that
+/// calls can be nested.
+#[derive(Default)]
+pub struct ExprBuilder {
+    expr: RefCell<RecExpr<Language>>,
+}
+
+impl ExprBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&self, node: Language) -> Id {
+        self.expr.borrow_mut().add(node)
+    }
+
+    pub fn var(&self, name: impl Into<String>, bitwidth: i64) -> Term {
+        let name = self.push(Language::String(name.into()));
+        let width = self.push(Language::Num(bitwidth));
+        Term {
+            id: self.push(Language::Var([name, width])),
+            bitwidth,
+        }
+    }
+
+    pub fn constant(&self, value: i64, bitwidth: i64) -> Term {
+        let value = self.push(Language::Num(value));
+        let width = self.push(Language::Num(bitwidth));
+        Term {
+            id: self.push(Language::Const([value, width])),
+            bitwidth,
+        }
+    }
+
+    pub fn unop(&self, op: Op, a: Term) -> Term {
+        let op = self.push(Language::Op(op));
+        let width = self.push(Language::Num(a.bitwidth));
+        Term {
+            id: self.push(Language::UnOp([op, width, a.id])),
+            bitwidth: a.bitwidth,
+        }
+    }
+
+    pub fn binop(&self, op: Op, a: Term, b: Term) -> Term {
+        let op = self.push(Language::Op(op));
+        let width = self.push(Language::Num(a.bitwidth));
+        Term {
+            id: self.push(Language::BinOp([op, width, a.id, b.id])),
+            bitwidth: a.bitwidth,
+        }
+    }
+
+    pub fn not(&self, a: Term) -> Term {
+        self.unop(Op::Not, a)
+    }
+
+    pub fn neg(&self, a: Term) -> Term {
+        self.unop(Op::Neg, a)
+    }
+
+    pub fn and(&self, a: Term, b: Term) -> Term {
+        self.binop(Op::And, a, b)
+    }
+
+    pub fn or(&self, a: Term, b: Term) -> Term {
+        self.binop(Op::Or, a, b)
+    }
+
+    pub fn xor(&self, a: Term, b: Term) -> Term {
+        self.binop(Op::Xor, a, b)
+    }
+
+    pub fn add(&self, a: Term, b: Term) -> Term {
+        self.binop(Op::Add, a, b)
+    }
+
+    pub fn sub(&self, a: Term, b: Term) -> Term {
+        self.binop(Op::Sub, a, b)
+    }
+
+    pub fn mul(&self, a: Term, b: Term) -> Term {
+        self.binop(Op::Mul, a, b)
+    }
+
+    pub fn asr(&self, a: Term, b: Term) -> Term {
+        self.binop(Op::Asr, a, b)
+    }
+
+    pub fn lsr(&self, a: Term, b: Term) -> Term {
+        self.binop(Op::Lsr, a, b)
+    }
+
+    /// One if `a` equals `b`, and zero otherwise, at the width of `a`.
+    pub fn eq(&self, a: Term, b: Term) -> Term {
+        self.binop(Op::Eq, a, b)
+    }
+
+    /// The expression rooted at `root`, without any nodes it doesn't use.
+    /// The builder can keep being used afterwards.
+    pub fn build(&self, root: Term) -> Result<RecExpr<Language>, ExprTypeError> {
+        let expr = self.expr.borrow();
+        let nodes = expr.as_ref();
+        let root = usize::from(root.id);
+        let mut used = vec![false; root + 1];
+        used[root] = true;
+        for i in (0..=root).rev() {
+            if used[i] {
+                for child in nodes[i].children() {
+                    used[usize::from(*child)] = true;
+                }
+            }
+        }
+        let mut out = RecExpr::default();
+        let mut ids = vec![None; root + 1];
+        for i in (0..=root).filter(|i| used[*i]) {
+            let node = nodes[i].clone().map_children(|child| {
+                ids[usize::from(child)].expect("children precede their parents")
+            });
+            ids[i] = Some(out.add(node));
+        }
+        typecheck_expr(&out)?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::language::TypeError;
+
+    #[test]
+    fn build_matches_parsed() {
+        let b = ExprBuilder::new();
+        let x = b.var("x", 8);
+        let y = b.var("y", 8);
+        // Not part of the result.
+        b.not(y);
+        let avg = b.sub(b.or(x, y), b.asr(b.xor(x, y), b.constant(1, 8)));
+        assert_eq!(
+            b.build(avg).unwrap().to_string(),
+            RecExpr::<Language>::from_str(
+                "(binop sub 8 (binop or 8 (var x 8) (var y 8))
+                  (binop asr 8 (binop xor 8 (var x 8) (var y 8)) (const 1 8)))"
+            )
+            .unwrap()
+            .to_string()
+        );
+
+        let mismatched = b.add(x, b.var("z", 4));
+        assert!(matches!(
+            b.build(mismatched).unwrap_err().error,
+            TypeError::WidthMismatch { .. }
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+//! Cooperative cancellation of runs.
+//!
+//! An embedding application keeps a clone of the [`CancellationToken`] it
+//! gave the [`Synthesizer`](crate::synthesizer::Synthesizer), and cancels it
+//! from any thread. The run notices between rewrite iterations, before and
+//! after extraction, between candidates, and (with
+//! [`RacketBackend`](crate::racket::RacketBackend)) during solver calls, and
+//! returns [`SynthesisError::Cancelled`](crate::synthesizer::SynthesisError)
+//! with whatever it had done so far.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// A flag shared by its clones. Once cancelled, it stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
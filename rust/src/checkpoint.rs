@@ -0,0 +1,244 @@
+//! Checkpoints which let a [`Synthesizer`] run resume after a crash or
+//! preemption.
+//!
+//! A checkpoint records the e-graph once rewriting finishes, in egglog syntax
+//! (see [`crate::egglog`]), and every backend verdict so far. A resumed run
+//! skips rewriting and only asks the backend about candidates it has no
+//! verdict for. The checkpoint also records what determines those (see
+//! [`RunIdentity`]), so that it isn't resumed by a different run.
+//!
+//! [`Synthesizer`]: crate::synthesizer::Synthesizer
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::LakeroadError,
+    program_set::ProgramSet,
+    synthesizer::{RunReport, VerdictReport},
+};
+
+/// Where checkpoints are written and how often.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckpointOptions {
+    pub path: PathBuf,
+    /// Write the checkpoint after this many new verdicts, as well as after
+    /// rewriting and verification. Zero means only after each phase.
+    #[serde(default = "default_every")]
+    pub every: usize,
+}
+
+fn default_every() -> usize {
+    10
+}
+
+impl CheckpointOptions {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        CheckpointOptions {
+            path: path.into(),
+            every: default_every(),
+        }
+    }
+}
+
+/// What a run's e-graph and verdicts depend on. A checkpoint is only
+/// resumed by a run with the same.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunIdentity {
+    pub seed: u64,
+    /// Each program's name, weight, and s-expression.
+    pub programs: Vec<(String, f64, String)>,
+    /// The rewrite rules' names, in order.
+    pub rules: Vec<String>,
+    pub iter_limit: usize,
+    pub node_limit: usize,
+    /// The backend's [`name`](crate::backend::SynthesisBackend::name).
+    pub backend: String,
+}
+
+impl RunIdentity {
+    /// The identity of a run over `programs`, with everything else left
+    /// to be filled in.
+    pub fn new(seed: u64, programs: &ProgramSet) -> Self {
+        RunIdentity {
+            seed,
+            programs: programs
+                .iter()
+                .map(|p| (p.name.clone(), p.weight, p.expr.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// What differs between this and `other`, in words, or `None` if
+    /// nothing does.
+    pub fn mismatch(&self, other: &RunIdentity) -> Option<&'static str> {
+        if self.seed != other.seed {
+            Some("seed")
+        } else if self.programs != other.programs {
+            Some("programs")
+        } else if self.rules != other.rules {
+            Some("rules")
+        } else if (self.iter_limit, self.node_limit) != (other.iter_limit, other.node_limit) {
+            Some("limits")
+        } else if self.backend != other.backend {
+            Some("backend")
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Checkpoints written before the rules, limits, and backend were
+    /// recorded have only the seed and programs, and are resumed by no run.
+    #[serde(flatten)]
+    pub run: RunIdentity,
+    /// Present once rewriting has finished.
+    pub exploration: Option<ExplorationState>,
+    /// The backend's verdicts so far, in the order checked.
+    pub verdicts: Vec<VerdictReport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExplorationState {
+    /// The rewritten e-graph, as written by
+    /// [`egraph_to_egglog`](crate::egglog::egraph_to_egglog).
+    pub egraph: String,
+    /// The report as it was after rewriting.
+    pub report: RunReport,
+}
+
+impl Checkpoint {
+    /// An empty checkpoint for the run `run`.
+    pub fn new(run: RunIdentity) -> Self {
+        Checkpoint {
+            run,
+            ..Default::default()
+        }
+    }
+
+    /// Whether this checkpoint was written by the run `run`.
+    pub fn is_for(&self, run: &RunIdentity) -> bool {
+        self.run.mismatch(run).is_none()
+    }
+
+    /// Reads the checkpoint at `path`, or returns `None` if there is none.
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, LakeroadError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(None);
+        }
+        serde_json::from_str(&fs::read_to_string(path)?)
+            .map(Some)
+            .map_err(|e| LakeroadError::Parse(format!("checkpoint {}: {}", path.display(), e)))
+    }
+
+    /// Writes the checkpoint to `path`. The file is replaced atomically, so
+    /// a crash while saving leaves the previous checkpoint intact.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), LakeroadError> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(
+            &partial,
+            serde_json::to_string(self).expect("checkpoints always serialize"),
+        )?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::RecExpr;
+
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let mut programs = ProgramSet::new();
+        programs.add(
+            "and",
+            RecExpr::from_str("(binop and 8 (var x 8) (var y 8))").unwrap(),
+        );
+        let run = RunIdentity {
+            rules: vec!["commute-and".to_string()],
+            iter_limit: 10,
+            node_limit: 1000,
+            backend: "racket".to_string(),
+            ..RunIdentity::new(3, &programs)
+        };
+        let mut checkpoint = Checkpoint::new(run.clone());
+        checkpoint.verdicts.push(VerdictReport {
+            instr: "(instr hole)".to_string(),
+            id: String::new(),
+            accepted: true,
+            seconds: 0.5,
+        });
+        let path =
+            std::env::temp_dir().join(format!("lakeroad-checkpoint-{}.json", std::process::id()));
+        assert_eq!(Checkpoint::load(&path).unwrap(), None);
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, checkpoint);
+        assert!(loaded.is_for(&run));
+        for (other, mismatch) in [
+            (
+                RunIdentity {
+                    seed: 4,
+                    ..run.clone()
+                },
+                "seed",
+            ),
+            (
+                RunIdentity {
+                    programs: vec![],
+                    ..run.clone()
+                },
+                "programs",
+            ),
+            (
+                RunIdentity {
+                    rules: vec![],
+                    ..run.clone()
+                },
+                "rules",
+            ),
+            (
+                RunIdentity {
+                    node_limit: 10,
+                    ..run.clone()
+                },
+                "limits",
+            ),
+            (
+                RunIdentity {
+                    backend: "z3".to_string(),
+                    ..run.clone()
+                },
+                "backend",
+            ),
+        ] {
+            assert!(!loaded.is_for(&other));
+            assert_eq!(run.mismatch(&other), Some(mismatch));
+        }
+
+        // A checkpoint from before the rules, limits, and backend were
+        // recorded still loads, but is for no run.
+        let old = r#"{"seed":3,"programs":[],"exploration":null,"verdicts":[]}"#;
+        let old: Checkpoint = serde_json::from_str(old).unwrap();
+        assert_eq!(old.run.seed, 3);
+        assert!(!old.is_for(&run));
+    }
+}
@@ -0,0 +1,430 @@
+//! Run configuration files (`lakeroad.toml`).
+//!
+//! A configuration gathers everything needed to reproduce a run:
+//!
+//! ```toml
+//! programs = ["programs/", "extra/avg.sexp"]
+//! rules = ["introduce-hole-var", "introduce-hole-op-both", "canonicalize"]
+//! solver = "racket"
+//! seed = 1
+//! parallel = true
+//! database = "runs.sqlite"
+//! arch = "xilinx-ultrascale-plus"
+//!
+//! [limits]
+//! iter_limit = 10
+//! node_limit = 100000
+//! max_instructions = 3
+//! time_budget = 600
+//!
+//! [cost]
+//! per_instruction = 0.5
+//!
+//! [ports]
+//! registers = 2
+//! destructive = true
+//! immediate = 12
+//!
+//! [trivial]
+//! subsumed = false
+//!
+//! [cost.primitives]
+//! dsp = 4
+//!
+//! [output]
+//! directory = "out"
+//! formats = ["verilog", "json"]
+//! report = true
+//! test_vectors = 16
+//!
+//! [checkpoint]
+//! path = "out/checkpoint.json"
+//! every = 10
+//! ```
+//!
+//! Only `programs` is required. Paths are relative to the configuration file.
+//! Directories are loaded with [`Corpus::load`]. Without `rules`, the
+//! [`default_rules`] are used. With `[checkpoint]`, the run resumes from the
+//! checkpoint if it exists (see [`crate::checkpoint`]).
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use egg::Rewrite;
+use serde::Deserialize;
+
+use crate::{
+    analysis::LanguageAnalysis,
+    checkpoint::CheckpointOptions,
+    corpus::Corpus,
+    emit::{export_instructions, export_testbench, ExportFormat},
+    error::LakeroadError,
+    language::Language,
+    profile::Timer,
+    program_set::ProgramSet,
+    prune::OperandPorts,
+    synthesizer::{default_rules, CostModel, SynthesisResult, Synthesizer},
+    trivial::TrivialFilter,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub programs: Vec<PathBuf>,
+    /// Rule names, as in [`rules_named`].
+    pub rules: Option<Vec<String>>,
+    #[serde(default)]
+    pub limits: Limits,
+    #[serde(default)]
+    pub solver: Solver,
+    #[serde(default)]
+    pub cost: CostModel,
+    /// See [`Synthesizer::with_operand_ports`].
+    pub ports: Option<OperandPorts>,
+    /// See [`Synthesizer::with_trivial_filter`].
+    pub trivial: Option<TrivialFilter>,
+    #[serde(default)]
+    pub output: Output,
+    /// See [`Synthesizer::with_seed`].
+    #[serde(default)]
+    pub seed: u64,
+    pub checkpoint: Option<CheckpointOptions>,
+    /// A [`Database`](crate::database::Database) to record the run in.
+    pub database: Option<PathBuf>,
+    /// See [`Synthesizer::with_parallel_exploration`].
+    #[serde(default)]
+    pub parallel: bool,
+    /// An [`Architecture`](crate::arch::Architecture) name; see
+    /// [`Synthesizer::with_architecture`].
+    pub arch: Option<String>,
+    /// The directory relative paths are resolved against.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub iter_limit: usize,
+    pub node_limit: usize,
+    pub max_instructions: usize,
+    /// See [`Synthesizer::with_k`].
+    pub k: usize,
+    /// Seconds the whole run may take; see
+    /// [`Synthesizer::with_time_budget`].
+    pub time_budget: Option<f64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            iter_limit: 10,
+            node_limit: 100_000,
+            max_instructions: 3,
+            k: 16,
+            time_budget: None,
+        }
+    }
+}
+
+/// Which backend checks candidates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Solver {
+    /// Accept every candidate.
+    #[default]
+    None,
+    /// [`RacketBackend`](crate::racket::RacketBackend).
+    Racket,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Output {
+    pub directory: PathBuf,
+    pub formats: Vec<ExportFormat>,
+    /// Whether to write the [`RunReport`](crate::synthesizer::RunReport) to
+    /// `report.json`.
+    pub report: bool,
+    /// How many test cases to check each instruction on, in a testbench
+    /// written next to each format's output as `isa_tb.<extension>` (see
+    /// [`export_testbench`]). No testbenches are written if zero.
+    pub test_vectors: usize,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output {
+            directory: PathBuf::from("."),
+            formats: vec![],
+            report: false,
+            test_vectors: 16,
+        }
+    }
+}
+
+/// `seconds` as a time budget.
+fn budget(seconds: f64) -> Result<Duration, LakeroadError> {
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| LakeroadError::Config(format!("time budget of {} seconds", seconds)))
+}
+
+/// Looks up rules by name, e.g. `"fuse-op"`. The available rules are the
+/// [`default_rules`], and each may be named once.
+pub fn rules_named(
+    names: &[String],
+) -> Result<Vec<Rewrite<Language, LanguageAnalysis>>, LakeroadError> {
+    let mut available = default_rules();
+    names
+        .iter()
+        .map(|name| {
+            available
+                .iter()
+                .position(|rule| rule.name.to_string() == *name)
+                .map(|i| available.swap_remove(i))
+                .ok_or_else(|| {
+                    LakeroadError::Config(format!("no rule named {}, or it is named twice", name))
+                })
+        })
+        .collect()
+}
+
+impl Config {
+    /// Parses a configuration whose relative paths are relative to the
+    /// current directory.
+    pub fn from_toml(input: &str) -> Result<Self, LakeroadError> {
+        toml::from_str(input).map_err(|e| LakeroadError::Parse(e.to_string()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LakeroadError> {
+        let path = path.as_ref();
+        let mut config = Self::from_toml(&fs::read_to_string(path)?)?;
+        config.base_dir = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        Ok(config)
+    }
+
+    fn resolve(&self, path: &Path) -> PathBuf {
+        self.base_dir.join(path)
+    }
+
+    pub fn load_programs(&self) -> Result<ProgramSet, LakeroadError> {
+        let mut programs = ProgramSet::new();
+        for path in &self.programs {
+            let path = self.resolve(path);
+            let loaded = if path.is_dir() {
+                Corpus::load(&path)?
+            } else {
+                Corpus::load_file(&path).ok_or_else(|| {
+                    LakeroadError::Config(format!("{} is not a program file", path.display()))
+                })??
+            };
+            for program in loaded.iter() {
+                if programs.get(&program.name).is_some() {
+                    return Err(LakeroadError::Config(format!(
+                        "a program named {} is loaded twice",
+                        program.name
+                    )));
+                }
+                programs.add_weighted(
+                    program.name.clone(),
+                    program.expr.clone(),
+                    program.weight,
+                )?;
+            }
+        }
+        Ok(programs)
+    }
+
+    /// Builds the [`Synthesizer`] this configuration describes, loading its
+    /// programs.
+    pub fn synthesizer(&self) -> Result<Synthesizer, LakeroadError> {
+        let mut synthesizer = Synthesizer::new()
+            .add_programs(&self.load_programs()?)
+            .with_iter_limit(self.limits.iter_limit)
+            .with_node_limit(self.limits.node_limit)
+            .with_max_instructions(self.limits.max_instructions)
+            .with_k(self.limits.k)
+            .with_seed(self.seed)
+            .with_parallel_exploration(self.parallel)
+            .with_cost_model(self.cost.clone());
+        if let Some(names) = &self.rules {
+            synthesizer = synthesizer.with_rules(rules_named(names)?);
+        }
+        if let Some(seconds) = self.limits.time_budget {
+            synthesizer = synthesizer.with_time_budget(budget(seconds)?);
+        }
+        if let Some(ports) = self.ports {
+            synthesizer = synthesizer.with_operand_ports(ports);
+        }
+        if let Some(filter) = self.trivial {
+            synthesizer = synthesizer.with_trivial_filter(filter);
+        }
+        if let Some(arch) = &self.arch {
+            synthesizer = synthesizer.with_architecture(crate::arch::by_name(arch)?);
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            synthesizer = synthesizer.with_checkpoint(CheckpointOptions {
+                path: self.resolve(&checkpoint.path),
+                every: checkpoint.every,
+            });
+        }
+        if self.solver == Solver::Racket {
+            #[cfg(all(feature = "racket", not(target_arch = "wasm32")))]
+            {
+                synthesizer =
+                    synthesizer.with_synthesis_backend(crate::racket::RacketBackend::new());
+            }
+            #[cfg(not(all(feature = "racket", not(target_arch = "wasm32"))))]
+            return Err(LakeroadError::Config(
+                "the racket solver needs the `racket` feature, and is unavailable in \
+                 WebAssembly builds"
+                    .to_string(),
+            ));
+        }
+        Ok(synthesizer)
+    }
+
+    #[cfg(all(feature = "database", not(target_arch = "wasm32")))]
+    fn record(&self, database: &Path, result: &SynthesisResult) -> Result<PathBuf, LakeroadError> {
+        let path = self.resolve(database);
+        crate::database::Database::open(&path)?.record(&result.report)?;
+        Ok(path)
+    }
+
+    #[cfg(not(all(feature = "database", not(target_arch = "wasm32"))))]
+    fn record(&self, _: &Path, _: &SynthesisResult) -> Result<PathBuf, LakeroadError> {
+        Err(LakeroadError::Config(
+            "databases need the `database` feature, and are unavailable in WebAssembly \
+             builds"
+                .to_string(),
+        ))
+    }
+
+    /// Writes the selected instructions in each output format, to
+    /// `isa.<extension>` in the output directory with their testbenches, and
+    /// the report if asked, and records the run in the database if there is
+    /// one. Returns the files written. The time taken to export the
+    /// instructions is added to the report's profile first.
+    pub fn write_outputs(
+        &self,
+        result: &mut SynthesisResult,
+    ) -> Result<Vec<PathBuf>, LakeroadError> {
+        let directory = self.resolve(&self.output.directory);
+        fs::create_dir_all(&directory)?;
+        let mut written = vec![];
+        let export = Timer::start();
+        for format in &self.output.formats {
+            let path = directory.join(format!("isa.{}", format.extension()));
+            fs::write(&path, export_instructions(*format, &result.instructions)?)?;
+            written.push(path);
+            if self.output.test_vectors > 0 {
+                let path = directory.join(format!("isa_tb.{}", format.testbench_extension()));
+                let testbench = export_testbench(
+                    *format,
+                    &result.instructions,
+                    self.output.test_vectors,
+                    self.seed,
+                )?;
+                fs::write(&path, testbench)?;
+                written.push(path);
+            }
+        }
+        result.report.profile.export = export.elapsed();
+        if self.output.report {
+            let path = directory.join("report.json");
+            fs::write(&path, result.report.to_json())?;
+            written.push(path);
+        }
+        if let Some(database) = &self.database {
+            written.push(self.record(database, result)?);
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config = Config::from_toml(
+            r#"
+            programs = ["programs/"]
+            rules = ["introduce-hole-var", "canonicalize"]
+            solver = "racket"
+
+            [limits]
+            max_instructions = 2
+            time_budget = 60
+
+            [cost]
+            per_instruction = 0.5
+
+            [cost.primitives]
+            dsp = 4
+
+            [ports]
+            registers = 2
+            destructive = true
+
+            [trivial]
+            subsumed = false
+
+            [output]
+            formats = ["verilog", "rosette"]
+
+            [checkpoint]
+            path = "checkpoint.json"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.programs, vec![PathBuf::from("programs/")]);
+        assert_eq!(config.solver, Solver::Racket);
+        assert_eq!(config.limits.max_instructions, 2);
+        assert_eq!(config.limits.iter_limit, 10);
+        assert_eq!(config.limits.time_budget, Some(60.0));
+        assert_eq!(config.cost.per_instruction, 0.5);
+        assert_eq!(config.cost.primitives.dsp, 4.0);
+        assert_eq!(config.cost.primitives.lut, 1.0);
+        assert_eq!(
+            config.ports,
+            Some(OperandPorts {
+                registers: 2,
+                destructive: true,
+                immediate: None,
+            })
+        );
+        assert_eq!(
+            config.trivial,
+            Some(TrivialFilter {
+                subsumed: false,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            config.output.formats,
+            vec![ExportFormat::Verilog, ExportFormat::Rosette]
+        );
+        assert_eq!(
+            config.checkpoint,
+            Some(CheckpointOptions::new("checkpoint.json"))
+        );
+        assert_eq!(
+            rules_named(config.rules.as_ref().unwrap()).unwrap().len(),
+            2
+        );
+
+        assert!(matches!(
+            rules_named(&["no-such-rule".to_string()]),
+            Err(LakeroadError::Config(_))
+        ));
+        assert!(matches!(
+            Config::from_toml("programs = []\nsolver = \"z3\""),
+            Err(LakeroadError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,250 @@
+//! Loading directories of program files.
+//!
+//! The format of each file is chosen by its extension:
+//!
+//! - `.lakeroad`, `.sexp`: an s-expression, with optional bitwidths (see
+//!   [`crate::frontends::elaborate`]),
+//! - `.lrc`: the C-like syntax of [`crate::frontends::c`],
+//! - `.v`: Verilog ([`crate::frontends::verilog`]),
+//! - `.json`: the interchange format of [`crate::frontends::json`] if the
+//!   top-level object has a `programs` key, or a Yosys netlist
+//!   ([`crate::frontends::yosys`]) otherwise,
+//! - `.ll`: LLVM IR ([`crate::frontends::llvm`]),
+//! - `.smt2`: SMT-LIB2 ([`crate::frontends::smtlib`]).
+//!
+//! Files with other extensions are ignored. Single-program formats are named
+//! after the file's stem; the others use the names their importer gives.
+
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    frontends::{
+        c::{parse_c, CFrontendError},
+        elaborate::{elaborate, ElaborationError},
+        json::{load_json, JsonError},
+        llvm::{import_llvm_ir, LlvmError},
+        smtlib::{import_smtlib, SmtError},
+        verilog::{import_verilog, VerilogError},
+        yosys::{import_yosys_json, YosysError},
+    },
+    language::{typecheck_expr, ExprTypeError},
+    program_set::ProgramSet,
+};
+
+/// Why a single file failed to load.
+#[derive(Debug)]
+pub enum ProgramFileError {
+    Io(io::Error),
+    Elaboration(ElaborationError),
+    C(CFrontendError),
+    Verilog(VerilogError),
+    Yosys(YosysError),
+    Json(JsonError),
+    Llvm(LlvmError),
+    Smt(SmtError),
+    /// The program `name` failed to type check.
+    Type {
+        name: String,
+        error: ExprTypeError,
+    },
+    /// A program named `name` was already loaded from another file.
+    DuplicateName {
+        name: String,
+    },
+}
+impl Display for ProgramFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProgramFileError::Io(e) => write!(f, "{}", e),
+            ProgramFileError::Elaboration(e) => write!(f, "{}", e),
+            ProgramFileError::C(e) => write!(f, "{}", e),
+            ProgramFileError::Verilog(e) => write!(f, "{}", e),
+            ProgramFileError::Yosys(e) => write!(f, "{}", e),
+            ProgramFileError::Json(e) => write!(f, "{}", e),
+            ProgramFileError::Llvm(e) => write!(f, "{}", e),
+            ProgramFileError::Smt(e) => write!(f, "{}", e),
+            ProgramFileError::Type { name, error } => write!(f, "{}: {}", name, error),
+            ProgramFileError::DuplicateName { name } => {
+                write!(f, "a program named {} was already loaded", name)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FileError {
+    pub path: PathBuf,
+    pub error: ProgramFileError,
+}
+impl Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+#[derive(Debug)]
+pub enum CorpusError {
+    /// The directory itself couldn't be read.
+    Io(io::Error),
+    /// Some files failed to load. Every failure is reported, not just the
+    /// first.
+    Files(Vec<FileError>),
+}
+impl Display for CorpusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CorpusError::Io(e) => write!(f, "{}", e),
+            CorpusError::Files(errors) => {
+                write!(f, "{} files failed to load:", errors.len())?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+impl std::error::Error for CorpusError {}
+
+/// A directory of program files.
+pub struct Corpus;
+
+impl Corpus {
+    /// Loads every program file directly inside `dir`, in order of file name.
+    pub fn load(dir: impl AsRef<Path>) -> Result<ProgramSet, CorpusError> {
+        let mut paths = fs::read_dir(dir)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .map_err(CorpusError::Io)?;
+        paths.sort();
+
+        let mut programs = ProgramSet::new();
+        let mut errors = vec![];
+        for path in paths.into_iter().filter(|path| path.is_file()) {
+            let loaded = match Self::load_file(&path) {
+                Some(loaded) => loaded,
+                None => continue,
+            };
+            let result = loaded.and_then(|loaded| {
+                for program in loaded.iter() {
+                    if programs.get(&program.name).is_some() {
+                        return Err(ProgramFileError::DuplicateName {
+                            name: program.name.clone(),
+                        });
+                    }
+                    typecheck_expr(&program.expr).map_err(|error| ProgramFileError::Type {
+                        name: program.name.clone(),
+                        error,
+                    })?;
+                }
+                Ok(loaded)
+            });
+            match result {
+                Ok(loaded) => {
+                    programs.append(&loaded);
+                }
+                Err(error) => errors.push(FileError { path, error }),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(programs)
+        } else {
+            Err(CorpusError::Files(errors))
+        }
+    }
+
+    /// Loads the programs in one file, or returns `None` if the file isn't a
+    /// program file.
+    pub fn load_file(path: &Path) -> Option<Result<ProgramSet, ProgramFileError>> {
+        let extension = path.extension()?.to_str()?;
+        if !matches!(
+            extension,
+            "lakeroad" | "sexp" | "lrc" | "v" | "json" | "ll" | "smt2"
+        ) {
+            return None;
+        }
+        let stem = path.file_stem()?.to_string_lossy().into_owned();
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => return Some(Err(ProgramFileError::Io(e))),
+        };
+        let single = |expr| {
+            let mut programs = ProgramSet::new();
+            programs.add(stem.clone(), expr);
+            programs
+        };
+        Some(match extension {
+            "lakeroad" | "sexp" => elaborate(&source)
+                .map(single)
+                .map_err(ProgramFileError::Elaboration),
+            "lrc" => parse_c(&source).map(single).map_err(ProgramFileError::C),
+            "v" => import_verilog(&source).map_err(ProgramFileError::Verilog),
+            "json"
+                if serde_json::from_str::<serde_json::Value>(&source)
+                    .map_or(false, |json| json.get("programs").is_some()) =>
+            {
+                load_json(&source).map_err(ProgramFileError::Json)
+            }
+            "json" => import_yosys_json(&source).map_err(ProgramFileError::Yosys),
+            "ll" => import_llvm_ir(&source).map_err(ProgramFileError::Llvm),
+            "smt2" => import_smtlib(&source).map_err(ProgramFileError::Smt),
+            _ => unreachable!(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::example_programs::all_programs;
+
+    #[test]
+    fn load_example_programs() {
+        let programs =
+            Corpus::load(Path::new(env!("CARGO_MANIFEST_DIR")).join("lakeroad_programs")).unwrap();
+        let expected = all_programs();
+        assert_eq!(programs.len(), expected.len());
+        for (name, expr) in expected {
+            assert_eq!(
+                programs.get(&name).unwrap().expr.to_string(),
+                expr.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn reports_every_bad_file() {
+        let dir = std::env::temp_dir().join(format!("lakeroad-corpus-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("good.lrc"), "u8 x, y; x & y").unwrap();
+        fs::write(
+            dir.join("bad_width.sexp"),
+            "(binop and (var x 8) (var y 4))",
+        )
+        .unwrap();
+        fs::write(dir.join("bad_syntax.lrc"), "u8 x; x +").unwrap();
+        fs::write(dir.join("notes.txt"), "not a program").unwrap();
+
+        let result = Corpus::load(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        match result {
+            Err(CorpusError::Files(errors)) => {
+                let mut names = errors
+                    .iter()
+                    .map(|e| e.path.file_name().unwrap().to_str().unwrap())
+                    .collect::<Vec<_>>();
+                names.sort();
+                assert_eq!(names, vec!["bad_syntax.lrc", "bad_width.sexp"]);
+            }
+            _ => panic!(),
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! A SQLite database of runs, for mining results across many runs.
+//!
+//! Each recorded [`RunReport`] gets a run id. The tables are:
+//!
+//! - `runs(id, recorded_at, seed, score, iterations, egraph_nodes,
+//!   egraph_classes, stop_reason, report)`, where `report` is the whole
+//!   report as JSON;
+//! - `programs(run_id, name, weight, cost)`;
+//! - `rule_applications(run_id, iteration, rule, applied)`;
+//! - `candidates(run_id, instr, accepted, seconds, isa_index, instr_id)`,
+//!   where `isa_index` is the candidate's position in the selected ISA, or
+//!   null if it wasn't selected, and `instr_id` is its
+//!   [`crate::ast::Instr::stable_id`], which is the same for the same
+//!   instruction in every run, or null in runs recorded before ids were.
+//!
+//! The methods below cover common questions; anything else can be asked in
+//! SQL through [`Database::connection`].
+
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection};
+
+use crate::{error::LakeroadError, synthesizer::RunReport};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    recorded_at INTEGER NOT NULL,
+    seed INTEGER NOT NULL,
+    score REAL NOT NULL,
+    iterations INTEGER NOT NULL,
+    egraph_nodes INTEGER NOT NULL,
+    egraph_classes INTEGER NOT NULL,
+    stop_reason TEXT,
+    report TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS programs (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    name TEXT NOT NULL,
+    weight REAL NOT NULL,
+    cost INTEGER
+);
+CREATE TABLE IF NOT EXISTS rule_applications (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    iteration INTEGER NOT NULL,
+    rule TEXT NOT NULL,
+    applied INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS candidates (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    instr TEXT NOT NULL,
+    accepted INTEGER NOT NULL,
+    seconds REAL NOT NULL,
+    isa_index INTEGER,
+    instr_id TEXT
+);
+CREATE INDEX IF NOT EXISTS candidates_by_instr ON candidates(instr);
+";
+
+/// Brings databases created before candidates had ids up to date.
+const ADD_INSTR_IDS: &str = "ALTER TABLE candidates ADD COLUMN instr_id TEXT";
+
+impl From<rusqlite::Error> for LakeroadError {
+    fn from(error: rusqlite::Error) -> Self {
+        LakeroadError::Database(error.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub id: i64,
+    /// Seconds since the Unix epoch.
+    pub recorded_at: i64,
+    pub seed: u64,
+    pub score: f64,
+    pub isa_size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CandidateRecord {
+    pub instr: String,
+    pub id: Option<String>,
+    pub accepted: bool,
+    pub seconds: f64,
+    pub isa_index: Option<usize>,
+}
+
+/// How often an instruction was found, accepted, and selected across runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstrStats {
+    /// The instruction as one of the runs found it.
+    pub instr: String,
+    pub id: Option<String>,
+    pub found: usize,
+    pub accepted: usize,
+    pub selected: usize,
+}
+
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, LakeroadError> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, LakeroadError> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, LakeroadError> {
+        connection.execute_batch(SCHEMA)?;
+        let has_ids = connection
+            .prepare("SELECT * FROM candidates LIMIT 0")?
+            .column_names()
+            .contains(&"instr_id");
+        if !has_ids {
+            connection.execute_batch(ADD_INSTR_IDS)?;
+        }
+        connection.execute_batch(
+            "CREATE INDEX IF NOT EXISTS candidates_by_instr_id ON candidates(instr_id)",
+        )?;
+        Ok(Database { connection })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Records a run, returning its id.
+    pub fn record(&mut self, report: &RunReport) -> Result<i64, LakeroadError> {
+        let recorded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO runs (recorded_at, seed, score, iterations, egraph_nodes,
+                egraph_classes, stop_reason, report)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                recorded_at,
+                report.seed as i64,
+                report.score,
+                report.iterations as i64,
+                report.egraph_nodes as i64,
+                report.egraph_classes as i64,
+                report.stop_reason,
+                report.to_json(),
+            ],
+        )?;
+        let run_id = transaction.last_insert_rowid();
+        for (i, name) in report.program_names.iter().enumerate() {
+            let coverage = report.coverage.get(i);
+            transaction.execute(
+                "INSERT INTO programs (run_id, name, weight, cost) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    run_id,
+                    name,
+                    coverage.map_or(1.0, |c| c.weight),
+                    coverage.and_then(|c| c.cost).map(|c| c as i64),
+                ],
+            )?;
+        }
+        for (i, iteration) in report.iteration_stats.iter().enumerate() {
+            for (rule, applied) in &iteration.rules {
+                transaction.execute(
+                    "INSERT INTO rule_applications (run_id, iteration, rule, applied)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![run_id, i as i64, rule, *applied as i64],
+                )?;
+            }
+        }
+        for verdict in &report.verdicts {
+            let isa_index = report.isa.iter().position(|i| *i == verdict.instr);
+            transaction.execute(
+                "INSERT INTO candidates (run_id, instr, accepted, seconds, isa_index, instr_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    run_id,
+                    verdict.instr,
+                    verdict.accepted,
+                    verdict.seconds,
+                    isa_index.map(|i| i as i64),
+                    (!verdict.id.is_empty()).then_some(&verdict.id),
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(run_id)
+    }
+
+    /// Every run, oldest first.
+    pub fn runs(&self) -> Result<Vec<RunSummary>, LakeroadError> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, recorded_at, seed, score,
+                (SELECT COUNT(*) FROM candidates
+                 WHERE run_id = runs.id AND isa_index IS NOT NULL)
+             FROM runs ORDER BY id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(RunSummary {
+                id: row.get(0)?,
+                recorded_at: row.get(1)?,
+                seed: row.get::<_, i64>(2)? as u64,
+                score: row.get(3)?,
+                isa_size: row.get::<_, i64>(4)? as usize,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// The run's report, as recorded.
+    pub fn report(&self, run_id: i64) -> Result<RunReport, LakeroadError> {
+        let json: String = self.connection.query_row(
+            "SELECT report FROM runs WHERE id = ?1",
+            params![run_id],
+            |row| row.get(0),
+        )?;
+        serde_json::from_str(&json).map_err(|e| LakeroadError::Database(e.to_string()))
+    }
+
+    /// The run's candidates, in the order they were checked.
+    pub fn candidates(&self, run_id: i64) -> Result<Vec<CandidateRecord>, LakeroadError> {
+        let mut statement = self.connection.prepare(
+            "SELECT instr, accepted, seconds, isa_index, instr_id FROM candidates
+             WHERE run_id = ?1 ORDER BY rowid",
+        )?;
+        let rows = statement.query_map(params![run_id], |row| {
+            Ok(CandidateRecord {
+                instr: row.get(0)?,
+                id: row.get(4)?,
+                accepted: row.get(1)?,
+                seconds: row.get(2)?,
+                isa_index: row.get::<_, Option<i64>>(3)?.map(|i| i as usize),
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// For each instruction ever found, how many runs found, accepted, and
+    /// selected it, most often selected first. Instructions are told apart
+    /// by id where they have one, so that those the same up to the
+    /// numbering of their arguments are counted together.
+    pub fn instr_stats(&self) -> Result<Vec<InstrStats>, LakeroadError> {
+        let mut statement = self.connection.prepare(
+            "SELECT MIN(instr) AS first, MAX(instr_id), COUNT(DISTINCT run_id),
+                COUNT(DISTINCT CASE WHEN accepted THEN run_id END),
+                COUNT(DISTINCT CASE WHEN isa_index IS NOT NULL THEN run_id END) AS selected
+             FROM candidates GROUP BY COALESCE(instr_id, instr)
+             ORDER BY selected DESC, first",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(InstrStats {
+                instr: row.get(0)?,
+                id: row.get(1)?,
+                found: row.get::<_, i64>(2)? as usize,
+                accepted: row.get::<_, i64>(3)? as usize,
+                selected: row.get::<_, i64>(4)? as usize,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// How many times each rule was applied in the run, summed over
+    /// iterations, by name.
+    pub fn rule_applications(&self, run_id: i64) -> Result<Vec<(String, usize)>, LakeroadError> {
+        let mut statement = self.connection.prepare(
+            "SELECT rule, SUM(applied) FROM rule_applications
+             WHERE run_id = ?1 GROUP BY rule ORDER BY rule",
+        )?;
+        let rows = statement.query_map(params![run_id], |row| {
+            Ok((row.get(0)?, row.get::<_, i64>(1)? as usize))
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::RecExpr;
+
+    use super::*;
+    use crate::{
+        rewrites::{canonicalize, introduce_hole_op_both, introduce_hole_var},
+        synthesizer::Synthesizer,
+    };
+
+    #[test]
+    fn record_and_query() {
+        let result = Synthesizer::new()
+            .add_program(
+                "and",
+                RecExpr::from_str("(binop and 8 (var x 8) (var y 8))").unwrap(),
+            )
+            .add_program(
+                "or",
+                RecExpr::from_str("(binop or 8 (var x 8) (var y 8))").unwrap(),
+            )
+            .with_rules(vec![
+                introduce_hole_var(),
+                introduce_hole_op_both(),
+                canonicalize(),
+            ])
+            .with_max_instructions(2)
+            .run()
+            .unwrap();
+        let mut database = Database::open_in_memory().unwrap();
+        let first = database.record(&result.report).unwrap();
+        let second = database.record(&result.report).unwrap();
+
+        let runs = database.runs().unwrap();
+        assert_eq!(
+            runs.iter().map(|r| r.id).collect::<Vec<_>>(),
+            vec![first, second]
+        );
+        assert_eq!(runs[0].isa_size, 2);
+        assert_eq!(database.report(first).unwrap().isa, result.report.isa);
+
+        let candidates = database.candidates(first).unwrap();
+        assert_eq!(candidates.len(), result.report.verdicts.len());
+        assert_eq!(
+            candidates.iter().filter(|c| c.isa_index.is_some()).count(),
+            2
+        );
+
+        let stats = database.instr_stats().unwrap();
+        assert_eq!(stats[0].selected, 2);
+        assert_eq!(stats[0].found, 2);
+        assert!(result
+            .report
+            .isa_ids
+            .contains(stats[0].id.as_ref().unwrap()));
+        assert!(database
+            .rule_applications(first)
+            .unwrap()
+            .iter()
+            .any(|(rule, applied)| rule == "introduce-hole-var" && *applied > 0));
+    }
+}
@@ -0,0 +1,36 @@
+//! Reproducible runs.
+//!
+//! Every randomized component takes its generator from [`rng`], seeded
+//! explicitly, so a run is determined by its inputs and seed. The generator
+//! is ChaCha8, whose output is specified independently of the platform and of
+//! the `rand` version, unlike `StdRng`'s.
+//!
+//! Nothing else in a run depends on hash seeds: egg hashes with a fixed
+//! hasher, and the crate's own `HashMap`s and `HashSet`s are only iterated
+//! where order doesn't matter or after sorting.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// The seed used when none is given.
+pub const DEFAULT_SEED: u64 = 0;
+
+pub type SeededRng = ChaCha8Rng;
+
+pub fn rng(seed: u64) -> SeededRng {
+    ChaCha8Rng::seed_from_u64(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn same_seed_same_stream() {
+        let a = rng(42).gen::<[u64; 4]>();
+        assert_eq!(a, rng(42).gen::<[u64; 4]>());
+        assert_ne!(a, rng(43).gen::<[u64; 4]>());
+    }
+}
@@ -0,0 +1,329 @@
+//! DSP slices, modeled as parameterized instructions.
+//!
+//! A DSP slice (e.g. Xilinx's DSP48) computes `((a ± d) * b) op c`, and can
+//! compare the result against a pattern, with each stage optional. A
+//! [`DspConfig`] picks which stages are used, and is an instruction like any
+//! other: its AST has a hole per input, in the order `a`, `d`, `b`, `c`, and
+//! `pattern`. [`dsp_rules`] fuse arithmetic of the same shape into `apply`s
+//! of the configurations, and [`DspConfig::implements`] asks a backend
+//! whether a configuration computes a given expression.
+//!
+//! Every stage has the same width, as all operators in the language do, and
+//! the rewrites only match operands in the configuration's order, so e.g.
+//! `c + a * b` isn't fused unless something first commutes it.
+
+use std::str::FromStr;
+
+use egg::{rewrite, EGraph, Id, Pattern, RecExpr, Rewrite, Subst, Var};
+
+use crate::{
+    analysis::{LanguageAnalysis, LanguageAnalysisData},
+    ast::Ast,
+    backend::SynthesisBackend,
+    error::LakeroadError,
+    language::{Language, Op},
+};
+
+/// Which stages of a DSP slice are used.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DspConfig {
+    /// `a + d` or `a - d`, into the multiplier.
+    pub pre_adder: Option<Op>,
+    /// Multiplies by `b`; otherwise the multiplier passes its input through.
+    pub multiply: bool,
+    /// Combines the product with `c`: `add`, `sub` (the product minus `c`),
+    /// `and`, `or`, or `xor`.
+    pub alu: Option<Op>,
+    /// Whether the output is whether the result equals `pattern`.
+    pub pattern_detect: bool,
+}
+
+impl DspConfig {
+    /// Every configuration which uses at least one stage. The pre-adder is
+    /// only used along with the multiplier, as it only feeds the multiplier.
+    pub fn all() -> Vec<DspConfig> {
+        let mut out = vec![];
+        for pre_adder in [None, Some(Op::Add), Some(Op::Sub)] {
+            for multiply in [false, true] {
+                for alu in [
+                    None,
+                    Some(Op::Add),
+                    Some(Op::Sub),
+                    Some(Op::And),
+                    Some(Op::Or),
+                    Some(Op::Xor),
+                ] {
+                    for pattern_detect in [false, true] {
+                        let config = DspConfig {
+                            pre_adder: pre_adder.clone(),
+                            multiply,
+                            alu: alu.clone(),
+                            pattern_detect,
+                        };
+                        if config != DspConfig::none()
+                            && (config.multiply || config.pre_adder.is_none())
+                        {
+                            out.push(config);
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// The inputs used, in the order of the holes.
+    pub fn inputs(&self) -> Vec<&'static str> {
+        let mut out = vec!["a"];
+        if self.pre_adder.is_some() {
+            out.push("d");
+        }
+        if self.multiply {
+            out.push("b");
+        }
+        if self.alu.is_some() {
+            out.push("c");
+        }
+        if self.pattern_detect {
+            out.push("pattern");
+        }
+        out
+    }
+
+    /// A name for the configuration, e.g. `dsp-preadd-add-mul-alu-sub`.
+    pub fn name(&self) -> String {
+        let mut out = "dsp".to_string();
+        if let Some(op) = &self.pre_adder {
+            out.push_str(&format!("-preadd-{}", op));
+        }
+        if self.multiply {
+            out.push_str("-mul");
+        }
+        if let Some(op) = &self.alu {
+            out.push_str(&format!("-alu-{}", op));
+        }
+        if self.pattern_detect {
+            out.push_str("-pattern");
+        }
+        out
+    }
+
+    /// The configuration an instr's AST computes, and its width, if the AST
+    /// has the shape [`instr`](Self::instr) gives it.
+    pub fn of_ast(ast: &Ast) -> Option<(DspConfig, usize)> {
+        let width = match ast {
+            Ast::BinOp { width, .. } | Ast::Hole { width } => *width,
+            Ast::UnOp { .. } | Ast::Apply { .. } => return None,
+        };
+        // Peels a stage using any of `ops` off the top of `ast`, if it's
+        // there, leaving its left operand.
+        fn peel(ast: &mut &Ast, ops: &[Op], width: i64) -> Option<Op> {
+            match *ast {
+                Ast::BinOp {
+                    op,
+                    width: w,
+                    lhs,
+                    rhs,
+                } if ops.contains(op) && *w == width && **rhs == (Ast::Hole { width }) => {
+                    *ast = &**lhs;
+                    Some(op.clone())
+                }
+                _ => None,
+            }
+        }
+        let mut ast = ast;
+        let pattern_detect = peel(&mut ast, &[Op::Eq], width).is_some();
+        let alu = peel(
+            &mut ast,
+            &[Op::Add, Op::Sub, Op::And, Op::Or, Op::Xor],
+            width,
+        );
+        let multiply = peel(&mut ast, &[Op::Mul], width).is_some();
+        let pre_adder = if multiply {
+            peel(&mut ast, &[Op::Add, Op::Sub], width)
+        } else {
+            None
+        };
+        let config = DspConfig {
+            pre_adder,
+            multiply,
+            alu,
+            pattern_detect,
+        };
+        match ast {
+            Ast::Hole { width: w } if *w == width && width > 0 && config != DspConfig::none() => {
+                Some((config, width as usize))
+            }
+            _ => None,
+        }
+    }
+
+    /// The configuration using no stages, which isn't a DSP at all.
+    fn none() -> DspConfig {
+        DspConfig {
+            pre_adder: None,
+            multiply: false,
+            alu: None,
+            pattern_detect: false,
+        }
+    }
+
+    /// The computation, as `binop`s (or `binop-ast`s, if `ast`) of `width`
+    /// with each input written as `input` returns.
+    fn template(&self, ast: bool, width: &str, input: impl Fn(&str) -> String) -> String {
+        let node = if ast { "binop-ast" } else { "binop" };
+        let binop =
+            |op: &Op, a: String, b: String| format!("({} {} {} {} {})", node, op, width, a, b);
+        let mut out = input("a");
+        if let Some(op) = &self.pre_adder {
+            out = binop(op, out, input("d"));
+        }
+        if self.multiply {
+            out = binop(&Op::Mul, out, input("b"));
+        }
+        if let Some(op) = &self.alu {
+            out = binop(op, out, input("c"));
+        }
+        if self.pattern_detect {
+            out = binop(&Op::Eq, out, input("pattern"));
+        }
+        out
+    }
+
+    /// The configuration as an `instr` of `width` bits, with a separate
+    /// argument for each input.
+    pub fn instr(&self, width: usize) -> RecExpr<Language> {
+        let width = width.to_string();
+        let args = (0..self.inputs().len())
+            .map(|i| format!(" {}", i))
+            .collect::<String>();
+        RecExpr::from_str(&format!(
+            "(instr {} (canonical-args{}))",
+            self.template(true, &width, |_| format!("(hole {})", width)),
+            args
+        ))
+        .unwrap()
+    }
+
+    /// The rewrite which fuses expressions of the configuration's shape,
+    /// at most `max_width` bits wide, into `apply`s of it.
+    fn rule(&self, max_width: usize) -> Rewrite<Language, LanguageAnalysis> {
+        let searcher: Pattern<Language> = self
+            .template(false, "?bw", |input| format!("?{}", input))
+            .parse()
+            .unwrap();
+        let args = self
+            .inputs()
+            .iter()
+            .map(|input| format!(" ?{}", input))
+            .collect::<String>();
+        let applier: Pattern<Language> = format!(
+            "(apply (instr {} (canonicalize (list{}))) (list{}))",
+            self.template(true, "?bw", |_| "(hole ?bw)".to_string()),
+            args,
+            args
+        )
+        .parse()
+        .unwrap();
+        rewrite!(self.name(); { searcher } => { applier } if width_at_most("?bw", max_width))
+    }
+
+    /// Fills the configuration's inputs with variables of `target`, at
+    /// `width` bits, so that it computes `target`, as checked by `backend`
+    /// (see [`SynthesisBackend::synthesize_holes`]). Returns `None` if no
+    /// filling does.
+    pub fn implements(
+        &self,
+        backend: &impl SynthesisBackend,
+        width: usize,
+        target: &RecExpr<Language>,
+    ) -> Result<Option<RecExpr<Language>>, LakeroadError> {
+        backend.synthesize_holes(&self.instr(width), target)
+    }
+}
+
+/// Condition which holds when the bitwidth bound to `var` is at most
+/// `max_width`.
+pub fn width_at_most(
+    var: &str,
+    max_width: usize,
+) -> impl Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    let var: Var = var.parse().unwrap();
+    move |egraph, _, subst| match egraph[subst[var]].data {
+        LanguageAnalysisData::Num(width) => width > 0 && width as usize <= max_width,
+        _ => false,
+    }
+}
+
+/// A rewrite for each of [`DspConfig::all`], fusing expressions at most
+/// `max_width` bits wide (e.g. 48 for a DSP48) into `apply`s of the
+/// configuration. Their `canonicalize`s need [`crate::rewrites::canonicalize`].
+pub fn dsp_rules(max_width: usize) -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    DspConfig::all()
+        .iter()
+        .map(|config| config.rule(max_width))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use egg::{Runner, Searcher};
+
+    use super::*;
+    use crate::{
+        equiv::{equiv_exhaustive, Exhaustive},
+        rewrites::canonicalize,
+    };
+
+    #[test]
+    fn fuse_and_verify_multiply_accumulate() {
+        let mac = |width: usize| {
+            RecExpr::from_str(&format!(
+                "(binop add {w} (binop mul {w} (binop add {w} (var a {w}) (var d {w})) (var b {w})) \
+                 (var c {w}))",
+                w = width
+            ))
+            .unwrap()
+        };
+        let config = DspConfig {
+            pre_adder: Some(Op::Add),
+            multiply: true,
+            alu: Some(Op::Add),
+            pattern_detect: false,
+        };
+        assert_eq!(config.name(), "dsp-preadd-add-mul-alu-add");
+        assert!(DspConfig::all().contains(&config));
+        for config in DspConfig::all() {
+            let instr = crate::ast::Instr::try_from(&config.instr(8)).unwrap();
+            assert_eq!(DspConfig::of_ast(&instr.ast), Some((config, 8)));
+        }
+
+        let fused: Pattern<Language> = "(apply (instr (binop-ast add ?bw (binop-ast mul ?bw \
+            (binop-ast add ?bw (hole ?bw) (hole ?bw)) (hole ?bw)) (hole ?bw)) ?args) ?list)"
+            .parse()
+            .unwrap();
+        for (width, fits) in [(8, true), (64, false)] {
+            let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+            let root = egraph.add_expr(&mac(width));
+            let mut rules = dsp_rules(48);
+            rules.push(canonicalize());
+            let runner = Runner::default().with_egraph(egraph).run(&rules);
+            let root = runner.egraph.find(root);
+            assert_eq!(
+                fused.search_eclass(&runner.egraph, root).is_some(),
+                fits,
+                "{} bits",
+                width
+            );
+        }
+
+        // Small enough to check every input.
+        let filled = config.implements(&Exhaustive, 2, &mac(2)).unwrap().unwrap();
+        assert_eq!(equiv_exhaustive(&filled, &mac(2)).unwrap(), None);
+        let sub = DspConfig {
+            alu: Some(Op::Sub),
+            ..config
+        };
+        assert_eq!(sub.implements(&Exhaustive, 2, &mac(2)).unwrap(), None);
+    }
+}
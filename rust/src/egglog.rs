@@ -0,0 +1,490 @@
+//! Conversion to and from egglog programs, for experimenting with egglog's
+//! scheduling and rulesets on the same problem.
+//!
+//! Every node of the language becomes a constructor of a single egglog sort
+//! `L` (see [`EGGLOG_SCHEMA`]); variadic `list`s and `canonical-args` are
+//! spelled as `Cons` lists. E-classes are named by `(Class n)` handles, so
+//! an exported e-graph is a sequence of `(union (Class n) node)` facts, which
+//! may be cyclic.
+
+use std::{collections::HashMap, fmt::Display};
+
+use egg::{EGraph, ENodeOrVar, Id, Language as LanguageTrait, PatternAst, RecExpr, Rewrite};
+
+use crate::{
+    analysis::LanguageAnalysis,
+    frontends::sexp::{parse, parse_all, Sexp, SexpError},
+    language::Language,
+};
+
+/// The egglog declaration of the language.
+pub const EGGLOG_SCHEMA: &str = "(datatype L
+  (Var L L)
+  (Const L L)
+  (UnOp L L L)
+  (BinOp L L L L)
+  (Reg L L)
+  (Extract L L L)
+  (Cat L L)
+  (Apply L L)
+  (Hole L)
+  (UnOpAst L L L)
+  (BinOpAst L L L L)
+  (ApplyAst L L)
+  (List L)
+  (Concat L L)
+  (Canonicalize L)
+  (CanonicalArgs L)
+  (Instr L L)
+  (Nil)
+  (Cons L L)
+  (Op String)
+  (Num i64)
+  (Str String)
+  (Class i64))";
+
+/// The egglog constructors of the fixed-arity nodes, by their names in the
+/// language.
+const CONSTRUCTORS: &[(&str, &str)] = &[
+    ("var", "Var"),
+    ("const", "Const"),
+    ("unop", "UnOp"),
+    ("binop", "BinOp"),
+    ("reg", "Reg"),
+    ("extract", "Extract"),
+    ("cat", "Cat"),
+    ("apply", "Apply"),
+    ("hole", "Hole"),
+    ("unop-ast", "UnOpAst"),
+    ("binop-ast", "BinOpAst"),
+    ("apply-ast", "ApplyAst"),
+    ("concat", "Concat"),
+    ("canonicalize", "Canonicalize"),
+    ("instr", "Instr"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EgglogError {
+    Parse(SexpError),
+    Malformed {
+        term: String,
+    },
+    /// A command other than `union`, `let`, and declarations.
+    UnsupportedCommand {
+        command: String,
+    },
+    /// `(Class n)` handles which are never given a node whose children are
+    /// all known.
+    Unresolved {
+        classes: Vec<i64>,
+    },
+}
+impl Display for EgglogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EgglogError::Parse(e) => write!(f, "{}", e),
+            EgglogError::Malformed { term } => write!(f, "malformed term {}", term),
+            EgglogError::UnsupportedCommand { command } => {
+                write!(f, "unsupported command {}", command)
+            }
+            EgglogError::Unresolved { classes } => {
+                write!(f, "unresolved classes {:?}", classes)
+            }
+        }
+    }
+}
+impl std::error::Error for EgglogError {}
+
+/// Writes one node, with its children written by `child`.
+fn node_to_egglog(node: &Language, mut child: impl FnMut(Id) -> String) -> String {
+    let cons = |children: &[Id], child: &mut dyn FnMut(Id) -> String| {
+        children.iter().rev().fold("(Nil)".to_string(), |tail, c| {
+            format!("(Cons {} {})", child(*c), tail)
+        })
+    };
+    match node {
+        Language::Op(op) => format!("(Op \"{}\")", op),
+        Language::Num(n) => format!("(Num {})", n),
+        Language::String(s) => format!("(Str \"{}\")", s),
+        Language::List(children) => format!("(List {})", cons(children, &mut child)),
+        Language::CanonicalArgs(children) => {
+            format!("(CanonicalArgs {})", cons(children, &mut child))
+        }
+        _ => {
+            let name = node.to_string();
+            let constructor = CONSTRUCTORS
+                .iter()
+                .find(|(op, _)| *op == name)
+                .map(|(_, c)| *c)
+                .unwrap();
+            let mut out = format!("({}", constructor);
+            for c in node.children() {
+                out.push(' ');
+                out.push_str(&child(*c));
+            }
+            out.push(')');
+            out
+        }
+    }
+}
+
+/// Writes a pattern as an egglog query or action.
+pub fn pattern_to_egglog(ast: &PatternAst<Language>) -> String {
+    fn go(ast: &PatternAst<Language>, id: Id) -> String {
+        match &ast[id] {
+            ENodeOrVar::Var(v) => v.to_string().trim_start_matches('?').to_string(),
+            ENodeOrVar::ENode(node) => node_to_egglog(node, |c| go(ast, c)),
+        }
+    }
+    go(ast, Id::from(ast.as_ref().len() - 1))
+}
+
+/// Writes each rewrite as an egglog `rewrite`. Rewrites whose searcher or
+/// applier isn't a pattern (e.g. `canonicalize`) can't be expressed, and
+/// their names are returned instead. Conditions written in Rust have no
+/// egglog counterpart either and are dropped, so conditional rewrites fire
+/// more often in egglog than here.
+pub fn rewrites_to_egglog(
+    rewrites: &[Rewrite<Language, LanguageAnalysis>],
+) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut skipped = vec![];
+    for rewrite in rewrites {
+        match (
+            rewrite.searcher.get_pattern_ast(),
+            rewrite.applier.get_pattern_ast(),
+        ) {
+            (Some(lhs), Some(rhs)) => out.push_str(&format!(
+                ";; {}\n(rewrite {} {})\n",
+                rewrite.name,
+                pattern_to_egglog(lhs),
+                pattern_to_egglog(rhs)
+            )),
+            _ => skipped.push(rewrite.name.to_string()),
+        }
+    }
+    (out, skipped)
+}
+
+/// Writes every e-node of the e-graph as a `union` with its e-class handle.
+pub fn egraph_to_egglog(egraph: &EGraph<Language, LanguageAnalysis>) -> String {
+    let mut out = String::new();
+    for class in egraph.classes() {
+        for node in &class.nodes {
+            let node = node_to_egglog(node, |c| format!("(Class {})", egraph.find(c)));
+            out.push_str(&format!("(union (Class {}) {})\n", class.id, node));
+        }
+    }
+    out
+}
+
+/// Writes a complete egglog program: the schema, the e-graph, and the
+/// rewrites (see [`rewrites_to_egglog`] for which are skipped).
+pub fn to_egglog(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    rewrites: &[Rewrite<Language, LanguageAnalysis>],
+) -> (String, Vec<String>) {
+    let (rules, skipped) = rewrites_to_egglog(rewrites);
+    (
+        format!(
+            "{}\n\n{}\n{}",
+            EGGLOG_SCHEMA,
+            egraph_to_egglog(egraph),
+            rules
+        ),
+        skipped,
+    )
+}
+
+/// Where the classes and `let` bindings of a term are looked up.
+struct Context<'a> {
+    classes: &'a HashMap<i64, Id>,
+    lets: &'a HashMap<String, Sexp>,
+}
+
+/// Adds a term to `out` through `add`, or returns `Ok(None)` if it refers
+/// to a class handle which hasn't been resolved yet.
+fn add_term(
+    term: &Sexp,
+    context: &Context,
+    add: &mut dyn FnMut(Language) -> Id,
+) -> Result<Option<Id>, EgglogError> {
+    let malformed = || EgglogError::Malformed {
+        term: term.to_string(),
+    };
+    let list = match term {
+        Sexp::Atom(name) => {
+            let bound = context.lets.get(name).ok_or_else(malformed)?;
+            return add_term(bound, context, add);
+        }
+        Sexp::List(list) => list,
+    };
+    let (head, args) = match list.split_first() {
+        Some((Sexp::Atom(head), args)) => (head.as_str(), args),
+        _ => return Err(malformed()),
+    };
+    let string = |arg: &Sexp| {
+        arg.as_atom()
+            .and_then(|a| a.strip_prefix('"'))
+            .and_then(|a| a.strip_suffix('"'))
+            .map(str::to_string)
+            .ok_or_else(malformed)
+    };
+    let node = match (head, args) {
+        ("Class", [n]) => {
+            let n = n
+                .as_atom()
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(malformed)?;
+            return Ok(context.classes.get(&n).copied());
+        }
+        ("Op", [op]) => Language::Op(string(op)?.parse().map_err(|_| malformed())?),
+        ("Num", [n]) => Language::Num(
+            n.as_atom()
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(malformed)?,
+        ),
+        ("Str", [s]) => Language::String(string(s)?),
+        ("List" | "CanonicalArgs", [list]) => {
+            let mut children = vec![];
+            let mut tail = list;
+            loop {
+                match tail.as_list() {
+                    Some([Sexp::Atom(nil)]) if nil == "Nil" => break,
+                    Some([Sexp::Atom(cons), item, rest]) if cons == "Cons" => {
+                        match add_term(item, context, add)? {
+                            Some(id) => children.push(id),
+                            None => return Ok(None),
+                        }
+                        tail = rest;
+                    }
+                    _ => return Err(malformed()),
+                }
+            }
+            if head == "List" {
+                Language::List(children.into())
+            } else {
+                Language::CanonicalArgs(children.into())
+            }
+        }
+        _ => {
+            let op = CONSTRUCTORS
+                .iter()
+                .find(|(_, c)| *c == head)
+                .map(|(op, _)| *op)
+                .ok_or_else(malformed)?;
+            let mut children = vec![];
+            for arg in args {
+                match add_term(arg, context, add)? {
+                    Some(id) => children.push(id),
+                    None => return Ok(None),
+                }
+            }
+            let ids = |n: usize| -> Result<Vec<Id>, EgglogError> {
+                if children.len() == n {
+                    Ok(children.clone())
+                } else {
+                    Err(malformed())
+                }
+            };
+            match op {
+                "var" => Language::Var(ids(2)?.try_into().unwrap()),
+                "const" => Language::Const(ids(2)?.try_into().unwrap()),
+                "unop" => Language::UnOp(ids(3)?.try_into().unwrap()),
+                "binop" => Language::BinOp(ids(4)?.try_into().unwrap()),
+                "reg" => Language::Reg(ids(2)?.try_into().unwrap()),
+                "extract" => Language::Extract(ids(3)?.try_into().unwrap()),
+                "cat" => Language::Cat(ids(2)?.try_into().unwrap()),
+                "apply" => Language::Apply(ids(2)?.try_into().unwrap()),
+                "hole" => Language::Hole(ids(1)?.try_into().unwrap()),
+                "unop-ast" => Language::UnOpAst(ids(3)?.try_into().unwrap()),
+                "binop-ast" => Language::BinOpAst(ids(4)?.try_into().unwrap()),
+                "apply-ast" => Language::ApplyAst(ids(2)?.try_into().unwrap()),
+                "concat" => Language::Concat(ids(2)?.try_into().unwrap()),
+                "canonicalize" => Language::Canonicalize(ids(1)?.try_into().unwrap()),
+                "instr" => Language::Instr(ids(2)?.try_into().unwrap()),
+                _ => unreachable!(),
+            }
+        }
+    };
+    Ok(Some(add(node)))
+}
+
+/// The `n` of a `(Class n)` handle.
+fn class_handle(term: &Sexp) -> Option<i64> {
+    match term.as_list() {
+        Some([Sexp::Atom(class), Sexp::Atom(n)]) if class == "Class" => n.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Collects every class handle in a term.
+fn referenced_classes(term: &Sexp, out: &mut Vec<i64>) {
+    match class_handle(term) {
+        Some(n) => out.push(n),
+        None => {
+            for item in term.as_list().unwrap_or(&[]) {
+                referenced_classes(item, out);
+            }
+        }
+    }
+}
+
+/// Reads a ground egglog term, e.g. the output of `extract`.
+pub fn term_from_egglog(input: &str) -> Result<RecExpr<Language>, EgglogError> {
+    let term = parse(input).map_err(EgglogError::Parse)?;
+    let mut expr = RecExpr::default();
+    let context = Context {
+        classes: &HashMap::default(),
+        lets: &HashMap::default(),
+    };
+    match add_term(&term, &context, &mut |node| expr.add(node))? {
+        Some(_) => Ok(expr),
+        None => Err(EgglogError::Malformed {
+            term: term.to_string(),
+        }),
+    }
+}
+
+/// Builds an e-graph from the `union` and `let` commands of an egglog
+/// program, such as one written by [`egraph_to_egglog`]. Declarations,
+/// rules, and schedules are skipped.
+pub fn egraph_from_egglog(input: &str) -> Result<EGraph<Language, LanguageAnalysis>, EgglogError> {
+    let mut egraph = EGraph::<Language, LanguageAnalysis>::default();
+    let mut lets = HashMap::new();
+    let mut unions = vec![];
+    for command in parse_all(input).map_err(EgglogError::Parse)? {
+        let list = command.as_list().unwrap_or(&[]);
+        match (
+            list.first().and_then(Sexp::as_atom),
+            list.get(1..).unwrap_or(&[]),
+        ) {
+            (Some("union"), [a, b]) => unions.push((a.clone(), b.clone())),
+            (Some("let"), [Sexp::Atom(name), term]) => {
+                lets.insert(name.clone(), term.clone());
+                unions.push((term.clone(), term.clone()));
+            }
+            (
+                Some(
+                    "datatype" | "sort" | "function" | "constructor" | "relation" | "rewrite"
+                    | "birewrite" | "rule" | "ruleset" | "run" | "run-schedule" | "check"
+                    | "extract" | "set-option",
+                ),
+                _,
+            ) => (),
+            _ => {
+                return Err(EgglogError::UnsupportedCommand {
+                    command: command.to_string(),
+                })
+            }
+        }
+    }
+
+    // Class handles are resolved once one of their nodes can be added, so
+    // keep going until nothing changes.
+    let mut classes = HashMap::new();
+    loop {
+        let mut pending = vec![];
+        let progress_before = classes.len();
+        let mut unioned = false;
+        for (a, b) in unions {
+            let context = Context {
+                classes: &classes,
+                lets: &lets,
+            };
+            let mut add = |node| egraph.add(node);
+            let a_id = add_term(&a, &context, &mut add)?;
+            let b_id = add_term(&b, &context, &mut add)?;
+            match (a_id, b_id) {
+                (Some(a_id), Some(b_id)) => {
+                    egraph.union(a_id, b_id);
+                    unioned = true;
+                }
+                (None, Some(id)) if class_handle(&a).is_some() => {
+                    classes.insert(class_handle(&a).unwrap(), id);
+                }
+                (Some(id), None) if class_handle(&b).is_some() => {
+                    classes.insert(class_handle(&b).unwrap(), id);
+                }
+                _ => pending.push((a, b)),
+            }
+        }
+        unions = pending;
+        if unions.is_empty() {
+            break;
+        }
+        if classes.len() == progress_before && !unioned {
+            let mut unresolved = vec![];
+            for (a, b) in &unions {
+                referenced_classes(a, &mut unresolved);
+                referenced_classes(b, &mut unresolved);
+            }
+            unresolved.retain(|n| !classes.contains_key(n));
+            unresolved.sort_unstable();
+            unresolved.dedup();
+            return Err(EgglogError::Unresolved {
+                classes: unresolved,
+            });
+        }
+    }
+    egraph.rebuild();
+    Ok(egraph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        example_programs::all_programs,
+        rewrites::{asr_nonnegative_to_lsr, canonicalize, introduce_hole_var},
+    };
+    use egg::Runner;
+
+    #[test]
+    fn egraph_round_trip() {
+        let program = &all_programs()["bithack_ceil_avg"];
+        let runner = Runner::<Language, LanguageAnalysis, ()>::default()
+            .with_expr(program)
+            .with_iter_limit(2)
+            .run(&[introduce_hole_var()]);
+        let mut imported = egraph_from_egglog(&egraph_to_egglog(&runner.egraph)).unwrap();
+        assert_eq!(
+            imported.number_of_classes(),
+            runner.egraph.number_of_classes()
+        );
+        assert_eq!(imported.total_size(), runner.egraph.total_size());
+        imported.add_expr(program);
+        assert_eq!(
+            imported.number_of_classes(),
+            runner.egraph.number_of_classes()
+        );
+    }
+
+    #[test]
+    fn rewrites() {
+        let (rules, skipped) = rewrites_to_egglog(&[asr_nonnegative_to_lsr(), canonicalize()]);
+        assert_eq!(
+            rules,
+            ";; asr-nonnegative-to-lsr\n\
+             (rewrite (BinOp (Op \"asr\") bw a b) (BinOp (Op \"lsr\") bw a b))\n"
+        );
+        assert_eq!(skipped, vec!["canonicalize".to_string()]);
+    }
+
+    #[test]
+    fn terms() {
+        let expr = term_from_egglog(
+            "(Apply (Instr (Hole (Num 8)) (CanonicalArgs (Cons (Num 0) (Nil))))
+                    (List (Cons (Var (Str \"x\") (Num 8)) (Nil))))",
+        )
+        .unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "(apply (instr (hole 8) (canonical-args 0)) (list (var x 8)))"
+        );
+        assert!(matches!(
+            egraph_from_egglog("(union (Class 0) (Hole (Class 1)))"),
+            Err(EgglogError::Unresolved { classes }) if classes == vec![0, 1]
+        ));
+    }
+}
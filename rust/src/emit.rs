@@ -0,0 +1,830 @@
+//! Emitting programs and instructions as hardware descriptions, and
+//! instructions as C intrinsics for the software written against them.
+//! Programs compiled to an ISA (see [`crate::isa::compile_program`]) can
+//! also be written as assembly-like listings.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+
+use egg::{Id, RecExpr};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arch::{Architecture, Netlist},
+    ast::Expr,
+    bitvec::BitVec,
+    equiv::{shared_vars, test_vectors},
+    error::LakeroadError,
+    eval::eval_expr,
+    frontends::json::{JsonProgram, JsonProgramSet},
+    interval::mask,
+    language::{instr_as_expr, Language, Op},
+    solver::to_racket,
+};
+
+/// The formats [`export_instructions`] can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Verilog,
+    Json,
+    Rosette,
+    /// A C header with a `static inline` reference implementation per
+    /// instruction.
+    C,
+}
+
+impl FromStr for ExportFormat {
+    type Err = LakeroadError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "verilog" => Ok(ExportFormat::Verilog),
+            "json" => Ok(ExportFormat::Json),
+            "rosette" => Ok(ExportFormat::Rosette),
+            "c" => Ok(ExportFormat::C),
+            _ => Err(LakeroadError::Parse(format!(
+                "unknown export format {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Verilog => "v",
+            ExportFormat::Json => "json",
+            ExportFormat::Rosette => "rkt",
+            ExportFormat::C => "h",
+        }
+    }
+
+    /// The extension of what [`export_testbench`] writes, which for C is a
+    /// program rather than a header.
+    pub fn testbench_extension(&self) -> &'static str {
+        match self {
+            ExportFormat::C => "c",
+            _ => self.extension(),
+        }
+    }
+}
+
+/// The `instr`s as expressions, named `instr0`, `instr1`, and so on.
+fn named_exprs(
+    instrs: &[RecExpr<Language>],
+) -> Result<Vec<(String, RecExpr<Language>)>, LakeroadError> {
+    instrs
+        .iter()
+        .enumerate()
+        .map(|(i, instr)| {
+            instr_as_expr(instr)
+                .map(|expr| (format!("instr{}", i), expr))
+                .ok_or_else(|| LakeroadError::Malformed(format!("not an instr: {}", instr)))
+        })
+        .collect()
+}
+
+/// Writes extracted `instr`s, named `instr0`, `instr1`, and so on, as one
+/// Verilog module, JSON program, Rosette function, or C intrinsic (see
+/// [`to_c`]) each.
+pub fn export_instructions(
+    format: ExportFormat,
+    instrs: &[RecExpr<Language>],
+) -> Result<String, LakeroadError> {
+    let exprs = named_exprs(instrs)?;
+    let unsupported = |name: &str| LakeroadError::Unsupported(format!("{} as {:?}", name, format));
+    Ok(match format {
+        ExportFormat::Verilog => exprs
+            .iter()
+            .map(|(name, expr)| to_verilog(name, expr).ok_or_else(|| unsupported(name)))
+            .collect::<Result<Vec<_>, _>>()?
+            .join("\n"),
+        ExportFormat::Json => {
+            let set = JsonProgramSet {
+                programs: exprs
+                    .iter()
+                    .map(|(name, expr)| {
+                        JsonProgram::from_expr(name.clone(), 1.0, expr)
+                            .map_err(|_| unsupported(name))
+                    })
+                    .collect::<Result<_, _>>()?,
+            };
+            serde_json::to_string_pretty(&set).map_err(|_| unsupported("instructions"))? + "\n"
+        }
+        ExportFormat::Rosette => exprs
+            .iter()
+            .map(|(name, expr)| {
+                let (body, map) = to_racket(expr, (expr.as_ref().len() - 1).into())?;
+                Ok(format!(
+                    "(define ({} {}) {})\n",
+                    name,
+                    map.names().collect::<Vec<_>>().join(" "),
+                    body
+                ))
+            })
+            .collect::<Result<String, LakeroadError>>()?,
+        ExportFormat::C => {
+            let intrinsics = exprs
+                .iter()
+                .map(|(name, expr)| to_c(name, expr).ok_or_else(|| unsupported(name)))
+                .collect::<Result<Vec<_>, _>>()?;
+            format!("{}\n{}{}", C_PRELUDE, intrinsics.join("\n"), C_EPILOGUE)
+        }
+    })
+}
+
+/// An instruction's output on one input, according to the native
+/// evaluator.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestCase {
+    /// A value for each argument, by name, e.g. `a0`.
+    pub inputs: BTreeMap<String, BitVec>,
+    pub output: BitVec,
+}
+
+/// `count` [`TestCase`]s for each of the `instrs`, named as by
+/// [`export_instructions`], on the [`test_vectors`] drawn from `seed`.
+pub fn instruction_test_cases(
+    instrs: &[RecExpr<Language>],
+    count: usize,
+    seed: u64,
+) -> Result<Vec<(String, Vec<TestCase>)>, LakeroadError> {
+    named_exprs(instrs)?
+        .into_iter()
+        .map(|(name, expr)| {
+            let expr = Expr::try_from(&expr)?;
+            let cases = test_vectors(&shared_vars(&expr, &expr)?, count, seed)
+                .into_iter()
+                .map(|inputs| {
+                    Ok(TestCase {
+                        output: eval_expr(&expr, &inputs)?,
+                        inputs: inputs.into_iter().collect(),
+                    })
+                })
+                .collect::<Result<_, LakeroadError>>()?;
+            Ok((name, cases))
+        })
+        .collect()
+}
+
+/// Writes a check of what [`export_instructions`] writes in `format`
+/// against `count` [`instruction_test_cases`] drawn from `seed`: for
+/// Verilog, a testbench module, `isa_tb`, which instantiates every module
+/// and prints `PASS` or each failure; for Rosette, `rackunit` checks to be
+/// loaded after the functions; for C, a program which includes the header
+/// as `isa.h`, prints the same, and exits nonzero on a failure; and for
+/// JSON, the test cases themselves, with values in hexadecimal.
+pub fn export_testbench(
+    format: ExportFormat,
+    instrs: &[RecExpr<Language>],
+    count: usize,
+    seed: u64,
+) -> Result<String, LakeroadError> {
+    let cases = instruction_test_cases(instrs, count, seed)?;
+    Ok(match format {
+        ExportFormat::Verilog => verilog_testbench(&cases),
+        ExportFormat::C => c_testbench(&cases),
+        ExportFormat::Rosette => {
+            let mut out = "(require rackunit)\n".to_string();
+            for ((name, cases), (_, expr)) in cases.iter().zip(named_exprs(instrs)?) {
+                // The arguments in the order the functions take them.
+                let (_, symbols) = to_racket(&expr, (expr.as_ref().len() - 1).into())?;
+                for case in cases {
+                    let args = symbols
+                        .names()
+                        .map(|arg| format!(" {}", case.inputs[arg]))
+                        .collect::<String>();
+                    out.push_str(&format!(
+                        "(check-equal? ({}{}) {})\n",
+                        name, args, case.output
+                    ));
+                }
+            }
+            out
+        }
+        ExportFormat::Json => {
+            #[derive(Serialize)]
+            struct JsonCase {
+                inputs: BTreeMap<String, String>,
+                output: String,
+            }
+            #[derive(Serialize)]
+            struct JsonInstr {
+                name: String,
+                cases: Vec<JsonCase>,
+            }
+            let hex = |value: &BitVec| format!("0x{}", value.to_hex());
+            let instrs = cases
+                .iter()
+                .map(|(name, cases)| JsonInstr {
+                    name: name.clone(),
+                    cases: cases
+                        .iter()
+                        .map(|case| JsonCase {
+                            inputs: case
+                                .inputs
+                                .iter()
+                                .map(|(arg, value)| (arg.clone(), hex(value)))
+                                .collect(),
+                            output: hex(&case.output),
+                        })
+                        .collect(),
+                })
+                .collect::<Vec<_>>();
+            serde_json::to_string_pretty(&instrs).expect("test cases always serialize") + "\n"
+        }
+    })
+}
+
+fn verilog_literal(value: &BitVec) -> String {
+    format!("{}'h{}", value.width(), value.to_hex())
+}
+
+fn verilog_testbench(cases: &[(String, Vec<TestCase>)]) -> String {
+    let mut declarations = String::new();
+    let mut checks = String::new();
+    for (name, cases) in cases {
+        let first = match cases.first() {
+            Some(first) => first,
+            None => continue,
+        };
+        let mut ports = vec![];
+        for (arg, value) in &first.inputs {
+            declarations.push_str(&format!(
+                "  reg [{}:0] {}_{};\n",
+                value.width() - 1,
+                name,
+                arg
+            ));
+            ports.push(format!(".{}({}_{})", arg, name, arg));
+        }
+        declarations.push_str(&format!(
+            "  wire [{}:0] {}_out;\n",
+            first.output.width() - 1,
+            name
+        ));
+        ports.push(format!(".out({}_out)", name));
+        declarations.push_str(&format!("  {} {}_dut({});\n", name, name, ports.join(", ")));
+
+        let formats = first
+            .inputs
+            .keys()
+            .map(|arg| format!("{}=%h", arg))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let regs = first
+            .inputs
+            .keys()
+            .map(|arg| format!("{}_{}, ", name, arg))
+            .collect::<String>();
+        for case in cases {
+            for (arg, value) in &case.inputs {
+                checks.push_str(&format!(
+                    "    {}_{} = {};\n",
+                    name,
+                    arg,
+                    verilog_literal(value)
+                ));
+            }
+            let expected = verilog_literal(&case.output);
+            checks.push_str(&format!(
+                "    #1;\n    if ({name}_out !== {expected}) begin\n      \
+                 $display(\"FAIL {name}({formats}) = %h, expected %h\", {regs}{name}_out, \
+                 {expected});\n      failures = failures + 1;\n    end\n",
+                name = name,
+                expected = expected,
+                formats = formats,
+                regs = regs,
+            ));
+        }
+    }
+    format!(
+        "module isa_tb;\n{}  integer failures = 0;\n  initial begin\n{}    \
+         if (failures == 0) $display(\"PASS\");\n    $finish;\n  end\nendmodule\n",
+        declarations, checks
+    )
+}
+
+/// Writes `expr`, mapped onto `arch`'s primitives by [`Netlist::map`], as a
+/// Verilog module which instantiates them, so that vendor tools keep the
+/// mapping instead of re-mapping behavioral Verilog like [`to_verilog`]'s.
+pub fn to_structural_verilog(
+    module: &str,
+    expr: &RecExpr<Language>,
+    arch: &dyn Architecture,
+) -> Result<String, LakeroadError> {
+    Ok(Netlist::map(arch, expr)?.to_verilog(module))
+}
+
+/// The start of a C header, with the helpers the intrinsics share.
+const C_PRELUDE: &str = "\
+#ifndef ISA_H
+#define ISA_H
+
+#include <stdint.h>
+
+/* The low `w` bits, for 1 <= w <= 64. */
+static inline uint64_t isa_mask(unsigned w) {
+  return w >= 64 ? UINT64_MAX : (UINT64_C(1) << w) - 1;
+}
+
+/* `a`, of `w` bits, shifted right by `b`, with all bits shifted out when
+   `b` is `w` or more. */
+static inline uint64_t isa_lsr(uint64_t a, uint64_t b, unsigned w) {
+  return b >= w ? 0 : a >> b;
+}
+
+static inline uint64_t isa_asr(uint64_t a, uint64_t b, unsigned w) {
+  uint64_t fill = (a >> (w - 1)) & 1 ? isa_mask(w) : 0;
+  return b >= w ? fill : ((a >> b) | (fill & ~(isa_mask(w) >> b)));
+}
+";
+
+const C_EPILOGUE: &str = "\n#endif /* ISA_H */\n";
+
+/// The unsigned C type which holds `width` bits, or `None` past 64.
+fn c_type(width: usize) -> Option<&'static str> {
+    match width {
+        1..=8 => Some("uint8_t"),
+        9..=16 => Some("uint16_t"),
+        17..=32 => Some("uint32_t"),
+        33..=64 => Some("uint64_t"),
+        _ => None,
+    }
+}
+
+/// Writes an expression of [`to_verilog`]'s shape, at most 64 bits wide
+/// throughout, as a `static inline` C function, `isa_<name>`, with one
+/// parameter per variable in name order. Returns `None` for anything else.
+///
+/// Every node, parameters included, is computed in a `uint64_t` and masked
+/// to its width, so that C's integer promotions can't change the result.
+pub fn to_c(name: &str, expr: &RecExpr<Language>) -> Option<String> {
+    let nodes = expr.as_ref();
+    let num = |id: Id| match nodes[usize::from(id)] {
+        Language::Num(n) if n > 0 && n <= 64 => Some(n as usize),
+        _ => None,
+    };
+    let op = |id: Id| match &nodes[usize::from(id)] {
+        Language::Op(op) => Some(op.clone()),
+        _ => None,
+    };
+    let local = |id: Id| format!("t{}", usize::from(id));
+
+    let mut params = BTreeMap::new();
+    let mut body = String::new();
+    let mut widths = vec![0; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        let (width, rhs) = match node {
+            Language::Num(_) | Language::String(_) | Language::Op(_) => continue,
+            Language::Var([name_id, width_id]) => {
+                let var = match &nodes[usize::from(*name_id)] {
+                    Language::String(var) => var.clone(),
+                    _ => return None,
+                };
+                let width = num(*width_id)?;
+                if *params.entry(var.clone()).or_insert(width) != width {
+                    return None;
+                }
+                (width, var)
+            }
+            Language::Const([value_id, width_id]) => {
+                let width = num(*width_id)?;
+                let value = match nodes[usize::from(*value_id)] {
+                    Language::Num(v) => v as u128 & mask(width),
+                    _ => return None,
+                };
+                (width, format!("UINT64_C({:#x})", value))
+            }
+            Language::UnOp([op_id, width_id, a]) => {
+                let width = num(*width_id)?;
+                let a = local(*a);
+                let rhs = match op(*op_id)? {
+                    Op::Not => format!("~{}", a),
+                    Op::Neg => format!("-{}", a),
+                    _ => return None,
+                };
+                (width, rhs)
+            }
+            Language::BinOp([op_id, width_id, a, b]) => {
+                let width = num(*width_id)?;
+                let (a, b) = (local(*a), local(*b));
+                let rhs = match op(*op_id)? {
+                    Op::And => format!("{} & {}", a, b),
+                    Op::Or => format!("{} | {}", a, b),
+                    Op::Xor => format!("{} ^ {}", a, b),
+                    Op::Add => format!("{} + {}", a, b),
+                    Op::Sub => format!("{} - {}", a, b),
+                    Op::Mul => format!("{} * {}", a, b),
+                    Op::Lsr => format!("isa_lsr({}, {}, {})", a, b, width),
+                    Op::Asr => format!("isa_asr({}, {}, {})", a, b, width),
+                    Op::Eq => format!("{} == {}", a, b),
+                    _ => return None,
+                };
+                (width, rhs)
+            }
+            Language::Extract([hi_id, lo_id, a]) => {
+                let (hi, lo) = match (&nodes[usize::from(*hi_id)], &nodes[usize::from(*lo_id)]) {
+                    (Language::Num(hi), Language::Num(lo)) if 0 <= *lo && lo <= hi && *hi < 64 => {
+                        (*hi as usize, *lo as usize)
+                    }
+                    _ => return None,
+                };
+                (hi - lo + 1, format!("{} >> {}", local(*a), lo))
+            }
+            Language::Cat([hi, lo]) => {
+                let width = widths[usize::from(*hi)] + widths[usize::from(*lo)];
+                if width > 64 {
+                    return None;
+                }
+                (
+                    width,
+                    format!(
+                        "{} << {} | {}",
+                        local(*hi),
+                        widths[usize::from(*lo)],
+                        local(*lo)
+                    ),
+                )
+            }
+            _ => return None,
+        };
+        widths[i] = width;
+        body.push_str(&format!(
+            "  uint64_t {} = ({}) & isa_mask({});\n",
+            local(Id::from(i)),
+            rhs,
+            width
+        ));
+    }
+
+    let root = Id::from(nodes.len() - 1);
+    let out = c_type(widths[usize::from(root)])?;
+    let params = params
+        .iter()
+        .map(|(param, width)| Some(format!("{} {}", c_type(*width)?, param)))
+        .collect::<Option<Vec<_>>>()?;
+    Some(format!(
+        "static inline {} isa_{}({}) {{\n{}  return ({}){};\n}}\n",
+        out,
+        name,
+        match params.is_empty() {
+            true => "void".to_string(),
+            false => params.join(", "),
+        },
+        body,
+        out,
+        local(root)
+    ))
+}
+
+fn c_testbench(cases: &[(String, Vec<TestCase>)]) -> String {
+    let literal = |value: &BitVec| format!("UINT64_C(0x{})", value.to_hex());
+    let mut checks = String::new();
+    for (name, cases) in cases {
+        for case in cases {
+            let args = case.inputs.values().map(literal).collect::<Vec<_>>();
+            checks.push_str(&format!(
+                "  if (isa_{name}({args}) != {expected}) {{\n    \
+                 printf(\"FAIL {name}({args}), expected {expected}\\n\");\n    \
+                 failures++;\n  }}\n",
+                name = name,
+                args = args.join(", "),
+                expected = literal(&case.output),
+            ));
+        }
+    }
+    format!(
+        "#include <stdio.h>\n\n#include \"isa.h\"\n\nint main(void) {{\n  int failures = 0;\n{}  \
+         if (failures == 0)\n    printf(\"PASS\\n\");\n  return failures != 0;\n}}\n",
+        checks
+    )
+}
+
+/// Writes an expression made of `var`s, `const`s, `unop`s, `binop`s,
+/// `extract`s, and `cat`s as a combinational Verilog module with one input per variable and a single
+/// output, `out`. Returns `None` for anything else, e.g. an `apply`.
+///
+/// Every node gets its own wire, so that `$signed` in arithmetic shifts isn't
+/// undone by the signedness of the surrounding expression.
+pub fn to_verilog(module: &str, expr: &RecExpr<Language>) -> Option<String> {
+    let nodes = expr.as_ref();
+    let num = |id: Id| match nodes[usize::from(id)] {
+        Language::Num(n) if n > 0 => Some(n as usize),
+        _ => None,
+    };
+    let op = |id: Id| match &nodes[usize::from(id)] {
+        Language::Op(op) => Some(op.clone()),
+        _ => None,
+    };
+    let name = |id: Id| match &nodes[usize::from(id)] {
+        Language::Var([name_id, _]) => match &nodes[usize::from(*name_id)] {
+            Language::String(name) => name.clone(),
+            _ => unreachable!(),
+        },
+        _ => format!("t{}", usize::from(id)),
+    };
+
+    let mut inputs: Vec<(String, usize)> = vec![];
+    let mut body = String::new();
+    let mut out_width = None;
+    // Each node's width, for `cat`s.
+    let mut widths = vec![0; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        let id = Id::from(i);
+        let (width, rhs) = match node {
+            Language::Num(_) | Language::String(_) | Language::Op(_) => continue,
+            Language::Var([name_id, width_id]) => {
+                let var = match &nodes[usize::from(*name_id)] {
+                    Language::String(var) => var.clone(),
+                    _ => return None,
+                };
+                let width = num(*width_id)?;
+                match inputs.iter().find(|(input, _)| *input == var) {
+                    Some((_, w)) if *w != width => return None,
+                    Some(_) => (),
+                    None => inputs.push((var, width)),
+                }
+                widths[i] = width;
+                out_width = Some(width);
+                continue;
+            }
+            Language::Const([value_id, width_id]) => {
+                let width = num(*width_id)?;
+                let value = match nodes[usize::from(*value_id)] {
+                    Language::Num(v) => v as u128 & mask(width),
+                    _ => return None,
+                };
+                (width, format!("{}'d{}", width, value))
+            }
+            Language::UnOp([op_id, width_id, a]) => {
+                let width = num(*width_id)?;
+                let a = name(*a);
+                let rhs = match op(*op_id)? {
+                    Op::Not => format!("~{}", a),
+                    Op::Neg => format!("-{}", a),
+                    _ => return None,
+                };
+                (width, rhs)
+            }
+            Language::BinOp([op_id, width_id, a, b]) => {
+                let width = num(*width_id)?;
+                let (a, b) = (name(*a), name(*b));
+                let rhs = match op(*op_id)? {
+                    Op::And => format!("{} & {}", a, b),
+                    Op::Or => format!("{} | {}", a, b),
+                    Op::Xor => format!("{} ^ {}", a, b),
+                    Op::Add => format!("{} + {}", a, b),
+                    Op::Sub => format!("{} - {}", a, b),
+                    Op::Mul => format!("{} * {}", a, b),
+                    Op::Lsr => format!("{} >> {}", a, b),
+                    Op::Asr => format!("$signed({}) >>> {}", a, b),
+                    // The one-bit result is zero-extended on assignment.
+                    Op::Eq => format!("{} == {}", a, b),
+                    _ => return None,
+                };
+                (width, rhs)
+            }
+            Language::Extract([hi_id, lo_id, a]) => {
+                let (hi, lo) = match (&nodes[usize::from(*hi_id)], &nodes[usize::from(*lo_id)]) {
+                    (Language::Num(hi), Language::Num(lo)) if 0 <= *lo && lo <= hi => {
+                        (*hi as usize, *lo as usize)
+                    }
+                    _ => return None,
+                };
+                (hi - lo + 1, format!("{}[{}:{}]", name(*a), hi, lo))
+            }
+            Language::Cat([hi, lo]) => (
+                widths[usize::from(*hi)] + widths[usize::from(*lo)],
+                format!("{{{}, {}}}", name(*hi), name(*lo)),
+            ),
+            _ => return None,
+        };
+        widths[i] = width;
+        body.push_str(&format!(
+            "  wire [{}:0] {};\n  assign {} = {};\n",
+            width - 1,
+            name(id),
+            name(id),
+            rhs
+        ));
+        out_width = Some(width);
+    }
+
+    let out_width = out_width?;
+    let mut ports = inputs
+        .iter()
+        .map(|(input, width)| format!("input [{}:0] {}", width - 1, input))
+        .collect::<Vec<_>>();
+    ports.push(format!("output [{}:0] out", out_width - 1));
+    Some(format!(
+        "module {}({});\n{}  assign out = {};\nendmodule\n",
+        module,
+        ports.join(", "),
+        body,
+        name(Id::from(nodes.len() - 1))
+    ))
+}
+
+/// A program compiled to an ISA, as [`crate::isa::compile_program`]
+/// returns it, as an assembly-like listing: a line per `apply`, naming the
+/// instruction by its index in `instructions` as [`export_instructions`]
+/// does, and writing its result to a new temporary, then a `ret` of the
+/// program's value. Operands are variables by name, constants prefixed with
+/// `#`, and earlier temporaries; `reg`s, `extract`s, and `cat`s outside the
+/// instructions get lines of their own.
+pub fn to_listing(
+    name: &str,
+    program: &RecExpr<Language>,
+    instructions: &[RecExpr<Language>],
+) -> Result<String, LakeroadError> {
+    let nodes = program.as_ref();
+    let instructions = instructions
+        .iter()
+        .map(|instr| instr.to_string())
+        .collect::<Vec<_>>();
+    let malformed = |what: String| LakeroadError::Malformed(format!("listing {}: {}", name, what));
+    let num = |id: Id| match nodes[usize::from(id)] {
+        Language::Num(n) => Ok(n.to_string()),
+        ref node => Err(malformed(format!("{:?} isn't a number", node))),
+    };
+
+    let mut out = format!("; {}\n", name);
+    // The operand each signal node is read as.
+    let mut operands: HashMap<Id, String> = HashMap::new();
+    let mut temporaries = 0;
+    let operand = |operands: &HashMap<Id, String>, id: &Id| {
+        operands
+            .get(id)
+            .cloned()
+            .ok_or_else(|| malformed(format!("node {} isn't a signal", id)))
+    };
+    for (i, node) in nodes.iter().enumerate() {
+        let id = Id::from(i);
+        let (mnemonic, args) = match node {
+            Language::Var(_) | Language::Const(_) => {
+                let leaf = RecExpr::from(nodes[..=i].to_vec());
+                let leaf = match Expr::try_from(&leaf)? {
+                    Expr::Var { name, .. } => name,
+                    Expr::Const { value, .. } => format!("#{}", value),
+                    _ => unreachable!("a var or const converts to one"),
+                };
+                operands.insert(id, leaf);
+                continue;
+            }
+            Language::Apply([instr, args]) => {
+                let instr = RecExpr::from(nodes[..=usize::from(*instr)].to_vec()).to_string();
+                let index = instructions
+                    .iter()
+                    .position(|i| *i == instr)
+                    .ok_or_else(|| malformed(format!("{} isn't in the ISA", instr)))?;
+                let args = match &nodes[usize::from(*args)] {
+                    Language::List(args) => args
+                        .iter()
+                        .map(|arg| operand(&operands, arg))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    node => return Err(malformed(format!("{:?} isn't a list", node))),
+                };
+                (format!("instr{}", index), args)
+            }
+            Language::Reg([_, arg]) => ("reg".to_string(), vec![operand(&operands, arg)?]),
+            Language::Extract([hi, lo, arg]) => (
+                "extract".to_string(),
+                vec![operand(&operands, arg)?, num(*hi)?, num(*lo)?],
+            ),
+            Language::Cat([hi, lo]) => (
+                "cat".to_string(),
+                vec![operand(&operands, hi)?, operand(&operands, lo)?],
+            ),
+            Language::UnOp(_) | Language::BinOp(_) => {
+                let op = RecExpr::from(nodes[..=i].to_vec());
+                return Err(malformed(format!("{} isn't an apply", op)));
+            }
+            // Parts of instructions and argument lists.
+            _ => continue,
+        };
+        let temporary = format!("t{}", temporaries);
+        temporaries += 1;
+        out.push_str(&format!(
+            "{} = {} {}\n",
+            temporary,
+            mnemonic,
+            args.join(", ")
+        ));
+        operands.insert(id, temporary);
+    }
+    let root = operand(&operands, &Id::from(nodes.len() - 1))?;
+    out.push_str(&format!("ret {}\n", root));
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        example_programs::all_programs, extract::hashcons, frontends::verilog::import_verilog,
+    };
+
+    #[test]
+    fn round_trip_through_verilog() {
+        let expr = &all_programs()["bithack_ceil_avg"];
+        let verilog = to_verilog("ceil_avg", expr).unwrap();
+        assert!(verilog.contains("$signed("));
+        let programs = import_verilog(&verilog).unwrap();
+        assert_eq!(
+            programs.get("ceil_avg.out").unwrap().expr.to_string(),
+            expr.to_string()
+        );
+    }
+
+    #[test]
+    fn export_instrs_as_rosette() {
+        let instr = "(instr (binop-ast and 8 (hole 8) (hole 8)) (canonical-args 0 1))"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            export_instructions(ExportFormat::Rosette, &[instr]).unwrap(),
+            "(define (instr0 a0 a1) (bvand a0 a1))\n"
+        );
+        let hole = "(instr (hole 8) (canonical-args 0))".parse().unwrap();
+        assert!(export_instructions(ExportFormat::Verilog, &[hole]).is_ok());
+    }
+
+    #[test]
+    fn export_instrs_as_c() {
+        let instr: RecExpr<Language> =
+            "(instr (binop-ast asr 8 (hole 8) (unop-ast neg 8 (hole 8))) (canonical-args 0 1))"
+                .parse()
+                .unwrap();
+        let header = export_instructions(ExportFormat::C, &[instr.clone()]).unwrap();
+        assert!(header.starts_with("#ifndef ISA_H\n"));
+        assert!(header.contains("static inline uint8_t isa_instr0(uint8_t a0, uint8_t a1) {"));
+        assert!(header.contains("isa_asr(t"));
+        assert!(export_testbench(ExportFormat::C, &[instr], 1, 0)
+            .unwrap()
+            .contains("if (isa_instr0(UINT64_C(0x00), UINT64_C(0x00)) != UINT64_C(0x00))"));
+        // Nothing wider than a `uint64_t`.
+        let wide = "(instr (binop-ast and 65 (hole 65) (hole 65)) (canonical-args 0 1))"
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            export_instructions(ExportFormat::C, &[wide]),
+            Err(LakeroadError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn list_compiled_programs() {
+        let instr = "(instr (binop-ast and 8 (hole 8) (unop-ast not 8 (hole 8))) \
+                     (canonical-args 0 1))";
+        let andn = instr.parse::<RecExpr<Language>>().unwrap();
+        // The shared `andn` is computed once.
+        let program = hashcons(
+            &format!(
+                "(apply {i} (list (apply {i} (list (var x 8) (const 3 8))) \
+                 (apply {i} (list (var x 8) (const 3 8)))))",
+                i = instr
+            )
+            .parse()
+            .unwrap(),
+        );
+        assert_eq!(
+            to_listing("twice", &program, &[andn.clone()]).unwrap(),
+            "; twice\nt0 = instr0 x, #3\nt1 = instr0 t0, t0\nret t1\n"
+        );
+        // Operators left over aren't in the ISA.
+        let unop = "(unop not 8 (var x 8))".parse().unwrap();
+        assert!(matches!(
+            to_listing("not", &unop, &[andn]),
+            Err(LakeroadError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn testbenches_check_the_evaluator() {
+        let instr: RecExpr<Language> =
+            "(instr (binop-ast xor 8 (hole 8) (hole 8)) (canonical-args 0 1))"
+                .parse()
+                .unwrap();
+        let cases = instruction_test_cases(&[instr.clone()], 8, 0).unwrap();
+        assert_eq!(cases[0].1.len(), 8);
+        for case in &cases[0].1 {
+            assert_eq!(case.output, case.inputs["a0"].xor(&case.inputs["a1"]));
+        }
+        // The corner cases come first: zeros, then ones.
+        let verilog = export_testbench(ExportFormat::Verilog, &[instr.clone()], 2, 0).unwrap();
+        assert!(verilog
+            .contains("instr0 instr0_dut(.a0(instr0_a0), .a1(instr0_a1), .out(instr0_out));"));
+        assert!(verilog.contains("if (instr0_out !== 8'h00)"));
+        assert_eq!(
+            export_testbench(ExportFormat::Rosette, &[instr], 2, 0).unwrap(),
+            "(require rackunit)\n\
+             (check-equal? (instr0 (bv #x00 8) (bv #x00 8)) (bv #x00 8))\n\
+             (check-equal? (instr0 (bv #x01 8) (bv #x01 8)) (bv #x00 8))\n"
+        );
+    }
+}
@@ -0,0 +1,869 @@
+//! Encoding the synthesized ISA as a RISC-V custom extension.
+//!
+//! [`assign_encodings`] packs instructions into the custom-0 and custom-1
+//! major opcodes. An instruction of two register operands is R-type, told
+//! apart from the others by its `funct3` and `funct7`; instructions of one
+//! register operand are R-type too, 32 to a `funct7`, told apart by the
+//! value in their `rs2` field. An instruction whose last operand is an
+//! immediate is I-type, and takes a whole `funct3` of its opcode, which R-type
+//! instructions then can't use; I-type instructions fill the opcodes'
+//! `funct3`s from the top, and R-type ones from the bottom.
+//!
+//! An immediate is as wide as the operand it stands for, unless it's known
+//! the programs only need narrower ones: [`infer_immediate_ranges`] finds the
+//! constants the programs pass each instruction as its last operand, and
+//! [`EncodingOptions::with_immediate_bits`] encodes the immediate in as few
+//! bits, sign-extended, as hold them.
+//!
+//! Instructions which don't fit, e.g. because they take three operands,
+//! because a value is wider than a register, or because the space is used
+//! up, are reported rather than encoded.
+//!
+//! [`opcode_fragments`] writes the encodings as the assembler toolchain
+//! describes instructions, so that it can be extended with them: lines for
+//! a riscv-opcodes extension file, and the defines and opcode table entries
+//! binutils keeps in `riscv-opc.h` and `riscv-opc.c`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use egg::RecExpr;
+use serde::Serialize;
+
+use crate::{
+    ast::Expr,
+    error::LakeroadError,
+    language::{instr_as_expr, Language},
+};
+
+/// The custom-0 major opcode.
+pub const CUSTOM_0: u32 = 0b0001011;
+/// The custom-1 major opcode.
+pub const CUSTOM_1: u32 = 0b0101011;
+/// How wide an I-type instruction's immediate is.
+pub const IMMEDIATE_BITS: i64 = 12;
+
+/// The opcode and `funct3` pairs, in the order R-type instructions take
+/// them.
+const SLOTS: [(u32, u32); 16] = [
+    (CUSTOM_0, 0),
+    (CUSTOM_0, 1),
+    (CUSTOM_0, 2),
+    (CUSTOM_0, 3),
+    (CUSTOM_0, 4),
+    (CUSTOM_0, 5),
+    (CUSTOM_0, 6),
+    (CUSTOM_0, 7),
+    (CUSTOM_1, 0),
+    (CUSTOM_1, 1),
+    (CUSTOM_1, 2),
+    (CUSTOM_1, 3),
+    (CUSTOM_1, 4),
+    (CUSTOM_1, 5),
+    (CUSTOM_1, 6),
+    (CUSTOM_1, 7),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Format {
+    /// `rd`, `rs1`, and `rs2`.
+    R,
+    /// `rd`, `rs1`, and a 12-bit immediate.
+    I,
+}
+
+/// Where an instruction reads an operand from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    Rs1,
+    Rs2,
+    Imm,
+}
+
+/// An instruction's encoding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Encoding {
+    /// `instr0`, `instr1`, and so on, as [`crate::emit::export_instructions`]
+    /// names them.
+    pub name: String,
+    pub format: Format,
+    pub opcode: u32,
+    pub funct3: u32,
+    /// Only for R-type instructions.
+    pub funct7: Option<u32>,
+    /// For R-type instructions of one operand, the value of the `rs2` field.
+    pub rs2: Option<u32>,
+    /// The field each operand is read from, in name order.
+    pub operands: Vec<(String, Field)>,
+    /// Only for I-type instructions, how many bits of the immediate field
+    /// hold its value, sign-extended.
+    pub immediate_bits: Option<i64>,
+}
+
+impl Encoding {
+    /// The bits the encoding fixes.
+    pub fn mask(&self) -> u32 {
+        let mut mask = 0x7f | 0x7 << 12;
+        if self.funct7.is_some() {
+            mask |= 0x7f << 25;
+        }
+        if self.rs2.is_some() {
+            mask |= 0x1f << 20;
+        }
+        mask
+    }
+
+    /// The values of the bits in [`Encoding::mask`].
+    pub fn match_bits(&self) -> u32 {
+        self.opcode
+            | self.funct3 << 12
+            | self.rs2.unwrap_or(0) << 20
+            | self.funct7.unwrap_or(0) << 25
+    }
+}
+
+/// An instruction which couldn't be encoded, and why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Unencoded {
+    pub name: String,
+    pub reason: String,
+}
+
+/// The instructions [`assign_encodings`] encoded, and those it couldn't, in
+/// the order they were given.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct EncodingReport {
+    pub encoded: Vec<Encoding>,
+    pub unencoded: Vec<Unencoded>,
+}
+
+/// How to encode instructions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodingOptions {
+    /// The width of a register: 32 or 64.
+    pub xlen: i64,
+    /// The instructions, by index, whose last operand is an immediate.
+    pub immediates: HashSet<usize>,
+    /// The bits the immediates of some of them need, if fewer than their
+    /// operands' widths.
+    pub immediate_bits: HashMap<usize, i64>,
+}
+
+impl Default for EncodingOptions {
+    fn default() -> Self {
+        EncodingOptions {
+            xlen: 32,
+            immediates: HashSet::new(),
+            immediate_bits: HashMap::new(),
+        }
+    }
+}
+
+impl EncodingOptions {
+    pub fn with_xlen(mut self, xlen: i64) -> Self {
+        self.xlen = xlen;
+        self
+    }
+
+    /// Encodes the `index`th instruction's last operand as an immediate.
+    pub fn with_immediate(mut self, index: usize) -> Self {
+        self.immediates.insert(index);
+        self
+    }
+
+    /// Encodes the `index`th instruction's immediate, if it has one, in
+    /// `bits` bits, sign-extended, e.g. the bits of its
+    /// [`ImmediateRange`].
+    pub fn with_immediate_bits(mut self, index: usize, bits: i64) -> Self {
+        self.immediate_bits.insert(index, bits);
+        self
+    }
+}
+
+/// The constants the programs pass an instruction as its last operand, read
+/// as signed, and the fewest bits of a sign-extended immediate which hold
+/// them all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ImmediateRange {
+    pub min: i64,
+    pub max: i64,
+    pub bits: i64,
+}
+
+impl ImmediateRange {
+    fn of(values: &[i64]) -> Option<Self> {
+        let (min, max) = (*values.iter().min()?, *values.iter().max()?);
+        // The bits of each, and its sign.
+        let bits = |v: i64| {
+            let magnitude = if v < 0 { !v } else { v };
+            65 - magnitude.leading_zeros() as i64
+        };
+        Some(ImmediateRange {
+            min,
+            max,
+            bits: bits(min).max(bits(max)),
+        })
+    }
+}
+
+/// For each of `instrs`, the range of the constants `programs` pass it as
+/// its last operand, where a subexpression of a program is the instruction
+/// applied to operands. `None` if no program computes the instruction, or
+/// one computes it of a last operand which isn't a constant, since its
+/// immediate then must be as wide as the operand.
+pub fn infer_immediate_ranges(
+    instrs: &[RecExpr<Language>],
+    programs: &[RecExpr<Language>],
+) -> Result<Vec<Option<ImmediateRange>>, LakeroadError> {
+    let programs = programs
+        .iter()
+        .map(Expr::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut ranges = vec![];
+    for instr in instrs {
+        let expr = instr_as_expr(instr)
+            .ok_or_else(|| LakeroadError::Malformed(format!("not an instr: {}", instr)))?;
+        let expr = Expr::try_from(&expr)?;
+        let mut operands = expr.vars();
+        sort_operands(&mut operands);
+        let immediate = match operands.pop() {
+            Some((name, _)) => name,
+            None => {
+                ranges.push(None);
+                continue;
+            }
+        };
+        let mut values = vec![];
+        let constant = programs
+            .iter()
+            .all(|program| immediates(&expr, &immediate, program, &mut values));
+        ranges.push(if constant {
+            ImmediateRange::of(&values)
+        } else {
+            None
+        });
+    }
+    Ok(ranges)
+}
+
+/// Pushes onto `values` the constant `pattern`'s operand `immediate` is
+/// wherever `pattern` matches a subexpression of `expr`, returning `false`
+/// if somewhere it isn't a constant.
+fn immediates(pattern: &Expr, immediate: &str, expr: &Expr, values: &mut Vec<i64>) -> bool {
+    let mut bindings = HashMap::new();
+    if bind(pattern, expr, &mut bindings) {
+        match bindings.get(immediate) {
+            Some(Expr::Const { value, width }) => values.push(signed(*value, *width)),
+            _ => return false,
+        }
+    }
+    match expr {
+        Expr::Var { .. } | Expr::Const { .. } => true,
+        Expr::UnOp { arg, .. } | Expr::Reg { arg, .. } | Expr::Extract { arg, .. } => {
+            immediates(pattern, immediate, arg, values)
+        }
+        Expr::BinOp { lhs, rhs, .. } | Expr::Cat { hi: lhs, lo: rhs } => {
+            immediates(pattern, immediate, lhs, values)
+                && immediates(pattern, immediate, rhs, values)
+        }
+        Expr::Apply { args, .. } => args
+            .iter()
+            .all(|arg| immediates(pattern, immediate, arg, values)),
+    }
+}
+
+/// Whether `expr` is `pattern` with its variables bound to subexpressions
+/// of the same widths, binding each the same everywhere.
+fn bind(pattern: &Expr, expr: &Expr, bindings: &mut HashMap<String, Expr>) -> bool {
+    match (pattern, expr) {
+        (Expr::Var { name, width }, _) => {
+            expr.width() == *width
+                && *bindings.entry(name.clone()).or_insert_with(|| expr.clone()) == *expr
+        }
+        (
+            Expr::UnOp { op, width, arg },
+            Expr::UnOp {
+                op: op2,
+                width: width2,
+                arg: arg2,
+            },
+        ) => op == op2 && width == width2 && bind(arg, arg2, bindings),
+        (
+            Expr::BinOp {
+                op,
+                width,
+                lhs,
+                rhs,
+            },
+            Expr::BinOp {
+                op: op2,
+                width: width2,
+                lhs: lhs2,
+                rhs: rhs2,
+            },
+        ) => op == op2 && width == width2 && bind(lhs, lhs2, bindings) && bind(rhs, rhs2, bindings),
+        (
+            Expr::Reg { width, arg },
+            Expr::Reg {
+                width: width2,
+                arg: arg2,
+            },
+        ) => width == width2 && bind(arg, arg2, bindings),
+        (
+            Expr::Extract { hi, lo, arg },
+            Expr::Extract {
+                hi: hi2,
+                lo: lo2,
+                arg: arg2,
+            },
+        ) => hi == hi2 && lo == lo2 && bind(arg, arg2, bindings),
+        (Expr::Cat { hi, lo }, Expr::Cat { hi: hi2, lo: lo2 }) => {
+            bind(hi, hi2, bindings) && bind(lo, lo2, bindings)
+        }
+        (
+            Expr::Apply { instr, args },
+            Expr::Apply {
+                instr: instr2,
+                args: args2,
+            },
+        ) => {
+            instr == instr2
+                && args.len() == args2.len()
+                && args
+                    .iter()
+                    .zip(args2)
+                    .all(|(arg, arg2)| bind(arg, arg2, bindings))
+        }
+        _ => pattern == expr,
+    }
+}
+
+/// A `width`-bit constant's value, read as signed.
+fn signed(value: i64, width: i64) -> i64 {
+    match width {
+        1..=63 => (value << (64 - width)) >> (64 - width),
+        _ => value,
+    }
+}
+
+/// Sorts an instruction's operands, named `a0`, `a1`, and so on by
+/// [`instr_as_expr`], by their canonical argument, so that `a10` comes after
+/// `a9` rather than `a1`.
+fn sort_operands(operands: &mut [(String, i64)]) {
+    operands.sort_by_key(|(name, _)| {
+        (
+            name.strip_prefix('a').and_then(|i| i.parse::<usize>().ok()),
+            name.clone(),
+        )
+    });
+}
+
+/// What an instruction needs encoded.
+enum Shape {
+    Unary(String),
+    Binary(String, String),
+    /// A register operand, if any, the immediate, and how many bits it
+    /// takes.
+    Immediate(Option<String>, String, i64),
+}
+
+/// The operands of the `index`th instruction, `expr`, in the shape they're
+/// encoded in, or why they can't be.
+fn shape(expr: &Expr, index: usize, options: &EncodingOptions) -> Result<Shape, String> {
+    let mut operands = expr.vars();
+    sort_operands(&mut operands);
+    if expr.width() > options.xlen {
+        return Err(format!(
+            "its result is {} bits, wider than a {}-bit register",
+            expr.width(),
+            options.xlen
+        ));
+    }
+    if let Some((_, width)) = operands.iter().find(|(_, w)| *w > options.xlen) {
+        return Err(format!(
+            "an operand is {} bits, wider than a register",
+            width
+        ));
+    }
+    let mut registers = operands.clone();
+    if options.immediates.contains(&index) {
+        let (immediate, width) = registers
+            .pop()
+            .ok_or_else(|| "it has no operand to be an immediate".to_string())?;
+        let bits = options
+            .immediate_bits
+            .get(&index)
+            .map_or(width, |bits| width.min(*bits));
+        if bits > IMMEDIATE_BITS {
+            return Err(format!(
+                "its immediate is {} bits, wider than I-type's {}",
+                bits, IMMEDIATE_BITS
+            ));
+        }
+        if registers.len() > 1 {
+            return Err(format!(
+                "it takes {} registers besides its immediate, and I-type has one",
+                registers.len()
+            ));
+        }
+        return Ok(Shape::Immediate(
+            registers.pop().map(|(name, _)| name),
+            immediate,
+            bits,
+        ));
+    }
+    let mut names = registers.into_iter().map(|(name, _)| name);
+    match (names.next(), names.next(), names.next()) {
+        (None, _, _) => Err("it takes no operands".to_string()),
+        (Some(a), None, _) => Ok(Shape::Unary(a)),
+        (Some(a), Some(b), None) => Ok(Shape::Binary(a, b)),
+        _ => Err(format!(
+            "it takes {} operands, and R-type reads two registers",
+            operands.len()
+        )),
+    }
+}
+
+/// Encodes `instrs` in the custom-0 and custom-1 opcodes, as the module
+/// documentation describes.
+pub fn assign_encodings(
+    instrs: &[RecExpr<Language>],
+    options: &EncodingOptions,
+) -> Result<EncodingReport, LakeroadError> {
+    if options.xlen != 32 && options.xlen != 64 {
+        return Err(LakeroadError::Config(format!(
+            "xlen {}, which isn't 32 or 64",
+            options.xlen
+        )));
+    }
+    let mut shapes = vec![];
+    for (i, instr) in instrs.iter().enumerate() {
+        let expr = instr_as_expr(instr)
+            .ok_or_else(|| LakeroadError::Malformed(format!("not an instr: {}", instr)))?;
+        shapes.push(shape(&Expr::try_from(&expr)?, i, options));
+    }
+
+    // Every instruction's encoding is set by one of the passes below.
+    let mut encodings: Vec<Result<Encoding, String>> = vec![Err(String::new()); shapes.len()];
+    let encoding = |i: usize, format, (opcode, funct3): (u32, u32), operands| Encoding {
+        name: format!("instr{}", i),
+        format,
+        opcode,
+        funct3,
+        funct7: None,
+        rs2: None,
+        operands,
+        immediate_bits: None,
+    };
+    // I-type instructions take slots from the top.
+    let mut top = SLOTS.len();
+    for (i, shape) in shapes.iter().enumerate() {
+        encodings[i] = match shape {
+            Err(reason) => Err(reason.clone()),
+            Ok(Shape::Immediate(register, immediate, bits)) if top > 0 => {
+                top -= 1;
+                let mut operands = vec![];
+                if let Some(register) = register {
+                    operands.push((register.clone(), Field::Rs1));
+                }
+                operands.push((immediate.clone(), Field::Imm));
+                Ok(Encoding {
+                    immediate_bits: Some(*bits),
+                    ..encoding(i, Format::I, SLOTS[top], operands)
+                })
+            }
+            Ok(Shape::Immediate(..)) => {
+                Err("every funct3 of custom-0 and custom-1 is taken".to_string())
+            }
+            Ok(_) => continue,
+        };
+    }
+    // R-type instructions take a `funct7` each of the slots left, from the
+    // bottom, and those of one operand share theirs.
+    let mut next = 0;
+    let mut take = || {
+        let (slot, funct7) = (next / 128, next % 128);
+        next += 1;
+        (slot < top).then(|| (SLOTS[slot], funct7 as u32))
+    };
+    let full = || "every funct7 left in custom-0 and custom-1 is taken".to_string();
+    for (i, shape) in shapes.iter().enumerate() {
+        if let Ok(Shape::Binary(a, b)) = shape {
+            encodings[i] = match take() {
+                Some((slot, funct7)) => Ok(Encoding {
+                    funct7: Some(funct7),
+                    ..encoding(
+                        i,
+                        Format::R,
+                        slot,
+                        vec![(a.clone(), Field::Rs1), (b.clone(), Field::Rs2)],
+                    )
+                }),
+                None => Err(full()),
+            };
+        }
+    }
+    // The slot and `funct7` unary instructions are sharing, and the next
+    // `rs2` in it.
+    let mut shared: Option<((u32, u32), u32, u32)> = None;
+    for (i, shape) in shapes.iter().enumerate() {
+        if let Ok(Shape::Unary(a)) = shape {
+            let (slot, funct7, rs2) = match shared {
+                Some((slot, funct7, rs2)) if rs2 < 32 => (slot, funct7, rs2),
+                _ => match take() {
+                    Some((slot, funct7)) => (slot, funct7, 0),
+                    None => {
+                        encodings[i] = Err(full());
+                        continue;
+                    }
+                },
+            };
+            shared = Some((slot, funct7, rs2 + 1));
+            encodings[i] = Ok(Encoding {
+                funct7: Some(funct7),
+                rs2: Some(rs2),
+                ..encoding(i, Format::R, slot, vec![(a.clone(), Field::Rs1)])
+            });
+        }
+    }
+
+    let mut report = EncodingReport::default();
+    for (i, encoding) in encodings.into_iter().enumerate() {
+        match encoding {
+            Ok(encoding) => report.encoded.push(encoding),
+            Err(reason) => report.unencoded.push(Unencoded {
+                name: format!("instr{}", i),
+                reason,
+            }),
+        }
+    }
+    Ok(report)
+}
+
+/// The opcode table fragments [`opcode_fragments`] writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpcodeFragments {
+    /// Lines for a riscv-opcodes extension file, one per instruction: its
+    /// name, its operand fields, and the values of the bits it fixes.
+    pub riscv_opcodes: String,
+    /// `MATCH_` and `MASK_` defines and `DECLARE_INSN`s, for binutils'
+    /// `include/opcode/riscv-opc.h`.
+    pub opc_h: String,
+    /// Entries for binutils' `riscv_opcodes` table, in
+    /// `opcodes/riscv-opc.c`.
+    pub opc_c: String,
+}
+
+impl OpcodeFragments {
+    /// Writes the fragments to `directory`, and returns the files written.
+    pub fn write(&self, directory: &Path) -> Result<Vec<PathBuf>, LakeroadError> {
+        fs::create_dir_all(directory)?;
+        let mut written = vec![];
+        for (name, contents) in [
+            ("rv_isa", &self.riscv_opcodes),
+            ("riscv-opc-isa.h", &self.opc_h),
+            ("riscv-opc-isa.c.inc", &self.opc_c),
+        ] {
+            let path = directory.join(name);
+            fs::write(&path, contents)?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+/// Opcode table fragments for the instructions `report` encoded. Every
+/// instruction writes its result to `rd`, and its operands are written in
+/// name order: binutils' `s` and `t` for `rs1` and `rs2`, and `j` for a
+/// signed 12-bit immediate.
+///
+/// `j` is the only immediate the tables describe, so an error if an
+/// immediate takes fewer bits (see [`Encoding::immediate_bits`]): the
+/// assembler would accept values the instruction can't hold.
+pub fn opcode_fragments(report: &EncodingReport) -> Result<OpcodeFragments, LakeroadError> {
+    let mut fragments = OpcodeFragments {
+        riscv_opcodes: String::new(),
+        opc_h: String::new(),
+        opc_c: String::new(),
+    };
+    let mut declarations = String::new();
+    for encoding in &report.encoded {
+        let (name, upper) = (&encoding.name, encoding.name.to_uppercase());
+        match encoding.immediate_bits {
+            Some(bits) if bits != IMMEDIATE_BITS => {
+                return Err(LakeroadError::Unsupported(format!(
+                    "{}'s immediate is {} bits, and the opcode tables only describe {}-bit ones",
+                    name, bits, IMMEDIATE_BITS
+                )))
+            }
+            _ => (),
+        }
+        let (fields, syntax): (Vec<_>, Vec<_>) = encoding
+            .operands
+            .iter()
+            .map(|(_, field)| match field {
+                Field::Rs1 => ("rs1", "s"),
+                Field::Rs2 => ("rs2", "t"),
+                Field::Imm => ("imm12", "j"),
+            })
+            .unzip();
+        let mut fixed = vec![];
+        if let Some(funct7) = encoding.funct7 {
+            fixed.push(format!("31..25={}", funct7));
+        }
+        if let Some(rs2) = encoding.rs2 {
+            fixed.push(format!("24..20={}", rs2));
+        }
+        fixed.push(format!("14..12={}", encoding.funct3));
+        fixed.push(format!("6..2=0x{:02X}", encoding.opcode >> 2));
+        fixed.push(format!("1..0={}", encoding.opcode & 0x3));
+
+        fragments.riscv_opcodes.push_str(&format!(
+            "{:<12} rd {} {}\n",
+            name,
+            fields.join(" "),
+            fixed.join(" ")
+        ));
+        fragments.opc_h.push_str(&format!(
+            "#define MATCH_{} {:#x}\n#define MASK_{} {:#x}\n",
+            upper,
+            encoding.match_bits(),
+            upper,
+            encoding.mask()
+        ));
+        declarations.push_str(&format!(
+            "DECLARE_INSN({}, MATCH_{}, MASK_{})\n",
+            name, upper, upper
+        ));
+        fragments.opc_c.push_str(&format!(
+            "{{\"{}\", 0, INSN_CLASS_I, \"d,{}\", MATCH_{}, MASK_{}, match_opcode, 0 }},\n",
+            name,
+            syntax.join(","),
+            upper,
+            upper
+        ));
+    }
+    if !declarations.is_empty() {
+        fragments
+            .opc_h
+            .push_str(&format!("#ifdef DECLARE_INSN\n{}#endif\n", declarations));
+    }
+    Ok(fragments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_custom_opcodes() {
+        let instrs = [
+            "(instr (binop-ast and 8 (hole 8) (unop-ast not 8 (hole 8))) (canonical-args 0 1))",
+            "(instr (unop-ast neg 8 (hole 8)) (canonical-args 0))",
+            "(instr (binop-ast add 8 (hole 8) (hole 8)) (canonical-args 0 1))",
+            "(instr (binop-ast add 8 (binop-ast add 8 (hole 8) (hole 8)) (hole 8)) \
+             (canonical-args 0 1 2))",
+            "(instr (binop-ast add 16 (hole 16) (hole 16)) (canonical-args 0 1))",
+            "(instr (unop-ast not 64 (hole 64)) (canonical-args 0))",
+        ]
+        .map(|instr| instr.parse::<RecExpr<Language>>().unwrap());
+        let options = EncodingOptions::default()
+            .with_immediate(2)
+            .with_immediate(4);
+        let report = assign_encodings(&instrs, &options).unwrap();
+
+        assert_eq!(
+            report
+                .encoded
+                .iter()
+                .map(|e| &e.name[..])
+                .collect::<Vec<_>>(),
+            vec!["instr0", "instr1", "instr2"]
+        );
+        let [binary, unary, immediate] = [0, 1, 2].map(|i| &report.encoded[i]);
+        assert_eq!(
+            (binary.mask(), binary.match_bits()),
+            (0xfe00707f, 0x0000000b)
+        );
+        assert_eq!((unary.mask(), unary.match_bits()), (0xfff0707f, 0x0200000b));
+        assert_eq!((immediate.mask(), immediate.match_bits()), (0x707f, 0x702b));
+        assert_eq!(
+            immediate.operands,
+            vec![
+                ("a0".to_string(), Field::Rs1),
+                ("a1".to_string(), Field::Imm)
+            ]
+        );
+
+        // Three operands, a 16-bit immediate, and a 64-bit result on RV32.
+        let reasons = report
+            .unencoded
+            .iter()
+            .map(|u| (u.name.as_str(), u.reason.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            vec![
+                (
+                    "instr3",
+                    "it takes 3 operands, and R-type reads two registers"
+                ),
+                ("instr4", "its immediate is 16 bits, wider than I-type's 12"),
+                (
+                    "instr5",
+                    "its result is 64 bits, wider than a 32-bit register"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn operands_in_canonical_order() {
+        let mut operands = (0..11)
+            .rev()
+            .map(|i| (format!("a{}", i), 8))
+            .collect::<Vec<_>>();
+        sort_operands(&mut operands);
+        assert_eq!(
+            operands
+                .iter()
+                .map(|(name, _)| &name[..])
+                .collect::<Vec<_>>(),
+            vec!["a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7", "a8", "a9", "a10"]
+        );
+    }
+
+    #[test]
+    fn write_opcode_fragments() {
+        let instrs = [
+            "(instr (binop-ast and 8 (hole 8) (unop-ast not 8 (hole 8))) (canonical-args 0 1))",
+            "(instr (unop-ast neg 8 (hole 8)) (canonical-args 0))",
+            "(instr (binop-ast add 16 (hole 16) (hole 16)) (canonical-args 0 1))",
+        ]
+        .map(|instr| instr.parse::<RecExpr<Language>>().unwrap());
+        let options = EncodingOptions::default()
+            .with_immediate(2)
+            .with_immediate_bits(2, IMMEDIATE_BITS);
+        let report = assign_encodings(&instrs, &options).unwrap();
+        let fragments = opcode_fragments(&report).unwrap();
+
+        assert_eq!(
+            fragments.riscv_opcodes,
+            "instr0       rd rs1 rs2 31..25=0 14..12=0 6..2=0x02 1..0=3\n\
+             instr1       rd rs1 31..25=1 24..20=0 14..12=0 6..2=0x02 1..0=3\n\
+             instr2       rd rs1 imm12 14..12=7 6..2=0x0A 1..0=3\n"
+        );
+        assert_eq!(
+            fragments.opc_h,
+            "#define MATCH_INSTR0 0xb\n#define MASK_INSTR0 0xfe00707f\n\
+             #define MATCH_INSTR1 0x200000b\n#define MASK_INSTR1 0xfff0707f\n\
+             #define MATCH_INSTR2 0x702b\n#define MASK_INSTR2 0x707f\n\
+             #ifdef DECLARE_INSN\n\
+             DECLARE_INSN(instr0, MATCH_INSTR0, MASK_INSTR0)\n\
+             DECLARE_INSN(instr1, MATCH_INSTR1, MASK_INSTR1)\n\
+             DECLARE_INSN(instr2, MATCH_INSTR2, MASK_INSTR2)\n\
+             #endif\n"
+        );
+        assert_eq!(
+            fragments.opc_c,
+            "{\"instr0\", 0, INSN_CLASS_I, \"d,s,t\", MATCH_INSTR0, MASK_INSTR0, match_opcode, 0 },\n\
+             {\"instr1\", 0, INSN_CLASS_I, \"d,s\", MATCH_INSTR1, MASK_INSTR1, match_opcode, 0 },\n\
+             {\"instr2\", 0, INSN_CLASS_I, \"d,s,j\", MATCH_INSTR2, MASK_INSTR2, match_opcode, 0 },\n"
+        );
+
+        // Nothing encoded, nothing to declare.
+        assert_eq!(
+            opcode_fragments(&EncodingReport::default()).unwrap().opc_h,
+            String::new()
+        );
+
+        // `j` would take immediates an 8-bit one can't hold.
+        let report = assign_encodings(
+            &instrs,
+            &EncodingOptions::default()
+                .with_immediate(2)
+                .with_immediate_bits(2, 8),
+        )
+        .unwrap();
+        assert!(matches!(
+            opcode_fragments(&report),
+            Err(LakeroadError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn infer_immediate_widths() {
+        let parse = |exprs: &[&str]| {
+            exprs
+                .iter()
+                .map(|expr| expr.parse::<RecExpr<Language>>().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let instrs = parse(&[
+            "(instr (binop-ast add 8 (hole 8) (hole 8)) (canonical-args 0 1))",
+            "(instr (binop-ast lsr 16 (hole 16) (hole 16)) (canonical-args 0 1))",
+            "(instr (unop-ast neg 8 (hole 8)) (canonical-args 0))",
+        ]);
+        let programs = parse(&[
+            "(binop add 8 (var x 8) (const -3 8))",
+            "(binop xor 8 (var y 8) (binop add 8 (var x 8) (const 100 8)))",
+            "(binop lsr 16 (var x 16) (const 3 16))",
+        ]);
+        let ranges = infer_immediate_ranges(&instrs, &programs).unwrap();
+        assert_eq!(
+            ranges,
+            vec![
+                Some(ImmediateRange {
+                    min: -3,
+                    max: 100,
+                    bits: 8
+                }),
+                Some(ImmediateRange {
+                    min: 3,
+                    max: 3,
+                    bits: 3
+                }),
+                None,
+            ]
+        );
+
+        // The 16-bit shift's amounts fit in I-type's immediate.
+        let options = EncodingOptions::default()
+            .with_immediate(1)
+            .with_immediate_bits(1, ranges[1].unwrap().bits);
+        let report = assign_encodings(&instrs, &options).unwrap();
+        assert_eq!(report.encoded.len(), 3);
+        assert_eq!(report.encoded[0].immediate_bits, None);
+        assert_eq!(report.encoded[1].format, Format::I);
+        assert_eq!(report.encoded[1].immediate_bits, Some(3));
+
+        // A shift by a variable amount needs every bit.
+        let programs = [programs, parse(&["(binop lsr 16 (var x 16) (var s 16))"])].concat();
+        let ranges = infer_immediate_ranges(&instrs, &programs).unwrap();
+        assert_eq!(ranges[1], None);
+
+        // The last of eleven operands is `a10`, not `a9`.
+        let (mut instr, mut program) = ("(hole 8)".to_string(), "(var x0 8)".to_string());
+        for i in 1..11 {
+            instr = format!("(binop-ast add 8 {} (hole 8))", instr);
+            program = match i {
+                10 => format!("(binop add 8 {} (const 5 8))", program),
+                _ => format!("(binop add 8 {} (var x{} 8))", program, i),
+            };
+        }
+        let args = (0..11).map(|i| i.to_string()).collect::<Vec<_>>();
+        let instr = format!("(instr {} (canonical-args {}))", instr, args.join(" "));
+        assert_eq!(
+            infer_immediate_ranges(&parse(&[instr.as_str()]), &parse(&[program.as_str()])).unwrap(),
+            vec![Some(ImmediateRange {
+                min: 5,
+                max: 5,
+                bits: 4
+            })]
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Equivalence checks by evaluating both terms, without a solver.
+//!
+//! Agreeing on a few test vectors doesn't prove two terms equivalent, but
+//! one mismatch proves they aren't. Standalone, [`quick_check`] is a sanity
+//! check; [`TestVectorPrefilter`] puts it in front of a backend, so that
+//! most inequivalent pairs never reach the solver. When the terms' inputs
+//! are only a few bits wide in total, [`equiv_exhaustive`] tries every
+//! input, which does prove equivalence.
+
+use std::collections::HashMap;
+
+use egg::RecExpr;
+use rand::Rng;
+
+use crate::{
+    ast::Expr,
+    backend::SynthesisBackend,
+    bitvec::BitVec,
+    determinism::{rng, DEFAULT_SEED},
+    error::LakeroadError,
+    eval::eval_expr,
+    language::Language,
+};
+
+/// A value for each variable.
+pub type TestVector = HashMap<String, BitVec>;
+
+/// A test vector on which two terms differ, and their values on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub inputs: TestVector,
+    pub a: BitVec,
+    pub b: BitVec,
+}
+
+/// The variables of `a` and `b`, and their widths, in the order they first
+/// appear. Fails if a variable has different widths in the two.
+pub fn shared_vars(a: &Expr, b: &Expr) -> Result<Vec<(String, usize)>, LakeroadError> {
+    let mut out: Vec<(String, usize)> = vec![];
+    for (name, width) in a.vars().into_iter().chain(b.vars()) {
+        let width = usize::try_from(width)
+            .map_err(|_| LakeroadError::Malformed(format!("bitwidth {}", width)))?;
+        match out.iter().find(|(n, _)| *n == name) {
+            Some((_, w)) if *w != width => {
+                return Err(LakeroadError::Malformed(format!(
+                    "{} is {} bits wide in one term and {} in the other",
+                    name, w, width
+                )))
+            }
+            Some(_) => (),
+            None => out.push((name, width)),
+        }
+    }
+    Ok(out)
+}
+
+/// `count` test vectors for `vars`. The first few give every variable the
+/// same corner case (zero, one, all ones, and the largest and smallest
+/// signed values); the rest are uniformly random, drawn from `seed`.
+pub fn test_vectors(vars: &[(String, usize)], count: usize, seed: u64) -> Vec<TestVector> {
+    let corners: [fn(usize) -> BitVec; 5] = [
+        BitVec::zero,
+        |width| BitVec::new(width, 1),
+        |width| BitVec::from_i64(width, -1),
+        |width| BitVec::from_i64(width, -1).lsr(&BitVec::new(width, 1)),
+        |width| {
+            BitVec::from_i64(width, -1)
+                .lsr(&BitVec::new(width, 1))
+                .not()
+        },
+    ];
+    let mut rng = rng(seed);
+    (0..count)
+        .map(|i| {
+            vars.iter()
+                .map(|(name, width)| {
+                    let value = match corners.get(i) {
+                        Some(corner) => corner(*width),
+                        None => {
+                            let words = (0..(width + 63) / 64)
+                                .map(|_| rng.gen())
+                                .collect::<Vec<u64>>();
+                            BitVec::from_words(*width, &words)
+                        }
+                    };
+                    (name.clone(), value)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The vectors on which `a` and `b` differ. Fails if either can't be
+/// evaluated, e.g. if a vector is missing one of their variables.
+pub fn mismatches(
+    a: &RecExpr<Language>,
+    b: &RecExpr<Language>,
+    vectors: &[TestVector],
+) -> Result<Vec<Mismatch>, LakeroadError> {
+    let (a, b) = (Expr::try_from(a)?, Expr::try_from(b)?);
+    let mut out = vec![];
+    for inputs in vectors {
+        let (a, b) = (eval_expr(&a, inputs)?, eval_expr(&b, inputs)?);
+        if a != b {
+            out.push(Mismatch {
+                inputs: inputs.clone(),
+                a,
+                b,
+            });
+        }
+    }
+    Ok(out)
+}
+
+/// The [`mismatches`] of `a` and `b` on `count` vectors generated by
+/// [`test_vectors`].
+pub fn quick_check(
+    a: &RecExpr<Language>,
+    b: &RecExpr<Language>,
+    count: usize,
+    seed: u64,
+) -> Result<Vec<Mismatch>, LakeroadError> {
+    let vars = shared_vars(&Expr::try_from(a)?, &Expr::try_from(b)?)?;
+    mismatches(a, b, &test_vectors(&vars, count, seed))
+}
+
+/// Every test vector for `vars`. The `n`th gives each variable, in order,
+/// the next bits of `n`, starting from the least significant. Fails if the
+/// variables are more than `max_bits` wide in total; `max_bits` must be less
+/// than 64.
+pub fn exhaustive_vectors(
+    vars: &[(String, usize)],
+    max_bits: usize,
+) -> Result<impl Iterator<Item = TestVector> + '_, LakeroadError> {
+    assert!(max_bits < 64, "can't enumerate {} bits of inputs", max_bits);
+    let bits = vars.iter().map(|(_, width)| width).sum::<usize>();
+    if bits > max_bits {
+        return Err(LakeroadError::Unsupported(format!(
+            "enumerating {} bits of inputs, more than {}",
+            bits, max_bits
+        )));
+    }
+    Ok((0..1u64 << bits).map(move |n| {
+        let mut offset = 0;
+        vars.iter()
+            .map(|(name, width)| {
+                let value = BitVec::new(*width, (n >> offset) as u128);
+                offset += width;
+                (name.clone(), value)
+            })
+            .collect()
+    }))
+}
+
+/// How many input bits, over all variables, [`equiv_exhaustive`] enumerates.
+pub const EXHAUSTIVE_BITS: usize = 20;
+
+/// Inputs on which two terms differ, and their values on them.
+pub type Counterexample = Mismatch;
+
+/// Whether `a` and `b` agree on every input, by trying them all: `None` if
+/// they do, and otherwise the first input on which they don't. Fails if
+/// their variables are more than [`EXHAUSTIVE_BITS`] wide in total.
+pub fn equiv_exhaustive(
+    a: &RecExpr<Language>,
+    b: &RecExpr<Language>,
+) -> Result<Option<Counterexample>, LakeroadError> {
+    equiv_exhaustive_up_to(a, b, EXHAUSTIVE_BITS)
+}
+
+/// Like [`equiv_exhaustive`], enumerating up to `max_bits` input bits,
+/// which must be less than 64.
+pub fn equiv_exhaustive_up_to(
+    a: &RecExpr<Language>,
+    b: &RecExpr<Language>,
+    max_bits: usize,
+) -> Result<Option<Counterexample>, LakeroadError> {
+    let (a, b) = (Expr::try_from(a)?, Expr::try_from(b)?);
+    for inputs in exhaustive_vectors(&shared_vars(&a, &b)?, max_bits)? {
+        let (a, b) = (eval_expr(&a, &inputs)?, eval_expr(&b, &inputs)?);
+        if a != b {
+            return Ok(Some(Mismatch { inputs, a, b }));
+        }
+    }
+    Ok(None)
+}
+
+/// A backend for tests which checks equivalence with [`equiv_exhaustive`],
+/// and finds every term feasible.
+#[cfg(test)]
+pub(crate) struct Exhaustive;
+
+#[cfg(test)]
+impl SynthesisBackend for Exhaustive {
+    fn check_feasible(&self, _: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+        Ok(true)
+    }
+
+    fn verify_equivalent(
+        &self,
+        a: &RecExpr<Language>,
+        b: &RecExpr<Language>,
+    ) -> Result<bool, LakeroadError> {
+        Ok(equiv_exhaustive(a, b)?.is_none())
+    }
+}
+
+/// A backend which answers equivalence queries with [`quick_check`] when it
+/// finds a mismatch, and asks the wrapped backend otherwise. Terms which
+/// can't be evaluated, e.g. bare `instr`s, always go to the wrapped backend.
+pub struct TestVectorPrefilter<B> {
+    backend: B,
+    vectors: usize,
+    seed: u64,
+}
+
+impl<B: SynthesisBackend> TestVectorPrefilter<B> {
+    pub fn new(backend: B) -> Self {
+        TestVectorPrefilter {
+            backend,
+            vectors: 64,
+            seed: DEFAULT_SEED,
+        }
+    }
+
+    /// How many test vectors to try before asking the backend.
+    pub fn with_vectors(mut self, vectors: usize) -> Self {
+        self.vectors = vectors;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+impl<B: SynthesisBackend> SynthesisBackend for TestVectorPrefilter<B> {
+    fn check_feasible(&self, expr: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+        self.backend.check_feasible(expr)
+    }
+
+    fn verify_equivalent(
+        &self,
+        a: &RecExpr<Language>,
+        b: &RecExpr<Language>,
+    ) -> Result<bool, LakeroadError> {
+        match quick_check(a, b, self.vectors, self.seed) {
+            Ok(mismatches) if !mismatches.is_empty() => Ok(false),
+            _ => self.backend.verify_equivalent(a, b),
+        }
+    }
+
+    fn check_candidate(&self, instr: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+        self.backend.check_candidate(instr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, str::FromStr};
+
+    use super::*;
+
+    /// Considers every pair equivalent, counting the queries.
+    #[derive(Default)]
+    struct Counter {
+        queries: Cell<usize>,
+    }
+
+    impl SynthesisBackend for &Counter {
+        fn check_feasible(&self, _: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+            Ok(true)
+        }
+
+        fn verify_equivalent(
+            &self,
+            _: &RecExpr<Language>,
+            _: &RecExpr<Language>,
+        ) -> Result<bool, LakeroadError> {
+            self.queries.set(self.queries.get() + 1);
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn prefilter_catches_mismatches() {
+        let expr = |s: &str| RecExpr::from_str(s).unwrap();
+        let and = expr("(binop and 8 (var x 8) (var y 8))");
+        let or = expr("(binop or 8 (var x 8) (var y 8))");
+        let mismatches = quick_check(&and, &or, 16, 0).unwrap();
+        assert!(!mismatches.is_empty());
+        for m in &mismatches {
+            assert_ne!(m.inputs["x"], m.inputs["y"]);
+        }
+        assert_eq!(
+            quick_check(&and, &expr("(binop and 8 (var y 8) (var x 8))"), 16, 0).unwrap(),
+            vec![]
+        );
+        assert!(quick_check(&and, &expr("(var x 4)"), 16, 0).is_err());
+
+        let counter = Counter::default();
+        let backend = TestVectorPrefilter::new(&counter);
+        assert!(!backend.verify_equivalent(&and, &or).unwrap());
+        assert_eq!(counter.queries.get(), 0);
+        assert!(backend.verify_equivalent(&and, &and).unwrap());
+        assert_eq!(counter.queries.get(), 1);
+    }
+
+    #[test]
+    fn exhaustive_proves_and_refutes() {
+        let expr = |s: &str| RecExpr::from_str(s).unwrap();
+        // x - y = x + (-y), on every input.
+        assert_eq!(
+            equiv_exhaustive(
+                &expr("(binop sub 8 (var x 8) (var y 8))"),
+                &expr("(binop add 8 (var x 8) (unop neg 8 (var y 8)))"),
+            )
+            .unwrap(),
+            None
+        );
+        // Logical and arithmetic shifts differ exactly on negative inputs,
+        // the first of which is 0x80.
+        let counterexample = equiv_exhaustive(
+            &expr("(binop lsr 8 (var x 8) (const 1 8))"),
+            &expr("(binop asr 8 (var x 8) (const 1 8))"),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(counterexample.inputs["x"], BitVec::new(8, 0x80));
+        assert!(matches!(
+            equiv_exhaustive(
+                &expr("(binop and 16 (var x 16) (var y 16))"),
+                &expr("(binop and 16 (var y 16) (var x 16))"),
+            ),
+            Err(LakeroadError::Unsupported(_))
+        ));
+    }
+}
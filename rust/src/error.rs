@@ -0,0 +1,66 @@
+//! The error type returned by the crate's public API.
+
+use thiserror::Error;
+
+use crate::{
+    corpus::{CorpusError, ProgramFileError},
+    frontends::sexp::SexpError,
+    language::ExprTypeError,
+    synthesizer::SynthesisError,
+};
+
+#[derive(Debug, Error)]
+pub enum LakeroadError {
+    /// Input which couldn't be parsed, e.g. a malformed program file.
+    #[error("parse error: {0}")]
+    Parse(String),
+    #[error("type error: {0}")]
+    Type(#[from] ExprTypeError),
+    /// A well-formed term the operation can't handle, e.g. an `apply` passed
+    /// to [`to_racket`](crate::solver::to_racket).
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+    /// A term which doesn't have the shape its node requires, e.g. a `var`
+    /// whose name isn't a string.
+    #[error("malformed term: {0}")]
+    Malformed(String),
+    /// The solver failed, as opposed to finding a query unsatisfiable.
+    #[error("solver error: {0}")]
+    Solver(String),
+    /// A configuration which is well-formed but can't be used, e.g. one
+    /// naming a rule which doesn't exist.
+    #[error("invalid configuration: {0}")]
+    Config(String),
+    /// The results database couldn't be read or written (see
+    /// [`crate::database`]).
+    #[error("database error: {0}")]
+    Database(String),
+    /// The operation noticed that its
+    /// [`CancellationToken`](crate::cancel::CancellationToken) was cancelled.
+    #[error("cancelled")]
+    Cancelled,
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Synthesis(#[from] SynthesisError),
+}
+
+impl From<SexpError> for LakeroadError {
+    fn from(error: SexpError) -> Self {
+        LakeroadError::Parse(error.to_string())
+    }
+}
+
+impl From<ProgramFileError> for LakeroadError {
+    fn from(error: ProgramFileError) -> Self {
+        LakeroadError::Parse(error.to_string())
+    }
+}
+
+impl From<CorpusError> for LakeroadError {
+    fn from(error: CorpusError) -> Self {
+        LakeroadError::Parse(error.to_string())
+    }
+}
+
+pub type Result<T, E = LakeroadError> = std::result::Result<T, E>;
@@ -0,0 +1,341 @@
+//! Concrete evaluation of operators on values of up to 128 bits, interpreted
+//! as unsigned and truncated to the operator's bitwidth, and of whole
+//! programs, at any width, with [`eval`]. Programs with `reg`s are evaluated
+//! cycle by cycle with [`eval_cycles`].
+
+use std::collections::HashMap;
+
+use egg::RecExpr;
+
+use crate::{
+    ast::{Ast, Expr, Instr},
+    bitvec::BitVec,
+    error::LakeroadError,
+    interval::mask,
+    language::{Language, Op},
+};
+
+/// The value of `expr` when each variable has the value `env` gives it, in
+/// the first cycle, where `reg`s are zero. Fails if a variable has no value
+/// or a value of the wrong width, or if an `apply` has the wrong number of
+/// arguments for its `instr`.
+pub fn eval(
+    expr: &RecExpr<Language>,
+    env: &HashMap<String, BitVec>,
+) -> Result<BitVec, LakeroadError> {
+    eval_expr(&Expr::try_from(expr)?, env)
+}
+
+/// Like [`eval`], on an [`Expr`].
+pub fn eval_expr(expr: &Expr, env: &HashMap<String, BitVec>) -> Result<BitVec, LakeroadError> {
+    eval_at(expr, std::slice::from_ref(env), 0)
+}
+
+/// The value of `expr` in each cycle, when each variable has the value the
+/// cycle's environment in `envs` gives it. A `reg` is zero in the first
+/// cycle, and after that its argument's value in the cycle before.
+pub fn eval_cycles(
+    expr: &Expr,
+    envs: &[HashMap<String, BitVec>],
+) -> Result<Vec<BitVec>, LakeroadError> {
+    (0..envs.len())
+        .map(|cycle| eval_at(expr, envs, cycle))
+        .collect()
+}
+
+/// The value of `expr` in cycle `cycle`, whose environment is `envs[cycle]`.
+fn eval_at(
+    expr: &Expr,
+    envs: &[HashMap<String, BitVec>],
+    cycle: usize,
+) -> Result<BitVec, LakeroadError> {
+    let env = &envs[cycle];
+    let width = |width: i64| match usize::try_from(width) {
+        Ok(width) if width > 0 => Ok(width),
+        _ => Err(LakeroadError::Malformed(format!("bitwidth {}", width))),
+    };
+    match expr {
+        Expr::Var { name, width: w } => {
+            let value = env.get(name).ok_or_else(|| {
+                LakeroadError::Malformed(format!("no value for variable {}", name))
+            })?;
+            if value.width() as i64 != *w {
+                return Err(LakeroadError::Malformed(format!(
+                    "variable {} is {} bits wide, but its value is {} bits wide",
+                    name,
+                    w,
+                    value.width()
+                )));
+            }
+            Ok(value.clone())
+        }
+        Expr::Const { value, width: w } => Ok(BitVec::from_i64(width(*w)?, *value)),
+        Expr::UnOp { op, width: w, arg } => unop(op, width(*w)?, eval_at(arg, envs, cycle)?),
+        Expr::BinOp {
+            op,
+            width: w,
+            lhs,
+            rhs,
+        } => binop(
+            op,
+            width(*w)?,
+            eval_at(lhs, envs, cycle)?,
+            eval_at(rhs, envs, cycle)?,
+        ),
+        Expr::Reg { width: w, arg } => match cycle {
+            0 => Ok(BitVec::zero(width(*w)?)),
+            _ => eval_at(arg, envs, cycle - 1),
+        },
+        Expr::Extract { hi, lo, arg } => {
+            let arg = eval_at(arg, envs, cycle)?;
+            match (usize::try_from(*hi), usize::try_from(*lo)) {
+                (Ok(hi), Ok(lo)) if lo <= hi && hi < arg.width() => Ok(arg.extract(hi, lo)),
+                _ => Err(LakeroadError::Malformed(format!(
+                    "bits {} to {} of a signal of bitwidth {}",
+                    hi,
+                    lo,
+                    arg.width()
+                ))),
+            }
+        }
+        Expr::Cat { hi, lo } => Ok(eval_at(hi, envs, cycle)?.concat(&eval_at(lo, envs, cycle)?)),
+        Expr::Apply { instr, args } => {
+            let args = args
+                .iter()
+                .map(|arg| eval_at(arg, envs, cycle))
+                .collect::<Result<Vec<_>, _>>()?;
+            eval_instr(instr, &args)
+        }
+    }
+}
+
+/// The value of `instr` applied to `args`. Either there's an argument for
+/// each hole, from left to right, as in the `apply`s rewriting produces, or
+/// one for each distinct canonical argument, which the holes numbered by it
+/// share.
+pub fn eval_instr(instr: &Instr, args: &[BitVec]) -> Result<BitVec, LakeroadError> {
+    let holes = instr.ast.num_holes();
+    if instr.canonical_args.len() != holes {
+        return Err(LakeroadError::Malformed(format!(
+            "an instr with {} holes and {} canonical args",
+            holes,
+            instr.canonical_args.len()
+        )));
+    }
+    let values = instr
+        .canonical_args
+        .iter()
+        .enumerate()
+        .map(|(hole, canonical)| {
+            let index = if args.len() == holes {
+                hole
+            } else {
+                *canonical as usize
+            };
+            args.get(index).cloned().ok_or_else(|| {
+                LakeroadError::Malformed(format!(
+                    "an instr with {} holes applied to {} args",
+                    holes,
+                    args.len()
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    eval_ast(&instr.ast, &mut values.into_iter())
+}
+
+/// The value of `ast`, filling its holes, from left to right, with
+/// `holes`.
+fn eval_ast(ast: &Ast, holes: &mut impl Iterator<Item = BitVec>) -> Result<BitVec, LakeroadError> {
+    let width = |width: i64| usize::try_from(width).unwrap_or(0);
+    match ast {
+        Ast::Hole { width: w } => {
+            let value = holes
+                .next()
+                .ok_or_else(|| LakeroadError::Malformed("too few args".to_string()))?;
+            check_width(&value, width(*w))?;
+            Ok(value)
+        }
+        Ast::UnOp { op, width: w, arg } => unop(op, width(*w), eval_ast(arg, holes)?),
+        Ast::BinOp {
+            op,
+            width: w,
+            lhs,
+            rhs,
+        } => {
+            let lhs = eval_ast(lhs, holes)?;
+            binop(op, width(*w), lhs, eval_ast(rhs, holes)?)
+        }
+        Ast::Apply { instr, args } => {
+            let args = args
+                .iter()
+                .map(|arg| eval_ast(arg, holes))
+                .collect::<Result<Vec<_>, _>>()?;
+            eval_instr(instr, &args)
+        }
+    }
+}
+
+fn unop(op: &Op, width: usize, a: BitVec) -> Result<BitVec, LakeroadError> {
+    check_width(&a, width)?;
+    a.unop(op)
+        .ok_or_else(|| LakeroadError::Malformed(format!("{} isn't unary", op)))
+}
+
+fn binop(op: &Op, width: usize, a: BitVec, b: BitVec) -> Result<BitVec, LakeroadError> {
+    // The operands of an add, sub or mul may share another width, and are
+    // zero-extended or truncated to the result's.
+    let (a, b) = if op.is_resizing() && a.width() == b.width() {
+        (resize(a, width), resize(b, width))
+    } else {
+        (a, b)
+    };
+    check_width(&a, width)?;
+    // Shift amounts may be narrower, and are zero-extended.
+    if !(op.is_shift() && b.width() <= width) {
+        check_width(&b, width)?;
+    }
+    a.binop(op, &b)
+        .ok_or_else(|| LakeroadError::Malformed(format!("{} isn't binary", op)))
+}
+
+/// `value` zero-extended or truncated to `width` bits.
+fn resize(value: BitVec, width: usize) -> BitVec {
+    match value.width() {
+        w if w < width => BitVec::zero(width - w).concat(&value),
+        w if w > width => value.extract(width - 1, 0),
+        _ => value,
+    }
+}
+
+/// Fails unless an operand is as wide as its operator, as it is in any
+/// well-typed program.
+fn check_width(value: &BitVec, width: usize) -> Result<(), LakeroadError> {
+    if value.width() != width {
+        return Err(LakeroadError::Malformed(format!(
+            "a {}-bit operand of a {}-bit operator",
+            value.width(),
+            width
+        )));
+    }
+    Ok(())
+}
+
+/// The value of `(unop op width a)`, or `None` if `op` isn't unary.
+pub fn eval_unop(op: &Op, width: usize, a: u128) -> Option<u128> {
+    let mask = mask(width);
+    let a = a & mask;
+    match op {
+        Op::Not => Some(!a & mask),
+        Op::Neg => Some(a.wrapping_neg() & mask),
+        _ => None,
+    }
+}
+
+/// The value of `(binop op width a b)`, or `None` if `op` isn't binary. A
+/// shift amount or resized operand narrower than `width` is given
+/// zero-extended, and a wider resized operand is truncated here.
+pub fn eval_binop(op: &Op, width: usize, a: u128, b: u128) -> Option<u128> {
+    let mask = mask(width);
+    let (a, b) = (a & mask, b & mask);
+    let shifted_out = b >= width as u128 || b >= 128;
+    match op {
+        Op::And => Some(a & b),
+        Op::Or => Some(a | b),
+        Op::Xor => Some(a ^ b),
+        Op::Add => Some(a.wrapping_add(b) & mask),
+        Op::Sub => Some(a.wrapping_sub(b) & mask),
+        Op::Mul => Some(a.wrapping_mul(b) & mask),
+        Op::Lsr if shifted_out => Some(0),
+        Op::Lsr => Some(a >> b),
+        Op::Asr => {
+            let negative = width > 0 && (a >> (width.min(128) - 1)) & 1 == 1;
+            Some(match (negative, shifted_out) {
+                (false, true) => 0,
+                (true, true) => mask,
+                (false, false) => a >> b,
+                (true, false) => (a >> b) | (mask & !(mask >> b)),
+            })
+        }
+        Op::Eq => Some((a == b) as u128),
+        Op::Not | Op::Neg => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eval_ops() {
+        assert_eq!(eval_unop(&Op::Not, 8, 0x0f), Some(0xf0));
+        assert_eq!(eval_unop(&Op::Neg, 8, 1), Some(0xff));
+        assert_eq!(eval_unop(&Op::And, 8, 1), None);
+        assert_eq!(eval_binop(&Op::Add, 8, 200, 100), Some(44));
+        assert_eq!(eval_binop(&Op::Sub, 8, 3, 5), Some(254));
+        assert_eq!(eval_binop(&Op::Mul, 8, 20, 13), Some(4));
+        assert_eq!(eval_binop(&Op::Lsr, 8, 0x80, 7), Some(1));
+        assert_eq!(eval_binop(&Op::Lsr, 8, 0x80, 8), Some(0));
+        assert_eq!(eval_binop(&Op::Asr, 8, 0x80, 7), Some(0xff));
+        assert_eq!(eval_binop(&Op::Asr, 8, 0x80, 200), Some(0xff));
+        assert_eq!(eval_binop(&Op::Asr, 8, 0x40, 1), Some(0x20));
+        assert_eq!(eval_binop(&Op::Eq, 8, 3, 3), Some(1));
+        assert_eq!(eval_binop(&Op::Not, 8, 3, 3), None);
+    }
+
+    #[test]
+    fn eval_programs() {
+        use std::str::FromStr;
+
+        let env = HashMap::from([
+            ("x".to_string(), BitVec::new(8, 0)),
+            ("y".to_string(), BitVec::new(8, 0x80)),
+        ]);
+        let eval_str = |s: &str| eval(&RecExpr::from_str(s).unwrap(), &env);
+        assert_eq!(
+            eval_str("(binop add 8 (var x 8) (const -1 8))").unwrap(),
+            BitVec::new(8, 0xff)
+        );
+        assert_eq!(
+            eval_str("(binop asr 8 (var y 8) (const 7 8))").unwrap(),
+            BitVec::new(8, 0xff)
+        );
+        assert_eq!(
+            eval_str("(binop lsr 8 (var y 8) (const 7 3))").unwrap(),
+            BitVec::new(8, 1)
+        );
+        assert_eq!(
+            eval_str("(binop eq 8 (unop neg 8 (var y 8)) (var y 8))").unwrap(),
+            BitVec::new(8, 1)
+        );
+        assert!(matches!(
+            eval_str("(var z 8)"),
+            Err(LakeroadError::Malformed(_))
+        ));
+        assert!(matches!(
+            eval_str("(var x 4)"),
+            Err(LakeroadError::Malformed(_))
+        ));
+
+        // An `and` of a variable with itself, with an arg per hole or per
+        // canonical arg.
+        let instr = "(instr (binop-ast and 8 (hole 8) (hole 8)) (canonical-args 0 0))";
+        for (args, var) in [
+            ("(list (var x 8) (var x 8))", "x"),
+            ("(list (var y 8))", "y"),
+        ] {
+            let apply = format!("(apply {} {})", instr, args);
+            assert_eq!(eval_str(&apply).unwrap(), env[var]);
+        }
+        assert!(eval_str(&format!("(apply {} (list))", instr)).is_err());
+
+        let env = HashMap::from([("x".to_string(), BitVec::new(128, u128::MAX))]);
+        let wide = RecExpr::from_str("(binop add 200 (var x 200) (var x 200))").unwrap();
+        assert!(eval(&wide, &env).is_err());
+        let env = HashMap::from([("x".to_string(), BitVec::new(200, u128::MAX))]);
+        assert_eq!(
+            eval(&wide, &env).unwrap(),
+            BitVec::from_words(200, &[u64::MAX - 1, u64::MAX, 1])
+        );
+    }
+}
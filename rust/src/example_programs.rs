@@ -45,7 +45,7 @@ mod tests {
 
     use egg::EGraph;
 
-    use crate::language::LanguageAnalysis;
+    use crate::analysis::LanguageAnalysis;
 
     use super::all_programs;
 
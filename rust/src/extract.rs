@@ -0,0 +1,1120 @@
+//! Extraction strategies beyond egg's greedy [`egg::Extractor`].
+//!
+//! All extractors here break ties deterministically: eclasses are visited in
+//! order of their ids, and equal-cost expressions are ordered by
+//! [`cmp_exprs`]. Two runs over the same egraph thus extract the same
+//! expressions, regardless of hash map iteration order.
+//!
+//! Also here: extracting the ISA's candidate instructions from a rewritten
+//! egraph ([`find_isa_instructions`]), and sampling programs' random
+//! implementations.
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use egg::{
+    AstSize, CostFunction, EGraph, Extractor, Id, Language as LanguageTrait, Pattern, RecExpr,
+    Searcher, Var,
+};
+use rand::{prelude::IteratorRandom, Rng};
+
+use crate::{
+    analysis::{is_pruned, LanguageAnalysis},
+    determinism::rng,
+    error::LakeroadError,
+    language::Language,
+};
+
+/// The number of unique nodes in an expression, i.e. its size when
+/// represented as a DAG. Unlike [`egg::AstSize`], shared subexpressions are
+/// only counted once.
+pub fn dag_size(expr: &RecExpr<Language>) -> usize {
+    hashcons(expr).as_ref().len()
+}
+
+/// `expr` with each distinct subexpression appearing once.
+pub fn hashcons(expr: &RecExpr<Language>) -> RecExpr<Language> {
+    let mut out = RecExpr::default();
+    append_hashconsed(&mut out, &mut HashMap::default(), expr);
+    out
+}
+
+/// The canonical order used to break ties between equal-cost expressions:
+/// smaller expressions first, then by their printed form.
+pub fn cmp_exprs(a: &RecExpr<Language>, b: &RecExpr<Language>) -> Ordering {
+    a.as_ref()
+        .len()
+        .cmp(&b.as_ref().len())
+        .then_with(|| a.to_string().cmp(&b.to_string()))
+}
+
+/// Adds `node` to `out`, reusing an existing identical node if there is one.
+pub(crate) fn add_hashconsed(
+    out: &mut RecExpr<Language>,
+    memo: &mut HashMap<Language, Id>,
+    node: Language,
+) -> Id {
+    if let Some(id) = memo.get(&node) {
+        return *id;
+    }
+    let id = out.add(node.clone());
+    memo.insert(node, id);
+    id
+}
+
+/// Copies all of `expr` into `out`, hash-consing along the way. Returns the id
+/// of `expr`'s root within `out`.
+pub(crate) fn append_hashconsed(
+    out: &mut RecExpr<Language>,
+    memo: &mut HashMap<Language, Id>,
+    expr: &RecExpr<Language>,
+) -> Id {
+    let mut new_ids: Vec<Id> = Vec::with_capacity(expr.as_ref().len());
+    for node in expr.as_ref() {
+        let mut node = node.clone();
+        node.children_mut()
+            .iter_mut()
+            .for_each(|id| *id = new_ids[usize::from(*id)]);
+        new_ids.push(add_hashconsed(out, memo, node));
+    }
+    *new_ids.last().expect("expression should not be empty")
+}
+
+/// Builds the memo table for an expression which is already hash-consed.
+fn memo_of(expr: &RecExpr<Language>) -> HashMap<Language, Id> {
+    expr.as_ref()
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.clone(), Id::from(i)))
+        .collect()
+}
+
+/// A partially-built implementation of an enode: the children chosen so far,
+/// all packed into a single hash-consed expression.
+struct Partial {
+    expr: RecExpr<Language>,
+    children: Vec<Id>,
+}
+
+/// Beam-search extraction: a middle ground between greedy [`egg::AstSize`]
+/// extraction and exact (e.g. ILP) extraction.
+///
+/// For every eclass we keep the `beam_width` cheapest implementations found so
+/// far, where cost is computed over the whole extracted expression by
+/// `cost_fn` (by default, [`dag_size`]). Because costs are computed over whole
+/// expressions rather than summed per child, the extractor can find
+/// implementations which reuse subexpressions across operands, which greedy
+/// tree-cost extraction cannot see. With a beam width of 1 this degenerates to
+/// a greedy extractor over the given cost.
+pub struct BeamExtractor<'a, F>
+where
+    F: Fn(&RecExpr<Language>) -> usize,
+{
+    egraph: &'a EGraph<Language, LanguageAnalysis>,
+    beam_width: usize,
+    cost_fn: F,
+    beams: HashMap<Id, Vec<(usize, RecExpr<Language>)>>,
+}
+
+impl<'a> BeamExtractor<'a, fn(&RecExpr<Language>) -> usize> {
+    /// Beam extractor minimizing [`dag_size`].
+    pub fn new(
+        egraph: &'a EGraph<Language, LanguageAnalysis>,
+        beam_width: usize,
+    ) -> Result<Self, LakeroadError> {
+        BeamExtractor::with_cost_fn(egraph, beam_width, dag_size)
+    }
+}
+
+impl<'a, F> BeamExtractor<'a, F>
+where
+    F: Fn(&RecExpr<Language>) -> usize,
+{
+    /// Maximum number of passes over the egraph. Beams are recomputed until
+    /// they stop changing, but cycles in the egraph can cause equal-cost
+    /// implementations to trade places indefinitely, so we cap the number of
+    /// passes.
+    const MAX_PASSES: usize = 100;
+
+    /// Beam extractor minimizing `cost_fn`. Fails if `beam_width` is zero,
+    /// as no implementation would be kept.
+    pub fn with_cost_fn(
+        egraph: &'a EGraph<Language, LanguageAnalysis>,
+        beam_width: usize,
+        cost_fn: F,
+    ) -> Result<Self, LakeroadError> {
+        if beam_width == 0 {
+            return Err(LakeroadError::Config(
+                "beam width 0, which isn't positive".to_string(),
+            ));
+        }
+        let mut extractor = BeamExtractor {
+            egraph,
+            beam_width,
+            cost_fn,
+            beams: HashMap::default(),
+        };
+        extractor.find_beams();
+        Ok(extractor)
+    }
+
+    /// Returns the cheapest implementation of the given eclass, along with
+    /// its cost, or `None` if it has no finite implementation.
+    pub fn find_best(&self, id: Id) -> Option<(usize, RecExpr<Language>)> {
+        self.find_beam(id).first().cloned()
+    }
+
+    /// Returns all implementations currently in the beam for the given
+    /// eclass, cheapest first.
+    pub fn find_beam(&self, id: Id) -> &[(usize, RecExpr<Language>)] {
+        self.beams
+            .get(&self.egraph.find(id))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    fn find_beams(&mut self) {
+        for _ in 0..Self::MAX_PASSES {
+            let mut changed = false;
+            let mut ids = self.egraph.classes().map(|c| c.id).collect::<Vec<_>>();
+            ids.sort();
+            for id in ids {
+                let new_beam = self.make_beam(id);
+                if new_beam.is_empty() {
+                    continue;
+                }
+                let old_costs = self
+                    .beams
+                    .get(&id)
+                    .map(|beam| beam.iter().map(|(cost, _)| *cost).collect::<Vec<_>>());
+                let new_costs = new_beam.iter().map(|(cost, _)| *cost).collect::<Vec<_>>();
+                if old_costs.as_ref() != Some(&new_costs) {
+                    changed = true;
+                    self.beams.insert(id, new_beam);
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Computes a new beam for an eclass from the current beams of its
+    /// children.
+    fn make_beam(&self, id: Id) -> Vec<(usize, RecExpr<Language>)> {
+        let mut candidates: Vec<(usize, RecExpr<Language>)> = Vec::default();
+        for node in &self.egraph[id].nodes {
+            let child_beams = node
+                .children()
+                .iter()
+                .map(|child| self.beams.get(&self.egraph.find(*child)))
+                .collect::<Option<Vec<_>>>();
+            let child_beams = match child_beams {
+                Some(v) => v,
+                // Some child doesn't have an implementation yet.
+                None => continue,
+            };
+
+            // Choose children one at a time, pruning the partial
+            // implementations back down to the beam width after each choice.
+            let mut partials = vec![Partial {
+                expr: RecExpr::default(),
+                children: vec![],
+            }];
+            for child_beam in child_beams {
+                let mut extended = Vec::default();
+                for partial in &partials {
+                    for (_, child_expr) in child_beam {
+                        let mut expr = partial.expr.clone();
+                        let mut memo = memo_of(&expr);
+                        let child_id = append_hashconsed(&mut expr, &mut memo, child_expr);
+                        let mut children = partial.children.clone();
+                        children.push(child_id);
+                        extended.push(Partial { expr, children });
+                    }
+                }
+                extended.sort_by_cached_key(|partial| {
+                    ((self.cost_fn)(&partial.expr), partial.expr.to_string())
+                });
+                extended.truncate(self.beam_width);
+                partials = extended;
+            }
+
+            for partial in partials {
+                let mut new_node = node.clone();
+                new_node
+                    .children_mut()
+                    .iter_mut()
+                    .zip(partial.children.iter())
+                    .for_each(|(id, new_id)| *id = *new_id);
+                let mut expr = partial.expr;
+                let mut memo = memo_of(&expr);
+                let root = add_hashconsed(&mut expr, &mut memo, new_node);
+                // Make sure the root is the last node of the expression.
+                if usize::from(root) != expr.as_ref().len() - 1 {
+                    continue;
+                }
+                candidates.push(((self.cost_fn)(&expr), expr));
+            }
+        }
+
+        candidates
+            .sort_by(|(a_cost, a), (b_cost, b)| a_cost.cmp(b_cost).then_with(|| cmp_exprs(a, b)));
+        candidates.dedup_by(|(_, a), (_, b)| a.as_ref() == b.as_ref());
+        candidates.truncate(self.beam_width);
+        candidates
+    }
+}
+
+/// Cost computed by [`DualObjective`].
+///
+/// Costs are compared only by their weighted total; the two components are
+/// kept around for reporting.
+#[derive(Debug, Clone, Copy)]
+pub struct DualCost {
+    /// Static instruction count, i.e. the number of `apply`s (and leftover
+    /// `unop`/`binop`s, each of which is counted as a single baseline
+    /// instruction).
+    pub instructions: usize,
+    /// Estimated cycles for a single execution of the program. For
+    /// instruction ASTs and `instr` nodes, this is instead the estimated
+    /// latency of the instruction.
+    pub cycles: usize,
+    /// The weighted combination of the above which we actually minimize.
+    pub weighted: f64,
+}
+impl PartialEq for DualCost {
+    fn eq(&self, other: &Self) -> bool {
+        self.weighted == other.weighted
+    }
+}
+impl PartialOrd for DualCost {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.weighted.partial_cmp(&other.weighted)
+    }
+}
+
+/// Cost function trading off static code size against estimated dynamic
+/// cycles.
+///
+/// The latency of an instruction is estimated as the depth of its AST
+/// (minimum 1), and instructions are assumed to execute sequentially, so the
+/// cycles of a program are the sum of the latencies of the instructions it
+/// applies. The minimized quantity is
+/// `size_weight * instructions + cycles_weight * frequency * cycles`, where
+/// `frequency` is the execution frequency of the program being extracted.
+#[derive(Debug, Clone, Copy)]
+pub struct DualObjective {
+    pub size_weight: f64,
+    pub cycles_weight: f64,
+    pub frequency: f64,
+}
+
+impl DualObjective {
+    fn make(&self, instructions: usize, cycles: usize) -> DualCost {
+        DualCost {
+            instructions,
+            cycles,
+            weighted: self.size_weight * instructions as f64
+                + self.cycles_weight * self.frequency * cycles as f64,
+        }
+    }
+}
+
+impl CostFunction<Language> for DualObjective {
+    type Cost = DualCost;
+
+    fn cost<C>(&mut self, enode: &Language, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        match enode {
+            &Language::Apply([instr_id, args_id]) => {
+                let (instr, args) = (costs(instr_id), costs(args_id));
+                self.make(1 + args.instructions, instr.cycles.max(1) + args.cycles)
+            }
+            &Language::Instr([ast_id, _]) => self.make(0, costs(ast_id).cycles),
+            &Language::ApplyAst([instr_id, args_id]) => {
+                let (instr, args) = (costs(instr_id), costs(args_id));
+                self.make(0, instr.cycles.max(1) + args.cycles)
+            }
+            Language::UnOpAst(_) | Language::BinOpAst(_) => {
+                let depth = enode
+                    .children()
+                    .iter()
+                    .map(|id| costs(*id).cycles)
+                    .max()
+                    .unwrap_or(0);
+                self.make(0, 1 + depth)
+            }
+            Language::UnOp(_) | Language::BinOp(_) => {
+                let (instructions, cycles) = enode.children().iter().fold((1, 1), |(i, c), id| {
+                    let cost = costs(*id);
+                    (i + cost.instructions, c + cost.cycles)
+                });
+                self.make(instructions, cycles)
+            }
+            _ => {
+                let (instructions, cycles) = enode.children().iter().fold((0, 0), |(i, c), id| {
+                    let cost = costs(*id);
+                    (i + cost.instructions, c + cost.cycles)
+                });
+                self.make(instructions, cycles)
+            }
+        }
+    }
+}
+
+/// Extracts each program under the [`DualObjective`] with the given weights.
+/// `programs` pairs each program root with its execution frequency, as
+/// returned by [`crate::program_set::ProgramSet::add_to_egraph`].
+pub fn extract_dual_objective(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    programs: &[(Id, f64)],
+    size_weight: f64,
+    cycles_weight: f64,
+) -> Vec<(DualCost, RecExpr<Language>)> {
+    programs
+        .iter()
+        .map(|&(root, frequency)| {
+            Extractor::new(
+                egraph,
+                DualObjective {
+                    size_weight,
+                    cycles_weight,
+                    frequency,
+                },
+            )
+            .find_best(root)
+        })
+        .collect()
+}
+
+/// Extracts the smallest term of every unpruned `instr` eclass. Fails if an
+/// `instr` eclass was merged with a smaller term which isn't an `instr`, as
+/// that term can't be used as an instruction.
+pub fn find_isa_instructions(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+) -> Result<Vec<(Id, RecExpr<Language>)>, LakeroadError> {
+    let mut out = Vec::default();
+    let ast_var: Var = "?ast".parse().unwrap();
+    let canonical_args_var: Var = "?canonical-args".parse().unwrap();
+    let extractor = Extractor::new(egraph, AstSize);
+    for search_match in format!(
+        "(instr {} {})",
+        ast_var.to_string(),
+        canonical_args_var.to_string()
+    )
+    .parse::<Pattern<_>>()
+    .unwrap()
+    .search(egraph)
+    {
+        if is_pruned(egraph, search_match.eclass) {
+            continue;
+        }
+        let (_, expr) = extractor.find_best(search_match.eclass);
+        if !matches!(expr.as_ref().last(), Some(Language::Instr(_))) {
+            return Err(LakeroadError::Malformed(format!(
+                "eclass {} matched (instr ?ast ?canonical-args) but extracted {}",
+                search_match.eclass, expr
+            )));
+        }
+        out.push((search_match.eclass, expr));
+
+        // I'm not sure if either of these will always be true. For now it's
+        // simpler to assume they are true and then deal with it when they're
+        // not. Basically, we're assuming that every (instr ?ast ?args) instance
+        // is unique. If these fail, it probably means that instructions were
+        // proven to be equivalent, which is actually cool and good but I just
+        // haven't thought about what to do in that case. Do we just take one
+        // instruction? Whatever we do, we'll need to make a more informed
+        // decision.
+        // assert_eq!(search_match.substs.len(), 1);
+        // assert_eq!(egraph[search_match.eclass].nodes.len(), 1);
+        // for subst in search_match.substs {
+        //     let ast_id = subst[ast_var];
+        //     let canonical_args_id = subst[canonical_args_var];
+        //     out.push((
+        //         search_match.eclass,
+        //         extract_ast(egraph, ast_id, canonical_args_id),
+        //     ));
+        // }
+    }
+
+    // Order candidates deterministically rather than by egraph iteration
+    // order.
+    out.sort_by(|(a_id, a), (b_id, b)| cmp_exprs(a, b).then_with(|| a_id.cmp(b_id)));
+
+    Ok(out)
+}
+
+/// Whether `instr_id` is reachable from `program_root`. To check many pairs,
+/// build a [`CoverageIndex`] once instead.
+pub fn instr_appears_in_program(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    instr_id: Id,
+    program_root: Id,
+) -> bool {
+    CoverageIndex::new(egraph, [program_root]).contains(program_root, instr_id)
+}
+
+/// Which programs' roots reach each eclass of an e-graph, so whether an
+/// instruction appears in a program is a lookup rather than a traversal.
+/// The roots' reachability is propagated in one pass over the e-graph, and
+/// kept up to date as roots are added and the e-graph grows.
+#[derive(Debug, Clone, Default)]
+pub struct CoverageIndex {
+    /// The index of each root's bit, under both the id it was added with and
+    /// its canonical id.
+    roots: HashMap<Id, usize>,
+    /// How many distinct roots there are.
+    programs: usize,
+    /// For each canonical eclass, a bit per root which reaches it.
+    reached: HashMap<Id, Vec<u64>>,
+}
+
+/// Sets the bits of `from` in `into`, returning whether any were new.
+fn union_bits(into: &mut Vec<u64>, from: &[u64]) -> bool {
+    if into.len() < from.len() {
+        into.resize(from.len(), 0);
+    }
+    let mut changed = false;
+    for (into, from) in into.iter_mut().zip(from) {
+        changed |= *into | from != *into;
+        *into |= from;
+    }
+    changed
+}
+
+impl CoverageIndex {
+    pub fn new(
+        egraph: &EGraph<Language, LanguageAnalysis>,
+        roots: impl IntoIterator<Item = Id>,
+    ) -> Self {
+        let mut index = CoverageIndex::default();
+        let mut worklist = vec![];
+        for root in roots {
+            worklist.push(index.mark(egraph, root));
+        }
+        index.propagate(egraph, worklist);
+        index
+    }
+
+    /// Adds a program root, if it isn't indexed yet.
+    pub fn add_program(&mut self, egraph: &EGraph<Language, LanguageAnalysis>, root: Id) {
+        if !self.roots.contains_key(&root) {
+            let marked = self.mark(egraph, root);
+            self.propagate(egraph, vec![marked]);
+        }
+    }
+
+    /// Catches up with an e-graph which has grown, or merged eclasses, since
+    /// the index was built from it. It must have been rebuilt.
+    pub fn update(&mut self, egraph: &EGraph<Language, LanguageAnalysis>) {
+        let mut reached: HashMap<Id, Vec<u64>> = HashMap::default();
+        for (id, bits) in std::mem::take(&mut self.reached) {
+            union_bits(reached.entry(egraph.find(id)).or_default(), &bits);
+        }
+        self.reached = reached;
+        let roots = self
+            .roots
+            .iter()
+            .map(|(root, bit)| (egraph.find(*root), *bit))
+            .collect::<Vec<_>>();
+        self.roots.extend(roots);
+        let mut worklist = self.reached.keys().copied().collect::<Vec<_>>();
+        worklist.sort();
+        self.propagate(egraph, worklist);
+    }
+
+    /// Whether the eclass `instr_id` is reachable from the program rooted at
+    /// `program_root`, which must have been indexed. Ids are compared as
+    /// they were canonical when the index was last built or updated.
+    pub fn contains(&self, program_root: Id, instr_id: Id) -> bool {
+        match (self.roots.get(&program_root), self.reached.get(&instr_id)) {
+            (Some(bit), Some(bits)) => bits
+                .get(bit / 64)
+                .map_or(false, |w| w >> (bit % 64) & 1 == 1),
+            _ => false,
+        }
+    }
+
+    /// Gives `root` the next bit and sets it on its eclass, returning the
+    /// eclass.
+    fn mark(&mut self, egraph: &EGraph<Language, LanguageAnalysis>, root: Id) -> Id {
+        let canonical = egraph.find(root);
+        let bit = match self.roots.get(&canonical) {
+            Some(bit) => *bit,
+            None => {
+                self.programs += 1;
+                self.programs - 1
+            }
+        };
+        self.roots.insert(root, bit);
+        self.roots.insert(canonical, bit);
+        let mut bits = vec![0; bit / 64 + 1];
+        bits[bit / 64] = 1 << (bit % 64);
+        union_bits(self.reached.entry(canonical).or_default(), &bits);
+        canonical
+    }
+
+    /// Passes the bits of the eclasses on `worklist` down to their
+    /// children, until nothing changes. Each eclass is revisited only when
+    /// it gains bits.
+    fn propagate(&mut self, egraph: &EGraph<Language, LanguageAnalysis>, mut worklist: Vec<Id>) {
+        while let Some(id) = worklist.pop() {
+            let bits = self.reached[&id].clone();
+            for enode in &egraph[id].nodes {
+                for child in enode.children() {
+                    let child = egraph.find(*child);
+                    if union_bits(self.reached.entry(child).or_default(), &bits) {
+                        worklist.push(child);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Where a program can use an instruction: an eclass reachable from the
+/// program's root which holds an `apply` of the instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgramSite {
+    pub eclass: Id,
+    /// The smallest term of the eclass in the program's own operators,
+    /// i.e. the code the instruction would replace.
+    pub source: RecExpr<Language>,
+}
+
+/// Counts nodes, like [`AstSize`], but only of the operators programs are
+/// written in, so that what's extracted is source code rather than
+/// `apply`s of instructions.
+struct SourceSize;
+
+impl CostFunction<Language> for SourceSize {
+    type Cost = usize;
+
+    fn cost<C>(&mut self, enode: &Language, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        match enode {
+            Language::Apply(_)
+            | Language::Instr(_)
+            | Language::Hole(_)
+            | Language::UnOpAst(_)
+            | Language::BinOpAst(_)
+            | Language::ApplyAst(_)
+            | Language::Canonicalize(_)
+            | Language::CanonicalArgs(_)
+            | Language::List(_)
+            | Language::Concat(_) => usize::MAX,
+            _ => enode
+                .children()
+                .iter()
+                .fold(1, |sum: usize, id| sum.saturating_add(costs(*id))),
+        }
+    }
+}
+
+/// The sites of the program rooted at `program_root` which the instruction
+/// in eclass `instr_id` can implement, in eclass order, with their source.
+/// `index` must cover the program.
+pub fn matched_sites(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    index: &CoverageIndex,
+    instr_id: Id,
+    program_root: Id,
+) -> Vec<ProgramSite> {
+    let instr_id = egraph.find(instr_id);
+    if !index.contains(program_root, instr_id) {
+        return vec![];
+    }
+    let extractor = Extractor::new(egraph, SourceSize);
+    let mut sites = egraph
+        .classes()
+        .filter(|class| index.contains(program_root, class.id))
+        .filter(|class| {
+            class.nodes.iter().any(|node| match node {
+                Language::Apply([instr, _]) => egraph.find(*instr) == instr_id,
+                _ => false,
+            })
+        })
+        .filter_map(|class| {
+            let (cost, source) = extractor.find_best(class.id);
+            (cost < usize::MAX).then(|| ProgramSite {
+                eclass: class.id,
+                source,
+            })
+        })
+        .collect::<Vec<_>>();
+    sites.sort_by_key(|site| site.eclass);
+    sites
+}
+
+/// Extract a random implementation of an expression in an egraph.
+/// Only extracts (apply ...) nodes.
+/// If an eclass contains a var node, the var variant is automatically
+/// extracted.
+fn extract_random(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    id: Id,
+    rng: &mut impl Rng,
+) -> RecExpr<Language> {
+    let new_nodes = egraph
+        .classes()
+        .filter_map(|eclass| {
+            // Important: if an eclass contains a var, automatically select
+            // the var. This prevents infinite loops where we select
+            // (var x) = (apply (instr (hole) _) [(var x)])
+            // = (apply (instr (hole) _) [(apply (instr (hole) _) [(var x)])])
+            if let Some(var_node) = eclass.nodes.iter().find(|l| match l {
+                Language::Var(_) => true,
+                _ => false,
+            }) {
+                return Some((eclass.id, var_node.clone()));
+            }
+            let tmp = eclass
+                .nodes
+                .iter()
+                .enumerate()
+                //.filter(|(i, _)| enodes_to_filter.contains(&(eclass.id, *i)))
+                .filter(|(_, l)| match l {
+                    Language::Var(_) => true,
+                    Language::Const(_) => true,
+                    Language::UnOp(_) => false,
+                    Language::BinOp(_) => false,
+                    Language::Reg(_) => true,
+                    Language::Extract(_) => true,
+                    Language::Cat(_) => true,
+                    Language::Apply(_) => true,
+                    Language::Hole(_) => true,
+                    Language::UnOpAst(_) => true,
+                    Language::BinOpAst(_) => true,
+                    Language::ApplyAst(_) => true,
+                    Language::List(_) => true,
+                    Language::Concat(_) => true,
+                    Language::Canonicalize(_) => false,
+                    Language::CanonicalArgs(_) => true,
+                    Language::Instr(_) => true,
+                    Language::Op(_) => true,
+                    Language::Num(_) => true,
+                    Language::String(_) => true,
+                })
+                .choose(rng)
+                .map(|(_, node)| (eclass.id, node.clone()));
+            if tmp.is_none() {
+                println!("eclass empty after filtering: {:?}", eclass);
+            }
+            tmp
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut out = RecExpr::default();
+    fn _recursively_build(
+        id: Id,
+        in_expr: &HashMap<Id, Language>,
+        out_expr: &mut RecExpr<Language>,
+        egraph: &EGraph<Language, LanguageAnalysis>,
+    ) -> Option<Id> {
+        if !in_expr.contains_key(&egraph.find(id)) {
+            return None;
+        }
+        let mut new_node = in_expr.get(&egraph.find(id)).unwrap().clone();
+        let new_ids = new_node
+            .children()
+            .iter()
+            .map(|id| _recursively_build(*id, in_expr, out_expr, egraph))
+            .collect::<Vec<_>>();
+        if new_ids.iter().any(Option::is_none) {
+            return None;
+        }
+        new_node
+            .children_mut()
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, id)| *id = new_ids[i].unwrap());
+
+        Some(out_expr.add(new_node))
+    }
+    _recursively_build(egraph.find(id), &new_nodes, &mut out, egraph);
+
+    out
+}
+
+/// Randomly sample implementations of the program and count how many times the
+/// requested instruction appears. enodes_to_filter are (eclass id, enode index)
+/// pairs indicating enodes to filter out. Currently we pass in the results of
+/// find_cycles.
+///
+/// This currently doesn't work. I'd like to get it working, but it's requiring
+/// a lot more effort than I originally thought. I thought there'd be a simple
+/// way to do this...
+pub fn sample_instr_in_program(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    instr: &RecExpr<Language>,
+    program_root: Id,
+    num_samples: usize,
+    seed: u64,
+) -> usize {
+    let mut count = 0;
+    let mut rng = rng(seed);
+
+    for _ in 0..num_samples {
+        let expr = extract_random(egraph, program_root, &mut rng);
+
+        let mut egraph = EGraph::<_, LanguageAnalysis>::default();
+        egraph.add_expr(&expr);
+        egraph.rebuild();
+
+        if let Some(_) = egraph.lookup_expr(&instr) {
+            count += 1;
+        }
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, str::FromStr};
+
+    use egg::{AstSize, Extractor, Runner};
+
+    use super::*;
+    use crate::{example_programs, rewrites::*};
+
+    #[test]
+    fn dag_size_counts_shared_nodes_once() {
+        let expr = RecExpr::from_str("(binop and 8 (var x 8) (var x 8))").unwrap();
+        // and, 8, var, x.
+        assert_eq!(dag_size(&expr), 4);
+    }
+
+    #[test]
+    fn coverage_index_matches_traversal() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let and = egraph.add_expr(&RecExpr::from_str("(binop and 8 (var x 8) (var y 8))").unwrap());
+        let xor = egraph.add_expr(&RecExpr::from_str("(binop xor 8 (var y 8) (var z 8))").unwrap());
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(3)
+            .run(&vec![
+                introduce_hole_var(),
+                fuse_op(),
+                introduce_hole_op_both(),
+                simplify_concat(),
+                canonicalize(),
+            ]);
+        let egraph = &runner.egraph;
+
+        let mut index = CoverageIndex::new(egraph, [and]);
+        index.add_program(egraph, xor);
+        for class in egraph.classes() {
+            for root in [and, xor] {
+                let mut reached = HashSet::new();
+                let mut worklist = vec![egraph.find(root)];
+                while let Some(id) = worklist.pop() {
+                    if reached.insert(id) {
+                        worklist
+                            .extend(egraph[id].nodes.iter().flat_map(|n| n.children().to_vec()));
+                    }
+                }
+                assert_eq!(index.contains(root, class.id), reached.contains(&class.id));
+            }
+        }
+        let y = egraph
+            .lookup_expr(&RecExpr::from_str("(var y 8)").unwrap())
+            .unwrap();
+        let z = egraph
+            .lookup_expr(&RecExpr::from_str("(var z 8)").unwrap())
+            .unwrap();
+        assert!(index.contains(and, y) && index.contains(xor, y));
+        assert!(!index.contains(and, z) && index.contains(xor, z));
+
+        // Once `y` and `z` are merged, `and` reaches what `z` did.
+        let mut egraph = runner.egraph.clone();
+        egraph.union(y, z);
+        egraph.rebuild();
+        index.update(&egraph);
+        assert!(index.contains(and, egraph.find(z)));
+    }
+
+    #[test]
+    fn beam_no_worse_than_greedy() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let id = egraph.add_expr(
+            &RecExpr::from_str(
+                "(binop xor 8 (binop xor 8 (var x 8) (var y 8)) (binop and 8 (var x 8) (var y 8)))",
+            )
+            .unwrap(),
+        );
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(3)
+            .run(&vec![
+                introduce_hole_var(),
+                fuse_op(),
+                introduce_hole_op_both(),
+                simplify_concat(),
+                canonicalize(),
+            ]);
+
+        let (_, greedy) = Extractor::new(&runner.egraph, AstSize).find_best(id);
+        let beam = BeamExtractor::new(&runner.egraph, 4).unwrap();
+        let (cost, expr) = beam.find_best(id).unwrap();
+        assert_eq!(cost, dag_size(&expr));
+        assert!(cost <= dag_size(&greedy));
+        assert!(beam.find_beam(id).len() <= 4);
+
+        assert!(matches!(
+            BeamExtractor::new(&runner.egraph, 0),
+            Err(LakeroadError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn dual_objective_counts_instructions() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let id = egraph.add_expr(
+            &RecExpr::from_str("(binop sub 8 (var x 8) (binop and 8 (var x 8) (var y 8)))")
+                .unwrap(),
+        );
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(4)
+            .run(&vec![
+                introduce_hole_var(),
+                fuse_op(),
+                introduce_hole_op_both(),
+                introduce_hole_op_left(),
+                introduce_hole_op_right(),
+                simplify_concat(),
+                canonicalize(),
+            ]);
+
+        for (size_weight, cycles_weight) in [(1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+            let results =
+                extract_dual_objective(&runner.egraph, &[(id, 10.0)], size_weight, cycles_weight);
+            let (cost, expr) = &results[0];
+            let num_instructions = expr
+                .as_ref()
+                .iter()
+                .filter(|node| {
+                    matches!(
+                        node,
+                        Language::Apply(_) | Language::BinOp(_) | Language::UnOp(_)
+                    )
+                })
+                .count();
+            assert_eq!(cost.instructions, num_instructions);
+        }
+    }
+
+    #[test]
+    fn extraction_is_deterministic() {
+        let run = || {
+            let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+            let id = egraph.add_expr(
+                &RecExpr::from_str("(binop sub 8 (var x 8) (binop and 8 (var x 8) (var y 8)))")
+                    .unwrap(),
+            );
+            let runner = Runner::default()
+                .with_egraph(egraph)
+                .with_iter_limit(4)
+                .run(&vec![
+                    introduce_hole_var(),
+                    fuse_op(),
+                    introduce_hole_op_both(),
+                    introduce_hole_op_left(),
+                    introduce_hole_op_right(),
+                    simplify_concat(),
+                    canonicalize(),
+                ]);
+            let beam = BeamExtractor::new(&runner.egraph, 3)
+                .find_beam(id)
+                .iter()
+                .map(|(_, expr)| expr.to_string())
+                .collect::<Vec<_>>();
+            let instrs = find_isa_instructions(&runner.egraph)
+                .unwrap()
+                .iter()
+                .map(|(_, expr)| expr.to_string())
+                .collect::<Vec<_>>();
+            (beam, instrs)
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test_log::test]
+    fn test_extract_random() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+
+        // https://github.com/mangpo/chlorophyll/tree/master/examples/bithack
+        // Bithack 1.
+        let bithack1_id = egraph.add_expr(
+            &RecExpr::from_str(
+                "
+(binop sub 8 (var x 8) (binop and 8 (var x 8) (var y 8)))
+",
+            )
+            .unwrap(),
+        );
+
+        extract_random(&egraph, bithack1_id, &mut rng(0));
+    }
+
+    #[test_log::test]
+    fn test_sample_instr_in_program() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+
+        // https://github.com/mangpo/chlorophyll/tree/master/examples/bithack
+        // Bithack 1.
+        let _bithack1_id = egraph.add_expr(
+            &RecExpr::from_str(
+                "
+(binop sub 8 (var x 8) (binop and 8 (var x 8) (var y 8)))
+",
+            )
+            .unwrap(),
+        );
+        // Bithack 2.
+        let _bithack2_id = egraph.add_expr(
+            &RecExpr::from_str(
+                "
+(unop not 8 (binop sub 8 (var x 8) (var y 8)))
+",
+            )
+            .unwrap(),
+        );
+        // Bithack 3.
+        let _bithack3_id = egraph.add_expr(
+            &RecExpr::from_str(
+                "
+(binop xor 8 (binop xor 8 (var x 8) (var y 8)) (binop and 8 (var x 8) (var y 8)))
+",
+            )
+            .unwrap(),
+        );
+
+        let runner = Runner::default().with_egraph(egraph).run(&vec![
+            introduce_hole_var(),
+            fuse_op(),
+            introduce_hole_op_both(),
+            introduce_hole_op_left(),
+            introduce_hole_op_right(),
+            simplify_concat(),
+            unary0(),
+            unary1(),
+            canonicalize(),
+        ]);
+
+        let and_instr =
+            RecExpr::from_str("(instr (binop-ast and 8 (hole 8) (hole 8)) (canonical-args 0 1))")
+                .unwrap();
+        let sub_instr =
+            RecExpr::from_str("(instr (binop-ast sub 8 (hole 8) (hole 8)) (canonical-args 0 1))")
+                .unwrap();
+        println!(
+            "And appears: {} times",
+            sample_instr_in_program(&runner.egraph, &and_instr, _bithack1_id, 1000, 0)
+        );
+        println!(
+            "Sub appears: {} times",
+            sample_instr_in_program(&runner.egraph, &sub_instr, _bithack1_id, 1000, 0)
+        );
+    }
+
+    #[test_log::test]
+    fn explore_many_programs() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let mut ids = Vec::new();
+        for (_, program) in example_programs::all_programs() {
+            ids.push(egraph.add_expr(&program));
+        }
+
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(300)
+            .run(&vec![
+                introduce_hole_var(),
+                fuse_op(),
+                introduce_hole_op_both(),
+                introduce_hole_op_left(),
+                introduce_hole_op_right(),
+                simplify_concat(),
+                unary0(),
+                unary1(),
+                canonicalize(),
+            ]);
+
+        runner.print_report();
+
+        // Ensure everything gets canonicalized. If you get errors in
+        // extract_ast, it may be because not everything got canonicalized.
+        let runner = Runner::default()
+            .with_egraph(runner.egraph)
+            .with_node_limit(1000000)
+            .run(&vec![canonicalize()]);
+
+        runner.print_report();
+
+        let potential_isa_instrs: Vec<_> = find_isa_instructions(&runner.egraph).unwrap();
+        println!("{} potential ISA instructions.", potential_isa_instrs.len());
+
+        // Each ID is one of the input programs; each instruction is a potential instruction.
+        // histogram[id][instr] = number of times instr appears in a random impl of the program.
+        let mut histogram: Vec<Vec<usize>> = vec![vec![0; potential_isa_instrs.len()]; ids.len()];
+
+        const NUM_SAMPLES: usize = 10;
+
+        let mut rng = rng(0);
+        for (id_i, id) in ids.iter().enumerate() {
+            // TODO this is a hack.
+            if extract_random(&runner.egraph, *id, &mut rng)
+                .as_ref()
+                .is_empty()
+            {
+                continue;
+            }
+            for _ in 0..NUM_SAMPLES {
+                let mut tmp_egr: EGraph<Language, LanguageAnalysis> = EGraph::default();
+                let mut random_impl = extract_random(&runner.egraph, *id, &mut rng);
+                while random_impl.as_ref().is_empty() {
+                    random_impl = extract_random(&runner.egraph, *id, &mut rng);
+                }
+                tmp_egr.add_expr(&random_impl);
+                tmp_egr.rebuild();
+
+                for (instr_i, (_, instr)) in potential_isa_instrs.iter().enumerate() {
+                    //println!("is\n{}\nin\n{}", instr.pretty(80), random_impl.pretty(80));
+                    if matches!(tmp_egr.lookup_expr(&instr), Some(_)) {
+                        histogram[id_i][instr_i] = histogram[id_i][instr_i] + 1;
+                    }
+                }
+            }
+        }
+
+        let mut histogram: Vec<_> = potential_isa_instrs
+            .iter()
+            .enumerate()
+            .map(|(instr_i, (_, instr))| {
+                let count = histogram
+                    .iter()
+                    .map(|l| l[instr_i])
+                    .reduce(|a, b| a + b)
+                    .unwrap();
+                (instr, count)
+            })
+            .collect();
+        histogram.sort_by_key(|v| v.1);
+        println!("Top 25 most frequent instructions:");
+        for (instr, count) in &histogram[histogram.len() - 25..] {
+            println!(
+                "Instruction\n{}\nappears {} times.",
+                instr.pretty(1000),
+                count
+            );
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! A C API, declared in `include/lakeroad.h`.
+//!
+//! Programs, explorations, and synthesized ISAs are opaque handles which the
+//! caller frees with the matching `_free` function. Functions which can fail
+//! return null or a negative number and record a message, which
+//! [`lakeroad_last_error`] returns until the next failing call on the same
+//! thread. Strings returned to the caller are freed with
+//! [`lakeroad_string_free`].
+//!
+//! Panics are caught at the boundary and reported as errors.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int},
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+    str::FromStr,
+};
+
+use egg::RecExpr;
+
+use crate::{
+    error::LakeroadError,
+    language::Language,
+    program_set::ProgramSet,
+    synthesizer::{Exploration, SynthesisResult, Synthesizer},
+};
+
+pub struct LakeroadPrograms(ProgramSet);
+pub struct LakeroadExploration(Exploration);
+pub struct LakeroadIsa(SynthesisResult);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "\\0")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, turning errors and panics into `None` and the last error.
+fn guard<T>(f: impl FnOnce() -> Result<T, LakeroadError>) -> Option<T> {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => Some(value),
+        Ok(Err(error)) => {
+            set_last_error(error.to_string());
+            None
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(format!("internal error: {}", message));
+            None
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char, what: &str) -> Result<&'a str, LakeroadError> {
+    if s.is_null() {
+        return Err(LakeroadError::Parse(format!("{} is null", what)));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| LakeroadError::Parse(format!("{} is not UTF-8", what)))
+}
+
+unsafe fn handle<'a, T>(handle: *const T, what: &str) -> Result<&'a T, LakeroadError> {
+    handle
+        .as_ref()
+        .ok_or_else(|| LakeroadError::Parse(format!("{} is null", what)))
+}
+
+fn to_c_string(s: String) -> *mut c_char {
+    CString::new(s).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// The message of the last failure on this thread, or null. The string is
+/// owned by the library and valid until the next failing call.
+#[no_mangle]
+pub extern "C" fn lakeroad_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// # Safety
+///
+/// `s` must be null or a string returned by this library, not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn lakeroad_programs_new() -> *mut LakeroadPrograms {
+    Box::into_raw(Box::new(LakeroadPrograms(ProgramSet::new())))
+}
+
+/// Adds the program `expr`, an s-expression in the egg syntax, named `name`.
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+///
+/// `programs` must be a live handle, and `name` and `expr` must be
+/// nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_programs_add(
+    programs: *mut LakeroadPrograms,
+    name: *const c_char,
+    expr: *const c_char,
+    weight: f64,
+) -> c_int {
+    guard(|| {
+        let programs = programs
+            .as_mut()
+            .ok_or_else(|| LakeroadError::Parse("programs is null".to_string()))?;
+        let name = str_arg(name, "name")?;
+        let expr = RecExpr::<Language>::from_str(str_arg(expr, "expr")?)
+            .map_err(|e| LakeroadError::Parse(e.to_string()))?;
+        programs.0.add_weighted(name, expr, weight)?;
+        Ok(0)
+    })
+    .unwrap_or(-1)
+}
+
+/// # Safety
+///
+/// `programs` must be null or a live handle, which this frees.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_programs_free(programs: *mut LakeroadPrograms) {
+    if !programs.is_null() {
+        drop(Box::from_raw(programs));
+    }
+}
+
+fn synthesizer(programs: &LakeroadPrograms, iter_limit: usize, node_limit: usize) -> Synthesizer {
+    Synthesizer::new()
+        .add_programs(&programs.0)
+        .with_iter_limit(iter_limit)
+        .with_node_limit(node_limit)
+}
+
+/// Rewrites the programs and extracts candidate instructions, or returns
+/// null on failure.
+///
+/// # Safety
+///
+/// `programs` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_explore(
+    programs: *const LakeroadPrograms,
+    iter_limit: usize,
+    node_limit: usize,
+) -> *mut LakeroadExploration {
+    guard(|| {
+        let programs = handle(programs, "programs")?;
+        let exploration = synthesizer(programs, iter_limit, node_limit).explore()?;
+        Ok(Box::into_raw(Box::new(LakeroadExploration(exploration))))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `exploration` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_exploration_num_candidates(
+    exploration: *const LakeroadExploration,
+) -> usize {
+    exploration
+        .as_ref()
+        .map_or(0, |exploration| exploration.0.candidates.len())
+}
+
+/// The `index`th candidate as an s-expression, or null if there is no such
+/// candidate. Free the result with [`lakeroad_string_free`].
+///
+/// # Safety
+///
+/// `exploration` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_exploration_candidate(
+    exploration: *const LakeroadExploration,
+    index: usize,
+) -> *mut c_char {
+    guard(|| {
+        let exploration = handle(exploration, "exploration")?;
+        let (_, instr) = exploration
+            .0
+            .candidates
+            .get(index)
+            .ok_or_else(|| LakeroadError::Parse(format!("no candidate {}", index)))?;
+        Ok(to_c_string(instr.to_string()))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `exploration` must be null or a live handle, which this frees.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_exploration_free(exploration: *mut LakeroadExploration) {
+    if !exploration.is_null() {
+        drop(Box::from_raw(exploration));
+    }
+}
+
+/// Runs the whole pipeline, accepting every candidate, and returns the ISA
+/// of at most `max_instructions` instructions, or null on failure.
+///
+/// # Safety
+///
+/// `programs` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_synthesize(
+    programs: *const LakeroadPrograms,
+    iter_limit: usize,
+    node_limit: usize,
+    max_instructions: usize,
+) -> *mut LakeroadIsa {
+    guard(|| {
+        let programs = handle(programs, "programs")?;
+        let result = synthesizer(programs, iter_limit, node_limit)
+            .with_max_instructions(max_instructions)
+            .run()?;
+        Ok(Box::into_raw(Box::new(LakeroadIsa(result))))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `isa` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_isa_num_instructions(isa: *const LakeroadIsa) -> usize {
+    isa.as_ref().map_or(0, |isa| isa.0.instructions.len())
+}
+
+/// The `index`th instruction as an s-expression, or null if there is no such
+/// instruction. Free the result with [`lakeroad_string_free`].
+///
+/// # Safety
+///
+/// `isa` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_isa_instruction(
+    isa: *const LakeroadIsa,
+    index: usize,
+) -> *mut c_char {
+    guard(|| {
+        let isa = handle(isa, "isa")?;
+        let instr = isa
+            .0
+            .instructions
+            .get(index)
+            .ok_or_else(|| LakeroadError::Parse(format!("no instruction {}", index)))?;
+        Ok(to_c_string(instr.to_string()))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// The ISA's score under the cost model.
+///
+/// # Safety
+///
+/// `isa` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_isa_score(isa: *const LakeroadIsa) -> f64 {
+    isa.as_ref().map_or(f64::NAN, |isa| isa.0.report.score)
+}
+
+/// # Safety
+///
+/// `isa` must be null or a live handle, which this frees.
+#[no_mangle]
+pub unsafe extern "C" fn lakeroad_isa_free(isa: *mut LakeroadIsa) {
+    if !isa.is_null() {
+        drop(Box::from_raw(isa));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synthesize_through_the_c_api() {
+        unsafe {
+            let programs = lakeroad_programs_new();
+            let name = CString::new("and").unwrap();
+            let expr = CString::new("(binop and 8 (var x 8) (var y 8))").unwrap();
+            assert_eq!(
+                lakeroad_programs_add(programs, name.as_ptr(), expr.as_ptr(), 1.0),
+                0
+            );
+            let bad = CString::new("(binop and").unwrap();
+            assert_eq!(
+                lakeroad_programs_add(programs, name.as_ptr(), bad.as_ptr(), 1.0),
+                -1
+            );
+            assert!(!lakeroad_last_error().is_null());
+
+            let exploration = lakeroad_explore(programs, 5, 10_000);
+            assert!(lakeroad_exploration_num_candidates(exploration) > 0);
+            let candidate = lakeroad_exploration_candidate(exploration, 0);
+            assert!(CStr::from_ptr(candidate)
+                .to_str()
+                .unwrap()
+                .starts_with("(instr"));
+            lakeroad_string_free(candidate);
+            assert!(lakeroad_exploration_candidate(exploration, usize::MAX).is_null());
+            lakeroad_exploration_free(exploration);
+
+            let isa = lakeroad_synthesize(programs, 5, 10_000, 1);
+            assert_eq!(lakeroad_isa_num_instructions(isa), 1);
+            lakeroad_isa_free(isa);
+            lakeroad_programs_free(programs);
+        }
+    }
+}
@@ -0,0 +1,384 @@
+//! A small C-like surface syntax. A program is a list of variable
+//! declarations followed by a single expression:
+//!
+//! ```text
+//! i8 x, y;
+//! (x | y) - ((x ^ y) >> 1)
+//! ```
+//!
+//! Declarations give each variable a width and a signedness (`uN` or `iN`).
+//! As in C, an operation is unsigned if either operand is unsigned, and `>>`
+//! is an arithmetic shift on signed values and a logical shift otherwise.
+//! Literals take the width of the expression they're used in. All operands
+//! of an operator must have the same width.
+//!
+//! Supported operators are `~`, unary `-`, `+`, `-`, `>>`, `==`, `&`, `^`,
+//! and `|`, with C's precedence. Operators without a counterpart in the
+//! language (e.g. `*` and `<<`) are rejected.
+
+use std::{collections::HashMap, fmt::Display};
+
+use egg::RecExpr;
+
+use crate::{
+    frontends::{
+        elaborate::{elaborate_sexp, ElaborationError},
+        sexp::Sexp,
+    },
+    language::Language,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CFrontendError {
+    UnexpectedToken {
+        offset: usize,
+        found: String,
+    },
+    UnexpectedEof,
+    UndeclaredVariable {
+        offset: usize,
+        name: String,
+    },
+    /// A C operator the language can't express.
+    UnsupportedOperator {
+        offset: usize,
+        op: String,
+    },
+    Elaboration(ElaborationError),
+}
+impl Display for CFrontendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CFrontendError::UnexpectedToken { offset, found } => {
+                write!(f, "unexpected {:?} at offset {}", found, offset)
+            }
+            CFrontendError::UnexpectedEof => write!(f, "unexpected end of input"),
+            CFrontendError::UndeclaredVariable { offset, name } => {
+                write!(f, "undeclared variable {} at offset {}", name, offset)
+            }
+            CFrontendError::UnsupportedOperator { offset, op } => {
+                write!(f, "unsupported operator {} at offset {}", op, offset)
+            }
+            CFrontendError::Elaboration(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl std::error::Error for CFrontendError {}
+impl From<ElaborationError> for CFrontendError {
+    fn from(e: ElaborationError) -> Self {
+        CFrontendError::Elaboration(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Punct(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "{}", s),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Punct(p) => write!(f, "{}", p),
+        }
+    }
+}
+
+/// Longest first, so that e.g. `>>` isn't read as two `>`s.
+const PUNCTUATION: &[&str] = &[
+    "<<", ">>", "==", "!=", "<=", ">=", "&&", "||", "(", ")", ",", ";", "~", "-", "+", "*", "/",
+    "%", "&", "|", "^", "!", "<", ">",
+];
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, CFrontendError> {
+    let mut out = vec![];
+    let mut pos = 0;
+    while pos < input.len() {
+        let rest = &input[pos..];
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+        } else if rest.starts_with("//") {
+            pos += rest.find('\n').unwrap_or(rest.len());
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            let token = if c.is_ascii_digit() {
+                let value = match word.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => word.parse(),
+                };
+                Token::Number(value.map_err(|_| CFrontendError::UnexpectedToken {
+                    offset: pos,
+                    found: word.to_string(),
+                })?)
+            } else {
+                Token::Ident(word.to_string())
+            };
+            out.push((pos, token));
+            pos += len;
+        } else {
+            let punct = PUNCTUATION
+                .iter()
+                .find(|p| rest.starts_with(**p))
+                .ok_or_else(|| CFrontendError::UnexpectedToken {
+                    offset: pos,
+                    found: c.to_string(),
+                })?;
+            out.push((pos, Token::Punct(punct)));
+            pos += punct.len();
+        }
+    }
+    Ok(out)
+}
+
+/// Parses a type name like `u8` or `i16` into its width and signedness.
+fn parse_type(name: &str) -> Option<(usize, bool)> {
+    let signed = match name.chars().next()? {
+        'u' => false,
+        'i' => true,
+        _ => return None,
+    };
+    match name[1..].parse() {
+        Ok(width) if width > 0 => Some((width, signed)),
+        _ => None,
+    }
+}
+
+/// A parsed subexpression and whether it's signed. Literals have no
+/// signedness of their own.
+type Typed = (Sexp, Option<bool>);
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// Declared widths and signedness.
+    vars: HashMap<String, (usize, bool)>,
+}
+
+fn atom(s: &str) -> Sexp {
+    Sexp::Atom(s.to_string())
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(0, |(offset, _)| *offset)
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), CFrontendError> {
+        let out = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or(CFrontendError::UnexpectedEof)?;
+        self.pos += 1;
+        Ok(out)
+    }
+
+    fn unexpected(offset: usize, token: &Token) -> CFrontendError {
+        CFrontendError::UnexpectedToken {
+            offset,
+            found: token.to_string(),
+        }
+    }
+
+    fn expect(&mut self, punct: &'static str) -> Result<(), CFrontendError> {
+        match self.next()? {
+            (_, Token::Punct(p)) if p == punct => Ok(()),
+            (offset, token) => Err(Self::unexpected(offset, &token)),
+        }
+    }
+
+    /// Parses leading declarations, e.g. `u8 x, y;`.
+    fn declarations(&mut self) -> Result<(), CFrontendError> {
+        while let Some(Token::Ident(name)) = self.peek() {
+            let ty = match parse_type(name) {
+                // A type followed by an identifier starts a declaration;
+                // anything else is the start of the expression.
+                Some(ty) if matches!(self.tokens.get(self.pos + 1), Some((_, Token::Ident(_)))) => {
+                    ty
+                }
+                _ => return Ok(()),
+            };
+            self.pos += 1;
+            loop {
+                match self.next()? {
+                    (_, Token::Ident(var)) => {
+                        self.vars.insert(var, ty);
+                    }
+                    (offset, token) => return Err(Self::unexpected(offset, &token)),
+                }
+                match self.next()? {
+                    (_, Token::Punct(",")) => (),
+                    (_, Token::Punct(";")) => break,
+                    (offset, token) => return Err(Self::unexpected(offset, &token)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The binding power and language op of a binary operator, following
+    /// C's precedence.
+    fn binary_op(token: &Token) -> Option<(u8, &'static str)> {
+        match token {
+            Token::Punct("|") => Some((1, "or")),
+            Token::Punct("^") => Some((2, "xor")),
+            Token::Punct("&") => Some((3, "and")),
+            Token::Punct("==") => Some((4, "eq")),
+            Token::Punct(">>") => Some((5, "shr")),
+            Token::Punct("+") => Some((6, "add")),
+            Token::Punct("-") => Some((6, "sub")),
+            Token::Punct("<<" | "*" | "/" | "%" | "!=" | "<" | ">" | "<=" | ">=" | "&&" | "||") => {
+                Some((0, ""))
+            }
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self, min_power: u8) -> Result<Typed, CFrontendError> {
+        let (mut lhs, mut lhs_signed) = self.unary()?;
+        while let Some(token) = self.peek().cloned() {
+            let (power, op) = match Self::binary_op(&token) {
+                Some((power, op)) if power >= min_power => (power, op),
+                _ => break,
+            };
+            let offset = self.offset();
+            if op.is_empty() {
+                return Err(CFrontendError::UnsupportedOperator {
+                    offset,
+                    op: token.to_string(),
+                });
+            }
+            self.pos += 1;
+            let (rhs, rhs_signed) = self.expr(power + 1)?;
+            // The usual arithmetic conversions: unsigned wins.
+            let signed = match (lhs_signed, rhs_signed) {
+                (Some(a), Some(b)) => Some(a && b),
+                (a, b) => a.or(b),
+            };
+            let op = match op {
+                "shr" if lhs_signed == Some(true) => "asr",
+                "shr" => "lsr",
+                op => op,
+            };
+            lhs = Sexp::List(vec![atom("binop"), atom(op), lhs, rhs]);
+            lhs_signed = if op == "asr" || op == "lsr" {
+                // Shifts take the type of their left operand.
+                lhs_signed
+            } else {
+                signed
+            };
+        }
+        Ok((lhs, lhs_signed))
+    }
+
+    fn unary(&mut self) -> Result<Typed, CFrontendError> {
+        let (offset, token) = self.next()?;
+        match token {
+            Token::Punct("~") | Token::Punct("-") => {
+                let op = if token == Token::Punct("~") {
+                    "not"
+                } else {
+                    "neg"
+                };
+                let (arg, signed) = self.unary()?;
+                Ok((Sexp::List(vec![atom("unop"), atom(op), arg]), signed))
+            }
+            Token::Punct("(") => {
+                let out = self.expr(1)?;
+                self.expect(")")?;
+                Ok(out)
+            }
+            Token::Number(n) => Ok((Sexp::List(vec![atom("const"), atom(&n.to_string())]), None)),
+            Token::Ident(name) => match self.vars.get(&name) {
+                Some((width, signed)) => Ok((
+                    Sexp::List(vec![atom("var"), atom(&name), atom(&width.to_string())]),
+                    Some(*signed),
+                )),
+                None => Err(CFrontendError::UndeclaredVariable { offset, name }),
+            },
+            Token::Punct("!") => Err(CFrontendError::UnsupportedOperator {
+                offset,
+                op: token.to_string(),
+            }),
+            token => Err(Self::unexpected(offset, &token)),
+        }
+    }
+}
+
+/// Parses a C-like program into the language.
+pub fn parse_c(input: &str) -> Result<RecExpr<Language>, CFrontendError> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+        vars: HashMap::default(),
+    };
+    parser.declarations()?;
+    let (sexp, _) = parser.expr(1)?;
+    // Allow a trailing semicolon.
+    if parser.peek() == Some(&Token::Punct(";")) {
+        parser.pos += 1;
+    }
+    if let Some((offset, token)) = parser.tokens.get(parser.pos) {
+        return Err(Parser::unexpected(*offset, token));
+    }
+    Ok(elaborate_sexp(&sexp)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::example_programs::all_programs;
+
+    #[test]
+    fn parse_ceil_avg() {
+        let expr = parse_c("i8 x, y; (x | y) - ((x ^ y) >> 1)").unwrap();
+        assert_eq!(
+            expr.to_string(),
+            all_programs()["bithack_ceil_avg"].to_string()
+        );
+    }
+
+    #[test]
+    fn precedence_and_signedness() {
+        // `>>` is logical on unsigned values, and binds tighter than `&`.
+        let expr = parse_c("u8 x; i8 y;\n x & y >> 1").unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "(binop and 8 (var x 8) (binop asr 8 (var y 8) (const 1 8)))"
+        );
+        let expr = parse_c("u8 x; i8 y;\n (x + y) >> 0x1").unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "(binop lsr 8 (binop add 8 (var x 8) (var y 8)) (const 1 8))"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            parse_c("u8 x; x * 2"),
+            Err(CFrontendError::UnsupportedOperator { offset: 8, .. })
+        ));
+        assert!(matches!(
+            parse_c("u8 x; x + y"),
+            Err(CFrontendError::UndeclaredVariable { .. })
+        ));
+        assert!(matches!(
+            parse_c("u8 x; u4 y; x + y"),
+            Err(CFrontendError::Elaboration(
+                ElaborationError::WidthMismatch { .. }
+            ))
+        ));
+    }
+}
@@ -0,0 +1,550 @@
+//! Bitwidth elaboration. Lets programs be written without a bitwidth on every
+//! operator, e.g.
+//!
+//! ```text
+//! (binop sub (binop or (var x 8) (var y)) (binop asr (var x) (const 1)))
+//! ```
+//!
+//! Widths may be given on any `var`, `const`, `unop`, or `binop`, or left
+//! out. Missing widths are inferred from the operands (all operands of an
+//! operator share its width) and from the other uses of the same variable,
+//! and then inserted, producing a program in the fully-annotated form the
+//! egraph expects. Fully-annotated programs elaborate to themselves.
+//!
+//! Values of different widths are mixed with `(zext w arg)` and `(sext w
+//! arg)`, which extend `arg` to `w` bits, e.g. `(binop add (var x 16) (zext
+//! (var y 8)))`; `w` may be left out too, but `arg`'s width must be known
+//! from `arg` itself. They're lowered to a `cat` of zeros, and to an `asr` of
+//! `arg` shifted to the top, since the egraph has no extensions of its own.
+//! [`elaborate_with`] can also insert them, extending an operand narrower
+//! than its operator as an [`Extension`] says. A shift's amount may be
+//! narrower than the value it shifts either way, and is left as it is, and
+//! an `add`, `sub` or `mul` with a width of its own may have operands of
+//! another, e.g. `(binop mul 16 (var x 8) (var y 8))`, which are resized to
+//! it rather than extended.
+
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use egg::{Id, RecExpr};
+
+use crate::{
+    frontends::sexp::{self, Sexp, SexpError},
+    language::{Language, Op},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElaborationError {
+    Parse(SexpError),
+    /// `form` isn't a `var`, `const`, `unop`, `binop`, `zext`, or `sext` of
+    /// the right shape.
+    Malformed {
+        form: String,
+    },
+    UnknownOp {
+        op: String,
+    },
+    /// Nothing in `form` determines its width, e.g. `(binop and (const 1)
+    /// (const 2))`.
+    CannotInfer {
+        form: String,
+    },
+    WidthMismatch {
+        form: String,
+        expected: usize,
+        found: usize,
+    },
+}
+impl Display for ElaborationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ElaborationError::Parse(e) => write!(f, "{}", e),
+            ElaborationError::Malformed { form } => write!(f, "malformed expression {}", form),
+            ElaborationError::UnknownOp { op } => write!(f, "unknown operator {}", op),
+            ElaborationError::CannotInfer { form } => {
+                write!(f, "cannot infer the bitwidth of {}", form)
+            }
+            ElaborationError::WidthMismatch {
+                form,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} expected bitwidth {}, found bitwidth {}",
+                form, expected, found
+            ),
+        }
+    }
+}
+impl std::error::Error for ElaborationError {}
+impl From<SexpError> for ElaborationError {
+    fn from(e: SexpError) -> Self {
+        ElaborationError::Parse(e)
+    }
+}
+
+/// What becomes of an operand narrower than its operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Extension {
+    /// It's a [`ElaborationError::WidthMismatch`], unless the program
+    /// extends it itself.
+    #[default]
+    Require,
+    /// It's zero-extended.
+    Zero,
+    /// It's sign-extended.
+    Sign,
+}
+
+enum Form<'a> {
+    Var {
+        name: &'a str,
+        width: Option<usize>,
+    },
+    Const {
+        value: i64,
+        width: Option<usize>,
+    },
+    UnOp {
+        op: Op,
+        width: Option<usize>,
+        arg: &'a Sexp,
+    },
+    BinOp {
+        op: Op,
+        width: Option<usize>,
+        a: &'a Sexp,
+        b: &'a Sexp,
+    },
+    /// `zext` or `sext`.
+    Extend {
+        signed: bool,
+        width: Option<usize>,
+        arg: &'a Sexp,
+    },
+}
+
+impl<'a> Form<'a> {
+    fn of(sexp: &'a Sexp) -> Result<Self, ElaborationError> {
+        let malformed = || ElaborationError::Malformed {
+            form: sexp.to_string(),
+        };
+        let atom = |s: &'a Sexp| s.as_atom().ok_or_else(malformed);
+        let width = |s: &Sexp| match s.as_atom().map(usize::from_str) {
+            Some(Ok(w)) if w > 0 => Ok(Some(w)),
+            _ => Err(malformed()),
+        };
+        let op = |s: &Sexp| {
+            let op = atom(s)?;
+            Op::from_str(op).map_err(|()| ElaborationError::UnknownOp { op: op.to_string() })
+        };
+        let list = sexp.as_list().ok_or_else(malformed)?;
+        let (head, rest) = list.split_first().ok_or_else(malformed)?;
+        match (atom(head)?, rest) {
+            ("var", [name]) => Ok(Form::Var {
+                name: atom(name)?,
+                width: None,
+            }),
+            ("var", [name, w]) => Ok(Form::Var {
+                name: atom(name)?,
+                width: width(w)?,
+            }),
+            ("const", [value]) | ("const", [value, _]) => Ok(Form::Const {
+                value: atom(value)?.parse().map_err(|_| malformed())?,
+                width: match rest {
+                    [_, w] => width(w)?,
+                    _ => None,
+                },
+            }),
+            ("unop", [o, arg]) => Ok(Form::UnOp {
+                op: op(o)?,
+                width: None,
+                arg,
+            }),
+            ("unop", [o, w, arg]) => Ok(Form::UnOp {
+                op: op(o)?,
+                width: width(w)?,
+                arg,
+            }),
+            ("binop", [o, a, b]) => Ok(Form::BinOp {
+                op: op(o)?,
+                width: None,
+                a,
+                b,
+            }),
+            ("binop", [o, w, a, b]) => Ok(Form::BinOp {
+                op: op(o)?,
+                width: width(w)?,
+                a,
+                b,
+            }),
+            (head @ ("zext" | "sext"), [arg]) => Ok(Form::Extend {
+                signed: head == "sext",
+                width: None,
+                arg,
+            }),
+            (head @ ("zext" | "sext"), [w, arg]) => Ok(Form::Extend {
+                signed: head == "sext",
+                width: width(w)?,
+                arg,
+            }),
+            _ => Err(malformed()),
+        }
+    }
+
+    fn width(&self) -> Option<usize> {
+        match self {
+            Form::Var { width, .. }
+            | Form::Const { width, .. }
+            | Form::UnOp { width, .. }
+            | Form::BinOp { width, .. }
+            | Form::Extend { width, .. } => *width,
+        }
+    }
+}
+
+/// Records the widths variables are annotated with.
+fn collect_var_widths(
+    sexp: &Sexp,
+    env: &mut HashMap<String, usize>,
+) -> Result<(), ElaborationError> {
+    match Form::of(sexp)? {
+        Form::Var {
+            name,
+            width: Some(width),
+        } => match env.insert(name.to_string(), width) {
+            Some(other) if other != width => Err(ElaborationError::WidthMismatch {
+                form: sexp.to_string(),
+                expected: other,
+                found: width,
+            }),
+            _ => Ok(()),
+        },
+        Form::Var { .. } | Form::Const { .. } => Ok(()),
+        Form::UnOp { arg, .. } | Form::Extend { arg, .. } => collect_var_widths(arg, env),
+        Form::BinOp { a, b, .. } => {
+            collect_var_widths(a, env)?;
+            collect_var_widths(b, env)
+        }
+    }
+}
+
+/// The width of `sexp` implied by its own annotations and those of its
+/// subexpressions, if any. Unless `extension` is [`Extension::Require`], an
+/// operator without a width is as wide as its widest operand.
+fn infer(
+    sexp: &Sexp,
+    env: &HashMap<String, usize>,
+    extension: Extension,
+) -> Result<Option<usize>, ElaborationError> {
+    let form = Form::of(sexp)?;
+    let annotated = form.width().is_some();
+    let extends =
+        extension != Extension::Require && matches!(form, Form::UnOp { .. } | Form::BinOp { .. });
+    let mut widths = vec![form.width()];
+    match form {
+        Form::Var { name, .. } => widths.push(env.get(name).cloned()),
+        Form::Const { .. } => (),
+        Form::UnOp { arg, .. } => widths.push(infer(arg, env, extension)?),
+        Form::BinOp { op, a, b, .. } => {
+            let (a, b) = (infer(a, env, extension)?, infer(b, env, extension)?);
+            // A shift's amount doesn't determine its width, and nor do an
+            // annotated resized op's operands.
+            if !(annotated && op.is_resizing()) {
+                widths.push(a);
+                if !op.is_shift() {
+                    widths.push(b);
+                }
+            }
+        }
+        Form::Extend { arg, .. } => {
+            infer(arg, env, extension)?;
+        }
+    }
+    let mut out: Option<usize> = None;
+    for width in widths.into_iter().flatten() {
+        match out {
+            Some(expected) if expected != width => {
+                if !extends || (annotated && width > expected) {
+                    return Err(ElaborationError::WidthMismatch {
+                        form: sexp.to_string(),
+                        expected,
+                        found: width,
+                    });
+                }
+                out = Some(expected.max(width));
+            }
+            _ => out = Some(width),
+        }
+    }
+    Ok(out)
+}
+
+/// Emits `sexp` into `expr` at the given width.
+fn emit(
+    sexp: &Sexp,
+    width: usize,
+    env: &mut HashMap<String, usize>,
+    expr: &mut RecExpr<Language>,
+    extension: Extension,
+) -> Result<Id, ElaborationError> {
+    let form = Form::of(sexp)?;
+    let mismatch = |found| ElaborationError::WidthMismatch {
+        form: sexp.to_string(),
+        expected: width,
+        found,
+    };
+    let annotated = form.width().is_some();
+    if let Some(found) = form.width().filter(|w| *w != width) {
+        return Err(mismatch(found));
+    }
+    if let Form::Extend { signed, arg, .. } = form {
+        let from = infer(arg, env, extension)?.ok_or_else(|| ElaborationError::CannotInfer {
+            form: arg.to_string(),
+        })?;
+        if from > width {
+            return Err(mismatch(from));
+        }
+        let arg_id = emit(arg, from, env, expr, extension)?;
+        return Ok(extend(expr, arg_id, signed, from, width));
+    }
+    let bw_id = expr.add(Language::Num(width as i64));
+    Ok(match form {
+        Form::Var { name, .. } => {
+            // Unannotated variables take the width of their first use.
+            let found = *env.entry(name.to_string()).or_insert(width);
+            if found != width {
+                return Err(mismatch(found));
+            }
+            let name_id = expr.add(Language::String(name.to_string()));
+            expr.add(Language::Var([name_id, bw_id]))
+        }
+        Form::Const { value, .. } => {
+            let value_id = expr.add(Language::Num(value));
+            expr.add(Language::Const([value_id, bw_id]))
+        }
+        Form::UnOp { op, arg, .. } => {
+            let op_id = expr.add(Language::Op(op));
+            let arg_id = emit_operand(arg, width, env, expr, extension)?;
+            expr.add(Language::UnOp([op_id, bw_id, arg_id]))
+        }
+        Form::BinOp { op, a, b, .. } => {
+            let op_id = expr.add(Language::Op(op.clone()));
+            // An annotated resized op's operands keep their own width if
+            // they're wider, or narrower and not to be extended.
+            let resized = match (infer(a, env, extension)?, infer(b, env, extension)?) {
+                (a, b) if annotated && op.is_resizing() => a.max(b).filter(|from| {
+                    *from > width || (*from < width && extension == Extension::Require)
+                }),
+                _ => None,
+            };
+            let (a_id, b_id) = match resized {
+                Some(from) => (
+                    emit_operand(a, from, env, expr, extension)?,
+                    emit_operand(b, from, env, expr, extension)?,
+                ),
+                None => (
+                    emit_operand(a, width, env, expr, extension)?,
+                    match infer(b, env, extension)? {
+                        Some(amount) if op.is_shift() && amount < width => {
+                            emit(b, amount, env, expr, extension)?
+                        }
+                        _ => emit_operand(b, width, env, expr, extension)?,
+                    },
+                ),
+            };
+            expr.add(Language::BinOp([op_id, bw_id, a_id, b_id]))
+        }
+        Form::Extend { .. } => unreachable!(),
+    })
+}
+
+/// Emits an operand of a `width`-bit operator, extending it if it's
+/// narrower and `extension` says how.
+fn emit_operand(
+    sexp: &Sexp,
+    width: usize,
+    env: &mut HashMap<String, usize>,
+    expr: &mut RecExpr<Language>,
+    extension: Extension,
+) -> Result<Id, ElaborationError> {
+    match infer(sexp, env, extension)? {
+        Some(from) if from < width && extension != Extension::Require => {
+            let id = emit(sexp, from, env, expr, extension)?;
+            Ok(extend(expr, id, extension == Extension::Sign, from, width))
+        }
+        _ => emit(sexp, width, env, expr, extension),
+    }
+}
+
+/// Adds `id`, `from` bits wide, zero- or sign-extended to `to` bits.
+fn extend(expr: &mut RecExpr<Language>, id: Id, signed: bool, from: usize, to: usize) -> Id {
+    if from == to {
+        return id;
+    }
+    let num = |expr: &mut RecExpr<Language>, n: usize| expr.add(Language::Num(n as i64));
+    let zero = num(expr, 0);
+    let pad = num(expr, to - from);
+    let zeros = expr.add(Language::Const([zero, pad]));
+    if !signed {
+        return expr.add(Language::Cat([zeros, id]));
+    }
+    // Shifted to the top, and arithmetically back down.
+    let top = expr.add(Language::Cat([id, zeros]));
+    let asr = expr.add(Language::Op(Op::Asr));
+    let bw = num(expr, to);
+    let amount = expr.add(Language::Const([pad, bw]));
+    expr.add(Language::BinOp([asr, bw, top, amount]))
+}
+
+/// Parses and elaborates a program whose bitwidths may be omitted.
+pub fn elaborate(input: &str) -> Result<RecExpr<Language>, ElaborationError> {
+    elaborate_sexp(&sexp::parse(input)?)
+}
+
+/// Like [`elaborate`], but extends operands narrower than their operators
+/// as `extension` says.
+pub fn elaborate_with(
+    input: &str,
+    extension: Extension,
+) -> Result<RecExpr<Language>, ElaborationError> {
+    elaborate_sexp_with(&sexp::parse(input)?, None, extension)
+}
+
+/// Elaborates an already-parsed program whose bitwidths may be omitted.
+pub fn elaborate_sexp(sexp: &Sexp) -> Result<RecExpr<Language>, ElaborationError> {
+    elaborate_sexp_at(sexp, None)
+}
+
+/// Like [`elaborate_sexp`], but if `width` is given, the program must have
+/// that width (which also determines the width of programs made up of only
+/// literals).
+pub fn elaborate_sexp_at(
+    sexp: &Sexp,
+    width: Option<usize>,
+) -> Result<RecExpr<Language>, ElaborationError> {
+    elaborate_sexp_with(sexp, width, Extension::Require)
+}
+
+fn elaborate_sexp_with(
+    sexp: &Sexp,
+    width: Option<usize>,
+    extension: Extension,
+) -> Result<RecExpr<Language>, ElaborationError> {
+    let mut env = HashMap::default();
+    collect_var_widths(sexp, &mut env)?;
+    let width = match (infer(sexp, &env, extension)?, width) {
+        (Some(found), Some(expected)) if found != expected => {
+            return Err(ElaborationError::WidthMismatch {
+                form: sexp.to_string(),
+                expected,
+                found,
+            })
+        }
+        (Some(width), _) | (None, Some(width)) => width,
+        (None, None) => {
+            return Err(ElaborationError::CannotInfer {
+                form: sexp.to_string(),
+            })
+        }
+    };
+    let mut expr = RecExpr::default();
+    emit(sexp, width, &mut env, &mut expr, extension)?;
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language::typecheck;
+
+    #[test]
+    fn elaborate_ceil_avg() {
+        let expr = elaborate(
+            "(binop sub (binop or (var x 8) (var y)) (binop asr (binop xor (var x) (var y)) (const 1)))",
+        )
+        .unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "(binop sub 8 (binop or 8 (var x 8) (var y 8)) \
+             (binop asr 8 (binop xor 8 (var x 8) (var y 8)) (const 1 8)))"
+        );
+        assert!(typecheck(&expr).is_ok());
+    }
+
+    #[test]
+    fn elaboration_errors() {
+        assert!(matches!(
+            elaborate("(binop and (const 1) (const 2))"),
+            Err(ElaborationError::CannotInfer { .. })
+        ));
+        assert!(matches!(
+            elaborate("(binop and (var x 8) (var x 4))"),
+            Err(ElaborationError::WidthMismatch {
+                expected: 8,
+                found: 4,
+                ..
+            })
+        ));
+        assert!(matches!(
+            elaborate("(binop and 4 (var x 8) (var y))"),
+            Err(ElaborationError::WidthMismatch { .. })
+        ));
+        assert!(matches!(
+            elaborate("(binop nand (var x 8) (var y))"),
+            Err(ElaborationError::UnknownOp { .. })
+        ));
+    }
+
+    #[test]
+    fn elaborate_mixed_widths() {
+        let program = "(binop add (var x 16) (binop and (var y 8) (const 15)))";
+        assert!(matches!(
+            elaborate(program),
+            Err(ElaborationError::WidthMismatch {
+                expected: 16,
+                found: 8,
+                ..
+            })
+        ));
+        let zext =
+            "(binop add 16 (var x 16) (cat (const 0 8) (binop and 8 (var y 8) (const 15 8))))";
+        assert_eq!(
+            elaborate_with(program, Extension::Zero)
+                .unwrap()
+                .to_string(),
+            zext
+        );
+        assert_eq!(
+            elaborate("(binop add (var x 16) (zext (binop and (var y 8) (const 15))))")
+                .unwrap()
+                .to_string(),
+            zext
+        );
+        let sext = elaborate("(sext 8 (var y 4))").unwrap();
+        assert_eq!(
+            sext.to_string(),
+            "(binop asr 8 (cat (var y 4) (const 0 4)) (const 4 8))"
+        );
+        assert!(typecheck(&sext).is_ok());
+
+        // A widening multiply's operands keep their width.
+        assert_eq!(
+            elaborate("(binop mul 16 (var x 8) (var y))")
+                .unwrap()
+                .to_string(),
+            "(binop mul 16 (var x 8) (var y 8))"
+        );
+
+        // A shift's amount stays narrow, and an operand can't be narrowed.
+        assert_eq!(
+            elaborate("(binop lsr (var x 8) (var s 3))")
+                .unwrap()
+                .to_string(),
+            "(binop lsr 8 (var x 8) (var s 3))"
+        );
+        assert!(matches!(
+            elaborate_with("(binop and 4 (var x 8) (var y))", Extension::Sign),
+            Err(ElaborationError::WidthMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,572 @@
+//! Importer for combinational lo-FIRRTL modules, e.g. as emitted by Chisel.
+//!
+//! Ports become `var`s and primops become ops:
+//!
+//! ```text
+//! circuit CeilAvg :
+//!   module CeilAvg :
+//!     input x : SInt<8>
+//!     input y : SInt<8>
+//!     output out : UInt<8>
+//!     node _T = or(x, y)
+//!     node _T_1 = pad(shr(asSInt(xor(x, y)), 1), 8)
+//!     out <= tail(sub(_T, asUInt(_T_1)), 1)
+//! ```
+//!
+//! FIRRTL's primops change widths (`add` grows by a bit, a static `shr`
+//! narrows, `eq` returns one bit) while the language's ops don't, so those
+//! primops are only accepted in the idioms which bring the result back to
+//! the width of the operands: `tail(add(a, b), 1)` (or `bits(.., w - 1, 0)`),
+//! `pad(shr(a, n), w)`, and `pad(eq(a, b), w)`. Each output of each module
+//! becomes a program named `module.output`. Registers, `when`s, and instances
+//! are rejected.
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    frontends::{
+        elaborate::{elaborate_sexp_at, ElaborationError},
+        sexp::Sexp,
+    },
+    program_set::ProgramSet,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FirrtlError {
+    Malformed {
+        line: usize,
+        what: String,
+    },
+    Unsupported {
+        line: usize,
+        what: String,
+    },
+    Undefined {
+        line: usize,
+        name: String,
+    },
+    /// An output which is never connected.
+    Unconnected {
+        name: String,
+    },
+    Elaboration {
+        name: String,
+        error: ElaborationError,
+    },
+}
+impl Display for FirrtlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FirrtlError::Malformed { line, what } => write!(f, "line {}: malformed {}", line, what),
+            FirrtlError::Unsupported { line, what } => {
+                write!(f, "line {}: unsupported {}", line, what)
+            }
+            FirrtlError::Undefined { line, name } => {
+                write!(f, "line {}: undefined {}", line, name)
+            }
+            FirrtlError::Unconnected { name } => write!(f, "{} is never connected", name),
+            FirrtlError::Elaboration { name, error } => write!(f, "{}: {}", name, error),
+        }
+    }
+}
+impl std::error::Error for FirrtlError {}
+
+/// A FIRRTL value, possibly of a width the language can't represent.
+#[derive(Debug, Clone)]
+enum Value {
+    Int {
+        expr: Sexp,
+        width: usize,
+        signed: bool,
+    },
+    /// `add`/`sub` of `width`-bit operands, one bit wider than them.
+    Widened {
+        op: &'static str,
+        a: Sexp,
+        b: Sexp,
+        width: usize,
+    },
+    /// `shr(a, amount)` of a `width`-bit `a`.
+    Narrowed {
+        a: Sexp,
+        amount: i64,
+        width: usize,
+        signed: bool,
+    },
+    /// `eq(a, b)` of `width`-bit operands.
+    Eq { a: Sexp, b: Sexp, width: usize },
+}
+
+fn atom(s: impl ToString) -> Sexp {
+    Sexp::Atom(s.to_string())
+}
+
+fn binop(op: &str, width: usize, a: Sexp, b: Sexp) -> Sexp {
+    Sexp::List(vec![atom("binop"), atom(op), atom(width), a, b])
+}
+
+/// Parses a ground type like `UInt<8>` into its width and signedness, or
+/// `None` for clocks and resets.
+fn ground_type(line: usize, ty: &str) -> Result<Option<(usize, bool)>, FirrtlError> {
+    let ty = ty.trim();
+    if matches!(ty, "Clock" | "Reset" | "AsyncReset") {
+        return Ok(None);
+    }
+    let (signed, rest) = if let Some(rest) = ty.strip_prefix("UInt") {
+        (false, rest)
+    } else if let Some(rest) = ty.strip_prefix("SInt") {
+        (true, rest)
+    } else {
+        return Err(FirrtlError::Unsupported {
+            line,
+            what: format!("type {}", ty),
+        });
+    };
+    rest.strip_prefix('<')
+        .and_then(|r| r.strip_suffix('>'))
+        .and_then(|w| w.trim().parse().ok())
+        .filter(|w| *w > 0)
+        .map(|w| Some((w, signed)))
+        .ok_or_else(|| FirrtlError::Unsupported {
+            line,
+            what: format!("type {} (widths must be explicit)", ty),
+        })
+}
+
+/// Splits `f(a, g(b, c), 1)` into `f` and its top-level arguments.
+fn split_call(text: &str) -> Option<(&str, Vec<&str>)> {
+    let open = text.find('(')?;
+    let inner = text.strip_suffix(')')?.get(open + 1..)?;
+    let mut args = vec![];
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in inner.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    if !inner.trim().is_empty() {
+        args.push(inner[start..].trim());
+    }
+    Some((text[..open].trim(), args))
+}
+
+#[derive(Default)]
+struct Module {
+    name: String,
+    /// Outputs and wires, with their widths.
+    sinks: HashMap<String, usize>,
+    /// Outputs, in declaration order.
+    outputs: Vec<String>,
+    /// Inputs, nodes, and connected wires.
+    values: HashMap<String, Value>,
+    /// What each output is connected to.
+    connections: HashMap<String, Sexp>,
+}
+
+impl Module {
+    /// Evaluates an expression which must have a representable width.
+    fn int(&self, line: usize, text: &str) -> Result<(Sexp, usize, bool), FirrtlError> {
+        match self.expr(line, text)? {
+            Value::Int {
+                expr,
+                width,
+                signed,
+            } => Ok((expr, width, signed)),
+            _ => Err(FirrtlError::Unsupported {
+                line,
+                what: format!("width change in {}", text.trim()),
+            }),
+        }
+    }
+
+    fn same_width(
+        &self,
+        line: usize,
+        a: &str,
+        b: &str,
+    ) -> Result<(Sexp, Sexp, usize, bool), FirrtlError> {
+        let (a_expr, a_width, signed) = self.int(line, a)?;
+        let (b_expr, b_width, _) = self.int(line, b)?;
+        if a_width != b_width {
+            return Err(FirrtlError::Unsupported {
+                line,
+                what: format!("operands of widths {} and {}", a_width, b_width),
+            });
+        }
+        Ok((a_expr, b_expr, a_width, signed))
+    }
+
+    fn expr(&self, line: usize, text: &str) -> Result<Value, FirrtlError> {
+        let text = text.trim();
+        let unsupported = |what: String| FirrtlError::Unsupported { line, what };
+        let malformed = || FirrtlError::Malformed {
+            line,
+            what: text.to_string(),
+        };
+        let (head, args) = match split_call(text) {
+            Some(call) => call,
+            None => {
+                return self
+                    .values
+                    .get(text)
+                    .cloned()
+                    .ok_or_else(|| FirrtlError::Undefined {
+                        line,
+                        name: text.to_string(),
+                    })
+            }
+        };
+
+        // Literals: `UInt<8>("h1")`, `SInt<4>(-1)`.
+        if head.starts_with("UInt") || head.starts_with("SInt") {
+            let (width, signed) = match ground_type(line, head) {
+                Ok(Some(ty)) => ty,
+                _ => return Err(unsupported(format!("literal {} without a width", text))),
+            };
+            let value = match args.as_slice() {
+                [v] => {
+                    let v = v.trim_matches('"');
+                    if let Some(hex) = v.strip_prefix('h') {
+                        i64::from_str_radix(hex, 16)
+                    } else if let Some(bin) = v.strip_prefix('b') {
+                        i64::from_str_radix(bin, 2)
+                    } else {
+                        v.parse()
+                    }
+                    .map_err(|_| malformed())?
+                }
+                _ => return Err(malformed()),
+            };
+            return Ok(Value::Int {
+                expr: Sexp::List(vec![atom("const"), atom(value), atom(width)]),
+                width,
+                signed,
+            });
+        }
+
+        let number = |arg: &str| arg.parse::<i64>().map_err(|_| malformed());
+        match (head, args.as_slice()) {
+            // Bitwise ops return UInts.
+            ("and" | "or" | "xor", [a, b]) => {
+                let (a, b, width, _) = self.same_width(line, a, b)?;
+                Ok(Value::Int {
+                    expr: binop(head, width, a, b),
+                    width,
+                    signed: false,
+                })
+            }
+            ("not", [a]) => {
+                let (a, width, _) = self.int(line, a)?;
+                Ok(Value::Int {
+                    expr: Sexp::List(vec![atom("unop"), atom("not"), atom(width), a]),
+                    width,
+                    signed: false,
+                })
+            }
+            ("asUInt" | "asSInt", [a]) => {
+                let (expr, width, _) = self.int(line, a)?;
+                Ok(Value::Int {
+                    expr,
+                    width,
+                    signed: head == "asSInt",
+                })
+            }
+            ("add" | "sub", [a, b]) => {
+                let (a, b, width, _) = self.same_width(line, a, b)?;
+                Ok(Value::Widened {
+                    op: if head == "add" { "add" } else { "sub" },
+                    a,
+                    b,
+                    width,
+                })
+            }
+            ("tail", [a, n]) if number(n)? == 1 => match self.expr(line, a)? {
+                Value::Widened { op, a, b, width } => Ok(Value::Int {
+                    expr: binop(op, width, a, b),
+                    width,
+                    signed: false,
+                }),
+                _ => Err(unsupported(format!("{} of anything but add or sub", text))),
+            },
+            ("bits", [a, hi, lo]) if number(lo)? == 0 => {
+                let hi = number(hi)?;
+                match self.expr(line, a)? {
+                    Value::Widened { op, a, b, width } if hi == width as i64 - 1 => {
+                        Ok(Value::Int {
+                            expr: binop(op, width, a, b),
+                            width,
+                            signed: false,
+                        })
+                    }
+                    Value::Int { expr, width, .. } if hi == width as i64 - 1 => Ok(Value::Int {
+                        expr,
+                        width,
+                        signed: false,
+                    }),
+                    _ => Err(unsupported(format!("bit select {}", text))),
+                }
+            }
+            ("shr", [a, n]) => {
+                let (a, width, signed) = self.int(line, a)?;
+                Ok(Value::Narrowed {
+                    a,
+                    amount: number(n)?,
+                    width,
+                    signed,
+                })
+            }
+            ("dshr", [a, b]) => {
+                let (a, b, width, signed) = self.same_width(line, a, b)?;
+                Ok(Value::Int {
+                    expr: binop(if signed { "asr" } else { "lsr" }, width, a, b),
+                    width,
+                    signed,
+                })
+            }
+            ("eq", [a, b]) => {
+                let (a, b, width, _) = self.same_width(line, a, b)?;
+                Ok(Value::Eq { a, b, width })
+            }
+            ("pad", [a, n]) => {
+                let n = number(n)?;
+                match self.expr(line, a)? {
+                    // Padding sign-extends SInts, so this is an arithmetic
+                    // shift exactly when `a` is signed.
+                    Value::Narrowed {
+                        a,
+                        amount,
+                        width,
+                        signed,
+                    } if n == width as i64 => {
+                        let amount = Sexp::List(vec![atom("const"), atom(amount), atom(width)]);
+                        Ok(Value::Int {
+                            expr: binop(if signed { "asr" } else { "lsr" }, width, a, amount),
+                            width,
+                            signed,
+                        })
+                    }
+                    Value::Eq { a, b, width } if n == width as i64 => Ok(Value::Int {
+                        expr: binop("eq", width, a, b),
+                        width,
+                        signed: false,
+                    }),
+                    // Padding to a smaller width does nothing.
+                    value @ Value::Int { width, .. } if n <= width as i64 => Ok(value),
+                    _ => Err(unsupported(format!("width change in {}", text))),
+                }
+            }
+            _ => Err(unsupported(format!("primop {}", text))),
+        }
+    }
+
+    /// Connects the output or wire `name` to `expr`.
+    fn connect(&mut self, line: usize, name: &str, expr: &str) -> Result<(), FirrtlError> {
+        let name = name.trim();
+        let width = *self.sinks.get(name).ok_or_else(|| FirrtlError::Undefined {
+            line,
+            name: name.to_string(),
+        })?;
+        let (expr, _, signed) = self.int(line, expr)?;
+        if self.outputs.iter().any(|output| output == name) {
+            self.connections.insert(name.to_string(), expr);
+        } else {
+            // Later reads of the wire see what it's connected to.
+            self.values.insert(
+                name.to_string(),
+                Value::Int {
+                    expr,
+                    width,
+                    signed,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn finish(self, programs: &mut ProgramSet) -> Result<(), FirrtlError> {
+        for output in &self.outputs {
+            let name = format!("{}.{}", self.name, output);
+            let expr = self
+                .connections
+                .get(output)
+                .ok_or_else(|| FirrtlError::Unconnected { name: name.clone() })?;
+            let expr = elaborate_sexp_at(expr, Some(self.sinks[output])).map_err(|error| {
+                FirrtlError::Elaboration {
+                    name: name.clone(),
+                    error,
+                }
+            })?;
+            programs.add(name, expr);
+        }
+        Ok(())
+    }
+}
+
+/// Imports every module of a lo-FIRRTL circuit.
+pub fn import_firrtl(input: &str) -> Result<ProgramSet, FirrtlError> {
+    let mut programs = ProgramSet::new();
+    let mut current: Option<Module> = None;
+
+    for (i, text) in input.lines().enumerate() {
+        let line = i + 1;
+        // Drop source locators and comments.
+        let text = text.split("@[").next().unwrap();
+        let text = text.split(';').next().unwrap().trim();
+        if text.is_empty() || text.starts_with("FIRRTL version") {
+            continue;
+        }
+        let malformed = || FirrtlError::Malformed {
+            line,
+            what: text.to_string(),
+        };
+        let (keyword, rest) = text.split_once(' ').unwrap_or((text, ""));
+        match keyword {
+            "circuit" => (),
+            "module" | "public" => {
+                if let Some(module) = current.take() {
+                    module.finish(&mut programs)?;
+                }
+                let name = rest.trim_start_matches("module ").trim_end_matches(':');
+                current = Some(Module {
+                    name: name.trim().to_string(),
+                    ..Default::default()
+                });
+            }
+            _ => {
+                let module = current.as_mut().ok_or_else(malformed)?;
+                match keyword {
+                    "input" | "output" | "wire" => {
+                        let (name, ty) = rest.split_once(':').ok_or_else(malformed)?;
+                        let name = name.trim().to_string();
+                        // Clocks and resets are only accepted if unused.
+                        let (width, signed) = match ground_type(line, ty)? {
+                            Some(ty) => ty,
+                            None => continue,
+                        };
+                        if keyword == "input" {
+                            let expr = Sexp::List(vec![atom("var"), atom(&name), atom(width)]);
+                            module.values.insert(
+                                name,
+                                Value::Int {
+                                    expr,
+                                    width,
+                                    signed,
+                                },
+                            );
+                        } else {
+                            if keyword == "output" {
+                                module.outputs.push(name.clone());
+                            }
+                            module.sinks.insert(name, width);
+                        }
+                    }
+                    "node" => {
+                        let (name, expr) = rest.split_once('=').ok_or_else(malformed)?;
+                        let value = module.expr(line, expr)?;
+                        module.values.insert(name.trim().to_string(), value);
+                    }
+                    "connect" => {
+                        let (name, expr) = rest.split_once(',').ok_or_else(malformed)?;
+                        module.connect(line, name, expr)?;
+                    }
+                    "skip" => (),
+                    "reg" | "regreset" | "when" | "else" | "inst" | "mem" | "extmodule" => {
+                        return Err(FirrtlError::Unsupported {
+                            line,
+                            what: format!("{} (only combinational modules are supported)", keyword),
+                        })
+                    }
+                    _ => {
+                        let (name, expr) = text.split_once("<=").ok_or_else(malformed)?;
+                        module.connect(line, name, expr)?;
+                    }
+                }
+            }
+        }
+    }
+    if let Some(module) = current {
+        module.finish(&mut programs)?;
+    }
+    Ok(programs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::example_programs::all_programs;
+
+    #[test]
+    fn import_ceil_avg() {
+        let programs = import_firrtl(
+            "circuit CeilAvg :
+               module CeilAvg :
+                 input clock : Clock
+                 input x : SInt<8>
+                 input y : SInt<8>
+                 output out : UInt<8>
+
+                 node _T = or(x, y) @[CeilAvg.scala 8:14]
+                 node _T_1 = xor(x, y)
+                 node _T_2 = pad(shr(asSInt(_T_1), 1), 8)
+                 node _T_3 = sub(_T, asUInt(_T_2))
+                 out <= tail(_T_3, 1)",
+        )
+        .unwrap();
+        assert_eq!(
+            programs.get("CeilAvg.out").unwrap().expr.to_string(),
+            all_programs()["bithack_ceil_avg"].to_string()
+        );
+    }
+
+    #[test]
+    fn wires_and_eq() {
+        let programs = import_firrtl(
+            "FIRRTL version 3.3.0
+             circuit M :
+               module M :
+                 input a : UInt<4>
+                 input b : UInt<4>
+                 output o : UInt<4>
+                 wire w : UInt<4>
+                 connect w, and(a, b)
+                 connect o, pad(eq(w, UInt<4>(\"h3\")), 4)",
+        )
+        .unwrap();
+        assert_eq!(
+            programs.get("M.o").unwrap().expr.to_string(),
+            "(binop eq 4 (binop and 4 (var a 4) (var b 4)) (const 3 4))"
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(matches!(
+            import_firrtl(
+                "circuit M :
+                   module M :
+                     input a : UInt<4>
+                     output o : UInt<5>
+                     o <= add(a, a)"
+            ),
+            Err(FirrtlError::Unsupported { line: 5, .. })
+        ));
+        assert!(matches!(
+            import_firrtl(
+                "circuit M :
+                   module M :
+                     input clock : Clock
+                     input a : UInt<4>
+                     output o : UInt<4>
+                     reg r : UInt<4>, clock
+                     o <= r"
+            ),
+            Err(FirrtlError::Unsupported { line: 6, .. })
+        ));
+    }
+}
@@ -0,0 +1,391 @@
+//! A JSON interchange format for programs, for generating exploration inputs
+//! from tools not written in Rust.
+//!
+//! A file holds a list of programs. Each program names its inputs and their
+//! widths, and lists its nodes in order; a node's `args` refer to earlier
+//! nodes by index, and the last node is the program's result:
+//!
+//! ```json
+//! {
+//!   "programs": [
+//!     {
+//!       "name": "ceil_avg",
+//!       "weight": 1.0,
+//!       "inputs": { "x": 8, "y": 8 },
+//!       "nodes": [
+//!         { "kind": "var", "name": "x", "width": 8 },
+//!         { "kind": "var", "name": "y", "width": 8 },
+//!         { "kind": "binop", "op": "or", "width": 8, "args": [0, 1] },
+//!         { "kind": "binop", "op": "xor", "width": 8, "args": [0, 1] },
+//!         { "kind": "const", "value": 1, "width": 8 },
+//!         { "kind": "binop", "op": "asr", "width": 8, "args": [3, 4] },
+//!         { "kind": "binop", "op": "sub", "width": 8, "args": [2, 5] }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `weight` is optional and defaults to 1. Loading checks the whole schema,
+//! including widths, and reports the first offending node.
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use egg::{Id, RecExpr};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    language::{Language, Op},
+    program_set::ProgramSet,
+};
+
+use JsonNode::{Binop, Const, Unop, Var};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonProgramSet {
+    pub programs: Vec<JsonProgram>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonProgram {
+    pub name: String,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    /// Input names and their widths.
+    pub inputs: BTreeMap<String, usize>,
+    pub nodes: Vec<JsonNode>,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum JsonNode {
+    Var {
+        name: String,
+        width: usize,
+    },
+    Const {
+        value: i64,
+        width: usize,
+    },
+    Unop {
+        op: String,
+        width: usize,
+        args: Vec<usize>,
+    },
+    Binop {
+        op: String,
+        width: usize,
+        args: Vec<usize>,
+    },
+}
+
+#[derive(Debug)]
+pub enum JsonError {
+    /// The input isn't JSON of the right shape.
+    Parse(serde_json::Error),
+    /// Node `node` of program `program` is invalid.
+    Schema {
+        program: String,
+        node: usize,
+        reason: String,
+    },
+    /// The program `program` has no nodes.
+    Empty { program: String },
+    /// The program `program` contains things other than `var`s, `const`s,
+    /// and operator applications, e.g. instruction applications.
+    Unrepresentable { program: String },
+}
+impl Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::Parse(e) => write!(f, "{}", e),
+            JsonError::Schema {
+                program,
+                node,
+                reason,
+            } => write!(f, "{}: node {}: {}", program, node, reason),
+            JsonError::Empty { program } => write!(f, "{} has no nodes", program),
+            JsonError::Unrepresentable { program } => {
+                write!(f, "{} can't be represented in JSON", program)
+            }
+        }
+    }
+}
+impl std::error::Error for JsonError {}
+
+impl JsonProgram {
+    /// Checks the program against the schema and converts it.
+    pub fn to_expr(&self) -> Result<RecExpr<Language>, JsonError> {
+        let error = |node: usize, reason: String| JsonError::Schema {
+            program: self.name.clone(),
+            node,
+            reason,
+        };
+        if self.nodes.is_empty() {
+            return Err(JsonError::Empty {
+                program: self.name.clone(),
+            });
+        }
+        let mut expr = RecExpr::default();
+        // The id and width of each node so far.
+        let mut done: Vec<(Id, usize)> = vec![];
+        for (i, node) in self.nodes.iter().enumerate() {
+            let (width, arity) = match node {
+                Var { width, .. } | Const { width, .. } => (*width, 0),
+                Unop { width, .. } => (*width, 1),
+                Binop { width, .. } => (*width, 2),
+            };
+            if width == 0 {
+                return Err(error(i, "widths must be positive".to_string()));
+            }
+            let args = match node {
+                Unop { args, .. } | Binop { args, .. } => args.as_slice(),
+                _ => &[],
+            };
+            if args.len() != arity {
+                return Err(error(
+                    i,
+                    format!("expected {} args, found {}", arity, args.len()),
+                ));
+            }
+            let mut arg_ids = vec![];
+            for arg in args {
+                let (id, arg_width) = *done.get(*arg).ok_or_else(|| {
+                    error(i, format!("arg {} doesn't refer to an earlier node", arg))
+                })?;
+                if arg_width != width {
+                    return Err(error(
+                        i,
+                        format!(
+                            "arg {} has width {}, but this node has width {}",
+                            arg, arg_width, width
+                        ),
+                    ));
+                }
+                arg_ids.push(id);
+            }
+            let op = |op: &str, unary: bool| match op.parse::<Op>() {
+                Ok(op) if matches!(op, Op::Not | Op::Neg) == unary => Ok(op),
+                Ok(_) => Err(error(
+                    i,
+                    format!(
+                        "{} is not a {} operator",
+                        op,
+                        if unary { "unary" } else { "binary" }
+                    ),
+                )),
+                Err(()) => Err(error(i, format!("unknown operator {}", op))),
+            };
+            let width_id = expr.add(Language::Num(width as i64));
+            let id = match node {
+                Var { name, .. } => {
+                    match self.inputs.get(name) {
+                        Some(w) if *w == width => (),
+                        Some(w) => {
+                            return Err(error(
+                                i,
+                                format!("input {} has width {}, not {}", name, w, width),
+                            ))
+                        }
+                        None => return Err(error(i, format!("{} is not an input", name))),
+                    }
+                    let name_id = expr.add(Language::String(name.clone()));
+                    expr.add(Language::Var([name_id, width_id]))
+                }
+                Const { value, .. } => {
+                    let value_id = expr.add(Language::Num(*value));
+                    expr.add(Language::Const([value_id, width_id]))
+                }
+                Unop { op: o, .. } => {
+                    let op_id = expr.add(Language::Op(op(o, true)?));
+                    expr.add(Language::UnOp([op_id, width_id, arg_ids[0]]))
+                }
+                Binop { op: o, .. } => {
+                    let op_id = expr.add(Language::Op(op(o, false)?));
+                    expr.add(Language::BinOp([op_id, width_id, arg_ids[0], arg_ids[1]]))
+                }
+            };
+            done.push((id, width));
+        }
+        Ok(expr)
+    }
+
+    /// Converts a program, which must be made only of `var`s, `const`s, and
+    /// operator applications.
+    pub fn from_expr(
+        name: impl Into<String>,
+        weight: f64,
+        expr: &RecExpr<Language>,
+    ) -> Result<Self, JsonError> {
+        let name = name.into();
+        let nodes = expr.as_ref();
+        let unrepresentable = || JsonError::Unrepresentable {
+            program: name.clone(),
+        };
+        let num = |id: Id| match nodes[usize::from(id)] {
+            Language::Num(n) => Ok(n),
+            _ => Err(unrepresentable()),
+        };
+        let op = |id: Id| match &nodes[usize::from(id)] {
+            Language::Op(op) => Ok(op.to_string()),
+            _ => Err(unrepresentable()),
+        };
+        let mut inputs = BTreeMap::new();
+        let mut out = vec![];
+        // The index in `out` of each node of `expr`.
+        let mut index: Vec<Option<usize>> = vec![];
+        let arg =
+            |index: &Vec<Option<usize>>, id: Id| index[usize::from(id)].ok_or_else(unrepresentable);
+        for node in nodes {
+            let json = match node {
+                Language::Num(_) | Language::String(_) | Language::Op(_) => {
+                    index.push(None);
+                    continue;
+                }
+                Language::Var([name_id, width_id]) => {
+                    let var = match &nodes[usize::from(*name_id)] {
+                        Language::String(s) => s.clone(),
+                        _ => return Err(unrepresentable()),
+                    };
+                    let width = num(*width_id)? as usize;
+                    inputs.insert(var.clone(), width);
+                    Var { name: var, width }
+                }
+                Language::Const([value_id, width_id]) => Const {
+                    value: num(*value_id)?,
+                    width: num(*width_id)? as usize,
+                },
+                Language::UnOp([op_id, width_id, a]) => Unop {
+                    op: op(*op_id)?,
+                    width: num(*width_id)? as usize,
+                    args: vec![arg(&index, *a)?],
+                },
+                Language::BinOp([op_id, width_id, a, b]) => Binop {
+                    op: op(*op_id)?,
+                    width: num(*width_id)? as usize,
+                    args: vec![arg(&index, *a)?, arg(&index, *b)?],
+                },
+                _ => return Err(unrepresentable()),
+            };
+            index.push(Some(out.len()));
+            out.push(json);
+        }
+        // The root must be the last node.
+        if index.last() != Some(&Some(out.len().wrapping_sub(1))) {
+            return Err(unrepresentable());
+        }
+        Ok(JsonProgram {
+            name,
+            weight,
+            inputs,
+            nodes: out,
+        })
+    }
+}
+
+/// Loads programs from the JSON interchange format.
+pub fn load_json(input: &str) -> Result<ProgramSet, JsonError> {
+    let set: JsonProgramSet = serde_json::from_str(input).map_err(JsonError::Parse)?;
+    let mut programs = ProgramSet::new();
+    for program in &set.programs {
+        programs
+            .add_weighted(program.name.clone(), program.to_expr()?, program.weight)
+            .map_err(|_| JsonError::Schema {
+                program: program.name.clone(),
+                node: 0,
+                reason: "weights must be finite and nonnegative".to_string(),
+            })?;
+    }
+    Ok(programs)
+}
+
+/// Stores programs in the JSON interchange format.
+pub fn store_json(programs: &ProgramSet) -> Result<String, JsonError> {
+    let set = JsonProgramSet {
+        programs: programs
+            .iter()
+            .map(|p| JsonProgram::from_expr(p.name.clone(), p.weight, &p.expr))
+            .collect::<Result<_, _>>()?,
+    };
+    Ok(serde_json::to_string_pretty(&set).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::example_programs::all_programs;
+
+    #[test]
+    fn round_trip() {
+        let programs = all_programs().into_iter().collect::<ProgramSet>();
+        let loaded = load_json(&store_json(&programs).unwrap()).unwrap();
+        assert_eq!(loaded.len(), programs.len());
+        for program in programs.iter() {
+            assert_eq!(
+                loaded.get(&program.name).unwrap().expr.to_string(),
+                program.expr.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn load_documented_example() {
+        let programs = load_json(
+            r#"{"programs": [{"name": "ceil_avg", "inputs": {"x": 8, "y": 8}, "nodes": [
+                {"kind": "var", "name": "x", "width": 8},
+                {"kind": "var", "name": "y", "width": 8},
+                {"kind": "binop", "op": "or", "width": 8, "args": [0, 1]},
+                {"kind": "binop", "op": "xor", "width": 8, "args": [0, 1]},
+                {"kind": "const", "value": 1, "width": 8},
+                {"kind": "binop", "op": "asr", "width": 8, "args": [3, 4]},
+                {"kind": "binop", "op": "sub", "width": 8, "args": [2, 5]}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            programs.get("ceil_avg").unwrap().expr.to_string(),
+            all_programs()["bithack_ceil_avg"].to_string()
+        );
+    }
+
+    #[test]
+    fn schema_errors_point_at_nodes() {
+        let error = |nodes: &str| match load_json(&format!(
+            r#"{{"programs": [{{"name": "p", "inputs": {{"x": 8}}, "nodes": [{}]}}]}}"#,
+            nodes
+        )) {
+            Err(JsonError::Schema { node, .. }) => node,
+            other => panic!("{:?}", other),
+        };
+        let x = r#"{"kind": "var", "name": "x", "width": 8}"#;
+        assert_eq!(error(r#"{"kind": "var", "name": "y", "width": 8}"#), 0);
+        assert_eq!(
+            error(&format!(
+                r#"{}, {{"kind": "binop", "op": "add", "width": 8, "args": [0, 2]}}"#,
+                x
+            )),
+            1
+        );
+        assert_eq!(
+            error(&format!(
+                r#"{}, {{"kind": "unop", "op": "add", "width": 8, "args": [0]}}"#,
+                x
+            )),
+            1
+        );
+        assert_eq!(
+            error(&format!(
+                r#"{}, {{"kind": "unop", "op": "not", "width": 4, "args": [0]}}"#,
+                x
+            )),
+            1
+        );
+        assert!(matches!(
+            load_json(r#"{"programs": [{"name": "p", "inputs": {}}]}"#),
+            Err(JsonError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,470 @@
+//! Importer for straight-line LLVM IR.
+//!
+//! Each function made of a single basic block of integer instructions over
+//! `iN` types becomes a program named after the function, with its
+//! parameters as `var`s. [`llvm_op`] is the table of LLVM instructions we
+//! can translate; everything else is reported as unsupported rather than
+//! skipped. Since the language's `eq` produces a full-width 0 or 1, `icmp eq`
+//! is only supported when its result is immediately `zext`ed back to the
+//! width of its operands.
+//!
+//! ```text
+//! define i8 @ceil_avg(i8 %x, i8 %y) {
+//!   %0 = or i8 %x, %y
+//!   %1 = xor i8 %x, %y
+//!   %2 = ashr i8 %1, 1
+//!   %3 = sub i8 %0, %2
+//!   ret i8 %3
+//! }
+//! ```
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    frontends::{
+        elaborate::{elaborate_sexp_at, ElaborationError},
+        sexp::Sexp,
+    },
+    language::Op,
+    program_set::ProgramSet,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LlvmError {
+    Malformed {
+        line: usize,
+        what: String,
+    },
+    /// An instruction with no counterpart in the language.
+    UnsupportedInstruction {
+        line: usize,
+        opcode: String,
+    },
+    /// Control flow, non-integer types, and the like.
+    Unsupported {
+        line: usize,
+        what: String,
+    },
+    UndefinedValue {
+        line: usize,
+        name: String,
+    },
+    Elaboration {
+        function: String,
+        error: ElaborationError,
+    },
+}
+impl Display for LlvmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LlvmError::Malformed { line, what } => write!(f, "line {}: malformed {}", line, what),
+            LlvmError::UnsupportedInstruction { line, opcode } => {
+                write!(f, "line {}: unsupported instruction {}", line, opcode)
+            }
+            LlvmError::Unsupported { line, what } => {
+                write!(f, "line {}: unsupported {}", line, what)
+            }
+            LlvmError::UndefinedValue { line, name } => {
+                write!(f, "line {}: undefined value {}", line, name)
+            }
+            LlvmError::Elaboration { function, error } => write!(f, "@{}: {}", function, error),
+        }
+    }
+}
+impl std::error::Error for LlvmError {}
+
+/// The op implementing a binary LLVM instruction, if there is one.
+pub fn llvm_op(opcode: &str) -> Option<Op> {
+    match opcode {
+        "add" => Some(Op::Add),
+        "sub" => Some(Op::Sub),
+        "mul" => Some(Op::Mul),
+        "and" => Some(Op::And),
+        "or" => Some(Op::Or),
+        "xor" => Some(Op::Xor),
+        "lshr" => Some(Op::Lsr),
+        "ashr" => Some(Op::Asr),
+        _ => None,
+    }
+}
+
+/// Instruction flags which don't change the result on the inputs where the
+/// result is defined.
+const FLAGS: &[&str] = &["nuw", "nsw", "exact", "disjoint"];
+
+/// A computed value.
+#[derive(Debug, Clone)]
+enum Value {
+    Int {
+        expr: Sexp,
+        width: usize,
+    },
+    /// The `i1` result of `icmp eq` on operands of the given width.
+    Eq {
+        a: Sexp,
+        b: Sexp,
+        width: usize,
+    },
+}
+
+fn atom(s: &str) -> Sexp {
+    Sexp::Atom(s.to_string())
+}
+
+/// Parses `iN`.
+fn int_type(ty: &str) -> Option<usize> {
+    ty.strip_prefix('i')?.parse().ok().filter(|w| *w > 0)
+}
+
+struct Function {
+    name: String,
+    ret_width: usize,
+    values: HashMap<String, Value>,
+    ret: Option<Sexp>,
+}
+
+impl Function {
+    fn operand(&self, line: usize, operand: &str, width: usize) -> Result<Sexp, LlvmError> {
+        let operand = operand.trim();
+        if let Some(name) = operand.strip_prefix('%') {
+            return match self.values.get(name) {
+                Some(Value::Int { expr, width: w }) if *w == width => Ok(expr.clone()),
+                Some(_) => Err(LlvmError::Unsupported {
+                    line,
+                    what: format!("use of %{} at type i{}", name, width),
+                }),
+                None => Err(LlvmError::UndefinedValue {
+                    line,
+                    name: operand.to_string(),
+                }),
+            };
+        }
+        let value = match operand {
+            "true" => 1,
+            "false" => 0,
+            _ => operand.parse::<i64>().map_err(|_| LlvmError::Unsupported {
+                line,
+                what: format!("operand {}", operand),
+            })?,
+        };
+        Ok(Sexp::List(vec![
+            atom("const"),
+            atom(&value.to_string()),
+            atom(&width.to_string()),
+        ]))
+    }
+
+    /// Handles one instruction.
+    fn instruction(&mut self, line: usize, text: &str) -> Result<(), LlvmError> {
+        let malformed = |what: &str| LlvmError::Malformed {
+            line,
+            what: what.to_string(),
+        };
+        // Drop attached metadata like `, !dbg !12`.
+        let text = text.split(", !").next().unwrap().trim();
+        let (dest, rhs) = match text.split_once('=') {
+            Some((dest, rhs)) => (Some(dest.trim()), rhs.trim()),
+            None => (None, text),
+        };
+        let mut words = rhs.split_whitespace();
+        let opcode = words.next().ok_or_else(|| malformed("instruction"))?;
+        let words: Vec<&str> = words.filter(|w| !FLAGS.contains(w)).collect();
+        let rest = words.join(" ");
+
+        let value = match opcode {
+            "ret" => {
+                let (ty, operand) = rest.split_once(' ').ok_or_else(|| malformed("ret"))?;
+                let width = int_type(ty).ok_or_else(|| LlvmError::Unsupported {
+                    line,
+                    what: format!("return type {}", ty),
+                })?;
+                if width != self.ret_width {
+                    return Err(malformed("ret of the wrong type"));
+                }
+                self.ret = Some(self.operand(line, operand, width)?);
+                return Ok(());
+            }
+            "icmp" => {
+                let (pred, rest) = rest.split_once(' ').ok_or_else(|| malformed("icmp"))?;
+                if pred != "eq" {
+                    return Err(LlvmError::UnsupportedInstruction {
+                        line,
+                        opcode: format!("icmp {}", pred),
+                    });
+                }
+                let (width, a, b) = self.binary_operands(line, rest)?;
+                Value::Eq { a, b, width }
+            }
+            "zext" => {
+                // zext i1 %c to iN
+                let (from, to) = rest.split_once(" to ").ok_or_else(|| malformed("zext"))?;
+                let operand =
+                    from.strip_prefix("i1 %")
+                        .ok_or_else(|| LlvmError::UnsupportedInstruction {
+                            line,
+                            opcode: "zext".to_string(),
+                        })?;
+                let width = int_type(to.trim()).ok_or_else(|| malformed("zext"))?;
+                match self.values.get(operand) {
+                    Some(Value::Eq { a, b, width: w }) if *w == width => Value::Int {
+                        expr: Sexp::List(vec![
+                            atom("binop"),
+                            atom("eq"),
+                            atom(&width.to_string()),
+                            a.clone(),
+                            b.clone(),
+                        ]),
+                        width,
+                    },
+                    _ => {
+                        return Err(LlvmError::UnsupportedInstruction {
+                            line,
+                            opcode: "zext".to_string(),
+                        })
+                    }
+                }
+            }
+            "br" | "switch" | "phi" | "select" | "call" | "load" | "store" | "alloca" => {
+                return Err(LlvmError::Unsupported {
+                    line,
+                    what: format!("{} (only straight-line integer code is supported)", opcode),
+                })
+            }
+            _ => match llvm_op(opcode) {
+                Some(op) => {
+                    let (width, a, b) = self.binary_operands(line, &rest)?;
+                    Value::Int {
+                        expr: Sexp::List(vec![
+                            atom("binop"),
+                            atom(&op.to_string()),
+                            atom(&width.to_string()),
+                            a,
+                            b,
+                        ]),
+                        width,
+                    }
+                }
+                None => {
+                    return Err(LlvmError::UnsupportedInstruction {
+                        line,
+                        opcode: opcode.to_string(),
+                    })
+                }
+            },
+        };
+
+        let dest = dest
+            .and_then(|d| d.strip_prefix('%'))
+            .ok_or_else(|| malformed("instruction without a result"))?;
+        self.values.insert(dest.to_string(), value);
+        Ok(())
+    }
+
+    /// Parses `iN a, b`.
+    fn binary_operands(&self, line: usize, text: &str) -> Result<(usize, Sexp, Sexp), LlvmError> {
+        let (ty, operands) = text.split_once(' ').ok_or_else(|| LlvmError::Malformed {
+            line,
+            what: "operands".to_string(),
+        })?;
+        let width = int_type(ty).ok_or_else(|| LlvmError::Unsupported {
+            line,
+            what: format!("type {}", ty),
+        })?;
+        let (a, b) = operands
+            .split_once(',')
+            .ok_or_else(|| LlvmError::Malformed {
+                line,
+                what: "operands".to_string(),
+            })?;
+        Ok((
+            width,
+            self.operand(line, a, width)?,
+            self.operand(line, b, width)?,
+        ))
+    }
+}
+
+/// Parses `define iN @name(iN %a, ...) ... {`.
+fn define(line: usize, text: &str) -> Result<Function, LlvmError> {
+    let malformed = || LlvmError::Malformed {
+        line,
+        what: "define".to_string(),
+    };
+    let at = text.find('@').ok_or_else(malformed)?;
+    let ret_ty = text[..at].split_whitespace().last().ok_or_else(malformed)?;
+    let ret_width = int_type(ret_ty).ok_or_else(|| LlvmError::Unsupported {
+        line,
+        what: format!("return type {}", ret_ty),
+    })?;
+    let open = text.find('(').ok_or_else(malformed)?;
+    let close = text.rfind(')').ok_or_else(malformed)?;
+    let name = text[at + 1..open].trim().trim_matches('"').to_string();
+
+    let mut values = HashMap::default();
+    for param in text[open + 1..close]
+        .split(',')
+        .filter(|p| !p.trim().is_empty())
+    {
+        let words: Vec<&str> = param.split_whitespace().collect();
+        let width = int_type(words[0]).ok_or_else(|| LlvmError::Unsupported {
+            line,
+            what: format!("parameter type {}", words[0]),
+        })?;
+        // Parameter attributes like `noundef` sit between the type and name.
+        let param_name = words
+            .last()
+            .and_then(|w| w.strip_prefix('%'))
+            .ok_or_else(malformed)?;
+        values.insert(
+            param_name.to_string(),
+            Value::Int {
+                expr: Sexp::List(vec![
+                    atom("var"),
+                    atom(param_name),
+                    atom(&width.to_string()),
+                ]),
+                width,
+            },
+        );
+    }
+    Ok(Function {
+        name,
+        ret_width,
+        values,
+        ret: None,
+    })
+}
+
+/// Imports every function in an LLVM IR module.
+pub fn import_llvm_ir(input: &str) -> Result<ProgramSet, LlvmError> {
+    let mut programs = ProgramSet::new();
+    let mut current: Option<Function> = None;
+    // Whether the current function has had a label or instruction yet, so
+    // that only the entry block may be labeled.
+    let mut started = false;
+    for (i, text) in input.lines().enumerate() {
+        let line = i + 1;
+        let text = text.split(';').next().unwrap().trim();
+        if text.is_empty() {
+            continue;
+        }
+        match &mut current {
+            None => {
+                if text.starts_with("define") {
+                    current = Some(define(line, text)?);
+                    started = false;
+                }
+                // Everything else at the top level (target triples,
+                // declarations, attributes, metadata) is irrelevant.
+            }
+            Some(function) => {
+                if text == "}" {
+                    let function = current.take().unwrap();
+                    let ret = function.ret.ok_or_else(|| LlvmError::Malformed {
+                        line,
+                        what: format!("@{} without a ret", function.name),
+                    })?;
+                    let expr =
+                        elaborate_sexp_at(&ret, Some(function.ret_width)).map_err(|error| {
+                            LlvmError::Elaboration {
+                                function: function.name.clone(),
+                                error,
+                            }
+                        })?;
+                    programs.add(function.name, expr);
+                } else if text.ends_with(':') {
+                    if started {
+                        return Err(LlvmError::Unsupported {
+                            line,
+                            what: "multiple basic blocks".to_string(),
+                        });
+                    }
+                    started = true;
+                } else if function.ret.is_some() {
+                    return Err(LlvmError::Unsupported {
+                        line,
+                        what: "multiple basic blocks".to_string(),
+                    });
+                } else {
+                    started = true;
+                    function.instruction(line, text)?;
+                }
+            }
+        }
+    }
+    match current {
+        Some(function) => Err(LlvmError::Malformed {
+            line: input.lines().count(),
+            what: format!("@{} is never closed", function.name),
+        }),
+        None => Ok(programs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::example_programs::all_programs;
+
+    #[test]
+    fn import_ceil_avg() {
+        let programs = import_llvm_ir(
+            "; ModuleID = 'ceil_avg.c'
+             define dso_local signext i8 @ceil_avg(i8 noundef signext %x, i8 noundef %y) #0 {
+             entry:
+               %0 = or i8 %x, %y
+               %1 = xor i8 %x, %y
+               %2 = ashr exact i8 %1, 1
+               %3 = sub nsw i8 %0, %2, !dbg !12
+               ret i8 %3
+             }
+             attributes #0 = { nounwind }",
+        )
+        .unwrap();
+        assert_eq!(
+            programs.get("ceil_avg").unwrap().expr.to_string(),
+            all_programs()["bithack_ceil_avg"].to_string()
+        );
+    }
+
+    #[test]
+    fn icmp_eq_with_zext() {
+        let programs = import_llvm_ir(
+            "define i8 @f(i8 %a) {
+               %c = icmp eq i8 %a, 0
+               %r = zext i1 %c to i8
+               ret i8 %r
+             }",
+        )
+        .unwrap();
+        assert_eq!(
+            programs.get("f").unwrap().expr.to_string(),
+            "(binop eq 8 (var a 8) (const 0 8))"
+        );
+    }
+
+    #[test]
+    fn unsupported_instructions() {
+        assert_eq!(
+            import_llvm_ir(
+                "define i8 @f(i8 %a) {
+                   %r = mul i8 %a, %a
+                   ret i8 %r
+                 }"
+            )
+            .unwrap_err(),
+            LlvmError::UnsupportedInstruction {
+                line: 2,
+                opcode: "mul".to_string()
+            }
+        );
+        assert!(matches!(
+            import_llvm_ir(
+                "define i8 @f(i8 %a, i1 %c) {
+                   br i1 %c, label %t, label %e
+                 }"
+            ),
+            Err(LlvmError::Unsupported { .. })
+        ));
+    }
+}
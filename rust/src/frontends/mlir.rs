@@ -0,0 +1,495 @@
+//! Importer for MLIR functions in the `arith` and `comb` dialects.
+//!
+//! Both `func.func`s and `hw.module`s (in either port syntax) are accepted,
+//! as long as their bodies are a single block of integer operations:
+//!
+//! ```text
+//! func.func @ceil_avg(%x: i8, %y: i8) -> i8 {
+//!   %0 = arith.ori %x, %y : i8
+//!   %1 = arith.xori %x, %y : i8
+//!   %c1 = arith.constant 1 : i8
+//!   %2 = arith.shrsi %1, %c1 : i8
+//!   %3 = arith.subi %0, %2 : i8
+//!   return %3 : i8
+//! }
+//! ```
+//!
+//! A `func.func` becomes a program named after the function; each output of
+//! an `hw.module` becomes a program named `module.output`. Arguments become
+//! `var`s. As with the other importers, equality comparisons are only
+//! supported when immediately zero-extended back to the width of their
+//! operands, and anything else without a counterpart in the language is
+//! reported rather than skipped.
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    frontends::{
+        elaborate::{elaborate_sexp_at, ElaborationError},
+        sexp::Sexp,
+    },
+    program_set::ProgramSet,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MlirError {
+    Malformed {
+        line: usize,
+        what: String,
+    },
+    UnsupportedOperation {
+        line: usize,
+        op: String,
+    },
+    Unsupported {
+        line: usize,
+        what: String,
+    },
+    UndefinedValue {
+        line: usize,
+        name: String,
+    },
+    Elaboration {
+        name: String,
+        error: ElaborationError,
+    },
+}
+impl Display for MlirError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MlirError::Malformed { line, what } => write!(f, "line {}: malformed {}", line, what),
+            MlirError::UnsupportedOperation { line, op } => {
+                write!(f, "line {}: unsupported operation {}", line, op)
+            }
+            MlirError::Unsupported { line, what } => {
+                write!(f, "line {}: unsupported {}", line, what)
+            }
+            MlirError::UndefinedValue { line, name } => {
+                write!(f, "line {}: undefined value %{}", line, name)
+            }
+            MlirError::Elaboration { name, error } => write!(f, "{}: {}", name, error),
+        }
+    }
+}
+impl std::error::Error for MlirError {}
+
+/// The language op of a binary `arith`/`comb` operation, if there is one.
+pub fn mlir_op(op: &str) -> Option<&'static str> {
+    match op {
+        "arith.addi" | "comb.add" => Some("add"),
+        "arith.subi" | "comb.sub" => Some("sub"),
+        "arith.muli" | "comb.mul" => Some("mul"),
+        "arith.andi" | "comb.and" => Some("and"),
+        "arith.ori" | "comb.or" => Some("or"),
+        "arith.xori" | "comb.xor" => Some("xor"),
+        "arith.shrui" | "comb.shru" => Some("lsr"),
+        "arith.shrsi" | "comb.shrs" => Some("asr"),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Int {
+        expr: Sexp,
+        width: usize,
+    },
+    /// The `i1` result of an equality comparison of operands of the given
+    /// width.
+    Eq {
+        a: Sexp,
+        b: Sexp,
+        width: usize,
+    },
+}
+
+fn atom(s: &str) -> Sexp {
+    Sexp::Atom(s.to_string())
+}
+
+fn int_type(ty: &str) -> Option<usize> {
+    ty.trim().strip_prefix('i')?.parse().ok().filter(|w| *w > 0)
+}
+
+struct Region {
+    /// `module` for `hw.module`s, which name their programs `module.output`.
+    module: Option<String>,
+    name: String,
+    /// Output names and widths, in order.
+    outputs: Vec<(String, usize)>,
+    values: HashMap<String, Value>,
+    results: Option<Vec<Sexp>>,
+}
+
+impl Region {
+    fn value(&self, line: usize, operand: &str) -> Result<&Value, MlirError> {
+        let name = operand
+            .trim()
+            .strip_prefix('%')
+            .ok_or_else(|| MlirError::Malformed {
+                line,
+                what: format!("operand {}", operand),
+            })?;
+        self.values
+            .get(name)
+            .ok_or_else(|| MlirError::UndefinedValue {
+                line,
+                name: name.to_string(),
+            })
+    }
+
+    fn int(&self, line: usize, operand: &str, width: usize) -> Result<Sexp, MlirError> {
+        match self.value(line, operand)? {
+            Value::Int { expr, width: w } if *w == width => Ok(expr.clone()),
+            _ => Err(MlirError::Unsupported {
+                line,
+                what: format!("use of {} at type i{}", operand.trim(), width),
+            }),
+        }
+    }
+
+    /// Handles one operation.
+    fn operation(&mut self, line: usize, text: &str) -> Result<(), MlirError> {
+        let malformed = |what: &str| MlirError::Malformed {
+            line,
+            what: what.to_string(),
+        };
+        let (dest, rhs) = match text.split_once(" = ") {
+            Some((dest, rhs)) => (Some(dest.trim()), rhs.trim()),
+            None => (None, text),
+        };
+        let (op, rest) = rhs.split_once(' ').unwrap_or((rhs, ""));
+        // The type comes after the last colon.
+        let (operands, ty) = rest.rsplit_once(':').unwrap_or((rest, ""));
+        // Drop attributes like `bin`.
+        let operands: Vec<&str> = operands
+            .split(',')
+            .map(|o| o.trim())
+            .map(|o| o.rsplit(' ').next().unwrap())
+            .filter(|o| !o.is_empty())
+            .collect();
+
+        let value = match op {
+            "return" | "func.return" | "hw.output" => {
+                let types: Vec<usize> = if ty.trim().is_empty() {
+                    vec![]
+                } else {
+                    ty.split(',')
+                        .map(|t| int_type(t).ok_or_else(|| malformed("result type")))
+                        .collect::<Result<_, _>>()?
+                };
+                if types.len() != operands.len() || types.len() != self.outputs.len() {
+                    return Err(malformed("return"));
+                }
+                let results = operands
+                    .iter()
+                    .zip(&types)
+                    .map(|(operand, width)| self.int(line, operand, *width))
+                    .collect::<Result<_, _>>()?;
+                self.results = Some(results);
+                return Ok(());
+            }
+            "arith.constant" | "hw.constant" => {
+                let width = int_type(ty).unwrap_or(1);
+                let value = match operands.first() {
+                    Some(&"true") => 1,
+                    Some(&"false") => 0,
+                    Some(v) => v.parse::<i64>().map_err(|_| malformed("constant"))?,
+                    None => return Err(malformed("constant")),
+                };
+                Value::Int {
+                    expr: Sexp::List(vec![
+                        atom("const"),
+                        atom(&value.to_string()),
+                        atom(&width.to_string()),
+                    ]),
+                    width,
+                }
+            }
+            "arith.cmpi" | "comb.icmp" => {
+                // `arith.cmpi eq, %a, %b` or `comb.icmp eq %a, %b`.
+                let predicate = rest.split(|c: char| c == ',' || c == ' ').next().unwrap();
+                if predicate != "eq" {
+                    return Err(MlirError::UnsupportedOperation {
+                        line,
+                        op: format!("{} {}", op, predicate),
+                    });
+                }
+                let width = int_type(ty).ok_or_else(|| malformed("comparison type"))?;
+                let (a, b) = match operands.as_slice() {
+                    [.., a, b] => (self.int(line, a, width)?, self.int(line, b, width)?),
+                    _ => return Err(malformed("comparison")),
+                };
+                Value::Eq { a, b, width }
+            }
+            "arith.extui" => {
+                // `arith.extui %c : i1 to i8`.
+                let width = ty
+                    .split_once(" to ")
+                    .and_then(|(_, to)| int_type(to))
+                    .ok_or_else(|| malformed("extui"))?;
+                match operands.as_slice() {
+                    [operand] => match self.value(line, operand)? {
+                        Value::Eq { a, b, width: w } if *w == width => Value::Int {
+                            expr: Sexp::List(vec![
+                                atom("binop"),
+                                atom("eq"),
+                                atom(&width.to_string()),
+                                a.clone(),
+                                b.clone(),
+                            ]),
+                            width,
+                        },
+                        _ => {
+                            return Err(MlirError::UnsupportedOperation {
+                                line,
+                                op: op.to_string(),
+                            })
+                        }
+                    },
+                    _ => return Err(malformed("extui")),
+                }
+            }
+            _ => match mlir_op(op) {
+                Some(lang_op) => {
+                    let width = int_type(ty).ok_or_else(|| malformed("operation type"))?;
+                    let exprs = operands
+                        .iter()
+                        .map(|operand| self.int(line, operand, width))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    // `comb` operations are variadic.
+                    let variadic = op.starts_with("comb.") && !matches!(lang_op, "lsr" | "asr");
+                    if exprs.len() < 2 || (!variadic && exprs.len() != 2) {
+                        return Err(malformed("operands"));
+                    }
+                    let mut exprs = exprs.into_iter();
+                    let first = exprs.next().unwrap();
+                    Value::Int {
+                        expr: exprs.fold(first, |acc, e| {
+                            Sexp::List(vec![
+                                atom("binop"),
+                                atom(lang_op),
+                                atom(&width.to_string()),
+                                acc,
+                                e,
+                            ])
+                        }),
+                        width,
+                    }
+                }
+                None => {
+                    return Err(MlirError::UnsupportedOperation {
+                        line,
+                        op: op.to_string(),
+                    })
+                }
+            },
+        };
+
+        let dest = dest
+            .and_then(|d| d.strip_prefix('%'))
+            .ok_or_else(|| malformed("operation without a result"))?;
+        self.values.insert(dest.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Parses a region header like `func.func @f(%x: i8) -> i8 {` or
+/// `hw.module @m(in %a : i8, out o : i8) {`.
+fn header(line: usize, text: &str) -> Result<Region, MlirError> {
+    let malformed = |what: &str| MlirError::Malformed {
+        line,
+        what: what.to_string(),
+    };
+    let is_module = text.starts_with("hw.module");
+    let at = text.find('@').ok_or_else(|| malformed("header"))?;
+    let open = text.find('(').ok_or_else(|| malformed("header"))?;
+    let close = text[open..]
+        .find(')')
+        .map(|i| open + i)
+        .ok_or_else(|| malformed("header"))?;
+    let name = text[at + 1..open].trim().to_string();
+
+    let mut values = HashMap::default();
+    let mut outputs = vec![];
+    for port in text[open + 1..close]
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+    {
+        let (port_name, ty) = port.split_once(':').ok_or_else(|| malformed("port"))?;
+        let width = int_type(ty).ok_or_else(|| MlirError::Unsupported {
+            line,
+            what: format!("port type {}", ty.trim()),
+        })?;
+        let port_name = port_name.trim();
+        if let Some(output) = port_name.strip_prefix("out ") {
+            outputs.push((output.trim().to_string(), width));
+            continue;
+        }
+        let arg = port_name
+            .trim_start_matches("in ")
+            .trim()
+            .strip_prefix('%')
+            .ok_or_else(|| malformed("argument"))?;
+        values.insert(
+            arg.to_string(),
+            Value::Int {
+                expr: Sexp::List(vec![atom("var"), atom(arg), atom(&width.to_string())]),
+                width,
+            },
+        );
+    }
+
+    // Results after `->`: either `i8` or `(o: i8, ...)`.
+    if let Some((_, results)) = text[close..].split_once("->") {
+        let results = results.trim_end_matches('{').trim();
+        let results = results
+            .strip_prefix('(')
+            .and_then(|r| r.strip_suffix(')'))
+            .unwrap_or(results);
+        for (i, result) in results.split(',').map(str::trim).enumerate() {
+            let (result_name, ty) = match result.split_once(':') {
+                Some((result_name, ty)) => (result_name.trim().to_string(), ty),
+                None => (i.to_string(), result),
+            };
+            let width = int_type(ty).ok_or_else(|| MlirError::Unsupported {
+                line,
+                what: format!("result type {}", ty.trim()),
+            })?;
+            outputs.push((result_name, width));
+        }
+    }
+
+    Ok(Region {
+        module: if is_module { Some(name.clone()) } else { None },
+        name,
+        outputs,
+        values,
+        results: None,
+    })
+}
+
+/// Imports every `func.func` and `hw.module` in a textual MLIR file.
+pub fn import_mlir(input: &str) -> Result<ProgramSet, MlirError> {
+    let mut programs = ProgramSet::new();
+    let mut current: Option<Region> = None;
+    for (i, text) in input.lines().enumerate() {
+        let line = i + 1;
+        let text = text.split("//").next().unwrap().trim();
+        if text.is_empty() {
+            continue;
+        }
+        match &mut current {
+            None => {
+                if text.starts_with("func.func") || text.starts_with("hw.module") {
+                    current = Some(header(line, text)?);
+                }
+                // Other top-level lines (`module {`, closing braces) are
+                // irrelevant.
+            }
+            Some(region) => {
+                if text == "}" {
+                    let region = current.take().unwrap();
+                    let results = region.results.ok_or_else(|| MlirError::Malformed {
+                        line,
+                        what: format!("@{} without a terminator", region.name),
+                    })?;
+                    for ((output, width), result) in region.outputs.iter().zip(results) {
+                        let name = match &region.module {
+                            Some(module) => format!("{}.{}", module, output),
+                            None if region.outputs.len() == 1 => region.name.clone(),
+                            None => format!("{}.{}", region.name, output),
+                        };
+                        let expr = elaborate_sexp_at(&result, Some(*width)).map_err(|error| {
+                            MlirError::Elaboration {
+                                name: name.clone(),
+                                error,
+                            }
+                        })?;
+                        programs.add(name, expr);
+                    }
+                } else if text.starts_with('^') || region.results.is_some() {
+                    return Err(MlirError::Unsupported {
+                        line,
+                        what: "multiple blocks".to_string(),
+                    });
+                } else {
+                    region.operation(line, text)?;
+                }
+            }
+        }
+    }
+    match current {
+        Some(region) => Err(MlirError::Malformed {
+            line: input.lines().count(),
+            what: format!("@{} is never closed", region.name),
+        }),
+        None => Ok(programs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::example_programs::all_programs;
+
+    #[test]
+    fn import_arith() {
+        let programs = import_mlir(
+            "module {
+               func.func @ceil_avg(%x: i8, %y: i8) -> i8 {
+                 %0 = arith.ori %x, %y : i8
+                 %1 = arith.xori %x, %y : i8
+                 %c1_i8 = arith.constant 1 : i8
+                 %2 = arith.shrsi %1, %c1_i8 : i8
+                 %3 = arith.subi %0, %2 : i8
+                 return %3 : i8
+               }
+             }",
+        )
+        .unwrap();
+        assert_eq!(
+            programs.get("ceil_avg").unwrap().expr.to_string(),
+            all_programs()["bithack_ceil_avg"].to_string()
+        );
+    }
+
+    #[test]
+    fn import_comb() {
+        let programs = import_mlir(
+            "hw.module @m(in %a : i4, in %b : i4, out o : i4, out e : i4) {
+               %0 = comb.and bin %a, %b, %a : i4
+               %1 = comb.icmp eq %a, %b : i4
+               %2 = arith.extui %1 : i1 to i4
+               hw.output %0, %2 : i4, i4
+             }",
+        )
+        .unwrap();
+        assert_eq!(
+            programs.get("m.o").unwrap().expr.to_string(),
+            "(binop and 4 (binop and 4 (var a 4) (var b 4)) (var a 4))"
+        );
+        assert_eq!(
+            programs.get("m.e").unwrap().expr.to_string(),
+            "(binop eq 4 (var a 4) (var b 4))"
+        );
+    }
+
+    #[test]
+    fn unsupported_operations() {
+        assert_eq!(
+            import_mlir(
+                "func.func @f(%a: i8) -> i8 {
+                   %0 = arith.muli %a, %a : i8
+                   return %0 : i8
+                 }"
+            )
+            .unwrap_err(),
+            MlirError::UnsupportedOperation {
+                line: 2,
+                op: "arith.muli".to_string()
+            }
+        );
+    }
+}
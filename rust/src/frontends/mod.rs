@@ -0,0 +1,15 @@
+//! Ways of getting programs into Lakeroad's language.
+
+pub mod c;
+pub mod elaborate;
+pub mod firrtl;
+pub mod json;
+pub mod llvm;
+pub mod mlir;
+pub mod rosette;
+pub mod sexp;
+pub mod smtlib;
+pub mod souper;
+pub mod verilog;
+pub mod wasm;
+pub mod yosys;
@@ -0,0 +1,268 @@
+//! Importer for Rosette bitvector expressions, the syntax [`to_racket`]
+//! emits and the Racket harness returns.
+//!
+//! Variables are bare symbols, whose widths come either from a map (such as
+//! the one [`to_racket`] returns) or from `define-symbolic` forms before the
+//! expression:
+//!
+//! ```text
+//! (define-symbolic x y (bitvector 8))
+//! (bvsub (bvor x y) (bvashr (bvxor x y) (bv 1 8)))
+//! ```
+//!
+//! Equality is accepted as `(bool->bitvector (bveq a b) (bitvector w))`,
+//! as emitted, or as `(if (bveq a b) (bv 1 w) (bv 0 w))`.
+//!
+//! [`to_racket`]: crate::solver::to_racket
+
+use std::{collections::HashMap, fmt::Display};
+
+use egg::RecExpr;
+
+use crate::{
+    frontends::{
+        elaborate::{elaborate_sexp, ElaborationError},
+        sexp::{parse_all, Sexp, SexpError},
+    },
+    language::Language,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RosetteError {
+    Parse(SexpError),
+    Malformed { form: String },
+    Unsupported { form: String },
+    Unbound { name: String },
+    Elaboration(ElaborationError),
+}
+impl Display for RosetteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RosetteError::Parse(e) => write!(f, "{}", e),
+            RosetteError::Malformed { form } => write!(f, "malformed {}", form),
+            RosetteError::Unsupported { form } => write!(f, "unsupported {}", form),
+            RosetteError::Unbound { name } => write!(f, "unbound symbol {}", name),
+            RosetteError::Elaboration(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl std::error::Error for RosetteError {}
+
+fn atom(s: impl ToString) -> Sexp {
+    Sexp::Atom(s.to_string())
+}
+
+/// The op of a Rosette bitvector function.
+fn rosette_op(f: &str) -> Option<&'static str> {
+    match f {
+        "bvand" => Some("and"),
+        "bvor" => Some("or"),
+        "bvxor" => Some("xor"),
+        "bvadd" => Some("add"),
+        "bvsub" => Some("sub"),
+        "bvmul" => Some("mul"),
+        "bvashr" => Some("asr"),
+        "bvlshr" => Some("lsr"),
+        "bvnot" => Some("not"),
+        "bvneg" => Some("neg"),
+        _ => None,
+    }
+}
+
+/// A width, written `8` or `(bitvector 8)`.
+fn width(sexp: &Sexp) -> Option<usize> {
+    match sexp {
+        Sexp::Atom(a) => a.parse().ok(),
+        Sexp::List(l) => match l.as_slice() {
+            [Sexp::Atom(head), w] if head == "bitvector" => w.as_atom()?.parse().ok(),
+            _ => None,
+        },
+    }
+}
+
+/// A literal value: decimal, `#x..`, or `#b..`.
+fn value(sexp: &Sexp) -> Option<i64> {
+    let a = sexp.as_atom()?;
+    if let Some(hex) = a.strip_prefix("#x") {
+        u64::from_str_radix(hex, 16).ok().map(|v| v as i64)
+    } else if let Some(bin) = a.strip_prefix("#b") {
+        u64::from_str_radix(bin, 2).ok().map(|v| v as i64)
+    } else {
+        a.parse().ok()
+    }
+}
+
+/// `(bv value width)`, as a value and width.
+fn bv(sexp: &Sexp) -> Option<(i64, usize)> {
+    match sexp.as_list()? {
+        [Sexp::Atom(head), v, w] if head == "bv" => Some((value(v)?, width(w)?)),
+        _ => None,
+    }
+}
+
+fn translate(sexp: &Sexp, widths: &HashMap<String, usize>) -> Result<Sexp, RosetteError> {
+    let malformed = || RosetteError::Malformed {
+        form: sexp.to_string(),
+    };
+    let unsupported = || RosetteError::Unsupported {
+        form: sexp.to_string(),
+    };
+    let list = match sexp {
+        Sexp::Atom(name) => {
+            let w = widths
+                .get(name)
+                .ok_or_else(|| RosetteError::Unbound { name: name.clone() })?;
+            return Ok(Sexp::List(vec![atom("var"), atom(name), atom(w)]));
+        }
+        Sexp::List(list) => list,
+    };
+    if let Some((v, w)) = bv(sexp) {
+        return Ok(Sexp::List(vec![atom("const"), atom(v), atom(w)]));
+    }
+    let eq = |cmp: &Sexp| -> Result<Sexp, RosetteError> {
+        match cmp.as_list() {
+            Some([Sexp::Atom(head), a, b]) if head == "bveq" => Ok(Sexp::List(vec![
+                atom("binop"),
+                atom("eq"),
+                translate(a, widths)?,
+                translate(b, widths)?,
+            ])),
+            _ => Err(unsupported()),
+        }
+    };
+    let head = list.first().and_then(Sexp::as_atom).ok_or_else(malformed)?;
+    match (head, &list[1..]) {
+        ("bool->bitvector", [cmp]) => eq(cmp),
+        ("bool->bitvector", [cmp, w]) => {
+            let w = width(w).ok_or_else(malformed)?;
+            match eq(cmp)? {
+                Sexp::List(mut l) => {
+                    l.insert(2, atom(w));
+                    Ok(Sexp::List(l))
+                }
+                Sexp::Atom(_) => unreachable!(),
+            }
+        }
+        ("if", [cmp, t, e]) => match (bv(t), bv(e)) {
+            (Some((1, w)), Some((0, w2))) if w == w2 => match eq(cmp)? {
+                Sexp::List(mut l) => {
+                    l.insert(2, atom(w));
+                    Ok(Sexp::List(l))
+                }
+                Sexp::Atom(_) => unreachable!(),
+            },
+            _ => Err(unsupported()),
+        },
+        (f, [a]) if matches!(rosette_op(f), Some("not" | "neg")) => Ok(Sexp::List(vec![
+            atom("unop"),
+            atom(rosette_op(f).unwrap()),
+            translate(a, widths)?,
+        ])),
+        // Rosette's associative ops are n-ary; fold them to the left.
+        (f, [first, rest @ ..]) if rosette_op(f).is_some() && !rest.is_empty() => {
+            let op = rosette_op(f).unwrap();
+            if rest.len() > 1 && !matches!(op, "and" | "or" | "xor" | "add") {
+                return Err(malformed());
+            }
+            rest.iter().try_fold(translate(first, widths)?, |acc, arg| {
+                Ok(Sexp::List(vec![
+                    atom("binop"),
+                    atom(op),
+                    acc,
+                    translate(arg, widths)?,
+                ]))
+            })
+        }
+        _ => Err(unsupported()),
+    }
+}
+
+/// Imports a single Rosette expression whose variables have the given
+/// widths.
+pub fn import_rosette_expr(
+    input: &str,
+    widths: &HashMap<String, usize>,
+) -> Result<RecExpr<Language>, RosetteError> {
+    import_rosette_with(input, widths.clone())
+}
+
+/// Imports a Rosette expression, preceded by `define-symbolic` forms giving
+/// the widths of its variables.
+pub fn import_rosette(input: &str) -> Result<RecExpr<Language>, RosetteError> {
+    import_rosette_with(input, HashMap::default())
+}
+
+fn import_rosette_with(
+    input: &str,
+    mut widths: HashMap<String, usize>,
+) -> Result<RecExpr<Language>, RosetteError> {
+    let forms = parse_all(input).map_err(RosetteError::Parse)?;
+    let (expr, definitions) = forms
+        .split_last()
+        .ok_or(RosetteError::Parse(SexpError::UnexpectedEof))?;
+    for definition in definitions {
+        let malformed = || RosetteError::Malformed {
+            form: definition.to_string(),
+        };
+        match definition.as_list() {
+            Some([Sexp::Atom(head), names @ .., ty]) if head == "define-symbolic" => {
+                let w = width(ty).ok_or_else(malformed)?;
+                for name in names {
+                    widths.insert(name.as_atom().ok_or_else(malformed)?.to_string(), w);
+                }
+            }
+            _ => {
+                return Err(RosetteError::Unsupported {
+                    form: definition.to_string(),
+                })
+            }
+        }
+    }
+    elaborate_sexp(&translate(expr, &widths)?).map_err(RosetteError::Elaboration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{example_programs::all_programs, solver::to_racket};
+
+    #[test]
+    fn round_trip_through_to_racket() {
+        for name in ["bithack_ceil_avg", "bithack_bithack2", "bithack_cycle"] {
+            let expr = &all_programs()[name];
+            let (racket, symbols) = to_racket(expr, (expr.as_ref().len() - 1).into()).unwrap();
+            let widths = symbols
+                .iter()
+                .map(|(name, width)| (name.to_string(), width))
+                .collect();
+            assert_eq!(
+                import_rosette_expr(&racket, &widths).unwrap().to_string(),
+                expr.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn define_symbolic_and_literals() {
+        assert_eq!(
+            import_rosette(
+                "(define-symbolic x y (bitvector 8))
+                 (bvadd x y (bv #x01 8) (if (bveq x y) (bv 1 8) (bv 0 8)))"
+            )
+            .unwrap()
+            .to_string(),
+            "(binop add 8 (binop add 8 (binop add 8 (var x 8) (var y 8)) (const 1 8)) \
+             (binop eq 8 (var x 8) (var y 8)))"
+        );
+        assert_eq!(
+            import_rosette("(bvand x y)"),
+            Err(RosetteError::Unbound {
+                name: "x".to_string()
+            })
+        );
+        assert!(matches!(
+            import_rosette("(define-symbolic x (bitvector 8)) (bvmul x x)"),
+            Err(RosetteError::Unsupported { .. })
+        ));
+    }
+}
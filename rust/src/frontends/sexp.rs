@@ -0,0 +1,160 @@
+//! A minimal s-expression reader shared by the textual frontends.
+
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sexp {
+    Atom(String),
+    List(Vec<Sexp>),
+}
+
+impl Sexp {
+    pub fn as_atom(&self) -> Option<&str> {
+        match self {
+            Sexp::Atom(a) => Some(a),
+            Sexp::List(_) => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Sexp]> {
+        match self {
+            Sexp::Atom(_) => None,
+            Sexp::List(l) => Some(l),
+        }
+    }
+}
+
+impl Display for Sexp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sexp::Atom(a) => write!(f, "{}", a),
+            Sexp::List(l) => {
+                write!(f, "(")?;
+                for (i, s) in l.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", s)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SexpError {
+    UnexpectedEof,
+    UnexpectedClose { offset: usize },
+    TrailingInput { offset: usize },
+}
+impl Display for SexpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SexpError::UnexpectedEof => write!(f, "unexpected end of input"),
+            SexpError::UnexpectedClose { offset } => {
+                write!(f, "unexpected ')' at offset {}", offset)
+            }
+            SexpError::TrailingInput { offset } => {
+                write!(f, "unexpected input after expression at offset {}", offset)
+            }
+        }
+    }
+}
+impl std::error::Error for SexpError {}
+
+struct Reader<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Skips whitespace and `;` line comments.
+    fn skip_trivia(&mut self) {
+        loop {
+            let rest = &self.input[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with(';') {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn read(&mut self) -> Result<Sexp, SexpError> {
+        self.skip_trivia();
+        match self.input[self.pos..].chars().next() {
+            None => Err(SexpError::UnexpectedEof),
+            Some(')') => Err(SexpError::UnexpectedClose { offset: self.pos }),
+            Some('(') => {
+                self.pos += 1;
+                let mut items = vec![];
+                loop {
+                    self.skip_trivia();
+                    match self.input[self.pos..].chars().next() {
+                        None => return Err(SexpError::UnexpectedEof),
+                        Some(')') => {
+                            self.pos += 1;
+                            return Ok(Sexp::List(items));
+                        }
+                        Some(_) => items.push(self.read()?),
+                    }
+                }
+            }
+            Some(_) => {
+                let rest = &self.input[self.pos..];
+                let len = rest
+                    .find(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == ';')
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                Ok(Sexp::Atom(rest[..len].to_string()))
+            }
+        }
+    }
+}
+
+/// Parses a single s-expression.
+pub fn parse(input: &str) -> Result<Sexp, SexpError> {
+    let mut sexps = parse_all(input)?;
+    match sexps.len() {
+        0 => Err(SexpError::UnexpectedEof),
+        1 => Ok(sexps.pop().unwrap()),
+        _ => {
+            // Report where the second expression starts.
+            let mut reader = Reader { input, pos: 0 };
+            reader.read()?;
+            reader.skip_trivia();
+            Err(SexpError::TrailingInput { offset: reader.pos })
+        }
+    }
+}
+
+/// Parses a sequence of s-expressions, e.g. the top-level forms of a file.
+pub fn parse_all(input: &str) -> Result<Vec<Sexp>, SexpError> {
+    let mut reader = Reader { input, pos: 0 };
+    let mut out = vec![];
+    loop {
+        reader.skip_trivia();
+        if reader.pos == input.len() {
+            return Ok(out);
+        }
+        out.push(reader.read()?);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sexps() {
+        let sexp = parse("(binop and ; a comment\n (var x) 8)").unwrap();
+        assert_eq!(sexp.to_string(), "(binop and (var x) 8)");
+        assert_eq!(parse_all("a (b) c").unwrap().len(), 3);
+        assert_eq!(parse("(a"), Err(SexpError::UnexpectedEof));
+        assert_eq!(parse(")"), Err(SexpError::UnexpectedClose { offset: 0 }));
+        assert_eq!(parse("a b"), Err(SexpError::TrailingInput { offset: 2 }));
+    }
+}
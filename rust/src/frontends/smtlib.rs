@@ -0,0 +1,480 @@
+//! Importer for SMT-LIB2 bitvector (QF_BV) terms.
+//!
+//! [`import_smtlib`] reads a script and turns each `define-fun` returning a
+//! bitvector into a program named after the function, with its parameters
+//! (and any `declare-const`s/`declare-fun`s it mentions) as `var`s. Calls to
+//! earlier `define-fun`s and `let`s are inlined. [`import_smt_term`]
+//! translates a single term given the widths of its free variables.
+//!
+//! Supported operators are `bvnot`, `bvneg`, `bvand`, `bvor`, `bvxor`,
+//! `bvadd`, `bvsub`, `bvmul`, `bvlshr`, and `bvashr`. Since the language's
+//! `eq` produces a full-width 0 or 1, `=` is only supported in the form
+//! `(ite (= a b) (_ bv1 w) (_ bv0 w))` with `w` the width of `a` and `b`.
+
+use std::{collections::HashMap, fmt::Display};
+
+use egg::RecExpr;
+
+use crate::{
+    frontends::{
+        elaborate::{elaborate_sexp_at, ElaborationError},
+        sexp::{self, Sexp, SexpError},
+    },
+    language::Language,
+    program_set::ProgramSet,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmtError {
+    Parse(SexpError),
+    Malformed {
+        form: String,
+    },
+    Unsupported {
+        form: String,
+    },
+    Unbound {
+        name: String,
+    },
+    SortMismatch {
+        form: String,
+    },
+    Elaboration {
+        name: String,
+        error: ElaborationError,
+    },
+}
+impl Display for SmtError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SmtError::Parse(e) => write!(f, "{}", e),
+            SmtError::Malformed { form } => write!(f, "malformed {}", form),
+            SmtError::Unsupported { form } => write!(f, "unsupported {}", form),
+            SmtError::Unbound { name } => write!(f, "unbound symbol {}", name),
+            SmtError::SortMismatch { form } => write!(f, "ill-sorted {}", form),
+            SmtError::Elaboration { name, error } => write!(f, "{}: {}", name, error),
+        }
+    }
+}
+impl std::error::Error for SmtError {}
+impl From<SexpError> for SmtError {
+    fn from(e: SexpError) -> Self {
+        SmtError::Parse(e)
+    }
+}
+
+/// A translated term.
+#[derive(Debug, Clone)]
+enum Term {
+    BitVec {
+        expr: Sexp,
+        width: usize,
+    },
+    /// `(= a b)` on bitvectors of the given width.
+    Eq {
+        a: Sexp,
+        b: Sexp,
+        width: usize,
+    },
+}
+
+fn atom(s: &str) -> Sexp {
+    Sexp::Atom(s.to_string())
+}
+
+fn malformed(form: &Sexp) -> SmtError {
+    SmtError::Malformed {
+        form: form.to_string(),
+    }
+}
+
+fn unsupported(form: &Sexp) -> SmtError {
+    SmtError::Unsupported {
+        form: form.to_string(),
+    }
+}
+
+/// Parses `(_ BitVec w)`.
+fn sort(sexp: &Sexp) -> Result<usize, SmtError> {
+    match sexp.as_list() {
+        Some([u, bv, w]) if u.as_atom() == Some("_") && bv.as_atom() == Some("BitVec") => w
+            .as_atom()
+            .and_then(|w| w.parse().ok())
+            .filter(|w| *w > 0)
+            .ok_or_else(|| malformed(sexp)),
+        _ => Err(unsupported(sexp)),
+    }
+}
+
+/// Parses a bitvector literal: `#b...`, `#x...`, or `(_ bvN w)`.
+fn literal(sexp: &Sexp) -> Option<Result<(i64, usize), SmtError>> {
+    let parse = |digits: &str, radix, bits_per_digit| {
+        if digits.len() * bits_per_digit > 64 {
+            return Err(unsupported(sexp));
+        }
+        u64::from_str_radix(digits, radix)
+            .map(|v| (v as i64, digits.len() * bits_per_digit))
+            .map_err(|_| malformed(sexp))
+    };
+    match sexp {
+        Sexp::Atom(a) => {
+            if let Some(digits) = a.strip_prefix("#b") {
+                Some(parse(digits, 2, 1))
+            } else {
+                a.strip_prefix("#x").map(|digits| parse(digits, 16, 4))
+            }
+        }
+        Sexp::List(items) => match items.as_slice() {
+            [u, bv, w] if u.as_atom() == Some("_") => {
+                let value = bv.as_atom()?.strip_prefix("bv")?;
+                Some(
+                    match (value.parse::<u64>(), w.as_atom().map(str::parse::<usize>)) {
+                        (Ok(v), Some(Ok(w))) if w > 0 => Ok((v as i64, w)),
+                        _ => Err(malformed(sexp)),
+                    },
+                )
+            }
+            _ => None,
+        },
+    }
+}
+
+struct DefineFun {
+    params: Vec<(String, usize)>,
+    ret_width: usize,
+    body: Sexp,
+}
+
+#[derive(Default)]
+struct Context {
+    /// Widths of declared constants.
+    consts: HashMap<String, usize>,
+    functions: HashMap<String, DefineFun>,
+}
+
+impl Context {
+    fn bitvec(
+        &self,
+        sexp: &Sexp,
+        scope: &HashMap<String, Term>,
+    ) -> Result<(Sexp, usize), SmtError> {
+        match self.term(sexp, scope)? {
+            Term::BitVec { expr, width } => Ok((expr, width)),
+            Term::Eq { .. } => Err(SmtError::SortMismatch {
+                form: sexp.to_string(),
+            }),
+        }
+    }
+
+    fn term(&self, sexp: &Sexp, scope: &HashMap<String, Term>) -> Result<Term, SmtError> {
+        if let Some(literal) = literal(sexp) {
+            let (value, width) = literal?;
+            return Ok(Term::BitVec {
+                expr: Sexp::List(vec![
+                    atom("const"),
+                    atom(&value.to_string()),
+                    atom(&width.to_string()),
+                ]),
+                width,
+            });
+        }
+        let items = match sexp {
+            Sexp::Atom(name) => {
+                return match (scope.get(name), self.consts.get(name)) {
+                    (Some(term), _) => Ok(term.clone()),
+                    (None, Some(width)) => Ok(Term::BitVec {
+                        expr: Sexp::List(vec![atom("var"), atom(name), atom(&width.to_string())]),
+                        width: *width,
+                    }),
+                    (None, None) => self.call(sexp, name, &[], scope),
+                }
+            }
+            Sexp::List(items) => items,
+        };
+        let (head, args) = items.split_first().ok_or_else(|| malformed(sexp))?;
+        let head = head.as_atom().ok_or_else(|| unsupported(sexp))?;
+        let same_width = |args: &[Sexp]| -> Result<(Vec<Sexp>, usize), SmtError> {
+            let mut width = None;
+            let mut out = vec![];
+            for arg in args {
+                let (expr, w) = self.bitvec(arg, scope)?;
+                if *width.get_or_insert(w) != w {
+                    return Err(SmtError::SortMismatch {
+                        form: sexp.to_string(),
+                    });
+                }
+                out.push(expr);
+            }
+            Ok((out, width.ok_or_else(|| malformed(sexp))?))
+        };
+        let op = match head {
+            "bvnot" => Some("not"),
+            "bvneg" => Some("neg"),
+            "bvand" => Some("and"),
+            "bvor" => Some("or"),
+            "bvxor" => Some("xor"),
+            "bvadd" => Some("add"),
+            "bvsub" => Some("sub"),
+            "bvmul" => Some("mul"),
+            "bvlshr" => Some("lsr"),
+            "bvashr" => Some("asr"),
+            _ => None,
+        };
+        match (head, op) {
+            ("bvnot" | "bvneg", Some(op)) => {
+                let (mut exprs, width) = same_width(args)?;
+                if exprs.len() != 1 {
+                    return Err(malformed(sexp));
+                }
+                Ok(Term::BitVec {
+                    expr: Sexp::List(vec![
+                        atom("unop"),
+                        atom(op),
+                        atom(&width.to_string()),
+                        exprs.remove(0),
+                    ]),
+                    width,
+                })
+            }
+            (_, Some(op)) => {
+                let (exprs, width) = same_width(args)?;
+                // bvand, bvor, bvxor, and bvadd are left-associative.
+                let n_ary = matches!(op, "and" | "or" | "xor" | "add");
+                if exprs.len() < 2 || (!n_ary && exprs.len() != 2) {
+                    return Err(malformed(sexp));
+                }
+                let mut exprs = exprs.into_iter();
+                let first = exprs.next().unwrap();
+                let expr = exprs.fold(first, |acc, e| {
+                    Sexp::List(vec![
+                        atom("binop"),
+                        atom(op),
+                        atom(&width.to_string()),
+                        acc,
+                        e,
+                    ])
+                });
+                Ok(Term::BitVec { expr, width })
+            }
+            ("=", None) => {
+                let (mut exprs, width) = same_width(args)?;
+                if exprs.len() != 2 {
+                    return Err(unsupported(sexp));
+                }
+                let b = exprs.pop().unwrap();
+                let a = exprs.pop().unwrap();
+                Ok(Term::Eq { a, b, width })
+            }
+            ("ite", None) => match args {
+                [cond, then, els] => match (
+                    self.term(cond, scope)?,
+                    literal(then).transpose()?,
+                    literal(els).transpose()?,
+                ) {
+                    (Term::Eq { a, b, width }, Some((1, w1)), Some((0, w0)))
+                        if w1 == width && w0 == width =>
+                    {
+                        Ok(Term::BitVec {
+                            expr: Sexp::List(vec![
+                                atom("binop"),
+                                atom("eq"),
+                                atom(&width.to_string()),
+                                a,
+                                b,
+                            ]),
+                            width,
+                        })
+                    }
+                    _ => Err(unsupported(sexp)),
+                },
+                _ => Err(malformed(sexp)),
+            },
+            ("let", None) => match args {
+                [Sexp::List(bindings), body] => {
+                    // Bindings are simultaneous, so evaluate them all in the
+                    // outer scope.
+                    let mut inner = scope.clone();
+                    for binding in bindings {
+                        match binding.as_list() {
+                            Some([Sexp::Atom(name), value]) => {
+                                inner.insert(name.clone(), self.term(value, scope)?);
+                            }
+                            _ => return Err(malformed(binding)),
+                        }
+                    }
+                    self.term(body, &inner)
+                }
+                _ => Err(malformed(sexp)),
+            },
+            _ => self.call(sexp, head, args, scope),
+        }
+    }
+
+    /// Inlines a call to a `define-fun`.
+    fn call(
+        &self,
+        sexp: &Sexp,
+        name: &str,
+        args: &[Sexp],
+        scope: &HashMap<String, Term>,
+    ) -> Result<Term, SmtError> {
+        let function = match self.functions.get(name) {
+            Some(function) => function,
+            None if name.starts_with("bv") || !args.is_empty() => return Err(unsupported(sexp)),
+            None => {
+                return Err(SmtError::Unbound {
+                    name: name.to_string(),
+                })
+            }
+        };
+        if function.params.len() != args.len() {
+            return Err(malformed(sexp));
+        }
+        let mut inner = HashMap::default();
+        for ((param, width), arg) in function.params.iter().zip(args) {
+            let (expr, w) = self.bitvec(arg, scope)?;
+            if w != *width {
+                return Err(SmtError::SortMismatch {
+                    form: sexp.to_string(),
+                });
+            }
+            inner.insert(param.clone(), Term::BitVec { expr, width: w });
+        }
+        self.term(&function.body, &inner)
+    }
+}
+
+/// Translates a single bitvector term whose free variables have the given
+/// widths.
+pub fn import_smt_term(
+    input: &str,
+    widths: &HashMap<String, usize>,
+) -> Result<RecExpr<Language>, SmtError> {
+    let context = Context {
+        consts: widths.clone(),
+        ..Context::default()
+    };
+    let (expr, width) = context.bitvec(&sexp::parse(input)?, &HashMap::default())?;
+    elaborate_sexp_at(&expr, Some(width)).map_err(|error| SmtError::Elaboration {
+        name: input.to_string(),
+        error,
+    })
+}
+
+/// Imports every bitvector-valued `define-fun` in an SMT-LIB2 script.
+pub fn import_smtlib(input: &str) -> Result<ProgramSet, SmtError> {
+    let mut context = Context::default();
+    let mut programs = ProgramSet::new();
+    for command in sexp::parse_all(input)? {
+        let items = command.as_list().ok_or_else(|| malformed(&command))?;
+        match items {
+            [head, Sexp::Atom(name), sort_sexp] if head.as_atom() == Some("declare-const") => {
+                context.consts.insert(name.clone(), sort(sort_sexp)?);
+            }
+            [head, Sexp::Atom(name), Sexp::List(args), sort_sexp]
+                if head.as_atom() == Some("declare-fun") && args.is_empty() =>
+            {
+                context.consts.insert(name.clone(), sort(sort_sexp)?);
+            }
+            [head, Sexp::Atom(name), Sexp::List(params), ret, body]
+                if head.as_atom() == Some("define-fun") =>
+            {
+                let params = params
+                    .iter()
+                    .map(|param| match param.as_list() {
+                        Some([Sexp::Atom(name), param_sort]) => {
+                            Ok((name.clone(), sort(param_sort)?))
+                        }
+                        _ => Err(malformed(param)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let function = DefineFun {
+                    params,
+                    ret_width: sort(ret)?,
+                    body: body.clone(),
+                };
+
+                // The parameters become variables of the program.
+                let scope = function
+                    .params
+                    .iter()
+                    .map(|(param, width)| {
+                        (
+                            param.clone(),
+                            Term::BitVec {
+                                expr: Sexp::List(vec![
+                                    atom("var"),
+                                    atom(param),
+                                    atom(&width.to_string()),
+                                ]),
+                                width: *width,
+                            },
+                        )
+                    })
+                    .collect();
+                let (expr, _) = context.bitvec(&function.body, &scope)?;
+                let expr = elaborate_sexp_at(&expr, Some(function.ret_width)).map_err(|error| {
+                    SmtError::Elaboration {
+                        name: name.clone(),
+                        error,
+                    }
+                })?;
+                programs.add(name.clone(), expr);
+                context.functions.insert(name.clone(), function);
+            }
+            // Everything else (set-logic, assert, check-sat, ...) doesn't
+            // define programs.
+            _ => (),
+        }
+    }
+    Ok(programs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::example_programs::all_programs;
+
+    #[test]
+    fn import_ceil_avg() {
+        let programs = import_smtlib(
+            "(set-logic QF_BV)
+             (define-fun half ((v (_ BitVec 8))) (_ BitVec 8) (bvashr v #x01))
+             (define-fun ceil_avg ((x (_ BitVec 8)) (y (_ BitVec 8))) (_ BitVec 8)
+               (let ((o (bvor x y)))
+                 (bvsub o (half (bvxor x y)))))
+             (check-sat)",
+        )
+        .unwrap();
+        assert_eq!(programs.len(), 2);
+        assert_eq!(
+            programs.get("ceil_avg").unwrap().expr.to_string(),
+            all_programs()["bithack_ceil_avg"].to_string()
+        );
+    }
+
+    #[test]
+    fn terms() {
+        let widths = HashMap::from([("a".to_string(), 4), ("b".to_string(), 4)]);
+        assert_eq!(
+            import_smt_term("(ite (= a b) (_ bv1 4) (_ bv0 4))", &widths)
+                .unwrap()
+                .to_string(),
+            "(binop eq 4 (var a 4) (var b 4))"
+        );
+        assert_eq!(
+            import_smt_term("(bvand a b #b0011)", &widths)
+                .unwrap()
+                .to_string(),
+            "(binop and 4 (binop and 4 (var a 4) (var b 4)) (const 3 4))"
+        );
+        assert!(matches!(
+            import_smt_term("(bvmul a b)", &widths),
+            Err(SmtError::Unsupported { .. })
+        ));
+        assert!(matches!(
+            import_smt_term("(bvand a #x01)", &widths),
+            Err(SmtError::SortMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,344 @@
+//! Importer for Souper IR, so the peephole-optimization candidates Souper
+//! harvests can be used as exploration input.
+//!
+//! A file holds one or more candidates separated by blank lines. Each left-
+//! hand side ends in `infer %x`, which becomes a program named `lhsN`; a
+//! following `result %y` (or a `cand %x %y` line) provides the right-hand
+//! side, named `rhsN`:
+//!
+//! ```text
+//! %0:i8 = var
+//! %1:i8 = var
+//! %2:i8 = or %0, %1
+//! %3:i8 = xor %0, %1
+//! %4:i8 = ashr %3, 1:i8
+//! %5:i8 = sub %2, %4
+//! infer %5
+//! ```
+//!
+//! Each `var` becomes a `var` named after its value (`x0`, `x1`, …), and
+//! dataflow facts attached to it, like `(knownBits=...)`, are dropped. Path
+//! conditions (`pc`, `blockpc`) only restrict the context the candidate
+//! appears in, so they are dropped too. Instructions map through
+//! [`llvm_op`], ignoring Souper's `nsw`/`nuw`/`exact` variants; `eq` is
+//! supported when `zext`ed (or `sext`ed, as a negation) back to the width of
+//! its operands.
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    frontends::{
+        elaborate::{elaborate_sexp_at, ElaborationError},
+        llvm::llvm_op,
+        sexp::Sexp,
+    },
+    program_set::ProgramSet,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SouperError {
+    Malformed {
+        line: usize,
+        what: String,
+    },
+    UnsupportedInstruction {
+        line: usize,
+        instruction: String,
+    },
+    Undefined {
+        line: usize,
+        name: String,
+    },
+    /// A value used at a width other than its own.
+    WidthMismatch {
+        line: usize,
+        name: String,
+    },
+    Elaboration {
+        name: String,
+        error: ElaborationError,
+    },
+}
+impl Display for SouperError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SouperError::Malformed { line, what } => write!(f, "line {}: malformed {}", line, what),
+            SouperError::UnsupportedInstruction { line, instruction } => {
+                write!(f, "line {}: unsupported instruction {}", line, instruction)
+            }
+            SouperError::Undefined { line, name } => {
+                write!(f, "line {}: undefined value {}", line, name)
+            }
+            SouperError::WidthMismatch { line, name } => {
+                write!(f, "line {}: {} used at the wrong width", line, name)
+            }
+            SouperError::Elaboration { name, error } => write!(f, "{}: {}", name, error),
+        }
+    }
+}
+impl std::error::Error for SouperError {}
+
+fn atom(s: impl ToString) -> Sexp {
+    Sexp::Atom(s.to_string())
+}
+
+/// A computed value.
+#[derive(Debug, Clone)]
+enum Value {
+    /// An expression, and its width if known.
+    Int { expr: Sexp, width: Option<usize> },
+    /// The `i1` result of `eq` on operands of the given width.
+    Eq { a: Sexp, b: Sexp, width: usize },
+}
+
+/// Strips Souper's flagged variants, e.g. `addnsw` or `lshrexact`.
+fn base_instruction(instruction: &str) -> &str {
+    ["nsw", "nuw", "nw", "exact"]
+        .iter()
+        .find_map(|flag| instruction.strip_suffix(flag))
+        .unwrap_or(instruction)
+}
+
+/// Splits `%2:i8` or `1:i8` into its name or value and its width.
+fn typed(line: usize, text: &str) -> Result<(&str, Option<usize>), SouperError> {
+    match text.trim().split_once(':') {
+        Some((value, ty)) => {
+            let width = ty
+                .strip_prefix('i')
+                .and_then(|w| w.parse().ok())
+                .filter(|w| *w > 0)
+                .ok_or_else(|| SouperError::Malformed {
+                    line,
+                    what: format!("type {}", ty),
+                })?;
+            Ok((value, Some(width)))
+        }
+        None => Ok((text.trim(), None)),
+    }
+}
+
+#[derive(Default)]
+struct Candidate {
+    values: HashMap<String, Value>,
+    lhs: Option<Sexp>,
+    rhs: Option<Sexp>,
+    width: Option<usize>,
+}
+
+impl Candidate {
+    fn operand(&self, line: usize, text: &str) -> Result<(Sexp, Option<usize>), SouperError> {
+        let (value, width) = typed(line, text)?;
+        if value.starts_with('%') {
+            return match self.values.get(value) {
+                Some(Value::Int { expr, width: w }) => Ok((expr.clone(), *w)),
+                Some(Value::Eq { .. }) => Err(SouperError::WidthMismatch {
+                    line,
+                    name: value.to_string(),
+                }),
+                None => Err(SouperError::Undefined {
+                    line,
+                    name: value.to_string(),
+                }),
+            };
+        }
+        let v = value.parse::<i64>().map_err(|_| SouperError::Malformed {
+            line,
+            what: format!("operand {}", text.trim()),
+        })?;
+        let mut expr = vec![atom("const"), atom(v)];
+        expr.extend(width.map(atom));
+        Ok((Sexp::List(expr), width))
+    }
+
+    fn instruction(&mut self, line: usize, text: &str) -> Result<(), SouperError> {
+        let malformed = |what: &str| SouperError::Malformed {
+            line,
+            what: what.to_string(),
+        };
+        let (dest, rhs) = text.split_once('=').ok_or_else(|| malformed(text))?;
+        let (dest, dest_width) = typed(line, dest)?;
+        // Drop dataflow facts, e.g. `var (knownBits=0xxxxxxx)`.
+        let rhs = rhs.split(" (").next().unwrap().trim();
+        let (instruction, operands) = rhs.split_once(' ').unwrap_or((rhs, ""));
+        let instruction = base_instruction(instruction);
+        let unsupported = || SouperError::UnsupportedInstruction {
+            line,
+            instruction: instruction.to_string(),
+        };
+
+        // Extensions are only supported on the result of an `eq`, which
+        // isn't an operand in its own right.
+        if matches!(instruction, "zext" | "sext") {
+            let value = match self.values.get(typed(line, operands)?.0) {
+                Some(Value::Eq { a, b, width }) if dest_width == Some(*width) => {
+                    let eq = Sexp::List(vec![
+                        atom("binop"),
+                        atom("eq"),
+                        atom(width),
+                        a.clone(),
+                        b.clone(),
+                    ]);
+                    Value::Int {
+                        expr: if instruction == "zext" {
+                            eq
+                        } else {
+                            Sexp::List(vec![atom("unop"), atom("neg"), atom(width), eq])
+                        },
+                        width: dest_width,
+                    }
+                }
+                _ => return Err(unsupported()),
+            };
+            self.values.insert(dest.to_string(), value);
+            return Ok(());
+        }
+
+        let operands = operands
+            .split(',')
+            .filter(|o| !o.trim().is_empty())
+            .map(|o| self.operand(line, o))
+            .collect::<Result<Vec<_>, _>>()?;
+        let width = operands.iter().find_map(|(_, w)| *w).or(dest_width);
+        let value = match (instruction, operands.as_slice()) {
+            ("var", []) => {
+                let width = dest_width.ok_or_else(|| malformed("var without a width"))?;
+                let name = format!("x{}", dest.trim_start_matches('%'));
+                Value::Int {
+                    expr: Sexp::List(vec![atom("var"), atom(name), atom(width)]),
+                    width: Some(width),
+                }
+            }
+            ("eq", [(a, _), (b, _)]) => Value::Eq {
+                a: a.clone(),
+                b: b.clone(),
+                width: width.ok_or_else(|| malformed("eq of unknown width"))?,
+            },
+            (op, [(a, _), (b, _)]) => {
+                let op = llvm_op(op).ok_or_else(unsupported)?;
+                let mut expr = vec![atom("binop"), atom(op)];
+                expr.extend(width.map(atom));
+                expr.extend([a.clone(), b.clone()]);
+                Value::Int {
+                    expr: Sexp::List(expr),
+                    width,
+                }
+            }
+            _ => return Err(unsupported()),
+        };
+        self.values.insert(dest.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Imports every candidate in a Souper file.
+pub fn import_souper(input: &str) -> Result<ProgramSet, SouperError> {
+    let mut programs = ProgramSet::new();
+    let mut candidate = Candidate::default();
+    let mut count = 0;
+    let mut finish =
+        |candidate: &mut Candidate, programs: &mut ProgramSet| -> Result<(), SouperError> {
+            // Blank lines inside a candidate don't end it.
+            if candidate.lhs.is_none() && candidate.rhs.is_none() {
+                return Ok(());
+            }
+            let candidate = std::mem::take(candidate);
+            for (side, expr) in [("lhs", candidate.lhs), ("rhs", candidate.rhs)] {
+                if let Some(expr) = expr {
+                    let name = format!("{}{}", side, count);
+                    let expr = elaborate_sexp_at(&expr, candidate.width).map_err(|error| {
+                        SouperError::Elaboration {
+                            name: name.clone(),
+                            error,
+                        }
+                    })?;
+                    programs.add(name, expr);
+                }
+            }
+            count += 1;
+            Ok(())
+        };
+
+    for (i, text) in input.lines().enumerate() {
+        let line = i + 1;
+        let text = text.split(';').next().unwrap().trim();
+        if text.is_empty() {
+            finish(&mut candidate, &mut programs)?;
+            continue;
+        }
+        let (keyword, rest) = text.split_once(' ').unwrap_or((text, ""));
+        match keyword {
+            "infer" => {
+                let (lhs, width) = candidate.operand(line, rest)?;
+                candidate.lhs = Some(lhs);
+                candidate.width = width;
+            }
+            "result" => candidate.rhs = Some(candidate.operand(line, rest)?.0),
+            "cand" => {
+                let (lhs, rhs) = rest.split_once(' ').ok_or(SouperError::Malformed {
+                    line,
+                    what: "cand".to_string(),
+                })?;
+                let (lhs, width) = candidate.operand(line, lhs)?;
+                candidate.lhs = Some(lhs);
+                candidate.width = width;
+                candidate.rhs = Some(candidate.operand(line, rhs)?.0);
+            }
+            "pc" | "blockpc" => (),
+            _ => candidate.instruction(line, text)?,
+        }
+    }
+    finish(&mut candidate, &mut programs)?;
+    Ok(programs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_candidates() {
+        let programs = import_souper(
+            "%0:i8 = var ; x
+             %1:i8 = var (knownBits=xxxxxxx0)
+             %2:i8 = or %0, %1
+             %3 = xor %0, %1
+             %4 = ashrexact %3, 1:i8
+             %5 = sub %2, %4
+             infer %5
+
+             %0:i4 = var
+             %1:i1 = eq %0, 3:i4
+             %2:i4 = sext %1
+             pc %1 1:i1
+             infer %2
+             result 0:i4",
+        )
+        .unwrap();
+        assert_eq!(
+            programs.get("lhs0").unwrap().expr.to_string(),
+            "(binop sub 8 (binop or 8 (var x0 8) (var x1 8)) \
+             (binop asr 8 (binop xor 8 (var x0 8) (var x1 8)) (const 1 8)))"
+        );
+        assert_eq!(
+            programs.get("lhs1").unwrap().expr.to_string(),
+            "(unop neg 4 (binop eq 4 (var x0 4) (const 3 4)))"
+        );
+        assert_eq!(
+            programs.get("rhs1").unwrap().expr.to_string(),
+            "(const 0 4)"
+        );
+        assert!(programs.get("rhs0").is_none());
+    }
+
+    #[test]
+    fn unsupported_instructions() {
+        assert!(matches!(
+            import_souper("%0:i8 = var\n%1:i8 = mul %0, %0\ninfer %1"),
+            Err(SouperError::UnsupportedInstruction { line: 2, .. })
+        ));
+        assert!(matches!(
+            import_souper("%0:i8 = var\n%1:i1 = eq %0, %0\n%2:i16 = zext %1\ninfer %2"),
+            Err(SouperError::UnsupportedInstruction { line: 3, .. })
+        ));
+    }
+}
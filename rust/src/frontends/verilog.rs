@@ -0,0 +1,634 @@
+//! Importer for combinational Verilog modules.
+//!
+//! Handles the subset of Verilog our benchmarks are written in: a module whose
+//! ports and wires are declared with `input`/`output`/`wire` (either in the
+//! port list or in the body, optionally `signed`, with a `[msb:lsb]` range),
+//! and whose body is a set of continuous `assign`s. Each output becomes one
+//! program, with the module's inputs as `var`s of their declared widths and
+//! intermediate wires inlined.
+//!
+//! Supported operators are `~`, unary `-`, `+`, `-`, `>>`, `>>>`, `==`, `&`,
+//! `^`, and `|`, plus `$signed(...)` and `$unsigned(...)`. As in Verilog,
+//! `>>>` is only an arithmetic shift on signed operands. Literal sizes are
+//! ignored; literals take the width of the expression they're used in. All
+//! operands of an operator must have the same width, and anything else
+//! (bit selects, concatenation, `always` blocks, ...) is rejected.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use crate::{
+    frontends::{
+        elaborate::{elaborate_sexp_at, ElaborationError},
+        sexp::Sexp,
+    },
+    program_set::ProgramSet,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerilogError {
+    UnexpectedToken {
+        line: usize,
+        found: String,
+    },
+    UnexpectedEof,
+    Undeclared {
+        name: String,
+    },
+    /// Verilog we don't handle, e.g. an unsupported operator or statement.
+    Unsupported {
+        line: usize,
+        what: String,
+    },
+    /// A wire which is never assigned, or assigned more than once.
+    BadDriver {
+        name: String,
+    },
+    CombinationalLoop {
+        name: String,
+    },
+    /// Elaborating the expression driving `net` failed, e.g. because of a
+    /// width mismatch.
+    Elaboration {
+        net: String,
+        error: ElaborationError,
+    },
+}
+impl Display for VerilogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerilogError::UnexpectedToken { line, found } => {
+                write!(f, "line {}: unexpected {:?}", line, found)
+            }
+            VerilogError::UnexpectedEof => write!(f, "unexpected end of input"),
+            VerilogError::Undeclared { name } => write!(f, "{} is not declared", name),
+            VerilogError::Unsupported { line, what } => {
+                write!(f, "line {}: unsupported {}", line, what)
+            }
+            VerilogError::BadDriver { name } => {
+                write!(f, "{} must be assigned exactly once", name)
+            }
+            VerilogError::CombinationalLoop { name } => {
+                write!(f, "combinational loop through {}", name)
+            }
+            VerilogError::Elaboration { net, error } => write!(f, "{}: {}", net, error),
+        }
+    }
+}
+impl std::error::Error for VerilogError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Punct(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "{}", s),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Punct(p) => write!(f, "{}", p),
+        }
+    }
+}
+
+/// Longest first.
+const PUNCTUATION: &[&str] = &[
+    ">>>", "<<<", "===", "!==", ">>", "<<", "==", "!=", "<=", ">=", "&&", "||", "(", ")", "[", "]",
+    "{", "}", ":", ";", ",", "=", "~", "-", "+", "*", "/", "%", "&", "|", "^", "!", "<", ">", "?",
+    "@", "#",
+];
+
+/// Parses the digits of a based literal, e.g. the `ff` in `8'hff`.
+fn parse_based(base: char, digits: &str) -> Option<i64> {
+    let radix = match base.to_ascii_lowercase() {
+        'b' => 2,
+        'o' => 8,
+        'd' => 10,
+        'h' => 16,
+        _ => return None,
+    };
+    i64::from_str_radix(&digits.replace('_', ""), radix).ok()
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, VerilogError> {
+    let mut out = vec![];
+    let mut pos = 0;
+    let line = |pos: usize| input[..pos].matches('\n').count() + 1;
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+    while pos < input.len() {
+        let rest = &input[pos..];
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+        } else if rest.starts_with("//") {
+            pos += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("/*") {
+            pos += rest.find("*/").map_or(rest.len(), |end| end + 2);
+        } else if c.is_ascii_digit() || c == '\'' {
+            // Either a plain decimal, or a (possibly sized) based literal
+            // like `8'hff` or `'sd3`.
+            let size_len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let (len, value) = if rest[size_len..].starts_with('\'') {
+                let mut i = size_len + 1;
+                if rest[i..].starts_with(|c| c == 's' || c == 'S') {
+                    i += 1;
+                }
+                match rest[i..].chars().next() {
+                    Some(base) if base.is_ascii_alphabetic() => {
+                        i += 1;
+                        let digits_len = rest[i..]
+                            .find(|c: char| !(c.is_ascii_hexdigit() || c == '_'))
+                            .unwrap_or(rest.len() - i);
+                        (i + digits_len, parse_based(base, &rest[i..i + digits_len]))
+                    }
+                    _ => (i, None),
+                }
+            } else {
+                (size_len, rest[..size_len].parse().ok())
+            };
+            let value = value.ok_or_else(|| VerilogError::UnexpectedToken {
+                line: line(pos),
+                found: rest[..len.max(1)].to_string(),
+            })?;
+            out.push((pos, Token::Number(value)));
+            pos += len;
+        } else if is_word(c) {
+            let len = rest.find(|c| !is_word(c)).unwrap_or(rest.len());
+            out.push((pos, Token::Ident(rest[..len].to_string())));
+            pos += len;
+        } else {
+            let punct = PUNCTUATION
+                .iter()
+                .find(|p| rest.starts_with(**p))
+                .ok_or_else(|| VerilogError::UnexpectedToken {
+                    line: line(pos),
+                    found: c.to_string(),
+                })?;
+            out.push((pos, Token::Punct(punct)));
+            pos += punct.len();
+        }
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Input,
+    Output,
+    Wire,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Net {
+    direction: Direction,
+    width: usize,
+    signed: bool,
+}
+
+/// A parsed expression, and whether it's signed.
+type Typed = (Sexp, bool);
+
+fn atom(s: &str) -> Sexp {
+    Sexp::Atom(s.to_string())
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// The line of the token at `pos`.
+    fn line_of(&self, pos: usize) -> usize {
+        let offset = self
+            .tokens
+            .get(pos)
+            .map_or(self.input.len(), |(offset, _)| *offset);
+        self.input[..offset].matches('\n').count() + 1
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn next(&mut self) -> Result<Token, VerilogError> {
+        let out = self
+            .tokens
+            .get(self.pos)
+            .map(|(_, t)| t.clone())
+            .ok_or(VerilogError::UnexpectedEof)?;
+        self.pos += 1;
+        Ok(out)
+    }
+
+    /// An error for the token just consumed.
+    fn unexpected(&self, token: &Token) -> VerilogError {
+        VerilogError::UnexpectedToken {
+            line: self.line_of(self.pos.saturating_sub(1)),
+            found: token.to_string(),
+        }
+    }
+
+    fn unsupported(&self, what: impl Into<String>) -> VerilogError {
+        VerilogError::Unsupported {
+            line: self.line_of(self.pos),
+            what: what.into(),
+        }
+    }
+
+    fn eat(&mut self, punct: &'static str) -> bool {
+        if self.peek() == Some(&Token::Punct(punct)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &'static str) -> Result<(), VerilogError> {
+        match self.next()? {
+            Token::Punct(p) if p == punct => Ok(()),
+            token => Err(self.unexpected(&token)),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, VerilogError> {
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            token => Err(self.unexpected(&token)),
+        }
+    }
+
+    fn number(&mut self) -> Result<i64, VerilogError> {
+        match self.next()? {
+            Token::Number(n) => Ok(n),
+            token => Err(self.unexpected(&token)),
+        }
+    }
+
+    /// Parses the rest of a declaration after its direction keyword: an
+    /// optional net type, `signed`, range, and then one name. Returns the
+    /// name.
+    fn declaration(
+        &mut self,
+        direction: Direction,
+        nets: &mut HashMap<String, Net>,
+    ) -> Result<Vec<String>, VerilogError> {
+        if matches!(self.peek(), Some(Token::Ident(t)) if t == "wire" || t == "logic") {
+            self.pos += 1;
+        }
+        let signed = matches!(self.peek(), Some(Token::Ident(t)) if t == "signed");
+        if signed {
+            self.pos += 1;
+        }
+        let width = if self.eat("[") {
+            let msb = self.number()?;
+            self.expect(":")?;
+            let lsb = self.number()?;
+            self.expect("]")?;
+            ((msb - lsb).abs() + 1) as usize
+        } else {
+            1
+        };
+        let mut names = vec![self.ident()?];
+        // In the body, one declaration may declare several nets. In an ANSI
+        // port list, a comma followed by a name continues the declaration
+        // too.
+        while self.peek() == Some(&Token::Punct(","))
+            && matches!(self.tokens.get(self.pos + 1), Some((_, Token::Ident(t)))
+                if !matches!(t.as_str(), "input" | "output" | "inout"))
+        {
+            self.pos += 1;
+            names.push(self.ident()?);
+        }
+        for name in &names {
+            nets.insert(
+                name.clone(),
+                Net {
+                    direction,
+                    width,
+                    signed,
+                },
+            );
+        }
+        Ok(names)
+    }
+
+    fn direction(token: Option<&Token>) -> Option<Direction> {
+        match token {
+            Some(Token::Ident(t)) if t == "input" => Some(Direction::Input),
+            Some(Token::Ident(t)) if t == "output" => Some(Direction::Output),
+            Some(Token::Ident(t)) if t == "wire" => Some(Direction::Wire),
+            _ => None,
+        }
+    }
+
+    /// The binding power and language op of a binary operator, following
+    /// Verilog's precedence. An empty op means the operator is unsupported.
+    fn binary_op(token: &Token) -> Option<(u8, &'static str)> {
+        match token {
+            Token::Punct("|") => Some((1, "or")),
+            Token::Punct("^") => Some((2, "xor")),
+            Token::Punct("&") => Some((3, "and")),
+            Token::Punct("==") => Some((4, "eq")),
+            Token::Punct(">>") => Some((5, "lsr")),
+            Token::Punct(">>>") => Some((5, "shr")),
+            Token::Punct("+") => Some((6, "add")),
+            Token::Punct("-") => Some((6, "sub")),
+            Token::Punct(
+                "<<" | "<<<" | "*" | "/" | "%" | "!=" | "===" | "!==" | "<" | ">" | "<=" | ">="
+                | "&&" | "||" | "?",
+            ) => Some((0, "")),
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self, min_power: u8, nets: &HashMap<String, Net>) -> Result<Typed, VerilogError> {
+        let (mut lhs, mut lhs_signed) = self.unary(nets)?;
+        while let Some(token) = self.peek().cloned() {
+            let (power, op) = match Self::binary_op(&token) {
+                Some((power, op)) if power >= min_power => (power, op),
+                _ => break,
+            };
+            if op.is_empty() {
+                return Err(self.unsupported(format!("operator {}", token)));
+            }
+            self.pos += 1;
+            let (rhs, rhs_signed) = self.expr(power + 1, nets)?;
+            let op = match op {
+                "shr" if lhs_signed => "asr",
+                "shr" => "lsr",
+                op => op,
+            };
+            // Shifts take the signedness of their left operand; everything
+            // else is signed only if both operands are.
+            if !matches!(op, "asr" | "lsr") {
+                lhs_signed = lhs_signed && rhs_signed;
+            }
+            lhs = Sexp::List(vec![atom("binop"), atom(op), lhs, rhs]);
+        }
+        Ok((lhs, lhs_signed))
+    }
+
+    fn unary(&mut self, nets: &HashMap<String, Net>) -> Result<Typed, VerilogError> {
+        match self.next()? {
+            Token::Punct(p @ ("~" | "-")) => {
+                let op = if p == "~" { "not" } else { "neg" };
+                let (arg, signed) = self.unary(nets)?;
+                Ok((Sexp::List(vec![atom("unop"), atom(op), arg]), signed))
+            }
+            Token::Punct("(") => {
+                let out = self.expr(1, nets)?;
+                self.expect(")")?;
+                Ok(out)
+            }
+            // Verilog literals are signed only when unsized and unbased, but
+            // since they take on the width of their context, treating them
+            // as signed lets them adopt the signedness of the other operand.
+            Token::Number(n) => Ok((Sexp::List(vec![atom("const"), atom(&n.to_string())]), true)),
+            Token::Ident(f) if f == "$signed" || f == "$unsigned" => {
+                self.expect("(")?;
+                let (arg, _) = self.expr(1, nets)?;
+                self.expect(")")?;
+                Ok((arg, f == "$signed"))
+            }
+            Token::Ident(name) => {
+                if self.peek() == Some(&Token::Punct("[")) {
+                    return Err(self.unsupported("bit select"));
+                }
+                match nets.get(&name) {
+                    Some(net) => Ok((Sexp::List(vec![atom("wire-ref"), atom(&name)]), net.signed)),
+                    None => Err(VerilogError::Undeclared { name }),
+                }
+            }
+            Token::Punct("{") => Err(self.unsupported("concatenation")),
+            token @ Token::Punct("!" | "&" | "|" | "^") => {
+                Err(self.unsupported(format!("operator {}", token)))
+            }
+            token => Err(self.unexpected(&token)),
+        }
+    }
+}
+
+/// Replaces references to nets with the expressions driving them, turning
+/// input references into `var`s.
+fn inline(
+    sexp: &Sexp,
+    nets: &HashMap<String, Net>,
+    drivers: &HashMap<String, Sexp>,
+    visiting: &mut HashSet<String>,
+) -> Result<Sexp, VerilogError> {
+    match sexp {
+        Sexp::List(items) if items.first() == Some(&atom("wire-ref")) => {
+            let name = items[1].as_atom().unwrap();
+            let net = nets[name];
+            if net.direction == Direction::Input {
+                return Ok(Sexp::List(vec![
+                    atom("var"),
+                    atom(name),
+                    atom(&net.width.to_string()),
+                ]));
+            }
+            let driver = drivers.get(name).ok_or_else(|| VerilogError::BadDriver {
+                name: name.to_string(),
+            })?;
+            if !visiting.insert(name.to_string()) {
+                return Err(VerilogError::CombinationalLoop {
+                    name: name.to_string(),
+                });
+            }
+            let out = inline(driver, nets, drivers, visiting)?;
+            visiting.remove(name);
+            // Check the driver against the net's declared width.
+            elaborate_sexp_at(&out, Some(net.width)).map_err(|error| {
+                VerilogError::Elaboration {
+                    net: name.to_string(),
+                    error,
+                }
+            })?;
+            Ok(out)
+        }
+        Sexp::List(items) => Ok(Sexp::List(
+            items
+                .iter()
+                .map(|item| inline(item, nets, drivers, visiting))
+                .collect::<Result<_, _>>()?,
+        )),
+        Sexp::Atom(_) => Ok(sexp.clone()),
+    }
+}
+
+/// Imports every combinational module in `input`. Each module output becomes
+/// a program named `module.output`.
+pub fn import_verilog(input: &str) -> Result<ProgramSet, VerilogError> {
+    let mut parser = Parser {
+        input,
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    let mut programs = ProgramSet::new();
+    while parser.peek().is_some() {
+        match parser.next()? {
+            Token::Ident(t) if t == "module" => (),
+            token => return Err(parser.unexpected(&token)),
+        }
+        let module = parser.ident()?;
+        let mut nets: HashMap<String, Net> = HashMap::default();
+        let mut ports: Vec<String> = vec![];
+        if parser.eat("#") {
+            return Err(parser.unsupported("module parameters"));
+        }
+        parser.expect("(")?;
+        if !parser.eat(")") {
+            loop {
+                match Parser::direction(parser.peek()) {
+                    Some(direction) => {
+                        parser.pos += 1;
+                        ports.extend(parser.declaration(direction, &mut nets)?);
+                    }
+                    // A non-ANSI port list just names the ports.
+                    None => ports.push(parser.ident()?),
+                }
+                if parser.eat(")") {
+                    break;
+                }
+                parser.expect(",")?;
+            }
+        }
+        parser.expect(";")?;
+
+        let mut drivers: HashMap<String, Sexp> = HashMap::default();
+        loop {
+            match parser.peek() {
+                Some(Token::Ident(t)) if t == "endmodule" => {
+                    parser.pos += 1;
+                    break;
+                }
+                Some(Token::Ident(t)) if t == "assign" => {
+                    parser.pos += 1;
+                    let name = parser.ident()?;
+                    if !nets.contains_key(&name) {
+                        return Err(VerilogError::Undeclared { name });
+                    }
+                    parser.expect("=")?;
+                    let (expr, _) = parser.expr(1, &nets)?;
+                    parser.expect(";")?;
+                    if drivers.insert(name.clone(), expr).is_some() {
+                        return Err(VerilogError::BadDriver { name });
+                    }
+                }
+                token => match Parser::direction(token) {
+                    Some(direction) => {
+                        parser.pos += 1;
+                        parser.declaration(direction, &mut nets)?;
+                        parser.expect(";")?;
+                    }
+                    None => match token {
+                        Some(Token::Ident(t)) => {
+                            let what = format!("statement {}", t);
+                            return Err(parser.unsupported(what));
+                        }
+                        Some(_) => {
+                            let token = parser.next()?;
+                            return Err(parser.unexpected(&token));
+                        }
+                        None => return Err(VerilogError::UnexpectedEof),
+                    },
+                },
+            }
+        }
+
+        for port in &ports {
+            let net = nets
+                .get(port)
+                .ok_or_else(|| VerilogError::Undeclared { name: port.clone() })?;
+            if net.direction != Direction::Output {
+                continue;
+            }
+            let name = format!("{}.{}", module, port);
+            let sexp = inline(
+                &Sexp::List(vec![atom("wire-ref"), atom(port)]),
+                &nets,
+                &drivers,
+                &mut HashSet::default(),
+            )?;
+            let expr = elaborate_sexp_at(&sexp, Some(nets[port].width)).map_err(|error| {
+                VerilogError::Elaboration {
+                    net: name.clone(),
+                    error,
+                }
+            })?;
+            programs.add(name, expr);
+        }
+    }
+    Ok(programs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::example_programs::all_programs;
+
+    #[test]
+    fn import_ceil_avg() {
+        let programs = import_verilog(
+            "// Ceiling of the average of two signed bytes.
+             module ceil_avg(input signed [7:0] x, y, output [7:0] out);
+               wire signed [7:0] t;
+               assign t = (x ^ y) >>> 1'b1;
+               assign out = (x | y) - t;
+             endmodule",
+        )
+        .unwrap();
+        assert_eq!(programs.len(), 1);
+        assert_eq!(
+            programs.get("ceil_avg.out").unwrap().expr.to_string(),
+            all_programs()["bithack_ceil_avg"].to_string()
+        );
+    }
+
+    #[test]
+    fn non_ansi_ports() {
+        let programs = import_verilog(
+            "module m(a, b, o);
+               input [3:0] a;
+               input [3:0] b;
+               output [3:0] o;
+               assign o = a >>> b;
+             endmodule",
+        )
+        .unwrap();
+        // `>>>` on unsigned operands is a logical shift.
+        assert_eq!(
+            programs.get("m.o").unwrap().expr.to_string(),
+            "(binop lsr 4 (var a 4) (var b 4))"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            import_verilog("module m(input [7:0] a, output [7:0] o); assign o = a * a; endmodule"),
+            Err(VerilogError::Unsupported { .. })
+        ));
+        assert!(matches!(
+            import_verilog(
+                "module m(input [7:0] a, output [7:0] o); wire [7:0] w; \
+                 assign w = o; assign o = w & a; endmodule"
+            ),
+            Err(VerilogError::CombinationalLoop { .. })
+        ));
+        assert!(matches!(
+            import_verilog("module m(input [7:0] a, output [3:0] o); assign o = a; endmodule"),
+            Err(VerilogError::Elaboration { .. })
+        ));
+    }
+}
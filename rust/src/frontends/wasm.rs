@@ -0,0 +1,525 @@
+//! Importer for straight-line WebAssembly integer functions, in the text
+//! format (convert binary modules with e.g. `wasm2wat` first).
+//!
+//! Each function whose body is a sequence of `i32`/`i64` arithmetic and
+//! bitwise instructions becomes a program named after the function, with
+//! its parameters as `var`s. Both flat and folded instructions are accepted:
+//!
+//! ```text
+//! (module
+//!   (func $ceil_avg (param $x i32) (param $y i32) (result i32)
+//!     (i32.sub
+//!       (i32.or (local.get $x) (local.get $y))
+//!       (i32.shr_s (i32.xor (local.get $x) (local.get $y)) (i32.const 1)))))
+//! ```
+//!
+//! WebAssembly takes shift amounts modulo the width, so shifts become
+//! `(binop lsr w a (binop and w b (const w-1 w)))`. `i32.eq` and friends
+//! produce an `i32`, so they map directly onto `eq` on `i32`s and, on
+//! `i64`s, only when followed by `i64.extend_i32_u`. Control flow, calls,
+//! memory, and instructions with no counterpart in the language are
+//! reported.
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    frontends::{
+        elaborate::{elaborate_sexp_at, ElaborationError},
+        sexp::{parse_all, Sexp, SexpError},
+    },
+    program_set::ProgramSet,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WasmError {
+    Parse(SexpError),
+    /// The input is a binary module rather than the text format.
+    Binary,
+    Malformed {
+        function: String,
+        what: String,
+    },
+    UnsupportedInstruction {
+        function: String,
+        instruction: String,
+    },
+    Unsupported {
+        function: String,
+        what: String,
+    },
+    /// An instruction popped more values than were on the stack, or the
+    /// function left the wrong number of results.
+    StackMismatch {
+        function: String,
+    },
+    Elaboration {
+        function: String,
+        error: ElaborationError,
+    },
+}
+impl Display for WasmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WasmError::Parse(e) => write!(f, "{}", e),
+            WasmError::Binary => write!(f, "binary modules are unsupported; use the text format"),
+            WasmError::Malformed { function, what } => {
+                write!(f, "${}: malformed {}", function, what)
+            }
+            WasmError::UnsupportedInstruction {
+                function,
+                instruction,
+            } => write!(f, "${}: unsupported instruction {}", function, instruction),
+            WasmError::Unsupported { function, what } => {
+                write!(f, "${}: unsupported {}", function, what)
+            }
+            WasmError::StackMismatch { function } => {
+                write!(f, "${}: operand stack mismatch", function)
+            }
+            WasmError::Elaboration { function, error } => write!(f, "${}: {}", function, error),
+        }
+    }
+}
+impl std::error::Error for WasmError {}
+
+fn atom(s: impl ToString) -> Sexp {
+    Sexp::Atom(s.to_string())
+}
+
+fn width_of(ty: &str) -> Option<usize> {
+    match ty {
+        "i32" => Some(32),
+        "i64" => Some(64),
+        _ => None,
+    }
+}
+
+/// A value on the operand stack.
+#[derive(Debug, Clone)]
+enum Value {
+    Int(Sexp, usize),
+    /// An `i64` comparison, whose `i32` result must be extended back to 64
+    /// bits.
+    Eq64(Sexp, Sexp),
+}
+
+struct Function {
+    name: String,
+    /// Parameter and local names (or indices) and their widths.
+    locals: Vec<(Option<String>, usize)>,
+    /// What each local currently holds.
+    values: HashMap<usize, Value>,
+    stack: Vec<Value>,
+}
+
+impl Function {
+    fn malformed(&self, what: impl ToString) -> WasmError {
+        WasmError::Malformed {
+            function: self.name.clone(),
+            what: what.to_string(),
+        }
+    }
+
+    fn pop(&mut self, width: usize) -> Result<Sexp, WasmError> {
+        match self.stack.pop() {
+            Some(Value::Int(expr, w)) if w == width => Ok(expr),
+            Some(Value::Int(_, w)) => Err(WasmError::Malformed {
+                function: self.name.clone(),
+                what: format!("i{} operand where i{} was expected", w, width),
+            }),
+            Some(Value::Eq64(..)) => Err(WasmError::Unsupported {
+                function: self.name.clone(),
+                what: "i64 comparison not extended with i64.extend_i32_u".to_string(),
+            }),
+            None => Err(WasmError::StackMismatch {
+                function: self.name.clone(),
+            }),
+        }
+    }
+
+    fn local(&self, index: &str) -> Result<usize, WasmError> {
+        match index.parse::<usize>() {
+            Ok(i) if i < self.locals.len() => Ok(i),
+            Ok(_) => Err(self.malformed(format!("local index {}", index))),
+            Err(_) => self
+                .locals
+                .iter()
+                .position(|(name, _)| name.as_deref() == Some(index))
+                .ok_or_else(|| self.malformed(format!("local {}", index))),
+        }
+    }
+
+    /// Runs one instruction, whose immediates are `args`.
+    fn run(&mut self, op: &str, args: &[&str]) -> Result<(), WasmError> {
+        let function = self.name.clone();
+        let unsupported = || WasmError::UnsupportedInstruction {
+            function: function.clone(),
+            instruction: op.to_string(),
+        };
+        let immediate = || {
+            args.first().copied().ok_or_else(|| WasmError::Malformed {
+                function: function.clone(),
+                what: format!("{} without an immediate", op),
+            })
+        };
+        match op {
+            "local.get" => {
+                let i = self.local(immediate()?)?;
+                let value = self
+                    .values
+                    .get(&i)
+                    .cloned()
+                    .ok_or_else(|| WasmError::Unsupported {
+                        function: self.name.clone(),
+                        what: format!("read of uninitialized local {}", args[0]),
+                    })?;
+                self.stack.push(value);
+                return Ok(());
+            }
+            "local.set" | "local.tee" => {
+                let i = self.local(immediate()?)?;
+                let width = self.locals[i].1;
+                let value = Value::Int(self.pop(width)?, width);
+                if op == "local.tee" {
+                    self.stack.push(value.clone());
+                }
+                self.values.insert(i, value);
+                return Ok(());
+            }
+            "drop" => {
+                return self
+                    .stack
+                    .pop()
+                    .map(|_| ())
+                    .ok_or(WasmError::StackMismatch {
+                        function: self.name.clone(),
+                    })
+            }
+            "nop" => return Ok(()),
+            _ => (),
+        }
+
+        let (ty, instr) = op.split_once('.').ok_or_else(unsupported)?;
+        let width = width_of(ty).ok_or_else(unsupported)?;
+        let w = atom(width);
+        let binop = |name: &str, a: Sexp, b: Sexp| {
+            Sexp::List(vec![atom("binop"), atom(name), w.clone(), a, b])
+        };
+        let value = match instr {
+            "const" => {
+                let text = immediate()?.replace('_', "");
+                let (negative, digits) = match text.strip_prefix('-') {
+                    Some(digits) => (true, digits),
+                    None => (false, text.as_str()),
+                };
+                let magnitude = match digits.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => digits.parse::<u64>(),
+                }
+                .map_err(|_| self.malformed(format!("constant {}", text)))?;
+                // Keep the bits; the width fixes the interpretation.
+                let value = if negative {
+                    (magnitude as i64).wrapping_neg()
+                } else {
+                    magnitude as i64
+                };
+                Value::Int(
+                    Sexp::List(vec![atom("const"), atom(value), w.clone()]),
+                    width,
+                )
+            }
+            "add" | "sub" | "and" | "or" | "xor" | "eq" => {
+                let b = self.pop(width)?;
+                let a = self.pop(width)?;
+                match (instr, width) {
+                    ("eq", 64) => Value::Eq64(a, b),
+                    _ => Value::Int(binop(instr, a, b), width),
+                }
+            }
+            "eqz" => {
+                let a = self.pop(width)?;
+                let zero = Sexp::List(vec![atom("const"), atom(0), w.clone()]);
+                match width {
+                    64 => Value::Eq64(a, zero),
+                    _ => Value::Int(binop("eq", a, zero), width),
+                }
+            }
+            "shr_u" | "shr_s" => {
+                let b = self.pop(width)?;
+                let a = self.pop(width)?;
+                let amount_mask = Sexp::List(vec![atom("const"), atom(width - 1), w.clone()]);
+                let amount = binop("and", b, amount_mask);
+                let op = if instr == "shr_u" { "lsr" } else { "asr" };
+                Value::Int(binop(op, a, amount), width)
+            }
+            "extend_i32_u" if width == 64 => match self.stack.pop() {
+                Some(Value::Eq64(a, b)) => Value::Int(binop("eq", a, b), 64),
+                Some(_) => {
+                    return Err(WasmError::Unsupported {
+                        function: self.name.clone(),
+                        what: "width change i64.extend_i32_u".to_string(),
+                    })
+                }
+                None => {
+                    return Err(WasmError::StackMismatch {
+                        function: self.name.clone(),
+                    })
+                }
+            },
+            _ => return Err(unsupported()),
+        };
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Runs a sequence of flat or folded instructions.
+    fn run_all(&mut self, body: &[Sexp]) -> Result<(), WasmError> {
+        let mut i = 0;
+        while i < body.len() {
+            match &body[i] {
+                Sexp::List(folded) => {
+                    let op = folded
+                        .first()
+                        .and_then(Sexp::as_atom)
+                        .ok_or_else(|| self.malformed(&body[i]))?;
+                    let split = folded[1..]
+                        .iter()
+                        .position(|s| s.as_list().is_some())
+                        .map_or(folded.len(), |p| p + 1);
+                    self.check_control(op)?;
+                    // Operands are evaluated before the instruction itself.
+                    self.run_all(&folded[split..])?;
+                    let args = folded[1..split]
+                        .iter()
+                        .filter_map(Sexp::as_atom)
+                        .collect::<Vec<_>>();
+                    self.run(op, &args)?;
+                    i += 1;
+                }
+                Sexp::Atom(op) => {
+                    self.check_control(op)?;
+                    let takes_immediate = op.starts_with("local.") || op.ends_with(".const");
+                    let args = match body.get(i + 1).and_then(Sexp::as_atom) {
+                        Some(arg) if takes_immediate => vec![arg],
+                        _ => vec![],
+                    };
+                    if op != "return" {
+                        self.run(op, &args)?;
+                    } else if i + 1 != body.len() {
+                        return Err(WasmError::Unsupported {
+                            function: self.name.clone(),
+                            what: "return before the end of the function".to_string(),
+                        });
+                    }
+                    i += 1 + args.len();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_control(&self, op: &str) -> Result<(), WasmError> {
+        if matches!(
+            op,
+            "block"
+                | "loop"
+                | "if"
+                | "else"
+                | "end"
+                | "br"
+                | "br_if"
+                | "br_table"
+                | "call"
+                | "call_indirect"
+                | "select"
+                | "unreachable"
+        ) || op.contains(".load")
+            || op.contains(".store")
+            || op.starts_with("global.")
+        {
+            Err(WasmError::Unsupported {
+                function: self.name.clone(),
+                what: format!("{} (only straight-line code is supported)", op),
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn unsupported_type(function: &str, ty: &str) -> WasmError {
+    WasmError::Unsupported {
+        function: function.to_string(),
+        what: format!("type {}", ty),
+    }
+}
+
+/// Imports a `func` form.
+fn import_func(func: &[Sexp], index: usize, programs: &mut ProgramSet) -> Result<(), WasmError> {
+    let mut name = match func.get(1).and_then(Sexp::as_atom) {
+        Some(id) if id.starts_with('$') => id[1..].to_string(),
+        _ => format!("func{}", index),
+    };
+    let mut locals = vec![];
+    let mut result = None;
+    let mut body_start = func.len();
+    for (i, item) in func.iter().enumerate().skip(1) {
+        let list = match item.as_list() {
+            Some(list) => list,
+            None if item.as_atom().map_or(false, |a| a.starts_with('$')) && i == 1 => continue,
+            None => {
+                body_start = i;
+                break;
+            }
+        };
+        let head = list.first().and_then(Sexp::as_atom);
+        let atoms = list[1..]
+            .iter()
+            .filter_map(Sexp::as_atom)
+            .collect::<Vec<_>>();
+        match head {
+            Some("export") if atoms.len() == 1 => name = atoms[0].trim_matches('"').to_string(),
+            Some("param") | Some("local") => match atoms.as_slice() {
+                // A named parameter or local.
+                [id, ty] if id.starts_with('$') => {
+                    let width = width_of(ty).ok_or_else(|| unsupported_type(&name, ty))?;
+                    locals.push((Some(id.to_string()), width, head == Some("param")));
+                }
+                tys => {
+                    for ty in tys {
+                        let width = width_of(ty).ok_or_else(|| unsupported_type(&name, ty))?;
+                        locals.push((None, width, head == Some("param")));
+                    }
+                }
+            },
+            Some("result") => match atoms.as_slice() {
+                [ty] if result.is_none() => {
+                    result = Some(width_of(ty).ok_or_else(|| unsupported_type(&name, ty))?)
+                }
+                _ => {
+                    return Err(WasmError::Unsupported {
+                        function: name.clone(),
+                        what: "multiple results".to_string(),
+                    })
+                }
+            },
+            Some("type") => (),
+            _ => {
+                body_start = i;
+                break;
+            }
+        }
+    }
+    let width = result.ok_or_else(|| WasmError::Unsupported {
+        function: name.clone(),
+        what: "functions without a result".to_string(),
+    })?;
+
+    let mut function = Function {
+        name: name.clone(),
+        locals: locals.iter().map(|(n, w, _)| (n.clone(), *w)).collect(),
+        values: HashMap::default(),
+        stack: vec![],
+    };
+    for (i, (id, w, is_param)) in locals.iter().enumerate() {
+        let var = match id {
+            Some(id) => id[1..].to_string(),
+            None => format!("p{}", i),
+        };
+        let value = if *is_param {
+            Sexp::List(vec![atom("var"), atom(var), atom(w)])
+        } else {
+            // Locals start out zeroed.
+            Sexp::List(vec![atom("const"), atom(0), atom(w)])
+        };
+        function.values.insert(i, Value::Int(value, *w));
+    }
+    function.run_all(&func[body_start..])?;
+    let expr = match function.stack.as_slice() {
+        [_] => function.pop(width)?,
+        _ => return Err(WasmError::StackMismatch { function: name }),
+    };
+    let expr = elaborate_sexp_at(&expr, Some(width)).map_err(|error| WasmError::Elaboration {
+        function: name.clone(),
+        error,
+    })?;
+    programs.add(name, expr);
+    Ok(())
+}
+
+/// Imports every function of a module in the WebAssembly text format. A
+/// bare sequence of `func`s is accepted too.
+pub fn import_wat(input: &str) -> Result<ProgramSet, WasmError> {
+    if input.starts_with("\0asm") {
+        return Err(WasmError::Binary);
+    }
+    let forms = parse_all(input).map_err(WasmError::Parse)?;
+    let fields = match forms.as_slice() {
+        [Sexp::List(module)] if module.first() == Some(&atom("module")) => module[1..].to_vec(),
+        _ => forms,
+    };
+    let mut programs = ProgramSet::new();
+    let funcs = fields
+        .iter()
+        .filter_map(Sexp::as_list)
+        .filter(|field| field.first() == Some(&atom("func")));
+    for (index, func) in funcs.enumerate() {
+        import_func(func, index, &mut programs)?;
+    }
+    Ok(programs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_folded_and_flat() {
+        let programs = import_wat(
+            "(module
+               (func $ceil_avg (param $x i32) (param $y i32) (result i32)
+                 (i32.sub
+                   (i32.or (local.get $x) (local.get $y))
+                   (i32.shr_s (i32.xor (local.get $x) (local.get $y)) (i32.const 1))))
+               (func (export \"is_zero\") (param i64) (result i64) (local $t i64)
+                 local.get 0
+                 local.tee $t
+                 i64.eqz
+                 i64.extend_i32_u
+                 return))",
+        )
+        .unwrap();
+        assert_eq!(
+            programs.get("ceil_avg").unwrap().expr.to_string(),
+            "(binop sub 32 (binop or 32 (var x 32) (var y 32)) \
+             (binop asr 32 (binop xor 32 (var x 32) (var y 32)) \
+             (binop and 32 (const 1 32) (const 31 32))))"
+        );
+        assert_eq!(
+            programs.get("is_zero").unwrap().expr.to_string(),
+            "(binop eq 64 (var p0 64) (const 0 64))"
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        let error = |body: &str| {
+            import_wat(&format!("(func $f (param $x i32) (result i32) {})", body)).unwrap_err()
+        };
+        assert!(matches!(
+            error("local.get $x i32.const 3 i32.mul"),
+            WasmError::UnsupportedInstruction { .. }
+        ));
+        assert!(matches!(
+            error("(if (result i32) (local.get $x) (then (i32.const 1)) (else (i32.const 2)))"),
+            WasmError::Unsupported { .. }
+        ));
+        assert!(matches!(
+            error("local.get $x local.get $x"),
+            WasmError::StackMismatch { .. }
+        ));
+        assert_eq!(
+            import_wat("\0asm\x01\0\0\0").unwrap_err(),
+            WasmError::Binary
+        );
+    }
+}
@@ -0,0 +1,394 @@
+//! Importer for netlists written by Yosys's `write_json`.
+//!
+//! Word-level cells (`$and`, `$or`, `$xor`, `$not`, `$neg`, `$add`, `$sub`,
+//! `$mul`, `$shr`, `$sshr`, `$eq`, and `$pos`) become the corresponding nodes, and
+//! the wire graph is resolved into one expression per module output, named
+//! `module.output` as in [`crate::frontends::verilog`]. Inputs become `var`s
+//! of their port widths.
+//!
+//! Every connection must carry a whole word: either exactly the output of a
+//! cell, exactly an input port, or a constant. Bit-level wiring, and operands
+//! narrower or wider than a cell's output (other than constants, which are
+//! resized), are rejected, as the language can't express them.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use serde_json::Value;
+
+use crate::{
+    frontends::{
+        elaborate::{elaborate_sexp_at, ElaborationError},
+        sexp::Sexp,
+    },
+    program_set::ProgramSet,
+};
+
+#[derive(Debug)]
+pub enum YosysError {
+    Json(serde_json::Error),
+    /// The JSON isn't shaped like a Yosys netlist.
+    Malformed {
+        what: String,
+    },
+    Unsupported {
+        what: String,
+    },
+    CombinationalLoop {
+        cell: String,
+    },
+    Elaboration {
+        net: String,
+        error: ElaborationError,
+    },
+}
+impl Display for YosysError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            YosysError::Json(e) => write!(f, "{}", e),
+            YosysError::Malformed { what } => write!(f, "malformed netlist: {}", what),
+            YosysError::Unsupported { what } => write!(f, "unsupported {}", what),
+            YosysError::CombinationalLoop { cell } => {
+                write!(f, "combinational loop through {}", cell)
+            }
+            YosysError::Elaboration { net, error } => write!(f, "{}: {}", net, error),
+        }
+    }
+}
+impl std::error::Error for YosysError {}
+impl From<serde_json::Error> for YosysError {
+    fn from(e: serde_json::Error) -> Self {
+        YosysError::Json(e)
+    }
+}
+
+fn malformed(what: impl Into<String>) -> YosysError {
+    YosysError::Malformed { what: what.into() }
+}
+
+fn unsupported(what: impl Into<String>) -> YosysError {
+    YosysError::Unsupported { what: what.into() }
+}
+
+/// A bit of a connection: either a net, or a constant `0`, `1`, `x`, or `z`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Bit {
+    Net(u64),
+    Const(String),
+}
+
+type Bits = Vec<Bit>;
+
+fn bits(value: &Value) -> Result<Bits, YosysError> {
+    value
+        .as_array()
+        .ok_or_else(|| malformed("connection is not a list of bits"))?
+        .iter()
+        .map(|bit| match bit {
+            Value::Number(n) => n
+                .as_u64()
+                .map(Bit::Net)
+                .ok_or_else(|| malformed(format!("bad net {}", n))),
+            Value::String(s) => Ok(Bit::Const(s.clone())),
+            other => Err(malformed(format!("bad bit {}", other))),
+        })
+        .collect()
+}
+
+/// Parameters are binary strings in older Yosys versions and numbers in
+/// newer ones.
+fn parameter(cell: &Value, name: &str) -> Option<u64> {
+    match cell.get("parameters")?.get(name)? {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => u64::from_str_radix(s, 2).ok(),
+        _ => None,
+    }
+}
+
+fn atom(s: &str) -> Sexp {
+    Sexp::Atom(s.to_string())
+}
+
+struct Module<'a> {
+    name: &'a str,
+    inputs: HashMap<Bits, (&'a str, usize)>,
+    /// Cells by the bits of their `Y` output.
+    drivers: HashMap<Bits, (&'a str, &'a Value)>,
+}
+
+impl<'a> Module<'a> {
+    /// The expression carried by `bits`.
+    fn resolve(&self, bits: &Bits, visiting: &mut HashSet<&'a str>) -> Result<Sexp, YosysError> {
+        if let Some(value) = self.constant(bits)? {
+            return Ok(Sexp::List(vec![
+                atom("const"),
+                atom(&value.to_string()),
+                atom(&bits.len().to_string()),
+            ]));
+        }
+        if let Some((name, width)) = self.inputs.get(bits) {
+            return Ok(Sexp::List(vec![
+                atom("var"),
+                atom(name),
+                atom(&width.to_string()),
+            ]));
+        }
+        if let Some((cell_name, cell)) = self.drivers.get(bits) {
+            if !visiting.insert(*cell_name) {
+                return Err(YosysError::CombinationalLoop {
+                    cell: cell_name.to_string(),
+                });
+            }
+            let out = self.cell(cell_name, cell, bits.len(), visiting)?;
+            visiting.remove(cell_name);
+            return Ok(out);
+        }
+        // The language's `eq` produces a full-width 0 or 1, which Yosys
+        // represents as a 1-bit `$eq` padded with zeros.
+        if let Some((cell_name, cell)) = self.drivers.get(&bits[..1.min(bits.len())].to_vec()) {
+            let padded = bits[1..].iter().all(|bit| *bit == Bit::Const("0".into()));
+            if cell.get("type").and_then(Value::as_str) == Some("$eq")
+                && padded
+                && parameter(cell, "A_WIDTH") == Some(bits.len() as u64)
+                && parameter(cell, "B_WIDTH") == Some(bits.len() as u64)
+            {
+                let connections = &cell["connections"];
+                return Ok(Sexp::List(vec![
+                    atom("binop"),
+                    atom("eq"),
+                    atom(&bits.len().to_string()),
+                    self.resolve(&self::bits(&connections["A"])?, visiting)?,
+                    self.resolve(&self::bits(&connections["B"])?, visiting)?,
+                ]));
+            }
+            return Err(unsupported(format!(
+                "use of part of the output of {}",
+                cell_name
+            )));
+        }
+        Err(unsupported(format!(
+            "bit-level connection {:?} in {}",
+            bits, self.name
+        )))
+    }
+
+    /// The value of `bits`, if they're all `0` or `1`.
+    fn constant(&self, bits: &Bits) -> Result<Option<u64>, YosysError> {
+        let mut value = 0u64;
+        for (i, bit) in bits.iter().enumerate() {
+            match bit {
+                Bit::Net(_) => return Ok(None),
+                Bit::Const(c) if c == "0" => (),
+                Bit::Const(c) if c == "1" && i < 64 => value |= 1 << i,
+                Bit::Const(c) if c == "1" => {
+                    return Err(unsupported("constant wider than 64 bits"))
+                }
+                Bit::Const(c) => return Err(unsupported(format!("constant bit {}", c))),
+            }
+        }
+        Ok(Some(value))
+    }
+
+    /// An operand of a cell of the given width. Constants are resized to fit.
+    fn operand(
+        &self,
+        cell_name: &str,
+        cell: &Value,
+        port: &str,
+        width: usize,
+        visiting: &mut HashSet<&'a str>,
+    ) -> Result<Sexp, YosysError> {
+        let bits = bits(&cell["connections"][port])
+            .map_err(|_| malformed(format!("{} has no port {}", cell_name, port)))?;
+        if bits.len() == width {
+            return self.resolve(&bits, visiting);
+        }
+        match self.constant(&bits)? {
+            Some(value) => Ok(Sexp::List(vec![atom("const"), atom(&value.to_string())])),
+            None => Err(unsupported(format!(
+                "{}-bit operand {} of {}-bit cell {}",
+                bits.len(),
+                port,
+                width,
+                cell_name
+            ))),
+        }
+    }
+
+    fn cell(
+        &self,
+        cell_name: &str,
+        cell: &Value,
+        width: usize,
+        visiting: &mut HashSet<&'a str>,
+    ) -> Result<Sexp, YosysError> {
+        let ty = cell
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or_else(|| malformed(format!("{} has no type", cell_name)))?;
+        let signed = parameter(cell, "A_SIGNED") == Some(1);
+        let (op, binary) = match ty {
+            "$and" => ("and", true),
+            "$or" => ("or", true),
+            "$xor" => ("xor", true),
+            "$add" => ("add", true),
+            "$sub" => ("sub", true),
+            "$mul" => ("mul", true),
+            "$shr" => ("lsr", true),
+            "$sshr" if signed => ("asr", true),
+            "$sshr" => ("lsr", true),
+            "$not" => ("not", false),
+            "$neg" => ("neg", false),
+            "$pos" => return self.operand(cell_name, cell, "A", width, visiting),
+            other => return Err(unsupported(format!("cell type {}", other))),
+        };
+        let width_atom = atom(&width.to_string());
+        let a = self.operand(cell_name, cell, "A", width, visiting)?;
+        Ok(if binary {
+            let b = self.operand(cell_name, cell, "B", width, visiting)?;
+            Sexp::List(vec![atom("binop"), atom(op), width_atom, a, b])
+        } else {
+            Sexp::List(vec![atom("unop"), atom(op), width_atom, a])
+        })
+    }
+}
+
+/// Imports every module in a Yosys JSON netlist.
+pub fn import_yosys_json(json: &str) -> Result<ProgramSet, YosysError> {
+    let netlist: Value = serde_json::from_str(json)?;
+    let modules = netlist
+        .get("modules")
+        .and_then(Value::as_object)
+        .ok_or_else(|| malformed("no modules"))?;
+
+    let mut programs = ProgramSet::new();
+    // Sort for a deterministic program order.
+    let mut module_names = modules.keys().collect::<Vec<_>>();
+    module_names.sort();
+    for module_name in module_names {
+        let module = &modules[module_name];
+        let ports = module
+            .get("ports")
+            .and_then(Value::as_object)
+            .ok_or_else(|| malformed(format!("{} has no ports", module_name)))?;
+        let mut inputs = HashMap::default();
+        let mut outputs = vec![];
+        for (port_name, port) in ports {
+            let port_bits = bits(&port["bits"])?;
+            match port.get("direction").and_then(Value::as_str) {
+                Some("input") => {
+                    let width = port_bits.len();
+                    inputs.insert(port_bits, (port_name.as_str(), width));
+                }
+                Some("output") => outputs.push((port_name, port_bits)),
+                other => {
+                    return Err(unsupported(format!(
+                        "port direction {:?} of {}",
+                        other, port_name
+                    )))
+                }
+            }
+        }
+
+        let mut drivers = HashMap::default();
+        if let Some(cells) = module.get("cells").and_then(Value::as_object) {
+            for (cell_name, cell) in cells {
+                let y = cell
+                    .get("connections")
+                    .and_then(|c| c.get("Y"))
+                    .ok_or_else(|| unsupported(format!("cell {} without a Y output", cell_name)))?;
+                drivers.insert(bits(y)?, (cell_name.as_str(), cell));
+            }
+        }
+
+        let module = Module {
+            name: module_name,
+            inputs,
+            drivers,
+        };
+        for (port_name, port_bits) in outputs {
+            let name = format!("{}.{}", module_name, port_name);
+            let sexp = module.resolve(&port_bits, &mut HashSet::default())?;
+            let expr = elaborate_sexp_at(&sexp, Some(port_bits.len())).map_err(|error| {
+                YosysError::Elaboration {
+                    net: name.clone(),
+                    error,
+                }
+            })?;
+            programs.add(name, expr);
+        }
+    }
+    Ok(programs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::example_programs::all_programs;
+
+    fn cell(ty: &str, signed: bool, a: &str, b: Option<&str>, y: &str) -> String {
+        format!(
+            r#"{{"type": "{}",
+                "parameters": {{"A_SIGNED": "{}", "A_WIDTH": 8, "B_WIDTH": 8, "Y_WIDTH": 8}},
+                "connections": {{"A": {}, {} "Y": {}}}}}"#,
+            ty,
+            if signed { "1" } else { "0" },
+            a,
+            b.map(|b| format!(r#""B": {},"#, b)).unwrap_or_default(),
+            y
+        )
+    }
+
+    #[test]
+    fn import_ceil_avg() {
+        let x = "[2, 3, 4, 5, 6, 7, 8, 9]";
+        let y = "[10, 11, 12, 13, 14, 15, 16, 17]";
+        let or = "[20, 21, 22, 23, 24, 25, 26, 27]";
+        let xor = "[30, 31, 32, 33, 34, 35, 36, 37]";
+        let shift = "[40, 41, 42, 43, 44, 45, 46, 47]";
+        let out = "[50, 51, 52, 53, 54, 55, 56, 57]";
+        let json = format!(
+            r#"{{"modules": {{"ceil_avg": {{
+                "ports": {{
+                    "x": {{"direction": "input", "bits": {x}}},
+                    "y": {{"direction": "input", "bits": {y}}},
+                    "out": {{"direction": "output", "bits": {out}}}
+                }},
+                "cells": {{
+                    "$or$1": {},
+                    "$xor$2": {},
+                    "$sshr$3": {},
+                    "$sub$4": {}
+                }}
+            }}}}}}"#,
+            cell("$or", true, x, Some(y), or),
+            cell("$xor", true, x, Some(y), xor),
+            cell("$sshr", true, xor, Some(r#"["1", "0"]"#), shift),
+            cell("$sub", true, or, Some(shift), out),
+            x = x,
+            y = y,
+            out = out,
+        );
+        let programs = import_yosys_json(&json).unwrap();
+        assert_eq!(
+            programs.get("ceil_avg.out").unwrap().expr.to_string(),
+            all_programs()["bithack_ceil_avg"].to_string()
+        );
+    }
+
+    #[test]
+    fn bit_level_wiring_is_rejected() {
+        let json = r#"{"modules": {"m": {
+            "ports": {
+                "a": {"direction": "input", "bits": [2, 3]},
+                "o": {"direction": "output", "bits": [3, 2]}
+            }
+        }}}"#;
+        assert!(matches!(
+            import_yosys_json(json),
+            Err(YosysError::Unsupported { .. })
+        ));
+    }
+}
@@ -0,0 +1,252 @@
+//! Unifying the instructions found by exploring programs at several widths.
+//!
+//! Exploring the same programs at 8, 16 and 32 bits, e.g. in parallel runs,
+//! finds much the same instructions at each width. [`unify_widths`] reports
+//! an instruction found at several widths once, as a template over a width
+//! `w`, e.g. `(instr (binop-ast add w (hole w) (hole w)) (canonical-args 0
+//! 1))`. Instructions are unified from the narrowest up: one joins a
+//! template if the template at its width is it or, failing that, if a
+//! backend proves the two compute the same, so that each generalization is
+//! verified up to the widest width it's claimed for. Only instructions all of
+//! whose signals are as wide as their output are width-parametric; the rest,
+//! e.g. shifts by narrower amounts, are reported as they are.
+
+use egg::RecExpr;
+
+use crate::{
+    ast::{Ast, Instr},
+    backend::SynthesisBackend,
+    error::LakeroadError,
+    language::Language,
+};
+
+/// An instruction found in one or more ISAs, at one or more widths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnifiedInstr {
+    /// The instruction, with its widths written `w` if it was found at more
+    /// than one.
+    pub instr: String,
+    /// The widths it was found at, narrowest first.
+    pub widths: Vec<i64>,
+    /// The ISA, and the index in it, of each instruction it stands for.
+    pub members: Vec<(usize, usize)>,
+}
+
+impl UnifiedInstr {
+    pub fn is_parametric(&self) -> bool {
+        self.widths.len() > 1
+    }
+}
+
+/// Instructions unified so far: the narrowest, which the others generalize.
+struct Group {
+    instr: Instr,
+    parametric: bool,
+    widths: Vec<i64>,
+    members: Vec<(usize, usize)>,
+}
+
+/// The instructions of `isas`, e.g. the ISAs selected for the same programs
+/// at different widths, with those which are the same up to their width
+/// reported once. Instructions which differ are proven to compute the same
+/// by `backend` before they're unified.
+pub fn unify_widths(
+    isas: &[Vec<RecExpr<Language>>],
+    backend: &impl SynthesisBackend,
+) -> Result<Vec<UnifiedInstr>, LakeroadError> {
+    let mut found = vec![];
+    for (isa, instrs) in isas.iter().enumerate() {
+        for (index, instr) in instrs.iter().enumerate() {
+            found.push(((isa, index), Instr::try_from(instr)?));
+        }
+    }
+    // Stable, so that instructions of one width stay in ISA order.
+    found.sort_by_key(|(_, instr)| instr.ast.width());
+
+    let mut groups: Vec<Group> = vec![];
+    for (member, instr) in found {
+        let width = instr.ast.width();
+        let parametric = is_uniform(&instr.ast, width);
+        let mut home = None;
+        for (i, group) in groups.iter().enumerate() {
+            if group.parametric != parametric {
+                continue;
+            }
+            let template = if parametric {
+                at_width(&group.instr, width)
+            } else {
+                group.instr.clone()
+            };
+            if template == instr
+                || (parametric
+                    && !group.widths.contains(&width)
+                    && equivalent(&template, &instr, backend)?)
+            {
+                home = Some(i);
+                break;
+            }
+        }
+        match home {
+            Some(i) => {
+                let group = &mut groups[i];
+                if !group.widths.contains(&width) {
+                    group.widths.push(width);
+                }
+                group.members.push(member);
+            }
+            None => groups.push(Group {
+                instr,
+                parametric,
+                widths: vec![width],
+                members: vec![member],
+            }),
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|group| UnifiedInstr {
+            instr: match group.widths.len() {
+                1 => RecExpr::from(&group.instr).to_string(),
+                _ => template(&group.instr),
+            },
+            widths: group.widths,
+            members: group.members,
+        })
+        .collect())
+}
+
+/// Whether every signal in `ast`, including in the instructions it applies,
+/// is `width` bits wide.
+fn is_uniform(ast: &Ast, width: i64) -> bool {
+    match ast {
+        Ast::Hole { width: w } => *w == width,
+        Ast::UnOp { width: w, arg, .. } => *w == width && is_uniform(arg, width),
+        Ast::BinOp {
+            width: w, lhs, rhs, ..
+        } => *w == width && is_uniform(lhs, width) && is_uniform(rhs, width),
+        Ast::Apply { instr, args } => {
+            is_uniform(&instr.ast, width) && args.iter().all(|arg| is_uniform(arg, width))
+        }
+    }
+}
+
+/// A uniform instruction at another width.
+fn at_width(instr: &Instr, width: i64) -> Instr {
+    fn go(ast: &Ast, width: i64) -> Ast {
+        match ast {
+            Ast::Hole { .. } => Ast::Hole { width },
+            Ast::UnOp { op, arg, .. } => Ast::UnOp {
+                op: op.clone(),
+                width,
+                arg: Box::new(go(arg, width)),
+            },
+            Ast::BinOp { op, lhs, rhs, .. } => Ast::BinOp {
+                op: op.clone(),
+                width,
+                lhs: Box::new(go(lhs, width)),
+                rhs: Box::new(go(rhs, width)),
+            },
+            Ast::Apply { instr, args } => Ast::Apply {
+                instr: Box::new(at_width(instr, width)),
+                args: args.iter().map(|arg| go(arg, width)).collect(),
+            },
+        }
+    }
+
+    Instr {
+        ast: go(&instr.ast, width),
+        canonical_args: instr.canonical_args.clone(),
+    }
+}
+
+/// A uniform instruction with its widths written `w`.
+fn template(instr: &Instr) -> String {
+    fn go(ast: &Ast) -> String {
+        match ast {
+            Ast::Hole { .. } => "(hole w)".to_string(),
+            Ast::UnOp { op, arg, .. } => format!("(unop-ast {} w {})", op, go(arg)),
+            Ast::BinOp { op, lhs, rhs, .. } => {
+                format!("(binop-ast {} w {} {})", op, go(lhs), go(rhs))
+            }
+            Ast::Apply { instr, args } => format!(
+                "(apply-ast {} (list{}))",
+                template(instr),
+                args.iter()
+                    .map(|arg| format!(" {}", go(arg)))
+                    .collect::<String>()
+            ),
+        }
+    }
+
+    format!(
+        "(instr {} (canonical-args{}))",
+        go(&instr.ast),
+        instr
+            .canonical_args
+            .iter()
+            .map(|arg| format!(" {}", arg))
+            .collect::<String>()
+    )
+}
+
+/// Whether `backend` proves `a` and `b` compute the same from the same
+/// arguments.
+fn equivalent(
+    a: &Instr,
+    b: &Instr,
+    backend: &impl SynthesisBackend,
+) -> Result<bool, LakeroadError> {
+    match (a.as_expr(), b.as_expr()) {
+        (Some(a), Some(b)) => backend.verify_equivalent(&RecExpr::from(&a), &RecExpr::from(&b)),
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::equiv::Exhaustive;
+
+    #[test]
+    fn unify_across_widths() {
+        let isa = |instrs: &[&str]| {
+            instrs
+                .iter()
+                .map(|instr| RecExpr::from_str(instr).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let isas = [
+            isa(&[
+                "(instr (binop-ast lsr 4 (hole 4) (hole 2)) (canonical-args 0 1))",
+                "(instr (binop-ast sub 4 (hole 4) (unop-ast neg 4 (hole 4))) (canonical-args 0 1))",
+            ]),
+            isa(&[
+                "(instr (binop-ast add 2 (hole 2) (hole 2)) (canonical-args 0 1))",
+                "(instr (binop-ast lsr 2 (hole 2) (hole 1)) (canonical-args 0 1))",
+                "(instr (binop-ast and 2 (hole 2) (hole 2)) (canonical-args 0 1))",
+            ]),
+            isa(&["(instr (binop-ast add 2 (hole 2) (hole 2)) (canonical-args 0 1))"]),
+        ];
+        let unified = unify_widths(&isas, &Exhaustive).unwrap();
+        // Adding a negation is adding, at the wider width.
+        assert_eq!(
+            unified[0],
+            UnifiedInstr {
+                instr: "(instr (binop-ast add w (hole w) (hole w)) (canonical-args 0 1))"
+                    .to_string(),
+                widths: vec![2, 4],
+                members: vec![(1, 0), (2, 0), (0, 1)],
+            }
+        );
+        // Shifts by narrower amounts aren't generalized.
+        assert_eq!(unified.len(), 4);
+        assert!(unified[1..].iter().all(|instr| !instr.is_parametric()));
+        assert_eq!(
+            unified[3].instr,
+            "(instr (binop-ast lsr 4 (hole 4) (hole 2)) (canonical-args 0 1))"
+        );
+    }
+}
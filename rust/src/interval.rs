@@ -0,0 +1,242 @@
+//! Unsigned value ranges of signals, tracked by the analysis.
+
+use crate::language::Op;
+
+/// Mask of the low `width` bits. Widths of 128 or more saturate to all ones,
+/// as ranges are only tracked up to 128 bits: the range of a wider signal is
+/// always [`Interval::full`].
+pub(crate) fn mask(width: usize) -> u128 {
+    if width >= 128 {
+        u128::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+/// The smallest all-ones value which is at least `v`.
+fn ones_covering(v: u128) -> u128 {
+    if v == 0 {
+        0
+    } else {
+        u128::MAX >> v.leading_zeros()
+    }
+}
+
+/// An inclusive range of values, interpreted as unsigned, which a signal may
+/// take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interval {
+    pub lo: u128,
+    pub hi: u128,
+}
+
+impl Interval {
+    /// The range of a signal about which nothing is known.
+    pub fn full(width: usize) -> Self {
+        Interval {
+            lo: 0,
+            hi: mask(width),
+        }
+    }
+
+    pub fn constant(v: u128) -> Self {
+        Interval { lo: v, hi: v }
+    }
+
+    /// The range of a `(const value width)`. Negative values are interpreted
+    /// in two's complement.
+    pub fn of_const(value: i64, width: usize) -> Self {
+        if value < 0 && width > 128 {
+            return Interval::full(width);
+        }
+        Interval::constant(value as u128 & mask(width))
+    }
+
+    pub fn as_constant(&self) -> Option<u128> {
+        if self.lo == self.hi {
+            Some(self.lo)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, v: u128) -> bool {
+        self.lo <= v && v <= self.hi
+    }
+
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        let lo = self.lo.max(other.lo);
+        let hi = self.hi.min(other.hi);
+        if lo <= hi {
+            Some(Interval { lo, hi })
+        } else {
+            None
+        }
+    }
+
+    /// The range of the low `width` bits of a signal in this range.
+    pub fn truncate(&self, width: usize) -> Self {
+        if self.hi <= mask(width) {
+            *self
+        } else {
+            Interval::full(width)
+        }
+    }
+
+    /// Whether the sign bit of a `width`-bit signal in this range is known to
+    /// be zero.
+    pub fn sign_bit_clear(&self, width: usize) -> bool {
+        self.hi <= mask(width) >> 1
+    }
+
+    /// The range of `(unop op width a)`.
+    pub fn unop(op: &Op, width: usize, a: Interval) -> Self {
+        if width > 128 {
+            return Interval::full(width);
+        }
+        let mask = mask(width);
+        match op {
+            Op::Not => Interval {
+                lo: mask - a.hi,
+                hi: mask - a.lo,
+            },
+            Op::Neg if a == Interval::constant(0) => a,
+            Op::Neg if a.lo > 0 => Interval {
+                lo: mask - a.hi + 1,
+                hi: mask - a.lo + 1,
+            },
+            _ => Interval::full(width),
+        }
+    }
+
+    /// The range of `(binop op width a b)`.
+    pub fn binop(op: &Op, width: usize, a: Interval, b: Interval) -> Self {
+        if width > 128 {
+            return Interval::full(width);
+        }
+        let mask = mask(width);
+        let lshr = |x: u128, s: u128| {
+            if s >= width as u128 {
+                0
+            } else {
+                x >> s
+            }
+        };
+        match op {
+            Op::And => match (a.as_constant(), b.as_constant()) {
+                (Some(a), Some(b)) => Interval::constant(a & b),
+                _ => Interval {
+                    lo: 0,
+                    hi: a.hi.min(b.hi),
+                },
+            },
+            Op::Or => Interval {
+                lo: a.lo.max(b.lo),
+                hi: ones_covering(a.hi.max(b.hi)),
+            },
+            Op::Xor => match (a.as_constant(), b.as_constant()) {
+                (Some(a), Some(b)) => Interval::constant(a ^ b),
+                _ => Interval {
+                    lo: 0,
+                    hi: ones_covering(a.hi.max(b.hi)),
+                },
+            },
+            Op::Add => match a.hi.checked_add(b.hi) {
+                Some(hi) if hi <= mask => Interval {
+                    lo: a.lo + b.lo,
+                    hi,
+                },
+                _ => Interval::full(width),
+            },
+            Op::Mul => match a.hi.checked_mul(b.hi) {
+                Some(hi) if hi <= mask => Interval {
+                    lo: a.lo * b.lo,
+                    hi,
+                },
+                _ => Interval::full(width),
+            },
+            Op::Sub if a.lo >= b.hi => Interval {
+                lo: a.lo - b.hi,
+                hi: a.hi - b.lo,
+            },
+            Op::Lsr => Interval {
+                lo: lshr(a.lo, b.hi),
+                hi: lshr(a.hi, b.lo),
+            },
+            // Arithmetic shifts act like logical shifts on nonnegative values.
+            Op::Asr if a.sign_bit_clear(width) => Interval::binop(&Op::Lsr, width, a, b),
+            Op::Eq => match (a.as_constant(), b.as_constant()) {
+                (Some(a), Some(b)) => Interval::constant((a == b) as u128),
+                _ if a.intersect(&b).is_none() => Interval::constant(0),
+                _ => Interval { lo: 0, hi: 1 },
+            },
+            _ => Interval::full(width),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_functions() {
+        let x = Interval::full(8);
+        assert_eq!(Interval::of_const(-1, 8), Interval::constant(255));
+        assert_eq!(
+            Interval::binop(&Op::And, 8, x, Interval::constant(15)),
+            Interval { lo: 0, hi: 15 }
+        );
+        assert_eq!(
+            Interval::binop(&Op::Lsr, 8, x, Interval::constant(4)),
+            Interval { lo: 0, hi: 15 }
+        );
+        assert_eq!(
+            Interval::binop(&Op::Lsr, 8, x, Interval::constant(8)),
+            Interval::constant(0)
+        );
+        assert_eq!(
+            Interval::binop(
+                &Op::Add,
+                8,
+                Interval { lo: 0, hi: 15 },
+                Interval { lo: 1, hi: 15 }
+            ),
+            Interval { lo: 1, hi: 30 }
+        );
+        assert_eq!(Interval::binop(&Op::Add, 8, x, Interval::constant(1)), x);
+        assert_eq!(
+            Interval::unop(&Op::Not, 8, Interval { lo: 0, hi: 15 }),
+            Interval { lo: 240, hi: 255 }
+        );
+        assert_eq!(
+            Interval::unop(&Op::Neg, 8, Interval::constant(1)),
+            Interval::constant(255)
+        );
+        assert_eq!(
+            Interval::binop(
+                &Op::Eq,
+                8,
+                Interval { lo: 0, hi: 15 },
+                Interval { lo: 16, hi: 20 }
+            ),
+            Interval::constant(0)
+        );
+    }
+
+    /// Values of signals wider than 128 bits don't fit in the bounds, so
+    /// nothing is known about them.
+    #[test]
+    fn wide_signals_are_full() {
+        let x = Interval::full(200);
+        // Shifting right by 130 bits leaves 70 of them, not zero.
+        assert_eq!(
+            Interval::binop(&Op::Lsr, 200, x, Interval::constant(130)),
+            x
+        );
+        assert_eq!(Interval::binop(&Op::And, 200, x, Interval::constant(15)), x);
+        assert_eq!(Interval::unop(&Op::Not, 200, Interval::constant(0)), x);
+        assert_eq!(Interval::of_const(-1, 200), x);
+        assert_eq!(Interval::of_const(5, 200), Interval::constant(5));
+    }
+}
@@ -0,0 +1,680 @@
+//! ISA selection: choosing which candidate instructions make up the ISA.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use egg::{
+    CostFunction, EGraph, Extractor, Id, Language as LanguageTrait, RecExpr, Rewrite, Runner,
+};
+
+use crate::{
+    analysis::LanguageAnalysis,
+    error::LakeroadError,
+    extract::{add_hashconsed, append_hashconsed, cmp_exprs, find_isa_instructions, CoverageIndex},
+    language::Language,
+    program_set::ProgramSet,
+};
+
+/// An ISA, represented as the set of eclass ids of its `instr`s. Ids are kept
+/// sorted so that equal ISAs compare equal.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Isa {
+    pub instructions: Vec<Id>,
+}
+
+impl Isa {
+    pub fn new(egraph: &EGraph<Language, LanguageAnalysis>, instructions: &[Id]) -> Self {
+        let mut instructions = instructions
+            .iter()
+            .map(|id| egraph.find(*id))
+            .collect::<Vec<_>>();
+        instructions.sort();
+        instructions.dedup();
+        Isa { instructions }
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+}
+
+/// Cost function counting the number of instruction applications needed to
+/// implement an expression using only the instructions in the ISA. Anything
+/// that isn't expressed as an `apply` of an ISA instruction (e.g. a leftover
+/// `binop`) costs [`usize::MAX`].
+struct IsaCost<'a> {
+    isa: &'a HashSet<Id>,
+}
+
+impl CostFunction<Language> for IsaCost<'_> {
+    type Cost = usize;
+
+    fn cost<C>(&mut self, enode: &Language, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        match enode {
+            &Language::Apply([instr_id, args_id]) if self.isa.contains(&instr_id) => {
+                costs(args_id).saturating_add(1)
+            }
+            Language::Apply(_) | Language::UnOp(_) | Language::BinOp(_) => usize::MAX,
+            Language::Var(_) | Language::Const(_) => 0,
+            _ => enode
+                .children()
+                .iter()
+                .fold(0usize, |acc, id| acc.saturating_add(costs(*id))),
+        }
+    }
+}
+
+/// The number of instructions needed to implement the program rooted at
+/// `root` using only the instructions in `isa`, or `None` if the ISA can't
+/// implement the program.
+pub fn program_cost(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    root: Id,
+    isa: &Isa,
+) -> Option<usize> {
+    let isa = isa.instructions.iter().cloned().collect::<HashSet<_>>();
+    let extractor = Extractor::new(egraph, IsaCost { isa: &isa });
+    match extractor.find_best(root).0 {
+        usize::MAX => None,
+        cost => Some(cost),
+    }
+}
+
+/// The program rooted at `root` rewritten into `isa`'s instructions: a DAG
+/// of `apply`s of `instructions`, the ISA's `instr`s in the order of
+/// `isa.instructions`, with each distinct subexpression computed once.
+/// Returns `None` if the ISA can't implement the program.
+pub fn compile_program(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    root: Id,
+    isa: &Isa,
+    instructions: &[RecExpr<Language>],
+) -> Option<RecExpr<Language>> {
+    /// Adds the best implementation of `eclass` to `out`.
+    fn build(
+        egraph: &EGraph<Language, LanguageAnalysis>,
+        extractor: &Extractor<IsaCost, Language, LanguageAnalysis>,
+        instructions: &HashMap<Id, &RecExpr<Language>>,
+        eclass: Id,
+        out: &mut RecExpr<Language>,
+        memo: &mut HashMap<Language, Id>,
+        built: &mut HashMap<Id, Id>,
+    ) -> Id {
+        let eclass = egraph.find(eclass);
+        if let Some(id) = built.get(&eclass) {
+            return *id;
+        }
+        let node = match extractor.find_best_node(eclass) {
+            // The instr as selected, rather than whichever of its eclass's
+            // terms is cheapest.
+            &Language::Apply([instr, args]) => {
+                let instr = append_hashconsed(out, memo, instructions[&egraph.find(instr)]);
+                let args = build(egraph, extractor, instructions, args, out, memo, built);
+                Language::Apply([instr, args])
+            }
+            node => node.clone().map_children(|child| {
+                build(egraph, extractor, instructions, child, out, memo, built)
+            }),
+        };
+        let id = add_hashconsed(out, memo, node);
+        built.insert(eclass, id);
+        id
+    }
+
+    let ids = isa.instructions.iter().cloned().collect::<HashSet<_>>();
+    let extractor = Extractor::new(egraph, IsaCost { isa: &ids });
+    if extractor.find_best_cost(root) == usize::MAX {
+        return None;
+    }
+    let instructions = isa
+        .instructions
+        .iter()
+        .cloned()
+        .zip(instructions)
+        .collect::<HashMap<_, _>>();
+    let mut out = RecExpr::default();
+    build(
+        egraph,
+        &extractor,
+        &instructions,
+        root,
+        &mut out,
+        &mut HashMap::default(),
+        &mut HashMap::default(),
+    );
+    Some(out)
+}
+
+/// Total cost of implementing all `programs` with `isa`, or `None` if some
+/// program can't be implemented. Each program is paired with its weight (see
+/// [`crate::program_set::Program::weight`]), which scales its cost.
+pub fn total_cost(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    programs: &[(Id, f64)],
+    isa: &Isa,
+) -> Option<f64> {
+    programs
+        .iter()
+        .map(|(root, weight)| program_cost(egraph, *root, isa).map(|cost| weight * cost as f64))
+        .sum()
+}
+
+/// Returns the `k` best ISAs of at most `max_instructions` instructions drawn
+/// from `candidates`, best first, along with their total weighted program
+/// cost. Only ISAs which implement every program are returned.
+///
+/// This enumerates every subset of the candidates up to the size bound, so it
+/// is only feasible for modest numbers of candidates.
+pub fn top_k_isas(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    candidates: &[Id],
+    programs: &[(Id, f64)],
+    max_instructions: usize,
+    k: usize,
+) -> Vec<(f64, Isa)> {
+    let candidates = Isa::new(egraph, candidates).instructions;
+
+    let mut best: Vec<(f64, Isa)> = Vec::default();
+    let mut visit = |isa: Isa| {
+        if let Some(cost) = total_cost(egraph, programs, &isa) {
+            best.push((cost, isa));
+            best.sort_by(|(a_cost, a), (b_cost, b)| {
+                a_cost.total_cmp(b_cost).then_with(|| a.cmp(b))
+            });
+            best.truncate(k);
+        }
+    };
+
+    // Enumerate subsets in order of increasing size.
+    for size in 1..=max_instructions.min(candidates.len()) {
+        let mut indices = (0..size).collect::<Vec<_>>();
+        loop {
+            visit(Isa {
+                instructions: indices.iter().map(|i| candidates[*i]).collect(),
+            });
+
+            // Advance to the next combination of `size` indices.
+            let mut i = size;
+            while i > 0 && indices[i - 1] == candidates.len() - size + i - 1 {
+                i -= 1;
+            }
+            if i == 0 {
+                break;
+            }
+            indices[i - 1] += 1;
+            for j in i..size {
+                indices[j] = indices[j - 1] + 1;
+            }
+        }
+    }
+
+    best
+}
+
+/// Settings for [`iterative_selection`].
+#[derive(Debug, Clone)]
+pub struct IterativeSelection {
+    /// Maximum number of select/re-explore rounds.
+    pub rounds: usize,
+    /// Number of instructions selected per round.
+    pub per_round: usize,
+    /// Iteration limit for each round's exploration.
+    pub iter_limit: usize,
+    /// Node limit for each round's exploration.
+    pub node_limit: usize,
+}
+
+impl Default for IterativeSelection {
+    fn default() -> Self {
+        Self {
+            rounds: 4,
+            per_round: 2,
+            iter_limit: 30,
+            node_limit: 100_000,
+        }
+    }
+}
+
+/// Result of [`iterative_selection`].
+#[derive(Debug, Clone)]
+pub struct IterativeSelectionResult {
+    /// The selected `instr`s, in the order they were selected.
+    pub instructions: Vec<RecExpr<Language>>,
+    /// The regions of the input programs which the selected instructions
+    /// don't cover. Inputs to these regions which are computed by selected
+    /// instructions are represented by fresh `var`s.
+    pub residual: ProgramSet,
+}
+
+/// Cost function preferring implementations which use the selected
+/// instructions, falling back to unimplemented `unop`s and `binop`s, and
+/// never using unselected instructions.
+struct SelectedCost<'a> {
+    selected: &'a HashSet<Id>,
+}
+
+impl CostFunction<Language> for SelectedCost<'_> {
+    type Cost = usize;
+
+    fn cost<C>(&mut self, enode: &Language, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        let children = enode
+            .children()
+            .iter()
+            .fold(0usize, |acc, id| acc.saturating_add(costs(*id)));
+        match enode {
+            &Language::Apply([instr_id, args_id]) if self.selected.contains(&instr_id) => {
+                costs(args_id).saturating_add(1)
+            }
+            Language::Apply(_) => usize::MAX,
+            Language::UnOp(_) | Language::BinOp(_) => children.saturating_add(2),
+            Language::Var(_) | Language::Const(_) => 0,
+            _ => children,
+        }
+    }
+}
+
+/// Whether an extracted `instr` is just a hole, i.e. the identity, or just an
+/// `apply-ast` of another instruction to holes, i.e. that instruction.
+pub fn is_hole_instr(instr: &RecExpr<Language>) -> bool {
+    match instr.as_ref().last() {
+        Some(&Language::Instr([ast_id, _])) => match &instr[ast_id] {
+            Language::Hole(_) => true,
+            &Language::ApplyAst([_, args_id]) => match &instr[args_id] {
+                Language::List(args) => args
+                    .iter()
+                    .all(|arg| matches!(instr[*arg], Language::Hole(_))),
+                _ => false,
+            },
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The number of holes and operators in an extracted `instr`'s AST. An
+/// `apply-ast` counts as one operator, however large the instruction it
+/// applies.
+pub fn instr_size(instr: &RecExpr<Language>) -> usize {
+    fn size(instr: &RecExpr<Language>, id: Id) -> usize {
+        match &instr[id] {
+            Language::Hole(_) => 1,
+            Language::UnOpAst(_) | Language::BinOpAst(_) => {
+                1 + instr[id].children()[2..]
+                    .iter()
+                    .map(|child| size(instr, *child))
+                    .sum::<usize>()
+            }
+            &Language::ApplyAst([_, args_id]) => 1 + size(instr, args_id),
+            Language::List(ids) => ids.iter().map(|child| size(instr, *child)).sum(),
+            &Language::Instr([ast_id, _]) => size(instr, ast_id),
+            _ => 0,
+        }
+    }
+    match instr.as_ref().len() {
+        0 => 0,
+        len => size(instr, Id::from(len - 1)),
+    }
+}
+
+/// Orders candidates so the most promising are checked first, and an
+/// interrupted run has already checked them: by the total weight of the
+/// programs they appear in, then by how rare their operators are among the
+/// candidates (the sum of one over the number of candidates using each of
+/// their operators), so that an operator few candidates implement is
+/// covered early, then smaller first, and then by [`cmp_exprs`].
+pub fn prioritize_candidates(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    roots: &[(Id, f64)],
+    candidates: &mut Vec<(Id, RecExpr<Language>)>,
+) {
+    let index = CoverageIndex::new(egraph, roots.iter().map(|(root, _)| *root));
+    let ops = |instr: &RecExpr<Language>| {
+        instr
+            .as_ref()
+            .iter()
+            .filter_map(|node| match node {
+                Language::Op(op) => Some(op.clone()),
+                _ => None,
+            })
+            // Ordered, so that the rarity sums round the same in every run.
+            .collect::<BTreeSet<_>>()
+    };
+    let mut users = HashMap::new();
+    for (_, instr) in candidates.iter() {
+        for op in ops(instr) {
+            *users.entry(op).or_insert(0) += 1;
+        }
+    }
+    let mut keyed = candidates
+        .drain(..)
+        .map(|(id, instr)| {
+            let coverage: f64 = roots
+                .iter()
+                .filter(|(root, _)| index.contains(*root, id))
+                .map(|(_, weight)| weight)
+                .sum();
+            let rarity: f64 = ops(&instr).iter().map(|op| 1.0 / users[op] as f64).sum();
+            (coverage, rarity, (id, instr))
+        })
+        .collect::<Vec<_>>();
+    keyed.sort_by(|(a_coverage, a_rarity, a), (b_coverage, b_rarity, b)| {
+        b_coverage
+            .total_cmp(a_coverage)
+            .then_with(|| b_rarity.total_cmp(a_rarity))
+            .then_with(|| instr_size(&a.1).cmp(&instr_size(&b.1)))
+            .then_with(|| cmp_exprs(&a.1, &b.1))
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    candidates.extend(keyed.into_iter().map(|(_, _, candidate)| candidate));
+}
+
+/// The bitwidth of the signal computed by `id`, read off of its `Num` child.
+fn width_of(expr: &RecExpr<Language>, id: Id) -> i64 {
+    let bw_id = match &expr[id] {
+        Language::Var([_, bw_id]) | Language::Const([_, bw_id]) => *bw_id,
+        Language::UnOp([_, bw_id, _]) | Language::UnOpAst([_, bw_id, _]) => *bw_id,
+        Language::BinOp([_, bw_id, _, _]) | Language::BinOpAst([_, bw_id, _, _]) => *bw_id,
+        Language::Hole([bw_id]) | Language::Reg([bw_id, _]) => *bw_id,
+        &Language::Apply([instr_id, _]) | &Language::ApplyAst([instr_id, _]) => {
+            return width_of(expr, instr_id)
+        }
+        &Language::Extract([hi_id, lo_id, _]) => match (&expr[hi_id], &expr[lo_id]) {
+            (Language::Num(hi), Language::Num(lo)) => return hi - lo + 1,
+            _ => panic!("expected bit indices"),
+        },
+        &Language::Cat([hi_id, lo_id]) => return width_of(expr, hi_id) + width_of(expr, lo_id),
+        &Language::Instr([ast_id, _]) => return width_of(expr, ast_id),
+        other => panic!("{:?} is not a signal", other),
+    };
+    match expr[bw_id] {
+        Language::Num(v) => v,
+        _ => panic!("expected bitwidth"),
+    }
+}
+
+/// Splits an extracted program into the maximal regions of `unop`s and
+/// `binop`s which aren't covered by `apply`s, replacing the outputs of covered
+/// regions with fresh variables.
+fn uncovered_regions(expr: &RecExpr<Language>, next_var: &mut usize) -> Vec<RecExpr<Language>> {
+    fn copy_region(
+        expr: &RecExpr<Language>,
+        id: Id,
+        out: &mut RecExpr<Language>,
+        worklist: &mut Vec<Id>,
+        next_var: &mut usize,
+    ) -> Id {
+        match &expr[id] {
+            Language::Apply(_) => {
+                worklist.push(id);
+                let name = out.add(Language::String(format!("covered{}", next_var)));
+                *next_var += 1;
+                let bw = out.add(Language::Num(width_of(expr, id)));
+                out.add(Language::Var([name, bw]))
+            }
+            node => {
+                let mut node = node.clone();
+                node.children_mut()
+                    .iter_mut()
+                    .for_each(|child| *child = copy_region(expr, *child, out, worklist, next_var));
+                out.add(node)
+            }
+        }
+    }
+
+    let mut out = Vec::default();
+    let mut worklist = vec![Id::from(expr.as_ref().len() - 1)];
+    while let Some(id) = worklist.pop() {
+        match &expr[id] {
+            Language::UnOp(_) | Language::BinOp(_) => {
+                let mut region = RecExpr::default();
+                copy_region(expr, id, &mut region, &mut worklist, next_var);
+                out.push(region);
+            }
+            &Language::Apply([_, args_id]) => worklist.push(args_id),
+            Language::List(ids) => worklist.extend(ids.iter()),
+            Language::Concat(ids) => worklist.extend(ids.iter()),
+            &Language::Reg([_, arg_id]) | &Language::Extract([_, _, arg_id]) => {
+                worklist.push(arg_id)
+            }
+            Language::Cat(ids) => worklist.extend(ids.iter()),
+            _ => (),
+        }
+    }
+    out
+}
+
+/// Coverage-guided iterative ISA selection.
+///
+/// Each round explores the (remaining) programs with `rules`, selects the
+/// `per_round` candidate instructions covering the most program weight,
+/// rewrites the programs in terms of all instructions selected so far, and
+/// carries the uncovered regions over into the next round. This finds
+/// instructions which only become attractive once part of each program is
+/// already covered, which one-shot exploration misses.
+pub fn iterative_selection(
+    programs: &ProgramSet,
+    rules: &[Rewrite<Language, LanguageAnalysis>],
+    config: &IterativeSelection,
+) -> Result<IterativeSelectionResult, LakeroadError> {
+    let mut selected: Vec<RecExpr<Language>> = Vec::default();
+    let mut residual = programs.clone();
+    let mut next_var = 0;
+
+    for _ in 0..config.rounds {
+        if residual.is_empty() {
+            break;
+        }
+
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let roots = residual.add_to_egraph(&mut egraph);
+        let egraph = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(config.iter_limit)
+            .with_node_limit(config.node_limit)
+            .run(rules)
+            .egraph;
+
+        let index = CoverageIndex::new(&egraph, roots.iter().map(|(root, _)| *root));
+        // Score the new candidates by the program weight they cover.
+        let mut scored = find_isa_instructions(&egraph)?
+            .into_iter()
+            .filter(|(_, instr)| !is_hole_instr(instr))
+            .filter(|(_, instr)| !selected.iter().any(|s| s.as_ref() == instr.as_ref()))
+            .map(|(id, instr)| {
+                let coverage: f64 = roots
+                    .iter()
+                    .filter(|(root, _)| index.contains(*root, id))
+                    .map(|(_, weight)| weight)
+                    .sum();
+                (coverage, instr)
+            })
+            .filter(|(coverage, _)| *coverage > 0.0)
+            .collect::<Vec<_>>();
+        scored
+            .sort_by(|(a_cov, a), (b_cov, b)| b_cov.total_cmp(a_cov).then_with(|| cmp_exprs(a, b)));
+        if scored.is_empty() {
+            break;
+        }
+        selected.extend(scored.into_iter().take(config.per_round).map(|(_, i)| i));
+
+        // Rewrite the programs in terms of the selected instructions, and keep
+        // whatever isn't covered.
+        let selected_ids = selected
+            .iter()
+            .filter_map(|instr| egraph.lookup_expr(instr))
+            .collect::<HashSet<_>>();
+        let extractor = Extractor::new(
+            &egraph,
+            SelectedCost {
+                selected: &selected_ids,
+            },
+        );
+        let mut new_residual = ProgramSet::new();
+        for ((root, weight), program) in roots.iter().zip(residual.iter()) {
+            let (_, expr) = extractor.find_best(*root);
+            for (i, region) in uncovered_regions(&expr, &mut next_var)
+                .into_iter()
+                .enumerate()
+            {
+                new_residual.add_weighted(format!("{}.{}", program.name, i), region, *weight)?;
+            }
+        }
+        residual = new_residual;
+    }
+
+    Ok(IterativeSelectionResult {
+        instructions: selected,
+        residual,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::{RecExpr, Runner};
+
+    use super::*;
+    use crate::{program_set::ProgramSet, rewrites::*};
+
+    #[test]
+    fn top_k_and() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let id = egraph.add_expr(&RecExpr::from_str("(binop and 8 (var x 8) (var y 8))").unwrap());
+        let runner = Runner::default().with_egraph(egraph).run(&vec![
+            introduce_hole_var(),
+            introduce_hole_op_both(),
+            canonicalize(),
+        ]);
+
+        let candidates = find_isa_instructions(&runner.egraph)
+            .unwrap()
+            .iter()
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        let isas = top_k_isas(&runner.egraph, &candidates, &[(id, 1.0)], 2, 3);
+
+        assert!(!isas.is_empty());
+        assert!(isas.len() <= 3);
+        // The AND instruction implements the program in one instruction.
+        assert_eq!(isas[0].0, 1.0);
+        assert!(isas.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(isas.iter().all(|(_, isa)| isa.len() <= 2));
+    }
+
+    #[test]
+    fn prioritize_covering_candidates() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let and = egraph.add_expr(&RecExpr::from_str("(binop and 8 (var x 8) (var y 8))").unwrap());
+        let or = egraph.add_expr(
+            &RecExpr::from_str("(binop or 8 (binop and 8 (var x 8) (var y 8)) (var z 8))").unwrap(),
+        );
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(3)
+            .run(&vec![
+                introduce_hole_var(),
+                introduce_hole_op_both(),
+                canonicalize(),
+            ]);
+        let mut candidates = find_isa_instructions(&runner.egraph)
+            .unwrap()
+            .into_iter()
+            .filter(|(_, instr)| !is_hole_instr(instr))
+            .collect::<Vec<_>>();
+        let count = candidates.len();
+        prioritize_candidates(&runner.egraph, &[(and, 1.0), (or, 1.0)], &mut candidates);
+        assert_eq!(candidates.len(), count);
+        // The `and`, which both programs use, comes first.
+        assert_eq!(
+            candidates[0].1.to_string(),
+            "(instr (binop-ast and 8 (hole 8) (hole 8)) (canonical-args 0 1))"
+        );
+    }
+
+    #[test]
+    fn weights_scale_cost() {
+        let mut programs = ProgramSet::new();
+        programs
+            .add_weighted(
+                "hot",
+                RecExpr::from_str("(binop and 8 (var x 8) (var y 8))").unwrap(),
+                10.0,
+            )
+            .unwrap();
+        programs
+            .add_weighted(
+                "cold",
+                RecExpr::from_str("(binop or 8 (var x 8) (var y 8))").unwrap(),
+                0.5,
+            )
+            .unwrap();
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let roots = programs.add_to_egraph(&mut egraph);
+        let runner = Runner::default().with_egraph(egraph).run(&vec![
+            introduce_hole_var(),
+            introduce_hole_op_both(),
+            canonicalize(),
+        ]);
+
+        let candidates = find_isa_instructions(&runner.egraph)
+            .unwrap()
+            .iter()
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        let isas = top_k_isas(&runner.egraph, &candidates, &roots, 2, 1);
+        // One AND for the hot program, one OR for the cold program.
+        assert_eq!(isas[0].0, 10.5);
+    }
+
+    #[test]
+    fn iterative_selection_bithacks() {
+        let mut programs = ProgramSet::new();
+        programs.add(
+            "bithack1",
+            RecExpr::from_str("(binop sub 8 (var x 8) (binop and 8 (var x 8) (var y 8)))").unwrap(),
+        );
+        programs.add(
+            "bithack3",
+            RecExpr::from_str(
+                "(binop xor 8 (binop xor 8 (var x 8) (var y 8)) (binop and 8 (var x 8) (var y 8)))",
+            )
+            .unwrap(),
+        );
+        let rules = vec![
+            introduce_hole_var(),
+            fuse_op(),
+            introduce_hole_op_both(),
+            introduce_hole_op_left(),
+            introduce_hole_op_right(),
+            simplify_concat(),
+            unary0(),
+            unary1(),
+            canonicalize(),
+        ];
+
+        let result = iterative_selection(
+            &programs,
+            &rules,
+            &IterativeSelection {
+                rounds: 2,
+                per_round: 1,
+                iter_limit: 5,
+                node_limit: 10_000,
+            },
+        )
+        .unwrap();
+        assert!(!result.instructions.is_empty());
+        assert!(result.instructions.len() <= 2);
+        assert!(result.instructions.iter().all(|i| !is_hole_instr(i)));
+    }
+}
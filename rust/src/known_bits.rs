@@ -0,0 +1,359 @@
+//! Known-zero/known-one bit masks of signals, tracked by the analysis (in the
+//! style of LLVM's known bits).
+
+use crate::{
+    interval::{mask, Interval},
+    language::Op,
+};
+
+/// Whether the bits of a `width`-bit signal fit in the masks. Nothing is
+/// known about wider signals.
+fn tracked(width: usize) -> bool {
+    width <= 128
+}
+
+/// Bits of a signal known to be zero or one. A bit set in neither mask is
+/// unknown; no bit is ever set in both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct KnownBits {
+    pub zeros: u128,
+    pub ones: u128,
+}
+
+impl KnownBits {
+    /// Nothing is known.
+    pub fn unknown() -> Self {
+        Self::default()
+    }
+
+    pub fn constant(v: u128, width: usize) -> Self {
+        if !tracked(width) {
+            return KnownBits::unknown();
+        }
+        KnownBits {
+            zeros: !v & mask(width),
+            ones: v & mask(width),
+        }
+    }
+
+    /// The known bits of a `(const value width)`.
+    pub fn of_const(value: i64, width: usize) -> Self {
+        KnownBits::constant(value as u128, width)
+    }
+
+    /// The bits implied by a value range: everything above the highest bit
+    /// the range's upper bound can set is zero.
+    pub fn of_range(range: &Interval, width: usize) -> Self {
+        if !tracked(width) {
+            return KnownBits::unknown();
+        }
+        if let Some(v) = range.as_constant() {
+            return KnownBits::constant(v, width);
+        }
+        let covering = if range.hi == 0 {
+            0
+        } else {
+            u128::MAX >> range.hi.leading_zeros()
+        };
+        KnownBits {
+            zeros: !covering & mask(width),
+            ones: 0,
+        }
+    }
+
+    /// The value, if every bit is known.
+    pub fn as_constant(&self, width: usize) -> Option<u128> {
+        if tracked(width) && (self.zeros | self.ones) == mask(width) {
+            Some(self.ones)
+        } else {
+            None
+        }
+    }
+
+    /// The known bits of a `from`-bit signal zero-extended to `to` bits,
+    /// whose new bits are zero.
+    pub fn zero_extend(&self, from: usize, to: usize) -> Self {
+        if !tracked(to) {
+            return KnownBits::unknown();
+        }
+        KnownBits {
+            zeros: self.zeros | (mask(to) & !mask(from)),
+            ones: self.ones,
+        }
+    }
+
+    /// The known bits of the low `width` bits of a signal.
+    pub fn truncate(&self, width: usize) -> Self {
+        KnownBits {
+            zeros: self.zeros & mask(width),
+            ones: self.ones & mask(width),
+        }
+    }
+
+    /// The known bits of `(cat self lo)`, `width` bits wide, where `lo` is
+    /// `lo_width` bits wide.
+    pub fn concat(&self, lo: &KnownBits, lo_width: usize, width: usize) -> Self {
+        if !tracked(width) {
+            return KnownBits::unknown();
+        }
+        let shift = |bits: u128| {
+            u32::try_from(lo_width)
+                .ok()
+                .and_then(|n| bits.checked_shl(n))
+        };
+        KnownBits {
+            zeros: shift(self.zeros).unwrap_or(0) | lo.zeros,
+            ones: shift(self.ones).unwrap_or(0) | lo.ones,
+        }
+    }
+
+    /// Bits which might be one.
+    pub fn possible_ones(&self, width: usize) -> u128 {
+        mask(width) & !self.zeros
+    }
+
+    fn bit(&self, i: usize) -> Option<bool> {
+        if i >= 128 {
+            None
+        } else if self.ones >> i & 1 == 1 {
+            Some(true)
+        } else if self.zeros >> i & 1 == 1 {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    fn with_bit(mut self, i: usize, bit: Option<bool>) -> Self {
+        match bit {
+            Some(true) => self.ones |= 1 << i,
+            Some(false) => self.zeros |= 1 << i,
+            None => (),
+        }
+        self
+    }
+
+    fn not(&self) -> Self {
+        KnownBits {
+            zeros: self.ones,
+            ones: self.zeros,
+        }
+    }
+
+    /// Combines what is known about two equivalent signals. If the two
+    /// disagree (which means the signal is unreachable), keeps `self`.
+    pub fn union(&self, other: &Self) -> Self {
+        let out = KnownBits {
+            zeros: self.zeros | other.zeros,
+            ones: self.ones | other.ones,
+        };
+        if out.zeros & out.ones != 0 {
+            *self
+        } else {
+            out
+        }
+    }
+
+    /// Ripple-carry addition over known bits, with the given carry in.
+    fn add(width: usize, a: KnownBits, b: KnownBits, carry_in: bool) -> Self {
+        let mut out = KnownBits::unknown();
+        let mut carry = Some(carry_in);
+        for i in 0..width.min(128) {
+            let (x, y) = (a.bit(i), b.bit(i));
+            let sum = match (x, y, carry) {
+                (Some(x), Some(y), Some(c)) => Some(x ^ y ^ c),
+                _ => None,
+            };
+            // The carry out is the majority of the three inputs, which is
+            // known whenever two known inputs agree.
+            let known = [x, y, carry];
+            carry = if known.iter().filter(|b| **b == Some(true)).count() >= 2 {
+                Some(true)
+            } else if known.iter().filter(|b| **b == Some(false)).count() >= 2 {
+                Some(false)
+            } else {
+                None
+            };
+            out = out.with_bit(i, sum);
+        }
+        out
+    }
+
+    /// The known bits of `(unop op width a)`.
+    pub fn unop(op: &Op, width: usize, a: KnownBits) -> Self {
+        if !tracked(width) {
+            return KnownBits::unknown();
+        }
+        match op {
+            Op::Not => KnownBits {
+                zeros: a.ones & mask(width),
+                ones: a.zeros & mask(width),
+            },
+            // -a = ~a + 1.
+            Op::Neg => KnownBits::add(width, a.not(), KnownBits::constant(0, width), true),
+            _ => KnownBits::unknown(),
+        }
+    }
+
+    /// The known bits of `(binop op width a b)`.
+    pub fn binop(op: &Op, width: usize, a: KnownBits, b: KnownBits) -> Self {
+        if !tracked(width) {
+            return KnownBits::unknown();
+        }
+        let mask = mask(width);
+        match op {
+            Op::And => KnownBits {
+                zeros: a.zeros | b.zeros,
+                ones: a.ones & b.ones,
+            },
+            Op::Or => KnownBits {
+                zeros: a.zeros & b.zeros,
+                ones: a.ones | b.ones,
+            },
+            Op::Xor => KnownBits {
+                zeros: (a.zeros & b.zeros) | (a.ones & b.ones),
+                ones: (a.zeros & b.ones) | (a.ones & b.zeros),
+            },
+            Op::Add => KnownBits::add(width, a, b, false),
+            // a - b = a + ~b + 1.
+            Op::Sub => KnownBits::add(width, a, b.not(), true),
+            Op::Mul => match (a.as_constant(width), b.as_constant(width)) {
+                (Some(a), Some(b)) => KnownBits::constant(a.wrapping_mul(b) & mask, width),
+                // The product has at least as many trailing zeros as the
+                // operands have between them.
+                _ => {
+                    let zeros = (a.zeros.trailing_ones() + b.zeros.trailing_ones()).min(128);
+                    KnownBits {
+                        zeros: mask & !(u128::MAX.checked_shl(zeros).unwrap_or(0)),
+                        ones: 0,
+                    }
+                }
+            },
+            Op::Lsr | Op::Asr => match b.as_constant(width) {
+                Some(s) if s >= width as u128 => match (op, a.bit(width - 1)) {
+                    (Op::Asr, Some(true)) => KnownBits::constant(mask, width),
+                    (Op::Asr, None) => KnownBits::unknown(),
+                    _ => KnownBits::constant(0, width),
+                },
+                Some(s) => {
+                    let shifted_in = mask & !(mask >> s);
+                    let shifted = KnownBits {
+                        zeros: a.zeros >> s,
+                        ones: a.ones >> s,
+                    };
+                    match (op, a.bit(width - 1)) {
+                        (Op::Asr, Some(true)) => KnownBits {
+                            zeros: shifted.zeros,
+                            ones: shifted.ones | shifted_in,
+                        },
+                        (Op::Asr, None) => KnownBits {
+                            zeros: shifted.zeros & !shifted_in,
+                            ones: shifted.ones & !shifted_in,
+                        },
+                        _ => KnownBits {
+                            zeros: shifted.zeros | shifted_in,
+                            ones: shifted.ones,
+                        },
+                    }
+                }
+                None => KnownBits::unknown(),
+            },
+            Op::Eq => {
+                // Only the lowest bit can be set.
+                let out = KnownBits {
+                    zeros: mask & !1,
+                    ones: 0,
+                };
+                match (a.as_constant(width), b.as_constant(width)) {
+                    (Some(a), Some(b)) => out.with_bit(0, Some(a == b)),
+                    _ if (a.ones & b.zeros) | (a.zeros & b.ones) != 0 => {
+                        out.with_bit(0, Some(false))
+                    }
+                    _ => out,
+                }
+            }
+            _ => KnownBits::unknown(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_functions() {
+        let x = KnownBits::unknown();
+        let c = |v| KnownBits::constant(v, 8);
+
+        assert_eq!(
+            KnownBits::binop(&Op::And, 8, x, c(0x0f)),
+            KnownBits {
+                zeros: 0xf0,
+                ones: 0
+            }
+        );
+        assert_eq!(
+            KnownBits::binop(&Op::Or, 8, x, c(0x80)),
+            KnownBits {
+                zeros: 0,
+                ones: 0x80
+            }
+        );
+        assert_eq!(
+            KnownBits::binop(&Op::Lsr, 8, x, c(4)),
+            KnownBits {
+                zeros: 0xf0,
+                ones: 0
+            }
+        );
+        assert_eq!(KnownBits::binop(&Op::Add, 8, c(200), c(100)), c(44));
+        assert_eq!(KnownBits::binop(&Op::Sub, 8, c(3), c(5)), c(254));
+        assert_eq!(KnownBits::unop(&Op::Neg, 8, c(1)), c(255));
+        // Adding two even numbers gives an even number.
+        let even = KnownBits { zeros: 1, ones: 0 };
+        assert_eq!(KnownBits::binop(&Op::Add, 8, even, even).zeros & 1, 1);
+        assert_eq!(KnownBits::binop(&Op::Mul, 8, even, even).zeros & 3, 3);
+        assert_eq!(
+            KnownBits::of_range(&Interval { lo: 0, hi: 15 }, 8),
+            KnownBits {
+                zeros: 0xf0,
+                ones: 0
+            }
+        );
+        // A zero-extended shift amount is known to be small.
+        let amount = KnownBits::unknown().zero_extend(3, 8);
+        assert_eq!(amount.possible_ones(8), 7);
+        assert_eq!(KnownBits::constant(0, 5).concat(&x, 3, 8), amount);
+    }
+
+    /// The masks only hold 128 bits, so nothing is known about wider
+    /// signals, rather than what's known about their low bits.
+    #[test]
+    fn wide_signals_are_unknown() {
+        let c = |v| KnownBits::constant(v, 100);
+        // (cat (const 0 100) (const 0 100)) isn't the constant 0.
+        let wide = c(0).concat(&c(0), 100, 200);
+        assert_eq!(wide, KnownBits::unknown());
+        assert_eq!(wide.as_constant(200), None);
+        assert_eq!(KnownBits::constant(0, 200).as_constant(200), None);
+        // Shifting a 200-bit signal right by 130 bits keeps 70 of them.
+        assert_eq!(
+            KnownBits::binop(
+                &Op::Lsr,
+                200,
+                KnownBits::unknown(),
+                KnownBits::constant(130, 200)
+            ),
+            KnownBits::unknown()
+        );
+        assert_eq!(
+            KnownBits::unop(&Op::Not, 200, KnownBits::unknown()),
+            KnownBits::unknown()
+        );
+        assert_eq!(
+            KnownBits::of_range(&Interval::constant(0), 200),
+            KnownBits::unknown()
+        );
+    }
+}
@@ -1,3 +1,82 @@
+//! Synthesizing ISAs from programs.
+//!
+//! The pipeline is in [`Synthesizer`]: programs (see [`frontends`]) are
+//! rewritten in an egraph over the [`Language`], candidate instructions are
+//! extracted from it, checked by a [`SynthesisBackend`], and selected into an
+//! ISA. The modules follow the pipeline:
+//!
+//! - [`language`], [`analysis`]: the terms, their types, and what the egraph
+//!   knows about them;
+//! - [`rewrites`]: the rewrites which find candidates;
+//! - [`extract`], [`isa`]: getting candidates and ISAs out of the egraph;
+//! - [`backend`], [`solver`], and the Racket backend: checking candidates.
+//!
+//! The types most programs need are re-exported here.
+
+pub mod analysis;
+pub mod arch;
+pub mod ast;
+pub mod backend;
+pub mod benchmarks;
+pub mod bitvec;
+pub mod builder;
+pub mod cancel;
+pub mod checkpoint;
+pub mod config;
+pub mod corpus;
+#[cfg(all(feature = "database", not(target_arch = "wasm32")))]
+pub mod database;
+pub mod determinism;
+pub mod dsp;
+pub mod egglog;
+pub mod emit;
+pub mod encoding;
+pub mod equiv;
+pub mod error;
+pub mod eval;
 #[cfg(test)]
 pub(crate) mod example_programs;
+pub mod extract;
+pub mod ffi;
+pub mod frontends;
+pub mod generalize;
+pub mod generate;
+pub mod interval;
+pub mod isa;
+pub mod known_bits;
 pub mod language;
+pub mod lut;
+pub mod metrics;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+pub mod parallel;
+pub mod pipeline;
+pub mod profile;
+pub mod program_set;
+pub mod progress;
+pub mod prune;
+pub mod qemu;
+#[cfg(all(feature = "racket", not(target_arch = "wasm32")))]
+pub mod racket;
+pub mod rewrites;
+pub mod sequential;
+pub mod session;
+pub mod simplify;
+pub mod smt;
+pub mod solver;
+pub mod split;
+pub mod superopt;
+pub mod synthesizer;
+pub mod target;
+pub mod trivial;
+pub mod truth_table;
+#[cfg(all(feature = "tui", not(target_arch = "wasm32")))]
+pub mod tui;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
+
+pub use analysis::{LanguageAnalysis, LanguageAnalysisData};
+pub use backend::SynthesisBackend;
+pub use error::LakeroadError;
+pub use language::{typecheck, Language, Op, Type};
+pub use program_set::ProgramSet;
+pub use synthesizer::{SynthesisResult, Synthesizer};
@@ -0,0 +1,336 @@
+//! Technology mapping to k-input LUTs.
+//!
+//! In this mode, the candidate instructions are LUTs: [`lut_rules`] abstract
+//! any cone whose arguments are at most `k` bits wide in total into an
+//! `apply`, [`LutBackend`] checks candidates by building their truth tables,
+//! and [`map_to_luts`] extracts the cover of a program using the fewest
+//! LUTs. An instruction with an `n`-bit output is `n` LUTs, one per output
+//! bit, as each bit depends on at most `k` input bits.
+//!
+//! Arguments are counted bit by bit, and constants count too, so wide
+//! bitwise operators aren't split into per-bit LUTs; this maps narrow (e.g.
+//! bit-blasted) programs.
+//!
+//! Architectures combine LUTs through dedicated muxes, e.g. Xilinx's
+//! `MUXF7`s, so [`map_to_muxed_luts`] also builds cones of a few more inputs
+//! than a LUT takes, which map onto mux trees (see
+//! [`crate::arch::Netlist::lut`]), as wide selects and priority encoders
+//! do. Each such cone costs the LUTs and muxes of its tree, so it's only
+//! extracted where it beats a cover of smaller cones.
+
+use std::collections::{BTreeSet, HashMap};
+
+use egg::{
+    rewrite, AstSize, CostFunction, EGraph, Extractor, Id, Language as LanguageTrait, RecExpr,
+    Rewrite, Runner, Subst, Var,
+};
+
+use crate::{
+    analysis::{LanguageAnalysis, LanguageAnalysisData::*},
+    backend::SynthesisBackend,
+    equiv::equiv_exhaustive,
+    error::LakeroadError,
+    language::Language,
+    rewrites::{canonicalize, introduce_hole_var, not_pruned, simplify_concat, unary0},
+    truth_table::{mux_tree_size, TruthTable},
+};
+
+/// Condition which holds when an `apply` whose arguments are the lists bound
+/// to `lists` and the signals bound to `signals` has at most `k` input bits:
+/// the widths of its distinct arguments, summed.
+pub fn cone_inputs_at_most(
+    k: usize,
+    lists: &[&str],
+    signals: &[&str],
+) -> impl Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    let lists: Vec<Var> = lists.iter().map(|var| var.parse().unwrap()).collect();
+    let signals: Vec<Var> = signals.iter().map(|var| var.parse().unwrap()).collect();
+    move |egraph, _, subst| {
+        let mut args = BTreeSet::new();
+        for var in &lists {
+            match &egraph[subst[*var]].data {
+                List(ids) => args.extend(ids.iter().map(|id| egraph.find(*id))),
+                _ => return false,
+            }
+        }
+        args.extend(signals.iter().map(|var| egraph.find(subst[*var])));
+        let mut bits = 0;
+        for id in args {
+            match &egraph[id].data {
+                Signal { width, .. } => bits += width,
+                _ => return false,
+            }
+        }
+        bits <= k
+    }
+}
+
+/// The rewrites which build LUT candidates of at most `k` input bits:
+/// versions of [`crate::rewrites::fuse_op`] and the `introduce_hole_op_*`
+/// rewrites which only fire within the limit, and the rewrites they need.
+pub fn lut_rules(k: usize) -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    vec![
+        introduce_hole_var(),
+        rewrite!(format!("fuse-op-lut-{}", k);
+                    "(binop ?op ?bw
+                      (apply (instr ?ast0 ?canonical-args0) ?args0)
+                      (apply (instr ?ast1 ?canonical-args1) ?args1))" =>
+                    "(apply
+                      (instr (binop-ast ?op ?bw ?ast0 ?ast1) (canonicalize (concat ?args0 ?args1)))
+                      (concat ?args0 ?args1))"
+                    if cone_inputs_at_most(k, &["?args0", "?args1"], &[])
+                    if not_pruned(&[("?ast0", "?canonical-args0"), ("?ast1", "?canonical-args1")])),
+        rewrite!(format!("introduce-hole-op-left-lut-{}", k);
+                    "(binop ?op ?bw
+                      ?left
+                      (apply (instr ?ast1 ?canonical-args1) ?args1))" =>
+                    "(apply
+                      (instr
+                       (binop-ast ?op ?bw (hole ?bw) ?ast1)
+                       (canonicalize (concat (list ?left) ?args1)))
+                      (concat (list ?left) ?args1))"
+                    if cone_inputs_at_most(k, &["?args1"], &["?left"])
+                    if not_pruned(&[("?ast1", "?canonical-args1")])),
+        rewrite!(format!("introduce-hole-op-right-lut-{}", k);
+                    "(binop ?op ?bw
+                      (apply (instr ?ast0 ?canonical-args0) ?args0)
+                      ?right)" =>
+                    "(apply
+                      (instr
+                       (binop-ast ?op ?bw ?ast0 (hole ?bw))
+                       (canonicalize (concat ?args0 (list ?right))))
+                      (concat ?args0 (list ?right)))"
+                    if cone_inputs_at_most(k, &["?args0"], &["?right"])
+                    if not_pruned(&[("?ast0", "?canonical-args0")])),
+        rewrite!(format!("introduce-hole-op-both-lut-{}", k);
+                    "(binop ?op ?bw ?a ?b)" =>
+                    "(apply
+                      (instr
+                       (binop-ast ?op ?bw (hole ?bw) (hole ?bw))
+                       (canonicalize (list ?a ?b)))
+                      (list ?a ?b))"
+                    if cone_inputs_at_most(k, &[], &["?a", "?b"])),
+        // Keeps the arguments, so stays within the limit.
+        unary0(),
+        simplify_concat(),
+        canonicalize(),
+    ]
+}
+
+/// Accepts candidates of at most `k` input bits, after building their truth
+/// tables, and checks equivalence by trying every input. `k` can be at most
+/// [`crate::truth_table::MAX_INPUT_BITS`].
+pub struct LutBackend {
+    k: usize,
+}
+
+impl LutBackend {
+    pub fn new(k: usize) -> Self {
+        LutBackend { k }
+    }
+}
+
+impl SynthesisBackend for LutBackend {
+    fn check_feasible(&self, expr: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+        match TruthTable::of_expr(expr) {
+            Ok(table) => Ok(table.input_bits() <= self.k),
+            Err(LakeroadError::Unsupported(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("LutBackend({})", self.k)
+    }
+
+    fn verify_equivalent(
+        &self,
+        a: &RecExpr<Language>,
+        b: &RecExpr<Language>,
+    ) -> Result<bool, LakeroadError> {
+        Ok(equiv_exhaustive(a, b)?.is_none())
+    }
+}
+
+/// Counts the LUTs a term needs, as a tree, then the muxes, and then its
+/// size, to break ties. An `apply` of an instr with an `n`-bit output takes
+/// `n` LUTs, except that a lone hole is a wire and takes none, and that an
+/// instr of more than `k` input bits takes the [`mux_tree_size`] of each
+/// output bit. Operators outside an `apply` can't be implemented, so they
+/// cost [`usize::MAX`] LUTs.
+struct LutCount<'a> {
+    egraph: &'a EGraph<Language, LanguageAnalysis>,
+    k: usize,
+    /// The smallest term of each eclass, for instrs' truth tables.
+    terms: Extractor<'a, AstSize, Language, LanguageAnalysis>,
+    /// The LUTs and muxes of each instr costed by its mux trees so far.
+    trees: HashMap<Id, (usize, usize)>,
+}
+
+impl LutCount<'_> {
+    fn is_hole(&self, instr: Id) -> bool {
+        self.egraph[instr].nodes.iter().any(|node| match node {
+            Language::Instr([ast, _]) => self.egraph[*ast]
+                .nodes
+                .iter()
+                .any(|node| matches!(node, Language::Hole(_))),
+            _ => false,
+        })
+    }
+
+    /// The input bits of an `apply` whose arguments are `args`: the widths
+    /// of its distinct arguments, summed.
+    fn input_bits(&self, args: Id) -> usize {
+        match &self.egraph[args].data {
+            List(ids) => ids
+                .iter()
+                .map(|id| self.egraph.find(*id))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .map(|id| match self.egraph[id].data {
+                    Signal { width, .. } => width,
+                    _ => 0,
+                })
+                .sum(),
+            _ => 0,
+        }
+    }
+
+    /// The LUTs and muxes of the mux trees of `instr`, which is wider than
+    /// `k`.
+    fn mux_trees(&mut self, instr: Id) -> (usize, usize) {
+        let instr = self.egraph.find(instr);
+        if let Some(tree) = self.trees.get(&instr) {
+            return *tree;
+        }
+        let tree = match TruthTable::of_instr(&self.terms.find_best(instr).1) {
+            Ok(table) => (0..table.output_width)
+                .map(|bit| mux_tree_size(&table.column(bit), self.k))
+                .fold((0, 0), |(a, b), (c, d)| (a + c, b + d)),
+            Err(_) => (usize::MAX, 0),
+        };
+        self.trees.insert(instr, tree);
+        tree
+    }
+}
+
+impl CostFunction<Language> for LutCount<'_> {
+    type Cost = (usize, usize, usize);
+
+    fn cost<C>(&mut self, enode: &Language, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        let add = |(a, b, c): Self::Cost, (d, e, f): Self::Cost| {
+            (
+                a.saturating_add(d),
+                b.saturating_add(e),
+                c.saturating_add(f),
+            )
+        };
+        let egraph = self.egraph;
+        let (luts, muxes) = match enode {
+            &Language::Apply([instr_id, _]) if self.is_hole(instr_id) => (0, 0),
+            &Language::Apply([instr_id, args_id]) => match egraph[instr_id].data {
+                Instr(_) if self.input_bits(args_id) > self.k => self.mux_trees(instr_id),
+                Instr(width) => (width, 0),
+                _ => (usize::MAX, 0),
+            },
+            Language::UnOp(_)
+            | Language::BinOp(_)
+            | Language::Canonicalize(_)
+            | Language::Concat(_) => (usize::MAX, 0),
+            _ => (0, 0),
+        };
+        enode
+            .children()
+            .iter()
+            .fold((luts, muxes, 1), |sum, id| add(sum, costs(*id)))
+    }
+}
+
+/// A program covered by LUTs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LutMapping {
+    /// How many LUTs the cover takes, counting shared cones once per use.
+    pub luts: usize,
+    /// How many muxes combine them into mux trees, counted the same way.
+    pub muxes: usize,
+    /// The program, as `apply`s of the LUTs' instrs.
+    pub expr: RecExpr<Language>,
+}
+
+/// Covers `program` with `k`-input LUTs, rewriting with [`lut_rules`] for up
+/// to `iter_limit` iterations and extracting the cover with the fewest LUTs.
+/// Fails if `program` can't be covered, e.g. if an operator has more than
+/// `k` bits of inputs.
+pub fn map_to_luts(
+    program: &RecExpr<Language>,
+    k: usize,
+    iter_limit: usize,
+) -> Result<LutMapping, LakeroadError> {
+    map_to_muxed_luts(program, k, 0, iter_limit)
+}
+
+/// Covers `program` with `k`-input LUTs and trees of them combined by up to
+/// `levels` levels of muxes, extracting the cover with the fewest LUTs and
+/// then the fewest muxes, as in [`map_to_luts`].
+pub fn map_to_muxed_luts(
+    program: &RecExpr<Language>,
+    k: usize,
+    levels: usize,
+    iter_limit: usize,
+) -> Result<LutMapping, LakeroadError> {
+    let mut egraph = EGraph::default();
+    let root = egraph.add_expr(program);
+    let runner = Runner::default()
+        .with_egraph(egraph)
+        .with_iter_limit(iter_limit)
+        .run(&lut_rules(k + levels));
+    let extractor = Extractor::new(
+        &runner.egraph,
+        LutCount {
+            egraph: &runner.egraph,
+            k,
+            terms: Extractor::new(&runner.egraph, AstSize),
+            trees: HashMap::new(),
+        },
+    );
+    match extractor.find_best(runner.egraph.find(root)) {
+        ((usize::MAX, _, _), _) => Err(LakeroadError::Unsupported(format!(
+            "covering {} with {}-input LUTs",
+            program, k
+        ))),
+        ((luts, muxes, _), expr) => Ok(LutMapping { luts, muxes, expr }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn map_cones_to_luts() {
+        // (a & b) ^ (c | d), one bit wide.
+        let program = RecExpr::from_str(
+            "(binop xor 1 (binop and 1 (var a 1) (var b 1)) (binop or 1 (var c 1) (var d 1)))",
+        )
+        .unwrap();
+        for (k, luts) in [(4, 1), (3, 2), (2, 3)] {
+            let mapping = map_to_luts(&program, k, 10).unwrap();
+            assert_eq!(mapping.luts, luts, "{}-LUTs: {}", k, mapping.expr);
+            assert_eq!(equiv_exhaustive(&program, &mapping.expr).unwrap(), None);
+        }
+
+        let wide = RecExpr::from_str("(binop and 8 (var x 8) (var y 8))").unwrap();
+        assert!(matches!(
+            map_to_luts(&wide, 4, 10),
+            Err(LakeroadError::Unsupported(_))
+        ));
+        let backend = LutBackend::new(4);
+        assert!(!backend.check_feasible(&wide).unwrap());
+        assert!(backend.check_feasible(&program).unwrap());
+    }
+}
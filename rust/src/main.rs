@@ -0,0 +1,715 @@
+//! The `lakeroad` command-line interface.
+//!
+//! Candidates are passed between subcommands as files with one `instr`
+//! s-expression per line, so e.g. `lakeroad explore progs/*.sexp >
+//! candidates && lakeroad verify candidates > verified && lakeroad select
+//! --candidates verified progs/*.sexp` runs the whole pipeline.
+
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use egg::{AstSize, RecExpr};
+#[cfg(feature = "database")]
+use lakeroad::database::Database;
+#[cfg(feature = "racket")]
+use lakeroad::racket::racket_backend;
+use lakeroad::{
+    arch,
+    ast::{Expr, Instr},
+    checkpoint::CheckpointOptions,
+    config::Config,
+    corpus::Corpus,
+    emit::{export_instructions, export_testbench, to_structural_verilog, ExportFormat},
+    encoding::{assign_encodings, infer_immediate_ranges, opcode_fragments, EncodingOptions},
+    error::LakeroadError,
+    language::Language,
+    lut::map_to_muxed_luts,
+    pipeline,
+    program_set::ProgramSet,
+    qemu::qemu_stubs,
+    session::Session,
+    simplify::{simplification_rules, simplify},
+    superopt::Superoptimizer,
+    synthesizer::{CandidateSummary, CostModel, Synthesizer},
+    target::Target,
+    trivial::TrivialFilter,
+};
+#[cfg(feature = "z3")]
+use lakeroad::{
+    arch::Netlist,
+    generalize::unify_widths,
+    smt::{SmtBackend, SmtSolver},
+};
+
+#[derive(Parser)]
+#[clap(name = "lakeroad", about = "Synthesize ISAs from programs")]
+struct Cli {
+    /// Serve metrics to Prometheus at this address, e.g. 0.0.0.0:9000.
+    #[cfg(feature = "prometheus")]
+    #[clap(long, global = true)]
+    metrics_address: Option<std::net::SocketAddr>,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Rewrite programs and print the candidate instructions found.
+    Explore {
+        #[clap(flatten)]
+        limits: Limits,
+        /// Program files, in any format the corpus loader understands.
+        #[clap(required = true)]
+        programs: Vec<PathBuf>,
+    },
+    /// Print the candidates the solver can implement.
+    Verify {
+        /// A file of candidates, one per line.
+        candidates: PathBuf,
+    },
+    /// Select an ISA for programs from a set of candidates.
+    Select {
+        /// Only select from these candidates. By default, every candidate
+        /// found is considered.
+        #[clap(long)]
+        candidates: Option<PathBuf>,
+        #[clap(long, default_value = "3")]
+        max_instructions: usize,
+        /// A fixed cost added for each instruction in the ISA.
+        #[clap(long, default_value = "0")]
+        per_instruction_cost: f64,
+        /// Extract each program's implementation by the primitives it takes
+        /// on this architecture, e.g. `xilinx-ultrascale-plus`, and report
+        /// them.
+        #[clap(long)]
+        arch: Option<String>,
+        #[clap(long, default_value = "0")]
+        seed: u64,
+        /// Write a JSON report of the run to this file.
+        #[clap(long)]
+        report: Option<PathBuf>,
+        /// Write each program, compiled to the ISA, as an assembly-like
+        /// listing to this file.
+        #[clap(long)]
+        listing: Option<PathBuf>,
+        /// Save progress to this file, resuming from it if it exists.
+        #[clap(long)]
+        checkpoint: Option<PathBuf>,
+        /// Save the checkpoint after this many new solver verdicts.
+        #[clap(long, default_value = "10")]
+        checkpoint_every: usize,
+        /// Record the run in this SQLite database.
+        #[clap(long)]
+        database: Option<PathBuf>,
+        /// Rewrite each program in parallel, then merge the e-graphs.
+        #[clap(long)]
+        parallel: bool,
+        /// List the candidates found, without checking them or selecting an
+        /// ISA.
+        #[clap(long)]
+        dry_run: bool,
+        /// Show the run's progress on a terminal dashboard.
+        #[clap(long)]
+        tui: bool,
+        /// Drop accepted candidates which are moves, identities, constants,
+        /// or the same as a smaller candidate before selecting an ISA.
+        #[clap(long)]
+        drop_trivial: bool,
+        #[clap(flatten)]
+        limits: Limits,
+        #[clap(required = true)]
+        programs: Vec<PathBuf>,
+    },
+    /// Write candidates in another format.
+    Export {
+        #[clap(long, value_enum)]
+        format: Format,
+        /// A file of candidates, one per line.
+        candidates: PathBuf,
+        /// Also write a testbench for the candidates to this file.
+        #[clap(long)]
+        testbench: Option<PathBuf>,
+        /// How many test cases the testbench checks per candidate.
+        #[clap(long, default_value = "16")]
+        test_vectors: usize,
+        /// Draws the testbench's test cases, past the corner cases.
+        #[clap(long, default_value = "0")]
+        seed: u64,
+    },
+    /// Assign candidates RISC-V custom-0 and custom-1 encodings, and print
+    /// them and the candidates which don't fit as JSON.
+    Encode {
+        /// A file of candidates, one per line.
+        candidates: PathBuf,
+        /// The width of a register.
+        #[clap(long, default_value = "32")]
+        xlen: i64,
+        /// Encode the last operand of the candidate on this line, counting
+        /// from zero, as an immediate.
+        #[clap(long)]
+        immediate: Vec<usize>,
+        /// Encode each immediate in as few bits as hold the constants these
+        /// programs pass it, rather than its operand's width.
+        #[clap(long)]
+        programs: Vec<PathBuf>,
+        /// Also write riscv-opcodes and binutils opcode table fragments for
+        /// the encoded candidates to this directory.
+        #[clap(long)]
+        opcodes: Option<PathBuf>,
+    },
+    /// Write QEMU helpers and decoder fragments for candidates, as RISC-V
+    /// custom-0 instructions, and print the files written.
+    Qemu {
+        /// A file of candidates, one per line.
+        candidates: PathBuf,
+        #[clap(long, default_value = "qemu")]
+        out: PathBuf,
+    },
+    /// Map programs onto an FPGA's primitives, and print them as
+    /// structural Verilog.
+    Map {
+        /// The architecture, e.g. `xilinx-ultrascale-plus`.
+        #[clap(long, default_value = "xilinx-ultrascale-plus")]
+        arch: String,
+        #[clap(long, default_value = "10")]
+        iter_limit: usize,
+        /// Prove each mapping computes its program with z3, from the
+        /// primitives' semantics.
+        #[clap(long)]
+        verify: bool,
+        /// Pipeline each program to take this many cycles.
+        #[clap(long)]
+        pipeline: Option<usize>,
+        #[clap(required = true)]
+        programs: Vec<PathBuf>,
+    },
+    /// Implement programs in a target's instructions, as described by a
+    /// TOML or JSON file, and print each implementation and its cost.
+    Implement {
+        #[clap(long)]
+        target: PathBuf,
+        #[clap(long, default_value = "10")]
+        iter_limit: usize,
+        /// Prove each implementation computes its program with z3.
+        #[clap(long)]
+        verify: bool,
+        #[clap(required = true)]
+        programs: Vec<PathBuf>,
+    },
+    /// Simplify programs, and print each one's smallest equivalent form.
+    Simplify {
+        #[clap(required = true)]
+        programs: Vec<PathBuf>,
+    },
+    /// Find the shortest implementation of each program in a fixed ISA by
+    /// enumerating programs of its instructions and proving them with z3,
+    /// and print it, or `-` if there's none within the length.
+    Superoptimize {
+        /// A file of the ISA's instructions, one per line.
+        #[clap(long)]
+        isa: PathBuf,
+        /// The most instructions to try.
+        #[clap(long, default_value = "3")]
+        max_length: usize,
+        #[clap(required = true)]
+        programs: Vec<PathBuf>,
+    },
+    /// Print the instructions of ISAs found for the same programs at
+    /// different widths, each instruction found at several widths once, as
+    /// a template over the width `w`, with the widths it was found at.
+    /// Instructions which only differ as written are proven the same with
+    /// z3.
+    Unify {
+        /// Files of each ISA's instructions, one per line.
+        #[clap(required = true)]
+        isas: Vec<PathBuf>,
+    },
+    /// Explore programs interactively; type `help` for the commands.
+    Repl {
+        /// Program files to load at the start.
+        programs: Vec<PathBuf>,
+    },
+    /// Run the whole pipeline as described by a configuration file.
+    Run {
+        #[clap(long, default_value = "lakeroad.toml")]
+        config: PathBuf,
+        /// List the candidates found, without checking them or selecting an
+        /// ISA.
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(clap::Args)]
+struct Limits {
+    #[clap(long, default_value = "10")]
+    iter_limit: usize,
+    #[clap(long, default_value = "100000")]
+    node_limit: usize,
+    /// Stop after this many seconds with what was found by then: rewriting
+    /// gets half, and checking candidates the rest.
+    #[clap(long)]
+    time_budget: Option<f64>,
+}
+
+impl Limits {
+    fn apply(&self, synthesizer: Synthesizer) -> Result<Synthesizer, Box<dyn Error>> {
+        let synthesizer = synthesizer
+            .with_iter_limit(self.iter_limit)
+            .with_node_limit(self.node_limit);
+        Ok(match self.time_budget {
+            Some(seconds) => synthesizer.with_time_budget(Duration::try_from_secs_f64(seconds)?),
+            None => synthesizer,
+        })
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum Format {
+    Verilog,
+    Json,
+    Rosette,
+    C,
+}
+
+impl From<Format> for ExportFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Verilog => ExportFormat::Verilog,
+            Format::Json => ExportFormat::Json,
+            Format::Rosette => ExportFormat::Rosette,
+            Format::C => ExportFormat::C,
+        }
+    }
+}
+
+fn load_programs(paths: &[PathBuf]) -> Result<ProgramSet, Box<dyn Error>> {
+    let mut programs = ProgramSet::new();
+    for path in paths {
+        let loaded = Corpus::load_file(path)
+            .ok_or_else(|| format!("{}: not a program file", path.display()))?
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        programs.append(&loaded);
+    }
+    Ok(programs)
+}
+
+fn load_candidates(path: &Path) -> Result<Vec<RecExpr<Language>>, Box<dyn Error>> {
+    fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            RecExpr::from_str(line.trim())
+                .map_err(|e| Box::<dyn Error>::from(format!("{}:{}: {}", path.display(), i + 1, e)))
+        })
+        .collect()
+}
+
+fn explore(
+    programs: &ProgramSet,
+    limits: &Limits,
+) -> Result<Vec<RecExpr<Language>>, Box<dyn Error>> {
+    Ok(limits
+        .apply(Synthesizer::new().add_programs(programs))?
+        .explore()?
+        .candidates
+        .into_iter()
+        .map(|(_, instr)| instr)
+        .collect())
+}
+
+/// Prints one candidate per line: its stable id, size, predicted cost (`-`
+/// if it can't implement every program alone), the programs it appears in,
+/// and the candidate.
+fn print_candidates(candidates: &[CandidateSummary]) {
+    println!("id\tsize\tcost\tprograms\tinstr");
+    for candidate in candidates {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            candidate.id,
+            candidate.size,
+            candidate
+                .predicted_cost
+                .map_or("-".to_string(), |cost| cost.to_string()),
+            candidate.programs.join(","),
+            candidate.instr
+        );
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+    let cli = Cli::parse();
+    #[cfg(feature = "prometheus")]
+    {
+        if let Some(address) = cli.metrics_address {
+            metrics_exporter_prometheus::PrometheusBuilder::new()
+                .with_http_listener(address)
+                .install()?;
+            lakeroad::metrics::describe();
+        }
+    }
+    match cli.command {
+        Command::Explore { limits, programs } => {
+            for instr in explore(&load_programs(&programs)?, &limits)? {
+                println!("{}", instr);
+            }
+        }
+        Command::Verify { candidates } => {
+            #[cfg(feature = "racket")]
+            for instr in load_candidates(&candidates)? {
+                if racket_backend(&instr)? {
+                    println!("{}", instr);
+                }
+            }
+            #[cfg(not(feature = "racket"))]
+            {
+                let _ = candidates;
+                return Err("verifying candidates needs the `racket` feature".into());
+            }
+        }
+        Command::Select {
+            candidates,
+            max_instructions,
+            per_instruction_cost,
+            arch,
+            seed,
+            report,
+            listing,
+            checkpoint,
+            checkpoint_every,
+            database,
+            parallel,
+            dry_run,
+            tui,
+            drop_trivial,
+            limits,
+            programs,
+        } => {
+            let allowed = match candidates {
+                Some(path) => Some(
+                    load_candidates(&path)?
+                        .iter()
+                        .map(|instr| instr.to_string())
+                        .collect::<HashSet<_>>(),
+                ),
+                None => None,
+            };
+            let mut synthesizer = limits
+                .apply(Synthesizer::new().add_programs(&load_programs(&programs)?))?
+                .with_max_instructions(max_instructions)
+                .with_seed(seed)
+                .with_parallel_exploration(parallel)
+                .with_cost_model(CostModel {
+                    per_instruction: per_instruction_cost,
+                    ..CostModel::default()
+                })
+                .with_backend(move |instr| {
+                    allowed
+                        .as_ref()
+                        .map_or(true, |allowed| allowed.contains(&instr.to_string()))
+                });
+            if let Some(arch) = arch {
+                synthesizer = synthesizer.with_architecture(arch::by_name(&arch)?);
+            }
+            if dry_run {
+                print_candidates(&synthesizer.dry_run()?);
+                return Ok(());
+            }
+            if let Some(path) = checkpoint {
+                synthesizer = synthesizer.with_checkpoint(CheckpointOptions {
+                    path,
+                    every: checkpoint_every,
+                });
+            }
+            if drop_trivial {
+                synthesizer = synthesizer.with_trivial_filter(TrivialFilter::default());
+            }
+            if tui {
+                #[cfg(feature = "tui")]
+                {
+                    synthesizer = synthesizer.with_observer(lakeroad::tui::Dashboard::new()?);
+                }
+                #[cfg(not(feature = "tui"))]
+                return Err("--tui needs the `tui` feature".into());
+            }
+            let result = synthesizer.run()?;
+            if let Some(path) = report {
+                fs::write(path, result.report.to_json())?;
+            }
+            if let Some(path) = listing {
+                let listings = result
+                    .report
+                    .coverage
+                    .iter()
+                    .filter_map(|coverage| coverage.listing.as_deref())
+                    .collect::<Vec<_>>();
+                fs::write(path, listings.join("\n"))?;
+            }
+            if let Some(path) = database {
+                #[cfg(feature = "database")]
+                Database::open(path)?.record(&result.report)?;
+                #[cfg(not(feature = "database"))]
+                {
+                    let _ = path;
+                    return Err("--database needs the `database` feature".into());
+                }
+            }
+            if result.report.incomplete {
+                eprintln!(
+                    "the time budget ran out after checking {} of {} candidates; \
+                     this is the best ISA among them",
+                    result.report.verdicts.len(),
+                    result.report.candidates
+                );
+            }
+            for instr in &result.instructions {
+                println!("{}", instr);
+            }
+            for coverage in &result.report.coverage {
+                if let Some(primitives) = &coverage.primitives {
+                    eprintln!("{}: {}", coverage.program, primitives);
+                }
+            }
+        }
+        Command::Export {
+            format,
+            candidates,
+            testbench,
+            test_vectors,
+            seed,
+        } => {
+            let candidates = load_candidates(&candidates)?;
+            print!(
+                "{}",
+                export_instructions(format.clone().into(), &candidates)?
+            );
+            if let Some(path) = testbench {
+                fs::write(
+                    path,
+                    export_testbench(format.into(), &candidates, test_vectors, seed)?,
+                )?;
+            }
+        }
+        Command::Encode {
+            candidates,
+            xlen,
+            immediate,
+            programs,
+            opcodes,
+        } => {
+            let candidates = load_candidates(&candidates)?;
+            let mut options = immediate
+                .into_iter()
+                .fold(EncodingOptions::default().with_xlen(xlen), |options, i| {
+                    options.with_immediate(i)
+                });
+            if !programs.is_empty() {
+                let programs = load_programs(&programs)?
+                    .iter()
+                    .map(|program| program.expr.clone())
+                    .collect::<Vec<_>>();
+                for (i, range) in infer_immediate_ranges(&candidates, &programs)?
+                    .into_iter()
+                    .enumerate()
+                {
+                    if let Some(range) = range {
+                        options = options.with_immediate_bits(i, range.bits);
+                    }
+                }
+            }
+            let report = assign_encodings(&candidates, &options)?;
+            if let Some(directory) = opcodes {
+                opcode_fragments(&report)?.write(&directory)?;
+            }
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Command::Qemu { candidates, out } => {
+            for path in qemu_stubs(&load_candidates(&candidates)?)?.write(&out)? {
+                println!("{}", path.display());
+            }
+        }
+        Command::Map {
+            arch,
+            iter_limit,
+            verify,
+            pipeline: latency,
+            programs,
+        } => {
+            let arch = arch::by_name(&arch)?;
+            #[cfg(feature = "z3")]
+            let mut solver = if verify { Some(SmtSolver::z3()?) } else { None };
+            #[cfg(not(feature = "z3"))]
+            if verify {
+                return Err("--verify needs the `z3` feature".into());
+            }
+            for program in load_programs(&programs)?.iter() {
+                // Programs LUTs can't cover alone, e.g. wide additions, are
+                // mapped as they are.
+                let mut expr = map_to_muxed_luts(
+                    &program.expr,
+                    arch.lut_inputs(),
+                    arch.mux_levels(),
+                    iter_limit,
+                )
+                .map_or_else(|_| program.expr.clone(), |mapping| mapping.expr);
+                if let Some(stages) = latency {
+                    expr = RecExpr::from(pipeline::pipeline(&Expr::try_from(&expr)?, stages)?);
+                }
+                #[cfg(feature = "z3")]
+                if let Some(solver) = &mut solver {
+                    let netlist = Netlist::map(arch.as_ref(), &expr)?;
+                    let correct = match latency {
+                        Some(stages) => {
+                            netlist.verify(solver, &expr)?
+                                && pipeline::verify_pipelined(
+                                    solver,
+                                    &Expr::try_from(&program.expr)?,
+                                    &Expr::try_from(&expr)?,
+                                    stages,
+                                )?
+                        }
+                        None => netlist.verify(solver, &program.expr)?,
+                    };
+                    if !correct {
+                        return Err(format!("the mapping of {} is wrong", program.name).into());
+                    }
+                }
+                let module = program
+                    .name
+                    .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+                print!("{}", to_structural_verilog(&module, &expr, arch.as_ref())?);
+            }
+        }
+        Command::Implement {
+            target,
+            iter_limit,
+            verify,
+            programs,
+        } => {
+            let target = Target::load(target)?;
+            #[cfg(feature = "z3")]
+            let mut solver = if verify { Some(SmtSolver::z3()?) } else { None };
+            #[cfg(not(feature = "z3"))]
+            if verify {
+                return Err("--verify needs the `z3` feature".into());
+            }
+            for program in load_programs(&programs)?.iter() {
+                let (cost, implementation) = target.implement(&program.expr, iter_limit)?;
+                #[cfg(feature = "z3")]
+                if let Some(solver) = &mut solver {
+                    if !target.verify(solver, &program.expr, &implementation)? {
+                        return Err(
+                            format!("the implementation of {} is wrong", program.name).into()
+                        );
+                    }
+                }
+                println!("{}\t{}\t{}", program.name, cost, implementation);
+            }
+        }
+        Command::Simplify { programs } => {
+            let rules = simplification_rules();
+            for program in load_programs(&programs)?.iter() {
+                let simplified = simplify(&program.expr, &rules, AstSize);
+                println!("{}\t{}", program.name, simplified);
+            }
+        }
+        Command::Superoptimize {
+            isa,
+            max_length,
+            programs,
+        } => {
+            let isa = load_candidates(&isa)?
+                .iter()
+                .map(Instr::try_from)
+                .collect::<Result<_, _>>()?;
+            let superoptimizer = Superoptimizer::new(isa).with_max_length(max_length);
+            #[cfg(feature = "z3")]
+            {
+                let backend = SmtBackend::new(SmtSolver::z3()?);
+                for program in load_programs(&programs)?.iter() {
+                    match superoptimizer.superoptimize(&program.expr, &backend)? {
+                        Some(implementation) => println!("{}\t{}", program.name, implementation),
+                        None => println!("{}\t-", program.name),
+                    }
+                }
+            }
+            #[cfg(not(feature = "z3"))]
+            {
+                let _ = (superoptimizer, programs);
+                return Err("superoptimizing needs the `z3` feature".into());
+            }
+        }
+        Command::Unify { isas } => {
+            let isas = isas
+                .iter()
+                .map(|path| load_candidates(path))
+                .collect::<Result<Vec<_>, _>>()?;
+            #[cfg(feature = "z3")]
+            {
+                let backend = SmtBackend::new(SmtSolver::z3()?);
+                for unified in unify_widths(&isas, &backend)? {
+                    let widths = unified
+                        .widths
+                        .iter()
+                        .map(|width| width.to_string())
+                        .collect::<Vec<_>>();
+                    println!("{}\t{}", unified.instr, widths.join(","));
+                }
+            }
+            #[cfg(not(feature = "z3"))]
+            {
+                let _ = isas;
+                return Err("unifying widths needs the `z3` feature".into());
+            }
+        }
+        Command::Repl { programs } => {
+            #[cfg(feature = "racket")]
+            let mut session = Session::new().with_backend(lakeroad::racket::RacketBackend::new());
+            #[cfg(not(feature = "racket"))]
+            let mut session = Session::new();
+            for path in &programs {
+                println!("{}", session.load(path)?);
+            }
+            let stdin = io::stdin();
+            loop {
+                print!("> ");
+                io::stdout().flush()?;
+                let mut line = String::new();
+                if stdin.read_line(&mut line)? == 0 || matches!(line.trim(), "quit" | "exit") {
+                    break;
+                }
+                match session.execute(&line) {
+                    Ok(out) if out.is_empty() => (),
+                    Ok(out) => println!("{}", out),
+                    Err(e) => eprintln!("error: {}", e),
+                }
+            }
+        }
+        Command::Run { config, dry_run } => {
+            let config = Config::load(config)?;
+            let synthesizer = config.synthesizer()?;
+            if dry_run {
+                print_candidates(&synthesizer.dry_run()?);
+                return Ok(());
+            }
+            let mut result = synthesizer.run()?;
+            for instr in &result.instructions {
+                println!("{}", instr);
+            }
+            for path in config.write_outputs(&mut result)? {
+                eprintln!("wrote {}", path.display());
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! Metrics for monitoring long runs, through the [`metrics`] facade.
+//!
+//! With the `metrics` feature, the [`Synthesizer`] updates the metrics below
+//! as it runs; they go to whichever recorder the embedding program installs,
+//! e.g. a Prometheus exporter. The `prometheus` feature adds such an
+//! exporter to the CLI (`lakeroad --metrics-address 0.0.0.0:9000 ...`).
+//! Without the feature, nothing is recorded.
+//!
+//! [`Synthesizer`]: crate::synthesizer::Synthesizer
+//! [`metrics`]: https://docs.rs/metrics
+
+use crate::progress::ProgressEvent;
+
+/// Counter: rewrite iterations run.
+pub const ITERATIONS: &str = "lakeroad_rewrite_iterations_total";
+/// Gauge: nodes in the e-graph being rewritten.
+pub const EGRAPH_NODES: &str = "lakeroad_egraph_nodes";
+/// Gauge: eclasses in the e-graph being rewritten.
+pub const EGRAPH_CLASSES: &str = "lakeroad_egraph_classes";
+/// Counter: candidate instructions extracted.
+pub const CANDIDATES: &str = "lakeroad_candidates_total";
+/// Gauge: candidates still waiting for a verdict.
+pub const SOLVER_QUEUE_DEPTH: &str = "lakeroad_solver_queue_depth";
+/// Counter: verdicts, labeled `verdict="accepted"` or `"rejected"`.
+pub const VERDICTS: &str = "lakeroad_verdicts_total";
+/// Histogram: seconds the backend took per candidate. Verdicts resumed
+/// from a checkpoint aren't timed.
+pub const SOLVER_SECONDS: &str = "lakeroad_solver_seconds";
+/// Counter: runs which selected an ISA.
+pub const RUNS: &str = "lakeroad_runs_total";
+/// Gauge: the score of the last selected ISA.
+pub const ISA_SCORE: &str = "lakeroad_isa_score";
+
+/// Registers descriptions of the metrics with the installed recorder. Call
+/// once, after installing it.
+#[cfg(feature = "metrics")]
+pub fn describe() {
+    use ::metrics::{describe_counter, describe_gauge, describe_histogram};
+
+    describe_counter!(ITERATIONS, "Rewrite iterations run.");
+    describe_gauge!(EGRAPH_NODES, "Nodes in the e-graph being rewritten.");
+    describe_gauge!(EGRAPH_CLASSES, "Eclasses in the e-graph being rewritten.");
+    describe_counter!(CANDIDATES, "Candidate instructions extracted.");
+    describe_gauge!(SOLVER_QUEUE_DEPTH, "Candidates waiting for a verdict.");
+    describe_counter!(VERDICTS, "Verdicts on candidates.");
+    describe_histogram!(SOLVER_SECONDS, "Seconds the backend took per candidate.");
+    describe_counter!(RUNS, "Runs which selected an ISA.");
+    describe_gauge!(ISA_SCORE, "The score of the last selected ISA.");
+}
+
+/// Updates the metrics derived from progress events.
+pub(crate) fn observe(event: &ProgressEvent) {
+    #[cfg(feature = "metrics")]
+    {
+        match event {
+            ProgressEvent::Phase(_) | ProgressEvent::Isa { .. } => (),
+            ProgressEvent::Iteration {
+                egraph_nodes,
+                egraph_classes,
+                ..
+            } => {
+                ::metrics::increment_counter!(ITERATIONS);
+                ::metrics::gauge!(EGRAPH_NODES, *egraph_nodes as f64);
+                ::metrics::gauge!(EGRAPH_CLASSES, *egraph_classes as f64);
+            }
+            ProgressEvent::Verdict {
+                accepted,
+                checked,
+                total,
+                ..
+            } => {
+                let verdict = if *accepted { "accepted" } else { "rejected" };
+                ::metrics::increment_counter!(VERDICTS, "verdict" => verdict);
+                ::metrics::gauge!(SOLVER_QUEUE_DEPTH, (total - checked) as f64);
+            }
+        }
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = event;
+}
+
+pub(crate) fn candidates_extracted(count: usize) {
+    #[cfg(feature = "metrics")]
+    {
+        ::metrics::counter!(CANDIDATES, count as u64);
+        ::metrics::gauge!(SOLVER_QUEUE_DEPTH, count as f64);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = count;
+}
+
+pub(crate) fn solver_time(seconds: f64) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(SOLVER_SECONDS, seconds);
+    #[cfg(not(feature = "metrics"))]
+    let _ = seconds;
+}
+
+pub(crate) fn isa_selected(score: f64) {
+    #[cfg(feature = "metrics")]
+    {
+        ::metrics::increment_counter!(RUNS);
+        ::metrics::gauge!(ISA_SCORE, score);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = score;
+}
@@ -0,0 +1,204 @@
+//! Rewriting each program in its own e-graph, in parallel, and merging the
+//! results.
+//!
+//! Rewriting one e-graph holding every program is sequential. When programs
+//! share little, rewriting them separately loses little, and the merged
+//! e-graph still hash-conses identical nodes, so an `instr` found for several
+//! programs ends up in a single eclass.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Duration};
+
+use egg::{EGraph, Id, Language as LanguageTrait, Rewrite, Runner};
+use rayon::prelude::*;
+
+use crate::{
+    analysis::LanguageAnalysis,
+    cancel::CancellationToken,
+    language::Language,
+    profile::{intervals, PhaseTime, Timer},
+    program_set::ProgramSet,
+    prune::OperandPorts,
+    synthesizer::IterationReport,
+};
+
+/// One program's e-graph after rewriting.
+pub struct ProgramExploration {
+    pub egraph: EGraph<Language, LanguageAnalysis>,
+    pub root: Id,
+    pub iterations: Vec<IterationReport>,
+    pub stop_reason: Option<String>,
+    /// Adding the program to its e-graph.
+    pub ingestion: PhaseTime,
+    /// Each iteration's time, as [`Profile::iterations`] has it.
+    ///
+    /// [`Profile::iterations`]: crate::profile::Profile::iterations
+    pub iteration_times: Vec<PhaseTime>,
+}
+
+/// Rewrites each program separately, on rayon's thread pool. Each program's
+/// e-graph is limited to `node_limit` nodes, and its rewriting to
+/// `time_limit`, or egg's default, and checks the hole
+/// introduction rewrites against `ports`, if any. Every program stops
+/// rewriting once `cancel` is cancelled.
+pub fn explore_programs(
+    programs: &ProgramSet,
+    rules: &[Rewrite<Language, LanguageAnalysis>],
+    iter_limit: usize,
+    node_limit: usize,
+    time_limit: Option<Duration>,
+    ports: Option<OperandPorts>,
+    cancel: &CancellationToken,
+) -> Vec<ProgramExploration> {
+    programs
+        .iter()
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|program| {
+            let ingestion = Timer::start();
+            let mut egraph = EGraph::new(LanguageAnalysis {
+                ports,
+                ..Default::default()
+            });
+            let root = egraph.add_expr(&program.expr);
+            let ingestion = ingestion.elapsed();
+            let rewriting = Timer::start();
+            // The time at the start of each iteration, and at the end.
+            let marks = Rc::new(RefCell::new(vec![]));
+            let hook_marks = marks.clone();
+            let cancel = cancel.clone();
+            let mut runner = Runner::default()
+                .with_egraph(egraph)
+                .with_iter_limit(iter_limit)
+                .with_node_limit(node_limit)
+                .with_hook(move |_| {
+                    hook_marks.borrow_mut().push(rewriting.elapsed());
+                    if cancel.is_cancelled() {
+                        return Err("cancelled".to_string());
+                    }
+                    Ok(())
+                });
+            if let Some(time_limit) = time_limit {
+                runner = runner.with_time_limit(time_limit);
+            }
+            let runner = runner.run(rules);
+            marks.borrow_mut().push(rewriting.elapsed());
+            let iteration_times = intervals(&marks.borrow());
+            ProgramExploration {
+                root: runner.egraph.find(root),
+                iterations: runner
+                    .iterations
+                    .iter()
+                    .map(IterationReport::from)
+                    .collect(),
+                stop_reason: runner.stop_reason.as_ref().map(|r| format!("{:?}", r)),
+                ingestion,
+                iteration_times,
+                egraph: runner.egraph,
+            }
+        })
+        .collect()
+}
+
+/// Adds every eclass of `from` to `into`, returning where each of `from`'s
+/// canonical eclasses ended up. Call [`EGraph::rebuild`] on `into` after
+/// merging.
+pub fn merge_egraph(
+    into: &mut EGraph<Language, LanguageAnalysis>,
+    from: &EGraph<Language, LanguageAnalysis>,
+) -> HashMap<Id, Id> {
+    let mut ids: HashMap<Id, Id> = HashMap::new();
+    let mut pending = from
+        .classes()
+        .flat_map(|class| class.nodes.iter().map(move |node| (class.id, node)))
+        .collect::<Vec<_>>();
+    // A node can only be added once its children are. Every eclass has a
+    // node whose children were added before it, so this terminates.
+    while !pending.is_empty() {
+        let before = pending.len();
+        pending.retain(|(class, node)| {
+            if !node
+                .children()
+                .iter()
+                .all(|child| ids.contains_key(&from.find(*child)))
+            {
+                return true;
+            }
+            let id = into.add((*node).clone().map_children(|child| ids[&from.find(child)]));
+            match ids.get(class) {
+                Some(existing) => {
+                    into.union(*existing, id);
+                }
+                None => {
+                    ids.insert(*class, id);
+                }
+            }
+            false
+        });
+        assert!(pending.len() < before, "no eclass could be added");
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::RecExpr;
+
+    use super::*;
+    use crate::{
+        extract::find_isa_instructions,
+        rewrites::{canonicalize, introduce_hole_var},
+    };
+
+    #[test]
+    fn merge_shares_instrs() {
+        let mut programs = ProgramSet::new();
+        programs.add(
+            "and",
+            RecExpr::from_str("(binop and 8 (var x 8) (var y 8))").unwrap(),
+        );
+        programs.add(
+            "and-or",
+            RecExpr::from_str("(binop or 8 (binop and 8 (var x 8) (var y 8)) (var z 8))").unwrap(),
+        );
+        let rules = [introduce_hole_var(), canonicalize()];
+        let explored = explore_programs(
+            &programs,
+            &rules,
+            5,
+            10_000,
+            None,
+            None,
+            &CancellationToken::new(),
+        );
+
+        let mut merged = EGraph::default();
+        let roots = explored
+            .iter()
+            .map(|e| merge_egraph(&mut merged, &e.egraph)[&e.root])
+            .collect::<Vec<_>>();
+        merged.rebuild();
+
+        // The `and` is shared, so the merged e-graph is smaller than the two
+        // separate ones.
+        assert!(
+            merged.number_of_classes()
+                < explored
+                    .iter()
+                    .map(|e| e.egraph.number_of_classes())
+                    .sum::<usize>()
+        );
+        for (program, root) in programs.iter().zip(roots) {
+            assert_eq!(merged.lookup_expr(&program.expr), Some(merged.find(root)));
+        }
+        let instrs = find_isa_instructions(&merged).unwrap();
+        let mut names = instrs
+            .iter()
+            .map(|(_, i)| i.to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), instrs.len());
+    }
+}
@@ -0,0 +1,256 @@
+//! Pipelining: cutting deep combinational cones with registers.
+//!
+//! [`pipeline`] inserts registers into a combinational program so that it
+//! takes a given number of cycles, spreading them evenly over the depth of
+//! the program. Every path from an input to the output goes through the same
+//! number of registers, so the pipelined program computes what the original
+//! did that many cycles later, which [`verify_pipelined`] proves. How the
+//! registers end up placed is up to [`retiming_rules`], which move them
+//! across operators so that extraction, e.g. with
+//! [`crate::arch::cost::extract_mapped`], can put them where the
+//! architecture has them, such as after a DSP's multiplier.
+
+use egg::{rewrite, Pattern, Rewrite};
+
+use crate::{
+    analysis::LanguageAnalysis,
+    ast::Expr,
+    error::LakeroadError,
+    language::{Language, Op},
+};
+
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+use crate::smt::{symbolic_cycles, SmtSolver, Term};
+
+/// How many operators the longest path from a leaf to the root of `expr`
+/// goes through, counting an `apply` as one.
+pub fn depth(expr: &Expr) -> usize {
+    match expr {
+        Expr::Var { .. } | Expr::Const { .. } => 0,
+        Expr::UnOp { arg, .. } => 1 + depth(arg),
+        Expr::BinOp { lhs, rhs, .. } => 1 + depth(lhs).max(depth(rhs)),
+        // Wiring, which takes no logic.
+        Expr::Reg { arg, .. } | Expr::Extract { arg, .. } => depth(arg),
+        Expr::Cat { hi, lo } => depth(hi).max(depth(lo)),
+        Expr::Apply { args, .. } => 1 + args.iter().map(depth).max().unwrap_or(0),
+    }
+}
+
+/// `expr` delayed by `cycles` registers.
+pub(crate) fn delayed(expr: Expr, cycles: usize) -> Expr {
+    (0..cycles).fold(expr, |expr, _| Expr::Reg {
+        width: expr.width(),
+        arg: Box::new(expr),
+    })
+}
+
+/// `expr`, which must be combinational, with registers inserted so that it
+/// takes `stages` cycles: its output is registered, and the operators are
+/// split into `stages` runs of about the same depth, with registers
+/// between them. Inputs are delayed to the run they're read in, and
+/// constants aren't registered.
+pub fn pipeline(expr: &Expr, stages: usize) -> Result<Expr, LakeroadError> {
+    if expr.reg_depth() > 0 {
+        return Err(LakeroadError::Unsupported(
+            "pipelining a program which already has registers".to_string(),
+        ));
+    }
+    if stages == 0 {
+        return Ok(expr.clone());
+    }
+    let total = depth(expr);
+
+    /// The pipelined `expr`, and the run it's computed in, from 1 to
+    /// `stages`, or `None` for constants, which are in every run.
+    fn go(expr: &Expr, stages: usize, total: usize) -> (Expr, Option<usize>) {
+        // A node `depth` operators deep is in the run that depth falls in.
+        let run = |depth: usize| ((depth * stages + total - 1) / total).max(1);
+        // An operand computed in `from`, registered into `to`.
+        let operand = |(expr, from): (Expr, Option<usize>), to: usize| match from {
+            Some(from) => delayed(expr, to - from),
+            None => expr,
+        };
+        match expr {
+            Expr::Var { .. } => (expr.clone(), Some(1)),
+            Expr::Const { .. } => (expr.clone(), None),
+            Expr::UnOp { op, width, arg } => {
+                let to = run(depth(expr));
+                let arg = operand(go(arg, stages, total), to);
+                (
+                    Expr::UnOp {
+                        op: op.clone(),
+                        width: *width,
+                        arg: Box::new(arg),
+                    },
+                    Some(to),
+                )
+            }
+            Expr::BinOp {
+                op,
+                width,
+                lhs,
+                rhs,
+            } => {
+                let to = run(depth(expr));
+                let lhs = operand(go(lhs, stages, total), to);
+                let rhs = operand(go(rhs, stages, total), to);
+                (
+                    Expr::BinOp {
+                        op: op.clone(),
+                        width: *width,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    },
+                    Some(to),
+                )
+            }
+            Expr::Apply { instr, args } => {
+                let to = run(depth(expr));
+                let args = args
+                    .iter()
+                    .map(|arg| operand(go(arg, stages, total), to))
+                    .collect();
+                (
+                    Expr::Apply {
+                        instr: instr.clone(),
+                        args,
+                    },
+                    Some(to),
+                )
+            }
+            // Wiring stays in the run its operands are computed in.
+            Expr::Extract { hi, lo, arg } => {
+                let (arg, from) = go(arg, stages, total);
+                (
+                    Expr::Extract {
+                        hi: *hi,
+                        lo: *lo,
+                        arg: Box::new(arg),
+                    },
+                    from,
+                )
+            }
+            Expr::Cat { hi, lo } => {
+                let (hi, lo) = (go(hi, stages, total), go(lo, stages, total));
+                let to = hi.1.max(lo.1);
+                let align = |side: (Expr, Option<usize>)| match to {
+                    Some(to) => operand(side, to),
+                    None => side.0,
+                };
+                (
+                    Expr::Cat {
+                        hi: Box::new(align(hi)),
+                        lo: Box::new(align(lo)),
+                    },
+                    to,
+                )
+            }
+            Expr::Reg { .. } => unreachable!("the program is combinational"),
+        }
+    }
+
+    if total == 0 {
+        return Ok(delayed(expr.clone(), stages));
+    }
+    let (out, run) = go(expr, stages, total);
+    // The output's register ends the last run.
+    Ok(delayed(out, stages + 1 - run.unwrap_or(stages)))
+}
+
+/// Rewrites which move a register from both operands of an operator to its
+/// result, and back. Registers only move across operators whose result is
+/// zero when their operands are, and never past a constant operand, so the
+/// first cycle, when registers are zero, is unchanged.
+pub fn retiming_rules() -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    let mut rules = vec![];
+    for op in [
+        Op::And,
+        Op::Or,
+        Op::Xor,
+        Op::Add,
+        Op::Sub,
+        Op::Mul,
+        Op::Lsr,
+        Op::Asr,
+    ] {
+        let before: Pattern<Language> = format!("(binop {} ?bw (reg ?bw ?a) (reg ?bw ?b))", op)
+            .parse()
+            .unwrap();
+        let after: Pattern<Language> = format!("(reg ?bw (binop {} ?bw ?a ?b))", op)
+            .parse()
+            .unwrap();
+        rules.push(rewrite!(format!("retime-{}", op); { before.clone() } => { after.clone() }));
+        rules.push(rewrite!(format!("retime-{}-rev", op); { after } => { before }));
+    }
+    rules.extend(rewrite!("retime-neg";
+                            "(unop neg ?bw (reg ?bw ?a))" <=> "(reg ?bw (unop neg ?bw ?a))"));
+    rules
+}
+
+/// Whether `pipelined` computes what `original` does, `latency` cycles
+/// later, in every cycle, as `solver` proves. Both are unrolled until
+/// neither output depends on the registers' starting values any more.
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+pub fn verify_pipelined(
+    solver: &mut SmtSolver,
+    original: &Expr,
+    pipelined: &Expr,
+    latency: usize,
+) -> Result<bool, LakeroadError> {
+    let cycles = original
+        .reg_depth()
+        .max(pipelined.reg_depth().saturating_sub(latency))
+        + 1;
+    let original = symbolic_cycles(original, cycles)?;
+    let pipelined = symbolic_cycles(pipelined, cycles + latency)?.split_off(latency);
+    let concat = |mut terms: Vec<Term>| match terms.len() {
+        1 => terms.remove(0),
+        _ => Term::app("concat", terms),
+    };
+    solver.equivalent(&concat(original), &concat(pipelined))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::{EGraph, RecExpr, Runner};
+
+    use super::*;
+    use crate::{
+        equiv::{shared_vars, test_vectors},
+        eval::eval_cycles,
+    };
+
+    #[test]
+    fn pipelined_programs_match_later() {
+        let program = Expr::try_from(
+            &RecExpr::from_str(
+                "(binop add 8 (binop mul 8 (binop sub 8 (var a 8) (var b 8)) (var c 8)) \
+                 (binop xor 8 (var d 8) (const 5 8)))",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let vectors = test_vectors(&shared_vars(&program, &program).unwrap(), 16, 0);
+        for stages in 1..=4 {
+            let pipelined = pipeline(&program, stages).unwrap();
+            assert_eq!(pipelined.reg_depth(), stages);
+            let original = eval_cycles(&program, &vectors).unwrap();
+            let later = eval_cycles(&pipelined, &vectors).unwrap();
+            assert_eq!(later[stages..], original[..vectors.len() - stages]);
+        }
+
+        // Retiming moves the registers on both operands to the result.
+        let retimed = RecExpr::from_str("(reg 8 (binop mul 8 (var a 8) (var b 8)))").unwrap();
+        let mut egraph = EGraph::default();
+        let root = egraph.add_expr(
+            &RecExpr::from_str("(binop mul 8 (reg 8 (var a 8)) (reg 8 (var b 8)))").unwrap(),
+        );
+        let runner = Runner::default().with_egraph(egraph).run(&retiming_rules());
+        assert_eq!(
+            runner.egraph.lookup_expr(&retimed),
+            Some(runner.egraph.find(root))
+        );
+    }
+}
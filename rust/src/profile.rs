@@ -0,0 +1,130 @@
+//! Wall-clock and CPU time per phase of a run, recorded in the
+//! [`RunReport`](crate::synthesizer::RunReport) as a [`Profile`].
+//!
+//! CPU time is the whole process's, so a phase which runs on several
+//! threads (e.g. parallel rewriting) can take more CPU time than wall-clock
+//! time, and anything else the process does at the same time is counted
+//! too. WebAssembly builds have no clocks, so there every phase takes zero
+//! seconds and CPU time isn't measured.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhaseTime {
+    pub wall_seconds: f64,
+    /// `None` where the process's CPU time can't be read.
+    pub cpu_seconds: Option<f64>,
+}
+
+impl PhaseTime {
+    /// The time from `earlier` to `self`, both measured by the same
+    /// [`Timer`].
+    fn since(self, earlier: PhaseTime) -> PhaseTime {
+        PhaseTime {
+            wall_seconds: self.wall_seconds - earlier.wall_seconds,
+            cpu_seconds: self
+                .cpu_seconds
+                .zip(earlier.cpu_seconds)
+                .map(|(now, then)| now - then),
+        }
+    }
+}
+
+/// How long each phase of a run took. Phases which didn't run take zero
+/// seconds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// Adding the programs to the e-graph, or reading it back from a
+    /// checkpoint. With parallel exploration, each program is added to its
+    /// own e-graph as part of rewriting, and this is the longest any took.
+    pub ingestion: PhaseTime,
+    /// Rewriting, including merging the programs' e-graphs with parallel
+    /// exploration.
+    pub rewriting: PhaseTime,
+    /// Each rewrite iteration's share of `rewriting`. With parallel
+    /// exploration, where the programs' iterations overlap, the longest of
+    /// the programs' same iteration.
+    pub iterations: Vec<PhaseTime>,
+    pub extraction: PhaseTime,
+    pub verification: PhaseTime,
+    pub selection: PhaseTime,
+    /// Writing the ISA out, which happens after the run, e.g. in
+    /// [`Config::write_outputs`](crate::config::Config::write_outputs).
+    pub export: PhaseTime,
+}
+
+/// Measures time from when it was started.
+#[derive(Debug, Clone, Copy)]
+pub struct Timer {
+    #[cfg(not(target_arch = "wasm32"))]
+    wall: std::time::Instant,
+    #[cfg(not(target_arch = "wasm32"))]
+    cpu: Option<cpu_time::ProcessTime>,
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        Timer {
+            #[cfg(not(target_arch = "wasm32"))]
+            wall: std::time::Instant::now(),
+            #[cfg(not(target_arch = "wasm32"))]
+            cpu: cpu_time::ProcessTime::try_now().ok(),
+        }
+    }
+
+    pub fn elapsed(&self) -> PhaseTime {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            PhaseTime {
+                wall_seconds: self.wall.elapsed().as_secs_f64(),
+                cpu_seconds: self
+                    .cpu
+                    .and_then(|cpu| cpu.try_elapsed().ok())
+                    .map(|cpu| cpu.as_secs_f64()),
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        PhaseTime::default()
+    }
+}
+
+/// The longest of `times`, by wall-clock time, e.g. of a phase which ran
+/// on several threads at once. Zero if there are none.
+pub fn longest(times: impl IntoIterator<Item = PhaseTime>) -> PhaseTime {
+    times
+        .into_iter()
+        .max_by(|a, b| a.wall_seconds.total_cmp(&b.wall_seconds))
+        .unwrap_or_default()
+}
+
+/// Splits the time up to each of `marks`, all measured by one [`Timer`],
+/// into the time between successive marks.
+pub(crate) fn intervals(marks: &[PhaseTime]) -> Vec<PhaseTime> {
+    marks
+        .windows(2)
+        .map(|pair| pair[1].since(pair[0]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_between_marks() {
+        let mark = |wall_seconds, cpu_seconds| PhaseTime {
+            wall_seconds,
+            cpu_seconds,
+        };
+        assert_eq!(
+            intervals(&[mark(0.0, Some(0.0)), mark(1.0, Some(0.5)), mark(3.0, None)]),
+            vec![mark(1.0, Some(0.5)), mark(2.0, None)]
+        );
+        assert_eq!(
+            longest([mark(1.0, Some(3.0)), mark(2.0, Some(0.5))]),
+            mark(2.0, Some(0.5))
+        );
+        assert_eq!(longest([]), PhaseTime::default());
+    }
+}
@@ -0,0 +1,119 @@
+//! Sets of named, weighted input programs.
+
+use egg::{EGraph, Id, RecExpr};
+
+use crate::{analysis::LanguageAnalysis, error::LakeroadError, language::Language};
+
+/// An input program.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub name: String,
+    pub expr: RecExpr<Language>,
+    /// How much this program matters relative to the others, e.g. a
+    /// profile-derived execution count. Costs and coverage benefits of the
+    /// program are scaled by this weight during ISA selection.
+    pub weight: f64,
+}
+
+/// The programs we're designing an ISA for.
+#[derive(Debug, Clone, Default)]
+pub struct ProgramSet {
+    programs: Vec<Program>,
+}
+
+impl ProgramSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a program with weight 1.
+    pub fn add(&mut self, name: impl Into<String>, expr: RecExpr<Language>) -> &mut Self {
+        self.programs.push(Program {
+            name: name.into(),
+            expr,
+            weight: 1.0,
+        });
+        self
+    }
+
+    /// Adds a program with the given weight, which must be finite and
+    /// nonnegative.
+    pub fn add_weighted(
+        &mut self,
+        name: impl Into<String>,
+        expr: RecExpr<Language>,
+        weight: f64,
+    ) -> Result<&mut Self, LakeroadError> {
+        let name = name.into();
+        if !weight.is_finite() || weight < 0.0 {
+            return Err(LakeroadError::Config(format!(
+                "program {} has weight {}, which isn't finite and nonnegative",
+                name, weight
+            )));
+        }
+        self.programs.push(Program { name, expr, weight });
+        Ok(self)
+    }
+
+    /// Adds every program in `other`, keeping their weights.
+    pub fn append(&mut self, other: &ProgramSet) -> &mut Self {
+        self.programs.extend(other.programs.iter().cloned());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Program> {
+        self.programs.iter().find(|p| p.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Program> {
+        self.programs.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    /// Adds every program to the egraph, returning each program's root paired
+    /// with its weight, in the order the programs were added.
+    pub fn add_to_egraph(&self, egraph: &mut EGraph<Language, LanguageAnalysis>) -> Vec<(Id, f64)> {
+        self.programs
+            .iter()
+            .map(|p| (egraph.add_expr(&p.expr), p.weight))
+            .collect()
+    }
+}
+
+impl FromIterator<(String, RecExpr<Language>)> for ProgramSet {
+    fn from_iter<T: IntoIterator<Item = (String, RecExpr<Language>)>>(iter: T) -> Self {
+        let mut out = ProgramSet::new();
+        for (name, expr) in iter {
+            out.add(name, expr);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn weights_are_finite_and_nonnegative() {
+        let expr = RecExpr::from_str("(var x 8)").unwrap();
+        let mut programs = ProgramSet::new();
+        programs.add_weighted("zero", expr.clone(), 0.0).unwrap();
+        for weight in [-1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                programs.add_weighted("bad", expr.clone(), weight),
+                Err(LakeroadError::Config(_))
+            ));
+        }
+        assert_eq!(programs.len(), 1);
+    }
+}
@@ -0,0 +1,61 @@
+//! Progress reporting for long-running synthesis.
+//!
+//! A [`ProgressObserver`] is notified as the [`Synthesizer`] moves between
+//! phases, after each rewrite iteration, for each solver verdict, and as the
+//! best ISA among the candidates accepted so far improves. Any
+//! `Fn(&ProgressEvent)` closure is an observer.
+//!
+//! [`Synthesizer`]: crate::synthesizer::Synthesizer
+
+use egg::RecExpr;
+
+use crate::language::Language;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Running the rewrites over the programs' e-graph.
+    Rewriting,
+    /// Extracting candidate instructions from the e-graph.
+    Extraction,
+    /// Checking each candidate with the backend.
+    Verification,
+    /// Choosing an ISA from the verified candidates.
+    Selection,
+}
+
+#[derive(Debug, Clone)]
+pub enum ProgressEvent<'a> {
+    /// A new phase started.
+    Phase(Phase),
+    /// Rewriting finished `iteration` iterations, leaving an e-graph of the
+    /// given size.
+    Iteration {
+        iteration: usize,
+        egraph_nodes: usize,
+        egraph_classes: usize,
+    },
+    /// The backend decided on the `checked`th of `total` candidates.
+    Verdict {
+        instr: &'a RecExpr<Language>,
+        accepted: bool,
+        checked: usize,
+        total: usize,
+    },
+    /// The best ISA among the candidates accepted so far, and its score.
+    /// Reported at most once a second during verification, when it
+    /// improves, and once more when it's selected.
+    Isa {
+        instrs: &'a [RecExpr<Language>],
+        score: f64,
+    },
+}
+
+pub trait ProgressObserver {
+    fn notify(&self, event: &ProgressEvent);
+}
+
+impl<F: Fn(&ProgressEvent)> ProgressObserver for F {
+    fn notify(&self, event: &ProgressEvent) {
+        self(event)
+    }
+}
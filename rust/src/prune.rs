@@ -0,0 +1,306 @@
+//! Pruning of instruction candidates which violate user constraints.
+//!
+//! Filtering candidates only when reading the ISA out of the egraph means the
+//! egraph still pays for every junk candidate, and for everything built out
+//! of them. Instead, [`pruning_hook`] marks violating `instr` eclasses in
+//! [`LanguageAnalysis::pruned`] between rewrite iterations. Marked
+//! instructions are skipped by [`find_isa_instructions`], and the hole
+//! introduction rewrites refuse to grow them. All the constraints are
+//! monotone (growing an instruction never fixes a violation), so nothing
+//! allowed is lost.
+//!
+//! [`OperandPorts`] describe the register file an instruction's encoding
+//! reads, which limits its operands more finely than an arity: e.g. a
+//! two-operand form like `add rd, rs`, whose destination is its first
+//! source, with an optional immediate. Instructions are pruned by the most
+//! operands the ports could supply, and, with the ports set in
+//! [`LanguageAnalysis::ports`], the hole introduction rewrites check each
+//! `apply` they build, whose constant arguments are the only ones which can
+//! be immediates (see [`crate::rewrites::ports_admit`]).
+//!
+//! [`find_isa_instructions`]: crate::extract::find_isa_instructions
+
+use std::collections::HashSet;
+
+use egg::{EGraph, Id, Runner};
+use serde::Deserialize;
+
+use crate::{
+    analysis::{LanguageAnalysis, LanguageAnalysisData},
+    language::{Language, Op},
+};
+
+/// Constraints on instruction candidates. `None` means unconstrained.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CandidateConstraints {
+    /// Maximum depth of the instruction's AST.
+    pub max_depth: Option<usize>,
+    /// Maximum number of distinct arguments.
+    pub max_arity: Option<usize>,
+    /// Ops which may not appear in the instruction's AST.
+    pub banned_ops: Vec<Op>,
+    /// The register file the instruction's operands come from.
+    pub ports: Option<OperandPorts>,
+}
+
+/// The operands an instruction's encoding has room for: one destination
+/// and some source registers, and maybe an immediate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OperandPorts {
+    /// Register specifiers, the destination's included, e.g. three for
+    /// `add rd, rs1, rs2`.
+    pub registers: usize,
+    /// Whether the destination is also the first source, as in `add rd,
+    /// rs`, rather than a specifier of its own.
+    #[serde(default)]
+    pub destructive: bool,
+    /// How many bits an immediate holds, if the encoding has one, which
+    /// supplies a constant operand besides the registers.
+    pub immediate: Option<usize>,
+}
+
+impl OperandPorts {
+    /// How many registers an instruction reads.
+    pub fn sources(&self) -> usize {
+        match self.destructive {
+            true => self.registers,
+            false => self.registers.saturating_sub(1),
+        }
+    }
+
+    /// Whether an instruction of `width` bits can read `registers` operands
+    /// which need a register, and `constants` which could be immediates.
+    pub fn admits(&self, width: usize, registers: usize, constants: usize) -> bool {
+        let immediate = constants > 0 && self.immediate.map_or(false, |bits| width <= bits);
+        registers <= self.sources() && registers + constants <= self.sources() + immediate as usize
+    }
+}
+
+impl CandidateConstraints {
+    /// Whether some representation of the instruction eclass `instr`
+    /// satisfies the constraints.
+    pub fn allows(&self, egraph: &EGraph<Language, LanguageAnalysis>, instr: Id) -> bool {
+        egraph[instr].nodes.iter().any(|node| match node {
+            &Language::Instr([ast, canonical_args]) => {
+                let depth_ok = match (self.max_depth, &egraph[ast].data) {
+                    (Some(max), LanguageAnalysisData::Signal { depth, .. }) => *depth <= max,
+                    _ => true,
+                };
+                let arity_ok = match self.max_arity {
+                    Some(max) => arity(egraph, canonical_args).map_or(true, |a| a <= max),
+                    None => true,
+                };
+                // Any of the arguments might be a constant.
+                let ports_ok = match (self.ports, &egraph[ast].data) {
+                    (Some(ports), LanguageAnalysisData::Signal { width, .. }) => {
+                        arity(egraph, canonical_args).map_or(true, |a| ports.admits(*width, 0, a))
+                    }
+                    _ => true,
+                };
+                depth_ok
+                    && arity_ok
+                    && ports_ok
+                    && (self.banned_ops.is_empty()
+                        || avoids_ops(egraph, ast, &self.banned_ops, &mut HashSet::default()))
+            }
+            _ => true,
+        })
+    }
+}
+
+/// The number of distinct arguments of an instruction, if known yet.
+fn arity(egraph: &EGraph<Language, LanguageAnalysis>, canonical_args: Id) -> Option<usize> {
+    egraph[canonical_args]
+        .nodes
+        .iter()
+        .filter_map(|node| match node {
+            Language::CanonicalArgs(ids) => Some(ids.iter().collect::<HashSet<_>>().len()),
+            _ => None,
+        })
+        .min()
+}
+
+/// Whether some representation of the AST eclass `id` avoids all of `banned`.
+/// Eclasses on the current path are treated as not avoiding, to cut cycles.
+fn avoids_ops(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    id: Id,
+    banned: &[Op],
+    visiting: &mut HashSet<Id>,
+) -> bool {
+    let id = egraph.find(id);
+    if !visiting.insert(id) {
+        return false;
+    }
+    let out = egraph[id].nodes.iter().any(|node| {
+        let (op_id, children) = match node {
+            &Language::UnOpAst([op, _, arg]) => (Some(op), vec![arg]),
+            &Language::BinOpAst([op, _, a, b]) => (Some(op), vec![a, b]),
+            _ => (None, vec![]),
+        };
+        let op_ok = match op_id.map(|op| &egraph[op].data) {
+            Some(LanguageAnalysisData::Op(op)) => !banned.contains(op),
+            _ => true,
+        };
+        op_ok
+            && children
+                .iter()
+                .all(|child| avoids_ops(egraph, *child, banned, visiting))
+    });
+    visiting.remove(&id);
+    out
+}
+
+/// Marks every instruction eclass violating `constraints` as pruned,
+/// returning how many were newly marked. Rebuilds the egraph first, so that
+/// the marks are left canonical.
+pub fn prune(
+    egraph: &mut EGraph<Language, LanguageAnalysis>,
+    constraints: &CandidateConstraints,
+) -> usize {
+    egraph.rebuild();
+    let violating = egraph
+        .classes()
+        .filter(|eclass| {
+            eclass
+                .nodes
+                .iter()
+                .any(|node| matches!(node, Language::Instr(_)))
+        })
+        .filter(|eclass| !constraints.allows(egraph, eclass.id))
+        .map(|eclass| eclass.id)
+        .collect::<Vec<_>>();
+
+    // Eclasses marked before may have been merged since.
+    let canonical: HashSet<Id> = egraph
+        .analysis
+        .pruned
+        .iter()
+        .map(|id| egraph.find(*id))
+        .collect();
+    let before = canonical.len();
+    egraph.analysis.pruned = canonical;
+    egraph.analysis.pruned.extend(violating);
+    egraph.analysis.pruned.len() - before
+}
+
+/// A [`Runner`] hook which prunes candidates before every iteration.
+pub fn pruning_hook(
+    constraints: CandidateConstraints,
+) -> impl FnMut(&mut Runner<Language, LanguageAnalysis>) -> Result<(), String> + 'static {
+    move |runner| {
+        prune(&mut runner.egraph, &constraints);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::RecExpr;
+
+    use super::*;
+    use crate::{
+        extract::find_isa_instructions,
+        rewrites::{
+            canonicalize, fuse_op, introduce_hole_op_both, introduce_hole_op_left,
+            introduce_hole_op_right, introduce_hole_var, simplify_concat,
+        },
+    };
+
+    #[test]
+    fn pruned_candidates_are_not_reported() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        egraph.add_expr(
+            &RecExpr::from_str("(binop xor 8 (binop and 8 (var x 8) (var y 8)) (var z 8))")
+                .unwrap(),
+        );
+        let constraints = CandidateConstraints {
+            max_depth: Some(2),
+            max_arity: Some(2),
+            banned_ops: vec![Op::Xor],
+            ports: None,
+        };
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(10)
+            .with_hook(pruning_hook(constraints.clone()))
+            .run(&vec![
+                introduce_hole_var(),
+                fuse_op(),
+                introduce_hole_op_both(),
+                introduce_hole_op_left(),
+                introduce_hole_op_right(),
+                simplify_concat(),
+                canonicalize(),
+            ]);
+        let mut egraph = runner.egraph;
+        // Catch anything added in the last iteration.
+        prune(&mut egraph, &constraints);
+
+        let isa = find_isa_instructions(&egraph).unwrap();
+        assert!(!isa.is_empty());
+        for (id, instr) in isa {
+            assert!(constraints.allows(&egraph, id), "{}", instr);
+            assert!(!instr.to_string().contains("xor"));
+        }
+    }
+
+    #[test]
+    fn ports_limit_registers_per_site() {
+        // Two registers, one of them the destination, and an 8-bit
+        // immediate.
+        let ports = OperandPorts {
+            registers: 2,
+            destructive: true,
+            immediate: Some(8),
+        };
+        let mut egraph = EGraph::new(LanguageAnalysis {
+            ports: Some(ports),
+            ..Default::default()
+        });
+        egraph.add_expr(
+            &RecExpr::from_str(
+                "(binop add 8 (binop xor 8 (var x 8) (var y 8)) (binop and 8 (var z 8) (const 3 8)))",
+            )
+            .unwrap(),
+        );
+        let constraints = CandidateConstraints {
+            ports: Some(ports),
+            ..Default::default()
+        };
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(10)
+            .with_hook(pruning_hook(constraints.clone()))
+            .run(&vec![
+                introduce_hole_var(),
+                fuse_op(),
+                introduce_hole_op_both(),
+                introduce_hole_op_left(),
+                introduce_hole_op_right(),
+                simplify_concat(),
+                canonicalize(),
+            ]);
+        let mut egraph = runner.egraph;
+        prune(&mut egraph, &constraints);
+
+        let isa = find_isa_instructions(&egraph)
+            .unwrap()
+            .into_iter()
+            .map(|(_, instr)| instr.to_string())
+            .collect::<Vec<_>>();
+        let holes = |instr: &String| instr.matches("(hole 8)").count();
+        assert!(isa.iter().all(|instr| holes(instr) <= 3));
+        // `xor` and `z` take both registers, and the constant is immediate.
+        assert!(isa
+            .iter()
+            .any(|instr| holes(instr) == 3 && instr.contains("(binop-ast and 8")));
+        // `x`, `y`, and the `and` take three.
+        assert!(!isa
+            .iter()
+            .any(|instr| holes(instr) == 3 && instr.contains("(binop-ast xor 8")));
+    }
+}
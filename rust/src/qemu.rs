@@ -0,0 +1,194 @@
+//! QEMU stubs for simulating software which uses the synthesized
+//! instructions.
+//!
+//! [`qemu_stubs`] writes what a QEMU RISC-V target needs to run programs
+//! with the instructions: a TCG helper per instruction, which calls the
+//! instruction's reference implementation from the C header (see
+//! [`crate::emit::to_c`]), its declaration, a `decodetree` fragment, and the
+//! translation function the decoder calls. Each instruction is decoded as an
+//! R-type instruction in the custom-0 opcode space, with `funct3` zero and
+//! its index as `funct7`, reading its operands from `rs1` and `rs2` and
+//! writing its result, zero-extended, to `rd`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use egg::RecExpr;
+
+use crate::{
+    ast::Expr,
+    emit::{export_instructions, ExportFormat},
+    error::LakeroadError,
+    language::{instr_as_expr, Language},
+};
+
+/// The custom-0 major opcode.
+const CUSTOM_0: &str = "0001011";
+
+/// The files [`qemu_stubs`] writes, to be added to QEMU's
+/// `target/riscv`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QemuStubs {
+    /// The reference implementations, as [`ExportFormat::C`] writes them.
+    pub header: String,
+    /// `DEF_HELPER` declarations, for `helper.h`.
+    pub helper_h: String,
+    /// The helpers' definitions.
+    pub helper_c: String,
+    /// Patterns for `insn32.decode`, which use its `@r` and `@r2` formats.
+    pub decode: String,
+    /// A `trans_` function per pattern, for `translate.c`.
+    pub trans: String,
+}
+
+impl QemuStubs {
+    /// Writes the stubs to `directory`, and returns the files written.
+    pub fn write(&self, directory: &Path) -> Result<Vec<PathBuf>, LakeroadError> {
+        fs::create_dir_all(directory)?;
+        let mut written = vec![];
+        for (name, contents) in [
+            ("isa.h", &self.header),
+            ("helper_isa.h", &self.helper_h),
+            ("isa_helper.c", &self.helper_c),
+            ("insn32_isa.decode", &self.decode),
+            ("trans_isa.c.inc", &self.trans),
+        ] {
+            let path = directory.join(name);
+            fs::write(&path, contents)?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+/// QEMU stubs for `instrs`, named `isa_instr0`, `isa_instr1`, and so on, as
+/// in the C header. Each instruction must take one or two operands, and be
+/// one the header can implement.
+pub fn qemu_stubs(instrs: &[RecExpr<Language>]) -> Result<QemuStubs, LakeroadError> {
+    if instrs.len() > 128 {
+        return Err(LakeroadError::Unsupported(format!(
+            "{} instructions, more than funct7 can tell apart",
+            instrs.len()
+        )));
+    }
+    let mut stubs = QemuStubs {
+        header: export_instructions(ExportFormat::C, instrs)?,
+        helper_h: String::new(),
+        helper_c: "#include \"qemu/osdep.h\"\n#include \"cpu.h\"\n\
+                   #include \"exec/helper-proto.h\"\n\n#include \"isa.h\"\n"
+            .to_string(),
+        decode: String::new(),
+        trans: String::new(),
+    };
+    for (i, instr) in instrs.iter().enumerate() {
+        let name = format!("isa_instr{}", i);
+        let expr = instr_as_expr(instr)
+            .ok_or_else(|| LakeroadError::Malformed(format!("not an instr: {}", instr)))?;
+        // In name order, as the header's functions take them.
+        let mut operands = Expr::try_from(&expr)?
+            .vars()
+            .into_iter()
+            .map(|(operand, _)| operand)
+            .collect::<Vec<_>>();
+        operands.sort();
+        // `@r2` has no `rs2`, so its bits are part of the pattern.
+        let (format, rs2, sources) = match operands.len() {
+            1 => ("@r2", "00000", vec!["rs1"]),
+            2 => ("@r", ".....", vec!["rs1", "rs2"]),
+            n => {
+                return Err(LakeroadError::Unsupported(format!(
+                    "{} takes {} operands, and RISC-V registers supply one or two",
+                    name, n
+                )))
+            }
+        };
+
+        stubs.helper_h.push_str(&format!(
+            "DEF_HELPER_FLAGS_{}({}, TCG_CALL_NO_RWG_SE, tl{})\n",
+            sources.len(),
+            name,
+            ", tl".repeat(sources.len())
+        ));
+        let params = operands
+            .iter()
+            .map(|operand| format!("target_ulong {}", operand))
+            .collect::<Vec<_>>();
+        stubs.helper_c.push_str(&format!(
+            "\ntarget_ulong HELPER({})({})\n{{\n    return {}({});\n}}\n",
+            name,
+            params.join(", "),
+            name,
+            operands.join(", ")
+        ));
+
+        stubs.decode.push_str(&format!(
+            "{:<12} {:07b} {} ..... 000 ..... {} {}\n",
+            name, i, rs2, CUSTOM_0, format
+        ));
+        let reads = sources
+            .iter()
+            .enumerate()
+            .map(|(n, source)| {
+                format!(
+                    "    TCGv src{} = get_gpr(ctx, a->{}, EXT_NONE);\n",
+                    n + 1,
+                    source
+                )
+            })
+            .collect::<String>();
+        let args = (1..=sources.len())
+            .map(|n| format!(", src{}", n))
+            .collect::<String>();
+        stubs.trans.push_str(&format!(
+            "static bool trans_{name}(DisasContext *ctx, arg_{name} *a)\n{{\n    \
+             TCGv dest = dest_gpr(ctx, a->rd);\n{reads}    \
+             gen_helper_{name}(dest{args});\n    \
+             gen_set_gpr(ctx, a->rd, dest);\n    return true;\n}}\n\n",
+            name = name,
+            reads = reads,
+            args = args,
+        ));
+    }
+    Ok(stubs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stubs_decode_custom_0() {
+        let instrs = [
+            "(instr (binop-ast and 8 (hole 8) (unop-ast not 8 (hole 8))) (canonical-args 0 1))",
+            "(instr (unop-ast neg 16 (hole 16)) (canonical-args 0))",
+        ]
+        .map(|instr| instr.parse::<RecExpr<Language>>().unwrap());
+        let stubs = qemu_stubs(&instrs).unwrap();
+        assert_eq!(
+            stubs.decode,
+            "isa_instr0   0000000 ..... ..... 000 ..... 0001011 @r\n\
+             isa_instr1   0000001 00000 ..... 000 ..... 0001011 @r2\n"
+        );
+        assert_eq!(
+            stubs.helper_h,
+            "DEF_HELPER_FLAGS_2(isa_instr0, TCG_CALL_NO_RWG_SE, tl, tl, tl)\n\
+             DEF_HELPER_FLAGS_1(isa_instr1, TCG_CALL_NO_RWG_SE, tl, tl)\n"
+        );
+        assert!(stubs
+            .helper_c
+            .contains("target_ulong HELPER(isa_instr0)(target_ulong a0, target_ulong a1)"));
+        assert!(stubs.trans.contains("gen_helper_isa_instr1(dest, src1);"));
+
+        // Three operands don't fit in an R-type instruction.
+        let three = "(instr (binop-ast add 8 (binop-ast add 8 (hole 8) (hole 8)) (hole 8)) \
+                     (canonical-args 0 1 2))"
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            qemu_stubs(&[three]),
+            Err(LakeroadError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+//! Checking candidates by running Racket in a subprocess (see
+//! [`RacketBackend`]).
+//!
+//! This and the SMT solver in [`crate::smt`] are the only parts of the
+//! crate which spawn processes. Each is built with a feature of its own,
+//! `racket` here and `z3` there, and left out of WebAssembly builds, where
+//! candidates are checked by a callback instead (see [`crate::web`]).
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use egg::RecExpr;
+use log::Level;
+
+use crate::{
+    backend::{candidate_expr, SynthesisBackend},
+    bitvec::BitVec,
+    cancel::CancellationToken,
+    equiv::TestVector,
+    error::LakeroadError,
+    language::{instr_id, Language},
+    solver::{to_racket, Symbols},
+};
+
+/// Asks the solver whether `expr`, a Racket expression over the variables in
+/// `map`, can be implemented. Returns `Ok(false)` if the query is
+/// unsatisfiable, and an error if Racket itself fails.
+pub fn call_racket(expr: String, map: &Symbols) -> Result<bool, LakeroadError> {
+    run_racket(
+        "../racket/attempt-to-synthesize.rkt",
+        &synthesis_query(expr, map),
+        &next_query(),
+        &CancellationToken::new(),
+    )
+}
+
+/// The input to `attempt-to-synthesize.rkt` for [`call_racket`].
+fn synthesis_query(expr: String, map: &Symbols) -> String {
+    format!(
+        "
+    (begin
+        {defines}
+        (define (f {args}) {expr})
+        f)",
+        defines = map.define_symbolic(),
+        args = map.names().collect::<Vec<_>>().join(" "),
+        expr = expr,
+    )
+}
+
+/// Numbers the queries which aren't about a candidate, so that their logged
+/// output can be told apart when several run at once.
+static QUERIES: AtomicUsize = AtomicUsize::new(0);
+
+/// The tag for the next query which isn't about a candidate.
+fn next_query() -> String {
+    format!("query {}", QUERIES.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Runs `script`'s `main` with `input` on stdin. Scripts exit with 1,
+/// silently, to answer no; anything else is a crash. If `cancel` is
+/// cancelled while the script runs, or running it fails, it's killed.
+///
+/// The script's output is logged line by line as it's printed, tagged with
+/// `tag`, e.g. the candidate's id: stdout at `info`, stderr at `warn`. The
+/// input is logged under the same tag at `debug`.
+fn run_racket(
+    script: &str,
+    input: &str,
+    tag: &str,
+    cancel: &CancellationToken,
+) -> Result<bool, LakeroadError> {
+    log::debug!("[{}] {}: {}", tag, script, input.trim());
+    let mut cmd = Command::new("racket");
+    cmd.arg("-tm");
+    cmd.arg(script);
+    cmd.stdin(Stdio::piped());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    let mut proc = cmd.spawn()?;
+    let result = converse(&mut proc, input, tag, cancel);
+    if result.is_err() {
+        // Neither leave the script running nor leave a zombie behind.
+        let _ = proc.kill();
+        let _ = proc.wait();
+    }
+    result
+}
+
+/// [`run_racket`], once `proc` is running.
+fn converse(
+    proc: &mut Child,
+    input: &str,
+    tag: &str,
+    cancel: &CancellationToken,
+) -> Result<bool, LakeroadError> {
+    // Read the output as it comes, so that it's logged promptly and the
+    // script never blocks on a full pipe.
+    let stdout = forward(
+        proc.stdout
+            .take()
+            .ok_or_else(|| LakeroadError::Solver("no stdout for racket".to_string()))?,
+        tag.to_string(),
+        Level::Info,
+    );
+    let stderr = forward(
+        proc.stderr
+            .take()
+            .ok_or_else(|| LakeroadError::Solver("no stderr for racket".to_string()))?,
+        tag.to_string(),
+        Level::Warn,
+    );
+    // Dropping stdin closes it, so the script sees the end of its input.
+    proc.stdin
+        .take()
+        .ok_or_else(|| LakeroadError::Solver("no stdin for racket".to_string()))?
+        .write_all(input.as_bytes())?;
+    let status = loop {
+        if let Some(status) = proc.try_wait()? {
+            break status;
+        }
+        if cancel.is_cancelled() {
+            return Err(LakeroadError::Cancelled);
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    stdout.join().expect("reading racket's stdout panicked")?;
+    let stderr = stderr.join().expect("reading racket's stderr panicked")?;
+
+    match status.code() {
+        Some(0) => Ok(true),
+        Some(1) if stderr.is_empty() => Ok(false),
+        _ => Err(LakeroadError::Solver(stderr)),
+    }
+}
+
+/// Logs each line of `stream` at `level` as it arrives, on another thread,
+/// and returns everything read once the stream closes.
+fn forward(
+    stream: impl Read + Send + 'static,
+    tag: String,
+    level: Level,
+) -> JoinHandle<io::Result<String>> {
+    thread::spawn(move || {
+        let mut stream = BufReader::new(stream);
+        let mut out = String::new();
+        let mut line = vec![];
+        while stream.read_until(b'\n', &mut line)? > 0 {
+            let text = String::from_utf8_lossy(&line);
+            log::log!(level, "[{}] {}", tag, text.trim_end());
+            out.push_str(&text);
+            line.clear();
+        }
+        Ok(out)
+    })
+}
+
+/// Checks terms with Rosette, by running Racket in a subprocess. Terms with
+/// no Racket equivalent are infeasible, rather than errors.
+#[derive(Debug, Clone, Default)]
+pub struct RacketBackend {
+    cancel: CancellationToken,
+}
+
+impl RacketBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Kills the running query, failing it with
+    /// [`LakeroadError::Cancelled`], once `cancel` is cancelled.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// [`SynthesisBackend::check_feasible`], with the query's output tagged
+    /// `tag`.
+    fn feasible(&self, expr: &RecExpr<Language>, tag: &str) -> Result<bool, LakeroadError> {
+        match to_racket(expr, (expr.as_ref().len() - 1).into()) {
+            Ok((racket, map)) => run_racket(
+                "../racket/attempt-to-synthesize.rkt",
+                &synthesis_query(racket, &map),
+                tag,
+                &self.cancel,
+            ),
+            Err(LakeroadError::Unsupported(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl SynthesisBackend for RacketBackend {
+    fn check_feasible(&self, expr: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+        self.feasible(expr, &next_query())
+    }
+
+    /// As by default, but with the output tagged with the candidate's id
+    /// (see [`instr_id`]), as it's reported, e.g. `[candidate 00c3…]`.
+    fn check_candidate(&self, instr: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+        match (candidate_expr(instr)?, instr_id(instr)) {
+            (Some(expr), Some(id)) => self.feasible(&expr, &format!("candidate {}", id)),
+            _ => Ok(false),
+        }
+    }
+
+    fn verify_equivalent(
+        &self,
+        a: &RecExpr<Language>,
+        b: &RecExpr<Language>,
+    ) -> Result<bool, LakeroadError> {
+        let (a, mut map) = to_racket(a, (a.as_ref().len() - 1).into())?;
+        let (b, b_map) = to_racket(b, (b.as_ref().len() - 1).into())?;
+        map.extend(&b_map)?;
+        let query = format!(
+            "
+    (begin
+        {defines}
+        (unsat? (verify (assert (bveq {a} {b})))))",
+            defines = map.define_symbolic(),
+            a = a,
+            b = b,
+        );
+        run_racket(
+            "../racket/verify-equivalent.rkt",
+            &query,
+            &next_query(),
+            &self.cancel,
+        )
+    }
+}
+
+/// Whether Rosette evaluates the lowering of `expr` (see [`to_racket`]),
+/// with each variable bound to its value in `inputs`, to `expected`. This is
+/// how the lowering is tested against [`crate::eval`].
+pub fn lowering_evaluates_to(
+    expr: &RecExpr<Language>,
+    inputs: &TestVector,
+    expected: &BitVec,
+) -> Result<bool, LakeroadError> {
+    let (racket, map) = to_racket(expr, (expr.as_ref().len() - 1).into())?;
+    let defines = map
+        .names()
+        .map(|name| match inputs.get(name) {
+            Some(value) => Ok(format!("(define {} {})", name, value)),
+            None => Err(LakeroadError::Malformed(format!(
+                "no value for variable {}",
+                name
+            ))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let query = format!(
+        "
+    (begin
+        {defines}
+        (bveq {racket} {expected}))",
+        defines = defines.join("\n"),
+        racket = racket,
+        expected = expected,
+    );
+    run_racket(
+        "../racket/verify-equivalent.rkt",
+        &query,
+        &next_query(),
+        &CancellationToken::new(),
+    )
+}
+
+/// Asks Racket whether `instr` can be implemented (see [`RacketBackend`]).
+pub fn racket_backend(instr: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+    RacketBackend::new().check_candidate(instr)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{ast::strategies::arb_expr, equiv::test_vectors, eval::eval_expr};
+
+    proptest! {
+        // Every case runs Racket, so there are few of them.
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        /// The Rosette lowering computes what the native evaluator does.
+        #[test]
+        fn lowering_matches_eval(
+            expr in prop::sample::select(vec![1, 3, 8, 32, 64, 100]).prop_flat_map(arb_expr),
+            seed in any::<u64>(),
+        ) {
+            let vars = expr
+                .vars()
+                .into_iter()
+                .map(|(name, width)| (name, width as usize))
+                .collect::<Vec<_>>();
+            // Past the corner cases, which come first.
+            let inputs = test_vectors(&vars, 8, seed).pop().unwrap();
+            let expected = eval_expr(&expr, &inputs).unwrap();
+            prop_assert!(
+                lowering_evaluates_to(&RecExpr::from(&expr), &inputs, &expected).unwrap(),
+                "{} should be {} on {:?}",
+                RecExpr::from(&expr),
+                expected,
+                inputs
+            );
+        }
+    }
+
+    #[test]
+    fn ceil_avg_to_racket_call_racket() {
+        let expr = &RecExpr::from_str(
+            "(binop sub 8 (binop or 8 (var x 8) (var y 8)) (binop asr 8 (binop xor 8 (var x 8) (var y 8)) (const 1 8)))",
+        )
+        .unwrap();
+
+        let (expr, map) = to_racket(expr, (expr.as_ref().len() - 1).into()).unwrap();
+
+        assert!(!call_racket(expr, &map).unwrap());
+    }
+
+    #[test]
+    fn forward_collects_lines() {
+        let out = forward(&b"warning: a\nb"[..], "query 0".to_string(), Level::Warn)
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_eq!(out, "warning: a\nb");
+    }
+}
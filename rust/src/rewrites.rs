@@ -0,0 +1,1368 @@
+//! The rewrites which turn programs into `apply`s of candidate `instr`s, and
+//! the conditions which restrict where they fire.
+
+use std::collections::{HashMap, HashSet};
+
+use egg::{
+    rewrite, Applier, ConditionalApplier, EGraph, Id, Pattern, RecExpr, Rewrite, Subst, Var,
+};
+
+use crate::{
+    analysis::{free_vars, is_pruned, LanguageAnalysis, LanguageAnalysisData::*},
+    ast::Instr,
+    error::LakeroadError,
+    language::Language,
+};
+
+/// Condition which holds when the matched eclass depends on at most
+/// `max_arity` distinct variables.
+pub fn arity_at_most(
+    max_arity: usize,
+) -> impl Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    move |egraph, eclass, _| free_vars(egraph, eclass).len() <= max_arity
+}
+
+/// Condition which holds when none of the instructions `(instr ast
+/// canonical-args)` named by the given pairs of vars have been pruned.
+pub fn not_pruned(
+    instrs: &[(&str, &str)],
+) -> impl Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    let instrs: Vec<(Var, Var)> = instrs
+        .iter()
+        .map(|(ast, canonical_args)| (ast.parse().unwrap(), canonical_args.parse().unwrap()))
+        .collect();
+    move |egraph, _, subst| {
+        if egraph.analysis.pruned.is_empty() {
+            return true;
+        }
+        instrs.iter().all(|(ast, canonical_args)| {
+            match egraph.lookup(Language::Instr([subst[*ast], subst[*canonical_args]])) {
+                Some(id) => !is_pruned(egraph, id),
+                None => true,
+            }
+        })
+    }
+}
+
+/// Condition which holds when the signal bound to `var` is as wide as the
+/// matched eclass, so that a `(hole ?bw)` can stand for it. Only a shift's
+/// amount, and a resized op's operands, can be narrower or wider.
+pub fn full_width(
+    var: &str,
+) -> impl Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    let var: Var = var.parse().unwrap();
+    move |egraph, eclass, subst| match (&egraph[eclass].data, &egraph[subst[var]].data) {
+        (Signal { width, .. }, Signal { width: found, .. }) => width == found,
+        _ => false,
+    }
+}
+
+/// Condition which holds when the `apply` a rewrite builds, whose
+/// arguments are those of the lists bound to `lists` and the signals bound
+/// to `signals`, fits the [`OperandPorts`](crate::prune::OperandPorts) in
+/// [`LanguageAnalysis::ports`], if there are any. Constant arguments may be
+/// immediates, and the rest need registers.
+pub fn ports_admit(
+    lists: &[&str],
+    signals: &[&str],
+) -> impl Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    let lists: Vec<Var> = lists.iter().map(|var| var.parse().unwrap()).collect();
+    let signals: Vec<Var> = signals.iter().map(|var| var.parse().unwrap()).collect();
+    move |egraph, eclass, subst| {
+        let (ports, width) = match (egraph.analysis.ports, &egraph[eclass].data) {
+            (Some(ports), Signal { width, .. }) => (ports, *width),
+            _ => return true,
+        };
+        let mut args = signals.iter().map(|var| subst[*var]).collect::<Vec<_>>();
+        for var in &lists {
+            match &egraph[subst[*var]].data {
+                List(ids) => args.extend(ids.iter()),
+                _ => return true,
+            }
+        }
+        let mut args = args
+            .into_iter()
+            .map(|id| egraph.find(id))
+            .collect::<Vec<_>>();
+        args.sort();
+        args.dedup();
+        let constants = args
+            .iter()
+            .filter(
+                |id| matches!(&egraph[**id].data, Signal { free_vars, .. } if free_vars.is_empty()),
+            )
+            .count();
+        ports.admits(width, args.len() - constants, constants)
+    }
+}
+
+pub fn introduce_hole_var() -> Rewrite<Language, LanguageAnalysis> {
+    rewrite!("introduce-hole-var";
+                "(var ?a ?bw)" =>
+                "(apply (instr (hole ?bw) (canonicalize (list (var ?a ?bw)))) (list (var ?a ?bw)))")
+}
+
+/// The rewrites which build a `binop-ast` on the ASTs of the `apply`s a
+/// `binop` already has, or on holes for operands which aren't `apply`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HoleIntroduction {
+    Fuse,
+    Left,
+    Right,
+    Both,
+}
+
+impl HoleIntroduction {
+    const ALL: [HoleIntroduction; 4] = [
+        HoleIntroduction::Fuse,
+        HoleIntroduction::Left,
+        HoleIntroduction::Right,
+        HoleIntroduction::Both,
+    ];
+
+    /// The rewrite, named with `suffix`, which also only fires where the
+    /// condition `extra` returns holds. `extra` is given the ASTs the new
+    /// `binop-ast` is built on, and how many holes it adds.
+    fn rewrite<C>(
+        self,
+        suffix: &str,
+        extra: impl FnOnce(&[&str], usize) -> C,
+    ) -> Rewrite<Language, LanguageAnalysis>
+    where
+        C: Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool + 'static,
+    {
+        let (name, searcher, applier) = match self {
+            HoleIntroduction::Fuse => (
+                "fuse-op",
+                "(binop ?op ?bw
+                  (apply (instr ?ast0 ?canonical-args0) ?args0)
+                  (apply (instr ?ast1 ?canonical-args1) ?args1))",
+                "(apply
+                  (instr (binop-ast ?op ?bw ?ast0 ?ast1) (canonicalize (concat ?args0 ?args1)))
+                  (concat ?args0 ?args1))",
+            ),
+            HoleIntroduction::Left => (
+                "introduce-hole-op-left",
+                "(binop ?op ?bw
+                  ?left
+                  (apply (instr ?ast1 ?canonical-args1) ?args1))",
+                "(apply
+                  (instr
+                   (binop-ast ?op ?bw (hole ?bw) ?ast1)
+                   (canonicalize (concat (list ?left) ?args1)))
+                  (concat (list ?left) ?args1))",
+            ),
+            HoleIntroduction::Right => (
+                "introduce-hole-op-right",
+                "(binop ?op ?bw
+                  (apply (instr ?ast0 ?canonical-args0) ?args0)
+                  ?right)",
+                "(apply
+                  (instr
+                   (binop-ast ?op ?bw ?ast0 (hole ?bw))
+                   (canonicalize (concat ?args0 (list ?right))))
+                  (concat ?args0 (list ?right)))",
+            ),
+            HoleIntroduction::Both => (
+                "introduce-hole-op-both",
+                "(binop ?op ?bw ?a ?b)",
+                "(apply
+                  (instr
+                   (binop-ast ?op ?bw (hole ?bw) (hole ?bw))
+                   (canonicalize (list ?a ?b)))
+                  (list ?a ?b))",
+            ),
+        };
+        // The instructions built on, as their ASTs and canonical arguments,
+        // and their `apply`s' lists of arguments; the operands holes stand
+        // for; and the operand which must be as wide as the `binop`.
+        let (instrs, lists, signals, hole_for): (&[(&str, &str)], &[&str], &[&str], _) = match self
+        {
+            HoleIntroduction::Fuse => (
+                &[("?ast0", "?canonical-args0"), ("?ast1", "?canonical-args1")],
+                &["?args0", "?args1"],
+                &[],
+                None,
+            ),
+            HoleIntroduction::Left => (
+                &[("?ast1", "?canonical-args1")],
+                &["?args1"],
+                &["?left"],
+                Some("?left"),
+            ),
+            HoleIntroduction::Right => (
+                &[("?ast0", "?canonical-args0")],
+                &["?args0"],
+                &["?right"],
+                Some("?right"),
+            ),
+            HoleIntroduction::Both => (&[], &[], &["?a", "?b"], Some("?b")),
+        };
+        let asts = instrs.iter().map(|(ast, _)| *ast).collect::<Vec<_>>();
+
+        let extra = extra(&asts, signals.len());
+        let full_width = hole_for.map(full_width);
+        let not_pruned = not_pruned(instrs);
+        let ports_admit = ports_admit(lists, signals);
+        let condition =
+            move |egraph: &mut EGraph<Language, LanguageAnalysis>, eclass: Id, subst: &Subst| {
+                extra(egraph, eclass, subst)
+                    && full_width
+                        .as_ref()
+                        .map_or(true, |full_width| full_width(egraph, eclass, subst))
+                    && not_pruned(egraph, eclass, subst)
+                    && ports_admit(egraph, eclass, subst)
+            };
+        Rewrite::new(
+            format!("{}{}", name, suffix),
+            searcher.parse::<Pattern<Language>>().unwrap(),
+            ConditionalApplier {
+                condition,
+                applier: applier.parse::<Pattern<Language>>().unwrap(),
+            },
+        )
+        .unwrap()
+    }
+}
+
+/// The condition of the unrestricted hole-introduction rewrites.
+fn always(_: &[&str], _: usize) -> fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    |_, _, _| true
+}
+
+// This shouldn't be called fusion. Or, more specifically, the next two rewrites
+// are also fusion in different forms. So only labeling this rewrite as fusion
+// is misleading.
+pub fn fuse_op() -> Rewrite<Language, LanguageAnalysis> {
+    HoleIntroduction::Fuse.rewrite("", always)
+}
+
+pub fn introduce_hole_op_left() -> Rewrite<Language, LanguageAnalysis> {
+    HoleIntroduction::Left.rewrite("", always)
+}
+
+pub fn introduce_hole_op_right() -> Rewrite<Language, LanguageAnalysis> {
+    HoleIntroduction::Right.rewrite("", always)
+}
+
+pub fn introduce_hole_op_both() -> Rewrite<Language, LanguageAnalysis> {
+    HoleIntroduction::Both.rewrite("", always)
+}
+
+/// Versions of the `introduce_hole_op_*` rewrites for the resized ops, e.g.
+/// `(binop mul 16 ?a ?b)` of 8-bit `?a` and `?b`, whose holes are as wide as
+/// the operands they stand for rather than the op.
+pub fn introduce_hole_op_resized() -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    /// An operand of the matched op: a signal a hole stands for, or an
+    /// `apply` whose AST and arguments become the new instruction's.
+    enum Operand {
+        Hole(Var),
+        Apply { ast: Var, args: Var },
+    }
+    struct Impl {
+        lhs: Operand,
+        rhs: Operand,
+    }
+    impl Applier<Language, LanguageAnalysis> for Impl {
+        fn apply_one(
+            &self,
+            egraph: &mut EGraph<Language, LanguageAnalysis>,
+            eclass: Id,
+            subst: &egg::Subst,
+            _searcher_ast: Option<&egg::PatternAst<Language>>,
+            _rule_name: egg::Symbol,
+        ) -> Vec<Id> {
+            let [op, bw] = ["?op", "?bw"].map(|var| subst[var.parse::<Var>().unwrap()]);
+            let resizing = egraph[op]
+                .nodes
+                .iter()
+                .any(|node| matches!(node, Language::Op(kind) if kind.is_resizing()));
+            if !resizing {
+                return vec![];
+            }
+            let width = match &egraph[eclass].data {
+                Signal { width, .. } => *width,
+                _ => return vec![],
+            };
+            let mut operand = |operand: &Operand| match operand {
+                Operand::Hole(var) => {
+                    let found = match &egraph[subst[*var]].data {
+                        Signal { width: found, .. } if *found != width => *found,
+                        _ => return None,
+                    };
+                    let found = egraph.add(Language::Num(found as i64));
+                    let hole = egraph.add(Language::Hole([found]));
+                    let args = egraph.add(Language::List(vec![subst[*var]].into_boxed_slice()));
+                    Some((hole, args))
+                }
+                Operand::Apply { ast, args } => Some((subst[*ast], subst[*args])),
+            };
+            let ((ast0, args0), (ast1, args1)) = match (operand(&self.lhs), operand(&self.rhs)) {
+                (Some(lhs), Some(rhs)) => (lhs, rhs),
+                _ => return vec![],
+            };
+            let ast = egraph.add(Language::BinOpAst([op, bw, ast0, ast1]));
+            let args = egraph.add(Language::Concat([args0, args1]));
+            let canonical_args = egraph.add(Language::Canonicalize([args]));
+            let instr = egraph.add(Language::Instr([ast, canonical_args]));
+            let apply = egraph.add(Language::Apply([instr, args]));
+            egraph.union(eclass, apply);
+
+            vec![eclass, apply]
+        }
+    }
+
+    let var = |name: &str| name.parse::<Var>().unwrap();
+    vec![
+        rewrite!("introduce-hole-op-resized-left";
+                    "(binop ?op ?bw
+                      ?left
+                      (apply (instr ?ast1 ?canonical-args1) ?args1))" =>
+                    { Impl {
+                        lhs: Operand::Hole(var("?left")),
+                        rhs: Operand::Apply { ast: var("?ast1"), args: var("?args1") },
+                    } }
+                    if not_pruned(&[("?ast1", "?canonical-args1")])
+                    if ports_admit(&["?args1"], &["?left"])),
+        rewrite!("introduce-hole-op-resized-right";
+                    "(binop ?op ?bw
+                      (apply (instr ?ast0 ?canonical-args0) ?args0)
+                      ?right)" =>
+                    { Impl {
+                        lhs: Operand::Apply { ast: var("?ast0"), args: var("?args0") },
+                        rhs: Operand::Hole(var("?right")),
+                    } }
+                    if not_pruned(&[("?ast0", "?canonical-args0")])
+                    if ports_admit(&["?args0"], &["?right"])),
+        rewrite!("introduce-hole-op-resized-both";
+                    "(binop ?op ?bw ?a ?b)" =>
+                    { Impl {
+                        lhs: Operand::Hole(var("?a")),
+                        rhs: Operand::Hole(var("?b")),
+                    } }
+                    if ports_admit(&[], &["?a", "?b"])),
+    ]
+}
+
+/// Limits on the ASTs of instruction candidates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AstBounds {
+    pub max_size: usize,
+    pub max_depth: usize,
+}
+
+/// Condition which holds when a new `binop-ast` whose children are the ASTs
+/// bound to `asts` plus `holes` fresh holes stays within `bounds`. Uses the
+/// sizes and depths tracked by the analysis, so no extraction is needed.
+pub fn ast_within_bounds(
+    asts: &[&str],
+    holes: usize,
+    bounds: AstBounds,
+) -> impl Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    let asts: Vec<Var> = asts.iter().map(|var| var.parse().unwrap()).collect();
+    move |egraph, _, subst| {
+        let mut size = 1 + holes;
+        let mut depth = if holes > 0 { 2 } else { 1 };
+        for var in &asts {
+            match &egraph[subst[*var]].data {
+                Signal {
+                    size: s, depth: d, ..
+                } => {
+                    size += s;
+                    depth = depth.max(1 + d);
+                }
+                _ => return false,
+            }
+        }
+        size <= bounds.max_size && depth <= bounds.max_depth
+    }
+}
+
+/// Versions of [`fuse_op`] and the `introduce_hole_op_*` rewrites which only
+/// build instruction ASTs within `bounds`.
+pub fn hole_introduction_bounded(bounds: AstBounds) -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    HoleIntroduction::ALL
+        .iter()
+        .map(|rule| {
+            rule.rewrite("-bounded", |asts, holes| {
+                ast_within_bounds(asts, holes, bounds)
+            })
+        })
+        .collect()
+}
+
+/// Versions of [`fuse_op`] and the `introduce_hole_op_*` rewrites which only
+/// fire on expressions depending on at most `max_arity` variables. Using
+/// these in place of the unrestricted rewrites keeps instructions with too
+/// many inputs out of the egraph entirely, rather than filtering them out
+/// after the fact.
+pub fn hole_introduction_max_arity(max_arity: usize) -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    let suffix = format!("-max-arity-{}", max_arity);
+    HoleIntroduction::ALL
+        .iter()
+        .map(|rule| rule.rewrite(&suffix, |_, _| arity_at_most(max_arity)))
+        .collect()
+}
+
+/// Rewrites which let each of `instrs`, e.g. instructions already verified
+/// and selected, be used as a single operator of larger candidates: an
+/// `apply` of one becomes an `apply` of a macro-op whose AST is just an
+/// `apply-ast` of it, with a hole per argument, which the other rewrites
+/// then build on like any other instruction. Fails if one of `instrs` isn't
+/// an `instr`.
+pub fn macro_op_rules(
+    instrs: &[RecExpr<Language>],
+) -> Result<Vec<Rewrite<Language, LanguageAnalysis>>, LakeroadError> {
+    instrs
+        .iter()
+        .enumerate()
+        .map(|(i, instr)| {
+            let holes = Instr::try_from(instr)?
+                .ast
+                .hole_widths()
+                .iter()
+                .map(|width| format!(" (hole {})", width))
+                .collect::<String>();
+            let searcher: Pattern<Language> = format!("(apply {} ?args)", instr)
+                .parse()
+                .map_err(|e| LakeroadError::Parse(format!("{}", e)))?;
+            let applier: Pattern<Language> = format!(
+                "(apply (instr (apply-ast {} (list{})) (canonicalize ?args)) ?args)",
+                instr, holes
+            )
+            .parse()
+            .map_err(|e| LakeroadError::Parse(format!("{}", e)))?;
+            Ok(Rewrite::new(format!("macro-op-{}", i), searcher, applier).unwrap())
+        })
+        .collect()
+}
+
+pub fn unary0() -> Rewrite<Language, LanguageAnalysis> {
+    rewrite!("unary0";
+                "(unop ?op ?bw (apply (instr ?ast ?canonical-args) ?args))" => 
+                "(apply (instr (unop-ast ?op ?bw ?ast) (canonicalize ?args)) ?args)"
+                if not_pruned(&[("?ast", "?canonical-args")]))
+}
+
+pub fn unary1() -> Rewrite<Language, LanguageAnalysis> {
+    rewrite!("unary1";
+                "(unop ?op ?bw (apply (instr ?ast ?canonical-args) ?args))" => 
+                "(apply
+                  (instr (unop-ast ?op ?bw (hole ?bw)) (canonicalize (list (apply (instr ?ast ?canonical-args) ?args))))
+                  (list (apply (instr ?ast ?canonical-args) ?args)))")
+}
+
+/// Condition which holds when the sign bit of the signal bound to `var` is
+/// known to be zero.
+pub fn sign_bit_clear(
+    var: &str,
+) -> impl Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    let var: Var = var.parse().unwrap();
+    move |egraph, _, subst| match &egraph[subst[var]].data {
+        Signal { width, range, .. } => range.sign_bit_clear(*width),
+        _ => false,
+    }
+}
+
+/// Condition which holds when the shift amount bound to `var` is known to be
+/// less than its bitwidth, i.e. the shift doesn't shift everything out.
+pub fn shift_amount_in_range(
+    var: &str,
+) -> impl Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    let var: Var = var.parse().unwrap();
+    move |egraph, _, subst| match &egraph[subst[var]].data {
+        Signal { width, range, .. } => range.hi < *width as u128,
+        _ => false,
+    }
+}
+
+/// Condition which holds when the signal bound to `mask` is known to be one
+/// wherever the signal bound to `var` might be one, i.e. when ANDing with
+/// `mask` has no effect.
+pub fn known_mask_redundant(
+    var: &str,
+    mask: &str,
+) -> impl Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    let var: Var = var.parse().unwrap();
+    let mask: Var = mask.parse().unwrap();
+    move |egraph, _, subst| match (&egraph[subst[var]].data, &egraph[subst[mask]].data) {
+        (Signal { width, known, .. }, Signal { known: mask, .. }) => {
+            known.possible_ones(*width) & !mask.ones == 0
+        }
+        _ => false,
+    }
+}
+
+/// Condition which holds when the signal bound to `var` is known to be zero.
+pub fn known_zero(
+    var: &str,
+) -> impl Fn(&mut EGraph<Language, LanguageAnalysis>, Id, &Subst) -> bool {
+    let var: Var = var.parse().unwrap();
+    move |egraph, _, subst| match &egraph[subst[var]].data {
+        Signal { width, known, .. } => known.as_constant(*width) == Some(0),
+        _ => false,
+    }
+}
+
+/// Removes masks which known bits show to be redundant, as is common in
+/// bithacks.
+pub fn and_redundant_mask() -> Rewrite<Language, LanguageAnalysis> {
+    rewrite!("and-redundant-mask";
+                "(binop and ?bw ?a ?mask)" => "?a"
+                if known_mask_redundant("?a", "?mask"))
+}
+
+/// ORing or XORing with a known zero has no effect.
+pub fn or_xor_known_zero() -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    vec![
+        rewrite!("or-known-zero";
+                    "(binop or ?bw ?a ?b)" => "?a"
+                    if known_zero("?b")),
+        rewrite!("xor-known-zero";
+                    "(binop xor ?bw ?a ?b)" => "?a"
+                    if known_zero("?b")),
+    ]
+}
+
+/// Arithmetic and logical right shifts agree on values whose sign bit is
+/// known to be zero.
+pub fn asr_nonnegative_to_lsr() -> Rewrite<Language, LanguageAnalysis> {
+    rewrite!("asr-nonnegative-to-lsr";
+                "(binop asr ?bw ?a ?b)" => "(binop lsr ?bw ?a ?b)"
+                if sign_bit_clear("?a"))
+}
+
+/// How many holes the AST eclass `id` has, counted in whichever of its
+/// terms is found first, or `None` if it isn't an AST.
+fn num_holes(
+    egraph: &EGraph<Language, LanguageAnalysis>,
+    id: Id,
+    visiting: &mut HashSet<Id>,
+) -> Option<usize> {
+    let id = egraph.find(id);
+    if !visiting.insert(id) {
+        return None;
+    }
+    let out = egraph[id].nodes.iter().find_map(|node| match node {
+        Language::Hole(_) => Some(1),
+        &Language::UnOpAst([_, _, a]) => num_holes(egraph, a, visiting),
+        &Language::BinOpAst([_, _, a, b]) => {
+            Some(num_holes(egraph, a, visiting)? + num_holes(egraph, b, visiting)?)
+        }
+        &Language::ApplyAst([_, args]) => match &egraph[args].data {
+            List(ids) => ids
+                .iter()
+                .map(|arg| num_holes(egraph, *arg, visiting))
+                .sum(),
+            _ => None,
+        },
+        _ => None,
+    });
+    visiting.remove(&id);
+    out
+}
+
+/// The `binop-ast` variants of [`and_redundant_mask`], [`or_xor_known_zero`],
+/// and [`asr_nonnegative_to_lsr`], which simplify the operator at the top of
+/// a candidate's AST by what the analysis knows about its operands' bits.
+/// They rewrite `apply`s, rather than the ASTs themselves, because dropping
+/// an operand drops its holes: the instruction built from the left operand
+/// alone is applied to the arguments which filled the left operand's holes,
+/// the first ones.
+pub fn known_bits_ast_rules() -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    struct KeepLeft;
+    impl Applier<Language, LanguageAnalysis> for KeepLeft {
+        fn apply_one(
+            &self,
+            egraph: &mut EGraph<Language, LanguageAnalysis>,
+            eclass: Id,
+            subst: &egg::Subst,
+            _searcher_ast: Option<&egg::PatternAst<Language>>,
+            _rule_name: egg::Symbol,
+        ) -> Vec<Id> {
+            let [ast, args] = ["?a", "?args"].map(|var| subst[var.parse::<Var>().unwrap()]);
+            let args = match (
+                num_holes(egraph, ast, &mut HashSet::new()),
+                &egraph[args].data,
+            ) {
+                (Some(holes), List(ids)) if holes <= ids.len() => ids[..holes].to_vec(),
+                // Ill-typed; leave it alone.
+                _ => return vec![],
+            };
+            let args = egraph.add(Language::List(args.into_boxed_slice()));
+            let canonical_args = egraph.add(Language::Canonicalize([args]));
+            let instr = egraph.add(Language::Instr([ast, canonical_args]));
+            let apply = egraph.add(Language::Apply([instr, args]));
+            egraph.union(eclass, apply);
+
+            vec![eclass, apply]
+        }
+    }
+
+    vec![
+        rewrite!("and-redundant-mask-ast";
+                    "(apply (instr (binop-ast and ?bw ?a ?mask) ?canonical-args) ?args)" =>
+                    { KeepLeft }
+                    if known_mask_redundant("?a", "?mask")),
+        rewrite!("or-known-zero-ast";
+                    "(apply (instr (binop-ast or ?bw ?a ?b) ?canonical-args) ?args)" =>
+                    { KeepLeft }
+                    if known_zero("?b")),
+        rewrite!("xor-known-zero-ast";
+                    "(apply (instr (binop-ast xor ?bw ?a ?b) ?canonical-args) ?args)" =>
+                    { KeepLeft }
+                    if known_zero("?b")),
+        rewrite!("asr-nonnegative-to-lsr-ast";
+                    "(apply (instr (binop-ast asr ?bw ?a ?b) ?canonical-args) ?args)" =>
+                    "(apply (instr (binop-ast lsr ?bw ?a ?b) ?canonical-args) ?args)"
+                    if sign_bit_clear("?a")),
+    ]
+}
+
+/// Identities which make a program smaller: double negations cancel,
+/// combining a signal with itself is it or zero, and adding, subtracting,
+/// or shifting by a known zero has no effect.
+pub fn identities() -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    vec![
+        rewrite!("not-not"; "(unop not ?bw (unop not ?bw ?a))" => "?a"),
+        rewrite!("neg-neg"; "(unop neg ?bw (unop neg ?bw ?a))" => "?a"),
+        rewrite!("and-self"; "(binop and ?bw ?a ?a)" => "?a"),
+        rewrite!("or-self"; "(binop or ?bw ?a ?a)" => "?a"),
+        rewrite!("xor-self"; "(binop xor ?bw ?a ?a)" => "(const 0 ?bw)"),
+        rewrite!("sub-self"; "(binop sub ?bw ?a ?a)" => "(const 0 ?bw)"),
+        rewrite!("add-known-zero";
+                    "(binop add ?bw ?a ?b)" => "?a"
+                    if known_zero("?b")
+                    if full_width("?a")),
+        rewrite!("sub-known-zero";
+                    "(binop sub ?bw ?a ?b)" => "?a"
+                    if known_zero("?b")
+                    if full_width("?a")),
+        rewrite!("lsr-known-zero";
+                    "(binop lsr ?bw ?a ?b)" => "?a"
+                    if known_zero("?b")),
+        rewrite!("asr-known-zero";
+                    "(binop asr ?bw ?a ?b)" => "?a"
+                    if known_zero("?b")),
+    ]
+}
+
+/// Adds a `const` to each `unop` and `binop` whose every bit the analysis
+/// knows, so that e.g. `(binop add 8 (const 2 8) (const 3 8))` can be
+/// extracted as `(const 5 8)`.
+pub fn fold_constants() -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    struct Impl;
+    impl Applier<Language, LanguageAnalysis> for Impl {
+        fn apply_one(
+            &self,
+            egraph: &mut EGraph<Language, LanguageAnalysis>,
+            eclass: Id,
+            _subst: &egg::Subst,
+            _searcher_ast: Option<&egg::PatternAst<Language>>,
+            _rule_name: egg::Symbol,
+        ) -> Vec<Id> {
+            let (value, width) = match &egraph[eclass].data {
+                Signal { width, known, .. } => match known.as_constant(*width) {
+                    Some(value) => (value, *width),
+                    None => return vec![],
+                },
+                _ => return vec![],
+            };
+            // `const` values are i64s; leave wider constants alone.
+            if value > i64::MAX as u128 && width > 64 {
+                return vec![];
+            }
+            let value_id = egraph.add(Language::Num(value as i64));
+            let width_id = egraph.add(Language::Num(width as i64));
+            let const_id = egraph.add(Language::Const([value_id, width_id]));
+            egraph.union(eclass, const_id);
+
+            vec![eclass, const_id]
+        }
+    }
+
+    vec![
+        rewrite!("fold-unop"; "(unop ?op ?bw ?a)" => { Impl }),
+        rewrite!("fold-binop"; "(binop ?op ?bw ?a ?b)" => { Impl }),
+    ]
+}
+
+/// Zero-extends each shift amount narrower than the value it shifts with a
+/// `cat` of zeros, so that the shift also has a canonical form whose
+/// operands are as wide as it, as the other rewrites expect.
+pub fn zero_extend_shift_amounts() -> Rewrite<Language, LanguageAnalysis> {
+    struct Impl;
+    impl Applier<Language, LanguageAnalysis> for Impl {
+        fn apply_one(
+            &self,
+            egraph: &mut EGraph<Language, LanguageAnalysis>,
+            eclass: Id,
+            subst: &egg::Subst,
+            _searcher_ast: Option<&egg::PatternAst<Language>>,
+            _rule_name: egg::Symbol,
+        ) -> Vec<Id> {
+            let [op, a, b] = ["?op", "?a", "?b"].map(|var| subst[var.parse::<Var>().unwrap()]);
+            let shift = egraph[op]
+                .nodes
+                .iter()
+                .any(|node| matches!(node, Language::Op(kind) if kind.is_shift()));
+            let (width, amount) = match (&egraph[eclass].data, &egraph[b].data) {
+                (Signal { width, .. }, Signal { width: amount, .. }) => (*width, *amount),
+                _ => return vec![],
+            };
+            if !shift || amount >= width {
+                return vec![];
+            }
+            let zero = egraph.add(Language::Num(0));
+            let zeros_width = egraph.add(Language::Num((width - amount) as i64));
+            let zeros = egraph.add(Language::Const([zero, zeros_width]));
+            let extended = egraph.add(Language::Cat([zeros, b]));
+            let width_id = egraph.add(Language::Num(width as i64));
+            let shift_id = egraph.add(Language::BinOp([op, width_id, a, extended]));
+            egraph.union(eclass, shift_id);
+
+            vec![eclass, shift_id]
+        }
+    }
+
+    rewrite!("zero-extend-shift-amount"; "(binop ?op ?bw ?a ?b)" => { Impl })
+}
+
+pub fn canonicalize() -> Rewrite<Language, LanguageAnalysis> {
+    struct Impl(Var);
+    impl Applier<Language, LanguageAnalysis> for Impl {
+        fn apply_one(
+            &self,
+            egraph: &mut EGraph<Language, LanguageAnalysis>,
+            eclass: Id,
+            subst: &egg::Subst,
+            _searcher_ast: Option<&egg::PatternAst<Language>>,
+            _rule_name: egg::Symbol,
+        ) -> Vec<Id> {
+            let ids = match &egraph[subst[self.0]].data {
+                List(v) => v.clone(),
+                // Ill-typed; leave it alone.
+                _ => return vec![],
+            };
+
+            let mut next = 0;
+            let mut map = HashMap::new();
+            for id in ids.iter() {
+                if !map.contains_key(id) {
+                    map.insert(id, next);
+                    next += 1;
+                }
+            }
+
+            let new_list = ids
+                .iter()
+                .cloned()
+                .map(|id| egraph.add(crate::language::Language::Num(*map.get(&id).unwrap())))
+                .collect::<Vec<_>>();
+
+            let canonical_args_id = egraph.add(crate::language::Language::CanonicalArgs(
+                new_list.into_boxed_slice(),
+            ));
+
+            egraph.union(eclass, canonical_args_id);
+
+            vec![eclass, canonical_args_id]
+        }
+    }
+
+    rewrite!("canonicalize";
+                "(canonicalize ?list)" => { Impl("?list".parse().unwrap()) })
+}
+
+pub fn simplify_concat() -> Rewrite<Language, LanguageAnalysis> {
+    struct Impl {
+        list0: Var,
+        list1: Var,
+    }
+    impl Applier<Language, LanguageAnalysis> for Impl {
+        fn apply_one(
+            &self,
+            egraph: &mut EGraph<Language, LanguageAnalysis>,
+            eclass: Id,
+            subst: &egg::Subst,
+            _searcher_ast: Option<&egg::PatternAst<Language>>,
+            _rule_name: egg::Symbol,
+        ) -> Vec<Id> {
+            let (ids0, ids1) = match (
+                &egraph[subst[self.list0]].data,
+                &egraph[subst[self.list1]].data,
+            ) {
+                (List(ids0), List(ids1)) => (ids0.clone(), ids1.clone()),
+                // Ill-typed; leave it alone.
+                _ => return vec![],
+            };
+            let new_list_id = egraph.add(Language::List([ids0, ids1].concat().into_boxed_slice()));
+            egraph.union(eclass, new_list_id);
+
+            vec![eclass, new_list_id]
+        }
+    }
+    let list0: Var = "?list0".parse().unwrap();
+    let list1: Var = "?list1".parse().unwrap();
+    rewrite!("simplify-concat";
+                { format!("(concat {} {})", list0.to_string(), list1.to_string()).parse::<Pattern<_>>().unwrap() }
+                =>
+                { Impl { list0, list1}})
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::{
+        CostFunction, ENodeOrVar, Extractor, Language as LanguageTrait, PatternAst, RecExpr,
+        Runner, Searcher,
+    };
+    use proptest::prelude::*;
+
+    use crate::{
+        ast::{strategies::arb_expr, Ast, Expr, Instr},
+        bitvec::BitVec,
+        equiv::{shared_vars, test_vectors},
+        eval::eval_expr,
+        extract::find_isa_instructions,
+        language::{typecheck, Op},
+    };
+
+    use super::*;
+
+    /// Every rewrite over programs, in each of its variants.
+    fn program_rules() -> Vec<Rewrite<Language, LanguageAnalysis>> {
+        let mut rules = vec![
+            introduce_hole_var(),
+            fuse_op(),
+            introduce_hole_op_both(),
+            introduce_hole_op_left(),
+            introduce_hole_op_right(),
+            unary0(),
+            unary1(),
+            and_redundant_mask(),
+            asr_nonnegative_to_lsr(),
+            zero_extend_shift_amounts(),
+        ];
+        rules.extend(introduce_hole_op_resized());
+        rules.extend(or_xor_known_zero());
+        rules.extend(known_bits_ast_rules());
+        rules.extend(identities());
+        rules.extend(fold_constants());
+        rules.extend(hole_introduction_bounded(AstBounds {
+            max_size: 4,
+            max_depth: 3,
+        }));
+        rules.extend(hole_introduction_max_arity(2));
+        rules
+    }
+
+    /// `expr` as an `apply` of an `instr` made of its operators, with a hole
+    /// for each leaf.
+    fn as_apply(expr: &Expr) -> Expr {
+        fn ast(expr: &Expr, args: &mut Vec<Expr>) -> Ast {
+            match expr {
+                Expr::Var { width, .. } | Expr::Const { width, .. } => {
+                    args.push(expr.clone());
+                    Ast::Hole { width: *width }
+                }
+                Expr::UnOp { op, width, arg } => Ast::UnOp {
+                    op: op.clone(),
+                    width: *width,
+                    arg: Box::new(ast(arg, args)),
+                },
+                Expr::BinOp {
+                    op,
+                    width,
+                    lhs,
+                    rhs,
+                } => Ast::BinOp {
+                    op: op.clone(),
+                    width: *width,
+                    lhs: Box::new(ast(lhs, args)),
+                    rhs: Box::new(ast(rhs, args)),
+                },
+                Expr::Reg { .. } | Expr::Extract { .. } | Expr::Cat { .. } | Expr::Apply { .. } => {
+                    unreachable!("arb_expr only generates vars, consts, unops, and binops")
+                }
+            }
+        }
+
+        let mut args = vec![];
+        let ast = ast(expr, &mut args);
+        let mut distinct: Vec<&Expr> = vec![];
+        let canonical_args = args
+            .iter()
+            .map(|arg| match distinct.iter().position(|d| *d == arg) {
+                Some(i) => i as i64,
+                None => {
+                    distinct.push(arg);
+                    distinct.len() as i64 - 1
+                }
+            })
+            .collect();
+        Expr::Apply {
+            instr: Instr {
+                ast,
+                canonical_args,
+            },
+            args,
+        }
+    }
+
+    /// An instance of `pattern` at `width` bits. Its expression variables
+    /// are bound to `exprs` in turn, each of its `apply`s is the next of
+    /// `exprs` [`as_apply`], and its operator variables are `unary` or
+    /// `binary`.
+    fn instantiate(
+        pattern: &PatternAst<Language>,
+        id: Id,
+        width: i64,
+        (unary, binary): (&Op, &Op),
+        exprs: &mut impl Iterator<Item = Expr>,
+    ) -> Expr {
+        let op = |id: Id, default: &Op| match &pattern[id] {
+            ENodeOrVar::ENode(Language::Op(op)) => op.clone(),
+            _ => default.clone(),
+        };
+        match &pattern[id] {
+            ENodeOrVar::ENode(Language::Var(_)) => Expr::Var {
+                name: "x0".to_string(),
+                width,
+            },
+            ENodeOrVar::ENode(Language::UnOp([o, _, arg])) => Expr::UnOp {
+                op: op(*o, unary),
+                width,
+                arg: Box::new(instantiate(pattern, *arg, width, (unary, binary), exprs)),
+            },
+            ENodeOrVar::ENode(Language::BinOp([o, _, lhs, rhs])) => Expr::BinOp {
+                op: op(*o, binary),
+                width,
+                lhs: Box::new(instantiate(pattern, *lhs, width, (unary, binary), exprs)),
+                rhs: Box::new(instantiate(pattern, *rhs, width, (unary, binary), exprs)),
+            },
+            ENodeOrVar::ENode(Language::Apply(_)) => as_apply(&exprs.next().unwrap()),
+            ENodeOrVar::Var(_) => exprs.next().unwrap(),
+            other => panic!("{:?} in a pattern over programs", other),
+        }
+    }
+
+    /// Prefers either the `apply`s which rewriting builds, or the `unop`s
+    /// and `binop`s which programs are written in. Never picks `canonicalize`
+    /// or `concat`, which can't be evaluated.
+    struct Prefer {
+        apply: bool,
+    }
+
+    impl CostFunction<Language> for Prefer {
+        type Cost = f64;
+
+        fn cost<C>(&mut self, enode: &Language, mut costs: C) -> Self::Cost
+        where
+            C: FnMut(Id) -> Self::Cost,
+        {
+            let own = match enode {
+                Language::Canonicalize(_) | Language::Concat(_) => f64::INFINITY,
+                Language::Apply(_) if !self.apply => 100.0,
+                Language::UnOp(_) | Language::BinOp(_) if self.apply => 100.0,
+                _ => 1.0,
+            };
+            enode.fold(own, |sum, id| sum + costs(id))
+        }
+    }
+
+    fn is_expr(node: &Language) -> bool {
+        matches!(
+            node,
+            Language::Var(_)
+                | Language::Const(_)
+                | Language::UnOp(_)
+                | Language::BinOp(_)
+                | Language::Reg(_)
+                | Language::Extract(_)
+                | Language::Cat(_)
+                | Language::Apply(_)
+        )
+    }
+
+    /// Checks that every expression enode in `egraph`, with its children
+    /// extracted preferring `apply`s, computes the same as its eclass
+    /// extracted preferring the original operators, on test vectors drawn
+    /// from `seed`.
+    fn check_classes(
+        egraph: &EGraph<Language, LanguageAnalysis>,
+        rule: &str,
+        seed: u64,
+    ) -> Result<(), TestCaseError> {
+        let original = Extractor::new(egraph, Prefer { apply: false });
+        let rewritten = Extractor::new(egraph, Prefer { apply: true });
+        for class in egraph.classes() {
+            if !class.nodes.iter().any(is_expr) {
+                continue;
+            }
+            let expected = Expr::try_from(&original.find_best(class.id).1)?;
+            for node in class.nodes.iter().filter(|node| is_expr(node)) {
+                let cost = node.fold(0.0, |sum, id| sum + rewritten.find_best_cost(id));
+                if !cost.is_finite() {
+                    continue;
+                }
+                let term = node.build_recexpr(|id| rewritten.find_best_node(id).clone());
+                let actual = Expr::try_from(&term)?;
+                for inputs in test_vectors(&shared_vars(&expected, &actual)?, 16, seed) {
+                    prop_assert_eq!(
+                        eval_expr(&actual, &inputs)?,
+                        eval_expr(&expected, &inputs)?,
+                        "{} rewrote {} to {}, which differ on {:?}",
+                        rule,
+                        RecExpr::from(&expected),
+                        term,
+                        inputs
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        /// Each rewrite, run on an instance of its pattern, only adds terms
+        /// which compute what their eclass already did.
+        #[test]
+        fn rewrites_preserve_semantics(
+            (width, exprs) in prop::sample::select(vec![1, 4, 8])
+                .prop_flat_map(|width| (Just(width), prop::collection::vec(arb_expr(width), 4))),
+            unary in prop::sample::select(vec![Op::Not, Op::Neg]),
+            binary in prop::sample::select(vec![
+                Op::And,
+                Op::Or,
+                Op::Xor,
+                Op::Add,
+                Op::Sub,
+                Op::Mul,
+                Op::Lsr,
+                Op::Asr,
+                Op::Eq,
+            ]),
+            seed in any::<u64>(),
+        ) {
+            for rule in program_rules() {
+                let pattern = rule.searcher.get_pattern_ast().unwrap();
+                let instance = instantiate(
+                    pattern,
+                    Id::from(pattern.as_ref().len() - 1),
+                    width,
+                    (&unary, &binary),
+                    &mut exprs.iter().cycle().cloned(),
+                );
+                let name = rule.name.to_string();
+                let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+                egraph.add_expr(&RecExpr::from(&instance));
+                // The hole-introduction rewrites leave `canonicalize`s and
+                // `concat`s for these two to finish.
+                let runner = Runner::default()
+                    .with_egraph(egraph)
+                    .with_iter_limit(4)
+                    .with_node_limit(10_000)
+                    .run(&vec![rule, canonicalize(), simplify_concat()]);
+                check_classes(&runner.egraph, &name, seed)?;
+            }
+        }
+    }
+
+    #[test]
+    fn known_bits_simplification() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        // The second mask is redundant.
+        let masked = egraph.add_expr(
+            &RecExpr::from_str("(binop and 8 (binop and 8 (var x 8) (const 15 8)) (const 15 8))")
+                .unwrap(),
+        );
+        let unmasked =
+            egraph.add_expr(&RecExpr::from_str("(binop and 8 (var x 8) (const 15 8))").unwrap());
+        // x | (x & 0) is x.
+        let ored = egraph.add_expr(
+            &RecExpr::from_str("(binop or 8 (var x 8) (binop and 8 (var x 8) (const 0 8)))")
+                .unwrap(),
+        );
+        let x = egraph.add_expr(&RecExpr::from_str("(var x 8)").unwrap());
+        let mut rules = vec![and_redundant_mask()];
+        rules.extend(or_xor_known_zero());
+        let runner = Runner::default().with_egraph(egraph).run(&rules);
+        assert_eq!(runner.egraph.find(masked), runner.egraph.find(unmasked));
+        assert_eq!(runner.egraph.find(ored), runner.egraph.find(x));
+
+        // The sum of two bytes has its sign bit clear, so the candidate's
+        // asr is an lsr, whatever the operands.
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let asr = egraph.add_expr(
+            &RecExpr::from_str(
+                "(apply (instr (binop-ast asr 16 (binop-ast add 16 (hole 8) (hole 8)) (hole 16)) \
+                 (canonical-args 0 1 2)) (list (var x 8) (var y 8) (var s 16)))",
+            )
+            .unwrap(),
+        );
+        let lsr = egraph.add_expr(
+            &RecExpr::from_str(
+                "(apply (instr (binop-ast lsr 16 (binop-ast add 16 (hole 8) (hole 8)) (hole 16)) \
+                 (canonical-args 0 1 2)) (list (var x 8) (var y 8) (var s 16)))",
+            )
+            .unwrap(),
+        );
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .run(&known_bits_ast_rules());
+        assert_eq!(runner.egraph.find(asr), runner.egraph.find(lsr));
+    }
+
+    #[test]
+    fn known_bits_ast_rules_keep_left_holes() {
+        // Built by hand, as holes alone never make a known zero: the right
+        // operand is zero, so only the left one's arguments are kept.
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let ored = egraph.add_expr(
+            &RecExpr::from_str(
+                "(apply (instr (binop-ast or 8 (binop-ast and 8 (hole 8) (hole 8)) \
+                 (binop-ast and 8 (hole 8) (const 0 8))) (canonical-args 0 1 0)) \
+                 (list (var x 8) (var y 8) (var x 8)))",
+            )
+            .unwrap(),
+        );
+        let left = egraph.add_expr(
+            &RecExpr::from_str(
+                "(apply (instr (binop-ast and 8 (hole 8) (hole 8)) (canonical-args 0 1)) \
+                 (list (var x 8) (var y 8)))",
+            )
+            .unwrap(),
+        );
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .run(&[known_bits_ast_rules(), vec![canonicalize()]].concat());
+        assert_eq!(runner.egraph.find(ored), runner.egraph.find(left));
+    }
+
+    #[test]
+    fn narrow_shift_amounts() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let id = egraph.add_expr(&RecExpr::from_str("(binop lsr 8 (var x 8) (var s 3))").unwrap());
+        let rules = vec![
+            introduce_hole_var(),
+            fuse_op(),
+            introduce_hole_op_both(),
+            introduce_hole_op_left(),
+            introduce_hole_op_right(),
+            zero_extend_shift_amounts(),
+            simplify_concat(),
+            canonicalize(),
+        ];
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(10)
+            .run(&rules);
+        "(binop lsr 8 (var x 8) (cat (const 0 5) (var s 3)))"
+            .parse::<Pattern<_>>()
+            .unwrap()
+            .search_eclass(&runner.egraph, id)
+            .unwrap();
+        // Holes stand for operands of their own width.
+        let instrs = find_isa_instructions(&runner.egraph)
+            .unwrap()
+            .into_iter()
+            .map(|(_, instr)| instr.to_string())
+            .collect::<Vec<_>>();
+        assert!(instrs.contains(
+            &"(instr (binop-ast lsr 8 (hole 8) (hole 3)) (canonical-args 0 1))".to_string()
+        ));
+        for instr in &instrs {
+            typecheck(&RecExpr::from_str(instr).unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn resized_ops() {
+        // A multiply-accumulate whose multiply widens its operands.
+        let program =
+            RecExpr::from_str("(binop add 16 (binop mul 16 (var x 8) (var y 8)) (var z 16))")
+                .unwrap();
+        assert_eq!(typecheck(&program), Ok(crate::language::Type::Signal(16)));
+        let env = HashMap::from([
+            ("x".to_string(), BitVec::new(8, 200)),
+            ("y".to_string(), BitVec::new(8, 200)),
+            ("z".to_string(), BitVec::new(16, 1)),
+        ]);
+        assert_eq!(
+            eval_expr(&Expr::try_from(&program).unwrap(), &env).unwrap(),
+            BitVec::new(16, 40001)
+        );
+
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        egraph.add_expr(&program);
+        let mut rules = vec![
+            introduce_hole_var(),
+            fuse_op(),
+            introduce_hole_op_both(),
+            introduce_hole_op_left(),
+            introduce_hole_op_right(),
+            simplify_concat(),
+            canonicalize(),
+        ];
+        rules.extend(introduce_hole_op_resized());
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(10)
+            .run(&rules);
+        // Holes stand for operands of their own width, not the op's.
+        let instrs = find_isa_instructions(&runner.egraph)
+            .unwrap()
+            .into_iter()
+            .map(|(_, instr)| instr.to_string())
+            .collect::<Vec<_>>();
+        for instr in [
+            "(instr (binop-ast mul 16 (hole 8) (hole 8)) (canonical-args 0 1))",
+            "(instr (binop-ast add 16 (binop-ast mul 16 (hole 8) (hole 8)) (hole 16)) (canonical-args 0 1 2))",
+        ] {
+            assert!(instrs.contains(&instr.to_string()), "{}", instr);
+        }
+        for instr in &instrs {
+            typecheck(&RecExpr::from_str(instr).unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn free_vars_and_arity() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let id = egraph.add_expr(
+            &RecExpr::from_str(
+                "(binop xor 8 (binop xor 8 (var x 8) (var y 8)) (binop and 8 (var z 8) (const 1 8)))",
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            free_vars(&egraph, id),
+            BTreeSet::from(["x".to_string(), "y".to_string(), "z".to_string()])
+        );
+
+        let mut rules = vec![introduce_hole_var(), simplify_concat(), canonicalize()];
+        rules.extend(hole_introduction_max_arity(2));
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(10)
+            .run(&rules);
+        for (instr_id, _) in find_isa_instructions(&runner.egraph).unwrap() {
+            for apply in runner.egraph.classes() {
+                for node in &apply.nodes {
+                    if let &Language::Apply([id, args_id]) = node {
+                        if runner.egraph.find(id) == instr_id {
+                            assert!(free_vars(&runner.egraph, args_id).len() <= 2);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ast_size_and_depth() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let id = egraph.add_expr(
+            &RecExpr::from_str("(binop xor 8 (binop and 8 (var x 8) (var y 8)) (var z 8))")
+                .unwrap(),
+        );
+        assert!(matches!(
+            egraph[id].data,
+            Signal {
+                size: 5,
+                depth: 3,
+                ..
+            }
+        ));
+
+        let bounds = AstBounds {
+            max_size: 3,
+            max_depth: 2,
+        };
+        let mut rules = vec![introduce_hole_var(), simplify_concat(), canonicalize()];
+        rules.extend(hole_introduction_bounded(bounds));
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(10)
+            .run(&rules);
+        let isa = find_isa_instructions(&runner.egraph).unwrap();
+        assert!(!isa.is_empty());
+        for (_, instr) in isa {
+            // Skip the instr node and its canonical args.
+            let ast_nodes = instr
+                .as_ref()
+                .iter()
+                .filter(|node| {
+                    matches!(
+                        node,
+                        Language::Hole(_) | Language::UnOpAst(_) | Language::BinOpAst(_)
+                    )
+                })
+                .count();
+            assert!(ast_nodes <= bounds.max_size);
+        }
+    }
+
+    #[test]
+    fn macro_ops_build_on_instructions() {
+        let and =
+            RecExpr::from_str("(instr (binop-ast and 8 (hole 8) (hole 8)) (canonical-args 0 1))")
+                .unwrap();
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        egraph.add_expr(
+            &RecExpr::from_str("(binop or 8 (binop and 8 (var x 8) (var y 8)) (var z 8))").unwrap(),
+        );
+        let mut rules = vec![
+            introduce_hole_var(),
+            introduce_hole_op_both(),
+            introduce_hole_op_right(),
+            simplify_concat(),
+            canonicalize(),
+        ];
+        rules.extend(macro_op_rules(&[and.clone()]).unwrap());
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(10)
+            .run(&rules);
+
+        let macro_op = format!(
+            "(instr (binop-ast or 8 (apply-ast {} (list (hole 8) (hole 8))) (hole 8)) \
+             (canonical-args 0 1 2))",
+            and
+        );
+        let instrs = find_isa_instructions(&runner.egraph).unwrap();
+        let (_, found) = instrs
+            .iter()
+            .find(|(_, instr)| instr.to_string() == macro_op)
+            .unwrap();
+        // Inlined, it's the instruction the `and` would have been fused into.
+        let inlined = Instr::try_from(found).unwrap().inline();
+        assert_eq!(
+            RecExpr::from(&inlined).to_string(),
+            "(instr (binop-ast or 8 (binop-ast and 8 (hole 8) (hole 8)) (hole 8)) \
+             (canonical-args 0 1 2))"
+        );
+        assert_eq!(crate::isa::instr_size(found), 5);
+        assert!(macro_op_rules(&[RecExpr::from_str("(var x 8)").unwrap()]).is_err());
+    }
+
+    #[test_log::test]
+    fn test_canonicalize() {
+        let mut egraph: EGraph<Language, LanguageAnalysis> = EGraph::default();
+        let id = egraph.add_expr(&RecExpr::from_str("(canonicalize (list 1 3 2 3))").unwrap());
+
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .run(&vec![canonicalize()]);
+
+        "(canonical-args 0 1 2 1)"
+            .parse::<Pattern<_>>()
+            .unwrap()
+            .search_eclass(&runner.egraph, id)
+            .unwrap();
+    }
+}
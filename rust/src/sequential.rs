@@ -0,0 +1,284 @@
+//! Sequential equivalence: whether two programs with `reg`s agree in every
+//! cycle, one possibly lagging the other by a fixed latency.
+//!
+//! Each program is a [`Machine`], a transition system whose state is its
+//! registers. [`bounded_check`] unrolls both from reset, where every
+//! register is zero, which finds a cycle in which they differ if there is
+//! one within the bound, but proves nothing past it. [`k_induction`] also
+//! checks that whenever they agree for `k` cycles in a row, from any state,
+//! they agree in the next, which together with agreeing in the first `k`
+//! cycles from reset proves they agree in every cycle.
+//!
+//! [`crate::smt::SmtBackend`] checks programs with `reg`s this way, so that
+//! stateful candidates can be verified against multi-cycle specifications.
+
+use crate::ast::Expr;
+
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+use crate::{
+    bitvec::BitVec,
+    error::LakeroadError,
+    pipeline::delayed,
+    smt::{cycle_var, symbolic_in, SmtSolver, Term},
+};
+
+/// A program as a transition system: its output, and each register's next
+/// value, in terms of its inputs and its registers' current values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Machine {
+    /// The program with each `reg` replaced by a variable holding its
+    /// register's current value.
+    pub output: Expr,
+    /// Each register's variable, its width, and its next value, with the
+    /// registers numbered in preorder.
+    pub registers: Vec<(String, i64, Expr)>,
+}
+
+/// The outcome of [`k_induction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequentialVerdict {
+    /// The programs agree in every cycle, as induction over `k` cycles
+    /// proves.
+    Equivalent { k: usize },
+    /// The programs differ in `cycle`, counting from reset.
+    Differs { cycle: usize },
+    /// Neither was shown up to the largest `k` tried.
+    Unknown,
+}
+
+impl Machine {
+    /// `expr` as a machine, whose registers' variables are named `prefix`
+    /// and their number.
+    pub fn new(expr: &Expr, prefix: &str) -> Self {
+        let mut registers = vec![];
+        let output = split(expr, prefix, &mut registers);
+        Machine { output, registers }
+    }
+}
+
+/// `expr` with its `reg`s replaced by variables named `prefix` and their
+/// number, which are pushed onto `registers` with their next values.
+fn split(expr: &Expr, prefix: &str, registers: &mut Vec<(String, i64, Expr)>) -> Expr {
+    match expr {
+        Expr::Reg { width, arg } => {
+            let name = format!("{}{}", prefix, registers.len());
+            let var = Expr::Var {
+                name: name.clone(),
+                width: *width,
+            };
+            // Numbered before the registers in its argument.
+            let i = registers.len();
+            registers.push((name, *width, var.clone()));
+            registers[i].2 = split(arg, prefix, registers);
+            var
+        }
+        Expr::Var { .. } | Expr::Const { .. } => expr.clone(),
+        Expr::UnOp { op, width, arg } => Expr::UnOp {
+            op: op.clone(),
+            width: *width,
+            arg: Box::new(split(arg, prefix, registers)),
+        },
+        Expr::BinOp {
+            op,
+            width,
+            lhs,
+            rhs,
+        } => Expr::BinOp {
+            op: op.clone(),
+            width: *width,
+            lhs: Box::new(split(lhs, prefix, registers)),
+            rhs: Box::new(split(rhs, prefix, registers)),
+        },
+        Expr::Extract { hi, lo, arg } => Expr::Extract {
+            hi: *hi,
+            lo: *lo,
+            arg: Box::new(split(arg, prefix, registers)),
+        },
+        Expr::Cat { hi, lo } => Expr::Cat {
+            hi: Box::new(split(hi, prefix, registers)),
+            lo: Box::new(split(lo, prefix, registers)),
+        },
+        Expr::Apply { instr, args } => Expr::Apply {
+            instr: instr.clone(),
+            args: args
+                .iter()
+                .map(|arg| split(arg, prefix, registers))
+                .collect(),
+        },
+    }
+}
+
+/// `spec`, delayed by `latency` cycles, and `candidate` as machines, whose
+/// registers are named apart by `tag`.
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+fn machines(spec: &Expr, candidate: &Expr, latency: usize, tag: &str) -> (Machine, Machine) {
+    (
+        Machine::new(&delayed(spec.clone(), latency), &format!("spec${}.r", tag)),
+        Machine::new(candidate, &format!("candidate${}.r", tag)),
+    )
+}
+
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+impl Machine {
+    /// The output in `cycle`, over the inputs and registers in that cycle,
+    /// as [`cycle_var`] names them.
+    fn output_in(&self, cycle: usize) -> Result<Term, LakeroadError> {
+        symbolic_in(&self.output, cycle)
+    }
+
+    /// Definitions of the registers in cycles 1 to `cycles`, from the
+    /// cycle before, and, if `reset`, as zero in cycle 0. Otherwise they're
+    /// free in cycle 0.
+    fn unroll(
+        &self,
+        cycles: usize,
+        reset: bool,
+    ) -> Result<Vec<(String, usize, Term)>, LakeroadError> {
+        let mut out = vec![];
+        if reset {
+            for (name, width, _) in &self.registers {
+                let width = bits(*width)?;
+                out.push((cycle_var(name, 0), width, Term::Const(BitVec::zero(width))));
+            }
+        }
+        for cycle in 0..cycles {
+            for (name, width, next) in &self.registers {
+                out.push((
+                    cycle_var(name, cycle + 1),
+                    bits(*width)?,
+                    symbolic_in(next, cycle)?,
+                ));
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+fn bits(width: i64) -> Result<usize, LakeroadError> {
+    usize::try_from(width).map_err(|_| LakeroadError::Malformed(format!("bitwidth {}", width)))
+}
+
+/// Whether `spec` and `candidate` agree in `cycle`, from reset.
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+fn agree_from_reset(
+    solver: &mut SmtSolver,
+    spec: &Machine,
+    candidate: &Machine,
+    cycle: usize,
+) -> Result<bool, LakeroadError> {
+    let mut definitions = spec.unroll(cycle, true)?;
+    definitions.extend(candidate.unroll(cycle, true)?);
+    solver.equivalent_under(
+        &definitions,
+        &spec.output_in(cycle)?,
+        &candidate.output_in(cycle)?,
+    )
+}
+
+/// The first cycle from reset, among the `cycles` after the first
+/// `latency`, in which `candidate`'s output differs from `spec`'s `latency`
+/// cycles before, or `None` if there isn't one.
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+pub fn bounded_check(
+    solver: &mut SmtSolver,
+    spec: &Expr,
+    candidate: &Expr,
+    latency: usize,
+    cycles: usize,
+) -> Result<Option<usize>, LakeroadError> {
+    let (spec, candidate) = machines(spec, candidate, latency, "reset");
+    for cycle in latency..latency + cycles {
+        if !agree_from_reset(solver, &spec, &candidate, cycle)? {
+            return Ok(Some(cycle));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether `candidate` computes what `spec` does, `latency` cycles later,
+/// in every cycle after the first `latency`, by induction over `k` cycles
+/// for `k` from 1 to `max_k`.
+///
+/// Registers only delay values, so a program's state is a function of its
+/// last [`Expr::reg_depth`] cycles of inputs, and `max_k` past both
+/// programs' depths, plus the latency, always decides.
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+pub fn k_induction(
+    solver: &mut SmtSolver,
+    spec: &Expr,
+    candidate: &Expr,
+    latency: usize,
+    max_k: usize,
+) -> Result<SequentialVerdict, LakeroadError> {
+    let (reset_spec, reset_candidate) = machines(spec, candidate, latency, "reset");
+    let (any_spec, any_candidate) = machines(spec, candidate, latency, "any");
+    for k in 1..=max_k {
+        // The base case: they agree in the first `k` cycles, all but the
+        // last of which the smaller `k`s checked.
+        let cycle = latency + k - 1;
+        if !agree_from_reset(solver, &reset_spec, &reset_candidate, cycle)? {
+            return Ok(SequentialVerdict::Differs { cycle });
+        }
+        // The step: from any state, agreeing for `k` cycles means agreeing
+        // in the next.
+        let mut definitions = any_spec.unroll(k, false)?;
+        definitions.extend(any_candidate.unroll(k, false)?);
+        let assumptions = (0..k)
+            .map(|cycle| Ok((any_spec.output_in(cycle)?, any_candidate.output_in(cycle)?)))
+            .collect::<Result<Vec<_>, LakeroadError>>()?;
+        if solver.equivalent_assuming(
+            &definitions,
+            &assumptions,
+            &any_spec.output_in(k)?,
+            &any_candidate.output_in(k)?,
+        )? {
+            return Ok(SequentialVerdict::Equivalent { k });
+        }
+    }
+    Ok(SequentialVerdict::Unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::RecExpr;
+
+    use super::*;
+
+    #[test]
+    fn registers_become_state() {
+        let expr = Expr::try_from(
+            &RecExpr::from_str("(binop add 8 (reg 8 (reg 8 (var x 8))) (var y 8))").unwrap(),
+        )
+        .unwrap();
+        let machine = Machine::new(&expr, "r");
+        let var = |name: &str| Expr::Var {
+            name: name.to_string(),
+            width: 8,
+        };
+        assert_eq!(
+            machine.output,
+            Expr::try_from(&RecExpr::from_str("(binop add 8 (var r0 8) (var y 8))").unwrap())
+                .unwrap()
+        );
+        // The outer register takes the inner one's value.
+        assert_eq!(
+            machine.registers,
+            vec![
+                ("r0".to_string(), 8, var("r1")),
+                ("r1".to_string(), 8, var("x"))
+            ]
+        );
+
+        // A spec with a cycle's latency gets a register in front of it.
+        #[cfg(feature = "z3")]
+        {
+            let (spec, candidate) = machines(&expr, &expr, 1, "t");
+            assert_eq!(spec.registers.len(), 3);
+            assert_eq!(spec.output, var("spec$t.r0"));
+            assert_eq!(candidate.registers[0].0, "candidate$t.r0");
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! A live exploration session driven by text commands, behind the CLI's
+//! `repl`. Programs share one e-graph, which is rewritten a few iterations
+//! at a time so that it can be inspected in between, e.g. to find out why an
+//! instruction never appears.
+
+use std::{fs, path::Path, str::FromStr};
+
+use egg::{EGraph, Id, Language as LanguageTrait, RecExpr, Rewrite, Runner};
+
+use crate::{
+    analysis::LanguageAnalysis,
+    backend::{FnBackend, SynthesisBackend},
+    corpus::Corpus,
+    emit::{export_instructions, ExportFormat},
+    error::LakeroadError,
+    extract::find_isa_instructions,
+    isa::is_hole_instr,
+    language::{typecheck_expr, Language},
+    synthesizer::{default_rules, timed, Backend, IterationReport},
+};
+
+/// The commands [`Session::execute`] understands.
+pub const HELP: &str = "\
+load <file>...           add the programs in each file
+add <name> <expr>        add a program
+programs                 list the programs and their eclasses
+rewrite [n]              run n rewrite iterations (default 1)
+eclass <id>              show an eclass's nodes and analysis data
+candidates               list the candidate instructions
+verify <n>               check candidate n with the backend
+export <format> [file]   write the accepted candidates as verilog, json, rosette, or c
+help                     show this message
+quit                     end the session";
+
+pub struct Session {
+    egraph: EGraph<Language, LanguageAnalysis>,
+    /// Each program's name and root, which may be stale; use
+    /// [`EGraph::find`].
+    programs: Vec<(String, Id)>,
+    rules: Vec<Rewrite<Language, LanguageAnalysis>>,
+    backend: Backend,
+    node_limit: usize,
+    iterations: usize,
+    /// The candidates last listed, which `verify` refers to by index.
+    candidates: Vec<(Id, RecExpr<Language>)>,
+    accepted: Vec<RecExpr<Language>>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session {
+            egraph: EGraph::default(),
+            programs: vec![],
+            rules: default_rules(),
+            backend: Box::new(FnBackend(|_: &RecExpr<Language>| true)),
+            node_limit: 100_000,
+            iterations: 0,
+            candidates: vec![],
+            accepted: vec![],
+        }
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the [`default_rules`].
+    pub fn with_rules(mut self, rules: Vec<Rewrite<Language, LanguageAnalysis>>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Checks candidates for `verify`. By default every candidate is
+    /// accepted.
+    pub fn with_backend(mut self, backend: impl SynthesisBackend + 'static) -> Self {
+        self.backend = Box::new(backend);
+        self
+    }
+
+    pub fn with_node_limit(mut self, node_limit: usize) -> Self {
+        self.node_limit = node_limit;
+        self
+    }
+
+    pub fn egraph(&self) -> &EGraph<Language, LanguageAnalysis> {
+        &self.egraph
+    }
+
+    /// Adds a program, returning its root.
+    pub fn add_program(
+        &mut self,
+        name: impl Into<String>,
+        expr: &RecExpr<Language>,
+    ) -> Result<Id, LakeroadError> {
+        typecheck_expr(expr)?;
+        let root = self.egraph.add_expr(expr);
+        self.egraph.rebuild();
+        self.programs.push((name.into(), root));
+        Ok(root)
+    }
+
+    /// Adds the programs in a file, returning their roots as `execute`
+    /// would print them.
+    pub fn load(&mut self, path: &Path) -> Result<String, LakeroadError> {
+        let programs = Corpus::load_file(path).ok_or_else(|| {
+            LakeroadError::Parse(format!("{}: not a program file", path.display()))
+        })??;
+        let mut out = vec![];
+        for program in programs.iter() {
+            let root = self.add_program(program.name.clone(), &program.expr)?;
+            out.push(format!("{}: eclass {}", program.name, root));
+        }
+        Ok(out.join("\n"))
+    }
+
+    /// Runs up to `iterations` rewrite iterations.
+    pub fn rewrite(&mut self, iterations: usize) -> Vec<IterationReport> {
+        let runner = Runner::default()
+            .with_egraph(std::mem::take(&mut self.egraph))
+            .with_iter_limit(iterations)
+            .with_node_limit(self.node_limit)
+            .run(&self.rules);
+        self.iterations += runner.iterations.len();
+        self.egraph = runner.egraph;
+        runner
+            .iterations
+            .iter()
+            .map(IterationReport::from)
+            .collect()
+    }
+
+    /// Lists the candidates other than the bare hole, and remembers them
+    /// for `verify`.
+    pub fn candidates(&mut self) -> Result<&[(Id, RecExpr<Language>)], LakeroadError> {
+        self.candidates = find_isa_instructions(&self.egraph)?
+            .into_iter()
+            .filter(|(_, instr)| !is_hole_instr(instr))
+            .collect();
+        Ok(&self.candidates)
+    }
+
+    /// Runs one command, returning what to print.
+    pub fn execute(&mut self, line: &str) -> Result<String, LakeroadError> {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let args = rest.split_whitespace().collect::<Vec<_>>();
+        let usage = || LakeroadError::Parse(format!("usage: {}", usage_of(command)));
+        match command {
+            "" => Ok(String::new()),
+            "help" => Ok(HELP.to_string()),
+            "load" => {
+                if args.is_empty() {
+                    return Err(usage());
+                }
+                let mut out = vec![];
+                for path in args {
+                    out.push(self.load(Path::new(path))?);
+                }
+                Ok(out.join("\n"))
+            }
+            "add" => {
+                let (name, expr) = rest.split_once(char::is_whitespace).ok_or_else(usage)?;
+                let expr = RecExpr::from_str(expr.trim())
+                    .map_err(|e| LakeroadError::Parse(e.to_string()))?;
+                let root = self.add_program(name, &expr)?;
+                Ok(format!("{}: eclass {}", name, root))
+            }
+            "programs" => Ok(self
+                .programs
+                .iter()
+                .map(|(name, root)| format!("{}: eclass {}", name, self.egraph.find(*root)))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "rewrite" => {
+                let iterations = match args.as_slice() {
+                    [] => 1,
+                    [n] => n.parse().map_err(|_| usage())?,
+                    _ => return Err(usage()),
+                };
+                let first = self.iterations;
+                let reports = self.rewrite(iterations);
+                Ok(reports
+                    .iter()
+                    .enumerate()
+                    .map(|(i, report)| {
+                        format!(
+                            "iteration {}: {} nodes, {} eclasses, {} applications",
+                            first + i + 1,
+                            report.egraph_nodes,
+                            report.egraph_classes,
+                            report.applied
+                        )
+                    })
+                    .chain(std::iter::once(format!(
+                        "now {} nodes, {} eclasses",
+                        self.egraph.total_size(),
+                        self.egraph.number_of_classes()
+                    )))
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            "eclass" => {
+                let id = match args.as_slice() {
+                    [id] => id.parse::<usize>().map_err(|_| usage())?,
+                    _ => return Err(usage()),
+                };
+                let eclass = self
+                    .egraph
+                    .classes()
+                    .find(|eclass| usize::from(eclass.id) == id)
+                    .ok_or_else(|| {
+                        LakeroadError::Parse(format!("{} isn't a canonical eclass", id))
+                    })?;
+                let mut out = eclass
+                    .nodes
+                    .iter()
+                    .map(|node| match node.children() {
+                        [] => format!("  {}", node),
+                        children => format!(
+                            "  ({} {})",
+                            node,
+                            children
+                                .iter()
+                                .map(|child| self.egraph.find(*child).to_string())
+                                .collect::<Vec<_>>()
+                                .join(" ")
+                        ),
+                    })
+                    .collect::<Vec<_>>();
+                out.push(format!("  data: {:?}", eclass.data));
+                Ok(out.join("\n"))
+            }
+            "candidates" => Ok(self
+                .candidates()?
+                .iter()
+                .enumerate()
+                .map(|(i, (id, instr))| format!("{}: {} (eclass {})", i, instr, id))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "verify" => {
+                let index = match args.as_slice() {
+                    [n] => n.parse::<usize>().map_err(|_| usage())?,
+                    _ => return Err(usage()),
+                };
+                if self.candidates.is_empty() {
+                    self.candidates()?;
+                }
+                let instr = match self.candidates.get(index) {
+                    Some((_, instr)) => instr.clone(),
+                    None => {
+                        return Err(LakeroadError::Parse(format!(
+                            "there are only {} candidates",
+                            self.candidates.len()
+                        )))
+                    }
+                };
+                let (accepted, seconds) = timed(|| self.backend.check_candidate(&instr));
+                let verdict = if accepted? {
+                    if !self.accepted.contains(&instr) {
+                        self.accepted.push(instr.clone());
+                    }
+                    "accepted"
+                } else {
+                    "rejected"
+                };
+                Ok(format!("{} {} in {:.3}s", verdict, instr, seconds))
+            }
+            "export" => {
+                let (format, path) = match args.as_slice() {
+                    [format] => (format, None),
+                    [format, path] => (format, Some(path)),
+                    _ => return Err(usage()),
+                };
+                let format = ExportFormat::from_str(format)?;
+                let out = export_instructions(format, &self.accepted)?;
+                match path {
+                    Some(path) => {
+                        fs::write(path, out)?;
+                        Ok(format!(
+                            "wrote {} instructions to {}",
+                            self.accepted.len(),
+                            path
+                        ))
+                    }
+                    None => Ok(out),
+                }
+            }
+            _ => Err(LakeroadError::Parse(format!(
+                "unknown command {:?}; try `help`",
+                line
+            ))),
+        }
+    }
+}
+
+/// The line of [`HELP`] describing `command`.
+fn usage_of(command: &str) -> &'static str {
+    HELP.lines()
+        .find(|line| line.split_whitespace().next() == Some(command))
+        .map_or(HELP, str::trim_end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explore_in_steps() {
+        let mut session = Session::new().with_backend(FnBackend(|instr: &RecExpr<Language>| {
+            instr.to_string().contains("binop-ast and")
+        }));
+        let added = session
+            .execute("add and (binop and 8 (var x 8) (var y 8))")
+            .unwrap();
+        assert!(added.starts_with("and: eclass "));
+        assert_eq!(session.execute("candidates").unwrap(), "");
+
+        let rewritten = session.execute("rewrite 2").unwrap();
+        assert!(rewritten.starts_with("iteration 1: "));
+        let rewritten = session.execute("rewrite").unwrap();
+        assert!(rewritten.contains(&format!("iteration {}: ", session.iterations)));
+
+        let candidates = session.execute("candidates").unwrap();
+        let and = candidates
+            .lines()
+            .position(|line| line.contains("binop-ast and"))
+            .unwrap();
+        assert!(session
+            .execute(&format!("verify {}", and))
+            .unwrap()
+            .starts_with("accepted"));
+        assert!(session.execute("export json").unwrap().contains("instr0"));
+
+        let root = session.egraph.find(session.programs[0].1);
+        let eclass = session.execute(&format!("eclass {}", root)).unwrap();
+        assert!(eclass.contains("apply"));
+
+        assert!(matches!(
+            session.execute("rewrite many"),
+            Err(LakeroadError::Parse(_))
+        ));
+        assert!(session.execute("frobnicate").is_err());
+    }
+}
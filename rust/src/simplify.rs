@@ -0,0 +1,87 @@
+//! Simplifying programs, independently of exploring ISAs.
+//!
+//! [`simplify`] rewrites a program with a rule set, by default
+//! [`simplification_rules`], and extracts its cheapest equivalent form by a
+//! cost function, e.g. [`egg::AstSize`]. Programs can be simplified before
+//! they're given to the [`crate::Synthesizer`], so that e.g. a redundant
+//! mask doesn't become part of a candidate, and the crate can be used as a
+//! standalone bitvector simplifier.
+
+use egg::{CostFunction, Extractor, RecExpr, Rewrite, Runner};
+
+use crate::{
+    analysis::LanguageAnalysis,
+    language::Language,
+    rewrites::{
+        and_redundant_mask, asr_nonnegative_to_lsr, fold_constants, identities, or_xor_known_zero,
+    },
+};
+
+/// The rewrites which simplify programs without introducing `apply`s: the
+/// [`identities`], constant folding, and the rewrites which use what the
+/// analysis knows about bits and ranges.
+pub fn simplification_rules() -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    let mut rules = vec![and_redundant_mask(), asr_nonnegative_to_lsr()];
+    rules.extend(or_xor_known_zero());
+    rules.extend(identities());
+    rules.extend(fold_constants());
+    rules
+}
+
+/// The cheapest program by `cost_model` equivalent to `expr` under `rules`.
+pub fn simplify<C>(
+    expr: &RecExpr<Language>,
+    rules: &[Rewrite<Language, LanguageAnalysis>],
+    cost_model: C,
+) -> RecExpr<Language>
+where
+    C: CostFunction<Language>,
+{
+    let runner = Runner::default().with_expr(expr).run(rules);
+    Extractor::new(&runner.egraph, cost_model)
+        .find_best(runner.roots[0])
+        .1
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::AstSize;
+
+    use super::*;
+
+    #[test]
+    fn simplify_bithacks() {
+        let simplified = |program: &str| {
+            simplify(
+                &RecExpr::from_str(program).unwrap(),
+                &simplification_rules(),
+                AstSize,
+            )
+            .to_string()
+        };
+        assert_eq!(
+            simplified("(binop add 8 (const 2 8) (const 3 8))"),
+            "(const 5 8)"
+        );
+        assert_eq!(
+            simplified("(unop not 8 (unop not 8 (binop or 8 (var x 8) (var x 8))))"),
+            "(var x 8)"
+        );
+        // `x ^ x` is zero, so the `and` is too, and so adding it does
+        // nothing.
+        assert_eq!(
+            simplified(
+                "(binop add 8 (var y 8) \
+                 (binop and 8 (binop xor 8 (var x 8) (var x 8)) (var y 8)))"
+            ),
+            "(var y 8)"
+        );
+        // The shift already clears the bits the mask does.
+        assert_eq!(
+            simplified("(binop and 8 (binop lsr 8 (var x 8) (const 4 8)) (const 15 8))"),
+            "(binop lsr 8 (var x 8) (const 4 8))"
+        );
+    }
+}
@@ -0,0 +1,611 @@
+//! Symbolic execution of programs into SMT-LIB bitvector terms, and a
+//! backend which checks them with an SMT solver directly.
+//!
+//! [`symbolic`] walks an expression, `apply`s included, and builds a
+//! [`Term`] in the theory of fixed-size bitvectors, whose operators have the
+//! language's semantics (e.g. `bvlshr` by the width or more is zero). The
+//! [`SmtBackend`] keeps one solver process running and asks each query
+//! between a `push` and a `pop`, so variables are declared once and the
+//! solver keeps what it learned between queries. Like the Racket backend,
+//! it runs a process, so it's built with a feature of its own, `z3`, and
+//! left out of WebAssembly builds, as is everything which proves with it.
+//!
+//! Programs with `reg`s are unrolled over cycles by [`symbolic_cycles`]. A
+//! program's output in a cycle only depends on the inputs of the cycles
+//! [`Expr::reg_depth`] back, and registers start at zero, so checking the
+//! first `reg_depth + 1` cycles checks every cycle. The backend checks them
+//! by k-induction instead (see [`crate::sequential`]), which also handles
+//! specifications a candidate lags by some cycles.
+
+use std::fmt::Display;
+
+use crate::{
+    ast::{Ast, Expr, Instr},
+    bitvec::BitVec,
+    error::LakeroadError,
+    language::Op,
+};
+
+/// A bitvector term.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Term {
+    Var {
+        name: String,
+        width: usize,
+    },
+    Const(BitVec),
+    /// An SMT-LIB operator applied to its operands, e.g. `bvadd`.
+    App {
+        op: &'static str,
+        args: Vec<Term>,
+    },
+    /// `1` if the operands are equal and `0` otherwise, at `width` bits.
+    Eq {
+        width: usize,
+        lhs: Box<Term>,
+        rhs: Box<Term>,
+    },
+    /// Bits `lo` to `hi` of `arg`, inclusive.
+    Extract {
+        hi: usize,
+        lo: usize,
+        arg: Box<Term>,
+    },
+    /// `arg` with `by` more bits, copies of its sign bit if `signed`.
+    Extend {
+        signed: bool,
+        by: usize,
+        arg: Box<Term>,
+    },
+}
+
+impl Display for Term {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            // Quoted, so that any name is a symbol.
+            Term::Var { name, .. } => write!(f, "|{}|", name),
+            Term::Const(value) => {
+                write!(f, "#b")?;
+                for i in (0..value.width()).rev() {
+                    write!(f, "{}", value.bit(i) as u8)?;
+                }
+                Ok(())
+            }
+            Term::App { op, args } => {
+                write!(f, "({}", op)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Term::Eq { width, lhs, rhs } => write!(
+                f,
+                "(ite (= {} {}) (_ bv1 {}) (_ bv0 {}))",
+                lhs, rhs, width, width
+            ),
+            Term::Extract { hi, lo, arg } => write!(f, "((_ extract {} {}) {})", hi, lo, arg),
+            Term::Extend { signed, by, arg } => write!(
+                f,
+                "((_ {} {}) {})",
+                if *signed {
+                    "sign_extend"
+                } else {
+                    "zero_extend"
+                },
+                by,
+                arg
+            ),
+        }
+    }
+}
+
+impl Term {
+    pub fn app(op: &'static str, args: Vec<Term>) -> Term {
+        Term::App { op, args }
+    }
+
+    pub fn extract(&self, hi: usize, lo: usize) -> Term {
+        Term::Extract {
+            hi,
+            lo,
+            arg: Box::new(self.clone()),
+        }
+    }
+
+    pub fn extend(&self, signed: bool, by: usize) -> Term {
+        Term::Extend {
+            signed,
+            by,
+            arg: Box::new(self.clone()),
+        }
+    }
+
+    /// The constant's value, if the term is a constant.
+    pub fn as_const(&self) -> Option<&BitVec> {
+        match self {
+            Term::Const(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The variables of the term and their widths, in the order they first
+    /// appear.
+    pub fn vars(&self) -> Vec<(String, usize)> {
+        fn go(term: &Term, out: &mut Vec<(String, usize)>) {
+            match term {
+                Term::Var { name, width } => {
+                    if !out.iter().any(|(n, _)| n == name) {
+                        out.push((name.clone(), *width));
+                    }
+                }
+                Term::Const(_) => (),
+                Term::App { args, .. } => args.iter().for_each(|arg| go(arg, out)),
+                Term::Eq { lhs, rhs, .. } => {
+                    go(lhs, out);
+                    go(rhs, out);
+                }
+                Term::Extract { arg, .. } | Term::Extend { arg, .. } => go(arg, out),
+            }
+        }
+
+        let mut out = vec![];
+        go(self, &mut out);
+        out
+    }
+}
+
+fn width(width: i64) -> Result<usize, LakeroadError> {
+    match usize::try_from(width) {
+        Ok(width) if width > 0 => Ok(width),
+        _ => Err(LakeroadError::Malformed(format!("bitwidth {}", width))),
+    }
+}
+
+fn unop(op: &Op, arg: Term) -> Result<Term, LakeroadError> {
+    let op = match op {
+        Op::Not => "bvnot",
+        Op::Neg => "bvneg",
+        _ => return Err(LakeroadError::Malformed(format!("{} isn't unary", op))),
+    };
+    Ok(Term::App {
+        op,
+        args: vec![arg],
+    })
+}
+
+/// `(binop op width lhs rhs)`, where `rhs` is `rhs_width` bits wide, as is
+/// `lhs` if the op is resized.
+fn binop(
+    op: &Op,
+    width: usize,
+    lhs: Term,
+    rhs: Term,
+    rhs_width: usize,
+) -> Result<Term, LakeroadError> {
+    // SMT-LIB's shifts take operands of one width, so a narrower amount is
+    // zero-extended.
+    let rhs = if op.is_shift() && rhs_width < width {
+        rhs.extend(false, width - rhs_width)
+    } else {
+        rhs
+    };
+    // Nor do its arithmetic ops resize, so a resized op's operands are
+    // zero-extended or truncated first.
+    let resize = |term: Term| match rhs_width {
+        w if w < width => term.extend(false, width - w),
+        w if w > width => term.extract(width - 1, 0),
+        _ => term,
+    };
+    let (lhs, rhs) = if op.is_resizing() {
+        (resize(lhs), resize(rhs))
+    } else {
+        (lhs, rhs)
+    };
+    let op = match op {
+        Op::And => "bvand",
+        Op::Or => "bvor",
+        Op::Xor => "bvxor",
+        Op::Add => "bvadd",
+        Op::Sub => "bvsub",
+        Op::Mul => "bvmul",
+        Op::Lsr => "bvlshr",
+        Op::Asr => "bvashr",
+        Op::Eq => {
+            return Ok(Term::Eq {
+                width,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            })
+        }
+        Op::Not | Op::Neg => return Err(LakeroadError::Malformed(format!("{} isn't binary", op))),
+    };
+    Ok(Term::App {
+        op,
+        args: vec![lhs, rhs],
+    })
+}
+
+/// The term `expr` computes, over its variables, in the first cycle, where
+/// `reg`s are zero as in [`crate::eval::eval`]. `apply`s are expanded, with
+/// their arguments bound to holes as in [`crate::eval::eval_instr`].
+pub fn symbolic(expr: &Expr) -> Result<Term, LakeroadError> {
+    symbolic_at(expr, 0, &|name, _| name.to_string())
+}
+
+/// The terms `expr` computes in each of the first `cycles` cycles, over
+/// its variables in each cycle, e.g. `x@0` and `x@1`, as
+/// [`crate::eval::eval_cycles`] evaluates it.
+pub fn symbolic_cycles(expr: &Expr, cycles: usize) -> Result<Vec<Term>, LakeroadError> {
+    (0..cycles).map(|cycle| symbolic_in(expr, cycle)).collect()
+}
+
+/// The term `expr` computes in cycle `cycle`, as [`symbolic_cycles`] does.
+pub fn symbolic_in(expr: &Expr, cycle: usize) -> Result<Term, LakeroadError> {
+    symbolic_at(expr, cycle, &cycle_var)
+}
+
+/// The name of variable `name` in cycle `cycle`, as [`symbolic_cycles`]
+/// names it.
+pub fn cycle_var(name: &str, cycle: usize) -> String {
+    format!("{}@{}", name, cycle)
+}
+
+fn symbolic_at(
+    expr: &Expr,
+    cycle: usize,
+    var: &dyn Fn(&str, usize) -> String,
+) -> Result<Term, LakeroadError> {
+    match expr {
+        Expr::Var { name, width: w } => Ok(Term::Var {
+            name: var(name, cycle),
+            width: width(*w)?,
+        }),
+        Expr::Const { value, width: w } => Ok(Term::Const(BitVec::from_i64(width(*w)?, *value))),
+        Expr::UnOp { op, arg, .. } => unop(op, symbolic_at(arg, cycle, var)?),
+        Expr::BinOp {
+            op,
+            width: w,
+            lhs,
+            rhs,
+        } => binop(
+            op,
+            width(*w)?,
+            symbolic_at(lhs, cycle, var)?,
+            symbolic_at(rhs, cycle, var)?,
+            width(rhs.width())?,
+        ),
+        Expr::Reg { width: w, arg } => match cycle {
+            0 => Ok(Term::Const(BitVec::zero(width(*w)?))),
+            _ => symbolic_at(arg, cycle - 1, var),
+        },
+        Expr::Extract { hi, lo, arg } => match (usize::try_from(*hi), usize::try_from(*lo)) {
+            (Ok(hi), Ok(lo)) if lo <= hi => Ok(symbolic_at(arg, cycle, var)?.extract(hi, lo)),
+            _ => Err(LakeroadError::Malformed(format!("bits {} to {}", hi, lo))),
+        },
+        Expr::Cat { hi, lo } => Ok(Term::app(
+            "concat",
+            vec![symbolic_at(hi, cycle, var)?, symbolic_at(lo, cycle, var)?],
+        )),
+        Expr::Apply { instr, args } => {
+            let args = args
+                .iter()
+                .map(|arg| symbolic_at(arg, cycle, var))
+                .collect::<Result<Vec<_>, _>>()?;
+            symbolic_instr(instr, &args)
+        }
+    }
+}
+
+/// The term `instr` computes when applied to `args`, which are bound to its
+/// holes as in [`crate::eval::eval_instr`].
+pub fn symbolic_instr(instr: &Instr, args: &[Term]) -> Result<Term, LakeroadError> {
+    let holes = instr.ast.num_holes();
+    let mut values = instr
+        .canonical_args
+        .iter()
+        .enumerate()
+        .map(|(hole, canonical)| {
+            let index = if args.len() == holes {
+                hole
+            } else {
+                *canonical as usize
+            };
+            args.get(index).cloned()
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|values| values.len() == holes)
+        .ok_or_else(|| {
+            LakeroadError::Malformed(format!(
+                "an instr with {} holes applied to {} args",
+                holes,
+                args.len()
+            ))
+        })?
+        .into_iter();
+    symbolic_ast(&instr.ast, &mut values)
+}
+
+fn symbolic_ast(ast: &Ast, holes: &mut impl Iterator<Item = Term>) -> Result<Term, LakeroadError> {
+    match ast {
+        Ast::Hole { .. } => holes
+            .next()
+            .ok_or_else(|| LakeroadError::Malformed("too few args".to_string())),
+        Ast::UnOp { op, arg, .. } => unop(op, symbolic_ast(arg, holes)?),
+        Ast::BinOp {
+            op,
+            width: w,
+            lhs,
+            rhs,
+        } => {
+            let lhs = symbolic_ast(lhs, holes)?;
+            let rhs_width = width(rhs.width())?;
+            binop(op, width(*w)?, lhs, symbolic_ast(rhs, holes)?, rhs_width)
+        }
+        Ast::Apply { instr, args } => {
+            let args = args
+                .iter()
+                .map(|arg| symbolic_ast(arg, holes))
+                .collect::<Result<Vec<_>, _>>()?;
+            symbolic_instr(instr, &args)
+        }
+    }
+}
+
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+pub use process::{SmtBackend, SmtSolver};
+
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+mod process {
+    use std::{
+        collections::{HashMap, HashSet},
+        io::{BufRead, BufReader, Write},
+        process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+        sync::Mutex,
+    };
+
+    use egg::RecExpr;
+
+    use super::{symbolic, Term};
+    use crate::{
+        ast::Expr,
+        backend::SynthesisBackend,
+        error::LakeroadError,
+        language::Language,
+        sequential::{k_induction, SequentialVerdict},
+    };
+
+    /// A running SMT solver which reads SMT-LIB from stdin, e.g. `z3 -in`.
+    pub struct SmtSolver {
+        child: Child,
+        stdin: ChildStdin,
+        stdout: BufReader<ChildStdout>,
+        /// The variables declared so far, and their widths.
+        declared: HashMap<String, usize>,
+    }
+
+    impl SmtSolver {
+        /// Starts `z3 -in`.
+        pub fn z3() -> Result<Self, LakeroadError> {
+            Self::spawn(Command::new("z3").arg("-in"))
+        }
+
+        /// Starts a solver with `command`, which must read SMT-LIB from
+        /// stdin and answer on stdout.
+        pub fn spawn(command: &mut Command) -> Result<Self, LakeroadError> {
+            let mut child = command
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()?;
+            let missing = || LakeroadError::Solver("no pipe to the SMT solver".to_string());
+            let stdin = child.stdin.take().ok_or_else(missing)?;
+            let stdout = BufReader::new(child.stdout.take().ok_or_else(missing)?);
+            let mut solver = SmtSolver {
+                child,
+                stdin,
+                stdout,
+                declared: HashMap::new(),
+            };
+            solver.send("(set-logic QF_BV)")?;
+            Ok(solver)
+        }
+
+        fn send(&mut self, command: &str) -> Result<(), LakeroadError> {
+            writeln!(self.stdin, "{}", command)?;
+            Ok(())
+        }
+
+        /// Declares the variables in `vars` not declared yet.
+        fn declare(&mut self, vars: Vec<(String, usize)>) -> Result<(), LakeroadError> {
+            for (name, width) in vars {
+                match self.declared.get(&name) {
+                    Some(w) if *w != width => {
+                        return Err(LakeroadError::Malformed(format!(
+                            "{} was declared {} bits wide, and is now {}",
+                            name, w, width
+                        )))
+                    }
+                    Some(_) => (),
+                    None => {
+                        self.send(&format!("(declare-const |{}| (_ BitVec {}))", name, width))?;
+                        self.declared.insert(name, width);
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        /// Whether `a` and `b` are equal for every value of their
+        /// variables. The query is popped afterwards; declarations stay.
+        pub fn equivalent(&mut self, a: &Term, b: &Term) -> Result<bool, LakeroadError> {
+            self.equivalent_under(&[], a, b)
+        }
+
+        /// As [`equivalent`](Self::equivalent), where the variables named
+        /// in `definitions` stand for their terms, of the given widths,
+        /// rather than for any value. Each definition can use the ones
+        /// before it. Definitions are popped along with the query.
+        pub fn equivalent_under(
+            &mut self,
+            definitions: &[(String, usize, Term)],
+            a: &Term,
+            b: &Term,
+        ) -> Result<bool, LakeroadError> {
+            self.equivalent_assuming(definitions, &[], a, b)
+        }
+
+        /// As [`equivalent_under`](Self::equivalent_under), for only the
+        /// values of the variables which make each pair in `assumptions`
+        /// equal.
+        pub fn equivalent_assuming(
+            &mut self,
+            definitions: &[(String, usize, Term)],
+            assumptions: &[(Term, Term)],
+            a: &Term,
+            b: &Term,
+        ) -> Result<bool, LakeroadError> {
+            let defined = definitions
+                .iter()
+                .map(|(name, _, _)| name.as_str())
+                .collect::<HashSet<_>>();
+            let mut vars = a.vars();
+            vars.extend(b.vars());
+            for (_, _, term) in definitions {
+                vars.extend(term.vars());
+            }
+            for (lhs, rhs) in assumptions {
+                vars.extend(lhs.vars());
+                vars.extend(rhs.vars());
+            }
+            vars.retain(|(name, _)| !defined.contains(name.as_str()));
+            self.declare(vars)?;
+            self.send("(push 1)")?;
+            for (name, width, term) in definitions {
+                self.send(&format!(
+                    "(define-fun |{}| () (_ BitVec {}) {})",
+                    name, width, term
+                ))?;
+            }
+            for (lhs, rhs) in assumptions {
+                self.send(&format!("(assert (= {} {}))", lhs, rhs))?;
+            }
+            self.send(&format!("(assert (not (= {} {})))", a, b))?;
+            self.send("(check-sat)")?;
+            self.send("(pop 1)")?;
+            self.stdin.flush()?;
+            let mut line = String::new();
+            self.stdout.read_line(&mut line)?;
+            match line.trim() {
+                "unsat" => Ok(true),
+                "sat" => Ok(false),
+                other => Err(LakeroadError::Solver(format!(
+                    "the SMT solver answered {:?}",
+                    other
+                ))),
+            }
+        }
+    }
+
+    impl Drop for SmtSolver {
+        fn drop(&mut self) {
+            let _ = self.send("(exit)");
+            let _ = self.stdin.flush();
+            let _ = self.child.wait();
+        }
+    }
+
+    /// Checks equivalence with an [`SmtSolver`], without going through
+    /// Racket. It doesn't know the target hardware, so it can't check
+    /// feasibility.
+    pub struct SmtBackend {
+        solver: Mutex<SmtSolver>,
+    }
+
+    impl SmtBackend {
+        pub fn new(solver: SmtSolver) -> Self {
+            SmtBackend {
+                solver: Mutex::new(solver),
+            }
+        }
+    }
+
+    impl SynthesisBackend for SmtBackend {
+        fn check_feasible(&self, _: &RecExpr<Language>) -> Result<bool, LakeroadError> {
+            Err(LakeroadError::Unsupported(
+                "the SMT backend can't check feasibility".to_string(),
+            ))
+        }
+
+        fn verify_equivalent(
+            &self,
+            a: &RecExpr<Language>,
+            b: &RecExpr<Language>,
+        ) -> Result<bool, LakeroadError> {
+            let (a_expr, b_expr) = (Expr::try_from(a)?, Expr::try_from(b)?);
+            if a_expr.reg_depth().max(b_expr.reg_depth()) > 0 {
+                return self.verify_sequential(a, b, 0);
+            }
+            self.solver
+                .lock()
+                .expect("an SMT query panicked")
+                .equivalent(&symbolic(&a_expr)?, &symbolic(&b_expr)?)
+        }
+
+        fn verify_sequential(
+            &self,
+            spec: &RecExpr<Language>,
+            candidate: &RecExpr<Language>,
+            latency: usize,
+        ) -> Result<bool, LakeroadError> {
+            let (spec, candidate) = (Expr::try_from(spec)?, Expr::try_from(candidate)?);
+            // Enough for induction to always decide; see `k_induction`.
+            let max_k = spec.reg_depth() + latency + candidate.reg_depth() + 1;
+            let mut solver = self.solver.lock().expect("an SMT query panicked");
+            match k_induction(&mut solver, &spec, &candidate, latency, max_k)? {
+                SequentialVerdict::Equivalent { .. } => Ok(true),
+                SequentialVerdict::Differs { .. } => Ok(false),
+                SequentialVerdict::Unknown => Err(LakeroadError::Solver(format!(
+                    "k-induction up to k = {} proved nothing",
+                    max_k
+                ))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use egg::RecExpr;
+
+    use super::*;
+
+    #[test]
+    fn symbolic_terms() {
+        let term = |s: &str| {
+            symbolic(&Expr::try_from(&RecExpr::from_str(s).unwrap()).unwrap())
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            term("(binop asr 4 (var x 4) (const -2 4))"),
+            "(bvashr |x| #b1110)"
+        );
+        assert_eq!(
+            term("(binop lsr 8 (var x 8) (var s 3))"),
+            "(bvlshr |x| ((_ zero_extend 5) |s|))"
+        );
+        assert_eq!(
+            term("(binop eq 2 (unop not 2 (var x 2)) (var y 2))"),
+            "(ite (= (bvnot |x|) |y|) (_ bv1 2) (_ bv0 2))"
+        );
+        // The instr's holes are both bound to `x`.
+        assert_eq!(
+            term(
+                "(apply (instr (binop-ast sub 8 (hole 8) (hole 8)) (canonical-args 0 0)) \
+                 (list (var x 8)))"
+            ),
+            "(bvsub |x| |x|)"
+        );
+    }
+}
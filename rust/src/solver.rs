@@ -0,0 +1,270 @@
+//! Translating expressions into Rosette, for the solver queries made by
+//! [`crate::racket`].
+
+use egg::{Id, RecExpr};
+
+use crate::{
+    error::LakeroadError,
+    language::{Language, Op},
+};
+
+/// The variables of translated terms, with their bitwidths, in the order
+/// they first appear. Queries define and take the variables in this order,
+/// so the same term always makes the same query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols(Vec<(String, usize)>);
+
+impl Symbols {
+    pub fn get(&self, name: &str) -> Option<&usize> {
+        self.0
+            .iter()
+            .find(|(symbol, _)| symbol == name)
+            .map(|(_, width)| width)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The variables' names, in order, e.g. as a function's arguments.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.0.iter().map(|(name, width)| (name.as_str(), *width))
+    }
+
+    /// Adds `name`, unless it's already there. Fails if it is, at another
+    /// width.
+    pub fn insert(&mut self, name: &str, width: usize) -> Result<(), LakeroadError> {
+        match self.get(name) {
+            None => {
+                self.0.push((name.to_string(), width));
+                Ok(())
+            }
+            Some(&other) if other == width => Ok(()),
+            Some(other) => Err(LakeroadError::Malformed(format!(
+                "{} is {} bits wide in one place and {} in another",
+                name, other, width
+            ))),
+        }
+    }
+
+    /// Adds `other`'s variables after these, as [`Symbols::insert`] does.
+    pub fn extend(&mut self, other: &Symbols) -> Result<(), LakeroadError> {
+        other
+            .iter()
+            .try_for_each(|(name, width)| self.insert(name, width))
+    }
+
+    /// A `define-symbolic` form for each variable, one per line.
+    pub fn define_symbolic(&self) -> String {
+        self.iter()
+            .map(|(name, width)| format!("(define-symbolic {} (bitvector {}))", name, width))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Returns the string representing the Racket expression, and its variables
+/// in the order they first appear. Fails on terms with no Racket equivalent,
+/// such as instructions, holes, and `apply`s.
+pub fn to_racket(expr: &RecExpr<Language>, id: Id) -> Result<(String, Symbols), LakeroadError> {
+    let mut symbols = Symbols::default();
+    let racket_string = to_racket_helper(expr, id, &mut symbols)?;
+    Ok((racket_string, symbols))
+}
+
+fn to_racket_helper(
+    expr: &RecExpr<Language>,
+    id: Id,
+    map: &mut Symbols,
+) -> Result<String, LakeroadError> {
+    let malformed = || LakeroadError::Malformed(format!("{:?}", expr[id]));
+    let num = |id: Id| match &expr[id] {
+        Language::Num(v) => Ok(*v),
+        _ => Err(malformed()),
+    };
+    let op = |id: Id| match &expr[id] {
+        Language::Op(op) => Ok(op),
+        _ => Err(malformed()),
+    };
+    match expr[id] {
+        Language::Var([name_id, bw_id]) => match (&expr[name_id], &expr[bw_id]) {
+            (Language::String(v), &Language::Num(bw)) if bw > 0 => {
+                map.insert(v, bw as usize)?;
+                Ok(v.clone())
+            }
+            _ => Err(malformed()),
+        },
+        Language::Const([val_id, bitwidth_id]) => Ok(format!(
+            "(bv {val} {bitwidth})",
+            val = num(val_id)?,
+            bitwidth = num(bitwidth_id)?,
+        )),
+        Language::BinOp([op_id, bw_id, a_id, b_id]) if matches!(op(op_id)?, Op::Eq) => Ok(format!(
+            "(bool->bitvector (bveq {a} {b}) (bitvector {bw}))",
+            a = to_racket_helper(expr, a_id, map)?,
+            b = to_racket_helper(expr, b_id, map)?,
+            bw = num(bw_id)?,
+        )),
+        Language::BinOp([op_id, bw_id, a_id, b_id]) => Ok(format!(
+            "({op} {a} {b})",
+            op = match op(op_id)? {
+                Op::And => "bvand",
+                Op::Or => "bvor",
+                Op::Sub => "bvsub",
+                Op::Xor => "bvxor",
+                Op::Asr => "bvashr",
+                Op::Lsr => "bvlshr",
+                Op::Add => "bvadd",
+                Op::Mul => "bvmul",
+                op => return Err(LakeroadError::Unsupported(format!("binary {}", op))),
+            },
+            // Rosette's ops take operands of one width, so a narrower shift
+            // amount is zero-extended, and a resized op's operands are
+            // zero-extended or truncated.
+            a = match op(op_id)?.is_resizing() {
+                true => resized(expr, a_id, num(bw_id)?, map)?,
+                false => to_racket_helper(expr, a_id, map)?,
+            },
+            b = match op(op_id)?.is_shift() || op(op_id)?.is_resizing() {
+                true => resized(expr, b_id, num(bw_id)?, map)?,
+                false => to_racket_helper(expr, b_id, map)?,
+            }
+        )),
+        Language::UnOp([op_id, _bw_id, arg_id]) => Ok(format!(
+            "({op} {a})",
+            op = match op(op_id)? {
+                Op::Not => "bvnot",
+                Op::Neg => "bvneg",
+                op => return Err(LakeroadError::Unsupported(format!("unary {}", op))),
+            },
+            a = to_racket_helper(expr, arg_id, map)?,
+        )),
+        Language::Extract([hi_id, lo_id, arg_id]) => Ok(format!(
+            "(extract {} {} {})",
+            num(hi_id)?,
+            num(lo_id)?,
+            to_racket_helper(expr, arg_id, map)?
+        )),
+        Language::Cat([hi_id, lo_id]) => Ok(format!(
+            "(concat {} {})",
+            to_racket_helper(expr, hi_id, map)?,
+            to_racket_helper(expr, lo_id, map)?
+        )),
+        ref node => Err(LakeroadError::Unsupported(format!("{:?}", node))),
+    }
+}
+
+/// The Racket expression for the signal `id`, zero-extended or truncated to
+/// `width` bits.
+fn resized(
+    expr: &RecExpr<Language>,
+    id: Id,
+    width: i64,
+    map: &mut Symbols,
+) -> Result<String, LakeroadError> {
+    let arg = to_racket_helper(expr, id, map)?;
+    Ok(match width_of(expr, id)? {
+        w if w < width => format!("(zero-extend {} (bitvector {}))", arg, width),
+        w if w > width => format!("(extract {} 0 {})", width - 1, arg),
+        _ => arg,
+    })
+}
+
+/// The bitwidth of the signal `id`, one of the terms [`to_racket`]
+/// translates.
+fn width_of(expr: &RecExpr<Language>, id: Id) -> Result<i64, LakeroadError> {
+    let num = |id: Id| match &expr[id] {
+        Language::Num(v) => Ok(*v),
+        _ => Err(LakeroadError::Malformed(format!("{:?}", expr[id]))),
+    };
+    match expr[id] {
+        Language::Var([_, bw_id])
+        | Language::Const([_, bw_id])
+        | Language::UnOp([_, bw_id, _])
+        | Language::BinOp([_, bw_id, _, _]) => num(bw_id),
+        Language::Extract([hi_id, lo_id, _]) => Ok(num(hi_id)? - num(lo_id)? + 1),
+        Language::Cat([hi_id, lo_id]) => Ok(width_of(expr, hi_id)? + width_of(expr, lo_id)?),
+        ref node => Err(LakeroadError::Unsupported(format!("{:?}", node))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::language::{typecheck_expr, ExprTypeError, TypeError};
+
+    use super::*;
+
+    #[test]
+    fn ceil_avg_to_racket() {
+        let expr = &RecExpr::from_str(
+            "(binop sub 8 (binop or 8 (var x 8) (var y 8)) (binop asr 8 (binop xor 8 (var x 8) (var y 8)) (const 1 8)))",
+        )
+        .unwrap();
+
+        let (expr, map) = to_racket(expr, (expr.as_ref().len() - 1).into()).unwrap();
+        assert_eq!(*map.get("x").unwrap(), 8);
+        assert_eq!(*map.get("y").unwrap(), 8);
+        assert_eq!(expr, "(bvsub (bvor x y) (bvashr (bvxor x y) (bv 1 8)))");
+    }
+
+    /// Variables come in the order they first appear, whatever their names.
+    #[test]
+    fn to_racket_variables_in_first_occurrence_order() {
+        let expr = &RecExpr::from_str(
+            "(binop add 8 (var z 8) (binop sub 8 (var a 8) (binop xor 8 (var m 8) (var z 8))))",
+        )
+        .unwrap();
+        let (_, map) = to_racket(expr, (expr.as_ref().len() - 1).into()).unwrap();
+        assert_eq!(map.names().collect::<Vec<_>>(), vec!["z", "a", "m"]);
+        assert_eq!(
+            map.define_symbolic(),
+            "(define-symbolic z (bitvector 8))\n\
+             (define-symbolic a (bitvector 8))\n\
+             (define-symbolic m (bitvector 8))"
+        );
+    }
+
+    #[test]
+    fn narrow_shift_amounts_to_racket() {
+        let expr = &RecExpr::from_str("(binop lsr 8 (var x 8) (extract 2 0 (var s 8)))").unwrap();
+        let (expr, map) = to_racket(expr, (expr.as_ref().len() - 1).into()).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(
+            expr,
+            "(bvlshr x (zero-extend (extract 2 0 s) (bitvector 8)))"
+        );
+    }
+
+    #[test]
+    fn to_racket_rejects_instrs() {
+        let expr =
+            &RecExpr::from_str("(instr (binop-ast and 8 (hole 8) (hole 8)) (canonical-args 0 1))")
+                .unwrap();
+        assert!(matches!(
+            to_racket(expr, (expr.as_ref().len() - 1).into()),
+            Err(LakeroadError::Unsupported(_))
+        ));
+        let expr = &RecExpr::from_str("(unop and 8 (var x 8))").unwrap();
+        assert!(matches!(
+            to_racket(expr, (expr.as_ref().len() - 1).into()),
+            Err(LakeroadError::Unsupported(_))
+        ));
+        assert!(matches!(
+            typecheck_expr(&RecExpr::default()),
+            Err(ExprTypeError {
+                error: TypeError::Empty,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+//! Splitting operations wider than a target's instructions or primitives.
+//!
+//! Programs are often wider than the instructions they should be built from,
+//! e.g. a 64-bit add on a target whose instructions take 8 bits. Every
+//! operator in the language has a single width, so [`split_rules`] rewrite
+//! a wide operation into operations on `extract`ed chunks of its operands,
+//! `cat`ed back together. Bitwise operators split chunk by chunk; adds and
+//! subtracts carry (or borrow) from each chunk into the next, computed from
+//! the chunk's operands and result. A rewrite splits off the low chunk, and
+//! the rest is split again, so a wide operation ends up in chunks of
+//! `chunk` bits, with the leftover bits on top. [`split`] extracts the
+//! program whose operators are all narrow enough.
+//!
+//! Multiplies aren't split here, since the chunks' products are twice as
+//! wide as the chunks; [`crate::arch::Netlist::map`] splits wide multiplies
+//! into partial products on DSPs instead.
+
+use egg::{
+    Applier, CostFunction, EGraph, Extractor, Id, Language as LanguageTrait, Pattern, RecExpr,
+    Rewrite, Runner, Subst, Var,
+};
+
+use crate::{
+    analysis::{LanguageAnalysis, LanguageAnalysisData::*},
+    language::{Language, Op},
+};
+
+/// Adds the nodes of the split program to an e-graph.
+struct Builder<'a> {
+    egraph: &'a mut EGraph<Language, LanguageAnalysis>,
+}
+
+impl Builder<'_> {
+    fn num(&mut self, n: i64) -> Id {
+        self.egraph.add(Language::Num(n))
+    }
+
+    fn zero(&mut self, width: i64) -> Id {
+        let (value, width) = (self.num(0), self.num(width));
+        self.egraph.add(Language::Const([value, width]))
+    }
+
+    fn extract(&mut self, hi: i64, lo: i64, arg: Id) -> Id {
+        let (hi, lo) = (self.num(hi), self.num(lo));
+        self.egraph.add(Language::Extract([hi, lo, arg]))
+    }
+
+    fn cat(&mut self, hi: Id, lo: Id) -> Id {
+        self.egraph.add(Language::Cat([hi, lo]))
+    }
+
+    fn unop(&mut self, op: Op, width: i64, arg: Id) -> Id {
+        let (op, width) = (self.egraph.add(Language::Op(op)), self.num(width));
+        self.egraph.add(Language::UnOp([op, width, arg]))
+    }
+
+    fn binop(&mut self, op: Op, width: i64, lhs: Id, rhs: Id) -> Id {
+        let (op, width) = (self.egraph.add(Language::Op(op)), self.num(width));
+        self.egraph.add(Language::BinOp([op, width, lhs, rhs]))
+    }
+
+    /// `bit`, a single bit, zero-extended to `width` bits.
+    fn zero_extend(&mut self, bit: Id, width: i64) -> Id {
+        match width {
+            1 => bit,
+            _ => {
+                let zero = self.zero(width - 1);
+                self.cat(zero, bit)
+            }
+        }
+    }
+}
+
+/// Splits `(binop op bw a b)`, or `(unop op bw a)`, off its low `chunk` bits
+/// when `bw` is wider.
+struct Split {
+    op: Op,
+    chunk: i64,
+    bw: Var,
+    a: Var,
+    b: Option<Var>,
+}
+
+impl Applier<Language, LanguageAnalysis> for Split {
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph<Language, LanguageAnalysis>,
+        eclass: Id,
+        subst: &Subst,
+        _searcher_ast: Option<&egg::PatternAst<Language>>,
+        _rule_name: egg::Symbol,
+    ) -> Vec<Id> {
+        let width = match egraph[subst[self.bw]].data {
+            Num(width) if width > self.chunk => width,
+            _ => return vec![],
+        };
+        // A resized op's operands aren't as wide as it.
+        match egraph[subst[self.a]].data {
+            Signal { width: a, .. } if a as i64 == width => (),
+            _ => return vec![],
+        }
+        let (chunk, high) = (self.chunk, width - self.chunk);
+        let mut n = Builder {
+            egraph: &mut *egraph,
+        };
+        let (a_lo, a_hi) = (
+            n.extract(chunk - 1, 0, subst[self.a]),
+            n.extract(width - 1, chunk, subst[self.a]),
+        );
+        let split = match self.b {
+            None => {
+                let (lo, hi) = (
+                    n.unop(self.op.clone(), chunk, a_lo),
+                    n.unop(self.op.clone(), high, a_hi),
+                );
+                n.cat(hi, lo)
+            }
+            Some(rhs) => {
+                let (b_lo, b_hi) = (
+                    n.extract(chunk - 1, 0, subst[rhs]),
+                    n.extract(width - 1, chunk, subst[rhs]),
+                );
+                let lo = n.binop(self.op.clone(), chunk, a_lo, b_lo);
+                let hi = n.binop(self.op.clone(), high, a_hi, b_hi);
+                let hi = match self.op {
+                    // The carry out of the low chunk is the top bit of
+                    // `(a & b) | ((a | b) & ~sum)`.
+                    Op::Add => {
+                        let both = n.binop(Op::And, chunk, a_lo, b_lo);
+                        let either = n.binop(Op::Or, chunk, a_lo, b_lo);
+                        let not_sum = n.unop(Op::Not, chunk, lo);
+                        let carried = n.binop(Op::And, chunk, either, not_sum);
+                        let carries = n.binop(Op::Or, chunk, both, carried);
+                        let carry = n.extract(chunk - 1, chunk - 1, carries);
+                        let carry = n.zero_extend(carry, high);
+                        n.binop(Op::Add, high, hi, carry)
+                    }
+                    // The borrow out of the low chunk is the top bit of
+                    // `(~a & b) | ((~a | b) & difference)`.
+                    Op::Sub => {
+                        let not_a = n.unop(Op::Not, chunk, a_lo);
+                        let only_b = n.binop(Op::And, chunk, not_a, b_lo);
+                        let either = n.binop(Op::Or, chunk, not_a, b_lo);
+                        let borrowed = n.binop(Op::And, chunk, either, lo);
+                        let borrows = n.binop(Op::Or, chunk, only_b, borrowed);
+                        let borrow = n.extract(chunk - 1, chunk - 1, borrows);
+                        let borrow = n.zero_extend(borrow, high);
+                        n.binop(Op::Sub, high, hi, borrow)
+                    }
+                    _ => hi,
+                };
+                n.cat(hi, lo)
+            }
+        };
+        egraph.union(eclass, split);
+        vec![eclass, split]
+    }
+}
+
+/// Simplifies an `extract` of a `cat` which reads only one of its halves,
+/// and an `extract` of a whole signal.
+struct ExtractCat {
+    hi: Var,
+    lo: Var,
+    arg: Var,
+}
+
+impl Applier<Language, LanguageAnalysis> for ExtractCat {
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph<Language, LanguageAnalysis>,
+        eclass: Id,
+        subst: &Subst,
+        _searcher_ast: Option<&egg::PatternAst<Language>>,
+        _rule_name: egg::Symbol,
+    ) -> Vec<Id> {
+        let width = |egraph: &EGraph<Language, LanguageAnalysis>, id: Id| match egraph[id].data {
+            Signal { width, .. } => Some(width as i64),
+            _ => None,
+        };
+        let (hi, lo) = match (&egraph[subst[self.hi]].data, &egraph[subst[self.lo]].data) {
+            (Num(hi), Num(lo)) => (*hi, *lo),
+            _ => return vec![],
+        };
+        let arg = subst[self.arg];
+        if lo == 0 && width(egraph, arg) == Some(hi + 1) {
+            egraph.union(eclass, arg);
+            return vec![eclass, arg];
+        }
+        let mut ids = vec![];
+        for node in egraph[arg].nodes.clone() {
+            if let Language::Cat([high, low]) = node {
+                let low_width = match width(egraph, low) {
+                    Some(width) => width,
+                    None => continue,
+                };
+                let mut n = Builder {
+                    egraph: &mut *egraph,
+                };
+                let read = if hi < low_width {
+                    n.extract(hi, lo, low)
+                } else if lo >= low_width {
+                    n.extract(hi - low_width, lo - low_width, high)
+                } else {
+                    continue;
+                };
+                egraph.union(eclass, read);
+                ids.push(read);
+            }
+        }
+        if !ids.is_empty() {
+            ids.insert(0, eclass);
+        }
+        ids
+    }
+}
+
+/// Rewrites which split `and`s, `or`s, `xor`s, `add`s, `sub`s, and `not`s
+/// wider than `chunk` bits into `chunk`-bit operations on `extract`s of
+/// their operands, and which read `extract`s of the resulting `cat`s from
+/// the halves they come from.
+pub fn split_rules(chunk: usize) -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    let var = |name: &str| -> Var { name.parse().unwrap() };
+    let mut rules = vec![];
+    for op in [Op::And, Op::Or, Op::Xor, Op::Add, Op::Sub] {
+        let searcher: Pattern<Language> = format!("(binop {} ?bw ?a ?b)", op).parse().unwrap();
+        let applier = Split {
+            op: op.clone(),
+            chunk: chunk as i64,
+            bw: var("?bw"),
+            a: var("?a"),
+            b: Some(var("?b")),
+        };
+        rules.push(Rewrite::new(format!("split-{}", op), searcher, applier).unwrap());
+    }
+    let searcher: Pattern<Language> = "(unop not ?bw ?a)".parse().unwrap();
+    let applier = Split {
+        op: Op::Not,
+        chunk: chunk as i64,
+        bw: var("?bw"),
+        a: var("?a"),
+        b: None,
+    };
+    rules.push(Rewrite::new("split-not", searcher, applier).unwrap());
+    let searcher: Pattern<Language> = "(extract ?hi ?lo ?arg)".parse().unwrap();
+    let applier = ExtractCat {
+        hi: var("?hi"),
+        lo: var("?lo"),
+        arg: var("?arg"),
+    };
+    rules.push(Rewrite::new("extract-cat", searcher, applier).unwrap());
+    rules
+}
+
+/// Prefers programs whose `unop`s and `binop`s are at most `chunk` bits
+/// wide, and then smaller ones.
+struct NarrowCost<'a> {
+    egraph: &'a EGraph<Language, LanguageAnalysis>,
+    chunk: i64,
+}
+
+impl CostFunction<Language> for NarrowCost<'_> {
+    /// The total width of operators wider than `chunk`, and the number of
+    /// nodes.
+    type Cost = (i64, usize);
+
+    fn cost<C>(&mut self, enode: &Language, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        let wide = match enode {
+            Language::UnOp([_, width, _]) | Language::BinOp([_, width, _, _]) => {
+                match self.egraph[*width].data {
+                    Num(width) if width > self.chunk => width,
+                    _ => 0,
+                }
+            }
+            _ => 0,
+        };
+        enode.children().iter().fold((wide, 1), |(wide, size), id| {
+            let (w, s) = costs(*id);
+            (wide + w, size + s)
+        })
+    }
+}
+
+/// `expr` with its operations split into `chunk`-bit ones by
+/// [`split_rules`], wherever they can be.
+pub fn split(expr: &RecExpr<Language>, chunk: usize) -> RecExpr<Language> {
+    let runner = Runner::default().with_expr(expr).run(&split_rules(chunk));
+    let cost = NarrowCost {
+        egraph: &runner.egraph,
+        chunk: chunk as i64,
+    };
+    Extractor::new(&runner.egraph, cost)
+        .find_best(runner.roots[0])
+        .1
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{
+        ast::Expr,
+        equiv::{shared_vars, test_vectors},
+        eval::eval_cycles,
+    };
+
+    #[test]
+    fn split_programs_are_equivalent() {
+        let program = RecExpr::from_str(
+            "(binop add 20 (binop xor 20 (var a 20) (var b 20)) \
+             (binop sub 20 (var c 20) (unop not 20 (var a 20))))",
+        )
+        .unwrap();
+        let split = split(&program, 8);
+        // 20 bits split into chunks of 8, 8, and 4.
+        assert!(split.as_ref().iter().all(|node| match node {
+            Language::UnOp([_, width, _]) | Language::BinOp([_, width, _, _]) => {
+                matches!(split[*width], Language::Num(width) if width <= 8)
+            }
+            _ => true,
+        }));
+        let (program, split) = (
+            Expr::try_from(&program).unwrap(),
+            Expr::try_from(&split).unwrap(),
+        );
+        let vectors = test_vectors(&shared_vars(&program, &split).unwrap(), 64, 0);
+        assert_eq!(
+            eval_cycles(&program, &vectors).unwrap(),
+            eval_cycles(&split, &vectors).unwrap()
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Superoptimization over a fixed ISA.
+//!
+//! Where [`crate::target::Target::implement`] finds implementations by
+//! rewriting, a [`Superoptimizer`] searches for them directly: it enumerates
+//! straight-line programs of one, two, three, ... `apply`s of the ISA's
+//! instructions, each taking the target's variables and constants or the
+//! results of earlier steps as operands, and returns the first whose last
+//! step a backend proves computes the target. Since the length bound only
+//! grows, that program is a shortest one. It's returned as a DAG: a step
+//! several others read appears once. Most programs are rejected on test
+//! vectors (see [`crate::equiv`]) without asking the backend.
+
+use egg::{Id, RecExpr};
+
+use crate::{
+    ast::{Expr, Instr},
+    backend::SynthesisBackend,
+    bitvec::BitVec,
+    determinism::DEFAULT_SEED,
+    equiv::{shared_vars, test_vectors, TestVector},
+    error::LakeroadError,
+    eval::{eval_expr, eval_instr},
+    language::Language,
+};
+
+/// Searches for the shortest program in an ISA's instructions computing a
+/// target expression.
+pub struct Superoptimizer {
+    isa: Vec<Instr>,
+    max_length: usize,
+    vectors: usize,
+    seed: u64,
+}
+
+/// An ISA instruction, and the width of each of its distinct operands.
+struct Signature<'a> {
+    instr: &'a Instr,
+    operands: Vec<i64>,
+}
+
+/// An operand available to the next step: a variable or constant of the
+/// target, or an earlier step, and its values on the test vectors.
+struct Operand {
+    width: i64,
+    values: Vec<BitVec>,
+    /// How many later steps read it.
+    uses: usize,
+}
+
+/// The state of a search for one target.
+struct Search<'a, B> {
+    signatures: Vec<Signature<'a>>,
+    target: &'a RecExpr<Language>,
+    width: i64,
+    expected: Vec<BitVec>,
+    backend: &'a B,
+    /// The target's leaves, followed by a step per `apply`.
+    pool: Vec<Operand>,
+    leaves: Vec<Expr>,
+    /// Each step's instruction and the operands it takes from `pool`.
+    steps: Vec<(usize, Vec<usize>)>,
+}
+
+impl Superoptimizer {
+    pub fn new(isa: Vec<Instr>) -> Self {
+        Superoptimizer {
+            isa,
+            max_length: 3,
+            vectors: 64,
+            seed: DEFAULT_SEED,
+        }
+    }
+
+    /// The most `apply`s to try.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// How many test vectors a program must pass before the backend is
+    /// asked about it.
+    pub fn with_vectors(mut self, vectors: usize) -> Self {
+        self.vectors = vectors;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// A shortest program computing `target` as `backend` proves it, or
+    /// `None` if none takes at most the maximum length. Each step is an
+    /// `apply`, and the program's root is the last.
+    pub fn superoptimize(
+        &self,
+        target: &RecExpr<Language>,
+        backend: &impl SynthesisBackend,
+    ) -> Result<Option<RecExpr<Language>>, LakeroadError> {
+        let expr = Expr::try_from(target)?;
+        let vectors = test_vectors(&shared_vars(&expr, &expr)?, self.vectors, self.seed);
+        let mut leaves = vec![];
+        for (name, width) in expr.vars() {
+            leaves.push(Expr::Var { name, width });
+        }
+        constants(&expr, &mut leaves);
+        let pool = leaves
+            .iter()
+            .map(|leaf| {
+                Ok(Operand {
+                    width: leaf.width(),
+                    values: evaluate(leaf, &vectors)?,
+                    uses: 0,
+                })
+            })
+            .collect::<Result<Vec<_>, LakeroadError>>()?;
+        let mut search = Search {
+            signatures: self.isa.iter().map(signature).collect::<Result<_, _>>()?,
+            target,
+            width: expr.width(),
+            expected: evaluate(&expr, &vectors)?,
+            backend,
+            pool,
+            leaves,
+            steps: vec![],
+        };
+        for length in 1..=self.max_length {
+            if let Some(found) = search.extend(length)? {
+                return Ok(Some(found));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The widths of `instr`'s distinct operands, in canonical order.
+fn signature(instr: &Instr) -> Result<Signature, LakeroadError> {
+    let holes = instr.ast.hole_widths();
+    let arity = instr
+        .canonical_args
+        .iter()
+        .map(|a| a + 1)
+        .max()
+        .unwrap_or(0);
+    let operands = (0..arity)
+        .map(|arg| {
+            instr
+                .canonical_args
+                .iter()
+                .zip(&holes)
+                .find(|(canonical, _)| **canonical == arg)
+                .map(|(_, width)| *width)
+                .ok_or_else(|| {
+                    LakeroadError::Malformed(format!(
+                        "canonical args {:?} skip {}",
+                        instr.canonical_args, arg
+                    ))
+                })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Signature { instr, operands })
+}
+
+/// Pushes the distinct constants of `expr` onto `out`.
+fn constants(expr: &Expr, out: &mut Vec<Expr>) {
+    match expr {
+        Expr::Const { .. } => {
+            if !out.contains(expr) {
+                out.push(expr.clone())
+            }
+        }
+        Expr::Var { .. } => (),
+        Expr::UnOp { arg, .. } | Expr::Reg { arg, .. } | Expr::Extract { arg, .. } => {
+            constants(arg, out)
+        }
+        Expr::BinOp { lhs, rhs, .. } | Expr::Cat { hi: lhs, lo: rhs } => {
+            constants(lhs, out);
+            constants(rhs, out);
+        }
+        Expr::Apply { args, .. } => args.iter().for_each(|arg| constants(arg, out)),
+    }
+}
+
+fn evaluate(expr: &Expr, vectors: &[TestVector]) -> Result<Vec<BitVec>, LakeroadError> {
+    vectors
+        .iter()
+        .map(|vector| eval_expr(expr, vector))
+        .collect()
+}
+
+impl<'a, B: SynthesisBackend> Search<'a, B> {
+    /// Adds `remaining` more steps to the program so far, returning the
+    /// program once it's proven to compute the target.
+    fn extend(&mut self, remaining: usize) -> Result<Option<RecExpr<Language>>, LakeroadError> {
+        for index in 0..self.signatures.len() {
+            let signature = &self.signatures[index];
+            if remaining == 1 && signature.instr.ast.width() != self.width {
+                continue;
+            }
+            let choices = signature
+                .operands
+                .iter()
+                .map(|width| {
+                    (0..self.pool.len())
+                        .filter(|i| self.pool[*i].width == *width)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            if choices.iter().any(Vec::is_empty) {
+                continue;
+            }
+            // Every tuple of operands, counting in a mixed radix.
+            let mut digits = vec![0; choices.len()];
+            loop {
+                let operands = digits
+                    .iter()
+                    .zip(&choices)
+                    .map(|(digit, choice)| choice[*digit])
+                    .collect::<Vec<_>>();
+                if let Some(found) = self.try_step(index, operands, remaining)? {
+                    return Ok(Some(found));
+                }
+                match digits
+                    .iter()
+                    .zip(&choices)
+                    .position(|(digit, choice)| digit + 1 < choice.len())
+                {
+                    Some(i) => {
+                        digits[i] += 1;
+                        digits[..i].fill(0);
+                    }
+                    None => break,
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Adds a step applying the `index`th instruction to `operands`, and
+    /// either checks it against the target, if it's the last, or extends
+    /// the program past it.
+    fn try_step(
+        &mut self,
+        index: usize,
+        operands: Vec<usize>,
+        remaining: usize,
+    ) -> Result<Option<RecExpr<Language>>, LakeroadError> {
+        // The same step twice computes the same value twice.
+        if self
+            .steps
+            .iter()
+            .any(|(i, step)| *i == index && *step == operands)
+        {
+            return Ok(None);
+        }
+        let instr = self.signatures[index].instr;
+        let values = (0..self.expected.len())
+            .map(|vector| {
+                let args = operands
+                    .iter()
+                    .map(|operand| self.pool[*operand].values[vector].clone())
+                    .collect::<Vec<_>>();
+                eval_instr(instr, &args)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.steps.push((index, operands.clone()));
+
+        let found = if remaining == 1 {
+            // A step nothing reads could be dropped, so a shorter program
+            // was already tried.
+            let leaves = self.leaves.len();
+            let unused = self.pool[leaves..]
+                .iter()
+                .enumerate()
+                .any(|(step, operand)| operand.uses == 0 && !operands.contains(&(leaves + step)));
+            if values != self.expected || unused {
+                None
+            } else {
+                let program = self.program();
+                match self.backend.verify_equivalent(self.target, &program)? {
+                    true => Some(program),
+                    false => None,
+                }
+            }
+        } else {
+            for operand in &operands {
+                self.pool[*operand].uses += 1;
+            }
+            self.pool.push(Operand {
+                width: instr.ast.width(),
+                values,
+                uses: 0,
+            });
+            let found = self.extend(remaining - 1)?;
+            self.pool.pop();
+            for operand in &operands {
+                self.pool[*operand].uses -= 1;
+            }
+            found
+        };
+        self.steps.pop();
+        Ok(found)
+    }
+
+    /// The steps so far, each an `apply` of the operands before it.
+    fn program(&self) -> RecExpr<Language> {
+        let mut out = RecExpr::default();
+        let mut ids: Vec<Id> = self
+            .leaves
+            .iter()
+            .map(|leaf| leaf.add_to(&mut out))
+            .collect();
+        for (index, operands) in &self.steps {
+            let instr = self.signatures[*index].instr.add_to(&mut out);
+            let args = out.add(Language::List(
+                operands.iter().map(|operand| ids[*operand]).collect(),
+            ));
+            ids.push(out.add(Language::Apply([instr, args])));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::equiv::{equiv_exhaustive, Exhaustive};
+
+    #[test]
+    fn shortest_programs_of_nands() {
+        let nand = RecExpr::from_str(
+            "(instr (unop-ast not 8 (binop-ast and 8 (hole 8) (hole 8))) (canonical-args 0 1))",
+        )
+        .unwrap();
+        let superoptimizer = Superoptimizer::new(vec![Instr::try_from(&nand).unwrap()]);
+        let applies = |program: &RecExpr<Language>| {
+            program
+                .as_ref()
+                .iter()
+                .filter(|node| matches!(node, Language::Apply(_)))
+                .count()
+        };
+
+        // An and is a nand of a nand with itself.
+        let and = RecExpr::from_str("(binop and 8 (var x 8) (var y 8))").unwrap();
+        let found = superoptimizer
+            .superoptimize(&and, &Exhaustive)
+            .unwrap()
+            .unwrap();
+        assert_eq!(applies(&found), 2);
+        assert!(equiv_exhaustive(&and, &found).unwrap().is_none());
+
+        // An or takes three: a nand of each operand's negation.
+        let or = RecExpr::from_str("(binop or 8 (var x 8) (var y 8))").unwrap();
+        let found = superoptimizer
+            .superoptimize(&or, &Exhaustive)
+            .unwrap()
+            .unwrap();
+        assert_eq!(applies(&found), 3);
+        assert!(equiv_exhaustive(&or, &found).unwrap().is_none());
+        assert_eq!(
+            superoptimizer
+                .with_max_length(2)
+                .superoptimize(&or, &Exhaustive)
+                .unwrap(),
+            None
+        );
+    }
+}
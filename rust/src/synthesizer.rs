@@ -0,0 +1,1403 @@
+//! The full pipeline behind a single builder: e-graph construction,
+//! rewriting, candidate extraction, verification, and ISA selection.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    rc::Rc,
+    time::Duration,
+};
+
+use egg::{EGraph, Id, RecExpr, Rewrite, Runner, StopReason};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::LanguageAnalysis,
+    arch::{
+        cost::{extract_mapped, PrimitiveWeights},
+        Architecture, PrimitiveCount,
+    },
+    backend::{FnBackend, SynthesisBackend},
+    cancel::CancellationToken,
+    checkpoint::{Checkpoint, CheckpointOptions, ExplorationState, RunIdentity},
+    determinism::DEFAULT_SEED,
+    egglog::{egraph_from_egglog, egraph_to_egglog},
+    emit::to_listing,
+    error::LakeroadError,
+    extract::{find_isa_instructions, matched_sites, CoverageIndex},
+    isa::{
+        compile_program, instr_size, is_hole_instr, prioritize_candidates, program_cost,
+        top_k_isas, total_cost, Isa,
+    },
+    language::{instr_id, Language},
+    lut::{lut_rules, LutBackend},
+    metrics,
+    profile::{intervals, Profile, Timer},
+    program_set::ProgramSet,
+    progress::{Phase, ProgressEvent, ProgressObserver},
+    prune::{prune, pruning_hook, CandidateConstraints, OperandPorts},
+    rewrites::{
+        canonicalize, fuse_op, introduce_hole_op_both, introduce_hole_op_left,
+        introduce_hole_op_resized, introduce_hole_op_right, introduce_hole_var,
+        known_bits_ast_rules, macro_op_rules, simplify_concat, unary0, unary1,
+        zero_extend_shift_amounts,
+    },
+    trivial::{TrivialFilter, Triviality},
+};
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use crate::{
+    parallel::{explore_programs, merge_egraph},
+    profile::longest,
+};
+
+/// The rules used when none are given.
+pub fn default_rules() -> Vec<Rewrite<Language, LanguageAnalysis>> {
+    let mut rules = vec![
+        introduce_hole_var(),
+        fuse_op(),
+        introduce_hole_op_both(),
+        introduce_hole_op_left(),
+        introduce_hole_op_right(),
+        zero_extend_shift_amounts(),
+        simplify_concat(),
+        unary0(),
+        unary1(),
+        canonicalize(),
+    ];
+    rules.extend(introduce_hole_op_resized());
+    rules.extend(known_bits_ast_rules());
+    rules
+}
+
+/// How ISAs are scored: the weighted number of instructions needed to
+/// implement the programs, plus a fixed cost per instruction in the ISA
+/// (e.g. an area estimate). With an architecture (see
+/// [`Synthesizer::with_architecture`]), each program's implementation is
+/// extracted by its `primitives`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CostModel {
+    pub per_instruction: f64,
+    pub primitives: PrimitiveWeights,
+}
+
+impl CostModel {
+    pub fn score(&self, program_cost: f64, isa: &Isa) -> f64 {
+        program_cost + self.per_instruction * isa.len() as f64
+    }
+}
+
+/// What happened during a run. Serializes to the JSON report written by
+/// [`RunReport::to_json`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunReport {
+    pub seed: u64,
+    pub programs: usize,
+    /// The programs' names, in the order they were added.
+    pub program_names: Vec<String>,
+    pub iterations: usize,
+    pub iteration_stats: Vec<IterationReport>,
+    /// Why rewriting stopped, if it did before saturating.
+    pub stop_reason: Option<String>,
+    /// Whether the time budget (see [`Synthesizer::with_time_budget`]) ran
+    /// out before rewriting saturated or every candidate was checked, so
+    /// that the ISA is the best among those found in time.
+    #[serde(default)]
+    pub incomplete: bool,
+    pub egraph_nodes: usize,
+    pub egraph_classes: usize,
+    /// Candidate instructions found in the e-graph.
+    pub candidates: usize,
+    /// Candidates the backend accepted.
+    pub verified: usize,
+    /// The backend's verdict on each candidate, in the order checked.
+    pub verdicts: Vec<VerdictReport>,
+    /// Accepted candidates dropped as trivial before selection (see
+    /// [`Synthesizer::with_trivial_filter`]).
+    #[serde(default)]
+    pub trivial: Vec<TrivialReport>,
+    /// The score of the selected ISA under the cost model.
+    pub score: f64,
+    /// The selected `instr`s.
+    pub isa: Vec<String>,
+    /// The selected `instr`s' [`Instr::stable_id`]s, which name the same
+    /// instructions across runs.
+    ///
+    /// [`Instr::stable_id`]: crate::ast::Instr::stable_id
+    #[serde(default)]
+    pub isa_ids: Vec<String>,
+    /// How each program is implemented by the selected ISA.
+    pub coverage: Vec<CoverageReport>,
+    /// How long each phase took.
+    #[serde(default)]
+    pub profile: Profile,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IterationReport {
+    /// The e-graph's size at the start of the iteration.
+    pub egraph_nodes: usize,
+    pub egraph_classes: usize,
+    /// How many times rules were applied.
+    pub applied: usize,
+    /// How many times each rule was applied, by name.
+    #[serde(default)]
+    pub rules: BTreeMap<String, usize>,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VerdictReport {
+    pub instr: String,
+    /// The instr's [`crate::ast::Instr::stable_id`].
+    #[serde(default)]
+    pub id: String,
+    pub accepted: bool,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrivialReport {
+    pub instr: String,
+    /// The instr's [`crate::ast::Instr::stable_id`].
+    pub id: String,
+    pub kind: Triviality,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoverageReport {
+    pub program: String,
+    pub weight: f64,
+    /// The number of instructions the program takes, as in
+    /// [`program_cost`].
+    pub cost: Option<usize>,
+    /// Indices into [`RunReport::isa`] of the instructions the program uses.
+    pub instructions: Vec<usize>,
+    /// The primitives the program's implementation takes on the
+    /// architecture, if there is one and the implementation maps onto it.
+    #[serde(default)]
+    pub primitives: Option<PrimitiveCount>,
+    /// Where in the program each of its instructions can be used.
+    #[serde(default)]
+    pub sites: Vec<SiteReport>,
+    /// The program compiled to the ISA, as an assembly-like listing (see
+    /// [`to_listing`]).
+    #[serde(default)]
+    pub listing: Option<String>,
+}
+
+/// A subexpression of a program which one of the ISA's instructions can
+/// implement.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SiteReport {
+    /// Index into [`RunReport::isa`] of the instruction.
+    pub instruction: usize,
+    pub eclass: usize,
+    /// The subexpression, in the program's operators.
+    pub source: String,
+}
+
+impl From<&egg::Iteration<()>> for IterationReport {
+    fn from(iteration: &egg::Iteration<()>) -> Self {
+        IterationReport {
+            egraph_nodes: iteration.egraph_nodes,
+            egraph_classes: iteration.egraph_classes,
+            applied: iteration.applied.values().sum(),
+            rules: iteration
+                .applied
+                .iter()
+                .map(|(rule, n)| (rule.to_string(), *n))
+                .collect(),
+            seconds: iteration.total_time,
+        }
+    }
+}
+
+impl RunReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports always serialize")
+    }
+}
+
+/// Runs `f`, returning how many seconds it took. WebAssembly builds have no
+/// clock, so there it always takes zero seconds.
+pub(crate) fn timed<T>(f: impl FnOnce() -> T) -> (T, f64) {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let start = std::time::Instant::now();
+        let out = f();
+        (out, start.elapsed().as_secs_f64())
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        (f(), 0.0)
+    }
+}
+
+/// The e-graph after rewriting and the candidates extracted from it, before
+/// any are checked by the backend.
+pub struct Exploration {
+    pub egraph: EGraph<Language, LanguageAnalysis>,
+    /// Each program's root, paired with its weight.
+    pub roots: Vec<(Id, f64)>,
+    /// Candidate `instr`s other than the bare hole, with their eclasses, in
+    /// the order they're checked (see [`prioritize_candidates`]).
+    pub candidates: Vec<(Id, RecExpr<Language>)>,
+    /// The report so far; `verified` and `score` are still zero.
+    pub report: RunReport,
+}
+
+/// A candidate as listed by [`Synthesizer::dry_run`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CandidateSummary {
+    pub instr: String,
+    /// The instr's [`crate::ast::Instr::stable_id`].
+    pub id: String,
+    /// The number of holes and operators in the instr's AST.
+    pub size: usize,
+    /// The programs the candidate appears in, by name.
+    pub programs: Vec<String>,
+    /// The score of the ISA holding only this candidate, or `None` if the
+    /// candidate can't implement every program by itself.
+    pub predicted_cost: Option<f64>,
+}
+
+/// The outcome of a successful run. The ISA's ids refer to `egraph`.
+pub struct SynthesisResult {
+    pub egraph: EGraph<Language, LanguageAnalysis>,
+    pub isa: Isa,
+    /// The selected `instr`s, in the order of `isa.instructions`.
+    pub instructions: Vec<RecExpr<Language>>,
+    pub report: RunReport,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SynthesisError {
+    NoPrograms,
+    /// No ISA within the instruction limit implements every program.
+    NoIsa {
+        report: Box<RunReport>,
+    },
+    /// The run's [`CancellationToken`] was cancelled. The report covers what
+    /// was done before, e.g. the verdicts reached so far.
+    Cancelled {
+        report: Box<RunReport>,
+    },
+}
+impl Display for SynthesisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SynthesisError::NoPrograms => write!(f, "no programs to synthesize an ISA for"),
+            SynthesisError::NoIsa { report } => write!(
+                f,
+                "no ISA implements every program ({} of {} candidates verified)",
+                report.verified, report.candidates
+            ),
+            SynthesisError::Cancelled { report } => write!(
+                f,
+                "cancelled after checking {} of {} candidates",
+                report.verdicts.len(),
+                report.candidates
+            ),
+        }
+    }
+}
+impl std::error::Error for SynthesisError {}
+
+/// Decides whether a candidate `instr` can be implemented.
+pub type Backend = Box<dyn SynthesisBackend>;
+
+/// Builds and runs the pipeline:
+///
+/// ```ignore
+/// let result = Synthesizer::new()
+///     .add_program("and", "(binop and 8 (var x 8) (var y 8))".parse().unwrap())
+///     .with_max_instructions(2)
+///     .run()?;
+/// ```
+pub struct Synthesizer {
+    programs: ProgramSet,
+    rules: Vec<Rewrite<Language, LanguageAnalysis>>,
+    backend: Backend,
+    cost_model: CostModel,
+    architecture: Option<Box<dyn Architecture>>,
+    iter_limit: usize,
+    node_limit: usize,
+    max_instructions: usize,
+    k: usize,
+    seed: u64,
+    observer: Option<Rc<dyn ProgressObserver>>,
+    checkpoint: Option<CheckpointOptions>,
+    parallel: bool,
+    ports: Option<OperandPorts>,
+    cancel: CancellationToken,
+    time_budget: Option<Duration>,
+    trivial: Option<TrivialFilter>,
+}
+
+impl Default for Synthesizer {
+    fn default() -> Self {
+        Synthesizer {
+            programs: ProgramSet::new(),
+            rules: default_rules(),
+            backend: Box::new(FnBackend(|_: &RecExpr<Language>| true)),
+            cost_model: CostModel::default(),
+            architecture: None,
+            iter_limit: 10,
+            node_limit: 100_000,
+            max_instructions: 3,
+            k: 16,
+            seed: DEFAULT_SEED,
+            observer: None,
+            checkpoint: None,
+            parallel: false,
+            ports: None,
+            cancel: CancellationToken::new(),
+            time_budget: None,
+            trivial: None,
+        }
+    }
+}
+
+impl Synthesizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_program(mut self, name: impl Into<String>, expr: RecExpr<Language>) -> Self {
+        self.programs.add(name, expr);
+        self
+    }
+
+    /// Adds every program in `programs`, keeping their weights.
+    pub fn add_programs(mut self, programs: &ProgramSet) -> Self {
+        self.programs.append(programs);
+        self
+    }
+
+    /// Replaces the [`default_rules`].
+    pub fn with_rules(mut self, rules: Vec<Rewrite<Language, LanguageAnalysis>>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Adds the [`macro_op_rules`] for `instrs`, e.g. an ISA selected by an
+    /// earlier run, so candidates can build on them. Adds to the rules set
+    /// so far, so call it after [`with_rules`](Self::with_rules).
+    pub fn with_macro_ops(mut self, instrs: &[RecExpr<Language>]) -> Result<Self, LakeroadError> {
+        self.rules.extend(macro_op_rules(instrs)?);
+        Ok(self)
+    }
+
+    /// Sets the check each candidate must pass to be selected. By default
+    /// every candidate is accepted.
+    pub fn with_backend(self, backend: impl Fn(&RecExpr<Language>) -> bool + 'static) -> Self {
+        self.with_synthesis_backend(FnBackend(backend))
+    }
+
+    /// Checks candidates with [`SynthesisBackend::check_candidate`]. Errors
+    /// from the backend end the run.
+    pub fn with_synthesis_backend(mut self, backend: impl SynthesisBackend + 'static) -> Self {
+        self.backend = Box::new(backend);
+        self
+    }
+
+    /// Explores k-input LUTs instead of instructions, replacing the rules
+    /// with [`lut_rules`] and the backend with a [`LutBackend`].
+    pub fn with_lut_mapping(self, k: usize) -> Self {
+        self.with_rules(lut_rules(k))
+            .with_synthesis_backend(LutBackend::new(k))
+    }
+
+    pub fn with_cost_model(mut self, cost_model: CostModel) -> Self {
+        self.cost_model = cost_model;
+        self
+    }
+
+    /// Extracts each program's implementation by the primitives it takes on
+    /// `arch`, weighted as in the cost model, and reports them.
+    pub fn with_architecture(mut self, arch: Box<dyn Architecture>) -> Self {
+        self.architecture = Some(arch);
+        self
+    }
+
+    pub fn with_iter_limit(mut self, iter_limit: usize) -> Self {
+        self.iter_limit = iter_limit;
+        self
+    }
+
+    pub fn with_node_limit(mut self, node_limit: usize) -> Self {
+        self.node_limit = node_limit;
+        self
+    }
+
+    /// The largest ISA considered.
+    pub fn with_max_instructions(mut self, max_instructions: usize) -> Self {
+        self.max_instructions = max_instructions;
+        self
+    }
+
+    /// How many of the ISAs with the lowest program cost the cost model
+    /// chooses between.
+    pub fn with_k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    /// Seeds every randomized part of the run (see [`crate::determinism`]).
+    /// The seed is recorded in the report, so that the run can be repeated.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Reports progress to `observer` during [`run`](Self::run).
+    pub fn with_observer(mut self, observer: impl ProgressObserver + 'static) -> Self {
+        self.observer = Some(Rc::new(observer));
+        self
+    }
+
+    /// Saves progress to a [`Checkpoint`] during [`run`](Self::run), and
+    /// resumes from it if it already exists. Fails if the checkpoint was
+    /// written by a run with a different seed, programs, rules, limits, or
+    /// backend.
+    pub fn with_checkpoint(mut self, options: CheckpointOptions) -> Self {
+        self.checkpoint = Some(options);
+        self
+    }
+
+    /// Rewrites each program in its own e-graph, in parallel, and merges
+    /// the e-graphs before extraction (see [`crate::parallel`]). The node
+    /// limit then applies to each program. Builds without the `parallel`
+    /// feature, and WebAssembly builds, always rewrite sequentially.
+    pub fn with_parallel_exploration(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Restricts candidates to operands `ports` can supply: the hole
+    /// introduction rewrites don't build an `apply` whose arguments don't
+    /// fit (see [`crate::rewrites::ports_admit`]), and instructions with
+    /// more operands than the ports have are pruned during rewriting and
+    /// never selected.
+    pub fn with_operand_ports(mut self, ports: OperandPorts) -> Self {
+        self.ports = Some(ports);
+        self
+    }
+
+    /// The constraints candidates are pruned by.
+    fn constraints(&self) -> CandidateConstraints {
+        CandidateConstraints {
+            ports: self.ports,
+            ..Default::default()
+        }
+    }
+
+    /// Runs in anytime mode, within `budget` of wall-clock time. Rewriting
+    /// stops after half the budget, in place of egg's default time limit,
+    /// and candidates are checked in priority order until the rest runs
+    /// out, so that a run which would otherwise outgrow its limits still
+    /// selects the best ISA among the candidates verified in time, with
+    /// [`RunReport::incomplete`] set. A solver call already underway isn't
+    /// interrupted. WebAssembly builds have no clock, so there the budget
+    /// never runs out.
+    pub fn with_time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    /// Drops the accepted candidates `filter` finds trivial, e.g. identities,
+    /// before selecting an ISA. Proving a candidate trivial may ask the
+    /// backend whether two terms are equivalent.
+    pub fn with_trivial_filter(mut self, filter: TrivialFilter) -> Self {
+        self.trivial = Some(filter);
+        self
+    }
+
+    /// Ends the run early, with [`SynthesisError::Cancelled`], once `cancel`
+    /// is cancelled. The run checks between rewrite iterations, around
+    /// extraction, and between candidates; to also interrupt a solver call,
+    /// give the backend the same token (e.g.
+    /// [`RacketBackend::with_cancellation`](crate::racket::RacketBackend::with_cancellation)).
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Fails with [`SynthesisError::Cancelled`] if the run was cancelled.
+    fn check_cancelled(&self, report: &RunReport) -> Result<(), LakeroadError> {
+        if self.cancel.is_cancelled() {
+            return Err(SynthesisError::Cancelled {
+                report: Box::new(report.clone()),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn notify(&self, event: ProgressEvent) {
+        metrics::observe(&event);
+        if let Some(observer) = &self.observer {
+            observer.notify(&event);
+        }
+    }
+
+    /// Runs only rewriting and candidate extraction.
+    pub fn explore(&self) -> Result<Exploration, LakeroadError> {
+        if self.programs.is_empty() {
+            return Err(SynthesisError::NoPrograms.into());
+        }
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        {
+            if self.parallel {
+                return self.explore_in_parallel();
+            }
+        }
+        let ingestion = Timer::start();
+        let mut egraph = EGraph::<Language, LanguageAnalysis>::new(LanguageAnalysis {
+            ports: self.ports,
+            ..Default::default()
+        });
+        let roots = self.programs.add_to_egraph(&mut egraph);
+        let ingestion = ingestion.elapsed();
+        self.notify(ProgressEvent::Phase(Phase::Rewriting));
+        let rewriting = Timer::start();
+        // The time at the start of each iteration, and at the end.
+        let marks = Rc::new(RefCell::new(vec![]));
+        let mut runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(self.iter_limit)
+            .with_node_limit(self.node_limit);
+        if let Some(budget) = self.time_budget {
+            runner = runner.with_time_limit(budget / 2);
+        }
+        let observer = self.observer.clone();
+        let cancel = self.cancel.clone();
+        let hook_marks = marks.clone();
+        // Hooks run before each iteration, so the last iteration is reported
+        // below instead.
+        runner = runner.with_hook(move |runner| {
+            hook_marks.borrow_mut().push(rewriting.elapsed());
+            if cancel.is_cancelled() {
+                return Err("cancelled".to_string());
+            }
+            if !runner.iterations.is_empty() {
+                let event = ProgressEvent::Iteration {
+                    iteration: runner.iterations.len(),
+                    egraph_nodes: runner.egraph.total_size(),
+                    egraph_classes: runner.egraph.number_of_classes(),
+                };
+                metrics::observe(&event);
+                if let Some(observer) = &observer {
+                    observer.notify(&event);
+                }
+            }
+            Ok(())
+        });
+        if self.ports.is_some() {
+            runner = runner.with_hook(pruning_hook(self.constraints()));
+        }
+        let runner = runner.run(&self.rules);
+        marks.borrow_mut().push(rewriting.elapsed());
+        self.notify(ProgressEvent::Iteration {
+            iteration: runner.iterations.len(),
+            egraph_nodes: runner.egraph.total_size(),
+            egraph_classes: runner.egraph.number_of_classes(),
+        });
+        let mut report = RunReport {
+            seed: self.seed,
+            programs: self.programs.len(),
+            program_names: self.programs.iter().map(|p| p.name.clone()).collect(),
+            iterations: runner.iterations.len(),
+            iteration_stats: runner
+                .iterations
+                .iter()
+                .map(IterationReport::from)
+                .collect(),
+            stop_reason: runner.stop_reason.as_ref().map(|r| format!("{:?}", r)),
+            incomplete: self.time_budget.is_some()
+                && matches!(runner.stop_reason, Some(StopReason::TimeLimit(_))),
+            egraph_nodes: runner.egraph.total_size(),
+            egraph_classes: runner.egraph.number_of_classes(),
+            profile: Profile {
+                ingestion,
+                rewriting: rewriting.elapsed(),
+                iterations: intervals(&marks.borrow()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        self.check_cancelled(&report)?;
+        self.extract(runner.egraph, roots, report)
+    }
+
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    fn explore_in_parallel(&self) -> Result<Exploration, LakeroadError> {
+        self.notify(ProgressEvent::Phase(Phase::Rewriting));
+        let rewriting = Timer::start();
+        let explored = explore_programs(
+            &self.programs,
+            &self.rules,
+            self.iter_limit,
+            self.node_limit,
+            self.time_budget.map(|budget| budget / 2),
+            self.ports,
+            &self.cancel,
+        );
+        let mut egraph = EGraph::<Language, LanguageAnalysis>::new(LanguageAnalysis {
+            ports: self.ports,
+            ..Default::default()
+        });
+        let roots = explored
+            .iter()
+            .zip(self.programs.iter())
+            .map(|(program, p)| {
+                (
+                    merge_egraph(&mut egraph, &program.egraph)[&program.root],
+                    p.weight,
+                )
+            })
+            .collect::<Vec<_>>();
+        egraph.rebuild();
+        let roots = roots
+            .into_iter()
+            .map(|(root, weight)| (egraph.find(root), weight))
+            .collect();
+        let rewriting = rewriting.elapsed();
+
+        // Iterations are reported with the programs' e-graphs summed.
+        let iterations = explored
+            .iter()
+            .map(|p| p.iterations.len())
+            .max()
+            .unwrap_or(0);
+        let iteration_stats = (0..iterations)
+            .map(|i| {
+                let mut sum = IterationReport::default();
+                for stat in explored.iter().filter_map(|p| p.iterations.get(i)) {
+                    sum.egraph_nodes += stat.egraph_nodes;
+                    sum.egraph_classes += stat.egraph_classes;
+                    sum.applied += stat.applied;
+                    for (rule, n) in &stat.rules {
+                        *sum.rules.entry(rule.clone()).or_default() += n;
+                    }
+                    sum.seconds = sum.seconds.max(stat.seconds);
+                }
+                sum
+            })
+            .collect::<Vec<IterationReport>>();
+        for (i, stats) in iteration_stats.iter().enumerate() {
+            self.notify(ProgressEvent::Iteration {
+                iteration: i + 1,
+                egraph_nodes: stats.egraph_nodes,
+                egraph_classes: stats.egraph_classes,
+            });
+        }
+        let mut stop_reasons = explored
+            .iter()
+            .filter_map(|p| p.stop_reason.clone())
+            .collect::<Vec<_>>();
+        stop_reasons.sort();
+        stop_reasons.dedup();
+        let report = RunReport {
+            seed: self.seed,
+            programs: self.programs.len(),
+            program_names: self.programs.iter().map(|p| p.name.clone()).collect(),
+            iterations,
+            iteration_stats,
+            stop_reason: (!stop_reasons.is_empty()).then(|| stop_reasons.join(", ")),
+            incomplete: self.time_budget.is_some()
+                && stop_reasons
+                    .iter()
+                    .any(|reason| reason.starts_with("TimeLimit")),
+            egraph_nodes: egraph.total_size(),
+            egraph_classes: egraph.number_of_classes(),
+            profile: Profile {
+                // The programs are added and rewritten at the same time, so
+                // each takes as long as the slowest program's.
+                ingestion: longest(explored.iter().map(|p| p.ingestion)),
+                rewriting,
+                iterations: (0..iterations)
+                    .map(|i| {
+                        longest(
+                            explored
+                                .iter()
+                                .filter_map(|p| p.iteration_times.get(i).copied()),
+                        )
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        self.check_cancelled(&report)?;
+        self.extract(egraph, roots, report)
+    }
+
+    /// Runs rewriting and candidate extraction, then summarizes the
+    /// candidates instead of checking them, so a rule set can be sanity
+    /// checked without any solver calls. Candidates which extract to the
+    /// same `instr` are listed once. Checkpoints are neither read nor
+    /// written.
+    pub fn dry_run(&self) -> Result<Vec<CandidateSummary>, LakeroadError> {
+        let Exploration {
+            egraph,
+            roots,
+            candidates,
+            ..
+        } = self.explore()?;
+        let coverage = CoverageIndex::new(&egraph, roots.iter().map(|(root, _)| *root));
+        let mut seen = HashSet::new();
+        Ok(candidates
+            .iter()
+            .filter(|(_, instr)| seen.insert(instr.to_string()))
+            .map(|(id, instr)| {
+                let isa = Isa::new(&egraph, &[*id]);
+                CandidateSummary {
+                    instr: instr.to_string(),
+                    id: instr_id(instr).unwrap_or_default(),
+                    size: instr_size(instr),
+                    programs: self
+                        .programs
+                        .iter()
+                        .zip(&roots)
+                        .filter(|(_, (root, _))| coverage.contains(*root, *id))
+                        .map(|(program, _)| program.name.clone())
+                        .collect(),
+                    predicted_cost: total_cost(&egraph, &roots, &isa)
+                        .map(|cost| self.cost_model.score(cost, &isa)),
+                }
+            })
+            .collect())
+    }
+
+    /// Extracts candidates from the rewritten e-graph of a checkpoint.
+    fn explore_from(&self, state: &ExplorationState) -> Result<Exploration, LakeroadError> {
+        let ingestion = Timer::start();
+        let egraph = egraph_from_egglog(&state.egraph)
+            .map_err(|e| LakeroadError::Parse(format!("checkpointed e-graph: {}", e)))?;
+        let roots = self
+            .programs
+            .iter()
+            .map(|program| {
+                egraph
+                    .lookup_expr(&program.expr)
+                    .map(|root| (root, program.weight))
+                    .ok_or_else(|| {
+                        LakeroadError::Malformed(format!(
+                            "checkpointed e-graph doesn't contain program {}",
+                            program.name
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut report = state.report.clone();
+        report.profile.ingestion = ingestion.elapsed();
+        self.extract(egraph, roots, report)
+    }
+
+    fn extract(
+        &self,
+        mut egraph: EGraph<Language, LanguageAnalysis>,
+        roots: Vec<(Id, f64)>,
+        mut report: RunReport,
+    ) -> Result<Exploration, LakeroadError> {
+        self.notify(ProgressEvent::Phase(Phase::Extraction));
+        self.check_cancelled(&report)?;
+        let extraction = Timer::start();
+        // Catch anything added after the last pruning, or while rewriting
+        // without it.
+        if self.ports.is_some() {
+            prune(&mut egraph, &self.constraints());
+        }
+        let mut candidates = find_isa_instructions(&egraph)?
+            .into_iter()
+            .filter(|(_, instr)| !is_hole_instr(instr))
+            .collect::<Vec<_>>();
+        prioritize_candidates(&egraph, &roots, &mut candidates);
+        report.candidates = candidates.len();
+        report.profile.extraction = extraction.elapsed();
+        self.check_cancelled(&report)?;
+        metrics::candidates_extracted(candidates.len());
+        Ok(Exploration {
+            egraph,
+            roots,
+            candidates,
+            report,
+        })
+    }
+
+    /// Loads the checkpoint, if checkpointing, or starts a new one.
+    fn load_checkpoint(&self) -> Result<Option<Checkpoint>, LakeroadError> {
+        let options = match &self.checkpoint {
+            Some(options) => options,
+            None => return Ok(None),
+        };
+        let run = self.identity();
+        match Checkpoint::load(&options.path)? {
+            Some(checkpoint) => match checkpoint.run.mismatch(&run) {
+                Some(mismatch) => Err(LakeroadError::Config(format!(
+                    "checkpoint {} was written by a run with a different {}",
+                    options.path.display(),
+                    mismatch
+                ))),
+                None => Ok(Some(checkpoint)),
+            },
+            None => Ok(Some(Checkpoint::new(run))),
+        }
+    }
+
+    /// What this run's checkpoints are written by.
+    fn identity(&self) -> RunIdentity {
+        RunIdentity {
+            rules: self
+                .rules
+                .iter()
+                .map(|rule| rule.name.to_string())
+                .collect(),
+            iter_limit: self.iter_limit,
+            node_limit: self.node_limit,
+            backend: self.backend.name(),
+            ..RunIdentity::new(self.seed, &self.programs)
+        }
+    }
+
+    fn save_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(), LakeroadError> {
+        match &self.checkpoint {
+            Some(options) => checkpoint.save(&options.path),
+            None => Ok(()),
+        }
+    }
+
+    /// The best ISA of the `verified` candidates under the cost model, its
+    /// score, and its instructions, or `None` if no ISA within the
+    /// instruction limit implements every program.
+    fn select(
+        &self,
+        egraph: &EGraph<Language, LanguageAnalysis>,
+        roots: &[(Id, f64)],
+        verified: &[(Id, RecExpr<Language>)],
+    ) -> Option<(f64, Isa, Vec<RecExpr<Language>>)> {
+        let ids = verified.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        let (score, isa) = top_k_isas(egraph, &ids, roots, self.max_instructions, self.k)
+            .into_iter()
+            .map(|(cost, isa)| (self.cost_model.score(cost, &isa), isa))
+            .min_by(|(a_score, a), (b_score, b)| {
+                a_score.total_cmp(b_score).then_with(|| a.cmp(b))
+            })?;
+        let instructions = isa
+            .instructions
+            .iter()
+            .map(|id| {
+                verified
+                    .iter()
+                    .find(|(candidate, _)| candidate == id)
+                    .map(|(_, instr)| instr.clone())
+                    .unwrap()
+            })
+            .collect();
+        Some((score, isa, instructions))
+    }
+
+    pub fn run(self) -> Result<SynthesisResult, LakeroadError> {
+        let started = Timer::start();
+        let out_of_time = || {
+            self.time_budget.map_or(false, |budget| {
+                started.elapsed().wall_seconds >= budget.as_secs_f64()
+            })
+        };
+        let mut checkpoint = self.load_checkpoint()?;
+        let resumed = checkpoint
+            .as_ref()
+            .and_then(|checkpoint| checkpoint.exploration.as_ref());
+        let exploration = match resumed {
+            Some(state) => self.explore_from(state)?,
+            None => {
+                let exploration = self.explore()?;
+                if let Some(checkpoint) = &mut checkpoint {
+                    checkpoint.exploration = Some(ExplorationState {
+                        egraph: egraph_to_egglog(&exploration.egraph),
+                        report: exploration.report.clone(),
+                    });
+                    self.save_checkpoint(checkpoint)?;
+                }
+                exploration
+            }
+        };
+        let Exploration {
+            egraph,
+            roots,
+            candidates,
+            mut report,
+        } = exploration;
+
+        self.notify(ProgressEvent::Phase(Phase::Verification));
+        let verification = Timer::start();
+        let cached = checkpoint.as_ref().map_or_else(HashMap::new, |checkpoint| {
+            checkpoint
+                .verdicts
+                .iter()
+                .map(|verdict| {
+                    // Checkpoints written before verdicts had ids only have
+                    // the instr.
+                    let id = if verdict.id.is_empty() {
+                        verdict
+                            .instr
+                            .parse::<RecExpr<Language>>()
+                            .ok()
+                            .and_then(|instr| instr_id(&instr))
+                            .unwrap_or_default()
+                    } else {
+                        verdict.id.clone()
+                    };
+                    (id, verdict.clone())
+                })
+                .collect()
+        });
+        let every = self.checkpoint.as_ref().map_or(0, |options| options.every);
+        let mut new_verdicts = 0;
+        let mut verified = vec![];
+        let (mut best_score, mut since_best) = (f64::INFINITY, None);
+        for (i, (id, instr)) in candidates.into_iter().enumerate() {
+            let stable_id = instr_id(&instr).unwrap_or_default();
+            // Instructions the same up to the numbering of their arguments
+            // share a verdict.
+            let verdict = match cached.get(&stable_id) {
+                Some(verdict) => VerdictReport {
+                    instr: instr.to_string(),
+                    id: stable_id,
+                    ..verdict.clone()
+                },
+                // Past the budget, only cached verdicts are reused.
+                None if out_of_time() => {
+                    report.incomplete = true;
+                    continue;
+                }
+                None => {
+                    let (accepted, seconds) = timed(|| {
+                        if self.cancel.is_cancelled() {
+                            return Err(LakeroadError::Cancelled);
+                        }
+                        self.backend.check_candidate(&instr)
+                    });
+                    let accepted = match accepted {
+                        Err(LakeroadError::Cancelled) => {
+                            // Keep the verdicts reached so far, so a resumed
+                            // run doesn't repeat them.
+                            if let Some(checkpoint) = &checkpoint {
+                                self.save_checkpoint(checkpoint)?;
+                            }
+                            report.profile.verification = verification.elapsed();
+                            return Err(SynthesisError::Cancelled {
+                                report: Box::new(report),
+                            }
+                            .into());
+                        }
+                        accepted => accepted?,
+                    };
+                    metrics::solver_time(seconds);
+                    let verdict = VerdictReport {
+                        instr: instr.to_string(),
+                        id: stable_id,
+                        accepted,
+                        seconds,
+                    };
+                    if let Some(checkpoint) = &mut checkpoint {
+                        checkpoint.verdicts.push(verdict.clone());
+                        new_verdicts += 1;
+                        if every > 0 && new_verdicts % every == 0 {
+                            self.save_checkpoint(checkpoint)?;
+                        }
+                    }
+                    verdict
+                }
+            };
+            let accepted = verdict.accepted;
+            report.verdicts.push(verdict);
+            self.notify(ProgressEvent::Verdict {
+                instr: &instr,
+                accepted,
+                checked: i + 1,
+                total: report.candidates,
+            });
+            if accepted {
+                verified.push((id, instr));
+                // Keeps an observer's view of the best ISA so far current,
+                // at most once a second, since selecting isn't free.
+                let due = since_best
+                    .as_ref()
+                    .map_or(true, |timer| timer.elapsed().wall_seconds >= 1.0);
+                if self.observer.is_some() && due {
+                    since_best = Some(Timer::start());
+                    if let Some((score, _, instructions)) = self.select(&egraph, &roots, &verified)
+                    {
+                        if score < best_score {
+                            best_score = score;
+                            self.notify(ProgressEvent::Isa {
+                                instrs: &instructions,
+                                score,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(checkpoint) = &checkpoint {
+            self.save_checkpoint(checkpoint)?;
+        }
+        report.verified = verified.len();
+        if let Some(filter) = &self.trivial {
+            report.trivial = filter
+                .filter(&mut verified, self.backend.as_ref(), self.seed)?
+                .into_iter()
+                .map(|(instr, kind)| TrivialReport {
+                    instr: instr.to_string(),
+                    id: instr_id(&instr).unwrap_or_default(),
+                    kind,
+                })
+                .collect();
+        }
+        report.profile.verification = verification.elapsed();
+
+        self.notify(ProgressEvent::Phase(Phase::Selection));
+        let selection = Timer::start();
+        let (score, isa, instructions) = match self.select(&egraph, &roots, &verified) {
+            Some(best) => best,
+            None => {
+                report.profile.selection = selection.elapsed();
+                return Err(SynthesisError::NoIsa {
+                    report: Box::new(report),
+                }
+                .into());
+            }
+        };
+        report.score = score;
+        metrics::isa_selected(score);
+        self.notify(ProgressEvent::Isa {
+            instrs: &instructions,
+            score,
+        });
+        report.isa = instructions.iter().map(|i| i.to_string()).collect();
+        report.isa_ids = instructions
+            .iter()
+            .map(|i| instr_id(i).unwrap_or_default())
+            .collect();
+        let coverage = CoverageIndex::new(&egraph, roots.iter().map(|(root, _)| *root));
+        report.coverage = self
+            .programs
+            .iter()
+            .zip(&roots)
+            .map(|(program, (root, _))| CoverageReport {
+                program: program.name.clone(),
+                weight: program.weight,
+                cost: program_cost(&egraph, *root, &isa),
+                instructions: isa
+                    .instructions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, id)| coverage.contains(*root, **id))
+                    .map(|(i, _)| i)
+                    .collect(),
+                primitives: self.architecture.as_ref().and_then(|arch| {
+                    extract_mapped(
+                        &egraph,
+                        *root,
+                        arch.as_ref(),
+                        &self.cost_model.primitives,
+                        Some(&isa),
+                    )
+                    .ok()
+                    .map(|(_, netlist)| netlist.count())
+                }),
+                sites: isa
+                    .instructions
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(i, id)| {
+                        matched_sites(&egraph, &coverage, *id, *root)
+                            .into_iter()
+                            .map(move |site| SiteReport {
+                                instruction: i,
+                                eclass: usize::from(site.eclass),
+                                source: site.source.to_string(),
+                            })
+                    })
+                    .collect(),
+                listing: compile_program(&egraph, *root, &isa, &instructions)
+                    .and_then(|compiled| to_listing(&program.name, &compiled, &instructions).ok()),
+            })
+            .collect();
+        report.profile.selection = selection.elapsed();
+        Ok(SynthesisResult {
+            egraph,
+            isa,
+            instructions,
+            report,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn and_or() -> Synthesizer {
+        Synthesizer::new()
+            .add_program(
+                "and",
+                RecExpr::from_str("(binop and 8 (var x 8) (var y 8))").unwrap(),
+            )
+            .add_program(
+                "or",
+                RecExpr::from_str("(binop or 8 (var x 8) (var y 8))").unwrap(),
+            )
+            .with_rules(vec![
+                introduce_hole_var(),
+                introduce_hole_op_both(),
+                canonicalize(),
+            ])
+            .with_max_instructions(2)
+    }
+
+    #[test]
+    fn synthesize_and_or() {
+        let result = and_or().run().unwrap();
+        assert_eq!(result.isa.len(), 2);
+        assert_eq!(result.report.score, 2.0);
+        assert_eq!(result.report.programs, 2);
+        assert!(result.report.verified <= result.report.candidates);
+        assert!(result
+            .instructions
+            .iter()
+            .any(|instr| instr.to_string().contains("binop-ast and")));
+        let report = &result.report;
+        assert_eq!(report.program_names, vec!["and", "or"]);
+        assert_eq!(report.verdicts.len(), report.candidates);
+        assert_eq!(report.isa.len(), 2);
+        for coverage in &report.coverage {
+            assert_eq!(coverage.cost, Some(1));
+            assert_eq!(coverage.instructions.len(), 1);
+            // The instruction captures the whole program.
+            let site = &coverage.sites[0];
+            assert_eq!(site.instruction, coverage.instructions[0]);
+            assert_eq!(
+                site.source,
+                format!("(binop {} 8 (var x 8) (var y 8))", coverage.program)
+            );
+            assert_eq!(
+                coverage.listing.as_deref().unwrap(),
+                format!(
+                    "; {}\nt0 = instr{} x, y\nret t0\n",
+                    coverage.program, coverage.instructions[0]
+                )
+            );
+        }
+        let profile = &report.profile;
+        assert!(!profile.iterations.is_empty());
+        assert!(profile.iterations.len() <= report.iterations);
+        assert!(profile.rewriting.wall_seconds > 0.0);
+        assert!(profile.rewriting.cpu_seconds.is_some());
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["coverage"][0]["program"], "and");
+        assert!(json["profile"]["verification"]["wall_seconds"].is_number());
+    }
+
+    #[test]
+    fn observer_sees_every_phase() {
+        let events = Rc::new(std::cell::RefCell::new(vec![]));
+        let log = events.clone();
+        let result = and_or()
+            .with_observer(move |event: &ProgressEvent| {
+                log.borrow_mut().push(match event {
+                    ProgressEvent::Phase(phase) => format!("{:?}", phase),
+                    ProgressEvent::Iteration { iteration, .. } => {
+                        format!("iteration {}", iteration)
+                    }
+                    ProgressEvent::Verdict { checked, total, .. } => {
+                        format!("verdict {}/{}", checked, total)
+                    }
+                    ProgressEvent::Isa { score, .. } => format!("isa {}", score),
+                })
+            })
+            .run()
+            .unwrap();
+        let events = events.borrow();
+        assert_eq!(events[0], "Rewriting");
+        assert!(events.contains(&format!("iteration {}", result.report.iterations)));
+        assert!(events.contains(&format!(
+            "verdict {}/{}",
+            result.report.candidates, result.report.candidates
+        )));
+        // The selected ISA is reported last.
+        assert_eq!(events[events.len() - 2], "Selection");
+        assert_eq!(events.last().unwrap(), "isa 2");
+    }
+
+    #[test]
+    fn backend_rejections() {
+        let result = and_or()
+            .with_backend(|instr| !instr.to_string().contains("binop-ast or"))
+            .run();
+        assert!(matches!(
+            result,
+            Err(LakeroadError::Synthesis(SynthesisError::NoIsa { .. }))
+        ));
+        assert!(matches!(
+            Synthesizer::new().run(),
+            Err(LakeroadError::Synthesis(SynthesisError::NoPrograms))
+        ));
+    }
+
+    #[test]
+    fn resume_from_checkpoint() {
+        let path =
+            std::env::temp_dir().join(format!("lakeroad-resume-{}.json", std::process::id()));
+        let options = CheckpointOptions {
+            path: path.clone(),
+            every: 1,
+        };
+        let first = and_or().with_checkpoint(options.clone()).run().unwrap();
+        // Every verdict is cached, so the backend is never called again.
+        let resumed = and_or()
+            .with_checkpoint(options.clone())
+            .with_backend(|_| panic!("verdict should come from the checkpoint"))
+            .run()
+            .unwrap();
+        assert_eq!(resumed.report.isa, first.report.isa);
+        assert_eq!(resumed.report.verdicts, first.report.verdicts);
+        assert!(matches!(
+            and_or().with_seed(1).with_checkpoint(options.clone()).run(),
+            Err(LakeroadError::Config(_))
+        ));
+        assert!(matches!(
+            and_or()
+                .with_iter_limit(3)
+                .with_checkpoint(options.clone())
+                .run(),
+            Err(LakeroadError::Config(_))
+        ));
+        assert!(matches!(
+            and_or().with_lut_mapping(4).with_checkpoint(options).run(),
+            Err(LakeroadError::Config(message)) if message.ends_with("different rules")
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn cancellation_keeps_partial_report() {
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(matches!(
+            and_or().with_cancellation(cancel).run(),
+            Err(LakeroadError::Synthesis(SynthesisError::Cancelled { report }))
+                if report.candidates == 0
+        ));
+
+        // Cancelled by the backend, as if from another thread, once the
+        // first candidate is checked.
+        let cancel = CancellationToken::new();
+        let backend_cancel = cancel.clone();
+        let result = and_or()
+            .with_backend(move |_| {
+                backend_cancel.cancel();
+                true
+            })
+            .with_cancellation(cancel)
+            .run();
+        match result {
+            Err(LakeroadError::Synthesis(SynthesisError::Cancelled { report })) => {
+                assert_eq!(report.verdicts.len(), 1);
+                assert!(report.candidates > 1);
+            }
+            _ => panic!("expected the run to be cancelled"),
+        }
+    }
+
+    #[test]
+    fn time_budget_keeps_partial_isa() {
+        // The first candidate checked, the fused `not` of an `and`, uses up
+        // the budget, and implements the program by itself.
+        let result = Synthesizer::new()
+            .add_program(
+                "nand",
+                RecExpr::from_str("(unop not 8 (binop and 8 (var x 8) (var y 8)))").unwrap(),
+            )
+            .with_backend(|_| {
+                std::thread::sleep(Duration::from_millis(600));
+                true
+            })
+            .with_time_budget(Duration::from_millis(500))
+            .run()
+            .unwrap();
+        let report = &result.report;
+        assert!(report.incomplete);
+        assert_eq!(report.verdicts.len(), 1);
+        assert!(report.candidates > 1);
+        assert_eq!(
+            report.isa,
+            vec![
+                "(instr (unop-ast not 8 (binop-ast and 8 (hole 8) (hole 8))) (canonical-args 0 1))"
+            ]
+        );
+        assert!(!and_or().run().unwrap().report.incomplete);
+    }
+
+    #[test]
+    fn dry_run_skips_backend() {
+        let candidates = and_or()
+            .with_backend(|_| panic!("dry runs don't check candidates"))
+            .dry_run()
+            .unwrap();
+        let and = candidates
+            .iter()
+            .find(|c| c.instr.contains("binop-ast and"))
+            .unwrap();
+        assert_eq!(and.size, 3);
+        assert_eq!(and.programs, vec!["and".to_string()]);
+        // `and` alone can't implement `or`.
+        assert_eq!(and.predicted_cost, None);
+        let mut instrs = candidates.iter().map(|c| &c.instr).collect::<Vec<_>>();
+        instrs.sort();
+        instrs.dedup();
+        assert_eq!(instrs.len(), candidates.len());
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn parallel_exploration_matches_sequential() {
+        let sequential = and_or().run().unwrap();
+        let parallel = and_or().with_parallel_exploration(true).run().unwrap();
+        assert_eq!(parallel.report.score, sequential.report.score);
+        let candidates = |report: &RunReport| {
+            let mut instrs = report
+                .verdicts
+                .iter()
+                .map(|v| v.instr.clone())
+                .collect::<Vec<_>>();
+            instrs.sort();
+            instrs
+        };
+        assert_eq!(candidates(&parallel.report), candidates(&sequential.report));
+        assert_eq!(
+            parallel.report.profile.iterations.len(),
+            parallel.report.iterations
+        );
+
+        // The merged e-graph checks operands against the ports, as the
+        // programs' e-graphs did.
+        let ports = OperandPorts {
+            registers: 3,
+            destructive: false,
+            immediate: None,
+        };
+        let exploration = and_or()
+            .with_operand_ports(ports)
+            .with_parallel_exploration(true)
+            .explore()
+            .unwrap();
+        assert_eq!(exploration.egraph.analysis.ports, Some(ports));
+    }
+}
@@ -0,0 +1,385 @@
+//! Target descriptions: a target's baseline instructions, loaded from a
+//! file instead of written in Rust.
+//!
+//! ```toml
+//! name = "tiny8"
+//! # Operations wider than this are split; see `crate::split`.
+//! width = 8
+//!
+//! [[instructions]]
+//! name = "andn"
+//! semantics = "(binop and 8 (var a 8) (unop not 8 (var b 8)))"
+//! cost = 1
+//!
+//! [[instructions]]
+//! name = "add"
+//! semantics = "(binop add 8 (var a 8) (var b 8))"
+//! ```
+//!
+//! Descriptions can also be JSON, with the same fields. Each instruction's
+//! semantics is a program over its operands, which must be well-typed and
+//! made of `unop`s and `binop`s, the shape an `instr` can take. [`Target`]
+//! turns a description into rewrites which replace an instruction's
+//! semantics with an `apply` of it, a cost per instruction to extract
+//! implementations by, and, with [`Target::verify`], a proof that an
+//! implementation computes the program it came from.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use egg::{
+    CostFunction, Extractor, Id, Language as LanguageTrait, Pattern, RecExpr, Rewrite, Runner,
+};
+use serde::Deserialize;
+
+use crate::{
+    analysis::LanguageAnalysis,
+    ast::{Ast, Expr, Instr},
+    error::LakeroadError,
+    language::{typecheck_expr, Language},
+    split::split_rules,
+};
+
+#[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+use crate::smt::{symbolic, SmtSolver};
+
+/// A target description, as written.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetDescription {
+    pub name: String,
+    /// The widest operation the instructions take, if programs should be
+    /// split to fit.
+    pub width: Option<usize>,
+    pub instructions: Vec<InstructionDescription>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InstructionDescription {
+    pub name: String,
+    /// The program the instruction computes, with a `var` per operand.
+    pub semantics: String,
+    #[serde(default = "default_cost")]
+    pub cost: f64,
+}
+
+fn default_cost() -> f64 {
+    1.0
+}
+
+/// A baseline instruction, checked and compiled from its description.
+#[derive(Debug, Clone)]
+pub struct TargetInstruction {
+    pub name: String,
+    pub semantics: Expr,
+    /// The `instr` with the semantics, whose holes are the semantics' `var`
+    /// occurrences from left to right.
+    pub instr: Instr,
+    /// The `var` filling each of `instr`'s holes.
+    pub operands: Vec<String>,
+    pub cost: f64,
+}
+
+/// A target loaded from a [`TargetDescription`].
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub name: String,
+    pub width: Option<usize>,
+    pub instructions: Vec<TargetInstruction>,
+}
+
+impl TargetDescription {
+    pub fn from_toml(input: &str) -> Result<Self, LakeroadError> {
+        toml::from_str(input).map_err(|e| LakeroadError::Parse(e.to_string()))
+    }
+
+    pub fn from_json(input: &str) -> Result<Self, LakeroadError> {
+        serde_json::from_str(input).map_err(|e| LakeroadError::Parse(e.to_string()))
+    }
+}
+
+impl Target {
+    /// Loads a description from a `.toml` or `.json` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LakeroadError> {
+        let path = path.as_ref();
+        let input = fs::read_to_string(path)?;
+        let description = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => TargetDescription::from_json(&input)?,
+            _ => TargetDescription::from_toml(&input)?,
+        };
+        Self::new(&description)
+    }
+
+    /// Checks each instruction's semantics and compiles it to an `instr`.
+    pub fn new(description: &TargetDescription) -> Result<Self, LakeroadError> {
+        let mut instructions: Vec<TargetInstruction> = vec![];
+        for instruction in &description.instructions {
+            if instructions.iter().any(|i| i.name == instruction.name) {
+                return Err(LakeroadError::Config(format!(
+                    "instruction {} is described twice",
+                    instruction.name
+                )));
+            }
+            let invalid = |why: String| {
+                LakeroadError::Config(format!("instruction {}: {}", instruction.name, why))
+            };
+            let expr: RecExpr<Language> = instruction
+                .semantics
+                .parse()
+                .map_err(|e| invalid(format!("{}", e)))?;
+            typecheck_expr(&expr)?;
+            let semantics = Expr::try_from(&expr)?;
+            let mut operands = vec![];
+            let ast = ast_of(&semantics, &mut operands)
+                .ok_or_else(|| invalid("semantics must be unops and binops of vars".to_string()))?;
+            let mut distinct: Vec<&String> = vec![];
+            let canonical_args = operands
+                .iter()
+                .map(
+                    |operand| match distinct.iter().position(|d| *d == operand) {
+                        Some(i) => i as i64,
+                        None => {
+                            distinct.push(operand);
+                            distinct.len() as i64 - 1
+                        }
+                    },
+                )
+                .collect();
+            if instruction.cost.is_nan() || instruction.cost < 0.0 {
+                return Err(invalid(format!("cost {}", instruction.cost)));
+            }
+            instructions.push(TargetInstruction {
+                name: instruction.name.clone(),
+                semantics,
+                instr: Instr {
+                    ast,
+                    canonical_args,
+                },
+                operands,
+                cost: instruction.cost,
+            });
+        }
+        Ok(Target {
+            name: description.name.clone(),
+            width: description.width,
+            instructions,
+        })
+    }
+
+    /// A rewrite per instruction from its semantics, with its operands as
+    /// pattern variables, to an `apply` of its `instr`, and the
+    /// [`split_rules`] for the target's width.
+    pub fn rules(&self) -> Vec<Rewrite<Language, LanguageAnalysis>> {
+        let mut rules = vec![];
+        for instruction in &self.instructions {
+            let searcher: Pattern<Language> = pattern(&instruction.semantics).parse().unwrap();
+            let args = instruction
+                .operands
+                .iter()
+                .map(|operand| format!(" ?{}", operand))
+                .collect::<String>();
+            let applier: Pattern<Language> = format!(
+                "(apply {} (list{}))",
+                RecExpr::from(&instruction.instr),
+                args
+            )
+            .parse()
+            .unwrap();
+            rules.push(
+                Rewrite::new(format!("target-{}", instruction.name), searcher, applier).unwrap(),
+            );
+        }
+        if let Some(width) = self.width {
+            rules.extend(split_rules(width));
+        }
+        rules
+    }
+
+    /// The cheapest implementation of `expr` in the target's instructions,
+    /// and its cost, the sum of the costs of the instructions it applies.
+    pub fn implement(
+        &self,
+        expr: &RecExpr<Language>,
+        iter_limit: usize,
+    ) -> Result<(f64, RecExpr<Language>), LakeroadError> {
+        let runner = Runner::default()
+            .with_iter_limit(iter_limit)
+            .with_expr(expr)
+            .run(&self.rules());
+        let egraph = &runner.egraph;
+        let costs = self
+            .instructions
+            .iter()
+            .filter_map(|instruction| {
+                egraph
+                    .lookup_expr(&RecExpr::from(&instruction.instr))
+                    .map(|id| (egraph.find(id), instruction.cost))
+            })
+            .collect();
+        let (cost, best) = Extractor::new(egraph, TargetCost { costs }).find_best(runner.roots[0]);
+        if cost.is_infinite() {
+            return Err(LakeroadError::Unsupported(format!(
+                "{} on {}, whose instructions don't cover it",
+                expr, self.name
+            )));
+        }
+        Ok((cost, best))
+    }
+
+    /// Whether `implementation` computes what `original` does, as `solver`
+    /// proves from the instructions' semantics.
+    #[cfg(all(feature = "z3", not(target_arch = "wasm32")))]
+    pub fn verify(
+        &self,
+        solver: &mut SmtSolver,
+        original: &RecExpr<Language>,
+        implementation: &RecExpr<Language>,
+    ) -> Result<bool, LakeroadError> {
+        solver.equivalent(
+            &symbolic(&Expr::try_from(original)?)?,
+            &symbolic(&Expr::try_from(implementation)?)?,
+        )
+    }
+}
+
+/// `expr` as an instruction AST, with a hole for each `var`, whose names
+/// are pushed onto `operands`, or `None` if it isn't the shape of one.
+fn ast_of(expr: &Expr, operands: &mut Vec<String>) -> Option<Ast> {
+    match expr {
+        Expr::Var { name, width } => {
+            operands.push(name.clone());
+            Some(Ast::Hole { width: *width })
+        }
+        Expr::UnOp { op, width, arg } => Some(Ast::UnOp {
+            op: op.clone(),
+            width: *width,
+            arg: Box::new(ast_of(arg, operands)?),
+        }),
+        Expr::BinOp {
+            op,
+            width,
+            lhs,
+            rhs,
+        } => Some(Ast::BinOp {
+            op: op.clone(),
+            width: *width,
+            lhs: Box::new(ast_of(lhs, operands)?),
+            rhs: Box::new(ast_of(rhs, operands)?),
+        }),
+        _ => None,
+    }
+}
+
+/// `expr`, which [`ast_of`] accepts, as a pattern with a variable per
+/// `var`.
+fn pattern(expr: &Expr) -> String {
+    match expr {
+        Expr::Var { name, .. } => format!("?{}", name),
+        Expr::UnOp { op, width, arg } => format!("(unop {} {} {})", op, width, pattern(arg)),
+        Expr::BinOp {
+            op,
+            width,
+            lhs,
+            rhs,
+        } => format!("(binop {} {} {} {})", op, width, pattern(lhs), pattern(rhs)),
+        _ => unreachable!("target semantics are unops and binops of vars"),
+    }
+}
+
+/// Charges each `apply` of one of the target's instructions its cost, and
+/// anything left unimplemented infinitely much.
+struct TargetCost {
+    costs: HashMap<Id, f64>,
+}
+
+impl CostFunction<Language> for TargetCost {
+    type Cost = f64;
+
+    fn cost<C>(&mut self, enode: &Language, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        let own = match enode {
+            Language::Apply([instr_id, _]) => match self.costs.get(instr_id) {
+                Some(cost) => *cost,
+                None => f64::INFINITY,
+            },
+            Language::UnOp(_) | Language::BinOp(_) => f64::INFINITY,
+            _ => 0.0,
+        };
+        enode
+            .children()
+            .iter()
+            .fold(own, |sum, id| sum + costs(*id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{
+        equiv::{shared_vars, test_vectors},
+        eval::eval_expr,
+    };
+
+    #[test]
+    fn implement_with_loaded_instructions() {
+        let target = Target::new(
+            &TargetDescription::from_toml(
+                r#"
+                name = "tiny8"
+                width = 8
+
+                [[instructions]]
+                name = "andn"
+                semantics = "(binop and 8 (var a 8) (unop not 8 (var b 8)))"
+
+                [[instructions]]
+                name = "xor"
+                semantics = "(binop xor 8 (var a 8) (var b 8))"
+                cost = 2
+                "#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(target.instructions[0].operands, vec!["a", "b"]);
+
+        // Sixteen bits take two of each, on the halves of the operands.
+        let program = RecExpr::from_str(
+            "(binop xor 16 (var x 16) (binop and 16 (var y 16) (unop not 16 (var z 16))))",
+        )
+        .unwrap();
+        let (cost, implementation) = target.implement(&program, 10).unwrap();
+        assert_eq!(cost, 6.0);
+        let (program, implementation) = (
+            Expr::try_from(&program).unwrap(),
+            Expr::try_from(&implementation).unwrap(),
+        );
+        for env in test_vectors(&shared_vars(&program, &implementation).unwrap(), 32, 0) {
+            assert_eq!(
+                eval_expr(&program, &env).unwrap(),
+                eval_expr(&implementation, &env).unwrap()
+            );
+        }
+
+        // Nothing implements an add.
+        let add = RecExpr::from_str("(binop add 8 (var x 8) (var y 8))").unwrap();
+        assert!(matches!(
+            target.implement(&add, 10),
+            Err(LakeroadError::Unsupported(_))
+        ));
+        // Constants have no place in an `instr`.
+        let constant = TargetDescription::from_json(
+            r#"{"name": "c", "instructions": [
+                {"name": "inc", "semantics": "(binop add 8 (var a 8) (const 1 8))"}]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            Target::new(&constant),
+            Err(LakeroadError::Config(_))
+        ));
+    }
+}
@@ -0,0 +1,262 @@
+//! Dropping trivial instructions before ISA selection.
+//!
+//! The backend accepts many candidates which no one would build, e.g. `x &
+//! x` or `x ^ x`. They cost nothing to accept, and crowd the candidates
+//! selection chooses among. A
+//! [`TrivialFilter`] drops the accepted candidates which are
+//!
+//! - moves: once the instructions they apply are inlined, just a hole;
+//! - identities: the same as one of their arguments;
+//! - constants: the same whatever their arguments;
+//! - subsumed: the same as a strictly smaller (see [`instr_size`]) candidate
+//!   which is kept, over the same arguments.
+//!
+//! All but moves are decided by evaluating: a mismatch on a few test vectors
+//! rules a candidate out, and otherwise it's proven trivial by trying every
+//! input, or, when its inputs are too wide, by the backend's
+//! [`SynthesisBackend::verify_equivalent`]. A candidate which can't be
+//! proven trivial, e.g. because the backend can't check equivalence, is
+//! kept.
+
+use std::collections::HashMap;
+
+use egg::{Id, RecExpr};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ast::{Ast, Expr, Instr},
+    backend::SynthesisBackend,
+    bitvec::BitVec,
+    equiv::{equiv_exhaustive, quick_check},
+    error::LakeroadError,
+    eval::eval_expr,
+    isa::instr_size,
+    language::Language,
+};
+
+/// How many test vectors a candidate is tried on before it's proven
+/// trivial.
+const VECTORS: usize = 16;
+
+/// Why a candidate was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Triviality {
+    Move,
+    Identity,
+    Constant,
+    Subsumed,
+}
+
+/// Which trivial candidates to drop. By default, all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrivialFilter {
+    pub moves: bool,
+    pub identities: bool,
+    pub constants: bool,
+    pub subsumed: bool,
+}
+
+impl Default for TrivialFilter {
+    fn default() -> Self {
+        TrivialFilter {
+            moves: true,
+            identities: true,
+            constants: true,
+            subsumed: true,
+        }
+    }
+}
+
+impl TrivialFilter {
+    /// Removes the trivial candidates from `candidates`, returning them, in
+    /// the order given, with why. Smaller candidates are considered first,
+    /// so that of several which compute the same, the smallest is kept.
+    /// The test vectors are drawn from `seed`.
+    pub fn filter(
+        &self,
+        candidates: &mut Vec<(Id, RecExpr<Language>)>,
+        backend: &dyn SynthesisBackend,
+        seed: u64,
+    ) -> Result<Vec<(RecExpr<Language>, Triviality)>, LakeroadError> {
+        let mut order = (0..candidates.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| instr_size(&candidates[*i].1));
+        let mut verdicts = vec![None; candidates.len()];
+        // The kept candidates' sizes and expressions, smallest first.
+        let mut kept: Vec<(usize, Expr)> = vec![];
+        for i in order {
+            let instr = &candidates[i].1;
+            let inlined = Instr::try_from(instr)?.inline();
+            if self.moves && matches!(inlined.ast, Ast::Hole { .. }) {
+                verdicts[i] = Some(Triviality::Move);
+                continue;
+            }
+            let expr = match inlined.as_expr() {
+                Some(expr) => expr,
+                None => continue,
+            };
+            let size = instr_size(instr);
+            verdicts[i] = self.triviality(&expr, size, &kept, backend, seed)?;
+            if verdicts[i].is_none() {
+                kept.push((size, expr));
+            }
+        }
+
+        let mut dropped = vec![];
+        for (candidate, verdict) in std::mem::take(candidates).into_iter().zip(verdicts) {
+            match verdict {
+                Some(triviality) => dropped.push((candidate.1, triviality)),
+                None => candidates.push(candidate),
+            }
+        }
+        Ok(dropped)
+    }
+
+    /// Why the candidate computing `expr`, of `size`, is trivial, if it is,
+    /// given the candidates `kept` so far.
+    fn triviality(
+        &self,
+        expr: &Expr,
+        size: usize,
+        kept: &[(usize, Expr)],
+        backend: &dyn SynthesisBackend,
+        seed: u64,
+    ) -> Result<Option<Triviality>, LakeroadError> {
+        let width = expr.width();
+        if self.identities {
+            for (name, var_width) in expr.vars() {
+                let var = Expr::Var {
+                    name,
+                    width: var_width,
+                };
+                if var_width == width && computes_same(expr, &var, backend, seed)? {
+                    return Ok(Some(Triviality::Identity));
+                }
+            }
+        }
+        if self.constants {
+            if let Some(constant) = constant_guess(expr)? {
+                if computes_same(expr, &constant, backend, seed)? {
+                    return Ok(Some(Triviality::Constant));
+                }
+            }
+        }
+        if self.subsumed {
+            for (_, smaller) in kept.iter().take_while(|(other, _)| *other < size) {
+                if smaller.width() == width && computes_same(expr, smaller, backend, seed)? {
+                    return Ok(Some(Triviality::Subsumed));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The constant `expr` would be: its value when its variables are all zero.
+/// `None` if that's too wide for a `const`.
+fn constant_guess(expr: &Expr) -> Result<Option<Expr>, LakeroadError> {
+    let zeros = expr
+        .vars()
+        .into_iter()
+        .map(|(name, width)| {
+            let width = usize::try_from(width)
+                .map_err(|_| LakeroadError::Malformed(format!("bitwidth {}", width)))?;
+            Ok((name, BitVec::zero(width)))
+        })
+        .collect::<Result<HashMap<_, _>, LakeroadError>>()?;
+    let width = expr.width();
+    Ok(eval_expr(expr, &zeros)?
+        .to_u128()
+        .filter(|_| width <= 64)
+        .map(|value| Expr::Const {
+            value: value as i64,
+            width,
+        }))
+}
+
+/// Whether `a` and `b` are proven to compute the same, trying test vectors
+/// drawn from `seed` first. Terms whose shared variables differ in width
+/// never do.
+fn computes_same(
+    a: &Expr,
+    b: &Expr,
+    backend: &dyn SynthesisBackend,
+    seed: u64,
+) -> Result<bool, LakeroadError> {
+    let (a, b) = (RecExpr::from(a), RecExpr::from(b));
+    match quick_check(&a, &b, VECTORS, seed) {
+        Ok(mismatches) if !mismatches.is_empty() => return Ok(false),
+        Ok(_) => (),
+        Err(LakeroadError::Malformed(_)) => return Ok(false),
+        Err(e) => return Err(e),
+    }
+    match equiv_exhaustive(&a, &b) {
+        Err(LakeroadError::Unsupported(_)) => (),
+        proven => return Ok(proven?.is_none()),
+    }
+    match backend.verify_equivalent(&a, &b) {
+        Err(LakeroadError::Unsupported(_)) => Ok(false),
+        verified => verified,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{backend::FnBackend, determinism::DEFAULT_SEED};
+
+    #[test]
+    fn drop_trivial_candidates() {
+        let candidates = [
+            "(instr (binop-ast xor 8 (hole 8) (binop-ast and 8 (hole 8) (hole 8))) (canonical-args 0 1 1))",
+            "(instr (binop-ast and 8 (hole 8) (hole 8)) (canonical-args 0 0))",
+            "(instr (apply-ast (instr (hole 8) (canonical-args 0)) (list (hole 8))) (canonical-args 0))",
+            "(instr (binop-ast xor 8 (hole 8) (hole 8)) (canonical-args 0 1))",
+            "(instr (binop-ast sub 8 (hole 8) (hole 8)) (canonical-args 0 0))",
+            // The identity, but too wide to try every input, and the
+            // backend can't check equivalence.
+            "(instr (binop-ast and 32 (hole 32) (hole 32)) (canonical-args 0 0))",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, instr)| (Id::from(i), RecExpr::from_str(instr).unwrap()))
+        .collect::<Vec<_>>();
+        let backend = FnBackend(|_: &RecExpr<Language>| true);
+        let mut kept = candidates.clone();
+        let dropped = TrivialFilter::default()
+            .filter(&mut kept, &backend, DEFAULT_SEED)
+            .unwrap();
+        assert_eq!(
+            kept.iter()
+                .map(|(id, _)| usize::from(*id))
+                .collect::<Vec<_>>(),
+            vec![3, 5]
+        );
+        assert_eq!(
+            dropped
+                .iter()
+                .map(|(_, triviality)| *triviality)
+                .collect::<Vec<_>>(),
+            vec![
+                // `x ^ (y & y)` is `x ^ y`.
+                Triviality::Subsumed,
+                Triviality::Identity,
+                Triviality::Move,
+                Triviality::Constant,
+            ]
+        );
+
+        // Only what's asked for is dropped.
+        let mut kept = candidates;
+        TrivialFilter {
+            subsumed: false,
+            ..Default::default()
+        }
+        .filter(&mut kept, &backend, DEFAULT_SEED)
+        .unwrap();
+        assert_eq!(kept.len(), 3);
+    }
+}
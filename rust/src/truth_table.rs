@@ -0,0 +1,332 @@
+//! Truth tables of programs and instructions with few input bits, for
+//! LUT-mapping backends and for reading what a candidate instruction
+//! actually computes.
+//!
+//! Input bits are numbered as in [`exhaustive_vectors`]: the first
+//! variable's bits come first, least significant first. Displays and cubes
+//! list them the other way around, most significant bit of the first
+//! variable first, the way they'd be written as numbers.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use egg::RecExpr;
+
+use crate::{
+    ast::Expr,
+    bitvec::BitVec,
+    equiv::exhaustive_vectors,
+    error::LakeroadError,
+    eval::eval_expr,
+    language::{instr_as_expr, Language},
+};
+
+/// The widest inputs, over all variables, a truth table is built for.
+pub const MAX_INPUT_BITS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TruthTable {
+    /// The variables and their widths.
+    pub inputs: Vec<(String, usize)>,
+    pub output_width: usize,
+    /// The output for each input, indexed by the input bits read as a
+    /// number.
+    pub rows: Vec<BitVec>,
+}
+
+/// A product of input literals, written as one character per input bit,
+/// most significant first: `1`, `0`, or `-` for a bit the cube doesn't
+/// depend on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Cube(pub String);
+
+impl TruthTable {
+    /// The truth table of `expr`. Fails if its variables are more than
+    /// [`MAX_INPUT_BITS`] wide in total.
+    pub fn of_expr(expr: &RecExpr<Language>) -> Result<Self, LakeroadError> {
+        let expr = Expr::try_from(expr)?;
+        let inputs = expr
+            .vars()
+            .into_iter()
+            .map(|(name, width)| match usize::try_from(width) {
+                Ok(width) => Ok((name, width)),
+                Err(_) => Err(LakeroadError::Malformed(format!("bitwidth {}", width))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let rows = exhaustive_vectors(&inputs, MAX_INPUT_BITS)?
+            .map(|vector| eval_expr(&expr, &vector))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(TruthTable {
+            output_width: rows[0].width(),
+            inputs,
+            rows,
+        })
+    }
+
+    /// The truth table of an `instr`, whose inputs are its canonical
+    /// arguments, named as in [`instr_as_expr`].
+    pub fn of_instr(instr: &RecExpr<Language>) -> Result<Self, LakeroadError> {
+        let expr = instr_as_expr(instr)
+            .ok_or_else(|| LakeroadError::Malformed(format!("{} isn't an instr", instr)))?;
+        Self::of_expr(&expr)
+    }
+
+    pub fn input_bits(&self) -> usize {
+        self.inputs.iter().map(|(_, width)| width).sum()
+    }
+
+    /// Output bit `bit`'s column: its value on each input, as the
+    /// functions below take them.
+    pub fn column(&self, bit: usize) -> Vec<bool> {
+        self.rows.iter().map(|row| row.bit(bit)).collect()
+    }
+
+    /// The input bits, as numbered in [`rows`](Self::rows), in the order
+    /// they're displayed, with a name for each.
+    fn columns(&self) -> Vec<(usize, String)> {
+        let mut offset = 0;
+        let mut out = vec![];
+        for (name, width) in &self.inputs {
+            for bit in (0..*width).rev() {
+                let label = if *width == 1 {
+                    name.clone()
+                } else {
+                    format!("{}[{}]", name, bit)
+                };
+                out.push((offset + bit, label));
+            }
+            offset += width;
+        }
+        out
+    }
+
+    /// A cover of the inputs for which output bit `bit` is one, by prime
+    /// implicants, chosen greedily, largest first. Not necessarily minimal.
+    pub fn cubes(&self, bit: usize) -> Vec<Cube> {
+        // A cube is the value of its cared-about bits, and a mask of the
+        // bits it doesn't care about.
+        let on = self
+            .rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row.bit(bit))
+            .map(|(n, _)| (n as u64, 0u64))
+            .collect::<HashSet<_>>();
+        let mut primes = vec![];
+        let mut cubes = on.clone();
+        while !cubes.is_empty() {
+            let mut merged = HashSet::new();
+            let mut next = HashSet::new();
+            for &(value, dont_care) in &cubes {
+                for i in 0..self.input_bits() {
+                    let flip = 1 << i;
+                    if dont_care & flip != 0 {
+                        continue;
+                    }
+                    if cubes.contains(&(value ^ flip, dont_care)) {
+                        merged.insert((value, dont_care));
+                        next.insert((value & !flip, dont_care | flip));
+                    }
+                }
+            }
+            primes.extend(cubes.difference(&merged).copied());
+            cubes = next;
+        }
+        // Larger cubes first, then by value, so the cover is deterministic.
+        primes.sort_by_key(|&(value, dont_care)| {
+            (std::cmp::Reverse(dont_care.count_ones()), value, dont_care)
+        });
+        let mut uncovered = on.iter().map(|(n, _)| *n).collect::<HashSet<_>>();
+        let mut cover = vec![];
+        for (value, dont_care) in primes {
+            let before = uncovered.len();
+            uncovered.retain(|n| n & !dont_care != value);
+            if uncovered.len() < before {
+                cover.push(self.cube(value, dont_care));
+            }
+        }
+        cover
+    }
+
+    fn cube(&self, value: u64, dont_care: u64) -> Cube {
+        Cube(
+            self.columns()
+                .iter()
+                .map(|(bit, _)| match (dont_care >> bit & 1, value >> bit & 1) {
+                    (1, _) => '-',
+                    (_, 1) => '1',
+                    _ => '0',
+                })
+                .collect(),
+        )
+    }
+
+    /// The table in Berkeley PLA format, as read by e.g. ABC and espresso,
+    /// with each output bit's [`cubes`](Self::cubes).
+    pub fn to_pla(&self) -> String {
+        let mut rows: HashMap<Cube, Vec<usize>> = HashMap::new();
+        for bit in 0..self.output_width {
+            for cube in self.cubes(bit) {
+                rows.entry(cube).or_default().push(bit);
+            }
+        }
+        let mut rows = rows.into_iter().collect::<Vec<_>>();
+        rows.sort();
+        let outputs = (0..self.output_width).rev().collect::<Vec<_>>();
+        let mut out = vec![
+            format!(".i {}", self.input_bits()),
+            format!(".o {}", self.output_width),
+            format!(
+                ".ilb {}",
+                self.columns()
+                    .into_iter()
+                    .map(|(_, label)| label)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            format!(
+                ".ob {}",
+                outputs
+                    .iter()
+                    .map(|bit| format!("out[{}]", bit))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            format!(".p {}", rows.len()),
+        ];
+        for (cube, bits) in rows {
+            let outputs = outputs
+                .iter()
+                .map(|bit| if bits.contains(bit) { '1' } else { '0' })
+                .collect::<String>();
+            out.push(format!("{} {}", cube.0, outputs));
+        }
+        out.push(".e".to_string());
+        out.join("\n") + "\n"
+    }
+}
+
+/// The rows of the single-output `table` where input bit `i` is `value`:
+/// the function with that input fixed.
+pub fn cofactor(table: &[bool], i: usize, value: bool) -> Vec<bool> {
+    (0..table.len())
+        .filter(|n| (n >> i & 1 == 1) == value)
+        .map(|n| table[n])
+        .collect()
+}
+
+/// How many input bits the single-output `table` depends on.
+pub fn support(table: &[bool]) -> usize {
+    let inputs = table.len().trailing_zeros() as usize;
+    (0..inputs)
+        .filter(|i| cofactor(table, *i, false) != cofactor(table, *i, true))
+        .count()
+}
+
+/// The input bit to split the single-output `table` on into a mux of its
+/// two [`cofactor`]s: the one which leaves the fewest inputs to either
+/// side, as the selects of a mux do.
+pub fn mux_select(table: &[bool]) -> usize {
+    let inputs = table.len().trailing_zeros() as usize;
+    (0..inputs)
+        .min_by_key(|i| {
+            support(&cofactor(table, *i, false)).max(support(&cofactor(table, *i, true)))
+        })
+        .expect("a function of at least one input")
+}
+
+/// How many `k`-input LUTs, and how many muxes combining them, the
+/// single-output `table` takes as a tree split on [`mux_select`]s, as
+/// [`crate::arch::Netlist::lut`] builds it.
+pub fn mux_tree_size(table: &[bool], k: usize) -> (usize, usize) {
+    match support(table) {
+        0 => (0, 0),
+        inputs if inputs <= k => (1, 0),
+        _ => {
+            let i = mux_select(table);
+            let (lo_luts, lo_muxes) = mux_tree_size(&cofactor(table, i, false), k);
+            let (hi_luts, hi_muxes) = mux_tree_size(&cofactor(table, i, true), k);
+            (lo_luts + hi_luts, lo_muxes + hi_muxes + 1)
+        }
+    }
+}
+
+impl Display for TruthTable {
+    /// Writes a row per input, with the inputs and the output in binary.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = self
+            .inputs
+            .iter()
+            .map(|(name, width)| format!("{:>width$}", name, width = width))
+            .collect::<Vec<_>>();
+        writeln!(f, "{} | out", names.join(" "))?;
+        for (n, row) in self.rows.iter().enumerate() {
+            let mut offset = 0;
+            let inputs = self
+                .inputs
+                .iter()
+                .map(|(name, width)| {
+                    let bits = (0..*width)
+                        .rev()
+                        .map(|bit| {
+                            if n >> (offset + bit) & 1 == 1 {
+                                '1'
+                            } else {
+                                '0'
+                            }
+                        })
+                        .collect::<String>();
+                    offset += width;
+                    format!("{:>width$}", bits, width = name.len().max(*width))
+                })
+                .collect::<Vec<_>>();
+            let output = (0..row.width())
+                .rev()
+                .map(|bit| if row.bit(bit) { '1' } else { '0' })
+                .collect::<String>();
+            writeln!(f, "{} | {}", inputs.join(" "), output)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn instr_tables_and_cubes() {
+        let instr = RecExpr::from_str(
+            "(instr (binop-ast or 1 (binop-ast and 1 (hole 1) (hole 1)) (hole 1)) \
+             (canonical-args 0 1 2))",
+        )
+        .unwrap();
+        let table = TruthTable::of_instr(&instr).unwrap();
+        assert_eq!(table.input_bits(), 3);
+        assert_eq!(table.output_width, 1);
+        // (a0 & a1) | a2, with a0 the least significant input bit.
+        let ones = (0..8).filter(|n| table.rows[*n].bit(0)).collect::<Vec<_>>();
+        assert_eq!(ones, vec![3, 4, 5, 6, 7]);
+        assert_eq!(
+            table.cubes(0),
+            vec![Cube("11-".to_string()), Cube("--1".to_string())]
+        );
+        assert!(table.to_pla().contains(".ilb a0 a1 a2\n"));
+        assert!(table.to_string().starts_with("a0 a1 a2 | out\n"));
+        assert_eq!(support(&cofactor(&table.column(0), 2, false)), 2);
+        // Six-input parity takes four 4-LUTs and three muxes, but only two
+        // 5-LUTs and one mux.
+        let parity = (0..64u32)
+            .map(|n| n.count_ones() % 2 == 1)
+            .collect::<Vec<_>>();
+        assert_eq!(mux_tree_size(&parity, 4), (4, 3));
+        assert_eq!(mux_tree_size(&parity, 5), (2, 1));
+
+        let wide = RecExpr::from_str("(binop and 16 (var x 16) (var y 16))").unwrap();
+        assert!(TruthTable::of_expr(&wide).is_err());
+    }
+}
@@ -0,0 +1,317 @@
+//! A terminal dashboard for long runs.
+//!
+//! A [`Dashboard`] is a [`ProgressObserver`] which draws a run's progress
+//! with ratatui: the e-graph's growth over the rewrite iterations, the
+//! candidates still waiting for a verdict, the solver's throughput, the
+//! verdicts so far, and the best ISA among the candidates accepted so far
+//! (see [`ProgressEvent::Isa`]). It redraws as events arrive, at most ten
+//! times a second, on the alternate screen of the terminal on stderr, so
+//! that stdout still holds only the run's output, and leaves it when it's
+//! dropped. The CLI shows it with `lakeroad select --tui`.
+
+use std::{
+    cell::{Cell, RefCell},
+    io::{self, Stderr},
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor, execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Style},
+    text::Line,
+    widgets::{Block, Borders, Gauge, List, ListItem, Paragraph, Sparkline},
+    Frame, Terminal,
+};
+
+use crate::progress::{Phase, ProgressEvent, ProgressObserver};
+
+/// What the dashboard shows, as of the events so far.
+#[derive(Debug, Clone, Default)]
+pub struct DashboardState {
+    pub phase: Option<Phase>,
+    /// The e-graph's size after each rewrite iteration.
+    pub egraph_nodes: Vec<u64>,
+    pub egraph_classes: usize,
+    /// The candidates extracted, known once the first is checked.
+    pub candidates: usize,
+    pub accepted: usize,
+    pub rejected: usize,
+    /// The best ISA so far, and its score.
+    pub isa: Option<(Vec<String>, f64)>,
+    verification_started: Option<Instant>,
+    /// Seconds from the start of verification to the last verdict.
+    verifying: f64,
+}
+
+impl DashboardState {
+    /// Updates the state with `event`, which happened at `now`.
+    pub fn observe(&mut self, event: &ProgressEvent, now: Instant) {
+        match event {
+            ProgressEvent::Phase(phase) => {
+                self.phase = Some(*phase);
+                if *phase == Phase::Verification {
+                    self.verification_started = Some(now);
+                }
+            }
+            // The last iteration can be reported twice.
+            ProgressEvent::Iteration {
+                iteration,
+                egraph_nodes,
+                egraph_classes,
+            } => {
+                self.egraph_nodes.truncate(iteration.saturating_sub(1));
+                self.egraph_nodes.push(*egraph_nodes as u64);
+                self.egraph_classes = *egraph_classes;
+            }
+            ProgressEvent::Verdict {
+                accepted, total, ..
+            } => {
+                if *accepted {
+                    self.accepted += 1;
+                } else {
+                    self.rejected += 1;
+                }
+                self.candidates = *total;
+                if let Some(started) = self.verification_started {
+                    self.verifying = now.duration_since(started).as_secs_f64();
+                }
+            }
+            ProgressEvent::Isa { instrs, score } => {
+                self.isa = Some((instrs.iter().map(ToString::to_string).collect(), *score));
+            }
+        }
+    }
+
+    pub fn checked(&self) -> usize {
+        self.accepted + self.rejected
+    }
+
+    /// Candidates still waiting for a verdict.
+    pub fn queue_depth(&self) -> usize {
+        self.candidates.saturating_sub(self.checked())
+    }
+
+    /// Verdicts per second since verification started, counting those
+    /// resumed from a checkpoint.
+    pub fn throughput(&self) -> Option<f64> {
+        (self.verifying > 0.0).then_some(self.checked() as f64 / self.verifying)
+    }
+}
+
+/// Draws `state`, `elapsed` into the run, onto `frame`.
+pub fn draw(frame: &mut Frame, state: &DashboardState, elapsed: Duration) {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(8),
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Min(3),
+        ])
+        .split(frame.size());
+    let block = |title: String| Block::default().borders(Borders::ALL).title(title);
+
+    let seconds = elapsed.as_secs();
+    let phase = state
+        .phase
+        .map_or("Starting".to_string(), |phase| format!("{:?}", phase));
+    frame.render_widget(
+        Paragraph::new(Line::from(format!(
+            "{}, elapsed {}:{:02}:{:02}",
+            phase,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )))
+        .block(block("lakeroad".to_string())),
+        areas[0],
+    );
+
+    frame.render_widget(
+        Sparkline::default()
+            .block(block(format!(
+                "E-graph: {} nodes, {} classes, {} iterations",
+                state.egraph_nodes.last().copied().unwrap_or(0),
+                state.egraph_classes,
+                state.egraph_nodes.len()
+            )))
+            .data(&state.egraph_nodes)
+            .style(Style::default().fg(Color::Cyan)),
+        areas[1],
+    );
+
+    let ratio = match state.candidates {
+        0 => 0.0,
+        total => state.checked() as f64 / total as f64,
+    };
+    frame.render_widget(
+        Gauge::default()
+            .block(block(format!(
+                "Candidates: {} of {} checked, {} queued",
+                state.checked(),
+                state.candidates,
+                state.queue_depth()
+            )))
+            .gauge_style(Style::default().fg(Color::Green))
+            .ratio(ratio.min(1.0)),
+        areas[2],
+    );
+
+    frame.render_widget(
+        Paragraph::new(Line::from(format!(
+            "{} accepted, {} rejected, {}",
+            state.accepted,
+            state.rejected,
+            state
+                .throughput()
+                .map_or("-".to_string(), |rate| format!("{:.2} verdicts/s", rate))
+        )))
+        .block(block("Solver".to_string())),
+        areas[3],
+    );
+
+    let (title, instrs) = match &state.isa {
+        Some((instrs, score)) => (format!("Best ISA so far: score {}", score), instrs.clone()),
+        None => ("Best ISA so far: none yet".to_string(), vec![]),
+    };
+    frame.render_widget(
+        List::new(instrs.into_iter().map(ListItem::new).collect::<Vec<_>>()).block(block(title)),
+        areas[4],
+    );
+}
+
+/// Shows a run's progress on the terminal until it's dropped.
+pub struct Dashboard {
+    state: RefCell<DashboardState>,
+    terminal: RefCell<Terminal<CrosstermBackend<Stderr>>>,
+    started: Instant,
+    drawn: Cell<Option<Instant>>,
+}
+
+impl Dashboard {
+    /// Takes over the alternate screen of the terminal on stderr.
+    pub fn new() -> io::Result<Self> {
+        let mut stderr = io::stderr();
+        execute!(stderr, EnterAlternateScreen, cursor::Hide)?;
+        Ok(Dashboard {
+            state: RefCell::new(DashboardState::default()),
+            terminal: RefCell::new(Terminal::new(CrosstermBackend::new(stderr))?),
+            started: Instant::now(),
+            drawn: Cell::new(None),
+        })
+    }
+}
+
+impl ProgressObserver for Dashboard {
+    fn notify(&self, event: &ProgressEvent) {
+        let now = Instant::now();
+        self.state.borrow_mut().observe(event, now);
+        // New phases and ISAs are always drawn; the rest are throttled.
+        let due = matches!(event, ProgressEvent::Phase(_) | ProgressEvent::Isa { .. })
+            || self
+                .drawn
+                .get()
+                .map_or(true, |drawn| now - drawn >= Duration::from_millis(100));
+        if !due {
+            return;
+        }
+        self.drawn.set(Some(now));
+        let state = self.state.borrow();
+        // A dashboard which can't be drawn isn't worth failing the run for.
+        let _ = self
+            .terminal
+            .borrow_mut()
+            .draw(|frame| draw(frame, &state, now - self.started));
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        let _ = execute!(
+            self.terminal.get_mut().backend_mut(),
+            LeaveAlternateScreen,
+            cursor::Show
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egg::RecExpr;
+    use ratatui::backend::TestBackend;
+
+    use super::*;
+    use crate::language::Language;
+
+    #[test]
+    fn dashboard_follows_run() {
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let instr: RecExpr<Language> =
+            "(instr (binop-ast and 8 (hole 8) (hole 8)) (canonical-args 0 1))"
+                .parse()
+                .unwrap();
+        let shown_instr = instr.to_string();
+        let mut state = DashboardState::default();
+        state.observe(&ProgressEvent::Phase(Phase::Rewriting), start);
+        for (iteration, egraph_nodes) in [(1, 10), (2, 40), (2, 40)] {
+            state.observe(
+                &ProgressEvent::Iteration {
+                    iteration,
+                    egraph_nodes,
+                    egraph_classes: egraph_nodes / 2,
+                },
+                start,
+            );
+        }
+        assert_eq!(state.egraph_nodes, vec![10, 40]);
+
+        state.observe(&ProgressEvent::Phase(Phase::Verification), at(1));
+        for (checked, accepted) in [(1, true), (2, false)] {
+            state.observe(
+                &ProgressEvent::Verdict {
+                    instr: &instr,
+                    accepted,
+                    checked,
+                    total: 5,
+                },
+                at(1 + 2 * checked as u64),
+            );
+        }
+        assert_eq!(state.queue_depth(), 3);
+        assert_eq!(state.throughput(), Some(0.5));
+        state.observe(
+            &ProgressEvent::Isa {
+                instrs: &[instr.clone()],
+                score: 1.0,
+            },
+            at(5),
+        );
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 24)).unwrap();
+        terminal
+            .draw(|frame| draw(frame, &state, Duration::from_secs(3725)))
+            .unwrap();
+        let screen = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect::<String>();
+        for shown in [
+            "Verification, elapsed 1:02:05",
+            "2 of 5 checked, 3 queued",
+            "1 accepted, 1 rejected, 0.50 verdicts/s",
+            shown_instr.as_str(),
+        ] {
+            assert!(screen.contains(shown), "{}", shown);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Entry points for WebAssembly builds, e.g. an in-browser demo.
+//!
+//! Build with `wasm-pack build --target web -- --no-default-features`, as
+//! the default features only make sense natively. Programs are passed in the JSON
+//! interchange format of [`crate::frontends::json`], and candidates are
+//! checked by a JavaScript callback rather than by Racket.
+
+use egg::RecExpr;
+use js_sys::Function;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    frontends::json::load_json,
+    language::{instr_as_expr, Language},
+    synthesizer::Synthesizer,
+};
+
+/// Calls `verify` with each candidate, both as an `instr` and as the
+/// expression it computes (see [`instr_as_expr`]). The candidate is accepted
+/// if `verify` returns a truthy value; if it throws, the candidate is
+/// rejected.
+pub fn js_backend(verify: Function) -> impl Fn(&RecExpr<Language>) -> bool {
+    move |instr| {
+        let expr = instr_as_expr(instr).map_or(JsValue::NULL, |expr| expr.to_string().into());
+        verify
+            .call2(&JsValue::NULL, &instr.to_string().into(), &expr)
+            .map_or(false, |accepted| accepted.is_truthy())
+    }
+}
+
+#[derive(Serialize)]
+struct Discovery {
+    isa: Vec<String>,
+    score: f64,
+    candidates: usize,
+    verified: usize,
+}
+
+fn synthesizer(
+    programs_json: &str,
+    iter_limit: usize,
+    node_limit: usize,
+) -> Result<Synthesizer, JsValue> {
+    let programs = load_json(programs_json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(Synthesizer::new()
+        .add_programs(&programs)
+        .with_iter_limit(iter_limit)
+        .with_node_limit(node_limit))
+}
+
+/// Returns the candidate instructions found for the programs, as an array of
+/// s-expressions.
+#[wasm_bindgen]
+pub fn explore(
+    programs_json: &str,
+    iter_limit: usize,
+    node_limit: usize,
+) -> Result<JsValue, JsValue> {
+    let exploration = synthesizer(programs_json, iter_limit, node_limit)?
+        .explore()
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(exploration
+        .candidates
+        .iter()
+        .map(|(_, instr)| JsValue::from_str(&instr.to_string()))
+        .collect::<js_sys::Array>()
+        .into())
+}
+
+/// Runs the whole pipeline, checking candidates with `verify` (see
+/// [`js_backend`]), and returns the selected ISA, its score, and the number
+/// of candidates found and accepted as a JSON string.
+#[wasm_bindgen]
+pub fn discover(
+    programs_json: &str,
+    verify: Function,
+    iter_limit: usize,
+    node_limit: usize,
+    max_instructions: usize,
+) -> Result<String, JsValue> {
+    let result = synthesizer(programs_json, iter_limit, node_limit)?
+        .with_backend(js_backend(verify))
+        .with_max_instructions(max_instructions)
+        .run()
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    serde_json::to_string(&Discovery {
+        isa: result.instructions.iter().map(|i| i.to_string()).collect(),
+        score: result.report.score,
+        candidates: result.report.candidates,
+        verified: result.report.verified,
+    })
+    .map_err(|e| JsValue::from_str(&e.to_string()))
+}